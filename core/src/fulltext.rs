@@ -0,0 +1,49 @@
+use futures::sync::mpsc::{channel, Sender};
+use std::sync::Mutex;
+
+use graph::prelude::*;
+
+/// Keeps the store up to date with the full-text searches that deployed
+/// subgraphs declare with `@fulltext`, so that it maintains the search
+/// documents of their entities.
+pub struct FulltextIndexing {
+    schema_event_sink: Sender<SchemaEvent>,
+}
+
+impl FulltextIndexing {
+    /// Creates the component. Full-text searches are picked up from the
+    /// schemas sent to `schema_event_sink` and passed on to `store`.
+    pub fn new<S>(logger: &Logger, store: Arc<Mutex<S>>) -> Self
+    where
+        S: FulltextStore + Send + 'static,
+    {
+        let logger = logger.new(o!("component" => "FulltextIndexing"));
+
+        let (schema_event_sink, schema_event_stream) = channel(100);
+        tokio::spawn(schema_event_stream.for_each(move |event| {
+            let (subgraph_id, definitions) = match event {
+                SchemaEvent::SchemaAdded(schema) | SchemaEvent::SchemaUpdated(schema) => {
+                    let definitions = schema.fulltext_definitions();
+                    (schema.id, definitions)
+                }
+                SchemaEvent::SchemaRemoved(schema) => (schema.id, vec![]),
+            };
+            if let Err(e) = store
+                .lock()
+                .unwrap()
+                .set_fulltext_definitions(&subgraph_id, definitions)
+            {
+                error!(logger, "Failed to set up full-text searches";
+                       "subgraph" => &subgraph_id, "error" => e.to_string());
+            }
+            Ok(())
+        }));
+
+        FulltextIndexing { schema_event_sink }
+    }
+
+    /// Returns a sink for the schemas of deployed subgraphs.
+    pub fn schema_event_sink(&self) -> Sender<SchemaEvent> {
+        self.schema_event_sink.clone()
+    }
+}
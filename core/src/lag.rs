@@ -0,0 +1,191 @@
+use ethereum_types::H256;
+use futures::sync::mpsc::{channel, Sender};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use graph::components::ethereum::{BlockId, BlockNumber, EthereumBlockHeader};
+use graph::prelude::*;
+use graph::tokio::timer::Interval;
+
+/// How often subgraphs are compared against the chain head.
+const CHECK_INTERVAL_SECS: u64 = 15;
+
+#[derive(Default)]
+struct LagState {
+    /// Hash of the latest block each subgraph wrote entities for.
+    indexed_blocks: HashMap<String, H256>,
+    /// Headers of the blocks in `indexed_blocks` that have been looked up
+    /// so far.
+    indexed_block_headers: HashMap<String, EthereumBlockHeader>,
+    lags: HashMap<String, IndexingLag>,
+}
+
+/// Periodically measures how far subgraphs are behind the chain head, and
+/// flags the subgraphs that fall further behind than one of their lag
+/// thresholds allows.
+///
+/// Subgraphs only report progress when they write entities, so the lag is
+/// measured from the latest block a subgraph wrote entities for.
+pub struct IndexingLagMonitor {
+    logger: Logger,
+    /// Lag thresholds by subgraph.
+    thresholds: Arc<HashMap<String, Vec<LagThreshold>>>,
+    state: Arc<Mutex<LagState>>,
+    store_event_sink: Sender<StoreEvent>,
+}
+
+impl IndexingLagMonitor {
+    /// Creates the component. The blocks that subgraphs have indexed are
+    /// picked up from the store events sent to `store_event_sink`.
+    pub fn new(logger: &Logger, thresholds: HashMap<String, Vec<LagThreshold>>) -> Self {
+        let logger = logger.new(o!("component" => "IndexingLagMonitor"));
+        let state = Arc::new(Mutex::new(LagState::default()));
+
+        let (store_event_sink, store_event_stream) = channel(100);
+        let indexed_state = state.clone();
+        tokio::spawn(store_event_stream.for_each(move |event| {
+            if let StoreEvent::SubgraphChanged(subgraph_id, EventSource::EthereumBlock(hash, _)) =
+                event
+            {
+                indexed_state
+                    .lock()
+                    .unwrap()
+                    .indexed_blocks
+                    .insert(subgraph_id, hash);
+            }
+            Ok(())
+        }));
+
+        IndexingLagMonitor {
+            logger,
+            thresholds: Arc::new(thresholds),
+            state,
+            store_event_sink,
+        }
+    }
+
+    /// Returns a sink for the events of the store that subgraphs write to.
+    pub fn store_event_sink(&self) -> Sender<StoreEvent> {
+        self.store_event_sink.clone()
+    }
+
+    /// Spawns a task that compares all subgraphs against the latest block
+    /// of the chain every 15 seconds.
+    pub fn start<A>(&self, adapter: Arc<Mutex<A>>)
+    where
+        A: EthereumAdapter,
+    {
+        let logger = self.logger.clone();
+        let error_logger = self.logger.clone();
+        let thresholds = self.thresholds.clone();
+        let state = self.state.clone();
+        tokio::spawn(
+            Interval::new(Instant::now(), Duration::from_secs(CHECK_INTERVAL_SECS))
+                .map_err(move |e| {
+                    error!(error_logger, "Indexing lag timer failed"; "error" => e.to_string())
+                })
+                .for_each(move |_| {
+                    Self::check(&logger, &mut *adapter.lock().unwrap(), &thresholds, &state);
+                    Ok(())
+                }),
+        );
+    }
+
+    /// Measures the lag of every subgraph that has indexed a block, and logs
+    /// when subgraphs start or stop lagging.
+    fn check<A>(
+        logger: &Logger,
+        adapter: &mut A,
+        thresholds: &HashMap<String, Vec<LagThreshold>>,
+        state: &Mutex<LagState>,
+    ) where
+        A: EthereumAdapter,
+    {
+        let head = match adapter
+            .block_header(BlockId::Number(BlockNumber::Latest))
+            .wait()
+        {
+            Ok(head) => head,
+            Err(e) => {
+                warn!(logger, "Failed to look up the latest block"; "error" => e.to_string());
+                return;
+            }
+        };
+
+        let indexed_blocks = state.lock().unwrap().indexed_blocks.clone();
+        for (subgraph_id, block_hash) in indexed_blocks {
+            let indexed = match adapter.block_header(BlockId::Hash(block_hash)).wait() {
+                Ok(indexed) => indexed,
+                Err(e) => {
+                    warn!(logger, "Failed to look up the latest block indexed";
+                          "subgraph" => &subgraph_id, "error" => e.to_string());
+                    continue;
+                }
+            };
+
+            let subgraph_thresholds = thresholds.get(&subgraph_id);
+            let lag = Self::measure(subgraph_id, &head, &indexed, subgraph_thresholds);
+            let mut state = state.lock().unwrap();
+            state.indexed_block_headers.insert(lag.subgraph_id.clone(), indexed);
+            let was_lagging = state
+                .lags
+                .get(&lag.subgraph_id)
+                .map_or(false, |previous| previous.lagging);
+            if lag.lagging && !was_lagging {
+                warn!(logger, "Subgraph is lagging behind the chain head";
+                      "subgraph" => &lag.subgraph_id,
+                      "blocks_behind" => lag.blocks_behind,
+                      "seconds_behind" => lag.seconds_behind);
+            } else if !lag.lagging && was_lagging {
+                info!(logger, "Subgraph caught up with the chain head";
+                      "subgraph" => &lag.subgraph_id,
+                      "blocks_behind" => lag.blocks_behind,
+                      "seconds_behind" => lag.seconds_behind);
+            }
+            state.lags.insert(lag.subgraph_id.clone(), lag);
+        }
+    }
+
+    /// Compares the latest block indexed for a subgraph with the chain head.
+    fn measure(
+        subgraph_id: String,
+        head: &EthereumBlockHeader,
+        indexed: &EthereumBlockHeader,
+        thresholds: Option<&Vec<LagThreshold>>,
+    ) -> IndexingLag {
+        let blocks_behind = head.number.saturating_sub(indexed.number);
+        let seconds_behind = head.timestamp.saturating_sub(indexed.timestamp);
+        IndexingLag {
+            subgraph_id,
+            head_block_number: head.number,
+            indexed_block_number: indexed.number,
+            blocks_behind,
+            seconds_behind,
+            lagging: thresholds.map_or(false, |thresholds| {
+                thresholds
+                    .iter()
+                    .any(|threshold| threshold.is_exceeded_by(blocks_behind, seconds_behind))
+            }),
+        }
+    }
+}
+
+impl IndexingLagStatus for IndexingLagMonitor {
+    fn indexing_lags(&self) -> Vec<IndexingLag> {
+        let mut lags: Vec<_> = self.state.lock().unwrap().lags.values().cloned().collect();
+        lags.sort_by(|a, b| a.subgraph_id.cmp(&b.subgraph_id));
+        lags
+    }
+
+    fn indexed_block(&self, subgraph_id: &str) -> Option<EthereumBlockHeader> {
+        let state = self.state.lock().unwrap();
+        match (
+            state.indexed_blocks.get(subgraph_id),
+            state.indexed_block_headers.get(subgraph_id),
+        ) {
+            (Some(hash), Some(header)) if hash == &header.hash => Some(header.clone()),
+            _ => None,
+        }
+    }
+}
@@ -2,13 +2,26 @@ extern crate ethereum_types;
 extern crate futures;
 extern crate graph;
 extern crate graph_graphql;
+extern crate graphql_parser;
 extern crate graph_runtime_wasm;
 extern crate serde;
 extern crate serde_yaml;
 
+mod fulltext;
+mod lag;
+mod mapping_log;
 mod query;
+mod retention;
 mod subgraph;
+mod throughput;
+mod usage;
 
-pub use query::QueryRunner;
+pub use fulltext::FulltextIndexing;
+pub use lag::IndexingLagMonitor;
+pub use mapping_log::MappingLogBuffer;
+pub use query::{QueryEngine, QueryRunner};
+pub use retention::EntityRetention;
 pub use subgraph::RuntimeManager;
 pub use subgraph::SubgraphProvider;
+pub use throughput::IndexingThroughput;
+pub use usage::IdleDeploymentCleanup;
@@ -0,0 +1,89 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use graph::prelude::*;
+
+/// Keeps the latest messages logged by the mappings of each subgraph in
+/// memory. Once a subgraph has logged `capacity` messages, every new message
+/// replaces its oldest one.
+pub struct MappingLogBuffer {
+    capacity: usize,
+    entries: Mutex<HashMap<String, VecDeque<MappingLogEntry>>>,
+}
+
+impl MappingLogBuffer {
+    pub fn new(capacity: usize) -> Self {
+        MappingLogBuffer {
+            capacity,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl MappingLogCapture for MappingLogBuffer {
+    fn capture(&self, entry: MappingLogEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        let subgraph_entries = entries
+            .entry(entry.subgraph_id.clone())
+            .or_insert_with(VecDeque::new);
+        if subgraph_entries.len() == self.capacity {
+            subgraph_entries.pop_front();
+        }
+        subgraph_entries.push_back(entry);
+    }
+
+    fn mapping_logs(&self, subgraph_id: &str, limit: Option<usize>) -> Vec<MappingLogEntry> {
+        let entries = self.entries.lock().unwrap();
+        let subgraph_entries = match entries.get(subgraph_id) {
+            Some(subgraph_entries) => subgraph_entries,
+            None => return vec![],
+        };
+        let skip = limit.map_or(0, |limit| subgraph_entries.len().saturating_sub(limit));
+        subgraph_entries.iter().skip(skip).cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MappingLogBuffer;
+    use graph::prelude::*;
+
+    fn entry(subgraph_id: &str, message: &str) -> MappingLogEntry {
+        MappingLogEntry {
+            subgraph_id: subgraph_id.to_owned(),
+            timestamp: 0,
+            level: MappingLogLevel::Info,
+            message: message.to_owned(),
+            handler: String::from("handleTransfer"),
+            block_number: 1,
+            block_hash: String::new(),
+            transaction_hash: String::new(),
+        }
+    }
+
+    fn messages(buffer: &MappingLogBuffer, subgraph_id: &str, limit: Option<usize>) -> Vec<String> {
+        buffer
+            .mapping_logs(subgraph_id, limit)
+            .into_iter()
+            .map(|entry| entry.message)
+            .collect()
+    }
+
+    #[test]
+    fn keeps_the_latest_messages_of_each_subgraph() {
+        let buffer = MappingLogBuffer::new(2);
+        buffer.capture(entry("a", "one"));
+        buffer.capture(entry("a", "two"));
+        buffer.capture(entry("b", "other"));
+        buffer.capture(entry("a", "three"));
+
+        assert_eq!(messages(&buffer, "a", None), vec!["two", "three"]);
+        assert_eq!(messages(&buffer, "a", Some(1)), vec!["three"]);
+        assert_eq!(messages(&buffer, "b", None), vec!["other"]);
+        assert!(messages(&buffer, "c", None).is_empty());
+    }
+}
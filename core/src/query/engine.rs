@@ -0,0 +1,116 @@
+use graphql_parser;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use graph::prelude::{QueryRunner as QueryRunnerTrait, *};
+use graph_graphql::prelude::*;
+
+use super::QueryRunner;
+
+/// Runs GraphQL queries against the entities of a subgraph in a store,
+/// without the GraphQL server. This lets other services embed the query
+/// engine and query an existing entities database directly.
+pub struct QueryEngine<S> {
+    runner: QueryRunner<S>,
+    /// The API schema derived from the schema of the subgraph.
+    schema: Schema,
+}
+
+impl<S> QueryEngine<S>
+where
+    S: Store + Sized + 'static,
+{
+    /// Creates an engine for the subgraph with the given schema, whose
+    /// entities are in `store`. Fails with everything that is wrong with the
+    /// schema if it can't be queried.
+    pub fn new(
+        logger: &Logger,
+        store: Arc<Mutex<S>>,
+        mut schema: Schema,
+    ) -> Result<Self, Vec<APISchemaError>> {
+        validate_schema(&schema.document)?;
+
+        // Entity types tell the store which subgraph their entities belong to
+        let id = schema.id.clone();
+        schema.add_subgraph_id_directives(id.clone());
+        let document = api_schema(&schema.document).map_err(|e| vec![e])?;
+
+        Ok(QueryEngine {
+            runner: QueryRunner::new(logger, store),
+            schema: Schema { id, document },
+        })
+    }
+
+    /// Runs queries that are pinned to a block with the `block` argument,
+    /// using `status` to look up the latest block the subgraph has indexed.
+    /// Without it, such queries are rejected.
+    pub fn with_indexing_status(mut self, status: Arc<IndexingLagStatus + Send + Sync>) -> Self {
+        self.runner = self.runner.with_indexing_status(status);
+        self
+    }
+
+    /// Resolves block hashes that queries are pinned to with `adapter`.
+    /// Without it, queries can only be pinned to block numbers.
+    pub fn with_ethereum_adapter(mut self, adapter: Arc<Mutex<EthereumAdapter>>) -> Self {
+        self.runner = self.runner.with_ethereum_adapter(adapter);
+        self
+    }
+
+    /// Fails queries that take longer than `timeout` to run.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.runner = self.runner.with_timeout(timeout);
+        self
+    }
+
+    /// Rejects queries that ask for more than `max_first` entities of a
+    /// collection at once.
+    pub fn with_max_first(mut self, max_first: u32) -> Self {
+        self.runner = self.runner.with_max_first(max_first);
+        self
+    }
+
+    /// Runs `middleware` around the resolution of every field of every
+    /// query, after the middleware added before.
+    pub fn with_field_middleware(mut self, middleware: Arc<FieldMiddleware>) -> Self {
+        self.runner = self.runner.with_field_middleware(middleware);
+        self
+    }
+
+    /// Returns the API schema that queries are run against.
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// Runs a query against the entities of the subgraph, at the block that
+    /// the `block` arguments of its top-level fields pin it to, or the latest
+    /// block. Errors of individual fields are reported in the result; queries
+    /// that can't be parsed or run at all fail, including those whose
+    /// top-level fields are at different blocks and, unless the engine has
+    /// an indexing status, those pinned to a block.
+    pub fn execute(
+        &self,
+        query: &str,
+        variables: Option<QueryVariables>,
+    ) -> Result<QueryResult, QueryError> {
+        let query = Query {
+            schema: self.schema.clone(),
+            document: graphql_parser::parse_query(query)?,
+            variables,
+            operation_name: None,
+            role: None,
+            explain: false,
+            trace: false,
+        };
+        let target = QueryTarget {
+            subgraph: SubgraphTarget::Deployment(self.schema.id.clone()),
+            block: block_constraint(&query)?,
+        };
+
+        match self.runner.run_query(query, target).wait()? {
+            QueryResponse::Complete(result) => Ok(result),
+            QueryResponse::Stream(_) => Err(QueryError::from(QueryExecutionError::NotSupported(
+                String::from("subscriptions"),
+            ))),
+        }
+    }
+}
@@ -1,3 +1,5 @@
+mod engine;
 mod runner;
 
+pub use self::engine::QueryEngine;
 pub use self::runner::QueryRunner;
@@ -1,14 +1,20 @@
-use futures::sync::mpsc::{channel, Receiver, Sender};
+use ethereum_types::H256;
 use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
+use graph::components::ethereum::{BlockId, BlockNumber, EthereumBlockError};
 use graph::prelude::{QueryRunner as QueryRunnerTrait, *};
 use graph_graphql::prelude::*;
 
 /// Common query runner implementation for The Graph.
 pub struct QueryRunner<S> {
     logger: Logger,
-    query_sink: Sender<Query>,
     store: Arc<Mutex<S>>,
+    timeout: Option<Duration>,
+    indexing_status: Option<Arc<IndexingLagStatus + Send + Sync>>,
+    ethereum_adapter: Option<Arc<Mutex<EthereumAdapter>>>,
+    middleware: Vec<Arc<FieldMiddleware>>,
+    max_first: Option<u32>,
 }
 
 impl<S> QueryRunner<S>
@@ -17,41 +23,193 @@ where
 {
     /// Creates a new query runner.
     pub fn new(logger: &Logger, store: Arc<Mutex<S>>) -> Self {
-        let (sink, stream) = channel(100);
-        let runner = QueryRunner {
+        QueryRunner {
             logger: logger.new(o!("component" => "QueryRunner")),
-            query_sink: sink,
             store: store,
-        };
-        runner.run_queries(stream);
-        runner
+            timeout: None,
+            indexing_status: None,
+            ethereum_adapter: None,
+            middleware: vec![],
+            max_first: None,
+        }
     }
 
-    /// Spawns a Tokio task to run any queries received through the given stream.
-    fn run_queries(&self, stream: Receiver<Query>) {
-        info!(self.logger, "Preparing to run queries");
+    /// Fails queries that take longer than `timeout` to run, and cancels
+    /// the store queries they still make.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
 
-        let logger = self.logger.clone();
-        let store = self.store.clone();
+    /// Looks up the latest block subgraphs have indexed in `status`, so
+    /// that queries at that block can be run and queries at older blocks
+    /// are rejected with the earliest block that can be queried.
+    pub fn with_indexing_status(mut self, status: Arc<IndexingLagStatus + Send + Sync>) -> Self {
+        self.indexing_status = Some(status);
+        self
+    }
 
-        tokio::spawn(stream.for_each(move |query| {
-            let options = ExecutionOptions {
-                logger: logger.clone(),
-                resolver: StoreResolver::new(&logger, store.clone()),
-            };
-            let result = execute(&query, options);
+    /// Resolves block hashes that queries are pinned to with `adapter`, and
+    /// rejects queries at blocks that are no longer on the canonical chain.
+    pub fn with_ethereum_adapter(mut self, adapter: Arc<Mutex<EthereumAdapter>>) -> Self {
+        self.ethereum_adapter = Some(adapter);
+        self
+    }
+
+    /// Rejects queries that ask for more than `max_first` entities of a
+    /// collection at once.
+    pub fn with_max_first(mut self, max_first: u32) -> Self {
+        self.max_first = Some(max_first);
+        self
+    }
+
+    /// Runs `middleware` around the resolution of every field of every
+    /// query, after the middleware added before.
+    pub fn with_field_middleware(mut self, middleware: Arc<FieldMiddleware>) -> Self {
+        self.middleware.push(middleware);
+        self
+    }
 
-            query
-                .result_sender
-                .send(result)
-                .expect("Failed to deliver query result");
-            Ok(())
-        }));
+    /// Checks that the query can be evaluated at the block of `target`, and
+    /// returns the number of the block if the query has to look up entities
+    /// from their history.
+    fn check_block(&self, target: &QueryTarget) -> Result<Option<u64>, QueryExecutionError> {
+        let not_supported = || {
+            QueryExecutionError::NotSupported(String::from("queries at a specific block"))
+        };
+
+        let block = match target.block {
+            None => return Ok(None),
+            Some(ref block) => block,
+        };
+        let subgraph_id = match target.subgraph {
+            SubgraphTarget::Deployment(ref id) => id,
+            SubgraphTarget::Name { .. } => return Err(not_supported()),
+        };
+        let indexed = match self.indexing_status {
+            Some(ref status) => status.indexed_block(subgraph_id),
+            None => None,
+        };
+
+        let number = match (block, &self.ethereum_adapter) {
+            (BlockConstraint::Number(number), _) => *number,
+            (BlockConstraint::Hash(hash), Some(adapter)) => {
+                Self::canonical_block_number(&mut *adapter.lock().unwrap(), *hash)?
+            }
+            (BlockConstraint::Hash(_), None) => return Err(not_supported()),
+        };
+
+        // Entities at older blocks are looked up from their history, which
+        // the store may only keep for some of the blocks
+        match indexed {
+            Some(ref indexed) if number == indexed.number => Ok(None),
+            Some(ref indexed) if number < indexed.number => {
+                let earliest = self
+                    .store
+                    .lock()
+                    .unwrap()
+                    .earliest_history_block(subgraph_id);
+                match earliest {
+                    Some(earliest) if earliest <= number => Ok(Some(number)),
+                    _ => Err(QueryExecutionError::BlockOutOfRangeError(
+                        number,
+                        earliest.unwrap_or(indexed.number),
+                    )),
+                }
+            }
+            _ => Err(not_supported()),
+        }
+    }
+
+    /// Looks up the number of the block with the given hash, and fails if
+    /// the block has been reorged out of the canonical chain.
+    fn canonical_block_number(
+        adapter: &mut EthereumAdapter,
+        hash: H256,
+    ) -> Result<u64, QueryExecutionError> {
+        let lookup_error = |e: EthereumBlockError| {
+            QueryExecutionError::BlockLookupError(format!("{:#x}", hash), e.to_string())
+        };
+
+        let block = match adapter.block_header(BlockId::Hash(hash)).wait() {
+            Ok(block) => block,
+            Err(EthereumBlockError::NotFound(_)) => {
+                return Err(QueryExecutionError::BlockNotFoundError(format!(
+                    "{:#x}",
+                    hash
+                )))
+            }
+            Err(e) => return Err(lookup_error(e)),
+        };
+        let canonical = adapter
+            .block_header(BlockId::Number(BlockNumber::Number(block.number)))
+            .wait()
+            .map_err(lookup_error)?;
+
+        if canonical.hash == hash {
+            Ok(block.number)
+        } else {
+            Err(QueryExecutionError::BlockReorgedOutError(
+                format!("{:#x}", hash),
+                block.number,
+                format!("{:#x}", canonical.hash),
+            ))
+        }
     }
 }
 
-impl<S> QueryRunnerTrait for QueryRunner<S> {
-    fn query_sink(&mut self) -> Sender<Query> {
-        self.query_sink.clone()
+impl<S> QueryRunnerTrait for QueryRunner<S>
+where
+    S: Store + Sized + 'static,
+{
+    fn run_query(&self, query: Query, target: QueryTarget) -> QueryResponseFuture {
+        debug!(self.logger, "Running query"; "target" => target.to_string());
+
+        let block = match self.check_block(&target) {
+            Ok(block) => block,
+            Err(e) => return Box::new(future::err(QueryError::from(e))),
+        };
+
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let mut resolver = StoreResolver::new(&self.logger, self.store.clone()).with_block(block);
+
+        // Stitched schemas report the indexing state of each of their
+        // deployments, which index independently of each other
+        let mut deployments = stitched_subgraph_ids(&query.schema.document);
+        if deployments.is_empty() {
+            deployments.push(query.schema.id.clone());
+        }
+        for deployment in deployments {
+            let indexed_block = match self.indexing_status {
+                Some(ref status) => status.indexed_block(&deployment),
+                None => None,
+            };
+            resolver = resolver.with_meta(deployment, indexed_block);
+        }
+
+        if let Some(deadline) = deadline {
+            resolver = resolver.with_deadline(deadline);
+        }
+        if query.explain {
+            resolver = resolver.with_query_plan();
+        }
+        if query.trace {
+            resolver = resolver.with_trace();
+        }
+        let options = ExecutionOptions {
+            logger: self.logger.clone(),
+            resolver,
+            middleware: self.middleware.clone(),
+            max_first: self.max_first,
+        };
+        let result = execute(&query, options);
+
+        // Fields whose store queries were cut short are missing from the result
+        if deadline.map_or(false, |deadline| Instant::now() >= deadline) {
+            warn!(self.logger, "Query timed out"; "target" => target.to_string());
+            return Box::new(future::err(QueryError::Timeout));
+        }
+
+        Box::new(future::ok(QueryResponse::Complete(result)))
     }
 }
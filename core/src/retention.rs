@@ -0,0 +1,106 @@
+use futures::sync::mpsc::{channel, Sender};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use graph::data::usage::current_timestamp;
+use graph::prelude::*;
+use graph::tokio::timer::Interval;
+
+/// How often entities are checked against the retention periods of their types.
+const PRUNE_INTERVAL_SECS: u64 = 60 * 60;
+
+/// Periodically prunes entities of types with a retention period, such as
+/// `@entity(retention: "30d")`, that have not been updated for longer than
+/// that period.
+pub struct EntityRetention {
+    logger: Logger,
+    /// Retention periods in seconds by subgraph and entity type.
+    retention_periods: Arc<Mutex<HashMap<String, HashMap<String, u64>>>>,
+    schema_event_sink: Sender<SchemaEvent>,
+}
+
+impl EntityRetention {
+    /// Creates the component. Retention periods are picked up from the
+    /// schemas sent to `schema_event_sink`.
+    pub fn new(logger: &Logger) -> Self {
+        let logger = logger.new(o!("component" => "EntityRetention"));
+        let retention_periods = Arc::new(Mutex::new(HashMap::new()));
+
+        let (schema_event_sink, schema_event_stream) = channel(100);
+        let periods = retention_periods.clone();
+        tokio::spawn(schema_event_stream.for_each(move |event| {
+            let mut periods = periods.lock().unwrap();
+            match event {
+                SchemaEvent::SchemaAdded(schema) | SchemaEvent::SchemaUpdated(schema) => {
+                    let subgraph_periods = schema.retention_periods();
+                    if subgraph_periods.is_empty() {
+                        periods.remove(&schema.id);
+                    } else {
+                        periods.insert(schema.id.clone(), subgraph_periods);
+                    }
+                }
+                SchemaEvent::SchemaRemoved(schema) => {
+                    periods.remove(&schema.id);
+                }
+            }
+            Ok(())
+        }));
+
+        EntityRetention {
+            logger,
+            retention_periods,
+            schema_event_sink,
+        }
+    }
+
+    /// Returns a sink for the schemas of deployed subgraphs.
+    pub fn schema_event_sink(&self) -> Sender<SchemaEvent> {
+        self.schema_event_sink.clone()
+    }
+
+    /// Spawns a task that prunes the entities in `store` once an hour.
+    pub fn start<S>(&self, store: Arc<Mutex<S>>)
+    where
+        S: EntityRetentionStore + Send + 'static,
+    {
+        let logger = self.logger.clone();
+        let error_logger = self.logger.clone();
+        let retention_periods = self.retention_periods.clone();
+        tokio::spawn(
+            Interval::new(Instant::now(), Duration::from_secs(PRUNE_INTERVAL_SECS))
+                .map_err(move |e| {
+                    error!(error_logger, "Entity retention timer failed";
+                           "error" => e.to_string())
+                })
+                .for_each(move |_| {
+                    let retention_periods = retention_periods.lock().unwrap().clone();
+                    Self::prune(&logger, &mut *store.lock().unwrap(), &retention_periods);
+                    Ok(())
+                }),
+        );
+    }
+
+    /// Deletes the entities in `store` that have outlived the retention
+    /// period of their type.
+    pub fn prune<S>(
+        logger: &Logger,
+        store: &mut S,
+        retention_periods: &HashMap<String, HashMap<String, u64>>,
+    ) where
+        S: EntityRetentionStore,
+    {
+        let now = current_timestamp();
+        for (subgraph_id, periods) in retention_periods {
+            for (entity_type, period) in periods {
+                let updated_before = now.saturating_sub(*period);
+                if let Err(e) = store.prune_entities(subgraph_id, entity_type, updated_before) {
+                    error!(logger, "Failed to prune entities";
+                           "subgraph" => subgraph_id,
+                           "entity" => entity_type,
+                           "error" => e.to_string());
+                }
+            }
+        }
+    }
+}
@@ -1,9 +1,19 @@
+use futures::future::{self, Loop};
 use futures::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 use graph::components::subgraph::RuntimeHostEvent;
 use graph::components::subgraph::SubgraphProviderEvent;
 use graph::prelude::*;
+use graph::tokio::timer::Delay;
+
+/// How often writing an event of a runtime host is attempted while another
+/// node is writing to the subgraph, before the subgraph is stopped here.
+const WRITE_ATTEMPTS: u32 = 3;
+
+/// How long to back off after another node wrote to the subgraph.
+const WRITE_RETRY_DELAY_MS: u64 = 500;
 
 pub struct RuntimeManager {
     logger: Logger,
@@ -42,26 +52,63 @@ impl RuntimeManager where {
         T: RuntimeHostBuilder,
     {
         // Handles each incoming event from the subgraph.
-        fn handle_event<S: Store + 'static>(store: Arc<Mutex<S>>, event: RuntimeHostEvent) {
+        fn handle_event<S: Store + 'static>(
+            store: &Mutex<S>,
+            event: RuntimeHostEvent,
+        ) -> Result<(), StoreWriteError> {
+            let mut store = store.lock().unwrap();
             match event {
                 RuntimeHostEvent::EntitySet(store_key, entity, event_source) => {
-                    store
-                        .lock()
-                        .unwrap()
-                        .set(store_key, entity, event_source)
-                        .expect("Failed to set entity in the store");
+                    store.set(store_key, entity, event_source)
                 }
                 RuntimeHostEvent::EntityRemoved(store_key, event_source) => {
-                    store
-                        .lock()
-                        .unwrap()
-                        .delete(store_key, event_source)
-                        .expect("Failed to delete entity from the store");
+                    store.delete(store_key, event_source)
                 }
+                RuntimeHostEvent::BlockReverted(subgraph_id, block_hash) => store
+                    .revert_block_operations(&subgraph_id, block_hash)
+                    .map(|_| ()),
             }
         }
 
-        let mut runtime_hosts = vec![];
+        // Writes an event to the store. While another node is writing to the
+        // subgraph, backs off without holding on to the store and tries again;
+        // fails if the other node keeps writing or the store fails to write,
+        // which the store only does after retrying conflicting transactions.
+        fn write_event<S: Store + 'static>(
+            logger: Logger,
+            store: Arc<Mutex<S>>,
+            event: RuntimeHostEvent,
+        ) -> impl Future<Item = (), Error = ()> {
+            future::loop_fn(1, move |attempt| match handle_event(&store, event.clone()) {
+                Ok(()) => future::Either::A(future::ok(Loop::Break(()))),
+                Err(StoreWriteError::NotWriter(subgraph_id)) => {
+                    if attempt >= WRITE_ATTEMPTS {
+                        error!(logger, "Another node keeps writing to the subgraph, stopping it";
+                               "subgraph" => &subgraph_id);
+                        return future::Either::A(future::err(()));
+                    }
+
+                    warn!(logger, "Another node is writing to the subgraph, backing off";
+                          "subgraph" => &subgraph_id, "attempt" => attempt);
+                    let logger = logger.clone();
+                    future::Either::B(
+                        Delay::new(Instant::now() + Duration::from_millis(WRITE_RETRY_DELAY_MS))
+                            .map(move |()| Loop::Continue(attempt + 1))
+                            .map_err(move |e| {
+                                error!(logger, "Failed to back off from writing";
+                                       "error" => e.to_string())
+                            }),
+                    )
+                }
+                Err(e) => {
+                    error!(logger, "Failed to write to the store, stopping the subgraph";
+                           "error" => e.to_string());
+                    future::Either::A(future::err(()))
+                }
+            })
+        }
+
+        let runtime_hosts: Arc<Mutex<Vec<T::Host>>> = Arc::new(Mutex::new(vec![]));
 
         tokio::spawn(receiver.for_each(move |event| {
             match event {
@@ -79,21 +126,38 @@ impl RuntimeManager where {
                     // Tokio task will terminate when the corresponding subgraph
                     // is removed and the host and its event sender are dropped
                     for mut new_host in new_hosts {
+                        let logger = logger.clone();
                         let store = store.clone();
-                        tokio::spawn(new_host.take_event_stream().unwrap().for_each(
-                            move |event| {
-                                handle_event(store.clone(), event);
-                                Ok(())
-                            },
-                        ));
+                        let hosts = runtime_hosts.clone();
+                        let manifest = manifest.clone();
+                        tokio::spawn(
+                            new_host
+                                .take_event_stream()
+                                .unwrap()
+                                .for_each(move |event| {
+                                    write_event(logger.clone(), store.clone(), event)
+                                })
+                                .or_else(move |()| {
+                                    // Stop all runtime hosts of the subgraph, since
+                                    // its events can't be written on this node
+                                    hosts
+                                        .lock()
+                                        .unwrap()
+                                        .retain(|host| host.subgraph_manifest() != &manifest);
+                                    Ok(())
+                                }),
+                        );
                         // Add the new host to the list of managed runtime hosts
-                        runtime_hosts.push(new_host);
+                        runtime_hosts.lock().unwrap().push(new_host);
                     }
                 }
                 SubgraphProviderEvent::SubgraphRemoved(ref manifest) => {
                     // Destroy all runtime hosts for this subgraph; this will
                     // also terminate the host's event stream
-                    runtime_hosts.retain(|host| host.subgraph_manifest() != manifest);
+                    runtime_hosts
+                        .lock()
+                        .unwrap()
+                        .retain(|host| host.subgraph_manifest() != manifest);
                 }
             }
 
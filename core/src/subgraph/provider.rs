@@ -1,5 +1,9 @@
 use futures::sync::mpsc::{channel, Receiver, Sender};
+use std::collections::HashSet;
+use std::sync::Mutex;
 
+use graph::data::mapping_pin::mapping_pin_changes;
+use graph::data::placement::placement_refusal;
 use graph::data::subgraph::SubgraphProviderError;
 use graph::prelude::{SubgraphProvider as SubgraphProviderTrait, *};
 
@@ -10,6 +14,10 @@ pub struct SubgraphProvider<L> {
     schema_event_stream: Option<Receiver<SchemaEvent>>,
     schema_event_sink: Sender<SchemaEvent>,
     resolver: Arc<L>,
+    mapping_pins: Option<Arc<Mutex<MappingPinStore + Send>>>,
+    placement_rules: Arc<Vec<PlacementRule>>,
+    /// IDs of the subgraphs whose schemas have been sent out.
+    subgraph_ids: Arc<Mutex<HashSet<String>>>,
 }
 
 impl<L: LinkResolver> SubgraphProvider<L> {
@@ -25,32 +33,118 @@ impl<L: LinkResolver> SubgraphProvider<L> {
             schema_event_stream: Some(schema_event_stream),
             schema_event_sink,
             resolver,
+            mapping_pins: None,
+            placement_rules: Arc::new(vec![]),
+            subgraph_ids: Arc::new(Mutex::new(HashSet::new())),
         };
 
         provider
     }
+
+    /// Pins subgraphs to the mappings they are first indexed with in
+    /// `mapping_pins`, and refuses to add them again with other mappings.
+    pub fn with_mapping_pins(mut self, mapping_pins: Arc<Mutex<MappingPinStore + Send>>) -> Self {
+        self.mapping_pins = Some(mapping_pins);
+        self
+    }
+
+    /// Refuses to add subgraphs that `placement_rules` do not place on this
+    /// node.
+    pub fn with_placement_rules(mut self, placement_rules: Vec<PlacementRule>) -> Self {
+        self.placement_rules = Arc::new(placement_rules);
+        self
+    }
+}
+
+/// Checks that `rules` place the subgraph `name` on this node.
+fn check_placement(
+    rules: &[PlacementRule],
+    name: &str,
+    subgraph: &SubgraphManifest,
+) -> Result<(), SubgraphProviderError> {
+    let networks: Vec<&str> = subgraph
+        .data_sources
+        .iter()
+        .filter_map(|data_source| data_source.network.as_ref().map(String::as_str))
+        .collect();
+    match placement_refusal(rules, name, &networks) {
+        Some(refusal) => Err(SubgraphProviderError::PlacementRefused(refusal)),
+        None => Ok(()),
+    }
+}
+
+/// Pins a subgraph to the mappings of `subgraph` the first time it is added.
+/// Afterwards, mappings that differ from the pinned ones are refused, unless
+/// `allow_mapping_changes` is set, in which case they are pinned instead.
+fn pin_mappings(
+    logger: &Logger,
+    store: &Mutex<MappingPinStore + Send>,
+    subgraph: &SubgraphManifest,
+    allow_mapping_changes: bool,
+) -> Result<(), SubgraphProviderError> {
+    let mut store = store.lock().unwrap();
+    let pinned = store
+        .mapping_pins(&subgraph.id)
+        .map_err(SubgraphProviderError::MappingPinError)?;
+    let current = MappingPin::for_manifest(subgraph);
+
+    let changes = mapping_pin_changes(&pinned, &current);
+    if !changes.is_empty() {
+        if !allow_mapping_changes {
+            return Err(SubgraphProviderError::MappingsChanged(changes.join("; ")));
+        }
+        warn!(logger, "Adding subgraph with changed mappings";
+              "subgraph" => &subgraph.id, "changes" => changes.join("; "));
+    } else if !pinned.is_empty() {
+        return Ok(());
+    }
+
+    store
+        .pin_mappings(&subgraph.id, current)
+        .map_err(SubgraphProviderError::MappingPinError)
 }
 
 impl<L: LinkResolver> SubgraphProviderTrait for SubgraphProvider<L> {
     fn add(
         &self,
+        name: String,
         link: String,
+        allow_mapping_changes: bool,
     ) -> Box<Future<Item = (), Error = SubgraphProviderError> + Send + 'static> {
+        let pin_logger = self.logger.clone();
         let send_logger = self.logger.clone();
         let schema_event_sink = self.schema_event_sink.clone();
         let event_sink = self.event_sink.clone();
+        let mapping_pins = self.mapping_pins.clone();
+        let placement_rules = self.placement_rules.clone();
+        let subgraph_ids = self.subgraph_ids.clone();
         Box::new(
             SubgraphManifest::resolve(Link { link }, self.resolver.clone())
                 .map_err(SubgraphProviderError::ResolveError)
+                .and_then(move |subgraph| -> Result<_, SubgraphProviderError> {
+                    check_placement(&placement_rules, &name, &subgraph)?;
+                    if let Some(mapping_pins) = mapping_pins {
+                        pin_mappings(&pin_logger, &mapping_pins, &subgraph, allow_mapping_changes)?;
+                    }
+                    Ok(subgraph)
+                })
                 .and_then(move |mut subgraph| {
                     subgraph
                         .schema
                         .add_subgraph_id_directives(subgraph.id.clone());
 
+                    // Adding a subgraph again replaces its schema
+                    let schema_event = if subgraph_ids.lock().unwrap().insert(subgraph.id.clone())
+                    {
+                        SchemaEvent::SchemaAdded(subgraph.schema.clone())
+                    } else {
+                        SchemaEvent::SchemaUpdated(subgraph.schema.clone())
+                    };
+
                     // Push the subgraph and the schema into their streams
                     let event_logger = send_logger.clone();
                     schema_event_sink
-                        .send(SchemaEvent::SchemaAdded(subgraph.schema.clone()))
+                        .send(schema_event)
                         .map_err(move |e| {
                             error!(send_logger, "Failed to forward subgraph schema: {}", e)
                         })
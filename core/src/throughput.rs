@@ -0,0 +1,182 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use graph::data::usage::current_timestamp;
+use graph::prelude::*;
+use graph::tokio::timer::Interval;
+
+/// How often the throughput of subgraphs is sampled; the store rolls the
+/// samples up per hour, so little is lost when the node stops.
+const SAMPLE_INTERVAL_SECS: u64 = 60;
+
+#[derive(Default)]
+struct Counts {
+    /// Numbers of the blocks the subgraph handled triggers in since the
+    /// previous sample. Each data source handles its triggers in its own
+    /// thread, so a block may be seen more than once and out of order.
+    blocks: HashSet<u64>,
+    triggers: u64,
+    entities: u64,
+    /// Number of the latest block counted in a previous sample.
+    sampled_block: Option<u64>,
+}
+
+struct ThroughputState {
+    /// When the current sample started, in seconds since the Unix epoch.
+    started_at: u64,
+    counts: HashMap<String, Counts>,
+}
+
+/// Counts how many blocks, triggers and entities subgraphs index, and
+/// persists the counts as a sample of their throughput once a minute.
+pub struct IndexingThroughput {
+    logger: Logger,
+    state: Arc<Mutex<ThroughputState>>,
+}
+
+impl IndexingThroughput {
+    pub fn new(logger: &Logger) -> Self {
+        IndexingThroughput {
+            logger: logger.new(o!("component" => "IndexingThroughput")),
+            state: Arc::new(Mutex::new(ThroughputState {
+                started_at: current_timestamp(),
+                counts: HashMap::new(),
+            })),
+        }
+    }
+
+    /// Spawns a task that writes a sample for every subgraph that indexed
+    /// anything to `store` once a minute.
+    pub fn start<S>(&self, store: Arc<Mutex<S>>)
+    where
+        S: ThroughputStore + Send + 'static,
+    {
+        let logger = self.logger.clone();
+        let error_logger = self.logger.clone();
+        let state = self.state.clone();
+        let interval = Duration::from_secs(SAMPLE_INTERVAL_SECS);
+        tokio::spawn(
+            Interval::new(Instant::now() + interval, interval)
+                .map_err(move |e| {
+                    error!(error_logger, "Throughput sampling timer failed";
+                           "error" => e.to_string())
+                })
+                .for_each(move |_| {
+                    let samples = Self::take_samples(&state, current_timestamp());
+                    if let Err(e) = store.lock().unwrap().record_throughput(&samples) {
+                        error!(logger, "Failed to record indexing throughput";
+                               "error" => e.to_string());
+                    }
+                    Ok(())
+                }),
+        );
+    }
+
+    /// Turns the counts since the previous sample into samples that end at
+    /// `now` and starts counting from zero.
+    fn take_samples(state: &Mutex<ThroughputState>, now: u64) -> Vec<ThroughputSample> {
+        let mut state = state.lock().unwrap();
+        let started_at = state.started_at;
+        state.started_at = now;
+        let samples = state
+            .counts
+            .iter_mut()
+            .filter(|&(_, ref counts)| counts.triggers > 0 || counts.entities > 0)
+            .map(|(subgraph_id, counts)| {
+                let sample = ThroughputSample {
+                    subgraph_id: subgraph_id.clone(),
+                    started_at,
+                    seconds: now.saturating_sub(started_at),
+                    blocks: counts.blocks.len() as u64,
+                    triggers: counts.triggers,
+                    entities: counts.entities,
+                };
+                // Keep the latest block, so that a block whose triggers span
+                // two samples is only counted once
+                let latest_block = counts.blocks.iter().cloned().max();
+                counts.sampled_block = latest_block.max(counts.sampled_block);
+                counts.blocks.clear();
+                counts.triggers = 0;
+                counts.entities = 0;
+                sample
+            })
+            .collect();
+        samples
+    }
+}
+
+impl ThroughputCapture for IndexingThroughput {
+    fn record_trigger(&self, subgraph_id: &str, block_number: u64) {
+        let mut state = self.state.lock().unwrap();
+        let counts = state
+            .counts
+            .entry(subgraph_id.to_owned())
+            .or_insert_with(Counts::default);
+        if counts.sampled_block.map_or(true, |sampled| block_number > sampled) {
+            counts.blocks.insert(block_number);
+        }
+        counts.triggers += 1;
+    }
+
+    fn record_entity_write(&self, subgraph_id: &str) {
+        let mut state = self.state.lock().unwrap();
+        state
+            .counts
+            .entry(subgraph_id.to_owned())
+            .or_insert_with(Counts::default)
+            .entities += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IndexingThroughput;
+    use graph::prelude::*;
+
+    #[test]
+    fn samples_count_blocks_triggers_and_entities_per_subgraph() {
+        let throughput = IndexingThroughput::new(&Logger::root(slog::Discard, o!()));
+        throughput.state.lock().unwrap().started_at = 100;
+        throughput.record_trigger("a", 1);
+        throughput.record_entity_write("a");
+        throughput.record_trigger("a", 1);
+        throughput.record_entity_write("a");
+        throughput.record_trigger("a", 2);
+        throughput.record_trigger("b", 2);
+        // Another data source of the subgraph catches up
+        throughput.record_trigger("a", 1);
+
+        let mut samples = IndexingThroughput::take_samples(&throughput.state, 160);
+        samples.sort_by(|a, b| a.subgraph_id.cmp(&b.subgraph_id));
+        assert_eq!(
+            samples,
+            vec![
+                ThroughputSample {
+                    subgraph_id: String::from("a"),
+                    started_at: 100,
+                    seconds: 60,
+                    blocks: 2,
+                    triggers: 4,
+                    entities: 2,
+                },
+                ThroughputSample {
+                    subgraph_id: String::from("b"),
+                    started_at: 100,
+                    seconds: 60,
+                    blocks: 1,
+                    triggers: 1,
+                    entities: 0,
+                },
+            ]
+        );
+
+        // Counting starts over, and a block is not counted again
+        throughput.record_trigger("a", 2);
+        let samples = IndexingThroughput::take_samples(&throughput.state, 220);
+        assert_eq!(samples.len(), 1);
+        assert_eq!(samples[0].started_at, 160);
+        assert_eq!(samples[0].blocks, 0);
+        assert_eq!(samples[0].triggers, 1);
+    }
+}
@@ -0,0 +1,92 @@
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use graph::data::usage::current_timestamp;
+use graph::prelude::*;
+use graph::tokio::timer::Interval;
+
+/// How often deployments are checked against the idle deployment policy.
+const CHECK_INTERVAL_SECS: u64 = 60 * 60;
+
+/// Periodically pauses or removes deployments that have not been queried
+/// for longer than the idle deployment policy allows.
+pub struct IdleDeploymentCleanup;
+
+impl IdleDeploymentCleanup {
+    /// Spawns a task that applies `policy` to the deployments tracked in
+    /// `store` once an hour.
+    pub fn start<S>(logger: &Logger, store: Arc<Mutex<S>>, policy: IdleDeploymentPolicy)
+    where
+        S: DeploymentUsageStore + CompressionStore + Send + 'static,
+    {
+        let logger = logger.new(o!("component" => "IdleDeploymentCleanup"));
+
+        info!(logger, "Cleaning up idle deployments";
+              "max_idle_days" => policy.max_idle_days,
+              "action" => format!("{:?}", policy.action));
+
+        let error_logger = logger.clone();
+        tokio::spawn(
+            Interval::new(Instant::now(), Duration::from_secs(CHECK_INTERVAL_SECS))
+                .map_err(move |e| {
+                    error!(error_logger, "Idle deployment cleanup timer failed";
+                           "error" => e.to_string())
+                })
+                .for_each(move |_| {
+                    Self::apply_policy(&logger, &mut *store.lock().unwrap(), policy);
+                    Ok(())
+                }),
+        );
+    }
+
+    /// Pauses or removes all deployments in `store` that the policy applies to.
+    pub fn apply_policy<S>(logger: &Logger, store: &mut S, policy: IdleDeploymentPolicy)
+    where
+        S: DeploymentUsageStore + CompressionStore,
+    {
+        let usage = match store.deployment_usage() {
+            Ok(usage) => usage,
+            Err(e) => {
+                error!(logger, "Failed to load deployment usage"; "error" => e.to_string());
+                return;
+            }
+        };
+
+        // Paused deployments stay idle, but there is nothing left to pause
+        let paused: HashSet<String> = match store.compressed_subgraphs() {
+            Ok(compressed) => compressed
+                .into_iter()
+                .map(|compressed| compressed.subgraph_id)
+                .collect(),
+            Err(e) => {
+                error!(logger, "Failed to load compressed subgraphs"; "error" => e.to_string());
+                return;
+            }
+        };
+
+        let now = current_timestamp();
+        for usage in usage.iter().filter(|usage| policy.applies_to(usage, now)) {
+            let subgraph_id = usage.subgraph_id.as_str();
+            let idle_days = usage.idle_seconds(now) / (24 * 60 * 60);
+
+            match policy.action {
+                IdleDeploymentAction::Pause if paused.contains(subgraph_id) => {}
+                IdleDeploymentAction::Pause => match store.compress_subgraph(subgraph_id) {
+                    Ok(_) => info!(logger, "Paused idle deployment";
+                                   "subgraph" => subgraph_id, "idle_days" => idle_days),
+                    Err(e) => error!(logger, "Failed to pause idle deployment";
+                                     "subgraph" => subgraph_id, "error" => e.to_string()),
+                },
+                IdleDeploymentAction::Remove => match store.delete_subgraph(subgraph_id) {
+                    Ok(entities) => info!(logger, "Removed idle deployment";
+                                          "subgraph" => subgraph_id,
+                                          "idle_days" => idle_days,
+                                          "entities" => entities),
+                    Err(e) => error!(logger, "Failed to remove idle deployment";
+                                     "subgraph" => subgraph_id, "error" => e.to_string()),
+                },
+            }
+        }
+    }
+}
@@ -1,15 +1,21 @@
 extern crate ethabi;
+extern crate ethereum_types;
 extern crate graph;
 extern crate graph_core;
+extern crate graph_graphql;
 extern crate graph_mock;
 extern crate graph_runtime_wasm;
+extern crate graphql_parser;
 extern crate ipfs_api;
 
+use ethereum_types::H256;
 use graph::components::ethereum::*;
 use graph::prelude::*;
-use graph_core::RuntimeManager;
-use graph_mock::FakeStore;
+use graph_core::{QueryEngine, RuntimeManager};
+use graph_graphql::prelude::object_value;
+use graph_mock::{FakeStore, MockStore};
 use graph_runtime_wasm::RuntimeHostBuilder;
+use graphql_parser::query as q;
 use ipfs_api::IpfsClient;
 use std::fs::read_to_string;
 use std::io::Cursor;
@@ -31,6 +37,21 @@ fn multiple_data_sources_per_subgraph() {
             unimplemented!()
         }
 
+        fn transaction_fees(
+            &mut self,
+            _transaction_hash: H256,
+            _block_hash: H256,
+        ) -> Box<Future<Item = EthereumTransactionFees, Error = EthereumTransactionError>> {
+            unimplemented!()
+        }
+
+        fn block_header(
+            &mut self,
+            _block_id: BlockId,
+        ) -> Box<Future<Item = EthereumBlockHeader, Error = EthereumBlockError>> {
+            unimplemented!()
+        }
+
         fn subscribe_to_event(
             &mut self,
             subscription: EthereumEventSubscription,
@@ -132,3 +153,96 @@ fn multiple_data_sources_per_subgraph() {
         }))
         .unwrap();
 }
+
+#[test]
+fn runs_queries_without_the_server() {
+    let logger = Logger::root(slog::Discard, o!());
+    let store = Arc::new(Mutex::new(MockStore::new(&logger)));
+    let schema = Schema {
+        id: String::from("users"),
+        document: graphql_parser::parse_schema("type User @entity { id: ID!, name: String! }")
+            .expect("Invalid test schema"),
+    };
+    let engine = QueryEngine::new(&logger, store, schema).expect("Invalid schema");
+
+    let result = engine
+        .execute("{ users { name } }", None)
+        .expect("Failed to run query");
+    assert!(result.errors.is_none());
+    let user = |name: &str| object_value(vec![("name", q::Value::String(name.to_owned()))]);
+    assert_eq!(
+        result.data,
+        Some(object_value(vec![(
+            "users",
+            q::Value::List(vec![user("Joe"), user("Jeff"), user("Linda")]),
+        )]))
+    );
+
+    // Queries that can't be parsed fail
+    assert!(engine.execute("{ users {", None).is_err());
+
+    // Queries can't be run at different blocks at once
+    assert!(
+        engine
+            .execute("{ a: users(block: { number: 1 }) { name } b: users { name } }", None)
+            .is_err()
+    );
+
+    // Queries at a block need to know which block the subgraph is at
+    assert!(
+        engine
+            .execute("{ users(block: { number: 10 }) { name } }", None)
+            .is_err()
+    );
+}
+
+#[test]
+fn runs_queries_at_a_block_without_the_server() {
+    struct IndexedAt(u64);
+
+    impl IndexingLagStatus for IndexedAt {
+        fn indexing_lags(&self) -> Vec<IndexingLag> {
+            vec![]
+        }
+
+        fn indexed_block(&self, _subgraph_id: &str) -> Option<EthereumBlockHeader> {
+            Some(EthereumBlockHeader {
+                hash: H256::from(self.0),
+                number: self.0,
+                timestamp: 0,
+            })
+        }
+    }
+
+    let logger = Logger::root(slog::Discard, o!());
+    let store = Arc::new(Mutex::new(MockStore::new(&logger)));
+    let schema = Schema {
+        id: String::from("users"),
+        document: graphql_parser::parse_schema("type User @entity { id: ID!, name: String! }")
+            .expect("Invalid test schema"),
+    };
+    let engine = QueryEngine::new(&logger, store, schema)
+        .expect("Invalid schema")
+        .with_indexing_status(Arc::new(IndexedAt(10)));
+
+    // The subgraph is at the block the query is pinned to
+    let result = engine
+        .execute("{ users(block: { number: 10 }) { name } }", None)
+        .expect("Failed to run query");
+    assert!(result.errors.is_none());
+    let user = |name: &str| object_value(vec![("name", q::Value::String(name.to_owned()))]);
+    assert_eq!(
+        result.data,
+        Some(object_value(vec![(
+            "users",
+            q::Value::List(vec![user("Joe"), user("Jeff"), user("Linda")]),
+        )]))
+    );
+
+    // The store keeps no history of older blocks, and the subgraph hasn't
+    // reached newer ones yet
+    for number in &[9, 11] {
+        let query = format!("{{ users(block: {{ number: {} }}) {{ name }} }}", number);
+        assert!(engine.execute(&query, None).is_err());
+    }
+}
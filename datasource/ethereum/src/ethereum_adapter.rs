@@ -1,17 +1,24 @@
-use ethabi::{RawLog, Token};
+use ethabi::{self, ParamType, RawLog, Token};
 use ethereum_types::H256;
 use futures::future;
 use futures::prelude::*;
 use futures::stream::iter_ok;
+use jsonrpc_core;
 use std::sync::Arc;
 use std::time::Duration;
 use web3;
 use web3::api::CreateFilter;
 use web3::api::{Eth, Web3};
-use web3::helpers::CallResult;
+use web3::error::{Error as Web3Error, ErrorKind as Web3ErrorKind};
+use web3::helpers::{self, CallResult};
 use web3::types::*;
 
 use graph::components::ethereum::{EthereumAdapter as EthereumAdapterTrait, *};
+use graph::serde_json;
+
+/// Selector of `Error(string)`, the function that Solidity encodes the
+/// reasons given to `revert` and `require` as.
+const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
 
 pub struct EthereumAdapterConfig<T: web3::Transport> {
     pub transport: T,
@@ -109,13 +116,82 @@ impl<T: web3::Transport + Send + Sync + 'static> EthereumAdapterTrait for Ethere
                             .number
                             .map(|number| number.as_u64())
                             .map(BlockNumber::Number),
-                    ).map_err(EthereumContractCallError::from)
+                    ).map_err(call_error)
                 })
-                // Decode the return values according to the ABI
-                .and_then(move |output| {
-                    call.function
+                // Decode the return values according to the ABI, unless the
+                // call reverted
+                .and_then(move |output| match decode_revert_reason(&output.0) {
+                    Some(reason) => Err(EthereumContractCallError::Revert(reason)),
+                    // Nodes like Geth return no data at all for calls that
+                    // revert without a reason
+                    None if output.0.is_empty() && !call.function.outputs.is_empty() => {
+                        Err(EthereumContractCallError::Revert(String::new()))
+                    }
+                    None => call
+                        .function
                         .decode_output(&output.0)
-                        .map_err(EthereumContractCallError::from)
+                        .map_err(EthereumContractCallError::from),
+                }),
+        )
+    }
+
+    fn transaction_fees(
+        &mut self,
+        transaction_hash: H256,
+        block_hash: H256,
+    ) -> Box<Future<Item = EthereumTransactionFees, Error = EthereumTransactionError>> {
+        let eth = self.eth_client.eth();
+        let transaction = eth.transaction(TransactionId::Hash(transaction_hash));
+        let receipt = eth.transaction_receipt(transaction_hash);
+
+        // Blocks as web3 knows them have no base fee yet, so it is read from
+        // the raw block
+        let base_fee_per_gas = self
+            .eth_client
+            .transport()
+            .execute(
+                "eth_getBlockByHash",
+                vec![helpers::serialize(&block_hash), helpers::serialize(&false)],
+            )
+            .map(|block| {
+                block
+                    .get("baseFeePerGas")
+                    .cloned()
+                    .and_then(|base_fee| serde_json::from_value::<U256>(base_fee).ok())
+            });
+
+        Box::new(
+            transaction
+                .join3(receipt, base_fee_per_gas)
+                .map_err(EthereumTransactionError::from)
+                .and_then(move |(transaction, receipt, base_fee_per_gas)| {
+                    match (transaction, receipt) {
+                        (Some(transaction), Some(receipt)) => Ok(EthereumTransactionFees {
+                            gas_price: transaction.gas_price,
+                            gas_used: receipt.gas_used,
+                            base_fee_per_gas,
+                        }),
+                        _ => Err(EthereumTransactionError::NotFound(transaction_hash)),
+                    }
+                }),
+        )
+    }
+
+    fn block_header(
+        &mut self,
+        block_id: BlockId,
+    ) -> Box<Future<Item = EthereumBlockHeader, Error = EthereumBlockError>> {
+        Box::new(
+            Self::block(self.eth_client.eth(), block_id.clone())
+                .map_err(EthereumBlockError::from)
+                .and_then(move |block| match (block.hash, block.number) {
+                    // Pending blocks have neither a hash nor a number yet
+                    (Some(hash), Some(number)) => Ok(EthereumBlockHeader {
+                        hash,
+                        number: number.as_u64(),
+                        timestamp: block.timestamp.as_u64(),
+                    }),
+                    _ => Err(EthereumBlockError::NotFound(block_id)),
                 }),
         )
     }
@@ -153,7 +229,11 @@ impl<T: web3::Transport + Send + Sync + 'static> EthereumAdapterTrait for Ethere
                     address: log.address,
                     event_signature: log.topics[0],
                     block_hash: log.block_hash.unwrap(),
+                    block_number: log.block_number.unwrap().as_u64(),
+                    transaction_hash: log.transaction_hash.unwrap(),
                     params: log_data.params,
+                    log_index: log.log_index.unwrap(),
+                    transaction_log_index: log.transaction_log_index.unwrap(),
                     removed: log.is_removed(),
                 }),
         )
@@ -163,3 +243,42 @@ impl<T: web3::Transport + Send + Sync + 'static> EthereumAdapterTrait for Ethere
         false
     }
 }
+
+/// Turns the errors that nodes like Parity report reverted calls with, whose
+/// data is `Reverted 0x...`, into `Revert` errors.
+fn call_error(e: Web3Error) -> EthereumContractCallError {
+    let reason = match e.kind() {
+        Web3ErrorKind::Rpc(rpc_error) => match rpc_error.data {
+            Some(jsonrpc_core::Value::String(ref data)) if data.starts_with("Reverted") => {
+                let data = data.trim_left_matches("Reverted").trim().to_owned();
+                let reason = serde_json::from_value::<Bytes>(jsonrpc_core::Value::String(data))
+                    .ok()
+                    .and_then(|data| decode_revert_reason(&data.0));
+                Some(reason.unwrap_or_default())
+            }
+            _ => None,
+        },
+        _ => None,
+    };
+
+    match reason {
+        Some(reason) => EthereumContractCallError::Revert(reason),
+        None => EthereumContractCallError::from(e),
+    }
+}
+
+/// Decodes the reason of a call that reverted with `Error(string)` from the
+/// data it returned.
+fn decode_revert_reason(data: &[u8]) -> Option<String> {
+    if data.len() < 4 || data[..4] != ERROR_SELECTOR {
+        return None;
+    }
+
+    match ethabi::decode(&[ParamType::String], &data[4..]) {
+        Ok(mut tokens) => match tokens.pop() {
+            Some(Token::String(reason)) => Some(reason),
+            _ => None,
+        },
+        Err(_) => None,
+    }
+}
@@ -9,7 +9,9 @@ extern crate web3;
 use ethabi::{Function, Param, ParamType, Token};
 use futures::prelude::*;
 use futures::{failed, finished};
-use graph::components::ethereum::EthereumContractCall;
+use graph::components::ethereum::{
+    EthereumContractCall, EthereumContractCallError, EthereumTransactionFees,
+};
 use graph::prelude::EthereumAdapter as EthereumAdapterTrait;
 use graph::serde_json;
 use graph_datasource_ethereum::{EthereumAdapter, EthereumAdapterConfig};
@@ -117,17 +119,7 @@ impl TestTransport {
     }
 }
 
-#[test]
-fn contract_call() {
-    let mut transport = TestTransport::default();
-
-    transport.add_response(serde_json::to_value(mock_block()).unwrap());
-    transport.add_response(jsonrpc_core::Value::String(format!(
-        "{:?}",
-        H256::from(100000)
-    )));
-
-    let mut adapter = EthereumAdapter::new(EthereumAdapterConfig { transport });
+fn balance_of_call() -> EthereumContractCall {
     let balance_of = Function {
         name: "balanceOf".to_owned(),
         inputs: vec![Param {
@@ -143,13 +135,104 @@ fn contract_call() {
     let function = Function::from(balance_of);
     let gnt_addr = Address::from_str("eF7FfF64389B814A946f3E92105513705CA6B990").unwrap();
     let holder_addr = Address::from_str("00d04c4b12C4686305bb4F4fC93487CdFBa62580").unwrap();
-    let call = EthereumContractCall {
+    EthereumContractCall {
         address: gnt_addr,
         block_id: BlockId::Number(BlockNumber::Latest),
         function: function,
         args: vec![Token::Address(holder_addr)],
-    };
-    let call_result = adapter.contract_call(call).wait().unwrap();
+    }
+}
+
+#[test]
+fn contract_call() {
+    let mut transport = TestTransport::default();
+
+    transport.add_response(serde_json::to_value(mock_block()).unwrap());
+    transport.add_response(jsonrpc_core::Value::String(format!(
+        "{:?}",
+        H256::from(100000)
+    )));
+
+    let mut adapter = EthereumAdapter::new(EthereumAdapterConfig { transport });
+    let call_result = adapter.contract_call(balance_of_call()).wait().unwrap();
 
     assert_eq!(call_result[0], Token::Uint(U256::from(100000)));
 }
+
+#[test]
+fn contract_call_reverted_with_reason() {
+    let mut transport = TestTransport::default();
+
+    // `Error(string)` selector followed by the ABI-encoded reason
+    let mut output = vec![0x08, 0xc3, 0x79, 0xa0];
+    output.extend(ethabi::encode(&[Token::String("Unknown owner".to_owned())]));
+    let output = output
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+
+    transport.add_response(serde_json::to_value(mock_block()).unwrap());
+    transport.add_response(jsonrpc_core::Value::String(format!("0x{}", output)));
+
+    let mut adapter = EthereumAdapter::new(EthereumAdapterConfig { transport });
+    match adapter.contract_call(balance_of_call()).wait() {
+        Err(EthereumContractCallError::Revert(reason)) => assert_eq!(reason, "Unknown owner"),
+        result => panic!("Expected the call to revert, got {:?}", result),
+    }
+}
+
+#[test]
+fn transaction_fees() {
+    let mut transport = TestTransport::default();
+
+    transport.add_response(
+        serde_json::from_str(
+            r#"{
+                "hash": "0x0000000000000000000000000000000000000000000000000000000000000001",
+                "nonce": "0x0",
+                "blockHash": "0x0000000000000000000000000000000000000000000000000000000000000002",
+                "blockNumber": "0x1",
+                "transactionIndex": "0x0",
+                "from": "0x0000000000000000000000000000000000000000",
+                "to": null,
+                "value": "0x0",
+                "gasPrice": "0x3b9aca00",
+                "gas": "0x5208",
+                "input": "0x"
+            }"#,
+        ).unwrap(),
+    );
+    transport.add_response(
+        serde_json::from_str(
+            r#"{
+                "transactionHash": "0x0000000000000000000000000000000000000000000000000000000000000001",
+                "transactionIndex": "0x0",
+                "blockHash": "0x0000000000000000000000000000000000000000000000000000000000000002",
+                "blockNumber": "0x1",
+                "cumulativeGasUsed": "0x5208",
+                "gasUsed": "0x5208",
+                "contractAddress": null,
+                "logs": [],
+                "status": "0x1"
+            }"#,
+        ).unwrap(),
+    );
+    let mut block = serde_json::to_value(mock_block()).unwrap();
+    block["baseFeePerGas"] = serde_json::Value::String("0x7".to_owned());
+    transport.add_response(block);
+
+    let mut adapter = EthereumAdapter::new(EthereumAdapterConfig { transport });
+    let fees = adapter
+        .transaction_fees(H256::from(1), H256::from(2))
+        .wait()
+        .unwrap();
+
+    assert_eq!(
+        fees,
+        EthereumTransactionFees {
+            gas_price: U256::from(1_000_000_000),
+            gas_used: U256::from(21_000),
+            base_fee_per_gas: Some(U256::from(7)),
+        }
+    );
+}
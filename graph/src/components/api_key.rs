@@ -0,0 +1,24 @@
+use data::api_key::{ApiKey, ApiKeyUsage};
+use util::error::GraphError;
+
+/// Common trait for stores that keep track of API keys and their usage.
+pub trait ApiKeyStore {
+    /// Issues a new API key for the client with the given name.
+    fn create_api_key(&mut self, name: String) -> Result<ApiKey, GraphError>;
+
+    /// Looks up an API key. Returns `None` if the key does not exist.
+    fn api_key(&self, key: &str) -> Result<Option<ApiKey>, GraphError>;
+
+    /// Lists all API keys.
+    fn api_keys(&self) -> Result<Vec<ApiKey>, GraphError>;
+
+    /// Renames the client an API key was issued to. Returns `false` if the
+    /// key does not exist.
+    fn rename_api_key(&mut self, key: &str, name: String) -> Result<bool, GraphError>;
+
+    /// Revokes an API key. Returns `false` if the key does not exist.
+    fn delete_api_key(&mut self, key: &str) -> Result<bool, GraphError>;
+
+    /// Adds usage to an API key.
+    fn record_api_key_usage(&mut self, key: &str, usage: ApiKeyUsage) -> Result<(), GraphError>;
+}
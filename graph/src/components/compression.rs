@@ -0,0 +1,22 @@
+use data::compression::CompressedSubgraph;
+use util::error::GraphError;
+
+/// Common trait for stores that can move the entities of dormant subgraphs
+/// into compressed storage.
+pub trait CompressionStore {
+    /// Moves all entities of a subgraph into compressed storage. They are
+    /// decompressed again as soon as the subgraph is accessed.
+    fn compress_subgraph(&mut self, subgraph_id: &str) -> Result<CompressedSubgraph, GraphError>;
+
+    /// Moves the entities of a subgraph back out of compressed storage.
+    /// Returns `false` if the subgraph is not compressed.
+    fn decompress_subgraph(&mut self, subgraph_id: &str) -> Result<bool, GraphError>;
+
+    /// Deletes all entities of a subgraph, whether they are compressed or
+    /// not, and stops tracking its usage. Returns the number of entities
+    /// deleted.
+    fn delete_subgraph(&mut self, subgraph_id: &str) -> Result<u64, GraphError>;
+
+    /// Lists all compressed subgraphs.
+    fn compressed_subgraphs(&self) -> Result<Vec<CompressedSubgraph>, GraphError>;
+}
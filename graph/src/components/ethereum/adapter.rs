@@ -1,5 +1,5 @@
 use ethabi::{Bytes, Error as ABIError, Event, Function, LogParam, ParamType, Token};
-use ethereum_types::{Address, H256};
+use ethereum_types::{Address, H256, U256};
 use failure::SyncFailure;
 use futures::{Future, Stream};
 use web3::error::Error as Web3Error;
@@ -40,6 +40,10 @@ pub enum EthereumContractCallError {
     /// `Token` is not of expected `ParamType`
     #[fail(display = "type mismatch, token {:?} is not of kind {:?}", _0, _1)]
     TypeError(Token, ParamType),
+    /// The call reverted with the given reason, which is empty if the
+    /// contract did not give one
+    #[fail(display = "call reverted: {}", _0)]
+    Revert(String),
 }
 
 impl From<Web3Error> for EthereumContractCallError {
@@ -54,6 +58,52 @@ impl From<ABIError> for EthereumContractCallError {
     }
 }
 
+/// The fees paid by a transaction, as of the block it was included in.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EthereumTransactionFees {
+    pub gas_price: U256,
+    pub gas_used: U256,
+    /// Base fee of the block, which only blocks since the London fork have.
+    pub base_fee_per_gas: Option<U256>,
+}
+
+#[derive(Fail, Debug)]
+pub enum EthereumTransactionError {
+    #[fail(display = "RPC error: {}", _0)]
+    RpcError(SyncFailure<Web3Error>),
+    #[fail(display = "transaction {:x} not found", _0)]
+    NotFound(H256),
+}
+
+impl From<Web3Error> for EthereumTransactionError {
+    fn from(err: Web3Error) -> EthereumTransactionError {
+        EthereumTransactionError::RpcError(SyncFailure::new(err))
+    }
+}
+
+/// The number and timestamp of a block.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EthereumBlockHeader {
+    pub hash: H256,
+    pub number: u64,
+    /// When the block was mined, in seconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+#[derive(Fail, Debug)]
+pub enum EthereumBlockError {
+    #[fail(display = "RPC error: {}", _0)]
+    RpcError(SyncFailure<Web3Error>),
+    #[fail(display = "block {:?} not found", _0)]
+    NotFound(BlockId),
+}
+
+impl From<Web3Error> for EthereumBlockError {
+    fn from(err: Web3Error) -> EthereumBlockError {
+        EthereumBlockError::RpcError(SyncFailure::new(err))
+    }
+}
+
 #[derive(Fail, Debug)]
 pub enum EthereumSubscriptionError {
     #[fail(display = "RPC error: {}", _0)]
@@ -97,7 +147,17 @@ pub struct EthereumEvent {
     pub address: Address,
     pub event_signature: H256,
     pub block_hash: H256,
+    /// Number of the block the log was included in.
+    pub block_number: u64,
+    /// Hash of the transaction that emitted the log.
+    pub transaction_hash: H256,
     pub params: Vec<LogParam>,
+    /// Position of the log in the block.
+    pub log_index: U256,
+    /// Position of the log in the transaction that emitted it.
+    pub transaction_log_index: U256,
+    /// Whether the log was removed from the chain by a reorg. Removed logs
+    /// are never passed to event handlers.
     pub removed: bool,
 }
 
@@ -112,6 +172,19 @@ pub trait EthereumAdapter: Send + 'static {
         call: EthereumContractCall,
     ) -> Box<Future<Item = Vec<Token>, Error = EthereumContractCallError>>;
 
+    /// Look up the fees paid by a transaction of the block with the given hash.
+    fn transaction_fees(
+        &mut self,
+        transaction_hash: H256,
+        block_hash: H256,
+    ) -> Box<Future<Item = EthereumTransactionFees, Error = EthereumTransactionError>>;
+
+    /// Look up the number and timestamp of a block, e.g. of the latest block.
+    fn block_header(
+        &mut self,
+        block_id: BlockId,
+    ) -> Box<Future<Item = EthereumBlockHeader, Error = EthereumBlockError>>;
+
     /// Subscribe to an event of a smart contract.
     fn subscribe_to_event(
         &mut self,
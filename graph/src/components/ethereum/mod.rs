@@ -1,11 +1,13 @@
 mod adapter;
 
 pub use self::adapter::{
-    BlockNumberRange, EthereumAdapter, EthereumContractCall, EthereumContractCallError,
-    EthereumContractState, EthereumContractStateError, EthereumContractStateRequest, EthereumEvent,
-    EthereumEventSubscription, EthereumSubscriptionError,
+    BlockNumberRange, EthereumAdapter, EthereumBlockError, EthereumBlockHeader,
+    EthereumContractCall, EthereumContractCallError, EthereumContractState,
+    EthereumContractStateError, EthereumContractStateRequest, EthereumEvent,
+    EthereumEventSubscription, EthereumSubscriptionError, EthereumTransactionError,
+    EthereumTransactionFees,
 };
 
-pub use web3::types::BlockNumber;
+pub use web3::types::{BlockId, BlockNumber};
 
 pub use ethabi::{Contract, Event};
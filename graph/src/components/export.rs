@@ -0,0 +1,10 @@
+use data::store::Entity;
+use util::error::GraphError;
+
+/// Common trait for stores that can dump all entities of a subgraph, e.g.
+/// to back them up or to move them elsewhere.
+pub trait EntityExportStore {
+    /// Returns the latest version of all entities of a subgraph, with their
+    /// types in `__typename`, ordered by type and ID.
+    fn export_entities(&self, subgraph_id: &str) -> Result<Vec<Entity>, GraphError>;
+}
@@ -0,0 +1,15 @@
+use data::schema::FulltextDefinition;
+use util::error::GraphError;
+
+/// Common trait for stores that keep search documents for the full-text
+/// searches that subgraphs declare with `@fulltext` on their entity types.
+pub trait FulltextStore {
+    /// Sets the full-text searches of a subgraph. From then on, the search
+    /// documents of entities are updated whenever the entities are written.
+    /// Existing entities that have no document yet get one right away.
+    fn set_fulltext_definitions(
+        &mut self,
+        subgraph_id: &str,
+        definitions: Vec<FulltextDefinition>,
+    ) -> Result<(), GraphError>;
+}
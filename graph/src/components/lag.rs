@@ -0,0 +1,13 @@
+use components::ethereum::EthereumBlockHeader;
+use data::lag::IndexingLag;
+
+/// Common trait for components that keep track of how far subgraphs are
+/// behind the chain head.
+pub trait IndexingLagStatus {
+    /// Lists the latest lag measured for each subgraph.
+    fn indexing_lags(&self) -> Vec<IndexingLag>;
+
+    /// Returns the latest block a subgraph wrote entities for, if it has been
+    /// looked up and the subgraph hasn't written entities since.
+    fn indexed_block(&self, subgraph_id: &str) -> Option<EthereumBlockHeader>;
+}
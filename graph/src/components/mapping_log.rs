@@ -0,0 +1,13 @@
+use data::mapping_log::MappingLogEntry;
+
+/// Common trait for components that keep the recent log messages of
+/// mappings, so that subgraph authors can see them without access to the
+/// node logs.
+pub trait MappingLogCapture {
+    /// Keeps a message logged by a mapping.
+    fn capture(&self, entry: MappingLogEntry);
+
+    /// Lists the latest messages of a subgraph that are still kept, oldest
+    /// first, limited to the last `limit` messages if given.
+    fn mapping_logs(&self, subgraph_id: &str, limit: Option<usize>) -> Vec<MappingLogEntry>;
+}
@@ -0,0 +1,14 @@
+use data::mapping_pin::MappingPin;
+use util::error::GraphError;
+
+/// Common trait for stores that record the mappings that subgraph
+/// deployments are indexed with.
+pub trait MappingPinStore {
+    /// Returns the mappings that a deployment is pinned to; empty if it was
+    /// never indexed.
+    fn mapping_pins(&self, subgraph_id: &str) -> Result<Vec<MappingPin>, GraphError>;
+
+    /// Pins a deployment to the given mappings, replacing the mappings it was
+    /// pinned to before.
+    fn pin_mappings(&mut self, subgraph_id: &str, pins: Vec<MappingPin>) -> Result<(), GraphError>;
+}
@@ -0,0 +1,7 @@
+use data::metrics::StoreLatency;
+
+/// Common trait for stores that measure how long accessing entities takes.
+pub trait StoreMetrics {
+    /// Lists the latencies of each store operation, per deployment.
+    fn store_latencies(&self) -> Vec<StoreLatency>;
+}
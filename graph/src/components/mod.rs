@@ -35,6 +35,33 @@
 
 use futures::prelude::*;
 
+/// Components dealing with API keys.
+pub mod api_key;
+
+/// Components dealing with compressed storage of subgraphs.
+pub mod compression;
+
+/// Components dealing with exporting the entities of subgraphs.
+pub mod export;
+
+/// Components dealing with full-text search.
+pub mod fulltext;
+
+/// Components dealing with how far subgraphs are behind the chain head.
+pub mod lag;
+
+/// Components dealing with messages logged by mappings.
+pub mod mapping_log;
+
+/// Components dealing with the mappings that deployments are pinned to.
+pub mod mapping_pin;
+
+/// Components dealing with store metrics.
+pub mod metrics;
+
+/// Components dealing with entities written by offchain handlers.
+pub mod offchain;
+
 /// Components dealing with subgraphs.
 pub mod subgraph;
 
@@ -44,12 +71,27 @@ pub mod ethereum;
 /// Components dealing with processing GraphQL queries.
 pub mod query;
 
+/// Components dealing with pruning entities after their retention period.
+pub mod retention;
+
+/// Components dealing with rewinding subgraphs to earlier blocks.
+pub mod rewind;
+
 /// Components dealing with serving GraphQL over the network.
 pub mod server;
 
 /// Components dealing with storing entities.
 pub mod store;
 
+/// Components dealing with indexing throughput.
+pub mod throughput;
+
+/// Components dealing with the usage of subgraph deployments.
+pub mod usage;
+
+/// Components dealing with versions of entities.
+pub mod version;
+
 pub mod link_resolver;
 
 /// Plug the outputs of `output` of type `E` to the matching inputs in `input`.
@@ -0,0 +1,27 @@
+use components::store::StoreKey;
+use data::store::Entity;
+use util::error::GraphError;
+
+/// Common trait for stores that keep the entities written by offchain
+/// handlers, e.g. from files on IPFS, in a causality region of their own,
+/// apart from the entities written for blocks.
+///
+/// Reading an entity merges the attributes set offchain into the entity
+/// with the same key that was written onchain, with the onchain attributes
+/// taking precedence. Offchain data thus never changes the onchain state,
+/// and can be dropped and fetched again at any time.
+pub trait OffchainEntityStore {
+    /// Merges `entity` into the entity with the given key that was fetched
+    /// from `source`, or inserts it.
+    fn set_offchain(
+        &mut self,
+        key: StoreKey,
+        entity: Entity,
+        source: String,
+    ) -> Result<(), GraphError>;
+
+    /// Drops the offchain entities of a subgraph that were fetched from
+    /// `source`, or all of them if `source` is `None`, so that they can be
+    /// fetched again. Returns how many entities were dropped.
+    fn drop_offchain(&mut self, subgraph_id: &str, source: Option<&str>) -> Result<u64, GraphError>;
+}
@@ -1,9 +1,18 @@
-use futures::sync::mpsc::Sender;
+use futures::Future;
 
-use data::query::Query;
+use data::query::{Query, QueryError, QueryResponse, QueryTarget};
+
+/// Future returned by [QueryRunner::run_query](trait.QueryRunner.html#tymethod.run_query).
+pub type QueryResponseFuture = Box<Future<Item = QueryResponse, Error = QueryError> + Send>;
 
 /// Common trait for query runners that run queries against a [Store](../store/trait.Store.html).
 pub trait QueryRunner {
-    // Sender to which others can write queries that need to be run.
-    fn query_sink(&mut self) -> Sender<Query>;
+    /// Runs a query against the subgraph (and, optionally, block) identified
+    /// by `target`.
+    fn run_query(&self, query: Query, target: QueryTarget) -> QueryResponseFuture;
+
+    /// Number of the latest block that queries can be run against, if known.
+    fn latest_block_number(&self) -> Option<u64> {
+        None
+    }
 }
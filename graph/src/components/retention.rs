@@ -0,0 +1,15 @@
+use util::error::GraphError;
+
+/// Common trait for stores that can prune entities that have outlived the
+/// retention period of their type.
+pub trait EntityRetentionStore {
+    /// Deletes the entities of a type that were last updated before
+    /// `updated_before`, in seconds since the Unix epoch, along with their
+    /// history. Returns the number of entities deleted.
+    fn prune_entities(
+        &mut self,
+        subgraph_id: &str,
+        entity_type: &str,
+        updated_before: u64,
+    ) -> Result<u64, GraphError>;
+}
@@ -0,0 +1,10 @@
+use util::error::GraphError;
+
+/// Common trait for stores that can undo the entity changes of the latest
+/// blocks of a subgraph.
+pub trait RewindStore {
+    /// Reverts all changes that a subgraph made to its entities in blocks
+    /// after `block_number`, and forgets these blocks. Returns the number
+    /// of blocks reverted.
+    fn rewind_subgraph(&mut self, subgraph_id: &str, block_number: u64) -> Result<u64, GraphError>;
+}
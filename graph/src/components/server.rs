@@ -1,17 +1,24 @@
 use futures::prelude::*;
-use futures::sync::mpsc::{Receiver, Sender};
+use futures::sync::mpsc::Sender;
 use futures::sync::oneshot::Canceled;
 use serde::ser::*;
 use std::error::Error;
 use std::fmt;
 use std::io;
-use std::sync::Arc;
-
-use super::store::StoreEvent;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use super::api_key::ApiKeyStore;
+use super::compression::CompressionStore;
+use super::lag::IndexingLagStatus;
+use super::mapping_log::MappingLogCapture;
+use super::metrics::StoreMetrics;
+use super::store::{BasicStore, StoreEvent};
 use super::subgraph::{SchemaEvent, SubgraphProvider};
-use data::query::{Query, QueryError};
+use super::throughput::ThroughputStore;
+use super::usage::DeploymentUsageStore;
+use data::query::QueryError;
 use prelude::Logger;
-use util::stream::StreamError;
 
 /// Errors that can occur while processing incoming requests.
 #[derive(Debug)]
@@ -20,6 +27,10 @@ pub enum GraphQLServerError {
     ClientError(String),
     QueryError(QueryError),
     InternalError(String),
+    NotFound(String),
+    Unauthorized(String),
+    Forbidden(String),
+    Unavailable(String),
 }
 
 impl From<Canceled> for GraphQLServerError {
@@ -53,6 +64,10 @@ impl fmt::Display for GraphQLServerError {
             &GraphQLServerError::ClientError(ref s) => write!(f, "{}", s),
             &GraphQLServerError::QueryError(ref e) => write!(f, "{}", e),
             &GraphQLServerError::InternalError(ref s) => write!(f, "{}", s),
+            &GraphQLServerError::NotFound(ref s) => write!(f, "{}", s),
+            &GraphQLServerError::Unauthorized(ref s) => write!(f, "{}", s),
+            &GraphQLServerError::Forbidden(ref s) => write!(f, "{}", s),
+            &GraphQLServerError::Unavailable(ref s) => write!(f, "{}", s),
         }
     }
 }
@@ -68,6 +83,10 @@ impl Error for GraphQLServerError {
             &GraphQLServerError::ClientError(_) => None,
             &GraphQLServerError::QueryError(ref e) => Some(e),
             &GraphQLServerError::InternalError(_) => None,
+            &GraphQLServerError::NotFound(_) => None,
+            &GraphQLServerError::Unauthorized(_) => None,
+            &GraphQLServerError::Forbidden(_) => None,
+            &GraphQLServerError::Unavailable(_) => None,
         }
     }
 }
@@ -100,23 +119,29 @@ pub trait GraphQLServer {
     /// subscription queries to re-run.
     fn store_event_sink(&mut self) -> Sender<StoreEvent>;
 
-    /// Receiver from which others can read incoming queries for processing.
-    /// Can only be called once. Any consecutive call will result in a StreamError.
-    fn query_stream(&mut self) -> Result<Receiver<Query>, StreamError>;
-
-    /// Creates a new Tokio task that, when spawned, brings up the GraphQL server.
+    /// Creates a new Tokio task that, when spawned, brings up the GraphQL server
+    /// on all of the given addresses.
     fn serve(
         &mut self,
-        port: u16,
+        addrs: &[SocketAddr],
     ) -> Result<Box<Future<Item = (), Error = ()> + Send>, Self::ServeError>;
 }
 
 pub trait JsonRpcServer {
     type Server;
 
+    /// Starts the admin server on all of the given addresses.
     fn serve(
-        port: u16,
+        addrs: &[SocketAddr],
         provider: Arc<impl SubgraphProvider>,
+        api_key_store: Arc<Mutex<impl ApiKeyStore + Send + 'static>>,
+        compression_store: Arc<Mutex<impl CompressionStore + Send + 'static>>,
+        usage_store: Arc<Mutex<impl DeploymentUsageStore + Send + 'static>>,
+        metrics_store: Arc<Mutex<impl StoreMetrics + Send + 'static>>,
+        entity_store: Arc<Mutex<impl BasicStore + Send + 'static>>,
+        lag_status: Arc<impl IndexingLagStatus + Send + Sync + 'static>,
+        mapping_logs: Arc<impl MappingLogCapture + Send + Sync + 'static>,
+        throughput_store: Arc<Mutex<impl ThroughputStore + Send + 'static>>,
         logger: Logger,
     ) -> Result<Self::Server, io::Error>;
 }
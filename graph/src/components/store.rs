@@ -1,8 +1,13 @@
 use ethereum_types::H256;
 use futures::sync::mpsc::Receiver;
 
+use data::query::QueryExecutionError;
 use data::store::*;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use serde_json;
 use std::fmt;
+use std::time::Duration;
 use util::stream::StreamError;
 
 /// Key by which an individual entity in the store can be accessed.
@@ -37,6 +42,79 @@ pub enum StoreFilter {
     NotStartsWith(Attribute, Value),
     EndsWith(Attribute, Value),
     NotEndsWith(Attribute, Value),
+    /// Matches strings that contain the value, ignoring case.
+    ContainsNoCase(Attribute, Value),
+    NotContainsNoCase(Attribute, Value),
+    /// Matches strings that start with the value, ignoring case.
+    StartsWithNoCase(Attribute, Value),
+    NotStartsWithNoCase(Attribute, Value),
+    /// Matches strings that end with the value, ignoring case.
+    EndsWithNoCase(Attribute, Value),
+    NotEndsWithNoCase(Attribute, Value),
+    /// Matches strings that match the value as a regular expression,
+    /// ignoring case.
+    MatchesRegex(Attribute, Value),
+    NotMatchesRegex(Attribute, Value),
+    /// Matches entities whose reference attribute points to an entity of the
+    /// given type that matches the nested filter.
+    Child(Attribute, String, Box<StoreFilter>),
+}
+
+impl StoreFilter {
+    /// Returns true if the filter, or a filter nested in it, matches regular
+    /// expressions.
+    pub fn uses_regex(&self) -> bool {
+        match self {
+            StoreFilter::And(filters) | StoreFilter::Or(filters) => {
+                filters.iter().any(|filter| filter.uses_regex())
+            }
+            StoreFilter::MatchesRegex(..) | StoreFilter::NotMatchesRegex(..) => true,
+            StoreFilter::Child(_, _, filter) => filter.uses_regex(),
+            _ => false,
+        }
+    }
+}
+
+/// A filter that a store cannot apply with the value it was given, e.g.
+/// `contains` with a number.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UnsupportedFilter {
+    /// Name of the filter, as it is written in GraphQL queries.
+    pub filter: String,
+    pub value: Value,
+}
+
+impl fmt::Display for UnsupportedFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Filter `{}` is not supported with the value {}",
+            self.filter,
+            serde_json::to_string(&self.value).unwrap_or_else(|_| format!("{:?}", self.value))
+        )
+    }
+}
+
+/// Why writing to a store failed.
+#[derive(Clone, Debug, PartialEq)]
+pub enum StoreWriteError {
+    /// Another node is writing to the deployment, e.g. because it is
+    /// assigned to more than one node; holds the ID of the subgraph.
+    NotWriter(String),
+    /// The write failed for a reason other than a passing conflict with
+    /// concurrent transactions, which the store retries; the store logs why.
+    Failed,
+}
+
+impl fmt::Display for StoreWriteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StoreWriteError::NotWriter(subgraph_id) => {
+                write!(f, "Another node is writing to subgraph {}", subgraph_id)
+            }
+            StoreWriteError::Failed => write!(f, "Failed to write to the store"),
+        }
+    }
 }
 
 /// The order in which entities should be restored from a store.
@@ -56,6 +134,21 @@ pub struct StoreRange {
     pub skip: usize,
 }
 
+/// The view of the store that a lookup of a GraphQL query runs in. Each
+/// lookup carries its own scope, so that concurrent queries cannot see each
+/// other's block or deadline.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct QueryScope {
+    /// The number of the block whose entities the lookup sees, or `None`
+    /// for their latest versions. Stores without the history of entities
+    /// ignore this.
+    pub block: Option<u64>,
+
+    /// How long the lookup may run, if limited. Stores that cannot cancel
+    /// lookups ignore this.
+    pub timeout: Option<Duration>,
+}
+
 /// A query for entities in a store.
 #[derive(Clone, Debug, PartialEq)]
 pub struct StoreQuery {
@@ -68,18 +161,65 @@ pub struct StoreQuery {
     /// Filter to filter entities by.
     pub filter: Option<StoreFilter>,
 
-    /// An optional attribute to order the entities by.
-    pub order_by: Option<String>,
+    /// An optional attribute to order the entities by, along with the type
+    /// of its values.
+    pub order_by: Option<(String, ValueType)>,
 
     /// The direction to order entities in.
     pub order_direction: Option<StoreOrder>,
 
     /// An optional range to limit the size of the result.
     pub range: Option<StoreRange>,
+
+    /// The block and timeout of the GraphQL query the lookup is made for.
+    pub scope: QueryScope,
+}
+
+/// The parents whose children a windowed query selects; the range of the
+/// query applies to the children of each parent separately.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StoreWindow {
+    /// The attribute through which children reference their parent.
+    pub attribute: Attribute,
+
+    /// Whether the attribute holds a list of references.
+    pub is_list: bool,
+
+    /// IDs of the parent entities.
+    pub parent_ids: Vec<String>,
+}
+
+/// A full-text search for entities, declared with `@fulltext` on their type.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FulltextSearch {
+    /// Name of the search.
+    pub name: String,
+
+    /// Postgres text search configuration of the search.
+    pub language: String,
+
+    /// The text to search for.
+    pub text: String,
+}
+
+/// An aggregate computed over the entities that match a store query.
+#[derive(Clone, Debug, PartialEq)]
+pub enum StoreAggregate {
+    /// The number of entities.
+    Count,
+
+    /// The sum of the numeric values of an attribute.
+    Sum(Attribute),
+
+    /// The smallest numeric value of an attribute.
+    Min(Attribute),
+
+    /// The largest numeric value of an attribute.
+    Max(Attribute),
 }
 
 /// Events emitted by implementations of [Store](trait.Store.html).
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum StoreEvent {
     /// An entity was added to the store.
     EntityAdded(Entity),
@@ -89,12 +229,20 @@ pub enum StoreEvent {
 
     /// An entity was changed in the store.
     EntityChanged(Entity),
+
+    /// Entities of the subgraph with the given ID were written while
+    /// processing the block that the event source identifies.
+    SubgraphChanged(String, EventSource),
 }
 
 /// The source of the events being sent to the store
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum EventSource {
-    EthereumBlock(H256),
+    /// An Ethereum block, identified by its hash, along with its number.
+    EthereumBlock(H256, u64),
+    /// An offchain handler, identified by the source it fetched data from,
+    /// e.g. an IPFS link.
+    Offchain(String),
 }
 
 // Implementing the display trait also provides a ToString trait implementation
@@ -102,7 +250,8 @@ impl fmt::Display for EventSource {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let printable_source = match *self {
             // Use LowerHex to format hash as hex string
-            EventSource::EthereumBlock(hash) => format!("{:x}", hash),
+            EventSource::EthereumBlock(hash, _) => format!("{:x}", hash),
+            EventSource::Offchain(ref source) => source.clone(),
         };
         write!(f, "{}", printable_source)
     }
@@ -113,14 +262,204 @@ pub trait BasicStore {
     /// Looks up an entity using the given store key.
     fn get(&self, key: StoreKey) -> Result<Entity, ()>;
 
-    /// Updates an entity using the given store key and entity data.
-    fn set(&mut self, key: StoreKey, entity: Entity, event_source: EventSource) -> Result<(), ()>;
+    /// Looks up entities of a subgraph by the IDs to fetch for each entity
+    /// type, in the given scope. Entities that don't exist are left out of
+    /// the result. Stores without the history of entities always look up
+    /// the latest versions.
+    fn get_many(
+        &self,
+        subgraph: &str,
+        ids_for_type: BTreeMap<String, Vec<String>>,
+        _scope: QueryScope,
+    ) -> Result<BTreeMap<String, Vec<Entity>>, ()> {
+        Ok(ids_for_type
+            .into_iter()
+            .map(|(entity, ids)| {
+                let entities = ids
+                    .into_iter()
+                    .filter_map(|id| {
+                        self.get(StoreKey {
+                            subgraph: subgraph.to_owned(),
+                            entity: entity.clone(),
+                            id,
+                        }).ok()
+                    })
+                    .collect();
+                (entity, entities)
+            })
+            .collect())
+    }
+
+    /// Updates an entity using the given store key and entity data. Fails
+    /// with `NotWriter` without writing if another node is writing to the
+    /// subgraph.
+    fn set(
+        &mut self,
+        key: StoreKey,
+        entity: Entity,
+        event_source: EventSource,
+    ) -> Result<(), StoreWriteError>;
 
-    /// Deletes an entity using the given store key.
-    fn delete(&mut self, key: StoreKey, event_source: EventSource) -> Result<(), ()>;
+    /// Deletes an entity using the given store key. Fails with `NotWriter`
+    /// without deleting if another node is writing to the subgraph.
+    fn delete(&mut self, key: StoreKey, event_source: EventSource) -> Result<(), StoreWriteError>;
 
     /// Queries the store for entities that match the store query.
     fn find(&self, query: StoreQuery) -> Result<Vec<Entity>, ()>;
+
+    /// Queries the store for the entities that match the store query and
+    /// reference one of the parents of the window, applying the range of
+    /// the query to the entities of each parent. Returns each entity along
+    /// with the ID of its parent, grouped by parent.
+    fn find_windowed(
+        &self,
+        query: StoreQuery,
+        window: StoreWindow,
+    ) -> Result<Vec<(String, Entity)>, ()> {
+        let mut entities = vec![];
+        for parent_id in window.parent_ids {
+            let filter = if window.is_list {
+                StoreFilter::Contains(
+                    window.attribute.clone(),
+                    Value::List(vec![Value::String(parent_id.clone())]),
+                )
+            } else {
+                StoreFilter::Equal(window.attribute.clone(), Value::String(parent_id.clone()))
+            };
+
+            let mut query = query.clone();
+            match query.filter.get_or_insert(StoreFilter::And(vec![])) {
+                StoreFilter::And(filters) => filters.push(filter),
+                _ => unreachable!("Top-level filters are always `And` filters"),
+            }
+
+            entities.extend(
+                self.find(query)?
+                    .into_iter()
+                    .map(|entity| (parent_id.clone(), entity)),
+            );
+        }
+        Ok(entities)
+    }
+
+    /// Returns the SQL that `find` would run for the store query, without
+    /// running it. Stores that are not backed by SQL return `None`.
+    fn explain(&self, _query: StoreQuery) -> Option<String> {
+        None
+    }
+
+    /// Checks that the store can run a store query, e.g. that it can apply
+    /// its filter, so that queries it would fail on fail with the reason
+    /// instead of a store error.
+    fn check_query(&self, _query: &StoreQuery) -> Result<(), QueryExecutionError> {
+        Ok(())
+    }
+
+    /// Returns the number of the earliest block whose entities of the
+    /// subgraph can be looked up, if the store keeps their history.
+    fn earliest_history_block(&self, _subgraph_id: &str) -> Option<u64> {
+        None
+    }
+
+    /// Reverts all changes that a subgraph made to its entities while
+    /// processing the block with the given hash, e.g. because the block was
+    /// reorged out of the chain, and returns how many changes were reverted.
+    /// Stores without the history of entities fail.
+    fn revert_block_operations(
+        &mut self,
+        _subgraph_id: &str,
+        _block_hash: H256,
+    ) -> Result<u64, StoreWriteError> {
+        Err(StoreWriteError::Failed)
+    }
+
+    /// Searches the entities of the type and subgraph of the query for the
+    /// text of a full-text search, best matches first, and returns the range
+    /// of the query. Its filter and order are ignored. Stores without
+    /// full-text search fail.
+    fn search(&self, _query: StoreQuery, _search: FulltextSearch) -> Result<Vec<Entity>, ()> {
+        Err(())
+    }
+
+    /// Computes the aggregates over the entities that match the filter of
+    /// the store query, in the order of `aggregates`. Counts are `Int`s; the
+    /// other aggregates are `BigDecimal`s, or `Null` if no entity has a value
+    /// for the attribute. The order and range of the query are ignored.
+    /// Stores that cannot aggregate fail.
+    fn aggregate(
+        &self,
+        _query: StoreQuery,
+        _aggregates: Vec<StoreAggregate>,
+    ) -> Result<Vec<Value>, ()> {
+        Err(())
+    }
+
+    /// Counts the entities that match the filter of the store query by the
+    /// bucket of width `size` that the numeric value of `attribute` falls
+    /// into. Returns the lower bound of every bucket, a multiple of `size`,
+    /// along with the number of entities in it, lowest bucket first. Empty
+    /// buckets and entities without a value are left out. The order and
+    /// range of the query are ignored. Stores that cannot aggregate fail.
+    fn histogram(
+        &self,
+        _query: StoreQuery,
+        _attribute: Attribute,
+        _size: scalar::BigDecimal,
+    ) -> Result<Vec<(scalar::BigDecimal, i32)>, ()> {
+        Err(())
+    }
+
+    /// Queries the store for the entities of any of `entity_types` that match
+    /// the store query, e.g. for the object types that implement an interface.
+    /// `query.entity` is ignored; the order and range of the query apply to
+    /// the entities of all types together.
+    fn find_any(&self, query: StoreQuery, entity_types: Vec<String>) -> Result<Vec<Entity>, ()> {
+        // The page may be made up of the entities of a single type, so every
+        // type needs to provide as many entities as the range spans
+        let mut entities = vec![];
+        for entity_type in entity_types {
+            let mut query = query.clone();
+            query.entity = entity_type;
+            query.range = query.range.map(|range| StoreRange {
+                first: range.first + range.skip,
+                skip: 0,
+            });
+            entities.extend(self.find(query)?);
+        }
+
+        if let Some((ref attribute, _)) = query.order_by {
+            entities.sort_by(|a, b| compare_values(a.get(attribute), b.get(attribute)));
+            if let Some(StoreOrder::Descending) = query.order_direction {
+                entities.reverse();
+            }
+        }
+
+        Ok(match query.range {
+            Some(range) => entities
+                .into_iter()
+                .skip(range.skip)
+                .take(range.first)
+                .collect(),
+            None => entities,
+        })
+    }
+}
+
+/// Orders two attribute values of the same type. Values of different types,
+/// lists and missing values are treated as equal.
+fn compare_values(a: Option<&Value>, b: Option<&Value>) -> Ordering {
+    match (a, b) {
+        (Some(Value::String(a)), Some(Value::String(b))) => a.cmp(b),
+        (Some(Value::Int(a)), Some(Value::Int(b))) => a.cmp(b),
+        (Some(Value::Float(a)), Some(Value::Float(b))) => {
+            a.partial_cmp(b).unwrap_or(Ordering::Equal)
+        }
+        (Some(Value::Bool(a)), Some(Value::Bool(b))) => a.cmp(b),
+        (Some(Value::BigInt(a)), Some(Value::BigInt(b))) => a.cmp(b),
+        (Some(Value::BigDecimal(a)), Some(Value::BigDecimal(b))) => a.cmp(b),
+        (Some(Value::Bytes(a)), Some(Value::Bytes(b))) => a.to_string().cmp(&b.to_string()),
+        _ => Ordering::Equal,
+    }
 }
 
 /// Common trait for store implementations.
@@ -1,3 +1,5 @@
+use ethereum_types::H256;
+
 use prelude::*;
 
 /// Events emitted by a runtime host.
@@ -7,6 +9,10 @@ pub enum RuntimeHostEvent {
     EntitySet(StoreKey, Entity, EventSource),
     /// An entity should be removed.
     EntityRemoved(StoreKey, EventSource),
+    /// The changes that the subgraph with the given ID made while processing
+    /// the block with the given hash should be reverted, because the block
+    /// was reorged out of the chain.
+    BlockReverted(String, H256),
 }
 
 /// Common trait for runtime host implementations.
@@ -17,6 +17,8 @@ pub enum SubgraphProviderEvent {
 pub enum SchemaEvent {
     /// A subgraph with a new schema was added.
     SchemaAdded(Schema),
+    /// The schema of an existing subgraph was replaced with a new one.
+    SchemaUpdated(Schema),
     /// A subgraph with an existing schema was removed.
     SchemaRemoved(Schema),
 }
@@ -25,8 +27,13 @@ pub enum SchemaEvent {
 pub trait SubgraphProvider:
     EventProducer<SubgraphProviderEvent> + EventProducer<SchemaEvent> + Send + Sync + 'static
 {
+    /// Adds the subgraph at `link` under `name`. Subgraphs whose mappings
+    /// changed since they were indexed are refused, unless
+    /// `allow_mapping_changes` is set.
     fn add(
         &self,
+        name: String,
         link: String,
+        allow_mapping_changes: bool,
     ) -> Box<Future<Item = (), Error = SubgraphProviderError> + Send + 'static>;
 }
@@ -0,0 +1,29 @@
+use data::throughput::ThroughputSample;
+use util::error::GraphError;
+
+/// Common trait for components that count how much subgraphs index, e.g.
+/// to sample their throughput periodically.
+pub trait ThroughputCapture {
+    /// Counts a trigger that a subgraph passed to a handler, along with its
+    /// block if the subgraph did not handle a trigger in it before.
+    fn record_trigger(&self, subgraph_id: &str, block_number: u64);
+
+    /// Counts an entity that a handler of a subgraph set or removed.
+    fn record_entity_write(&self, subgraph_id: &str);
+}
+
+/// Common trait for stores that keep the history of how much subgraphs
+/// indexed over time.
+pub trait ThroughputStore {
+    /// Persists samples of the throughput of subgraphs.
+    fn record_throughput(&mut self, samples: &[ThroughputSample]) -> Result<(), GraphError>;
+
+    /// Lists the throughput of a subgraph per hour, for the hours that
+    /// started at or after `since`, in seconds since the Unix epoch, oldest
+    /// first. Each combines the samples taken during its hour.
+    fn throughput_history(
+        &self,
+        subgraph_id: &str,
+        since: u64,
+    ) -> Result<Vec<ThroughputSample>, GraphError>;
+}
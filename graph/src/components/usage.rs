@@ -0,0 +1,16 @@
+use data::usage::DeploymentUsage;
+use util::error::GraphError;
+
+/// Common trait for stores that keep track of how much subgraph deployments
+/// are being queried.
+pub trait DeploymentUsageStore {
+    /// Starts tracking the usage of a deployment, unless it is tracked
+    /// already.
+    fn track_deployment(&mut self, subgraph_id: &str) -> Result<(), GraphError>;
+
+    /// Records that a deployment was queried just now.
+    fn record_deployment_query(&mut self, subgraph_id: &str) -> Result<(), GraphError>;
+
+    /// Lists the usage of all tracked deployments.
+    fn deployment_usage(&self) -> Result<Vec<DeploymentUsage>, GraphError>;
+}
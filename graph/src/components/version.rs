@@ -0,0 +1,23 @@
+use components::store::{EventSource, StoreKey};
+use data::store::Entity;
+use util::error::GraphError;
+
+/// Common trait for stores that keep a version of every entity, which starts
+/// at 1 and is bumped whenever the entity is set, so that writers can detect
+/// when an entity was modified since they read it.
+pub trait EntityVersionStore {
+    /// Returns the version of an entity, or `None` if it doesn't exist.
+    fn entity_version(&self, key: StoreKey) -> Result<Option<u64>, GraphError>;
+
+    /// Sets an entity like `BasicStore::set`, but only if its version is
+    /// still `expected_version`; `None` expects the entity not to exist yet.
+    /// Returns the new version of the entity, or `None` if the entity was
+    /// modified in the meantime and nothing was written.
+    fn set_if_version(
+        &mut self,
+        key: StoreKey,
+        entity: Entity,
+        expected_version: Option<u64>,
+        event_source: EventSource,
+    ) -> Result<Option<u64>, GraphError>;
+}
@@ -0,0 +1,61 @@
+/// An API key through which queries are attributed to a client.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiKey {
+    /// The secret key that clients send along with their queries.
+    pub key: String,
+    /// Human-readable name of the client the key was issued to.
+    pub name: String,
+    /// Usage accumulated by the key so far.
+    pub usage: ApiKeyUsage,
+}
+
+/// Usage attributed to an API key.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiKeyUsage {
+    /// Number of queries.
+    pub queries: u64,
+    /// Number of queries that failed.
+    pub errors: u64,
+    /// Accumulated cost of all queries, measured in values returned.
+    pub cost: u64,
+}
+
+impl ApiKeyUsage {
+    /// Usage of a single query.
+    pub fn query(failed: bool, cost: u64) -> Self {
+        ApiKeyUsage {
+            queries: 1,
+            errors: if failed { 1 } else { 0 },
+            cost,
+        }
+    }
+
+    /// Fraction of queries that failed.
+    pub fn error_rate(&self) -> f64 {
+        if self.queries == 0 {
+            0.0
+        } else {
+            self.errors as f64 / self.queries as f64
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ApiKeyUsage;
+
+    #[test]
+    fn error_rate_of_unused_keys_is_zero() {
+        assert_eq!(ApiKeyUsage::default().error_rate(), 0.0);
+    }
+
+    #[test]
+    fn error_rate_is_the_fraction_of_failed_queries() {
+        let usage = ApiKeyUsage {
+            queries: 4,
+            errors: 1,
+            cost: 10,
+        };
+        assert_eq!(usage.error_rate(), 0.25);
+    }
+}
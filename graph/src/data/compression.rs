@@ -0,0 +1,39 @@
+/// A subgraph whose entities have been moved into compressed storage.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompressedSubgraph {
+    /// ID of the subgraph.
+    pub subgraph_id: String,
+    /// Number of entities in compressed storage.
+    pub entities: u64,
+    /// Size of the entity data before compression, in bytes.
+    pub size: u64,
+    /// Size of the entity data after compression, in bytes.
+    pub compressed_size: u64,
+}
+
+impl CompressedSubgraph {
+    /// Compressed size as a fraction of the original size.
+    pub fn ratio(&self) -> f64 {
+        if self.size == 0 {
+            1.0
+        } else {
+            self.compressed_size as f64 / self.size as f64
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CompressedSubgraph;
+
+    #[test]
+    fn ratio_is_the_fraction_of_the_original_size() {
+        let subgraph = CompressedSubgraph {
+            subgraph_id: String::from("cold"),
+            entities: 10,
+            size: 1000,
+            compressed_size: 250,
+        };
+        assert_eq!(subgraph.ratio(), 0.25);
+    }
+}
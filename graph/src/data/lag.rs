@@ -0,0 +1,92 @@
+use std::str::FromStr;
+
+/// How far a subgraph may fall behind the chain head before it is flagged
+/// as lagging.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LagThreshold {
+    /// Number of blocks behind the chain head, e.g. `100blocks`.
+    Blocks(u64),
+    /// Seconds between the latest block indexed and the chain head, e.g.
+    /// `300s`, `5m` or `1h`.
+    Seconds(u64),
+}
+
+impl LagThreshold {
+    /// Returns true if a subgraph that is this far behind exceeds the
+    /// threshold.
+    pub fn is_exceeded_by(&self, blocks_behind: u64, seconds_behind: u64) -> bool {
+        match self {
+            LagThreshold::Blocks(blocks) => blocks_behind > *blocks,
+            LagThreshold::Seconds(seconds) => seconds_behind > *seconds,
+        }
+    }
+}
+
+impl FromStr for LagThreshold {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid lag threshold: {}", s);
+
+        if s.ends_with("blocks") {
+            return s[..s.len() - "blocks".len()]
+                .parse()
+                .map(LagThreshold::Blocks)
+                .map_err(|_| invalid());
+        }
+
+        let unit = match s.chars().last() {
+            Some('s') => 1,
+            Some('m') => 60,
+            Some('h') => 60 * 60,
+            _ => return Err(invalid()),
+        };
+        s[..s.len() - 1]
+            .parse::<u64>()
+            .ok()
+            .and_then(|count| count.checked_mul(unit))
+            .map(LagThreshold::Seconds)
+            .ok_or_else(invalid)
+    }
+}
+
+/// How far a subgraph is behind the chain head.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct IndexingLag {
+    /// ID of the subgraph.
+    pub subgraph_id: String,
+    /// Number of the latest block of the chain.
+    pub head_block_number: u64,
+    /// Number of the latest block the subgraph wrote entities for.
+    pub indexed_block_number: u64,
+    pub blocks_behind: u64,
+    /// Seconds between the timestamps of the two blocks.
+    pub seconds_behind: u64,
+    /// Whether the subgraph is further behind than one of its thresholds
+    /// allows.
+    pub lagging: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LagThreshold;
+
+    #[test]
+    fn parses_thresholds_in_blocks_and_seconds() {
+        assert_eq!("100blocks".parse(), Ok(LagThreshold::Blocks(100)));
+        assert_eq!("90s".parse(), Ok(LagThreshold::Seconds(90)));
+        assert_eq!("5m".parse(), Ok(LagThreshold::Seconds(300)));
+        assert_eq!("2h".parse(), Ok(LagThreshold::Seconds(7200)));
+        assert!("100".parse::<LagThreshold>().is_err());
+        assert!("1d".parse::<LagThreshold>().is_err());
+        assert!("blocks".parse::<LagThreshold>().is_err());
+    }
+
+    #[test]
+    fn thresholds_are_exceeded_by_larger_lags() {
+        assert!(!LagThreshold::Blocks(10).is_exceeded_by(10, 1000));
+        assert!(LagThreshold::Blocks(10).is_exceeded_by(11, 0));
+        assert!(!LagThreshold::Seconds(60).is_exceeded_by(1000, 60));
+        assert!(LagThreshold::Seconds(60).is_exceeded_by(0, 61));
+    }
+}
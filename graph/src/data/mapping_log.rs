@@ -0,0 +1,28 @@
+/// Severity of a message logged by a mapping.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MappingLogLevel {
+    Debug,
+    Info,
+    Warning,
+    Error,
+}
+
+/// A message logged by a mapping through `log.debug`, `log.info`,
+/// `log.warning` or `log.error`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct MappingLogEntry {
+    /// ID of the subgraph the mapping belongs to.
+    pub subgraph_id: String,
+    /// Seconds since the epoch at which the message was logged.
+    pub timestamp: u64,
+    pub level: MappingLogLevel,
+    pub message: String,
+    /// Name of the handler that logged the message.
+    pub handler: String,
+    /// Number and hash of the block of the event being handled.
+    pub block_number: u64,
+    pub block_hash: String,
+    /// Hash of the transaction that emitted the event being handled.
+    pub transaction_hash: String,
+}
@@ -0,0 +1,106 @@
+use parity_wasm;
+
+use data::subgraph::SubgraphManifest;
+use util::ethereum::keccak256;
+
+/// The mappings that a data source of a deployment is indexed with.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MappingPin {
+    /// Name of the data source.
+    pub data_source: String,
+    /// Version of the mapping API that the mappings use.
+    pub api_version: String,
+    /// Keccak-256 hash of the WASM module of the mappings, as a hex string.
+    pub module_hash: String,
+}
+
+impl MappingPin {
+    /// Returns the mappings of all data sources of a manifest.
+    pub fn for_manifest(manifest: &SubgraphManifest) -> Vec<MappingPin> {
+        manifest
+            .data_sources
+            .iter()
+            .map(|data_source| {
+                let module = parity_wasm::serialize(data_source.mapping.runtime.clone())
+                    .expect("Failed to serialize WASM module");
+                MappingPin {
+                    data_source: data_source.name.clone(),
+                    api_version: data_source.mapping.api_version.clone(),
+                    module_hash: format!("{:#x}", keccak256(&module)),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Describes every way in which the `current` mappings of a deployment
+/// differ from the `pinned` ones. Nothing differs if no mappings are pinned.
+pub fn mapping_pin_changes(pinned: &[MappingPin], current: &[MappingPin]) -> Vec<String> {
+    if pinned.is_empty() {
+        return vec![];
+    }
+
+    let mut changes = vec![];
+    for pin in current {
+        match pinned.iter().find(|other| other.data_source == pin.data_source) {
+            None => changes.push(format!("data source `{}` was added", pin.data_source)),
+            Some(other) => {
+                if other.api_version != pin.api_version {
+                    changes.push(format!(
+                        "data source `{}` changed apiVersion from {} to {}",
+                        pin.data_source, other.api_version, pin.api_version
+                    ));
+                }
+                if other.module_hash != pin.module_hash {
+                    changes.push(format!(
+                        "data source `{}` changed its module from {} to {}",
+                        pin.data_source, other.module_hash, pin.module_hash
+                    ));
+                }
+            }
+        }
+    }
+    for other in pinned {
+        if !current.iter().any(|pin| pin.data_source == other.data_source) {
+            changes.push(format!("data source `{}` was removed", other.data_source));
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{mapping_pin_changes, MappingPin};
+
+    fn pin(data_source: &str, api_version: &str, module_hash: &str) -> MappingPin {
+        MappingPin {
+            data_source: data_source.to_owned(),
+            api_version: api_version.to_owned(),
+            module_hash: module_hash.to_owned(),
+        }
+    }
+
+    #[test]
+    fn nothing_changes_without_pinned_mappings() {
+        assert!(mapping_pin_changes(&[], &[pin("Token", "0.1.0", "0x1")]).is_empty());
+        assert!(
+            mapping_pin_changes(&[pin("Token", "0.1.0", "0x1")], &[pin("Token", "0.1.0", "0x1")])
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn describes_changed_added_and_removed_data_sources() {
+        let pinned = vec![pin("Token", "0.1.0", "0x1"), pin("Market", "0.1.0", "0x2")];
+        let current = vec![pin("Token", "0.2.0", "0x3"), pin("Auction", "0.1.0", "0x4")];
+        assert_eq!(
+            mapping_pin_changes(&pinned, &current),
+            vec![
+                "data source `Token` changed apiVersion from 0.1.0 to 0.2.0",
+                "data source `Token` changed its module from 0x1 to 0x3",
+                "data source `Auction` was added",
+                "data source `Market` was removed",
+            ]
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use std::fmt;
+use std::time::Duration;
+
+/// Upper bounds of the latency histogram buckets, in milliseconds.
+pub const LATENCY_BUCKETS_MS: [f64; 12] = [
+    1.0, 2.5, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0,
+];
+
+/// The ways in which entities are accessed in a store.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StoreOperation {
+    Get,
+    GetMany,
+    Find,
+    Set,
+    Delete,
+}
+
+impl fmt::Display for StoreOperation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StoreOperation::Get => write!(f, "get"),
+            StoreOperation::GetMany => write!(f, "get_many"),
+            StoreOperation::Find => write!(f, "find"),
+            StoreOperation::Set => write!(f, "set"),
+            StoreOperation::Delete => write!(f, "delete"),
+        }
+    }
+}
+
+/// Number of observations that took at most `le_ms` milliseconds.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct LatencyBucket {
+    pub le_ms: f64,
+    pub count: u64,
+}
+
+/// Cumulative histogram of how long operations took.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct LatencyHistogram {
+    pub buckets: Vec<LatencyBucket>,
+    /// Number of observations, including those slower than the last bucket.
+    pub count: u64,
+    /// Sum of all observations, in milliseconds.
+    pub sum_ms: f64,
+}
+
+impl LatencyHistogram {
+    pub fn new() -> Self {
+        LatencyHistogram {
+            buckets: LATENCY_BUCKETS_MS
+                .iter()
+                .map(|le_ms| LatencyBucket {
+                    le_ms: *le_ms,
+                    count: 0,
+                })
+                .collect(),
+            count: 0,
+            sum_ms: 0.0,
+        }
+    }
+
+    /// Adds an observation to all buckets it fits into.
+    pub fn observe(&mut self, latency: Duration) {
+        let ms = latency.as_secs() as f64 * 1000.0 + f64::from(latency.subsec_nanos()) / 1e6;
+
+        for bucket in self.buckets.iter_mut().filter(|bucket| ms <= bucket.le_ms) {
+            bucket.count += 1;
+        }
+        self.count += 1;
+        self.sum_ms += ms;
+    }
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Latencies of one kind of store operation against one deployment.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct StoreLatency {
+    pub subgraph_id: String,
+    pub operation: StoreOperation,
+    pub histogram: LatencyHistogram,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::LatencyHistogram;
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let mut histogram = LatencyHistogram::new();
+        histogram.observe(Duration::from_millis(3));
+        histogram.observe(Duration::from_millis(40));
+        histogram.observe(Duration::from_secs(10));
+
+        let count = |le_ms: f64| {
+            histogram
+                .buckets
+                .iter()
+                .find(|bucket| bucket.le_ms == le_ms)
+                .unwrap()
+                .count
+        };
+        assert_eq!(count(1.0), 0);
+        assert_eq!(count(5.0), 1);
+        assert_eq!(count(50.0), 2);
+        assert_eq!(count(5000.0), 2);
+        assert_eq!(histogram.count, 3);
+        assert_eq!(histogram.sum_ms, 10043.0);
+    }
+}
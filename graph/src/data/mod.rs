@@ -1,3 +1,25 @@
+/// Data types for dealing with API keys.
+pub mod api_key;
+
+/// Data types for dealing with compressed subgraphs.
+pub mod compression;
+
+/// Data types for dealing with how far subgraphs are behind the chain head.
+pub mod lag;
+
+/// Data types for dealing with messages logged by mappings.
+pub mod mapping_log;
+
+/// Data types for dealing with the mappings that deployments are pinned to.
+pub mod mapping_pin;
+
+/// Data types for dealing with metrics.
+pub mod metrics;
+
+/// Data types for dealing with the rules that decide which deployments a node
+/// indexes.
+pub mod placement;
+
 /// Data types for dealing with subgraphs.
 pub mod subgraph;
 
@@ -9,3 +31,12 @@ pub mod schema;
 
 /// Data types for dealing with storing entities.
 pub mod store;
+
+/// Data types for describing the state of a node.
+pub mod system;
+
+/// Data types for dealing with indexing throughput.
+pub mod throughput;
+
+/// Data types for dealing with the usage of subgraph deployments.
+pub mod usage;
@@ -0,0 +1,186 @@
+use std::str::FromStr;
+
+/// Whether a node indexes the deployments that a rule matches.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Placement {
+    Accept,
+    Refuse,
+}
+
+/// What a placement rule matches deployments by.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PlacementMatch {
+    /// Subgraph names matching a pattern in which `*` stands for any
+    /// sequence of characters, e.g. `internal/*`.
+    Name(String),
+    /// Deployments with a data source on a network, e.g. `ropsten`.
+    Network(String),
+}
+
+/// A rule like `accept:name=internal/*` or `refuse:network=ropsten` that
+/// decides whether a node indexes the deployments it matches.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlacementRule {
+    pub placement: Placement,
+    pub matches: PlacementMatch,
+}
+
+impl PlacementRule {
+    /// Returns true if the rule applies to the subgraph `name`, whose data
+    /// sources are on `networks`.
+    pub fn applies_to(&self, name: &str, networks: &[&str]) -> bool {
+        match self.matches {
+            PlacementMatch::Name(ref pattern) => pattern_matches(pattern, name),
+            PlacementMatch::Network(ref network) => networks.contains(&network.as_str()),
+        }
+    }
+}
+
+impl FromStr for PlacementRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid placement rule: {}", s);
+
+        let mut split = s.splitn(2, ':');
+        let placement = match split.next() {
+            Some("accept") => Placement::Accept,
+            Some("refuse") => Placement::Refuse,
+            _ => return Err(invalid()),
+        };
+        let mut split = split.next().ok_or_else(invalid)?.splitn(2, '=');
+        let matches = match (split.next(), split.next()) {
+            (Some("name"), Some(pattern)) if !pattern.is_empty() => {
+                PlacementMatch::Name(pattern.to_owned())
+            }
+            (Some("network"), Some(network)) if !network.is_empty() => {
+                PlacementMatch::Network(network.to_owned())
+            }
+            _ => return Err(invalid()),
+        };
+        Ok(PlacementRule { placement, matches })
+    }
+}
+
+/// Describes why `rules` refuse to index the subgraph `name`, whose data
+/// sources are on `networks`, on this node. The first rule that applies
+/// decides; if none does, the deployment is refused only when there are
+/// rules that accept deployments.
+pub fn placement_refusal(
+    rules: &[PlacementRule],
+    name: &str,
+    networks: &[&str],
+) -> Option<String> {
+    let accepts = rules.iter().any(|rule| rule.placement == Placement::Accept);
+    match rules.iter().find(|rule| rule.applies_to(name, networks)) {
+        Some(rule) if rule.placement == Placement::Refuse => Some(match rule.matches {
+            PlacementMatch::Name(ref pattern) => {
+                format!("subgraph names matching `{}` are refused", pattern)
+            }
+            PlacementMatch::Network(ref network) => {
+                format!("subgraphs on network `{}` are refused", network)
+            }
+        }),
+        Some(_) => None,
+        None if accepts => Some(format!("no rule accepts subgraph `{}`", name)),
+        None => None,
+    }
+}
+
+/// Returns true if `name` matches `pattern`, in which `*` stands for any
+/// sequence of characters.
+fn pattern_matches(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    if !name.starts_with(first) {
+        return false;
+    }
+    let mut rest = &name[first.len()..];
+    let mut parts: Vec<&str> = parts.collect();
+    let last = match parts.pop() {
+        Some(last) => last,
+        // Without a `*`, the name must be the pattern itself
+        None => return rest.is_empty(),
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(rules: &[&str]) -> Vec<PlacementRule> {
+        rules.iter().map(|rule| rule.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn parses_rules_by_name_and_network() {
+        assert_eq!(
+            "accept:name=internal/*".parse(),
+            Ok(PlacementRule {
+                placement: Placement::Accept,
+                matches: PlacementMatch::Name(String::from("internal/*")),
+            })
+        );
+        assert_eq!(
+            "refuse:network=ropsten".parse(),
+            Ok(PlacementRule {
+                placement: Placement::Refuse,
+                matches: PlacementMatch::Network(String::from("ropsten")),
+            })
+        );
+        assert!("allow:name=a".parse::<PlacementRule>().is_err());
+        assert!("accept:id=a".parse::<PlacementRule>().is_err());
+        assert!("accept:name=".parse::<PlacementRule>().is_err());
+        assert!("refuse".parse::<PlacementRule>().is_err());
+    }
+
+    #[test]
+    fn patterns_match_any_characters_for_stars() {
+        assert!(pattern_matches("internal/*", "internal/tokens"));
+        assert!(pattern_matches("*/tokens", "internal/tokens"));
+        assert!(pattern_matches("in*/*s", "internal/tokens"));
+        assert!(pattern_matches("*", ""));
+        assert!(pattern_matches("tokens", "tokens"));
+        assert!(!pattern_matches("tokens", "tokens2"));
+        assert!(!pattern_matches("internal/*", "external/tokens"));
+        assert!(!pattern_matches("a*a", "a"));
+    }
+
+    #[test]
+    fn first_applying_rule_decides() {
+        let rules = rules(&[
+            "refuse:network=ropsten",
+            "accept:name=internal/*",
+            "refuse:name=*",
+        ]);
+        assert_eq!(placement_refusal(&rules, "internal/tokens", &["mainnet"]), None);
+        assert_eq!(
+            placement_refusal(&rules, "internal/tokens", &["mainnet", "ropsten"]),
+            Some(String::from("subgraphs on network `ropsten` are refused"))
+        );
+        assert_eq!(
+            placement_refusal(&rules, "public/tokens", &[]),
+            Some(String::from("subgraph names matching `*` are refused"))
+        );
+    }
+
+    #[test]
+    fn unmatched_deployments_are_refused_only_by_accept_rules() {
+        assert_eq!(placement_refusal(&[], "tokens", &[]), None);
+        assert_eq!(
+            placement_refusal(&rules(&["refuse:network=ropsten"]), "tokens", &["mainnet"]),
+            None
+        );
+        assert_eq!(
+            placement_refusal(&rules(&["accept:network=mainnet"]), "tokens", &[]),
+            Some(String::from("no rule accepts subgraph `tokens`"))
+        );
+    }
+}
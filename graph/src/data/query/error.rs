@@ -5,6 +5,8 @@ use std::error::Error;
 use std::fmt;
 use std::string::FromUtf8Error;
 
+use components::store::UnsupportedFilter;
+
 /// Error caused while executing a [Query](struct.Query.html).
 #[derive(Debug)]
 pub enum QueryExecutionError {
@@ -12,13 +14,100 @@ pub enum QueryExecutionError {
     OperationNotFound(String),
     NotSupported(String),
     NoRootQueryObjectType,
+    NoRootSubscriptionObjectType,
     ResolveEntityError(Pos, String),
     NonNullError(Pos, String),
+    /// A non-null field or list element failed, and its error has been
+    /// reported already; the nearest nullable value that contains it is
+    /// null instead.
+    NullPropagated,
     ListValueError(Pos, String),
+    /// The value resolved for a field is not a valid value of its scalar or
+    /// enum type; holds the field name, the value and the type name.
+    ValueCoercionError(Pos, String, q::Value, String),
     NamedTypeError(String),
     AbstractTypeError(String),
     InvalidArgumentError(Pos, String, q::Value),
     MissingArgumentError(Pos, String),
+    InvalidVariableTypeError(Pos, String),
+    MissingVariableError(Pos, String),
+    TooComplex(u64, u64),
+    /// A collection field was asked for more entities at once than the node
+    /// allows; holds the value of `first` and the maximum.
+    FirstTooLargeError(Pos, i64, u32),
+    /// A field marked `@notStored` was queried; holds the type and field name.
+    NotStoredFieldError(Pos, String, String),
+    /// Fields with the same response key select different fields or
+    /// arguments; holds the response key.
+    FieldConflictError(Pos, String),
+    /// Top-level fields of the query are at different blocks, e.g. because
+    /// only one of them is pinned to a block; holds their response keys.
+    BlockConflictError(Pos, String, String),
+    /// The query asked for a block that the subgraph holds no data for
+    /// anymore; holds the requested and the earliest queryable block.
+    BlockOutOfRangeError(u64, u64),
+    /// The query is pinned to a block hash that the node doesn't know.
+    BlockNotFoundError(String),
+    /// The query is pinned to a block that was reorged out of the canonical
+    /// chain; holds its hash and number, and the hash of the block that
+    /// replaced it.
+    BlockReorgedOutError(String, u64, String),
+    /// Looking up the block with the given hash failed.
+    BlockLookupError(String, String),
+    /// A field that the type does not have was selected; holds the type
+    /// and field name.
+    UnknownFieldError(Pos, String, String),
+    /// An argument that the field does not have was passed; holds the field
+    /// and argument name.
+    UnknownArgumentError(Pos, String, String),
+    UnknownTypeError(Pos, String),
+    UnknownFragmentError(Pos, String),
+    /// A fragment can never apply where it is used; holds the type of the
+    /// fragment and the type it is used on.
+    FragmentTypeError(Pos, String, String),
+    /// A fragment spreads itself, directly or through other fragments;
+    /// holds the name of the fragment.
+    FragmentCycleError(Pos, String),
+    UndefinedVariableError(Pos, String),
+    /// A filter of the query cannot be applied to the value it was given;
+    /// holds the filter and a description of the problem.
+    UnsupportedFilterError(String, String),
+    /// The query filters or orders by an attribute that the store can't
+    /// compare, because it holds interned values; holds the entity type and
+    /// the attribute.
+    InternedAttributeError(String, String),
+    /// Querying the store for entities failed; holds the entity types.
+    StoreError(String),
+    /// The time the query was given ran out before the field was resolved.
+    TimeoutError,
+}
+
+impl QueryExecutionError {
+    /// Returns the position in the query that the error refers to, if any.
+    pub fn position(&self) -> Option<Pos> {
+        match self {
+            QueryExecutionError::ResolveEntityError(pos, _)
+            | QueryExecutionError::NonNullError(pos, _)
+            | QueryExecutionError::ListValueError(pos, _)
+            | QueryExecutionError::ValueCoercionError(pos, _, _, _)
+            | QueryExecutionError::InvalidArgumentError(pos, _, _)
+            | QueryExecutionError::MissingArgumentError(pos, _)
+            | QueryExecutionError::InvalidVariableTypeError(pos, _)
+            | QueryExecutionError::MissingVariableError(pos, _)
+            | QueryExecutionError::FirstTooLargeError(pos, _, _)
+            | QueryExecutionError::NotStoredFieldError(pos, _, _)
+            | QueryExecutionError::FieldConflictError(pos, _)
+            | QueryExecutionError::BlockConflictError(pos, _, _)
+            | QueryExecutionError::UnknownFieldError(pos, _, _)
+            | QueryExecutionError::UnknownArgumentError(pos, _, _)
+            | QueryExecutionError::UnknownTypeError(pos, _)
+            | QueryExecutionError::UnknownFragmentError(pos, _)
+            | QueryExecutionError::FragmentTypeError(pos, _, _)
+            | QueryExecutionError::FragmentCycleError(pos, _)
+            | QueryExecutionError::UndefinedVariableError(pos, _) => Some(*pos),
+            _ => None,
+        }
+    }
 }
 
 impl Error for QueryExecutionError {
@@ -34,7 +123,10 @@ impl Error for QueryExecutionError {
 impl fmt::Display for QueryExecutionError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            QueryExecutionError::OperationNameRequired => write!(f, "Operation name required"),
+            QueryExecutionError::OperationNameRequired => write!(
+                f,
+                "Operation name required, as the query does not contain exactly one operation"
+            ),
             QueryExecutionError::OperationNotFound(s) => {
                 write!(f, "Operation name not found: {}", s)
             }
@@ -42,15 +134,26 @@ impl fmt::Display for QueryExecutionError {
             QueryExecutionError::NoRootQueryObjectType => {
                 write!(f, "No root Query type defined in the schema")
             }
+            QueryExecutionError::NoRootSubscriptionObjectType => {
+                write!(f, "No root Subscription type defined in the schema")
+            }
             QueryExecutionError::ResolveEntityError(_, s) => {
                 write!(f, "Failed to resolve entity: {}", s)
             }
             QueryExecutionError::NonNullError(_, s) => {
                 write!(f, "Null value resolved for non-null field: {}", s)
             }
+            QueryExecutionError::NullPropagated => {
+                write!(f, "Null propagated from a non-null value that failed")
+            }
             QueryExecutionError::ListValueError(_, s) => {
                 write!(f, "Non-list value resolved for list field: {}", s)
             }
+            QueryExecutionError::ValueCoercionError(_, field_name, value, type_name) => write!(
+                f,
+                "Value {} resolved for field \"{}\" is not a valid {}",
+                value, field_name, type_name
+            ),
             QueryExecutionError::NamedTypeError(s) => {
                 write!(f, "Failed to resolve named type: {}", s)
             }
@@ -63,6 +166,144 @@ impl fmt::Display for QueryExecutionError {
             QueryExecutionError::MissingArgumentError(_, s) => {
                 write!(f, "No value provided for required argument: {}", s)
             }
+            QueryExecutionError::InvalidVariableTypeError(_, s) => {
+                write!(f, "Variable \"{}\" has an invalid value", s)
+            }
+            QueryExecutionError::MissingVariableError(_, s) => {
+                write!(f, "No value provided for required variable \"{}\"", s)
+            }
+            QueryExecutionError::TooComplex(complexity, max_complexity) => write!(
+                f,
+                "Query is too complex: its complexity of {} exceeds the maximum of {}",
+                complexity, max_complexity
+            ),
+            QueryExecutionError::FirstTooLargeError(_, first, max_first) => write!(
+                f,
+                "Argument \"first\" is {} but may be at most {}; page through the entities \
+                 instead",
+                first, max_first
+            ),
+            QueryExecutionError::NotStoredFieldError(_, type_name, field_name) => write!(
+                f,
+                "Field \"{}.{}\" is not stored by this subgraph and cannot be queried",
+                type_name, field_name
+            ),
+            QueryExecutionError::FieldConflictError(_, response_key) => write!(
+                f,
+                "Fields \"{}\" conflict because they select different fields or arguments; \
+                 use different aliases on the fields to fetch both",
+                response_key
+            ),
+            QueryExecutionError::BlockConflictError(_, first, second) => write!(
+                f,
+                "Fields \"{}\" and \"{}\" are at different blocks; all top-level fields of a \
+                 query must be at the same block, so query them separately",
+                first, second
+            ),
+            QueryExecutionError::BlockOutOfRangeError(block, earliest) => write!(
+                f,
+                "Block #{} is not available anymore; the earliest block that can be queried \
+                 is #{}",
+                block, earliest
+            ),
+            QueryExecutionError::BlockNotFoundError(hash) => write!(f, "Block {} not found", hash),
+            QueryExecutionError::BlockReorgedOutError(hash, number, canonical_hash) => write!(
+                f,
+                "Block {} was reorged out of the chain; block #{} is now {}",
+                hash, number, canonical_hash
+            ),
+            QueryExecutionError::BlockLookupError(hash, e) => {
+                write!(f, "Failed to look up block {}: {}", hash, e)
+            }
+            QueryExecutionError::UnknownFieldError(_, type_name, field_name) => {
+                write!(f, "Type \"{}\" has no field \"{}\"", type_name, field_name)
+            }
+            QueryExecutionError::UnknownArgumentError(_, field_name, argument_name) => write!(
+                f,
+                "Field \"{}\" has no argument \"{}\"",
+                field_name, argument_name
+            ),
+            QueryExecutionError::UnknownTypeError(_, s) => write!(f, "Unknown type \"{}\"", s),
+            QueryExecutionError::UnknownFragmentError(_, s) => {
+                write!(f, "Unknown fragment \"{}\"", s)
+            }
+            QueryExecutionError::FragmentTypeError(_, fragment_type, parent_type) => write!(
+                f,
+                "Fragment on \"{}\" can never apply to \"{}\"",
+                fragment_type, parent_type
+            ),
+            QueryExecutionError::FragmentCycleError(_, s) => {
+                write!(f, "Fragment \"{}\" spreads itself", s)
+            }
+            QueryExecutionError::UndefinedVariableError(_, s) => {
+                write!(f, "Variable \"${}\" is not defined", s)
+            }
+            QueryExecutionError::UnsupportedFilterError(_, message) => write!(f, "{}", message),
+            QueryExecutionError::InternedAttributeError(entity_type, attribute) => write!(
+                f,
+                "{} entities can't be filtered or ordered by `{}`, since it holds values that \
+                 are too large to compare",
+                entity_type, attribute
+            ),
+            QueryExecutionError::StoreError(entity_types) => {
+                write!(f, "Failed to query {} entities from the store", entity_types)
+            }
+            QueryExecutionError::TimeoutError => write!(f, "Query timed out"),
+        }
+    }
+}
+
+/// Details that clients can act on, reported as the extensions of errors
+/// about the block a query is pinned to.
+#[derive(Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BlockErrorExtensions {
+    code: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    earliest_block: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    block_number: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    canonical_block_hash: Option<String>,
+}
+
+impl BlockErrorExtensions {
+    fn for_error(e: &QueryExecutionError) -> Option<Self> {
+        match e {
+            QueryExecutionError::BlockOutOfRangeError(_, earliest) => Some(BlockErrorExtensions {
+                code: "BLOCK_OUT_OF_RANGE",
+                earliest_block: Some(*earliest),
+                ..Default::default()
+            }),
+            QueryExecutionError::BlockReorgedOutError(_, number, canonical_hash) => {
+                Some(BlockErrorExtensions {
+                    code: "REORGED_OUT",
+                    block_number: Some(*number),
+                    canonical_block_hash: Some(canonical_hash.clone()),
+                    ..Default::default()
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A segment of the path from the root of a response to a field; either
+/// the response key of a field or an index into a list.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+impl Serialize for PathSegment {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            PathSegment::Key(key) => serializer.serialize_str(key),
+            PathSegment::Index(index) => serializer.serialize_u64(*index as u64),
         }
     }
 }
@@ -73,6 +314,17 @@ pub enum QueryError {
     EncodingError(FromUtf8Error),
     ParseError(q::ParseError),
     ExecutionError(QueryExecutionError),
+    /// An error that occurred while executing the field at the given
+    /// position in the query and path in the response.
+    FieldError(Pos, Vec<PathSegment>, QueryExecutionError),
+    /// The query did not finish within the time it was given.
+    Timeout,
+}
+
+impl From<UnsupportedFilter> for QueryExecutionError {
+    fn from(e: UnsupportedFilter) -> Self {
+        QueryExecutionError::UnsupportedFilterError(e.filter.clone(), e.to_string())
+    }
 }
 
 impl From<FromUtf8Error> for QueryError {
@@ -102,6 +354,7 @@ impl Error for QueryError {
         match self {
             &QueryError::EncodingError(ref e) => Some(e),
             &QueryError::ExecutionError(ref e) => Some(e),
+            &QueryError::FieldError(_, _, ref e) => Some(e),
             _ => None,
         }
     }
@@ -112,7 +365,9 @@ impl fmt::Display for QueryError {
         match self {
             &QueryError::EncodingError(ref e) => write!(f, "{}", e),
             &QueryError::ExecutionError(ref e) => write!(f, "{}", e),
+            &QueryError::FieldError(_, _, ref e) => write!(f, "{}", e),
             &QueryError::ParseError(ref e) => write!(f, "{}", e),
+            &QueryError::Timeout => write!(f, "Query timed out"),
         }
     }
 }
@@ -122,7 +377,13 @@ impl Serialize for QueryError {
     where
         S: Serializer,
     {
-        let mut map = serializer.serialize_map(Some(1))?;
+        let mut map = serializer.serialize_map(None)?;
+        let locations = |line, column| {
+            let mut location = HashMap::new();
+            location.insert("line", line);
+            location.insert("column", column);
+            vec![location]
+        };
 
         let msg = match self {
             // Serialize parse errors with their location (line, column) to make it easier
@@ -139,38 +400,42 @@ impl Serialize for QueryError {
                 let colon_pos = parts[0].rfind(":").unwrap();
                 let (a, b) = parts[0].split_at(colon_pos);
 
-                // Find the line and column numbers and convert them to u32
-                let line: u32 = a
+                // Find the line and column numbers and parse them
+                let line: usize = a
                     .matches(char::is_numeric)
                     .collect::<String>()
                     .parse()
                     .unwrap();
-                let column: u32 = b
+                let column: usize = b
                     .matches(char::is_numeric)
                     .collect::<String>()
                     .parse()
                     .unwrap();
 
-                // Generate the list of locations
-                let mut location = HashMap::new();
-                location.insert("line", line);
-                location.insert("column", column);
-                map.serialize_entry("locations", &vec![location])?;
+                map.serialize_entry("locations", &locations(line, column))?;
 
                 // Only use the remainder after the location as the error message
                 parts[1].to_string()
             }
 
-            // Serialize entity resolution errors using their position
-            QueryError::ExecutionError(QueryExecutionError::ResolveEntityError(pos, _))
-            | QueryError::ExecutionError(QueryExecutionError::NonNullError(pos, _))
-            | QueryError::ExecutionError(QueryExecutionError::ListValueError(pos, _))
-            | QueryError::ExecutionError(QueryExecutionError::InvalidArgumentError(pos, _, _))
-            | QueryError::ExecutionError(QueryExecutionError::MissingArgumentError(pos, _)) => {
-                let mut location = HashMap::new();
-                location.insert("line", pos.line);
-                location.insert("column", pos.column);
-                map.serialize_entry("locations", &vec![location])?;
+            // Serialize execution errors using their position, if they have one
+            QueryError::ExecutionError(e) => {
+                if let Some(pos) = e.position() {
+                    map.serialize_entry("locations", &locations(pos.line, pos.column))?;
+                }
+                if let Some(extensions) = BlockErrorExtensions::for_error(e) {
+                    map.serialize_entry("extensions", &extensions)?;
+                }
+                format!("{}", self)
+            }
+
+            // Serialize field errors with the path of the field in the
+            // response, and the position of the field unless the error
+            // points somewhere more specific, e.g. to an argument
+            QueryError::FieldError(field_pos, path, e) => {
+                let pos = e.position().unwrap_or(*field_pos);
+                map.serialize_entry("locations", &locations(pos.line, pos.column))?;
+                map.serialize_entry("path", path)?;
                 format!("{}", self)
             }
             _ => format!("{}", self),
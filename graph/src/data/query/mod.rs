@@ -1,7 +1,12 @@
 mod error;
 mod query;
 mod result;
+mod target;
 
-pub use self::error::{QueryError, QueryExecutionError};
+pub use self::error::{PathSegment, QueryError, QueryExecutionError};
 pub use self::query::{Query, QueryVariableValue, QueryVariables};
-pub use self::result::QueryResult;
+pub use self::result::{
+    ExecutionTrace, QueryPlan, QueryPlanStep, QueryResponse, QueryResult, QueryTrace,
+    ResolverTrace, StoreQueryTrace,
+};
+pub use self::target::{BlockConstraint, QueryTarget, SubgraphTarget};
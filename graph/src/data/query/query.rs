@@ -1,21 +1,15 @@
-use futures::sync::oneshot;
 use graphql_parser::query as q;
+use serde::{self, Deserialize};
+use serde_json;
 use std::cmp::PartialEq;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::ops::{Deref, DerefMut};
 
-use super::QueryResult;
 use data::schema::Schema;
 
-#[derive(Deserialize)]
-#[serde(untagged, remote = "q::Value")]
-enum GraphQLValue {
-    String(String),
-}
-
 /// Variable value for a GraphQL query.
-#[derive(Debug, Deserialize)]
-pub struct QueryVariableValue(#[serde(with = "GraphQLValue")] q::Value);
+#[derive(Debug)]
+pub struct QueryVariableValue(q::Value);
 
 impl Deref for QueryVariableValue {
     type Target = q::Value;
@@ -43,6 +37,38 @@ impl<'a> From<&'a str> for QueryVariableValue {
     }
 }
 
+impl<'de> Deserialize<'de> for QueryVariableValue {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value: serde_json::Value = Deserialize::deserialize(deserializer)?;
+        Ok(QueryVariableValue(graphql_value(value)))
+    }
+}
+
+/// Converts a JSON value into the GraphQL value it represents. Numbers that
+/// are not 32-bit integers become floats, which is how GraphQL treats them.
+fn graphql_value(value: serde_json::Value) -> q::Value {
+    match value {
+        serde_json::Value::Null => q::Value::Null,
+        serde_json::Value::Bool(b) => q::Value::Boolean(b),
+        serde_json::Value::Number(number) => match number.as_i64() {
+            Some(i) if i >= i32::min_value() as i64 && i <= i32::max_value() as i64 => {
+                q::Value::Int(q::Number::from(i as i32))
+            }
+            _ => q::Value::Float(number.as_f64().unwrap_or(0.0)),
+        },
+        serde_json::Value::String(s) => q::Value::String(s),
+        serde_json::Value::Array(values) => {
+            q::Value::List(values.into_iter().map(graphql_value).collect())
+        }
+        serde_json::Value::Object(fields) => q::Value::Object(
+            fields
+                .into_iter()
+                .map(|(name, value)| (name, graphql_value(value)))
+                .collect::<BTreeMap<_, _>>(),
+        ),
+    }
+}
+
 /// Variable values for a GraphQL query.
 #[derive(Debug, Deserialize)]
 pub struct QueryVariables(HashMap<String, QueryVariableValue>);
@@ -79,5 +105,15 @@ pub struct Query {
     pub schema: Schema,
     pub document: q::Document,
     pub variables: Option<QueryVariables>,
-    pub result_sender: oneshot::Sender<QueryResult>,
+    /// The name of the operation to run, if the document contains several.
+    pub operation_name: Option<String>,
+    /// The verified role of the client. Fields marked `@restricted` are only
+    /// visible to clients with the matching role.
+    pub role: Option<String>,
+    /// Whether to include the plan of the query, i.e. the store queries it
+    /// results in, with the result.
+    pub explain: bool,
+    /// Whether to include how long resolving each field took, and the store
+    /// queries made, with the result.
+    pub trace: bool,
 }
@@ -1,17 +1,31 @@
+use futures::stream;
+use futures::Stream;
 use graphql_parser::query as q;
+use std::fmt;
+use std::time::Duration;
 
-use super::error::{QueryError, QueryExecutionError};
+use super::error::{PathSegment, QueryError, QueryExecutionError};
 
 /// The result of running a query, if successful.
 #[derive(Debug)]
 pub struct QueryResult {
     pub data: Option<q::Value>,
     pub errors: Option<Vec<QueryError>>,
+    /// How the query was resolved, if the client asked for it.
+    pub plan: Option<QueryPlan>,
+    /// How long resolving the fields of the query took, if the client asked
+    /// for it.
+    pub trace: Option<QueryTrace>,
 }
 
 impl QueryResult {
     pub fn new(data: Option<q::Value>) -> Self {
-        QueryResult { data, errors: None }
+        QueryResult {
+            data,
+            errors: None,
+            plan: None,
+            trace: None,
+        }
     }
 
     pub fn add_error(&mut self, e: QueryError) {
@@ -27,3 +41,108 @@ impl From<QueryExecutionError> for QueryResult {
         result
     }
 }
+
+/// The steps taken to resolve a query, in the order in which they were taken.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct QueryPlan {
+    pub steps: Vec<QueryPlanStep>,
+}
+
+/// A store query made while resolving a field.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct QueryPlanStep {
+    /// The field that was resolved.
+    pub field: String,
+    /// The entity type that was queried.
+    pub entity: String,
+    /// How the entities were fetched, e.g. `find` or `prefetch`.
+    pub strategy: String,
+    /// The SQL the store generated for the query, if it uses SQL.
+    pub sql: Option<String>,
+}
+
+/// How long it took to execute a query and to resolve each of its fields,
+/// in the format of Apollo tracing. Durations and offsets from the start of
+/// the execution are in nanoseconds.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryTrace {
+    pub version: u32,
+    pub duration: u64,
+    pub execution: ExecutionTrace,
+    /// The store queries made, in the order in which they were made.
+    pub store_queries: Vec<StoreQueryTrace>,
+}
+
+impl QueryTrace {
+    /// Converts a duration into the nanoseconds that traces are measured in.
+    pub fn nanos(duration: Duration) -> u64 {
+        duration.as_secs() * 1_000_000_000 + u64::from(duration.subsec_nanos())
+    }
+}
+
+/// The fields resolved while executing a query.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct ExecutionTrace {
+    pub resolvers: Vec<ResolverTrace>,
+}
+
+/// The time it took to resolve a field, including its subfields.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolverTrace {
+    pub path: Vec<PathSegment>,
+    pub parent_type: String,
+    pub field_name: String,
+    pub return_type: String,
+    pub start_offset: u64,
+    pub duration: u64,
+}
+
+/// A store query made while resolving a field.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoreQueryTrace {
+    /// The field that was resolved.
+    pub field: String,
+    /// The entity type that was queried.
+    pub entity: String,
+    /// How the entities were fetched, e.g. `find` or `prefetch`.
+    pub strategy: String,
+    pub start_offset: u64,
+}
+
+/// The response produced by running a query.
+///
+/// Regular queries produce a single, complete result. Subscriptions and
+/// incrementally delivered queries produce a stream of results instead.
+pub enum QueryResponse {
+    Complete(QueryResult),
+    Stream(Box<Stream<Item = QueryResult, Error = ()> + Send>),
+}
+
+impl QueryResponse {
+    /// Turns the response into a stream of results; a complete result
+    /// becomes a stream with a single item.
+    pub fn into_stream(self) -> Box<Stream<Item = QueryResult, Error = ()> + Send> {
+        match self {
+            QueryResponse::Complete(result) => Box::new(stream::once(Ok(result))),
+            QueryResponse::Stream(results) => results,
+        }
+    }
+}
+
+impl fmt::Debug for QueryResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QueryResponse::Complete(result) => write!(f, "Complete({:?})", result),
+            QueryResponse::Stream(_) => write!(f, "Stream(..)"),
+        }
+    }
+}
+
+impl From<QueryResult> for QueryResponse {
+    fn from(result: QueryResult) -> Self {
+        QueryResponse::Complete(result)
+    }
+}
@@ -0,0 +1,142 @@
+use ethereum_types::H256;
+use std::fmt;
+use std::str::FromStr;
+
+/// Identifies the subgraph a query is to be run against.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SubgraphTarget {
+    /// A specific subgraph deployment, identified by its ID.
+    Deployment(String),
+    /// A named subgraph at a specific version, written as `name@version`.
+    Name { name: String, version: String },
+}
+
+impl fmt::Display for SubgraphTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SubgraphTarget::Deployment(id) => write!(f, "{}", id),
+            SubgraphTarget::Name { name, version } => write!(f, "{}@{}", name, version),
+        }
+    }
+}
+
+impl FromStr for SubgraphTarget {
+    type Err = String;
+
+    /// Parses `name@version` into a named target and anything else into a
+    /// deployment ID.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err(String::from("empty subgraph target"));
+        }
+
+        let mut parts = s.splitn(2, '@');
+        let name = parts.next().unwrap();
+        match parts.next() {
+            Some(version) if !name.is_empty() && !version.is_empty() => Ok(SubgraphTarget::Name {
+                name: name.to_owned(),
+                version: version.to_owned(),
+            }),
+            Some(_) => Err(format!("invalid subgraph target `{}`", s)),
+            None => Ok(SubgraphTarget::Deployment(name.to_owned())),
+        }
+    }
+}
+
+/// Constrains the block at which a query is evaluated.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum BlockConstraint {
+    /// Evaluate the query against the block with this hash.
+    Hash(H256),
+    /// Evaluate the query against the block with this number.
+    Number(u64),
+}
+
+impl BlockConstraint {
+    /// Pins a query to a block hash given in hex, with or without `0x`.
+    pub fn hash_from_hex(hash: &str) -> Option<Self> {
+        let hash = if hash.starts_with("0x") {
+            &hash[2..]
+        } else {
+            hash
+        };
+        H256::from_str(hash).ok().map(BlockConstraint::Hash)
+    }
+}
+
+/// Where a query should be run: which subgraph, and optionally at which block.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct QueryTarget {
+    pub subgraph: SubgraphTarget,
+    pub block: Option<BlockConstraint>,
+}
+
+impl QueryTarget {
+    /// Targets the latest state of the given subgraph.
+    pub fn new(subgraph: SubgraphTarget) -> Self {
+        QueryTarget {
+            subgraph,
+            block: None,
+        }
+    }
+
+    /// Targets the given subgraph at a specific block.
+    pub fn at_block(subgraph: SubgraphTarget, block: BlockConstraint) -> Self {
+        QueryTarget {
+            subgraph,
+            block: Some(block),
+        }
+    }
+}
+
+impl fmt::Display for QueryTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.block {
+            None => write!(f, "{}", self.subgraph),
+            Some(BlockConstraint::Hash(ref hash)) => write!(f, "{} at {:x}", self.subgraph, hash),
+            Some(BlockConstraint::Number(number)) => write!(f, "{} at #{}", self.subgraph, number),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_deployment_targets() {
+        assert_eq!(
+            "QmSubgraph".parse::<SubgraphTarget>(),
+            Ok(SubgraphTarget::Deployment("QmSubgraph".to_owned()))
+        );
+    }
+
+    #[test]
+    fn parses_named_targets() {
+        assert_eq!(
+            "users@1".parse::<SubgraphTarget>(),
+            Ok(SubgraphTarget::Name {
+                name: "users".to_owned(),
+                version: "1".to_owned(),
+            })
+        );
+        assert!("@1".parse::<SubgraphTarget>().is_err());
+        assert!("users@".parse::<SubgraphTarget>().is_err());
+        assert!("".parse::<SubgraphTarget>().is_err());
+    }
+
+    #[test]
+    fn parses_block_hashes() {
+        let hash = "0x1d5a0ec27ba64ce1bfd2d43b5f3e53e3ae9c9ffe3e0e62a8c1f6b8e06a8e7c44";
+        assert_eq!(
+            BlockConstraint::hash_from_hex(hash),
+            Some(BlockConstraint::Hash(H256::from_str(&hash[2..]).unwrap()))
+        );
+        assert_eq!(
+            BlockConstraint::hash_from_hex(&hash[2..]),
+            BlockConstraint::hash_from_hex(hash)
+        );
+        assert_eq!(BlockConstraint::hash_from_hex("0x1234"), None);
+        assert_eq!(BlockConstraint::hash_from_hex("latest"), None);
+    }
+}
@@ -1,4 +1,5 @@
 use graphql_parser::{schema, Pos};
+use std::collections::{HashMap, HashSet};
 
 /// A GraphQL schema with additional meta data.
 #[derive(Clone, Debug)]
@@ -7,7 +8,224 @@ pub struct Schema {
     pub document: schema::Document,
 }
 
+/// Returns true unless the field is marked `@notStored`. Values that
+/// mappings set for fields that are not stored are dropped, and the fields
+/// cannot be queried.
+pub fn is_field_stored(field: &schema::Field) -> bool {
+    !field
+        .directives
+        .iter()
+        .any(|directive| directive.name == "notStored")
+}
+
+/// Returns true if the field is derived with `@derivedFrom`. Derived fields
+/// are resolved by looking up the entities that reference the parent entity,
+/// so values that mappings set for them are dropped.
+pub fn is_field_derived(field: &schema::Field) -> bool {
+    field
+        .directives
+        .iter()
+        .any(|directive| directive.name == "derivedFrom")
+}
+
+/// Returns the retention period of an entity type in seconds, if it has
+/// one, or the invalid period that was given in its `@entity(retention: ...)`
+/// directive.
+pub fn retention_period(object_type: &schema::ObjectType) -> Result<Option<u64>, String> {
+    let value = object_type
+        .directives
+        .iter()
+        .filter(|directive| directive.name == "entity")
+        .flat_map(|directive| directive.arguments.iter())
+        .find(|(name, _)| name == "retention")
+        .map(|(_, value)| value);
+
+    match value {
+        None => Ok(None),
+        Some(schema::Value::String(period)) => parse_retention_period(period)
+            .map(Some)
+            .ok_or_else(|| period.to_owned()),
+        Some(value) => Err(format!("{}", value)),
+    }
+}
+
+/// Parses a period such as `"30d"` into seconds. Periods are a positive
+/// number of seconds (`s`), minutes (`m`), hours (`h`) or days (`d`).
+fn parse_retention_period(period: &str) -> Option<u64> {
+    let unit = match period.chars().last()? {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        _ => return None,
+    };
+    let count = period[..period.len() - 1].parse::<u64>().ok()?;
+    if count == 0 {
+        return None;
+    }
+    count.checked_mul(unit)
+}
+
+/// Postgres text search configurations that full-text searches can use.
+const FULLTEXT_LANGUAGES: &[&str] = &[
+    "simple",
+    "danish",
+    "dutch",
+    "english",
+    "finnish",
+    "french",
+    "german",
+    "hungarian",
+    "italian",
+    "norwegian",
+    "portuguese",
+    "romanian",
+    "russian",
+    "spanish",
+    "swedish",
+    "turkish",
+];
+
+/// A full-text search over string fields of an entity type, declared with
+/// `@fulltext(name: "...", language: "...", fields: [...])` on the type.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FulltextDefinition {
+    /// Name of the search; it is queried with the `<name>Search` field.
+    pub name: String,
+    /// The entity type whose entities are searched.
+    pub entity: String,
+    /// Postgres text search configuration, `english` unless specified.
+    pub language: String,
+    /// The fields whose text is searched.
+    pub fields: Vec<String>,
+}
+
+/// Returns the full-text searches declared on an entity type, or what is
+/// wrong with one of them.
+pub fn fulltext_definitions(
+    object_type: &schema::ObjectType,
+) -> Result<Vec<FulltextDefinition>, String> {
+    object_type
+        .directives
+        .iter()
+        .filter(|directive| directive.name == "fulltext")
+        .map(|directive| {
+            let argument = |name: &str| {
+                directive
+                    .arguments
+                    .iter()
+                    .find(|(argument_name, _)| argument_name == name)
+                    .map(|(_, value)| value)
+            };
+
+            let name = match argument("name") {
+                Some(schema::Value::String(name)) if !name.is_empty() => name.to_owned(),
+                _ => return Err(String::from("expected a `name`")),
+            };
+            let language = match argument("language") {
+                None => String::from("english"),
+                Some(schema::Value::String(language))
+                    if FULLTEXT_LANGUAGES.contains(&language.as_str()) =>
+                {
+                    language.to_owned()
+                }
+                Some(value) => return Err(format!("unsupported language {}", value)),
+            };
+            let fields = match argument("fields") {
+                Some(schema::Value::List(values)) if !values.is_empty() => values
+                    .iter()
+                    .map(|value| match value {
+                        schema::Value::String(field) => Ok(field.to_owned()),
+                        value => Err(format!("expected field names, found {}", value)),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?,
+                _ => return Err(String::from("expected a non-empty list of `fields`")),
+            };
+
+            for field_name in fields.iter() {
+                match object_type.fields.iter().find(|f| &f.name == field_name) {
+                    Some(field) if is_field_stored(field) && is_text_type(&field.field_type) => (),
+                    Some(_) => {
+                        return Err(format!("field \"{}\" is not a stored String field", field_name))
+                    }
+                    None => return Err(format!("field \"{}\" does not exist", field_name)),
+                }
+            }
+
+            Ok(FulltextDefinition {
+                name,
+                entity: object_type.name.clone(),
+                language,
+                fields,
+            })
+        })
+        .collect()
+}
+
+/// Returns true for `String` types and lists of them.
+fn is_text_type(field_type: &schema::Type) -> bool {
+    match field_type {
+        schema::Type::NamedType(name) => name == "String",
+        schema::Type::NonNullType(t) | schema::Type::ListType(t) => is_text_type(t),
+    }
+}
+
 impl Schema {
+    /// Returns the fields of each entity type that are not stored, either
+    /// because they are marked `@notStored` or because they are derived.
+    pub fn not_stored_fields(&self) -> HashMap<String, HashSet<String>> {
+        self.document
+            .definitions
+            .iter()
+            .filter_map(|definition| match definition {
+                schema::Definition::TypeDefinition(schema::TypeDefinition::Object(t)) => Some(t),
+                _ => None,
+            })
+            .map(|object_type| {
+                let fields = object_type
+                    .fields
+                    .iter()
+                    .filter(|field| !is_field_stored(field) || is_field_derived(field))
+                    .map(|field| field.name.clone())
+                    .collect::<HashSet<_>>();
+                (object_type.name.clone(), fields)
+            })
+            .filter(|(_, fields)| !fields.is_empty())
+            .collect()
+    }
+
+    /// Returns the retention period in seconds of each entity type that has
+    /// a valid one. Entities of these types that have not been updated for
+    /// longer than that are pruned.
+    pub fn retention_periods(&self) -> HashMap<String, u64> {
+        self.document
+            .definitions
+            .iter()
+            .filter_map(|definition| match definition {
+                schema::Definition::TypeDefinition(schema::TypeDefinition::Object(t)) => Some(t),
+                _ => None,
+            })
+            .filter_map(|object_type| match retention_period(object_type) {
+                Ok(Some(period)) => Some((object_type.name.clone(), period)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the valid full-text searches declared on entity types.
+    pub fn fulltext_definitions(&self) -> Vec<FulltextDefinition> {
+        self.document
+            .definitions
+            .iter()
+            .filter_map(|definition| match definition {
+                schema::Definition::TypeDefinition(schema::TypeDefinition::Object(t)) => Some(t),
+                _ => None,
+            })
+            .filter_map(|object_type| fulltext_definitions(object_type).ok())
+            .flat_map(|definitions| definitions.into_iter())
+            .collect()
+    }
+
     // Adds a @subgraphId(id: ...) directive to object/interface/enum types in the schema.
     pub fn add_subgraph_id_directives(&mut self, id: String) {
         for definition in self.document.definitions.iter_mut() {
@@ -42,3 +260,89 @@ impl Schema {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use graphql_parser;
+
+    use super::{FulltextDefinition, Schema};
+
+    #[test]
+    fn leaves_out_fields_that_are_not_stored_or_derived() {
+        let schema = Schema {
+            id: String::from("test"),
+            document: graphql_parser::parse_schema(
+                "
+                type Tx { id: ID!, input: Bytes @notStored, from: Account! }
+                type Account { id: ID!, txs: [Tx!]! @derivedFrom(field: \"from\") }
+                type Block { id: ID!, number: BigInt! }
+                ",
+            ).unwrap(),
+        };
+
+        let fields = schema.not_stored_fields();
+        assert_eq!(fields.len(), 2);
+        assert!(fields["Tx"].contains("input"));
+        assert!(fields["Account"].contains("txs"));
+        assert_eq!(fields["Account"].len(), 1);
+    }
+
+    #[test]
+    fn reads_retention_periods_of_entity_types() {
+        let schema = Schema {
+            id: String::from("test"),
+            document: graphql_parser::parse_schema(
+                "
+                type Snapshot @entity(retention: \"30d\") { id: ID! }
+                type Minute @entity(retention: \"90m\") { id: ID! }
+                type Invalid @entity(retention: \"forever\") { id: ID! }
+                type Token @entity { id: ID! }
+                ",
+            ).unwrap(),
+        };
+
+        let periods = schema.retention_periods();
+        assert_eq!(periods.len(), 2);
+        assert_eq!(periods["Snapshot"], 30 * 24 * 60 * 60);
+        assert_eq!(periods["Minute"], 90 * 60);
+    }
+
+    #[test]
+    fn reads_fulltext_definitions_of_entity_types() {
+        let schema = Schema {
+            id: String::from("test"),
+            document: graphql_parser::parse_schema(
+                "
+                type Band @fulltext(name: \"band\", fields: [\"name\", \"genres\"]) {
+                    id: ID!, name: String!, genres: [String!]!, founded: Int
+                }
+                type Song @fulltext(name: \"song\", language: \"french\", fields: [\"title\"]) {
+                    id: ID!, title: String!
+                }
+                type Label @fulltext(name: \"label\", fields: [\"founded\"]) {
+                    id: ID!, founded: Int
+                }
+                ",
+            ).unwrap(),
+        };
+
+        let definitions = schema.fulltext_definitions();
+        assert_eq!(
+            definitions,
+            vec![
+                FulltextDefinition {
+                    name: String::from("band"),
+                    entity: String::from("Band"),
+                    language: String::from("english"),
+                    fields: vec![String::from("name"), String::from("genres")],
+                },
+                FulltextDefinition {
+                    name: String::from("song"),
+                    entity: String::from("Song"),
+                    language: String::from("french"),
+                    fields: vec![String::from("title")],
+                },
+            ]
+        );
+    }
+}
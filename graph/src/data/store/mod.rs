@@ -12,9 +12,44 @@ pub mod scalar;
 /// An entity attribute name is represented as a string.
 pub type Attribute = String;
 
+/// The type of the values of an attribute, which determines how they are
+/// compared when entities are ordered by it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ValueType {
+    Boolean,
+    BigDecimal,
+    BigInt,
+    Bytes,
+    Float,
+    Int,
+    /// Strings, IDs, enum values and references to other entities.
+    String,
+    List,
+}
+
+impl ValueType {
+    /// Returns the type of the values of a field with the given GraphQL type.
+    pub fn from_field_type(field_type: &schema::Type) -> ValueType {
+        match field_type {
+            schema::Type::NonNullType(inner) => ValueType::from_field_type(inner),
+            schema::Type::ListType(_) => ValueType::List,
+            schema::Type::NamedType(name) => match name.as_str() {
+                "Boolean" => ValueType::Boolean,
+                BIG_DECIMAL_SCALAR => ValueType::BigDecimal,
+                BIG_INT_SCALAR => ValueType::BigInt,
+                BYTES_SCALAR => ValueType::Bytes,
+                "Float" => ValueType::Float,
+                "Int" => ValueType::Int,
+                _ => ValueType::String,
+            },
+        }
+    }
+}
+
 pub const ID: &str = "ID";
 pub const BYTES_SCALAR: &str = "Bytes";
 pub const BIG_INT_SCALAR: &str = "BigInt";
+pub const BIG_DECIMAL_SCALAR: &str = "BigDecimal";
 
 /// An attribute value is represented as an enum with variants for all supported value types.
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
@@ -28,6 +63,7 @@ pub enum Value {
     Null,
     Bytes(scalar::Bytes),
     BigInt(scalar::BigInt),
+    BigDecimal(scalar::BigDecimal),
 }
 
 impl Value {
@@ -62,9 +98,22 @@ impl Value {
                     BIG_INT_SCALAR => {
                         Value::BigInt(scalar::BigInt::from_str(s).expect("Value is not a number"))
                     }
+                    BIG_DECIMAL_SCALAR => Value::BigDecimal(
+                        scalar::BigDecimal::from_str(s).expect("Value is not a decimal number"),
+                    ),
                     _ => Value::String(s.clone()),
                 }
             }
+            // Numbers given for big number types keep their full precision
+            (query::Value::Int(i), NamedType(n))
+                if n == BIG_INT_SCALAR || n == BIG_DECIMAL_SCALAR =>
+            {
+                let number = i.as_i64().expect("Value is not a number").to_string();
+                Self::from_query_value(&query::Value::String(number), ty)
+            }
+            (query::Value::Float(f), NamedType(n)) if n == BIG_DECIMAL_SCALAR => {
+                Self::from_query_value(&query::Value::String(f.to_string()), ty)
+            }
             (query::Value::Int(i), _) => Value::Int(
                 i.to_owned()
                     .as_i64()
@@ -92,6 +141,7 @@ impl From<Value> for query::Value {
             }
             Value::Bytes(bytes) => query::Value::String(bytes.to_string()),
             Value::BigInt(number) => query::Value::String(number.to_string()),
+            Value::BigDecimal(number) => query::Value::String(number.to_string()),
         }
     }
 }
@@ -202,3 +252,18 @@ fn value_bigint() {
     );
     assert_eq!(query::Value::from(from_query), graphql_value);
 }
+
+#[test]
+fn value_bigdecimal() {
+    let graphql_value = query::Value::String("-12345678901234567890.0123456789".to_owned());
+    let ty = query::Type::NamedType(BIG_DECIMAL_SCALAR.to_owned());
+    let from_query = Value::from_query_value(&graphql_value, &ty);
+    assert_eq!(
+        from_query,
+        Value::BigDecimal(FromStr::from_str("-12345678901234567890.0123456789").unwrap())
+    );
+    assert_eq!(query::Value::from(from_query), graphql_value);
+
+    let from_query = Value::from_query_value(&query::Value::Float(1.5), &ty);
+    assert_eq!(from_query, Value::BigDecimal(FromStr::from_str("1.5").unwrap()));
+}
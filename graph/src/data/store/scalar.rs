@@ -1,3 +1,4 @@
+use bigdecimal;
 use hex;
 use num_bigint;
 use serde::{self, Deserialize, Serialize};
@@ -47,6 +48,39 @@ impl<'de> Deserialize<'de> for BigInt {
     }
 }
 
+/// An arbitrary precision decimal number that's serialized as a string.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct BigDecimal(bigdecimal::BigDecimal);
+
+impl Display for BigDecimal {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for BigDecimal {
+    type Err = <bigdecimal::BigDecimal as FromStr>::Err;
+
+    fn from_str(s: &str) -> Result<BigDecimal, Self::Err> {
+        bigdecimal::BigDecimal::from_str(s).map(|x| BigDecimal(x))
+    }
+}
+
+impl Serialize for BigDecimal {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_string().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for BigDecimal {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let decimal_string: &str = Deserialize::deserialize(deserializer)?;
+        BigDecimal::from_str(decimal_string).map_err(D::Error::custom)
+    }
+}
+
 /// A byte array that's serialized as a hex string prefixed by `0x`.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Bytes(Box<[u8]>);
@@ -9,6 +9,7 @@ use parity_wasm;
 use parity_wasm::elements::Module;
 use serde_yaml;
 use tokio::prelude::*;
+use util::error::GraphError;
 
 use std::sync::Arc;
 
@@ -18,6 +19,12 @@ pub enum SubgraphProviderError {
     ResolveError(SubgraphManifestResolveError),
     #[fail(display = "error sending subgraph")]
     SendError,
+    #[fail(display = "the mappings of the subgraph changed since it was indexed: {}", _0)]
+    MappingsChanged(String),
+    #[fail(display = "failed to look up the mappings of the subgraph: {}", _0)]
+    MappingPinError(GraphError),
+    #[fail(display = "subgraph is not indexed on this node: {}", _0)]
+    PlacementRefused(String),
 }
 
 #[derive(Fail, Debug)]
@@ -101,6 +108,10 @@ impl UnresolvedMappingABI {
 pub struct MappingEventHandler {
     pub event: String,
     pub handler: String,
+    /// The entity types that the handler reads or writes, if declared. Only
+    /// handlers that declare them can run concurrently with other handlers.
+    #[serde(default)]
+    pub entities: Option<Vec<String>>,
 }
 
 #[derive(Clone, Debug, Hash, Eq, PartialEq, Deserialize)]
@@ -160,6 +171,9 @@ impl UnresolvedMapping {
 #[derive(Clone, Debug, Hash, Eq, PartialEq, Deserialize)]
 pub struct BaseDataSource<C, W> {
     pub kind: String,
+    /// Network of the source, e.g. `mainnet`, if the manifest declares it.
+    #[serde(default)]
+    pub network: Option<String>,
     pub name: String,
     pub source: Source,
     pub mapping: BaseMapping<C, W>,
@@ -175,12 +189,14 @@ impl UnresolvedDataSource {
     ) -> impl Future<Item = DataSource, Error = failure::Error> {
         let UnresolvedDataSource {
             kind,
+            network,
             name,
             source,
             mapping,
         } = self;
         mapping.resolve(resolver).map(|mapping| DataSource {
             kind,
+            network,
             name,
             source,
             mapping,
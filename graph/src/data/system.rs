@@ -0,0 +1,33 @@
+use data::usage::DeploymentUsage;
+
+/// A snapshot of the state of a node, as queried through the `graph-system`
+/// schema.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SystemInfo {
+    /// Version of the node software.
+    pub version: String,
+    /// Names of the optional features that are enabled on the node.
+    pub features: Vec<String>,
+    pub deployments: Vec<DeploymentInfo>,
+    pub chain_heads: Vec<ChainHead>,
+}
+
+/// A subgraph deployment known to the node.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeploymentInfo {
+    /// ID of the deployed subgraph.
+    pub id: String,
+    /// Whether the node currently serves queries for the deployment.
+    pub served: bool,
+    /// How much the deployment has been queried, if usage is tracked.
+    pub usage: Option<DeploymentUsage>,
+}
+
+/// The latest block of a chain that the node has indexed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChainHead {
+    /// Name of the chain, e.g. `ethereum`.
+    pub network: String,
+    /// Number of the latest block, if known.
+    pub block_number: Option<u64>,
+}
@@ -0,0 +1,61 @@
+/// How much a subgraph indexed during a period of time.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ThroughputSample {
+    /// ID of the deployed subgraph.
+    pub subgraph_id: String,
+    /// When the period started, in seconds since the Unix epoch.
+    pub started_at: u64,
+    /// Length of the period in seconds.
+    pub seconds: u64,
+    /// Number of blocks that the subgraph handled triggers in.
+    pub blocks: u64,
+    /// Number of triggers, such as Ethereum events, passed to handlers.
+    pub triggers: u64,
+    /// Number of entities that handlers set or removed.
+    pub entities: u64,
+}
+
+impl ThroughputSample {
+    pub fn blocks_per_second(&self) -> f64 {
+        self.per_second(self.blocks)
+    }
+
+    pub fn triggers_per_second(&self) -> f64 {
+        self.per_second(self.triggers)
+    }
+
+    pub fn entities_per_second(&self) -> f64 {
+        self.per_second(self.entities)
+    }
+
+    fn per_second(&self, count: u64) -> f64 {
+        if self.seconds == 0 {
+            0.0
+        } else {
+            count as f64 / self.seconds as f64
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ThroughputSample;
+
+    #[test]
+    fn rates_are_averaged_over_the_period() {
+        let sample = ThroughputSample {
+            subgraph_id: String::from("subgraph"),
+            started_at: 0,
+            seconds: 3600,
+            blocks: 360,
+            triggers: 7200,
+            entities: 900,
+        };
+        assert_eq!(sample.blocks_per_second(), 0.1);
+        assert_eq!(sample.triggers_per_second(), 2.0);
+        assert_eq!(sample.entities_per_second(), 0.25);
+
+        let empty = ThroughputSample { seconds: 0, ..sample };
+        assert_eq!(empty.blocks_per_second(), 0.0);
+    }
+}
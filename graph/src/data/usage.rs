@@ -0,0 +1,101 @@
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// How much a subgraph deployment is being queried.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeploymentUsage {
+    /// ID of the deployed subgraph.
+    pub subgraph_id: String,
+    /// Number of queries against the deployment.
+    pub queries: u64,
+    /// When usage tracking started, in seconds since the Unix epoch.
+    pub tracked_since: u64,
+    /// When the deployment was last queried, in seconds since the Unix epoch.
+    pub last_queried_at: Option<u64>,
+}
+
+impl DeploymentUsage {
+    /// Seconds without queries up to `now`. Deployments that were never
+    /// queried count as idle since tracking started.
+    pub fn idle_seconds(&self, now: u64) -> u64 {
+        now.saturating_sub(self.last_queried_at.unwrap_or(self.tracked_since))
+    }
+}
+
+/// What to do with deployments that have not been queried for a while.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IdleDeploymentAction {
+    /// Move the entities of the deployment into compressed storage.
+    Pause,
+    /// Delete all entities of the deployment.
+    Remove,
+}
+
+impl FromStr for IdleDeploymentAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pause" => Ok(IdleDeploymentAction::Pause),
+            "remove" => Ok(IdleDeploymentAction::Remove),
+            _ => Err(format!("Invalid idle deployment action: {}", s)),
+        }
+    }
+}
+
+/// Policy for cleaning up deployments that have not been queried for a
+/// number of days.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IdleDeploymentPolicy {
+    pub max_idle_days: u64,
+    pub action: IdleDeploymentAction,
+}
+
+impl IdleDeploymentPolicy {
+    /// Returns true if the deployment has been idle for too long at `now`.
+    pub fn applies_to(&self, usage: &DeploymentUsage, now: u64) -> bool {
+        usage.idle_seconds(now) >= self.max_idle_days * SECONDS_PER_DAY
+    }
+}
+
+/// Returns the current time in seconds since the Unix epoch.
+pub fn current_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System time is before the Unix epoch")
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DeploymentUsage, IdleDeploymentAction, IdleDeploymentPolicy, SECONDS_PER_DAY};
+
+    fn usage(last_queried_at: Option<u64>) -> DeploymentUsage {
+        DeploymentUsage {
+            subgraph_id: String::from("subgraph"),
+            queries: if last_queried_at.is_some() { 1 } else { 0 },
+            tracked_since: 1000,
+            last_queried_at,
+        }
+    }
+
+    #[test]
+    fn unqueried_deployments_are_idle_since_tracking_started() {
+        assert_eq!(usage(None).idle_seconds(1500), 500);
+        assert_eq!(usage(Some(1200)).idle_seconds(1500), 300);
+        assert_eq!(usage(Some(1200)).idle_seconds(1100), 0);
+    }
+
+    #[test]
+    fn policy_applies_to_deployments_idle_for_too_many_days() {
+        let policy = IdleDeploymentPolicy {
+            max_idle_days: 2,
+            action: IdleDeploymentAction::Pause,
+        };
+        assert!(!policy.applies_to(&usage(Some(1000)), 1000 + SECONDS_PER_DAY));
+        assert!(policy.applies_to(&usage(Some(1000)), 1000 + 2 * SECONDS_PER_DAY));
+        assert!(policy.applies_to(&usage(None), 1000 + 3 * SECONDS_PER_DAY));
+    }
+}
@@ -1,3 +1,4 @@
+extern crate bigdecimal;
 extern crate ethabi;
 extern crate ethereum_types;
 extern crate futures;
@@ -49,25 +50,53 @@ pub mod prelude {
 
     pub use std::sync::Arc;
 
+    pub use components::api_key::ApiKeyStore;
+    pub use components::compression::CompressionStore;
     pub use components::ethereum::EthereumAdapter;
+    pub use components::export::EntityExportStore;
+    pub use components::fulltext::FulltextStore;
+    pub use components::lag::IndexingLagStatus;
     pub use components::link_resolver::LinkResolver;
-    pub use components::query::QueryRunner;
+    pub use components::mapping_log::MappingLogCapture;
+    pub use components::mapping_pin::MappingPinStore;
+    pub use components::metrics::StoreMetrics;
+    pub use components::offchain::OffchainEntityStore;
+    pub use components::query::{QueryResponseFuture, QueryRunner};
+    pub use components::retention::EntityRetentionStore;
+    pub use components::rewind::RewindStore;
     pub use components::server::{GraphQLServer, JsonRpcServer};
     pub use components::store::{
-        BasicStore, EventSource, Store, StoreEvent, StoreFilter, StoreKey, StoreOrder, StoreQuery,
-        StoreRange,
+        BasicStore, EventSource, FulltextSearch, QueryScope, Store, StoreAggregate, StoreEvent,
+        StoreFilter, StoreKey, StoreOrder, StoreQuery, StoreRange, StoreWindow, StoreWriteError,
+        UnsupportedFilter,
     };
     pub use components::subgraph::{
         RuntimeHost, RuntimeHostBuilder, RuntimeHostEvent, RuntimeManager, SchemaEvent,
         SubgraphProvider, SubgraphProviderEvent,
     };
+    pub use components::throughput::{ThroughputCapture, ThroughputStore};
+    pub use components::usage::DeploymentUsageStore;
+    pub use components::version::EntityVersionStore;
     pub use components::{EventConsumer, EventProducer};
 
+    pub use data::api_key::{ApiKey, ApiKeyUsage};
+    pub use data::compression::CompressedSubgraph;
+    pub use data::lag::{IndexingLag, LagThreshold};
+    pub use data::mapping_log::{MappingLogEntry, MappingLogLevel};
+    pub use data::mapping_pin::MappingPin;
+    pub use data::metrics::{LatencyHistogram, StoreLatency, StoreOperation};
+    pub use data::placement::{Placement, PlacementMatch, PlacementRule};
     pub use data::query::{
-        Query, QueryError, QueryExecutionError, QueryResult, QueryVariableValue, QueryVariables,
+        BlockConstraint, ExecutionTrace, PathSegment, Query, QueryError, QueryExecutionError,
+        QueryPlan, QueryPlanStep, QueryResponse, QueryResult, QueryTarget, QueryTrace,
+        QueryVariableValue, QueryVariables, ResolverTrace, StoreQueryTrace, SubgraphTarget,
     };
-    pub use data::schema::Schema;
-    pub use data::store::{Attribute, Entity, Value};
+    pub use data::schema::{FulltextDefinition, Schema};
+    pub use data::store::{Attribute, Entity, Value, ValueType};
     pub use data::subgraph::{DataSource, Link, SubgraphManifest, SubgraphManifestResolveError};
+    pub use data::system::{ChainHead, DeploymentInfo, SystemInfo};
+    pub use data::throughput::ThroughputSample;
+    pub use data::usage::{DeploymentUsage, IdleDeploymentAction, IdleDeploymentPolicy};
+    pub use util::error::{ErrorCategory, GraphError};
     pub use util::stream::StreamError;
 }
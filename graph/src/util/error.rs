@@ -0,0 +1,159 @@
+use failure::{self, Fail};
+use std::fmt;
+
+use data::subgraph::SubgraphProviderError;
+
+/// Broad classes of errors that can occur anywhere in the system.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorCategory {
+    /// Invalid configuration, e.g. bad command line arguments or addresses
+    /// that cannot be bound to.
+    Config,
+    /// Errors talking to or reported by the store.
+    Store,
+    /// Errors talking to or reported by an Ethereum node.
+    Chain,
+    /// Errors processing a query.
+    Query,
+    /// Anything else.
+    Internal,
+}
+
+impl ErrorCategory {
+    /// Process exit code to use when an error of this category is fatal.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ErrorCategory::Internal => 1,
+            ErrorCategory::Config => 2,
+            ErrorCategory::Store => 3,
+            ErrorCategory::Chain => 4,
+            ErrorCategory::Query => 5,
+        }
+    }
+
+    /// Error code reported to API clients.
+    pub fn api_code(&self) -> &'static str {
+        match self {
+            ErrorCategory::Internal => "INTERNAL_ERROR",
+            ErrorCategory::Config => "CONFIG_ERROR",
+            ErrorCategory::Store => "STORE_ERROR",
+            ErrorCategory::Chain => "CHAIN_ERROR",
+            ErrorCategory::Query => "QUERY_ERROR",
+        }
+    }
+
+    /// The category with the given API error code, if any.
+    pub fn from_api_code(code: &str) -> Option<Self> {
+        match code {
+            "INTERNAL_ERROR" => Some(ErrorCategory::Internal),
+            "CONFIG_ERROR" => Some(ErrorCategory::Config),
+            "STORE_ERROR" => Some(ErrorCategory::Store),
+            "CHAIN_ERROR" => Some(ErrorCategory::Chain),
+            "QUERY_ERROR" => Some(ErrorCategory::Query),
+            _ => None,
+        }
+    }
+}
+
+/// An error together with the category it belongs to.
+#[derive(Debug)]
+pub struct GraphError {
+    category: ErrorCategory,
+    error: failure::Error,
+}
+
+impl GraphError {
+    pub fn new<E: Into<failure::Error>>(category: ErrorCategory, error: E) -> Self {
+        GraphError {
+            category,
+            error: error.into(),
+        }
+    }
+
+    /// Creates an error that consists of nothing but a message.
+    pub fn from_message<S: Into<String>>(category: ErrorCategory, message: S) -> Self {
+        Self::new(category, failure::err_msg(message.into()))
+    }
+
+    pub fn config<E: Into<failure::Error>>(error: E) -> Self {
+        Self::new(ErrorCategory::Config, error)
+    }
+
+    pub fn store<E: Into<failure::Error>>(error: E) -> Self {
+        Self::new(ErrorCategory::Store, error)
+    }
+
+    pub fn chain<E: Into<failure::Error>>(error: E) -> Self {
+        Self::new(ErrorCategory::Chain, error)
+    }
+
+    pub fn query<E: Into<failure::Error>>(error: E) -> Self {
+        Self::new(ErrorCategory::Query, error)
+    }
+
+    pub fn internal<E: Into<failure::Error>>(error: E) -> Self {
+        Self::new(ErrorCategory::Internal, error)
+    }
+
+    pub fn category(&self) -> ErrorCategory {
+        self.category
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        self.category.exit_code()
+    }
+
+    pub fn api_code(&self) -> &'static str {
+        self.category.api_code()
+    }
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl Fail for GraphError {
+    fn cause(&self) -> Option<&Fail> {
+        Some(self.error.cause())
+    }
+}
+
+impl From<SubgraphProviderError> for GraphError {
+    fn from(e: SubgraphProviderError) -> Self {
+        match e {
+            SubgraphProviderError::ResolveError(_) => GraphError::config(e),
+            SubgraphProviderError::SendError => GraphError::internal(e),
+            SubgraphProviderError::MappingsChanged(_) => GraphError::config(e),
+            SubgraphProviderError::MappingPinError(error) => error,
+            SubgraphProviderError::PlacementRefused(_) => GraphError::config(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use failure::err_msg;
+
+    use super::*;
+
+    #[test]
+    fn exit_codes_follow_the_category() {
+        assert_eq!(GraphError::config(err_msg("bad port")).exit_code(), 2);
+        assert_eq!(GraphError::store(err_msg("no connection")).exit_code(), 3);
+        assert_eq!(GraphError::chain(err_msg("no node")).exit_code(), 4);
+        assert_eq!(GraphError::internal(err_msg("oops")).exit_code(), 1);
+    }
+
+    #[test]
+    fn display_uses_the_inner_error() {
+        let e = GraphError::query(err_msg("unknown field"));
+        assert_eq!(e.to_string(), "unknown field");
+        assert_eq!(e.api_code(), "QUERY_ERROR");
+        assert_eq!(
+            ErrorCategory::from_api_code(e.api_code()),
+            Some(ErrorCategory::Query)
+        );
+    }
+}
@@ -4,10 +4,14 @@ use tiny_keccak::Keccak;
 
 /// Hashes a string to a H256 hash.
 pub fn string_to_h256(s: &str) -> H256 {
+    keccak256(&s.replace(" ", "").into_bytes())
+}
+
+/// Computes the Keccak-256 hash of `data`.
+pub fn keccak256(data: &[u8]) -> H256 {
     let mut result = [0u8; 32];
-    let data = s.replace(" ", "").into_bytes();
     let mut sponge = Keccak::new_keccak256();
-    sponge.update(&data);
+    sponge.update(data);
     sponge.finalize(&mut result);
 
     H256::from_slice(&result)
@@ -1,8 +1,14 @@
 /// Utilities for working with streams.
 pub mod stream;
 
+/// Error type shared by all components.
+pub mod error;
+
 /// Logging facilities based on `slog`.
 pub mod log;
 
 /// Utils for working with ethereum data types
 pub mod ethereum;
+
+/// Limiting concurrency with futures.
+pub mod semaphore;
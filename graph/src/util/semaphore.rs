@@ -0,0 +1,122 @@
+use futures::task::{self, Task};
+use futures::{Async, Future, Poll};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug)]
+struct State {
+    available: usize,
+    waiting: Vec<Task>,
+}
+
+/// A counting semaphore that limits how many futures may hold a permit at
+/// the same time. Futures acquiring a permit while none are available are
+/// parked until another permit is released.
+#[derive(Clone, Debug)]
+pub struct Semaphore {
+    state: Arc<Mutex<State>>,
+}
+
+impl Semaphore {
+    /// Creates a semaphore with `permits` permits.
+    pub fn new(permits: usize) -> Self {
+        Semaphore {
+            state: Arc::new(Mutex::new(State {
+                available: permits,
+                waiting: vec![],
+            })),
+        }
+    }
+
+    /// Returns a future that resolves to a permit once one is available.
+    pub fn acquire(&self) -> Acquire {
+        Acquire {
+            state: self.state.clone(),
+        }
+    }
+
+    /// Number of permits that are currently not held by anyone.
+    pub fn available_permits(&self) -> usize {
+        self.state.lock().unwrap().available
+    }
+}
+
+/// Future returned by [Semaphore::acquire](struct.Semaphore.html#method.acquire).
+pub struct Acquire {
+    state: Arc<Mutex<State>>,
+}
+
+impl Future for Acquire {
+    type Item = Permit;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut state = self.state.lock().unwrap();
+        if state.available > 0 {
+            state.available -= 1;
+            Ok(Async::Ready(Permit {
+                state: self.state.clone(),
+            }))
+        } else {
+            state.waiting.push(task::current());
+            Ok(Async::NotReady)
+        }
+    }
+}
+
+/// A permit acquired from a [Semaphore](struct.Semaphore.html). The permit
+/// is released when it is dropped.
+#[derive(Debug)]
+pub struct Permit {
+    state: Arc<Mutex<State>>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut state = self.state.lock().unwrap();
+        state.available += 1;
+
+        // Wake up everyone who is waiting; those that lose the race for the
+        // released permit will simply register themselves again
+        for task in state.waiting.drain(..) {
+            task.notify();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future;
+
+    use super::*;
+
+    #[test]
+    fn permits_are_released_on_drop() {
+        let semaphore = Semaphore::new(2);
+
+        let first = semaphore.acquire().wait().unwrap();
+        let second = semaphore.acquire().wait().unwrap();
+        assert_eq!(semaphore.available_permits(), 0);
+
+        drop(first);
+        assert_eq!(semaphore.available_permits(), 1);
+        drop(second);
+        assert_eq!(semaphore.available_permits(), 2);
+    }
+
+    #[test]
+    fn acquire_waits_for_a_released_permit() {
+        let semaphore = Semaphore::new(1);
+        let permit = semaphore.acquire().wait().unwrap();
+
+        let pending = future::lazy(|| {
+            let mut pending = semaphore.acquire();
+            assert!(pending.poll().unwrap().is_not_ready());
+
+            drop(permit);
+            pending
+        });
+
+        let _permit = pending.wait().unwrap();
+        assert_eq!(semaphore.available_permits(), 0);
+    }
+}
@@ -136,11 +136,19 @@ fn enum_value(enum_value: &s::EnumValue) -> q::Value {
                 .as_ref()
                 .map_or(q::Value::Null, |s| q::Value::String(s.to_owned())),
         ),
-        ("isDeprecated", q::Value::Boolean(false)),
-        ("deprecationReason", q::Value::Null),
+        ("isDeprecated", is_deprecated(&enum_value.directives)),
+        ("deprecationReason", deprecation_reason(&enum_value.directives)),
     ])
 }
 
+fn is_deprecated(directives: &[s::Directive]) -> q::Value {
+    q::Value::Boolean(sast::get_deprecation_reason(directives).is_some())
+}
+
+fn deprecation_reason(directives: &[s::Directive]) -> q::Value {
+    sast::get_deprecation_reason(directives).map_or(q::Value::Null, q::Value::String)
+}
+
 fn input_object_type_object(
     schema: &Schema,
     type_objects: &mut TypeObjectsMap,
@@ -260,8 +268,8 @@ fn field_object(schema: &Schema, type_objects: &mut TypeObjectsMap, field: &s::F
         ),
         ("args", input_values(schema, type_objects, &field.arguments)),
         ("type", type_object(schema, type_objects, &field.field_type)),
-        ("isDeprecated", q::Value::Boolean(false)),
-        ("deprecationReason", q::Value::Null),
+        ("isDeprecated", is_deprecated(&field.directives)),
+        ("deprecationReason", deprecation_reason(&field.directives)),
     ])
 }
 
@@ -290,8 +298,8 @@ fn scalar_type_object(scalar_type: &s::ScalarType) -> q::Value {
                 .as_ref()
                 .map_or(q::Value::Null, |s| q::Value::String(s.to_owned())),
         ),
-        ("isDeprecated", q::Value::Boolean(false)),
-        ("deprecationReason", q::Value::Null),
+        ("isDeprecated", is_deprecated(&scalar_type.directives)),
+        ("deprecationReason", deprecation_reason(&scalar_type.directives)),
     ])
 }
 
@@ -409,7 +417,10 @@ fn input_value(
             input_value
                 .default_value
                 .as_ref()
-                .map_or(q::Value::Null, |value| value.clone()),
+                .map_or(q::Value::Null, |value| match value {
+                    q::Value::String(s) => q::Value::String(s.to_owned()),
+                    value => q::Value::String(value.to_string()),
+                }),
         ),
     ])
 }
@@ -440,16 +451,21 @@ impl<'a> IntrospectionResolver<'a> {
         }
     }
 
+    /// Returns the type object of a root operation type, or `null` if the
+    /// schema has no such type.
+    fn root_type_object(&self, root_type: Option<&s::ObjectType>) -> q::Value {
+        root_type
+            .and_then(|t| self.type_objects.get(&t.name))
+            .map(|t| t.clone())
+            .unwrap_or(q::Value::Null)
+    }
+
     fn schema_object(&self) -> q::Value {
+        let document = &self.schema.document;
         object_value(vec![
-            (
-                "queryType",
-                self.type_objects
-                    .get(&String::from("Query"))
-                    .map(|t| t.clone())
-                    .unwrap_or(q::Value::Null),
-            ),
-            ("mutationType", q::Value::Null),
+            ("queryType", self.root_type_object(sast::get_root_query_type(document))),
+            ("mutationType", self.root_type_object(sast::get_root_mutation_type(document))),
+            ("subscriptionType", self.root_type_object(sast::get_root_subscription_type(document))),
             (
                 "types",
                 q::Value::List(
@@ -479,13 +495,39 @@ impl<'a> IntrospectionResolver<'a> {
 impl<'a> Resolver for IntrospectionResolver<'a> {
     fn resolve_objects(
         &self,
+        _schema: &s::Document,
         parent: &Option<q::Value>,
         field: &q::Name,
         _field_definition: &s::Field,
         _object_type: &s::ObjectType,
-        _arguments: &HashMap<&q::Name, q::Value>,
-    ) -> q::Value {
-        match field.as_str() {
+        arguments: &HashMap<&q::Name, q::Value>,
+    ) -> Result<q::Value, QueryExecutionError> {
+        Ok(match field.as_str() {
+            "fields" | "enumValues" => {
+                let include_deprecated = match arguments.get(&String::from("includeDeprecated")) {
+                    Some(q::Value::Boolean(include_deprecated)) => *include_deprecated,
+                    _ => false,
+                };
+
+                match object_field(parent, field.as_str()) {
+                    Some(q::Value::List(values)) => q::Value::List(
+                        values
+                            .iter()
+                            .filter(|value| {
+                                include_deprecated || match value {
+                                    q::Value::Object(data) => {
+                                        data.get("isDeprecated") != Some(&q::Value::Boolean(true))
+                                    }
+                                    _ => true,
+                                }
+                            })
+                            .cloned()
+                            .collect(),
+                    ),
+                    Some(value) => value.clone(),
+                    None => q::Value::Null,
+                }
+            }
             "possibleTypes" => {
                 let type_names = object_field(parent, "possibleTypes")
                     .and_then(|value| match value {
@@ -512,18 +554,19 @@ impl<'a> Resolver for IntrospectionResolver<'a> {
             _ => object_field(parent, field.as_str())
                 .map(|value| value.clone())
                 .unwrap_or(q::Value::Null),
-        }
+        })
     }
 
     fn resolve_object(
         &self,
+        _schema: &s::Document,
         parent: &Option<q::Value>,
         field: &q::Name,
         _field_definition: &s::Field,
         _object_type: &s::ObjectType,
         arguments: &HashMap<&q::Name, q::Value>,
-    ) -> q::Value {
-        match field.as_str() {
+    ) -> Result<q::Value, QueryExecutionError> {
+        Ok(match field.as_str() {
             "__schema" => self.schema_object(),
             "__type" => self.type_object(arguments),
             "type" => object_field(parent, "type")
@@ -541,6 +584,6 @@ impl<'a> Resolver for IntrospectionResolver<'a> {
             _ => object_field(parent, field.as_str())
                 .map(|value| value.clone())
                 .unwrap_or(q::Value::Null),
-        }
+        })
     }
 }
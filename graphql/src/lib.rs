@@ -1,3 +1,4 @@
+extern crate futures;
 extern crate graph;
 extern crate graphql_parser;
 extern crate indexmap;
@@ -19,11 +20,25 @@ mod values;
 /// Utilities for querying `Store` components.
 mod store;
 
+/// Live GraphQL subscriptions that are kept up to date with the store.
+mod subscription;
+
+/// The built-in schema for querying the state of the node.
+pub mod system;
+
 /// Prelude that exports the most important traits and types.
 pub mod prelude {
     pub use super::introspection::{introspection_schema, IntrospectionResolver};
-    pub use super::query::{execute, ExecutionOptions, Resolver};
-    pub use super::schema::{api_schema, APISchemaError};
+    pub use super::query::{
+        block_constraint, execute, normalize_query, query_complexity, query_hash, ExecutionOptions,
+        FieldInfo, FieldMiddleware, PrefetchField, Resolver,
+    };
+    pub use super::schema::{
+        api_schema, stitch_schemas, stitched_subgraph_ids, validate_schema, APISchemaError,
+        SchemaStitchError,
+    };
     pub use super::store::{build_query, StoreResolver};
+    pub use super::subscription::SubscriptionManager;
+    pub use super::system::{system_schema, SystemResolver, SYSTEM_SCHEMA_ID};
     pub use super::values::{object_value, MaybeCoercible, SerializableValue};
 }
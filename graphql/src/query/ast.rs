@@ -1,4 +1,5 @@
 use graphql_parser::query::*;
+use std::collections::HashMap;
 
 use graph::prelude::QueryExecutionError;
 
@@ -12,14 +13,13 @@ pub fn get_operation<'a>(
     match (name, operations.len()) {
         (None, 1) => Ok(operations[0]),
         (None, _) => Err(QueryExecutionError::OperationNameRequired),
-        (Some(s), n) if n > 0 => operations
+        (Some(s), _) => operations
             .into_iter()
             .find(|op| match get_operation_name(op) {
                 Some(n) => s == n,
                 None => false,
             })
             .ok_or(QueryExecutionError::OperationNotFound(s.to_string())),
-        _ => Err(QueryExecutionError::OperationNameRequired),
     }
 }
 
@@ -47,48 +47,59 @@ pub fn get_operation_name(operation: &OperationDefinition) -> Option<&Name> {
     }
 }
 
-/// Looks up a directive in a selection, if it is provided.
-pub fn get_directive(selection: &Selection, name: Name) -> Option<&Directive> {
-    match selection {
-        Selection::Field(field) => field
-            .directives
-            .iter()
-            .find(|directive| directive.name == name),
-        _ => None,
+/// Returns the variable definitions of the given operation.
+pub fn get_variable_definitions(operation: &OperationDefinition) -> &[VariableDefinition] {
+    match operation {
+        OperationDefinition::Mutation(m) => &m.variable_definitions,
+        OperationDefinition::Query(q) => &q.variable_definitions,
+        OperationDefinition::SelectionSet(_) => &[],
+        OperationDefinition::Subscription(s) => &s.variable_definitions,
     }
 }
 
+/// Returns the directive with the given name on a selection, if there is one.
+pub fn get_directive<'a>(selection: &'a Selection, name: &str) -> Option<&'a Directive> {
+    let directives = match selection {
+        Selection::Field(field) => &field.directives,
+        Selection::FragmentSpread(spread) => &spread.directives,
+        Selection::InlineFragment(fragment) => &fragment.directives,
+    };
+    directives.iter().find(|directive| directive.name == name)
+}
+
 /// Looks up the value of an argument in a vector of (name, value) tuples.
 pub fn get_argument_value<'a>(arguments: &'a Vec<(Name, Value)>, name: &Name) -> Option<&'a Value> {
     arguments.iter().find(|(n, _)| n == name).map(|(_, v)| v)
 }
 
-/// Returns true if a selection should be skipped (as per the `@skip` directive).
-pub fn skip_selection(selection: &Selection) -> bool {
-    match get_directive(selection, "skip".to_string()) {
-        Some(directive) => match get_argument_value(&directive.arguments, &"if".to_string()) {
-            Some(val) => match val {
-                Value::Boolean(skip_if) => *skip_if,
-                _ => false,
-            },
-            None => true,
+/// Evaluates the `if` argument of a `@skip` or `@include` directive, which
+/// may be a literal or a variable.
+fn get_directive_condition(
+    directive: &Directive,
+    variables: &HashMap<Name, Value>,
+) -> Option<bool> {
+    match get_argument_value(&directive.arguments, &"if".to_string()) {
+        Some(Value::Boolean(condition)) => Some(*condition),
+        Some(Value::Variable(name)) => match variables.get(name) {
+            Some(Value::Boolean(condition)) => Some(*condition),
+            _ => None,
         },
-        None => false,
+        _ => None,
     }
 }
 
+/// Returns true if a selection should be skipped (as per the `@skip` directive).
+pub fn skip_selection(selection: &Selection, variables: &HashMap<Name, Value>) -> bool {
+    get_directive(selection, "skip")
+        .and_then(|directive| get_directive_condition(directive, variables))
+        .unwrap_or(false)
+}
+
 /// Returns true if a selection should be included (as per the `@include` directive).
-pub fn include_selection(selection: &Selection) -> bool {
-    match get_directive(selection, "include".to_string()) {
-        Some(directive) => match get_argument_value(&directive.arguments, &"include".to_string()) {
-            Some(val) => match val {
-                Value::Boolean(include) => *include,
-                _ => false,
-            },
-            None => true,
-        },
-        None => true,
-    }
+pub fn include_selection(selection: &Selection, variables: &HashMap<Name, Value>) -> bool {
+    get_directive(selection, "include")
+        .and_then(|directive| get_directive_condition(directive, variables))
+        .unwrap_or(true)
 }
 
 /// Returns the response key of a field, which is either its name or its alias (if there is one).
@@ -0,0 +1,259 @@
+use graphql_parser::{query as q, Pos};
+use std::collections::HashSet;
+
+use graph::prelude::{BlockConstraint, Query, QueryExecutionError};
+
+use query::ast as qast;
+
+/// Determines the block that a query is pinned to through the `block`
+/// arguments of its top-level fields, e.g. `{ users(block: { number: 10 }) { id } }`
+/// or `{ users(block: { hash: $hash }) { id } }`, with variables substituted.
+///
+/// The whole query is run at one block, so its top-level fields must either
+/// all be pinned to the same block or all be at the latest block. `_meta`
+/// always reports the latest block and can only be combined with fields at
+/// the latest block; introspection fields are the same at every block.
+pub fn block_constraint(query: &Query) -> Result<Option<BlockConstraint>, QueryExecutionError> {
+    let operation_name = query.operation_name.as_ref().map(String::as_str);
+    let operation = qast::get_operation(&query.document, operation_name)?;
+    let selection_set = match operation {
+        q::OperationDefinition::Query(operation) => &operation.selection_set,
+        q::OperationDefinition::SelectionSet(selection_set) => selection_set,
+        q::OperationDefinition::Subscription(subscription) => &subscription.selection_set,
+        q::OperationDefinition::Mutation(mutation) => &mutation.selection_set,
+    };
+
+    let mut fields = vec![];
+    collect_fields(query, selection_set, &mut HashSet::new(), &mut fields);
+
+    let mut pinned: Option<(&q::Field, Option<BlockConstraint>)> = None;
+    for field in fields.into_iter().filter(|field| !field.name.starts_with("__")) {
+        let block = field_block(query, operation, field)?;
+        match pinned {
+            Some((first, ref first_block)) if *first_block != block => {
+                return Err(QueryExecutionError::BlockConflictError(
+                    field.position,
+                    qast::get_response_key(first).to_owned(),
+                    qast::get_response_key(field).to_owned(),
+                ))
+            }
+            Some(_) => continue,
+            None => (),
+        }
+        pinned = Some((field, block));
+    }
+    Ok(pinned.and_then(|(_, block)| block))
+}
+
+/// Collects the fields of a selection set, including those of the fragments
+/// it spreads.
+fn collect_fields<'a>(
+    query: &'a Query,
+    selection_set: &'a q::SelectionSet,
+    visited_fragments: &mut HashSet<&'a q::Name>,
+    fields: &mut Vec<&'a q::Field>,
+) {
+    for selection in selection_set.items.iter() {
+        match selection {
+            q::Selection::Field(field) => fields.push(field),
+            q::Selection::FragmentSpread(spread) => {
+                if !visited_fragments.insert(&spread.fragment_name) {
+                    continue;
+                }
+                if let Some(fragment) = qast::get_fragment(&query.document, &spread.fragment_name)
+                {
+                    collect_fields(query, &fragment.selection_set, visited_fragments, fields);
+                }
+            }
+            q::Selection::InlineFragment(fragment) => {
+                collect_fields(query, &fragment.selection_set, visited_fragments, fields)
+            }
+        }
+    }
+}
+
+/// Returns the block that the `block` argument of a field pins it to, or
+/// `None` if the field is at the latest block.
+fn field_block(
+    query: &Query,
+    operation: &q::OperationDefinition,
+    field: &q::Field,
+) -> Result<Option<BlockConstraint>, QueryExecutionError> {
+    let value = match qast::get_argument_value(&field.arguments, &String::from("block")) {
+        Some(value) => substitute_variables(query, operation, field.position, value)?,
+        None => return Ok(None),
+    };
+
+    let invalid = || {
+        QueryExecutionError::InvalidArgumentError(
+            field.position,
+            String::from("block"),
+            value.clone(),
+        )
+    };
+    match value {
+        q::Value::Null => Ok(None),
+        q::Value::Object(ref block) => match (block.get("number"), block.get("hash")) {
+            (Some(q::Value::Int(number)), None) => number
+                .as_i64()
+                .filter(|number| *number >= 0)
+                .map(|number| Some(BlockConstraint::Number(number as u64)))
+                .ok_or_else(invalid),
+            (None, Some(q::Value::String(hash))) => BlockConstraint::hash_from_hex(hash)
+                .map(Some)
+                .ok_or_else(invalid),
+            _ => Err(invalid()),
+        },
+        _ => Err(invalid()),
+    }
+}
+
+/// Replaces the variables in an argument value with their values, or the
+/// defaults of the operation if no value was given.
+fn substitute_variables(
+    query: &Query,
+    operation: &q::OperationDefinition,
+    position: Pos,
+    value: &q::Value,
+) -> Result<q::Value, QueryExecutionError> {
+    match value {
+        q::Value::Variable(name) => {
+            let definition = qast::get_variable_definitions(operation)
+                .iter()
+                .find(|definition| &definition.name == name)
+                .ok_or_else(|| {
+                    QueryExecutionError::UndefinedVariableError(position, name.clone())
+                })?;
+            Ok(query
+                .variables
+                .as_ref()
+                .and_then(|variables| variables.get(name))
+                .map(|value| (**value).clone())
+                .or_else(|| definition.default_value.clone())
+                .unwrap_or(q::Value::Null))
+        }
+        q::Value::Object(object) => object
+            .iter()
+            .map(|(key, value)| {
+                substitute_variables(query, operation, position, value)
+                    .map(|value| (key.clone(), value))
+            })
+            .collect::<Result<_, _>>()
+            .map(q::Value::Object),
+        q::Value::List(values) => values
+            .iter()
+            .map(|value| substitute_variables(query, operation, position, value))
+            .collect::<Result<_, _>>()
+            .map(q::Value::List),
+        value => Ok(value.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use graph::prelude::*;
+    use graph::serde_json;
+    use graphql_parser;
+
+    use super::block_constraint;
+
+    const HASH: &str = "0x1d5a0ec27ba64ce1bfd2d43b5f3e53e3ae9c9ffe3e0e62a8c1f6b8e06a8e7c44";
+
+    fn query(document: &str, variables: Option<&str>) -> Query {
+        Query {
+            schema: Schema {
+                id: String::from("test"),
+                document: graphql_parser::parse_schema("type Query { users: [User!]! }").unwrap(),
+            },
+            document: graphql_parser::parse_query(document).unwrap(),
+            variables: variables.map(|variables| serde_json::from_str(variables).unwrap()),
+            operation_name: None,
+            role: None,
+            explain: false,
+            trace: false,
+        }
+    }
+
+    fn block(document: &str, variables: Option<&str>) -> Option<BlockConstraint> {
+        block_constraint(&query(document, variables)).expect("Should determine the block")
+    }
+
+    #[test]
+    fn finds_literal_blocks() {
+        assert_eq!(
+            block("{ users(first: 5, block: { number: 10 }) { name } }", None),
+            Some(BlockConstraint::Number(10))
+        );
+        assert_eq!(
+            block(&format!("{{ users(block: {{ hash: \"{}\" }}) {{ name }} }}", HASH), None),
+            BlockConstraint::hash_from_hex(HASH)
+        );
+        assert_eq!(block("{ users { name } }", None), None);
+        assert_eq!(block("{ users(block: null) { name } }", None), None);
+    }
+
+    #[test]
+    fn substitutes_variables() {
+        assert_eq!(
+            block(
+                "query($block: Block_height) { users(block: $block) { name } }",
+                Some("{\"block\": {\"number\": 7}}"),
+            ),
+            Some(BlockConstraint::Number(7))
+        );
+        assert_eq!(
+            block(
+                "query($n: Int = 3) { users(block: { number: $n }) { name } }",
+                None
+            ),
+            Some(BlockConstraint::Number(3))
+        );
+        assert_eq!(
+            block(
+                "query($block: Block_height) { users(block: $block) { name } }",
+                None
+            ),
+            None
+        );
+        block_constraint(&query("{ users(block: $block) { name } }", None))
+            .expect_err("Should reject undefined variables");
+    }
+
+    #[test]
+    fn requires_all_top_level_fields_at_the_same_block() {
+        assert_eq!(
+            block(
+                "{ a: users(block: { number: 5 }) { id } ...F __typename } \
+                 fragment F on Query { b: users(block: { number: 5 }) { id } }",
+                None,
+            ),
+            Some(BlockConstraint::Number(5))
+        );
+        for document in &[
+            "{ a: users(block: { number: 5 }) { id } b: users(block: { number: 6 }) { id } }",
+            "{ a: users(block: { number: 5 }) { id } b: users { id } }",
+            "{ users(block: { number: 5 }) { id } _meta { block { number } } }",
+            "{ users(block: { number: 5 }) { id } ... on Query { users { id } } }",
+        ] {
+            match block_constraint(&query(document, None)) {
+                Err(QueryExecutionError::BlockConflictError(..)) => (),
+                result => panic!("Unexpected result for {}: {:?}", document, result),
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_blocks() {
+        for document in &[
+            "{ users(block: { number: -1 }) { id } }",
+            "{ users(block: { hash: \"latest\" }) { id } }",
+            "{ users(block: { number: 1, hash: \"0x00\" }) { id } }",
+            "{ users(block: 5) { id } }",
+        ] {
+            match block_constraint(&query(document, None)) {
+                Err(QueryExecutionError::InvalidArgumentError(..)) => (),
+                result => panic!("Unexpected result for {}: {:?}", document, result),
+            }
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use graphql_parser::query as q;
+use graphql_parser::schema as s;
+use std::collections::HashMap;
+
+use graph::prelude::{Query, QueryExecutionError};
+
+use query::ast as qast;
+use schema::api::named_type_name;
+use schema::ast as sast;
+
+/// Number of entities that list fields without a `first` argument are
+/// assumed to return, which is what the store returns for them.
+const DEFAULT_FIRST: u64 = 100;
+
+/// Estimates how expensive a query is to run. Every field costs 1, and the
+/// cost of the fields selected below a list field counts once for every
+/// entity the list may hold, i.e. `first` or 100 times. Fields with a
+/// `first` argument that are not lists, such as connections, count the
+/// fields below them `first` times instead of the lists they contain.
+///
+/// Fragments that spread themselves, directly or through other fragments,
+/// are rejected, since they would have to be counted forever.
+pub fn query_complexity(query: &Query) -> Result<u64, QueryExecutionError> {
+    let schema = &query.schema.document;
+    let operation_name = query.operation_name.as_ref().map(String::as_str);
+
+    let (root_type, selection_set) = match qast::get_operation(&query.document, operation_name)? {
+        q::OperationDefinition::Query(operation) => {
+            (sast::get_root_query_type(schema), &operation.selection_set)
+        }
+        q::OperationDefinition::SelectionSet(selection_set) => {
+            (sast::get_root_query_type(schema), selection_set)
+        }
+        q::OperationDefinition::Subscription(subscription) => (
+            sast::get_root_subscription_type(schema),
+            &subscription.selection_set,
+        ),
+        q::OperationDefinition::Mutation(_) => return Ok(0),
+    };
+
+    match root_type {
+        Some(root_type) => selection_set_complexity(
+            query,
+            &root_type.fields,
+            selection_set,
+            false,
+            &mut Fragments::default(),
+        ),
+        None => Ok(0),
+    }
+}
+
+/// The fragments seen while estimating the cost of a query.
+#[derive(Default)]
+struct Fragments<'a> {
+    /// The fragments whose selection sets are being counted, to detect
+    /// fragments that spread themselves.
+    path: Vec<&'a q::Name>,
+    /// The cost of each fragment, by whether it was spread in a paged
+    /// selection set, so that fragments spread many times are only
+    /// counted once.
+    costs: HashMap<(&'a q::Name, bool), u64>,
+}
+
+/// Returns the cost of a selection set on a type with the given fields. If
+/// `paged` is set, the selection set is already counted once per entity of
+/// a page, so lists in it without a `first` argument are only counted once.
+fn selection_set_complexity<'a>(
+    query: &'a Query,
+    fields: &'a [s::Field],
+    selection_set: &'a q::SelectionSet,
+    paged: bool,
+    fragments: &mut Fragments<'a>,
+) -> Result<u64, QueryExecutionError> {
+    let mut complexity: u64 = 0;
+    for selection in selection_set.items.iter() {
+        let cost = match selection {
+            q::Selection::Field(field) => {
+                field_complexity(query, fields, field, paged, fragments)?
+            }
+            q::Selection::FragmentSpread(spread) => {
+                fragment_complexity(query, spread, paged, fragments)?
+            }
+            q::Selection::InlineFragment(fragment) => {
+                let fragment_fields = match fragment.type_condition {
+                    Some(q::TypeCondition::On(ref name)) => type_fields(query, name),
+                    None => fields,
+                };
+                selection_set_complexity(
+                    query,
+                    fragment_fields,
+                    &fragment.selection_set,
+                    paged,
+                    fragments,
+                )?
+            }
+        };
+        complexity = complexity.saturating_add(cost);
+    }
+    Ok(complexity)
+}
+
+fn fragment_complexity<'a>(
+    query: &'a Query,
+    spread: &'a q::FragmentSpread,
+    paged: bool,
+    fragments: &mut Fragments<'a>,
+) -> Result<u64, QueryExecutionError> {
+    let name = &spread.fragment_name;
+    if fragments.path.contains(&name) {
+        return Err(QueryExecutionError::FragmentCycleError(
+            spread.position,
+            name.clone(),
+        ));
+    }
+    if let Some(cost) = fragments.costs.get(&(name, paged)) {
+        return Ok(*cost);
+    }
+
+    let fragment = match qast::get_fragment(&query.document, name) {
+        Some(fragment) => fragment,
+        None => return Ok(0),
+    };
+    let q::TypeCondition::On(ref type_name) = fragment.type_condition;
+
+    fragments.path.push(name);
+    let cost = selection_set_complexity(
+        query,
+        type_fields(query, type_name),
+        &fragment.selection_set,
+        paged,
+        fragments,
+    );
+    fragments.path.pop();
+
+    let cost = cost?;
+    fragments.costs.insert((name, paged), cost);
+    Ok(cost)
+}
+
+fn field_complexity<'a>(
+    query: &'a Query,
+    fields: &'a [s::Field],
+    field: &'a q::Field,
+    paged: bool,
+    fragments: &mut Fragments<'a>,
+) -> Result<u64, QueryExecutionError> {
+    // `__typename` is not resolved, and introspection is cheap
+    if field.name == "__typename" {
+        return Ok(0);
+    }
+    if field.name.starts_with("__") {
+        return Ok(1);
+    }
+
+    let definition = match fields.iter().find(|definition| definition.name == field.name) {
+        Some(definition) => definition,
+        None => return Ok(1),
+    };
+
+    let first = first_argument(query, field);
+    let is_list = is_list_type(&definition.field_type);
+    let (multiplier, paged) = match (first, is_list) {
+        (Some(first), true) => (first, false),
+        (Some(first), false) => (first, true),
+        (None, true) if !paged => (DEFAULT_FIRST, false),
+        (None, _) => (1, paged),
+    };
+
+    let child_fields = type_fields(query, named_type_name(&definition.field_type));
+    let children =
+        selection_set_complexity(query, child_fields, &field.selection_set, paged, fragments)?;
+    Ok(multiplier.saturating_mul(children).saturating_add(1))
+}
+
+/// Returns the value of the `first` argument of a field, which may be a
+/// literal or a variable.
+fn first_argument(query: &Query, field: &q::Field) -> Option<u64> {
+    let value = match qast::get_argument_value(&field.arguments, &String::from("first"))? {
+        q::Value::Variable(name) => query
+            .variables
+            .as_ref()
+            .and_then(|variables| variables.get(name))
+            .map(|value| (**value).clone())?,
+        value => value.clone(),
+    };
+
+    match value {
+        q::Value::Int(number) => number.as_i64().filter(|n| *n >= 0).map(|n| n as u64),
+        _ => None,
+    }
+}
+
+/// Returns the fields of an object or interface type, or none for all
+/// other types.
+fn type_fields<'a>(query: &'a Query, name: &s::Name) -> &'a [s::Field] {
+    match sast::get_named_type(&query.schema.document, name) {
+        Some(s::TypeDefinition::Object(t)) => &t.fields,
+        Some(s::TypeDefinition::Interface(t)) => &t.fields,
+        _ => &[],
+    }
+}
+
+fn is_list_type(field_type: &s::Type) -> bool {
+    match field_type {
+        s::Type::NonNullType(t) => is_list_type(t),
+        s::Type::ListType(_) => true,
+        s::Type::NamedType(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use graph::prelude::*;
+    use graph::serde_json;
+    use graphql_parser;
+
+    use super::query_complexity;
+
+    fn query(document: &str, variables: Option<&str>) -> Query {
+        Query {
+            schema: Schema {
+                id: String::from("test"),
+                document: graphql_parser::parse_schema(
+                    "
+                    type Query {
+                        users(first: Int): [User!]!
+                        usersConnection(first: Int): UserConnection!
+                    }
+                    type User { id: ID!, name: String!, friends(first: Int): [User!]! }
+                    type UserConnection { edges: [UserEdge!]! }
+                    type UserEdge { node: User! }
+                    ",
+                ).unwrap(),
+            },
+            document: graphql_parser::parse_query(document).unwrap(),
+            variables: variables.map(|variables| serde_json::from_str(variables).unwrap()),
+            operation_name: None,
+            role: None,
+            explain: false,
+            trace: false,
+        }
+    }
+
+    #[test]
+    fn weights_fields_by_first_and_nesting() {
+        let cost = |document| query_complexity(&query(document, None)).unwrap();
+
+        assert_eq!(cost("{ users(first: 10) { id name } }"), 21);
+        assert_eq!(cost("{ users { __typename id } }"), 101);
+        assert_eq!(
+            cost("{ users(first: 10) { id friends(first: 5) { name } } }"),
+            1 + 10 * (1 + 1 + 5 * 1)
+        );
+        assert_eq!(
+            cost("{ usersConnection(first: 10) { edges { node { id } } } }"),
+            1 + 10 * (1 + 1 * (1 + 1))
+        );
+    }
+
+    #[test]
+    fn resolves_first_from_variables_and_fragments() {
+        let document = "
+            query users($first: Int) { users(first: $first) { ...userFields } }
+            fragment userFields on User { id name }
+        ";
+        let query = query(document, Some("{ \"first\": 5 }"));
+        assert_eq!(query_complexity(&query).unwrap(), 11);
+    }
+
+    #[test]
+    fn rejects_fragments_that_spread_themselves() {
+        for document in &[
+            "{ users { ...F } } fragment F on User { ...F }",
+            "{ users { ...F } } fragment F on User { friends { ...G } } \
+             fragment G on User { id ...F }",
+        ] {
+            match query_complexity(&query(document, None)) {
+                Err(QueryExecutionError::FragmentCycleError(_, name)) => assert_eq!(name, "F"),
+                result => panic!("Unexpected result for {}: {:?}", document, result),
+            }
+        }
+
+        // Fragments spread in several places are not cycles
+        let document = "
+            { a: users(first: 2) { ...F } b: users(first: 3) { ...F friends(first: 1) { ...F } } }
+            fragment F on User { id name }
+        ";
+        assert_eq!(
+            query_complexity(&query(document, None)).unwrap(),
+            (1 + 2 * 2) + (1 + 3 * (2 + 1 + 1 * 2))
+        );
+    }
+}
@@ -1,13 +1,20 @@
 use graphql_parser::query as q;
 use graphql_parser::schema as s;
+use graphql_parser::Pos;
 use indexmap::IndexMap;
 use std::cmp;
 use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::Instant;
 
+use graph::data::schema::is_field_stored;
 use graph::prelude::*;
 
 use prelude::*;
 use query::ast as qast;
+use query::validation::validate_query;
+use schema::access;
+use schema::api::{named_type_name, META_TYPE_NAME};
 use schema::ast as sast;
 
 /// Contextual information passed around during query execution.
@@ -25,14 +32,28 @@ where
     pub introspection_schema: &'a s::Document,
     /// The query to execute.
     pub query: &'a Query,
+    /// The values of the operation's variables, coerced to their types.
+    pub variable_values: Arc<HashMap<q::Name, q::Value>>,
     /// The resolver to use.
     pub resolver: Arc<R1>,
     /// The introspection resolver to use.
     pub introspection_resolver: Arc<R2>,
     /// The current field stack (e.g. allUsers > friends > name).
     pub fields: Vec<&'a q::Field>,
+    /// The path of the current field in the response, e.g. allUsers > 0 > friends.
+    pub path: Vec<PathSegment>,
+    /// Errors of fields below the root selection set, which are added to the
+    /// result once the query has been executed.
+    pub errors: Arc<Mutex<Vec<QueryError>>>,
     /// Whether or not we're executing an introspection query
     pub introspecting: bool,
+    /// When the execution started and the fields resolved so far, if the
+    /// query is being traced.
+    pub trace: Option<(Instant, Arc<Mutex<Vec<ResolverTrace>>>)>,
+    /// The middleware to run around the resolution of every field.
+    pub middleware: Arc<Vec<Arc<FieldMiddleware>>>,
+    /// The largest number of entities a field may ask for with `first`.
+    pub max_first: Option<u32>,
 }
 
 impl<'a, R1, R2> ExecutionContext<'a, R1, R2>
@@ -44,6 +65,15 @@ where
     pub fn for_field(&mut self, field: &'a q::Field) -> Self {
         let mut ctx = self.clone();
         ctx.fields.push(field);
+        ctx.path.push(PathSegment::Key(qast::get_response_key(field).to_owned()));
+        ctx
+    }
+
+    /// Creates a derived context for an item of the list the current field
+    /// resolved to.
+    pub fn for_index(&self, index: usize) -> Self {
+        let mut ctx = self.clone();
+        ctx.path.push(PathSegment::Index(index));
         ctx
     }
 }
@@ -57,6 +87,11 @@ where
     pub logger: slog::Logger,
     /// The resolver to use.
     pub resolver: R,
+    /// The middleware to run around the resolution of every field.
+    pub middleware: Vec<Arc<FieldMiddleware>>,
+    /// The largest number of entities a field may ask for with `first`, if
+    /// there is a limit.
+    pub max_first: Option<u32>,
 }
 
 /// Executes a query and returns a result.
@@ -65,30 +100,66 @@ where
     R: Resolver,
 {
     info!(options.logger, "Execute");
+    let start = Instant::now();
 
-    // Obtain the only operation of the query (fail if there is none or more than one)
-    let operation = match qast::get_operation(&query.document, None) {
+    // Obtain the operation to run, which must be named if there are several
+    let operation_name = query.operation_name.as_ref().map(String::as_str);
+    let operation = match qast::get_operation(&query.document, operation_name) {
         Ok(op) => op,
         Err(e) => return QueryResult::from(e),
     };
 
+    // Hide all fields that the client is not allowed to access
+    let restricted_schema =
+        access::restricted_schema(&query.schema, query.role.as_ref().map(String::as_str));
+    let schema = restricted_schema.as_ref().unwrap_or(&query.schema);
+
+    // Report everything that is wrong with the query at once, rather than
+    // failing on the first field that can't be executed
+    if let Err(errors) = validate_query(&schema.document, &query.document, operation) {
+        let mut result = QueryResult::new(None);
+        for error in errors {
+            result.add_error(QueryError::from(error));
+        }
+        return result;
+    }
+
+    // Coerce the variables provided with the query to the types they are defined with
+    let variable_values = match coerce_variable_values(schema, operation, &query.variables) {
+        Ok(values) => values,
+        Err(e) => return QueryResult::from(e),
+    };
+
     // Create an introspection type store and resolver
     let introspection_schema = introspection_schema();
-    let introspection_resolver = IntrospectionResolver::new(&options.logger, &query.schema);
+    let introspection_resolver = IntrospectionResolver::new(&options.logger, schema);
 
     // Create a fresh execution context
+    let resolver = Arc::new(options.resolver);
+    let errors = Arc::new(Mutex::new(vec![]));
+    let trace = if query.trace {
+        Some((start, Arc::new(Mutex::new(vec![]))))
+    } else {
+        None
+    };
     let ctx = ExecutionContext {
         logger: options.logger,
-        resolver: Arc::new(options.resolver),
-        schema: &query.schema,
+        resolver: resolver.clone(),
+        schema,
         introspection_resolver: Arc::new(introspection_resolver),
         introspection_schema: &introspection_schema,
         introspecting: false,
         query,
+        variable_values: Arc::new(variable_values),
         fields: vec![],
+        path: vec![],
+        errors: errors.clone(),
+        trace: trace.clone(),
+        middleware: Arc::new(options.middleware),
+        max_first: options.max_first,
     };
 
-    match operation {
+    let mut result = match operation {
         // Execute top-level `query { ... }` expressions
         &q::OperationDefinition::Query(q::Query {
             ref selection_set, ..
@@ -99,11 +170,34 @@ where
             execute_root_selection_set(ctx, selection_set, &None)
         }
 
+        // Execute top-level `subscription { ... }` expressions, which
+        // yield the current results of the subscription
+        &q::OperationDefinition::Subscription(q::Subscription {
+            ref selection_set, ..
+        }) => execute_root_subscription_selection_set(ctx, selection_set),
+
         // Everything else (e.g. mutations) is unsupported
         _ => QueryResult::from(QueryExecutionError::NotSupported(
-            "Only queries are supported".to_string(),
+            "Only queries and subscriptions are supported".to_string(),
         )),
+    };
+
+    for error in errors.lock().unwrap().drain(..) {
+        result.add_error(error);
     }
+    if query.explain {
+        result.plan = resolver.query_plan();
+    }
+    if let Some((start, resolvers)) = trace {
+        let resolvers = resolvers.lock().unwrap().clone();
+        result.trace = Some(QueryTrace {
+            version: 1,
+            duration: QueryTrace::nanos(start.elapsed()),
+            execution: ExecutionTrace { resolvers },
+            store_queries: resolver.store_query_trace(start),
+        });
+    }
+    result
 }
 
 /// Executes the root selection set of a query.
@@ -123,58 +217,166 @@ where
     };
 
     // Execute the root selection set against the root query type
-    execute_selection_set(ctx, selection_set, query_type, initial_value)
-        .unwrap_or_else(QueryResult::from)
+    root_result(execute_selection_set(ctx, selection_set, query_type, initial_value))
+}
+
+/// Executes the root selection set of a subscription.
+fn execute_root_subscription_selection_set<'a, R1, R2>(
+    ctx: ExecutionContext<'a, R1, R2>,
+    selection_set: &'a q::SelectionSet,
+) -> QueryResult
+where
+    R1: Resolver,
+    R2: Resolver,
+{
+    // Obtain the root Subscription type and fail if there isn't one
+    let subscription_type = match sast::get_root_subscription_type(&ctx.schema.document) {
+        Some(t) => t,
+        None => return QueryResult::from(QueryExecutionError::NoRootSubscriptionObjectType),
+    };
+
+    root_result(execute_selection_set(ctx, selection_set, subscription_type, &None))
+}
+
+/// Turns the value of the root selection set into the result of the query.
+/// The errors of fields are added to the result later.
+fn root_result(data: Result<q::Value, QueryExecutionError>) -> QueryResult {
+    match data {
+        Ok(data) => QueryResult::new(Some(data)),
+
+        // A non-null root field failed, so there is no data at all
+        Err(QueryExecutionError::NullPropagated) => QueryResult::new(Some(q::Value::Null)),
+
+        Err(e) => QueryResult::from(e),
+    }
+}
+
+/// Reports the error of a field, or of an element of its list value, at
+/// `path`. Errors that only tell that a non-null value further down failed
+/// have been reported where that value failed.
+fn report_field_error(
+    errors: &Mutex<Vec<QueryError>>,
+    position: Pos,
+    path: Vec<PathSegment>,
+    e: QueryExecutionError,
+) {
+    match e {
+        QueryExecutionError::NullPropagated => {}
+        e => errors
+            .lock()
+            .unwrap()
+            .push(QueryError::FieldError(position, path, e)),
+    }
 }
 
 /// Executes a selection set, requiring the result to be of the given object type.
 ///
 /// Allows passing in a parent value during recursive processing of objects and their fields.
+///
+/// Fields that fail are null, and their errors are reported with their path.
+/// If a non-null field fails, the whole object is null, which the caller
+/// learns from a `NullPropagated` error.
 fn execute_selection_set<'a, R1, R2>(
     mut ctx: ExecutionContext<'a, R1, R2>,
     selection_set: &'a q::SelectionSet,
     object_type: &s::ObjectType,
     object_value: &Option<q::Value>,
-) -> Result<QueryResult, QueryExecutionError>
+) -> Result<q::Value, QueryExecutionError>
 where
     R1: Resolver,
     R2: Resolver,
 {
-    let mut result = QueryResult::new(None);
     let mut result_map: BTreeMap<String, q::Value> = BTreeMap::new();
+    let mut null_propagated = false;
 
     // Group fields with the same response key, so we can execute them together
     let grouped_field_set = collect_fields(ctx.clone(), object_type, selection_set, None);
 
     // Process all field groups in order
     for (response_key, fields) in grouped_field_set {
-        // If the field exists on the object, execute it and add its result to the result map
-        if let Some((ref field, introspecting)) =
-            get_field_type(ctx.clone(), object_type, &fields[0].name)
-        {
+        let mut path = ctx.path.clone();
+        path.push(PathSegment::Key(response_key.to_owned()));
+
+        // The `__typename` meta field is the name of the object type, which
+        // clients like Apollo use to tell the types of results apart
+        if fields[0].name == "__typename" {
+            result_map.insert(
+                response_key.to_owned(),
+                q::Value::String(object_type.name.clone()),
+            );
+            continue;
+        }
+
+        // Only fields that exist on the object are executed
+        let (field, introspecting) =
+            match get_field_type(ctx.clone(), object_type, &fields[0].name) {
+                Some(field) => field,
+                None => continue,
+            };
+        let position = fields[0].position;
+
+        let value = if !fields_can_merge(&fields) {
+            // Fields are only merged into one value if they are the same field
+            // with the same arguments; aliases tell all others apart
+            Err(QueryExecutionError::FieldConflictError(
+                fields[fields.len() - 1].position,
+                response_key.to_owned(),
+            ))
+        } else if !introspecting && !is_field_stored(field) {
+            // Fields that are not stored have no values to return
+            Err(QueryExecutionError::NotStoredFieldError(
+                position,
+                object_type.name.clone(),
+                field.name.clone(),
+            ))
+        } else {
             // Push the new field onto the context's field stack
             let mut ctx = ctx.for_field(&fields[0]);
 
             // Remember whether or not we're introspecting now
             ctx.introspecting = introspecting;
 
-            match execute_field(ctx, object_type, object_value, &fields[0], field, fields) {
-                Ok(v) => {
-                    result_map.insert(response_key.to_owned(), v);
-                }
-                Err(e) => {
-                    result.add_error(QueryError::from(e));
+            let trace = ctx.trace.clone();
+            let started = Instant::now();
+            let value = execute_field(ctx, object_type, object_value, &fields[0], field, fields);
+            if let Some((start, resolvers)) = trace {
+                resolvers.lock().unwrap().push(ResolverTrace {
+                    path: path.clone(),
+                    parent_type: object_type.name.clone(),
+                    field_name: field.name.clone(),
+                    return_type: type_string(&field.field_type),
+                    start_offset: QueryTrace::nanos(started.duration_since(start)),
+                    duration: QueryTrace::nanos(started.elapsed()),
+                });
+            }
+            value
+        };
+
+        match value {
+            Ok(v) => {
+                result_map.insert(response_key.to_owned(), v);
+            }
+
+            // Failed fields are null, unless they are non-null, in which
+            // case the object is null. The remaining fields are executed
+            // anyway, so that all of their errors are reported
+            Err(e) => {
+                report_field_error(&ctx.errors, position, path, e);
+                match field.field_type {
+                    s::Type::NonNullType(_) => null_propagated = true,
+                    _ => {
+                        result_map.insert(response_key.to_owned(), q::Value::Null);
+                    }
                 }
-            };
-        }
+            }
+        };
     }
 
-    // If we have result data, wrap it in an output object
-    if !result_map.is_empty() {
-        result.data = Some(q::Value::Object(result_map));
+    if null_propagated {
+        Err(QueryExecutionError::NullPropagated)
+    } else {
+        Ok(q::Value::Object(result_map))
     }
-
-    Ok(result)
 }
 
 /// Collects fields of a selection set.
@@ -195,8 +397,8 @@ where
     let selections: Vec<_> = selection_set
         .items
         .iter()
-        .filter(|selection| !qast::skip_selection(selection))
-        .filter(|selection| qast::include_selection(selection))
+        .filter(|selection| !qast::skip_selection(selection, &ctx.variable_values))
+        .filter(|selection| qast::include_selection(selection, &ctx.variable_values))
         .collect();
 
     for selection in selections {
@@ -250,27 +452,63 @@ where
                                 )
                             });
 
-                    if let Some(grouped_field_set) = fragment_grouped_field_set {
-                        // Add all items from each fragments group to the field group
-                        // with the corresponding response key
-                        for (response_key, mut fragment_group) in grouped_field_set {
-                            if !grouped_fields.contains_key(response_key) {
-                                grouped_fields.insert(response_key, vec![]);
-                            }
-                            let mut group = grouped_fields.get_mut(response_key).unwrap();
-                            group.append(&mut fragment_group);
-                        }
+                    if let Some(fragment_grouped_fields) = fragment_grouped_field_set {
+                        merge_field_groups(&mut grouped_fields, fragment_grouped_fields);
                     }
                 }
             }
 
-            q::Selection::InlineFragment(_) => unimplemented!(),
+            q::Selection::InlineFragment(fragment) => {
+                // Inline fragments without a type condition always apply
+                let applies = fragment.type_condition.as_ref().map_or(true, |condition| {
+                    does_fragment_type_apply(ctx.clone(), object_type, condition)
+                });
+
+                if applies {
+                    let fragment_grouped_fields = collect_fields(
+                        ctx.clone(),
+                        object_type,
+                        &fragment.selection_set,
+                        Some(visited_fragments.clone()),
+                    );
+                    merge_field_groups(&mut grouped_fields, fragment_grouped_fields);
+                }
+            }
         };
     }
 
     grouped_fields
 }
 
+/// Returns true if all fields with the same response key select the same
+/// field with the same arguments, in any order.
+fn fields_can_merge(fields: &[&q::Field]) -> bool {
+    let sorted_arguments = |field: &q::Field| {
+        let mut arguments = field.arguments.iter().collect::<Vec<_>>();
+        arguments.sort_by(|a, b| a.0.cmp(&b.0));
+        arguments
+    };
+
+    let first_arguments = sorted_arguments(fields[0]);
+    fields[1..]
+        .iter()
+        .all(|field| field.name == fields[0].name && sorted_arguments(field) == first_arguments)
+}
+
+/// Adds the fields of each group in `other` to the group with the same
+/// response key in `grouped_fields`.
+fn merge_field_groups<'a>(
+    grouped_fields: &mut IndexMap<&'a String, Vec<&'a q::Field>>,
+    other: IndexMap<&'a String, Vec<&'a q::Field>>,
+) {
+    for (response_key, mut other_group) in other {
+        grouped_fields
+            .entry(response_key)
+            .or_insert_with(Vec::new)
+            .append(&mut other_group);
+    }
+}
+
 /// Determines whether a fragment is applicable to the given object type.
 fn does_fragment_type_apply<'a, R1, R2>(
     ctx: ExecutionContext<'a, R1, R2>,
@@ -296,7 +534,7 @@ where
 
     match named_type {
         // The fragment applies to the object type if its type is the same object type
-        Some(s::TypeDefinition::Object(ot)) => object_type == ot,
+        Some(s::TypeDefinition::Object(ot)) => object_type.name == ot.name,
 
         // The fragment also applies to the object type if its type is an interface
         // that the object type implements
@@ -327,26 +565,168 @@ fn execute_field<'a, R1, R2>(
     object_type: &s::ObjectType,
     object_value: &Option<q::Value>,
     field: &'a q::Field,
-    field_definition: &s::Field,
+    field_definition: &'a s::Field,
     fields: Vec<&'a q::Field>,
 ) -> Result<q::Value, QueryExecutionError>
 where
     R1: Resolver,
     R2: Resolver,
 {
-    coerce_argument_values(ctx.clone(), object_type, field)
+    coerce_argument_values(ctx.clone(), field_definition, field)
         .and_then(|argument_values| {
-            resolve_field_value(
-                ctx.clone(),
-                object_type,
-                object_value,
-                field,
-                field_definition,
-                &field_definition.field_type,
-                &argument_values,
-            )
+            let info = FieldInfo {
+                path: &ctx.path,
+                parent_type: &object_type.name,
+                field_name: &field_definition.name,
+                arguments: &argument_values,
+            };
+            with_middleware(&ctx.middleware, &info, || {
+                resolve_field_value(
+                    ctx.clone(),
+                    object_type,
+                    object_value,
+                    field,
+                    field_definition,
+                    &field_definition.field_type,
+                    &argument_values,
+                )
+            })
+        })
+        .and_then(|mut value| {
+            if !ctx.introspecting {
+                prefetch(ctx.clone(), field_definition, &fields, &mut value);
+            }
+            complete_value(ctx, field, &field_definition.field_type, fields, value)
+        })
+}
+
+/// Resolves a field with `resolve`, unless one of the `before_field` hooks of
+/// the middleware resolves it instead, and passes the result to the
+/// `after_field` hooks.
+fn with_middleware<F>(
+    middleware: &[Arc<FieldMiddleware>],
+    info: &FieldInfo,
+    resolve: F,
+) -> Result<q::Value, QueryExecutionError>
+where
+    F: FnOnce() -> Result<q::Value, QueryExecutionError>,
+{
+    if middleware.is_empty() {
+        return resolve();
+    }
+
+    let started = Instant::now();
+    let result = middleware
+        .iter()
+        .filter_map(|middleware| match middleware.before_field(info) {
+            Ok(None) => None,
+            Ok(Some(value)) => Some(Ok(value)),
+            Err(e) => Some(Err(e)),
+        })
+        .next()
+        .unwrap_or_else(resolve);
+    let duration = started.elapsed();
+    for middleware in middleware.iter().rev() {
+        middleware.after_field(info, &result, duration);
+    }
+    result
+}
+
+/// Resolves the fields selected from the objects of a field's value for
+/// all objects at once, level by level, rather than for each object.
+fn prefetch<'a, R1, R2>(
+    ctx: ExecutionContext<'a, R1, R2>,
+    field_definition: &'a s::Field,
+    fields: &[&'a q::Field],
+    value: &mut q::Value,
+) where
+    R1: Resolver,
+    R2: Resolver,
+{
+    let type_name = named_type_name(&field_definition.field_type);
+    let object_type = match sast::get_named_type(&ctx.schema.document, type_name) {
+        Some(s::TypeDefinition::Object(t)) => t,
+        _ => return,
+    };
+
+    // Connections hold their entities in edges, which they resolve themselves,
+    // and aggregates and `_meta` describe entities rather than being entities
+    if sast::get_connection_node_type(&ctx.schema.document, object_type).is_some()
+        || sast::get_aggregate_entity_type(&ctx.schema.document, object_type).is_some()
+        || object_type.name == META_TYPE_NAME
+    {
+        return;
+    }
+
+    let prefetch_fields = plan_prefetch(ctx.clone(), object_type, fields);
+    if prefetch_fields.is_empty() {
+        return;
+    }
+
+    match *value {
+        q::Value::List(ref mut objects) => {
+            ctx.resolver.prefetch(&ctx.schema.document, objects, &prefetch_fields)
+        }
+        ref mut object @ q::Value::Object(_) => {
+            let mut objects = vec![object.clone()];
+            ctx.resolver.prefetch(&ctx.schema.document, &mut objects, &prefetch_fields);
+            *object = objects.remove(0);
+        }
+        _ => {}
+    }
+}
+
+/// Determines which of the fields selected from objects of `object_type`
+/// resolve to other objects and can be prefetched, recursively.
+fn plan_prefetch<'a, R1, R2>(
+    ctx: ExecutionContext<'a, R1, R2>,
+    object_type: &'a s::ObjectType,
+    fields: &[&'a q::Field],
+) -> Vec<PrefetchField<'a>>
+where
+    R1: Resolver,
+    R2: Resolver,
+{
+    let mut grouped_fields = IndexMap::new();
+    for field in fields {
+        let field_groups = collect_fields(ctx.clone(), object_type, &field.selection_set, None);
+        merge_field_groups(&mut grouped_fields, field_groups);
+    }
+
+    // A field selected under several response keys, e.g. with different
+    // arguments, can't hold a single prefetched value
+    let mut selections = HashMap::new();
+    for group in grouped_fields.values() {
+        *selections.entry(&group[0].name).or_insert(0) += 1;
+    }
+
+    grouped_fields
+        .values()
+        .filter(|group| selections[&group[0].name] == 1)
+        .filter_map(|group| {
+            let field = group[0];
+            let definition = sast::get_field_type(object_type, &field.name)?;
+            let type_name = named_type_name(&definition.field_type);
+            let field_object_type = match sast::get_named_type(&ctx.schema.document, type_name) {
+                Some(s::TypeDefinition::Object(t)) => t,
+                _ => return None,
+            };
+            if sast::get_connection_node_type(&ctx.schema.document, field_object_type).is_some()
+                || sast::get_aggregate_entity_type(&ctx.schema.document, field_object_type)
+                    .is_some()
+            {
+                return None;
+            }
+
+            Some(PrefetchField {
+                name: &field.name,
+                definition,
+                object_type: field_object_type,
+                arguments: coerce_argument_values(ctx.clone(), definition, field).ok()?,
+                children: plan_prefetch(ctx.clone(), field_object_type, group),
+            })
         })
-        .and_then(|value| complete_value(ctx, field, &field_definition.field_type, fields, value))
+        .collect()
 }
 
 /// Resolves the value of a field.
@@ -422,21 +802,56 @@ where
         // Let the resolver decide how the field (with the given object type)
         // is resolved into an entity based on the (potential) parent object
         s::TypeDefinition::Object(t) => if ctx.introspecting {
-            Ok(ctx.introspection_resolver.resolve_object(
+            ctx.introspection_resolver.resolve_object(
+                &ctx.schema.document,
                 object_value,
                 &field.name,
                 field_definition,
                 t,
                 argument_values,
-            ))
+            )
+        } else if let Some(node_type) =
+            sast::get_connection_node_type(&ctx.schema.document, t)
+        {
+            ctx.resolver.resolve_connection(
+                &ctx.schema.document,
+                object_value,
+                &field.name,
+                field_definition,
+                node_type,
+                argument_values,
+            )
+        } else if let Some(entity_type) =
+            sast::get_aggregate_entity_type(&ctx.schema.document, t)
+        {
+            ctx.resolver.resolve_aggregate(
+                &ctx.schema.document,
+                object_value,
+                &field.name,
+                field_definition,
+                entity_type,
+                argument_values,
+            )
+        } else if let Some(entity_type) =
+            sast::get_histogram_entity_type(&ctx.schema.document, t)
+        {
+            ctx.resolver.resolve_histogram(
+                &ctx.schema.document,
+                object_value,
+                &field.name,
+                field_definition,
+                entity_type,
+                argument_values,
+            )
         } else {
-            Ok(ctx.resolver.resolve_object(
+            ctx.resolver.resolve_object(
+                &ctx.schema.document,
                 object_value,
                 &field.name,
                 field_definition,
                 t,
                 argument_values,
-            ))
+            )
         },
 
         // Let the resolver decide how values in the resolved object value
@@ -465,14 +880,43 @@ where
             _ => Ok(q::Value::Null),
         },
 
-        // We will implement these later
-        s::TypeDefinition::Interface(_) => unimplemented!(),
-        s::TypeDefinition::Union(_) => unimplemented!(),
+        // Resolve interfaces and unions into an entity of the first object
+        // type that implements the interface or is a member of the union and
+        // has one
+        s::TypeDefinition::Interface(_) | s::TypeDefinition::Union(_) => {
+            for object_type in abstract_object_types(&ctx.schema.document, named_type) {
+                let value = ctx.resolver.resolve_object(
+                    &ctx.schema.document,
+                    object_value,
+                    &field.name,
+                    field_definition,
+                    object_type,
+                    argument_values,
+                )?;
+                if value != q::Value::Null {
+                    return Ok(value);
+                }
+            }
+            Ok(q::Value::Null)
+        }
 
         _ => unimplemented!(),
     }
 }
 
+/// Returns the object types that implement an interface or are members of a
+/// union.
+fn abstract_object_types<'a>(
+    schema: &'a s::Document,
+    abstract_type: &s::TypeDefinition,
+) -> Vec<&'a s::ObjectType> {
+    match abstract_type {
+        s::TypeDefinition::Interface(t) => sast::get_object_types_implementing(schema, &t.name),
+        s::TypeDefinition::Union(t) => sast::get_union_member_types(schema, t),
+        _ => vec![],
+    }
+}
+
 /// Resolves the value of a field that corresponds to a list type.
 fn resolve_field_value_for_list_type<'a, R1, R2>(
     ctx: ExecutionContext<'a, R1, R2>,
@@ -512,21 +956,23 @@ where
                 // Let the resolver decide how the list field (with the given item object type)
                 // is resolved into a entities based on the (potential) parent object
                 s::TypeDefinition::Object(t) => if ctx.introspecting {
-                    Ok(ctx.introspection_resolver.resolve_objects(
+                    ctx.introspection_resolver.resolve_objects(
+                        &ctx.schema.document,
                         object_value,
                         &field.name,
                         field_definition,
                         t,
                         argument_values,
-                    ))
+                    )
                 } else {
-                    Ok(ctx.resolver.resolve_objects(
+                    ctx.resolver.resolve_objects(
+                        &ctx.schema.document,
                         object_value,
                         &field.name,
                         field_definition,
                         t,
                         argument_values,
-                    ))
+                    )
                 },
 
                 // Let the resolver decide how values in the resolved object value
@@ -555,16 +1001,54 @@ where
                     _ => Ok(q::Value::Null),
                 },
 
-                // We will implement these later
-                s::TypeDefinition::Interface(_) => unimplemented!(),
-                s::TypeDefinition::Union(_) => unimplemented!(),
+                // Let the resolver decide how the list field is resolved into
+                // entities of all object types that implement the interface
+                // or are members of the union
+                s::TypeDefinition::Interface(_) | s::TypeDefinition::Union(_) => {
+                    ctx.resolver.resolve_abstract_objects(
+                        &ctx.schema.document,
+                        object_value,
+                        &field.name,
+                        field_definition,
+                        &abstract_object_types(&ctx.schema.document, named_type),
+                        argument_values,
+                    )
+                }
 
                 _ => unimplemented!(),
             }
         }
 
-        // We don't support nested lists yet
-        s::Type::ListType(_) => unimplemented!(),
+        // Nested lists are resolved like lists of their innermost type and
+        // completed level by level; they can only hold scalars and enums
+        s::Type::ListType(nested_type) => {
+            let type_name = named_type_name(nested_type);
+            let named_type = sast::get_named_type(
+                if ctx.introspecting {
+                    ctx.introspection_schema
+                } else {
+                    &ctx.schema.document
+                },
+                type_name,
+            );
+            match named_type {
+                Some(s::TypeDefinition::Scalar(_)) | Some(s::TypeDefinition::Enum(_)) => {
+                    resolve_field_value_for_list_type(
+                        ctx.clone(),
+                        object_type,
+                        object_value,
+                        field,
+                        field_definition,
+                        nested_type,
+                        argument_values,
+                    )
+                }
+                _ => Err(QueryExecutionError::NotSupported(format!(
+                    "nested lists of \"{}\" in field \"{}\"",
+                    type_name, field.name
+                ))),
+            }
+        }
     }
 }
 
@@ -600,19 +1084,31 @@ where
     // Complete list values
     if let s::Type::ListType(inner_type) = field_type {
         return match resolved_value {
-            // Complete list values individually
+            // Complete list values individually. Elements that fail to
+            // complete are null, with an error, unless they are non-null, in
+            // which case the whole list is null
             q::Value::List(values) => {
                 let mut out = Vec::with_capacity(values.len());
-                for value in values.into_iter() {
-                    out.push(complete_value(
-                        ctx.clone(),
-                        field,
-                        inner_type,
-                        fields.clone(),
-                        value,
-                    )?);
+                let mut null_propagated = false;
+                for (index, value) in values.into_iter().enumerate() {
+                    let element_ctx = ctx.for_index(index);
+                    let path = element_ctx.path.clone();
+                    match complete_value(element_ctx, field, inner_type, fields.clone(), value) {
+                        Ok(value) => out.push(value),
+                        Err(e) => {
+                            report_field_error(&ctx.errors, field.position, path, e);
+                            match inner_type.as_ref() {
+                                s::Type::NonNullType(_) => null_propagated = true,
+                                _ => out.push(q::Value::Null),
+                            }
+                        }
+                    }
+                }
+                if null_propagated {
+                    Err(QueryExecutionError::NullPropagated)
+                } else {
+                    Ok(q::Value::List(out))
                 }
-                Ok(q::Value::List(out))
             }
 
             // Return field error if the resolved value for the list is not a list
@@ -639,39 +1135,49 @@ where
     };
 
     match named_type {
-        // Complete scalar values; we're assuming that the resolver has
-        // already returned a valid value for the scalar type
-        Some(s::TypeDefinition::Scalar(_)) => return Ok(resolved_value),
+        // Complete scalar values by coercing them to the scalar type
+        Some(s::TypeDefinition::Scalar(scalar_type)) => {
+            resolved_value.coerce(scalar_type).ok_or_else(|| {
+                QueryExecutionError::ValueCoercionError(
+                    field.position,
+                    field.name.to_string(),
+                    resolved_value.clone(),
+                    scalar_type.name.clone(),
+                )
+            })
+        }
 
-        // Complete enum values; we're assuming that the resolver has
-        // already returned a valid value for the enum type
-        Some(s::TypeDefinition::Enum(_)) => return Ok(resolved_value),
+        // Complete enum values by coercing them to the enum type
+        Some(s::TypeDefinition::Enum(enum_type)) => {
+            resolved_value.coerce(enum_type).ok_or_else(|| {
+                QueryExecutionError::ValueCoercionError(
+                    field.position,
+                    field.name.to_string(),
+                    resolved_value.clone(),
+                    enum_type.name.clone(),
+                )
+            })
+        }
 
         // Complete object types recursively
-        Some(s::TypeDefinition::Object(object_type)) => execute_selection_set(
+        Some(s::TypeDefinition::Object(object_type)) => complete_object(
             ctx.clone(),
             &merge_selection_sets(fields),
             object_type,
-            &Some(resolved_value),
-        ).map(|result| match result.data {
-            Some(v) => v,
-            None => q::Value::Null,
-        }),
+            resolved_value,
+        ),
 
         // Resolve interface types using the resolved value and complete the value recursively
         Some(s::TypeDefinition::Interface(_)) => {
             let object_type =
                 resolve_abstract_type(ctx.clone(), named_type.unwrap(), &resolved_value)?;
 
-            execute_selection_set(
+            complete_object(
                 ctx.clone(),
                 &merge_selection_sets(fields),
                 object_type,
-                &Some(resolved_value),
-            ).map(|result| match result.data {
-                Some(v) => v,
-                None => q::Value::Null,
-            })
+                resolved_value,
+            )
         }
 
         // Resolve union types using the resolved value and complete the value recursively
@@ -679,21 +1185,32 @@ where
             let object_type =
                 resolve_abstract_type(ctx.clone(), named_type.unwrap(), &resolved_value)?;
 
-            execute_selection_set(
+            complete_object(
                 ctx.clone(),
                 &merge_selection_sets(fields),
                 object_type,
-                &Some(resolved_value),
-            ).map(|result| match result.data {
-                Some(v) => v,
-                None => q::Value::Null,
-            })
+                resolved_value,
+            )
         }
 
         _ => unimplemented!(),
     }
 }
 
+/// Executes the selection set of an object value.
+fn complete_object<'a, R1, R2>(
+    ctx: ExecutionContext<'a, R1, R2>,
+    selection_set: &'a q::SelectionSet,
+    object_type: &s::ObjectType,
+    object_value: q::Value,
+) -> Result<q::Value, QueryExecutionError>
+where
+    R1: Resolver,
+    R2: Resolver,
+{
+    execute_selection_set(ctx, selection_set, object_type, &Some(object_value))
+}
+
 /// Resolves an abstract type (interface, union) into an object type based on the given value.
 fn resolve_abstract_type<'a, R1, R2>(
     ctx: ExecutionContext<'a, R1, R2>,
@@ -706,19 +1223,19 @@ where
 {
     // Let the resolver handle the type resolution, return an error if the resolution
     // yields nothing
-    ctx.resolver
-        .resolve_abstract_type(
-            if ctx.introspecting {
-                ctx.introspection_schema
-            } else {
-                &ctx.schema.document
-            },
+    let object_type = if ctx.introspecting {
+        ctx.introspection_resolver.resolve_abstract_type(
+            ctx.introspection_schema,
             abstract_type,
             object_value,
         )
-        .ok_or(QueryExecutionError::AbstractTypeError(
-            sast::get_type_name(abstract_type).to_string(),
-        ))
+    } else {
+        ctx.resolver
+            .resolve_abstract_type(&ctx.schema.document, abstract_type, object_value)
+    };
+    object_type.ok_or(QueryExecutionError::AbstractTypeError(
+        sast::get_type_name(abstract_type).to_string(),
+    ))
 }
 
 /// Merges the selection sets of several fields into a single selection set.
@@ -752,7 +1269,7 @@ fn merge_selection_sets(fields: Vec<&q::Field>) -> q::SelectionSet {
 /// Coerces argument values into GraphQL values.
 fn coerce_argument_values<'a, R1, R2>(
     ctx: ExecutionContext<'a, R1, R2>,
-    object_type: &'a s::ObjectType,
+    field_definition: &'a s::Field,
     field: &'a q::Field,
 ) -> Result<HashMap<&'a q::Name, q::Value>, QueryExecutionError>
 where
@@ -761,35 +1278,57 @@ where
 {
     let mut coerced_values = HashMap::new();
 
-    if let Some(argument_definitions) = sast::get_argument_definitions(object_type, &field.name) {
-        for argument_def in argument_definitions.iter() {
-            match qast::get_argument_value(&field.arguments, &argument_def.name) {
-                // We don't support variables yet
-                Some(q::Value::Variable(_)) => unimplemented!(),
-
-                // There is no value, either use the default or fail
-                None => {
-                    if let Some(ref default_value) = argument_def.default_value {
-                        coerced_values.insert(&argument_def.name, default_value.clone());
-                    } else if let s::Type::NonNullType(_) = argument_def.value_type {
-                        return Err(QueryExecutionError::MissingArgumentError(
-                            field.position.clone(),
-                            argument_def.name.to_owned(),
-                        ));
-                    };
-                }
+    for argument_def in field_definition.arguments.iter() {
+        match qast::get_argument_value(&field.arguments, &argument_def.name) {
+            // Use the value of the variable, if one was provided
+            Some(q::Value::Variable(name)) if ctx.variable_values.contains_key(name) => {
+                let value = &ctx.variable_values[name];
+                coerced_values.insert(
+                    &argument_def.name,
+                    match value {
+                        q::Value::Null => q::Value::Null,
+                        _ => coerce_argument_value(ctx.clone(), field, argument_def, value)?,
+                    },
+                );
+            }
 
-                // There is a value for the argument, attempt to coerce it to the
-                // value type of the argument definition
-                Some(v) => {
-                    coerced_values.insert(
-                        &argument_def.name,
-                        coerce_argument_value(ctx.clone(), field, argument_def, v)?,
-                    );
-                }
-            };
+            // There is no value, either use the default or fail
+            None | Some(q::Value::Variable(_)) => {
+                if let Some(ref default_value) = argument_def.default_value {
+                    coerced_values.insert(&argument_def.name, default_value.clone());
+                } else if let s::Type::NonNullType(_) = argument_def.value_type {
+                    return Err(QueryExecutionError::MissingArgumentError(
+                        field.position.clone(),
+                        argument_def.name.to_owned(),
+                    ));
+                };
+            }
+
+            // There is a value for the argument, attempt to coerce it to the
+            // value type of the argument definition
+            Some(v) => {
+                let value = substitute_variables(v, &ctx.variable_values);
+                coerced_values.insert(
+                    &argument_def.name,
+                    coerce_argument_value(ctx.clone(), field, argument_def, &value)?,
+                );
+            }
+        };
+    }
+
+    // Don't let a single field fetch arbitrarily many entities
+    if let (Some(max_first), Some(q::Value::Int(first))) =
+        (ctx.max_first, coerced_values.get(&String::from("first")))
+    {
+        let first = first.as_i64().unwrap_or(i64::max_value());
+        if first > i64::from(max_first) {
+            return Err(QueryExecutionError::FirstTooLargeError(
+                field.position,
+                first,
+                max_first,
+            ));
         }
-    };
+    }
 
     Ok(coerced_values)
 }
@@ -828,6 +1367,101 @@ where
     })
 }
 
+/// Coerces the values provided for the variables of an operation into the
+/// types the variables are defined with, falling back to their defaults.
+fn coerce_variable_values(
+    schema: &Schema,
+    operation: &q::OperationDefinition,
+    variables: &Option<QueryVariables>,
+) -> Result<HashMap<q::Name, q::Value>, QueryExecutionError> {
+    use values::coercion::coerce_value;
+
+    // Variables can only have input types
+    let resolver = |name: &s::Name| match sast::get_named_type(&schema.document, name) {
+        Some(t @ s::TypeDefinition::Scalar(_))
+        | Some(t @ s::TypeDefinition::Enum(_))
+        | Some(t @ s::TypeDefinition::InputObject(_)) => Some(t),
+        _ => None,
+    };
+
+    let mut coerced_values = HashMap::new();
+
+    for variable_def in qast::get_variable_definitions(operation) {
+        let value = variables
+            .as_ref()
+            .and_then(|variables| variables.get(&variable_def.name));
+
+        match value.map(|value| &**value) {
+            // There is no value, either use the default or fail
+            None => {
+                if let Some(ref default_value) = variable_def.default_value {
+                    coerced_values.insert(variable_def.name.clone(), default_value.clone());
+                } else if let q::Type::NonNullType(_) = variable_def.var_type {
+                    return Err(QueryExecutionError::MissingVariableError(
+                        variable_def.position.clone(),
+                        variable_def.name.to_owned(),
+                    ));
+                }
+            }
+
+            // Null is a valid value for all nullable types
+            Some(q::Value::Null) => match variable_def.var_type {
+                q::Type::NonNullType(_) => {
+                    return Err(QueryExecutionError::MissingVariableError(
+                        variable_def.position.clone(),
+                        variable_def.name.to_owned(),
+                    ))
+                }
+                _ => {
+                    coerced_values.insert(variable_def.name.clone(), q::Value::Null);
+                }
+            },
+
+            // There is a value for the variable, attempt to coerce it to the
+            // type of the variable definition
+            Some(value) => {
+                let coerced_value = coerce_value(value, &variable_def.var_type, &resolver)
+                    .ok_or_else(|| {
+                        QueryExecutionError::InvalidVariableTypeError(
+                            variable_def.position.clone(),
+                            variable_def.name.to_owned(),
+                        )
+                    })?;
+                coerced_values.insert(variable_def.name.clone(), coerced_value);
+            }
+        }
+    }
+
+    Ok(coerced_values)
+}
+
+/// Replaces variables nested in lists and input objects with their values.
+/// Variables without a value are replaced with `null`.
+fn substitute_variables(
+    value: &q::Value,
+    variable_values: &HashMap<q::Name, q::Value>,
+) -> q::Value {
+    match value {
+        q::Value::Variable(name) => variable_values
+            .get(name)
+            .cloned()
+            .unwrap_or(q::Value::Null),
+        q::Value::List(values) => q::Value::List(
+            values
+                .iter()
+                .map(|value| substitute_variables(value, variable_values))
+                .collect(),
+        ),
+        q::Value::Object(fields) => q::Value::Object(
+            fields
+                .iter()
+                .map(|(name, value)| (name.clone(), substitute_variables(value, variable_values)))
+                .collect(),
+        ),
+        _ => value.clone(),
+    }
+}
+
 fn get_field_type<'a, R1, R2>(
     ctx: ExecutionContext<'a, R1, R2>,
     object_type: &'a s::ObjectType,
@@ -849,3 +1483,12 @@ where
 
     sast::get_field_type(object_type, name).map(|t| (t, ctx.introspecting))
 }
+
+/// Renders a type the way it is written in a schema, e.g. `[User!]!`.
+fn type_string(field_type: &s::Type) -> String {
+    match field_type {
+        s::Type::NamedType(name) => name.to_owned(),
+        s::Type::ListType(inner) => format!("[{}]", type_string(inner)),
+        s::Type::NonNullType(inner) => format!("{}!", type_string(inner)),
+    }
+}
@@ -0,0 +1,42 @@
+use graphql_parser::query as q;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use graph::prelude::{PathSegment, QueryExecutionError};
+
+/// A field that is being resolved, as seen by a `FieldMiddleware`.
+pub struct FieldInfo<'a> {
+    /// The path of the field in the response, e.g. allUsers > 0 > friends.
+    pub path: &'a [PathSegment],
+    /// The name of the object type the field belongs to.
+    pub parent_type: &'a str,
+    pub field_name: &'a str,
+    /// The arguments of the field, coerced to their types.
+    pub arguments: &'a HashMap<&'a q::Name, q::Value>,
+}
+
+/// Hooks that run around the resolution of every field, which let
+/// embedders add their own tracing, caching or authorization to the
+/// execution of queries.
+///
+/// The `before_field` hooks run in the order the middleware is passed in,
+/// and the `after_field` hooks in the reverse order.
+pub trait FieldMiddleware: Send + Sync {
+    /// Runs before a field is resolved. Returning a value resolves the field
+    /// to it instead, e.g. for a cached value, and returning an error fails
+    /// the field, e.g. if the client may not query it. Either way, the
+    /// `before_field` hooks of later middleware are skipped.
+    fn before_field(&self, _field: &FieldInfo) -> Result<Option<q::Value>, QueryExecutionError> {
+        Ok(None)
+    }
+
+    /// Runs after a field has been resolved, with the value or error it was
+    /// resolved to and how long that took.
+    fn after_field(
+        &self,
+        _field: &FieldInfo,
+        _result: &Result<q::Value, QueryExecutionError>,
+        _duration: Duration,
+    ) {
+    }
+}
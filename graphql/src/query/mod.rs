@@ -7,5 +7,25 @@ pub mod execution;
 /// Common trait for field resolvers used in the execution.
 pub mod resolver;
 
+/// Hooks that embedders can run around the resolution of fields.
+pub mod middleware;
+
+/// Canonical representation and hashing of queries.
+pub mod normalize;
+
+/// Estimation of how expensive queries are to run.
+pub mod complexity;
+
+/// Validation of queries against the schema before they are executed.
+pub mod validation;
+
+/// Determination of the block that queries are run at.
+pub mod block;
+
+pub use self::block::block_constraint;
+pub use self::complexity::query_complexity;
 pub use self::execution::{execute, ExecutionOptions};
-pub use self::resolver::Resolver;
+pub use self::middleware::{FieldInfo, FieldMiddleware};
+pub use self::normalize::{normalize_query, query_hash};
+pub use self::resolver::{PrefetchField, Resolver};
+pub use self::validation::validate_query;
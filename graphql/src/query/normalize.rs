@@ -0,0 +1,337 @@
+use graphql_parser::query::*;
+use std::collections::{BTreeMap, HashMap};
+
+use graph::prelude::QueryVariables;
+use graph::serde_json;
+use graph::util::ethereum::keccak256;
+
+/// Returns a canonical text representation of a query document, so that
+/// equivalent queries are represented the same way.
+///
+/// Whitespace and comments are dropped, arguments and selections are sorted,
+/// and variables with scalar values are inlined. Values of all other
+/// variables are appended to the output, as they affect the query result,
+/// and so is the name of the operation to run, if one is given.
+pub fn normalize_query(
+    document: &Document,
+    variables: Option<&QueryVariables>,
+    operation_name: Option<&str>,
+) -> String {
+    let mut inlined = HashMap::new();
+    let mut remaining = BTreeMap::new();
+
+    for definition in document.definitions.iter() {
+        for variable in variable_definitions(definition) {
+            let value = variables
+                .and_then(|variables| variables.get(&variable.name))
+                .map(|value| (**value).clone())
+                .or_else(|| variable.default_value.clone());
+
+            match value {
+                Some(ref value) if is_trivial(value) => {
+                    inlined.insert(variable.name.clone(), value.clone());
+                }
+                Some(value) => {
+                    remaining.insert(variable.name.clone(), value);
+                }
+                None => {}
+            }
+        }
+    }
+
+    let normalizer = Normalizer { inlined };
+
+    let mut definitions: Vec<String> = document
+        .definitions
+        .iter()
+        .map(|definition| normalizer.definition(definition))
+        .collect();
+    definitions.sort();
+
+    let mut output = definitions.join(" ");
+    for (name, value) in remaining.iter() {
+        output.push_str(&format!(" ${}={}", name, normalizer.value(value)));
+    }
+    if let Some(operation_name) = operation_name {
+        output.push_str(&format!(" operationName={}", operation_name));
+    }
+    output
+}
+
+/// Returns a hash of the normalized query that can be used as a cache key.
+pub fn query_hash(
+    document: &Document,
+    variables: Option<&QueryVariables>,
+    operation_name: Option<&str>,
+) -> String {
+    format!(
+        "{:x}",
+        keccak256(normalize_query(document, variables, operation_name).as_bytes())
+    )
+}
+
+fn variable_definitions(definition: &Definition) -> &[VariableDefinition] {
+    match definition {
+        Definition::Operation(OperationDefinition::Query(query)) => &query.variable_definitions,
+        Definition::Operation(OperationDefinition::Mutation(mutation)) => {
+            &mutation.variable_definitions
+        }
+        Definition::Operation(OperationDefinition::Subscription(subscription)) => {
+            &subscription.variable_definitions
+        }
+        _ => &[],
+    }
+}
+
+/// Scalar values are inlined into the normalized query.
+fn is_trivial(value: &Value) -> bool {
+    match value {
+        Value::List(_) | Value::Object(_) | Value::Variable(_) => false,
+        _ => true,
+    }
+}
+
+struct Normalizer {
+    inlined: HashMap<Name, Value>,
+}
+
+impl Normalizer {
+    fn definition(&self, definition: &Definition) -> String {
+        match definition {
+            Definition::Operation(OperationDefinition::SelectionSet(set)) => {
+                self.selection_set(set)
+            }
+            Definition::Operation(OperationDefinition::Query(query)) => self.operation(
+                "query",
+                &query.name,
+                &query.variable_definitions,
+                &query.directives,
+                &query.selection_set,
+            ),
+            Definition::Operation(OperationDefinition::Mutation(mutation)) => self.operation(
+                "mutation",
+                &mutation.name,
+                &mutation.variable_definitions,
+                &mutation.directives,
+                &mutation.selection_set,
+            ),
+            Definition::Operation(OperationDefinition::Subscription(subscription)) => self
+                .operation(
+                    "subscription",
+                    &subscription.name,
+                    &subscription.variable_definitions,
+                    &subscription.directives,
+                    &subscription.selection_set,
+                ),
+            Definition::Fragment(fragment) => format!(
+                "fragment {} {}{}{}",
+                fragment.name,
+                self.type_condition(&fragment.type_condition),
+                self.directives(&fragment.directives),
+                self.selection_set(&fragment.selection_set)
+            ),
+        }
+    }
+
+    fn operation(
+        &self,
+        kind: &str,
+        name: &Option<Name>,
+        variable_definitions: &[VariableDefinition],
+        directives: &[Directive],
+        selection_set: &SelectionSet,
+    ) -> String {
+        let mut variables: Vec<String> = variable_definitions
+            .iter()
+            .filter(|variable| !self.inlined.contains_key(&variable.name))
+            .map(|variable| {
+                let mut s = format!("${}:{}", variable.name, self.var_type(&variable.var_type));
+                if let Some(ref default_value) = variable.default_value {
+                    s.push_str(&format!("={}", self.value(default_value)));
+                }
+                s
+            })
+            .collect();
+        variables.sort();
+
+        let mut s = String::from(kind);
+        if let Some(name) = name {
+            s.push_str(&format!(" {}", name));
+        }
+        if !variables.is_empty() {
+            s.push_str(&format!("({})", variables.join(",")));
+        }
+        s.push_str(&self.directives(directives));
+        s.push_str(&self.selection_set(selection_set));
+        s
+    }
+
+    fn selection_set(&self, selection_set: &SelectionSet) -> String {
+        let mut selections: Vec<String> = selection_set
+            .items
+            .iter()
+            .map(|selection| self.selection(selection))
+            .collect();
+        selections.sort();
+        format!("{{{}}}", selections.join(","))
+    }
+
+    fn selection(&self, selection: &Selection) -> String {
+        match selection {
+            Selection::Field(field) => {
+                let mut s = String::new();
+                if let Some(ref alias) = field.alias {
+                    s.push_str(&format!("{}:", alias));
+                }
+                s.push_str(&field.name);
+                s.push_str(&self.arguments(&field.arguments));
+                s.push_str(&self.directives(&field.directives));
+                if !field.selection_set.items.is_empty() {
+                    s.push_str(&self.selection_set(&field.selection_set));
+                }
+                s
+            }
+            Selection::FragmentSpread(spread) => format!(
+                "...{}{}",
+                spread.fragment_name,
+                self.directives(&spread.directives)
+            ),
+            Selection::InlineFragment(fragment) => format!(
+                "...{}{}{}",
+                fragment
+                    .type_condition
+                    .as_ref()
+                    .map_or(String::new(), |condition| self.type_condition(condition)),
+                self.directives(&fragment.directives),
+                self.selection_set(&fragment.selection_set)
+            ),
+        }
+    }
+
+    fn type_condition(&self, condition: &TypeCondition) -> String {
+        match condition {
+            TypeCondition::On(name) => format!("on {}", name),
+        }
+    }
+
+    fn directives(&self, directives: &[Directive]) -> String {
+        directives
+            .iter()
+            .map(|directive| {
+                format!(
+                    "@{}{}",
+                    directive.name,
+                    self.arguments(&directive.arguments)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("")
+    }
+
+    fn arguments(&self, arguments: &[(Name, Value)]) -> String {
+        if arguments.is_empty() {
+            return String::new();
+        }
+
+        let mut arguments: Vec<String> = arguments
+            .iter()
+            .map(|(name, value)| format!("{}:{}", name, self.value(value)))
+            .collect();
+        arguments.sort();
+        format!("({})", arguments.join(","))
+    }
+
+    fn var_type(&self, var_type: &Type) -> String {
+        match var_type {
+            Type::NamedType(name) => name.clone(),
+            Type::ListType(t) => format!("[{}]", self.var_type(t)),
+            Type::NonNullType(t) => format!("{}!", self.var_type(t)),
+        }
+    }
+
+    fn value(&self, value: &Value) -> String {
+        match value {
+            Value::Variable(name) => match self.inlined.get(name) {
+                Some(value) => self.value(value),
+                None => format!("${}", name),
+            },
+            Value::Int(number) => format!("{}", number.as_i64().unwrap_or(0)),
+            Value::Float(f) => format!("{}", f),
+            Value::String(s) => serde_json::to_string(s).expect("Failed to serialize string"),
+            Value::Boolean(b) => format!("{}", b),
+            Value::Null => String::from("null"),
+            Value::Enum(name) => name.clone(),
+            Value::List(values) => format!(
+                "[{}]",
+                values
+                    .iter()
+                    .map(|value| self.value(value))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+            // Object fields are kept sorted by the `BTreeMap`
+            Value::Object(fields) => format!(
+                "{{{}}}",
+                fields
+                    .iter()
+                    .map(|(name, value)| format!("{}:{}", name, self.value(value)))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use graph::prelude::{QueryVariableValue, QueryVariables};
+    use graphql_parser;
+
+    use super::{normalize_query, query_hash};
+
+    #[test]
+    fn ignores_whitespace_comments_and_ordering() {
+        let a = graphql_parser::parse_query(
+            "{ users(first: 10, skip: 5) { name id } # all users\n }",
+        ).unwrap();
+        let b = graphql_parser::parse_query("{users(skip:5,first:10){id,name}}").unwrap();
+
+        assert_eq!(
+            normalize_query(&a, None, None),
+            "{users(first:10,skip:5){id,name}}"
+        );
+        assert_eq!(query_hash(&a, None, None), query_hash(&b, None, None));
+    }
+
+    #[test]
+    fn inlines_scalar_variables() {
+        let document = graphql_parser::parse_query(
+            "query users($name: String) { users(where: { name: $name }) { id } }",
+        ).unwrap();
+        let mut variables = QueryVariables::new();
+        variables.insert(String::from("name"), QueryVariableValue::from("Jordi"));
+
+        assert_eq!(
+            normalize_query(&document, Some(&variables), None),
+            "query users{users(where:{name:\"Jordi\"}){id}}"
+        );
+    }
+
+    #[test]
+    fn distinguishes_different_queries() {
+        let a = graphql_parser::parse_query("{ users(first: 10) { id } }").unwrap();
+        let b = graphql_parser::parse_query("{ users(first: 11) { id } }").unwrap();
+        assert_ne!(query_hash(&a, None, None), query_hash(&b, None, None));
+    }
+
+    #[test]
+    fn distinguishes_operations_of_the_same_document() {
+        let document =
+            graphql_parser::parse_query("query a { users { id } } query b { users { name } }")
+                .unwrap();
+        assert_ne!(
+            query_hash(&document, None, Some("a")),
+            query_hash(&document, None, Some("b"))
+        );
+    }
+}
@@ -1,103 +1,214 @@
 use graphql_parser::{query as q, schema as s};
 use std::collections::HashMap;
+use std::time::Instant;
+
+use graph::prelude::{QueryExecutionError, QueryPlan, StoreQueryTrace};
 
 use prelude::*;
+use schema::ast as sast;
+
+/// A field whose values can be fetched for many parent objects at once,
+/// along with the fields to fetch for those values in turn.
+pub struct PrefetchField<'a> {
+    /// Name of the field on the parent objects.
+    pub name: &'a q::Name,
+    pub definition: &'a s::Field,
+    /// The type of the objects the field resolves to.
+    pub object_type: &'a s::ObjectType,
+    pub arguments: HashMap<&'a q::Name, q::Value>,
+    pub children: Vec<PrefetchField<'a>>,
+}
 
 /// A GraphQL resolver that can resolve entities, enum values, scalar types and interfaces/unions.
+///
+/// Entities that can't be resolved, e.g. because the store fails, are
+/// reported as errors of the fields they are resolved for.
 pub trait Resolver: Clone {
     /// Resolves entities referenced by a parent object.
     fn resolve_objects(
         &self,
+        schema: &s::Document,
         parent: &Option<q::Value>,
         field: &q::Name,
         field_definition: &s::Field,
         object_type: &s::ObjectType,
         arguments: &HashMap<&q::Name, q::Value>,
-    ) -> q::Value;
+    ) -> Result<q::Value, QueryExecutionError>;
+
+    /// Resolves entities of any of the object types that implement an
+    /// interface or are members of a union. By default, the entities of each
+    /// object type are resolved separately.
+    fn resolve_abstract_objects(
+        &self,
+        schema: &s::Document,
+        parent: &Option<q::Value>,
+        field: &q::Name,
+        field_definition: &s::Field,
+        object_types: &[&s::ObjectType],
+        arguments: &HashMap<&q::Name, q::Value>,
+    ) -> Result<q::Value, QueryExecutionError> {
+        let mut objects = vec![];
+        for object_type in object_types {
+            if let q::Value::List(values) = self.resolve_objects(
+                schema,
+                parent,
+                field,
+                field_definition,
+                object_type,
+                arguments,
+            )? {
+                objects.extend(values);
+            }
+        }
+        Ok(q::Value::List(objects))
+    }
 
     /// Resolves an entity referenced by a parent object.
     fn resolve_object(
         &self,
+        schema: &s::Document,
         parent: &Option<q::Value>,
         field: &q::Name,
         field_definition: &s::Field,
         object_type: &s::ObjectType,
         arguments: &HashMap<&q::Name, q::Value>,
-    ) -> q::Value;
+    ) -> Result<q::Value, QueryExecutionError>;
+
+    /// Resolves a page of a connection to the entities of `node_type`, with
+    /// `edges` and `pageInfo`.
+    fn resolve_connection(
+        &self,
+        _schema: &s::Document,
+        _parent: &Option<q::Value>,
+        _field: &q::Name,
+        _field_definition: &s::Field,
+        _node_type: &s::ObjectType,
+        _arguments: &HashMap<&q::Name, q::Value>,
+    ) -> Result<q::Value, QueryExecutionError> {
+        Ok(q::Value::Null)
+    }
 
-    /// Resolves an enum value for a given enum type.
-    fn resolve_enum_value(&self, enum_type: &s::EnumType, value: Option<&q::Value>) -> q::Value {
-        value
-            .and_then(|value| value.coerce(enum_type))
-            .unwrap_or(q::Value::Null)
+    /// Resolves the aggregates of the entities of `entity_type`, with `count`
+    /// and the `sum`, `min` and `max` of their numeric fields.
+    fn resolve_aggregate(
+        &self,
+        _schema: &s::Document,
+        _parent: &Option<q::Value>,
+        _field: &q::Name,
+        _field_definition: &s::Field,
+        _entity_type: &s::ObjectType,
+        _arguments: &HashMap<&q::Name, q::Value>,
+    ) -> Result<q::Value, QueryExecutionError> {
+        Ok(q::Value::Null)
     }
 
-    /// Resolves a scalar value for a given scalar type.
+    /// Resolves a histogram of the entities of `entity_type`, with `buckets`
+    /// that count the entities by ranges of the numeric `field` argument.
+    fn resolve_histogram(
+        &self,
+        _schema: &s::Document,
+        _parent: &Option<q::Value>,
+        _field: &q::Name,
+        _field_definition: &s::Field,
+        _entity_type: &s::ObjectType,
+        _arguments: &HashMap<&q::Name, q::Value>,
+    ) -> Result<q::Value, QueryExecutionError> {
+        Ok(q::Value::Null)
+    }
+
+    /// Returns the store queries made to resolve the query so far, if the
+    /// resolver records them.
+    fn query_plan(&self) -> Option<QueryPlan> {
+        None
+    }
+
+    /// Returns the store queries made to resolve the query so far, with the
+    /// times they were made at as offsets from `start`, if the resolver
+    /// traces them.
+    fn store_query_trace(&self, _start: Instant) -> Vec<StoreQueryTrace> {
+        vec![]
+    }
+
+    /// Resolves `fields` for all `objects` at once and adds the results to
+    /// the objects, so that the fields don't need to be resolved for one
+    /// object at a time.
+    fn prefetch(
+        &self,
+        _schema: &s::Document,
+        _objects: &mut Vec<q::Value>,
+        _fields: &[PrefetchField],
+    ) {
+    }
+
+    /// Resolves an enum value for a given enum type. The execution coerces
+    /// the value to the enum type, so it is passed on as it is by default.
+    fn resolve_enum_value(&self, _enum_type: &s::EnumType, value: Option<&q::Value>) -> q::Value {
+        value.cloned().unwrap_or(q::Value::Null)
+    }
+
+    /// Resolves a scalar value for a given scalar type. The execution
+    /// coerces the value to the scalar type, so it is passed on as it is by
+    /// default.
     fn resolve_scalar_value(
         &self,
-        scalar_type: &s::ScalarType,
+        _scalar_type: &s::ScalarType,
         value: Option<&q::Value>,
     ) -> q::Value {
-        value
-            .and_then(|value| value.coerce(scalar_type))
-            .unwrap_or(q::Value::Null)
+        value.cloned().unwrap_or(q::Value::Null)
     }
 
-    /// Resolves a list of enum values for a given enum type.
-    fn resolve_enum_values(&self, enum_type: &s::EnumType, value: Option<&q::Value>) -> q::Value {
-        value
-            .and_then(|value| match value {
-                q::Value::List(values) => Some(values),
-                _ => None,
-            })
-            .and_then(|values| {
-                let coerced_values: Vec<q::Value> = values
-                    .iter()
-                    .filter_map(|value| value.coerce(enum_type))
-                    .collect();
-
-                if coerced_values.len() == values.len() {
-                    Some(q::Value::List(coerced_values))
-                } else {
-                    None
-                }
-            })
-            .unwrap_or(q::Value::Null)
+    /// Resolves a list of enum values, which may be nested, for a given enum
+    /// type. The execution coerces every element, so the list is passed on
+    /// as it is by default.
+    fn resolve_enum_values(&self, _enum_type: &s::EnumType, value: Option<&q::Value>) -> q::Value {
+        value.cloned().unwrap_or(q::Value::Null)
     }
 
-    /// Resolves a list of scalar values for a given list type.
+    /// Resolves a list of scalar values, which may be nested, for a given
+    /// scalar type. The execution coerces every element, so the list is
+    /// passed on as it is by default.
     fn resolve_scalar_values(
         &self,
-        scalar_type: &s::ScalarType,
+        _scalar_type: &s::ScalarType,
         value: Option<&q::Value>,
     ) -> q::Value {
-        value
-            .and_then(|value| match value {
-                q::Value::List(values) => Some(values),
-                _ => None,
-            })
-            .and_then(|values| {
-                let coerced_values: Vec<q::Value> = values
-                    .iter()
-                    .filter_map(|value| value.coerce(scalar_type))
-                    .collect();
-
-                if coerced_values.len() == values.len() {
-                    Some(q::Value::List(coerced_values))
-                } else {
-                    None
-                }
-            })
-            .unwrap_or(q::Value::Null)
+        value.cloned().unwrap_or(q::Value::Null)
     }
 
-    // Resolves an abstract type into the specific type of an object.
+    /// Resolves an abstract type into the specific type of an object, based
+    /// on the `__typename` of the object.
     fn resolve_abstract_type<'a>(
         &self,
-        _schema: &'a s::Document,
-        _abstract_type: &s::TypeDefinition,
-        _object_value: &q::Value,
+        schema: &'a s::Document,
+        abstract_type: &s::TypeDefinition,
+        object_value: &q::Value,
     ) -> Option<&'a s::ObjectType> {
-        None
+        let type_name = match object_value {
+            q::Value::Object(object) => match object.get("__typename") {
+                Some(q::Value::String(type_name)) => type_name,
+                _ => return None,
+            },
+            _ => return None,
+        };
+
+        let object_type = match sast::get_named_type(schema, type_name) {
+            Some(s::TypeDefinition::Object(object_type)) => object_type,
+            _ => return None,
+        };
+
+        // The object type must be one of the possible types of the abstract type
+        let is_possible_type = match abstract_type {
+            s::TypeDefinition::Interface(interface_type) => object_type
+                .implements_interfaces
+                .contains(&interface_type.name),
+            s::TypeDefinition::Union(union_type) => union_type.types.contains(type_name),
+            _ => false,
+        };
+
+        if is_possible_type {
+            Some(object_type)
+        } else {
+            None
+        }
     }
 }
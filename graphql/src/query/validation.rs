@@ -0,0 +1,330 @@
+use graphql_parser::query as q;
+use graphql_parser::schema as s;
+use graphql_parser::Pos;
+use std::collections::HashSet;
+
+use graph::prelude::QueryExecutionError;
+
+use introspection::introspection_schema;
+use query::ast as qast;
+use schema::api::named_type_name;
+use schema::ast as sast;
+use values::coercion::coerce_value;
+
+/// Validates an operation of a query against the schema it is run against,
+/// before it is executed. Checks that the fields that are selected exist on
+/// their types, that their arguments exist and have values of the right
+/// type, that fragments exist, can apply where they are spread and don't
+/// spread themselves, and that all variables that are used are defined.
+/// Returns all errors found.
+pub fn validate_query(
+    schema: &s::Document,
+    document: &q::Document,
+    operation: &q::OperationDefinition,
+) -> Result<(), Vec<QueryExecutionError>> {
+    let introspection_schema = introspection_schema();
+    let mut validator = Validator {
+        schema,
+        introspection_schema: &introspection_schema,
+        document,
+        variables: qast::get_variable_definitions(operation)
+            .iter()
+            .map(|variable| &variable.name)
+            .collect(),
+        validated_fragments: HashSet::new(),
+        fragment_path: vec![],
+        errors: vec![],
+    };
+
+    let (root_type, selection_set) = match operation {
+        q::OperationDefinition::Query(query) => {
+            (sast::get_root_query_type(schema), &query.selection_set)
+        }
+        q::OperationDefinition::SelectionSet(selection_set) => {
+            (sast::get_root_query_type(schema), selection_set)
+        }
+        q::OperationDefinition::Subscription(subscription) => (
+            sast::get_root_subscription_type(schema),
+            &subscription.selection_set,
+        ),
+        // Mutations are rejected when the query is executed
+        q::OperationDefinition::Mutation(_) => return Ok(()),
+    };
+
+    if let Some(root_type) = root_type.and_then(|t| sast::get_named_type(schema, &t.name)) {
+        validator.validate_selection_set(root_type, selection_set);
+    }
+
+    if validator.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(validator.errors)
+    }
+}
+
+struct Validator<'a> {
+    schema: &'a s::Document,
+    introspection_schema: &'a s::Document,
+    document: &'a q::Document,
+    /// The variables defined by the operation.
+    variables: HashSet<&'a q::Name>,
+    /// Fragments only need to be validated once, no matter how often they
+    /// are spread.
+    validated_fragments: HashSet<&'a q::Name>,
+    /// The fragments whose selection sets are being validated.
+    fragment_path: Vec<&'a q::Name>,
+    errors: Vec<QueryExecutionError>,
+}
+
+impl<'a> Validator<'a> {
+    /// Looks up a type in the schema, or in the introspection schema for
+    /// the types of introspection fields.
+    fn named_type(&self, name: &s::Name) -> Option<&'a s::TypeDefinition> {
+        sast::get_named_type(self.schema, name)
+            .or_else(|| sast::get_named_type(self.introspection_schema, name))
+    }
+
+    /// Looks up a field of an object or interface type. The root query type
+    /// also has the `__schema` and `__type` introspection fields.
+    fn field_definition(
+        &self,
+        parent_type: &'a s::TypeDefinition,
+        name: &q::Name,
+    ) -> Option<&'a s::Field> {
+        let fields = match parent_type {
+            s::TypeDefinition::Object(t) => &t.fields,
+            s::TypeDefinition::Interface(t) => &t.fields,
+            _ => return None,
+        };
+
+        fields.iter().find(|field| &field.name == name).or_else(|| {
+            match (
+                sast::get_root_query_type(self.schema),
+                sast::get_root_query_type(self.introspection_schema),
+            ) {
+                (Some(query_type), Some(introspection_query_type))
+                    if sast::get_type_name(parent_type) == &query_type.name =>
+                {
+                    sast::get_field_type(introspection_query_type, name)
+                }
+                _ => None,
+            }
+        })
+    }
+
+    /// Returns the names of the object types that values of a type can have.
+    fn possible_types(&self, t: &'a s::TypeDefinition) -> HashSet<&'a s::Name> {
+        match t {
+            s::TypeDefinition::Object(t) => vec![&t.name].into_iter().collect(),
+            s::TypeDefinition::Interface(t) => {
+                sast::get_object_types_implementing(self.schema, &t.name)
+                    .into_iter()
+                    .map(|object_type| &object_type.name)
+                    .collect()
+            }
+            s::TypeDefinition::Union(t) => t.types.iter().collect(),
+            _ => HashSet::new(),
+        }
+    }
+
+    fn validate_selection_set(
+        &mut self,
+        parent_type: &'a s::TypeDefinition,
+        selection_set: &'a q::SelectionSet,
+    ) {
+        for selection in selection_set.items.iter() {
+            match selection {
+                q::Selection::Field(field) => self.validate_field(parent_type, field),
+                q::Selection::FragmentSpread(spread) => {
+                    self.validate_directives(spread.position, &spread.directives);
+
+                    let fragment = match qast::get_fragment(self.document, &spread.fragment_name) {
+                        Some(fragment) => fragment,
+                        None => {
+                            self.errors.push(QueryExecutionError::UnknownFragmentError(
+                                spread.position,
+                                spread.fragment_name.clone(),
+                            ));
+                            continue;
+                        }
+                    };
+
+                    if self.fragment_path.contains(&&fragment.name) {
+                        self.errors.push(QueryExecutionError::FragmentCycleError(
+                            spread.position,
+                            spread.fragment_name.clone(),
+                        ));
+                        continue;
+                    }
+
+                    if let Some(fragment_type) = self.validate_type_condition(
+                        spread.position,
+                        parent_type,
+                        &fragment.type_condition,
+                    ) {
+                        if self.validated_fragments.insert(&fragment.name) {
+                            self.fragment_path.push(&fragment.name);
+                            self.validate_selection_set(fragment_type, &fragment.selection_set);
+                            self.fragment_path.pop();
+                        }
+                    }
+                }
+                q::Selection::InlineFragment(fragment) => {
+                    self.validate_directives(fragment.position, &fragment.directives);
+
+                    let fragment_type = match fragment.type_condition {
+                        Some(ref type_condition) => self.validate_type_condition(
+                            fragment.position,
+                            parent_type,
+                            type_condition,
+                        ),
+                        None => Some(parent_type),
+                    };
+                    if let Some(fragment_type) = fragment_type {
+                        self.validate_selection_set(fragment_type, &fragment.selection_set);
+                    }
+                }
+            }
+        }
+    }
+
+    fn validate_field(&mut self, parent_type: &'a s::TypeDefinition, field: &'a q::Field) {
+        self.validate_directives(field.position, &field.directives);
+
+        if field.name == "__typename" {
+            return;
+        }
+
+        let field_definition = match self.field_definition(parent_type, &field.name) {
+            Some(field_definition) => field_definition,
+            None => {
+                self.errors.push(QueryExecutionError::UnknownFieldError(
+                    field.position,
+                    sast::get_type_name(parent_type).to_owned(),
+                    field.name.clone(),
+                ));
+                return;
+            }
+        };
+
+        for (name, value) in field.arguments.iter() {
+            self.validate_variables(field.position, value);
+
+            let argument = match field_definition
+                .arguments
+                .iter()
+                .find(|argument| &argument.name == name)
+            {
+                Some(argument) => argument,
+                None => {
+                    self.errors.push(QueryExecutionError::UnknownArgumentError(
+                        field.position,
+                        field.name.clone(),
+                        name.clone(),
+                    ));
+                    continue;
+                }
+            };
+
+            // Values with variables are checked once the variables are known
+            if !self.is_valid_value(value, &argument.value_type) {
+                self.errors.push(QueryExecutionError::InvalidArgumentError(
+                    field.position,
+                    name.clone(),
+                    value.clone(),
+                ));
+            }
+        }
+
+        if let Some(field_type) = self.named_type(named_type_name(&field_definition.field_type)) {
+            match field_type {
+                s::TypeDefinition::Object(_)
+                | s::TypeDefinition::Interface(_)
+                | s::TypeDefinition::Union(_) => {
+                    self.validate_selection_set(field_type, &field.selection_set)
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Checks that a fragment with the given type condition can apply to
+    /// values of the parent type, and returns the type of the fragment if so.
+    fn validate_type_condition(
+        &mut self,
+        position: Pos,
+        parent_type: &'a s::TypeDefinition,
+        type_condition: &'a q::TypeCondition,
+    ) -> Option<&'a s::TypeDefinition> {
+        let q::TypeCondition::On(ref name) = type_condition;
+
+        let fragment_type = match self.named_type(name) {
+            Some(fragment_type) => fragment_type,
+            None => {
+                self.errors
+                    .push(QueryExecutionError::UnknownTypeError(position, name.clone()));
+                return None;
+            }
+        };
+
+        let possible_types = self.possible_types(parent_type);
+        if self
+            .possible_types(fragment_type)
+            .iter()
+            .any(|name| possible_types.contains(name))
+        {
+            Some(fragment_type)
+        } else {
+            self.errors.push(QueryExecutionError::FragmentTypeError(
+                position,
+                name.clone(),
+                sast::get_type_name(parent_type).to_owned(),
+            ));
+            None
+        }
+    }
+
+    fn validate_directives(&mut self, position: Pos, directives: &'a [q::Directive]) {
+        for directive in directives {
+            for (_, value) in directive.arguments.iter() {
+                self.validate_variables(position, value);
+            }
+        }
+    }
+
+    /// Checks that all variables used in a value are defined.
+    fn validate_variables(&mut self, position: Pos, value: &'a q::Value) {
+        match value {
+            q::Value::Variable(name) if !self.variables.contains(name) => self
+                .errors
+                .push(QueryExecutionError::UndefinedVariableError(position, name.clone())),
+            q::Value::List(values) => for value in values {
+                self.validate_variables(position, value);
+            },
+            q::Value::Object(fields) => for value in fields.values() {
+                self.validate_variables(position, value);
+            },
+            _ => (),
+        }
+    }
+
+    /// Checks whether a value can be coerced to a type. Values that contain
+    /// variables, and `null` for nullable types, are considered valid.
+    fn is_valid_value(&self, value: &q::Value, value_type: &s::Type) -> bool {
+        match (value, value_type) {
+            (q::Value::Null, s::Type::NonNullType(_)) => false,
+            (q::Value::Null, _) => true,
+            _ if contains_variables(value) => true,
+            _ => coerce_value(value, value_type, &|name: &s::Name| self.named_type(name)).is_some(),
+        }
+    }
+}
+
+fn contains_variables(value: &q::Value) -> bool {
+    match value {
+        q::Value::Variable(_) => true,
+        q::Value::List(values) => values.iter().any(contains_variables),
+        q::Value::Object(fields) => fields.values().any(contains_variables),
+        _ => false,
+    }
+}
@@ -0,0 +1,115 @@
+use graphql_parser::schema::*;
+
+use graph::prelude::Schema;
+
+/// Returns the role required to access a field, as declared with the
+/// `@restricted(role: "...")` directive. Fields without the directive are
+/// public.
+pub fn get_field_role(field: &Field) -> Option<&str> {
+    field
+        .directives
+        .iter()
+        .find(|directive| directive.name == "restricted")
+        .and_then(|directive| {
+            directive
+                .arguments
+                .iter()
+                .find(|(name, _)| name == "role")
+                .and_then(|(_, value)| match value {
+                    Value::String(role) => Some(role.as_str()),
+                    Value::Enum(role) => Some(role.as_str()),
+                    _ => None,
+                })
+        })
+}
+
+/// Returns true if a client with the given role may access the field.
+pub fn is_field_accessible(field: &Field, role: Option<&str>) -> bool {
+    match get_field_role(field) {
+        None => true,
+        Some(required) => role == Some(required),
+    }
+}
+
+/// Returns a copy of the schema with all fields removed that a client with
+/// the given role may not access, or `None` if nothing has to be removed.
+///
+/// Removed fields are indistinguishable from fields that do not exist, both
+/// when querying and when introspecting the schema.
+pub fn restricted_schema(schema: &Schema, role: Option<&str>) -> Option<Schema> {
+    let is_restricted = schema
+        .document
+        .definitions
+        .iter()
+        .flat_map(|definition| type_fields(definition))
+        .any(|field| !is_field_accessible(field, role));
+
+    if !is_restricted {
+        return None;
+    }
+
+    let mut restricted = schema.clone();
+    for definition in restricted.document.definitions.iter_mut() {
+        match definition {
+            Definition::TypeDefinition(TypeDefinition::Object(t)) => {
+                t.fields.retain(|field| is_field_accessible(field, role))
+            }
+            Definition::TypeDefinition(TypeDefinition::Interface(t)) => {
+                t.fields.retain(|field| is_field_accessible(field, role))
+            }
+            _ => {}
+        }
+    }
+    Some(restricted)
+}
+
+fn type_fields(definition: &Definition) -> &[Field] {
+    match definition {
+        Definition::TypeDefinition(TypeDefinition::Object(t)) => &t.fields,
+        Definition::TypeDefinition(TypeDefinition::Interface(t)) => &t.fields,
+        _ => &[],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use graph::prelude::Schema;
+    use graphql_parser;
+
+    use super::restricted_schema;
+    use schema::ast;
+
+    fn schema() -> Schema {
+        Schema {
+            id: String::from("restricted"),
+            document: graphql_parser::parse_schema(
+                "type User { id: ID!, name: String, email: String @restricted(role: \"admin\") }",
+            ).unwrap(),
+        }
+    }
+
+    fn user_fields(schema: &Schema) -> Vec<String> {
+        match ast::get_named_type(&schema.document, &String::from("User")) {
+            Some(graphql_parser::schema::TypeDefinition::Object(t)) => {
+                t.fields.iter().map(|field| field.name.clone()).collect()
+            }
+            _ => panic!("User type not found"),
+        }
+    }
+
+    #[test]
+    fn hides_restricted_fields_from_other_roles() {
+        let schema = schema();
+
+        let public = restricted_schema(&schema, None).expect("Fields should be hidden");
+        assert_eq!(user_fields(&public), vec!["id", "name"]);
+
+        let other = restricted_schema(&schema, Some("user")).expect("Fields should be hidden");
+        assert_eq!(user_fields(&other), vec!["id", "name"]);
+    }
+
+    #[test]
+    fn keeps_restricted_fields_for_their_role() {
+        assert!(restricted_schema(&schema(), Some("admin")).is_none());
+    }
+}
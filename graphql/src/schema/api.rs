@@ -5,11 +5,43 @@ use std::error::Error;
 use std::fmt;
 use std::iter::IntoIterator;
 
+use graph::data::schema::{
+    fulltext_definitions, is_field_derived, is_field_stored, retention_period,
+    FulltextDefinition,
+};
+
+use schema::access;
 use schema::ast;
 
+/// Name of the `Query` field that describes the indexing state of the
+/// subgraph.
+pub(crate) const META_FIELD_NAME: &str = "_meta";
+/// Name of the type of the `_meta` field.
+pub(crate) const META_TYPE_NAME: &str = "_Meta_";
+/// Name of the type of the block that `_meta` reports.
+const BLOCK_TYPE_NAME: &str = "_Block_";
+
+/// Input type of the `block` argument of `Query` fields.
+const BLOCK_HEIGHT_TYPE_NAME: &str = "Block_height";
+
 #[derive(Debug)]
 pub enum APISchemaError {
     TypeExists(String),
+    /// A field marked `@notStored` is non-nullable; holds the type and field name.
+    NonNullNotStoredField(String, String),
+    /// An entity type has a retention period that cannot be parsed; holds the
+    /// type name and the period.
+    InvalidRetentionPeriod(String, String),
+    /// An entity type has an invalid `@fulltext` directive; holds the type
+    /// name and what is wrong with it.
+    InvalidFulltextDefinition(String, String),
+    /// A field has an invalid `@derivedFrom` directive; holds the type and
+    /// field name, and what is wrong with the directive.
+    InvalidDerivedField(String, String, String),
+    /// An entity type has no `id: ID!` field; holds the type name.
+    IdFieldMissing(String),
+    /// A type of the input schema has a name that the API schema uses.
+    ReservedTypeName(String),
 }
 
 impl Error for APISchemaError {
@@ -28,6 +60,34 @@ impl fmt::Display for APISchemaError {
             APISchemaError::TypeExists(s) => {
                 write!(f, "Type \"{}\" already exists in the input schema", s)
             }
+            APISchemaError::NonNullNotStoredField(type_name, field_name) => write!(
+                f,
+                "Field \"{}.{}\" is not stored and must therefore be nullable",
+                type_name, field_name
+            ),
+            APISchemaError::InvalidRetentionPeriod(type_name, period) => write!(
+                f,
+                "Type \"{}\" has an invalid retention period {}, expected e.g. \"30d\"",
+                type_name, period
+            ),
+            APISchemaError::InvalidFulltextDefinition(type_name, reason) => write!(
+                f,
+                "Type \"{}\" has an invalid full-text search: {}",
+                type_name, reason
+            ),
+            APISchemaError::InvalidDerivedField(type_name, field_name, reason) => write!(
+                f,
+                "Field \"{}.{}\" is derived from an invalid field: {}",
+                type_name, field_name, reason
+            ),
+            APISchemaError::IdFieldMissing(type_name) => {
+                write!(f, "Type \"{}\" must have an \"id: ID!\" field", type_name)
+            }
+            APISchemaError::ReservedTypeName(type_name) => write!(
+                f,
+                "Type name \"{}\" is reserved for the API schema",
+                type_name
+            ),
         }
     }
 }
@@ -35,26 +95,296 @@ impl fmt::Display for APISchemaError {
 /// Derives a full-fledged GraphQL API schema from an input schema.
 ///
 /// The input schema should only have type/enum/interface/union definitions
-/// and must not include a root Query type. This Query type is derived,
-/// with all its fields and their input arguments, based on the existing
-/// types.
+/// and must not include root Query or Subscription types. These types are
+/// derived, with all their fields and their input arguments, based on the
+/// existing types.
 pub fn api_schema(input_schema: &Document) -> Result<Document, APISchemaError> {
     let object_types = ast::get_object_type_definitions(input_schema);
     let interface_types = ast::get_interface_type_definitions(input_schema);
+    let union_types = ast::get_union_type_definitions(input_schema);
+
+    validate_not_stored_fields(&object_types)?;
+    validate_derived_fields(input_schema, &object_types)?;
+    validate_retention_periods(&object_types)?;
+    let fulltext_definitions = validate_fulltext_definitions(&object_types)?;
 
     let mut schema = input_schema.clone();
     add_builtin_scalar_types(&mut schema)?;
     add_order_direction_enum(&mut schema);
+    add_page_info_type(&mut schema)?;
+    add_meta_types(&mut schema)?;
+    add_block_height_type(&mut schema)?;
     add_types_for_object_types(&mut schema, &object_types)?;
     add_types_for_interface_types(&mut schema, &interface_types)?;
-    add_query_type(&mut schema, &object_types, &interface_types)?;
+    add_types_for_union_types(&mut schema, input_schema, &union_types)?;
+    add_collection_arguments(&mut schema, &object_types, &interface_types, &union_types);
+    add_connection_types(&mut schema, &object_types)?;
+    add_aggregate_types(&mut schema, &object_types)?;
+    add_histogram_types(&mut schema, &object_types)?;
+    add_query_type(
+        &mut schema,
+        &object_types,
+        &interface_types,
+        &union_types,
+        &fulltext_definitions,
+    )?;
+    add_subscription_type(&mut schema, &object_types, &interface_types, &union_types)?;
 
     Ok(schema)
 }
 
+/// Checks an input schema for the problems that would keep it from being
+/// served, and returns all of them rather than only the first: entity types
+/// without an `id: ID!` field, types whose names the API schema uses, and
+/// invalid `@derivedFrom` directives.
+pub fn validate_schema(input_schema: &Document) -> Result<(), Vec<APISchemaError>> {
+    let object_types = ast::get_object_type_definitions(input_schema);
+    let mut errors = vec![];
+
+    for object_type in object_types.iter() {
+        let has_id = object_type.fields.iter().any(|field| {
+            field.name == "id"
+                && field.field_type
+                    == Type::NonNullType(Box::new(Type::NamedType("ID".to_string())))
+        });
+        if !has_id {
+            errors.push(APISchemaError::IdFieldMissing(object_type.name.clone()));
+        }
+    }
+
+    let reserved_names = reserved_type_names(input_schema);
+    for definition in input_schema.definitions.iter() {
+        let name = match definition {
+            Definition::TypeDefinition(TypeDefinition::Scalar(t)) => &t.name,
+            Definition::TypeDefinition(TypeDefinition::Object(t)) => &t.name,
+            Definition::TypeDefinition(TypeDefinition::Interface(t)) => &t.name,
+            Definition::TypeDefinition(TypeDefinition::Union(t)) => &t.name,
+            Definition::TypeDefinition(TypeDefinition::Enum(t)) => &t.name,
+            Definition::TypeDefinition(TypeDefinition::InputObject(t)) => &t.name,
+            _ => continue,
+        };
+        if reserved_names.contains(name) {
+            errors.push(APISchemaError::ReservedTypeName(name.clone()));
+        }
+    }
+
+    for object_type in object_types.iter() {
+        for field in object_type.fields.iter().filter(|field| is_field_derived(field)) {
+            if let Err(e) = validate_derived_field(input_schema, object_type, field) {
+                errors.push(e);
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Returns the names of the types that `api_schema` adds for the given
+/// input schema.
+fn reserved_type_names(input_schema: &Document) -> Vec<String> {
+    let mut names: Vec<String> = [
+        "Boolean",
+        "ID",
+        "Int",
+        "Float",
+        "String",
+        "Bytes",
+        "BigInt",
+        "BigDecimal",
+        "OrderDirection",
+        "PageInfo",
+        META_TYPE_NAME,
+        BLOCK_TYPE_NAME,
+        BLOCK_HEIGHT_TYPE_NAME,
+        "Query",
+        "Mutation",
+        "Subscription",
+    ].iter()
+        .map(|name| name.to_string())
+        .collect();
+
+    let object_types = ast::get_object_type_definitions(input_schema);
+    let interface_types = ast::get_interface_type_definitions(input_schema);
+    let union_types = ast::get_union_type_definitions(input_schema);
+    for name in object_types
+        .iter()
+        .map(|t| &t.name)
+        .chain(interface_types.iter().map(|t| &t.name))
+        .chain(union_types.iter().map(|t| &t.name))
+    {
+        names.push(format!("{}_orderBy", name));
+        names.push(format!("{}_filter", name));
+    }
+    for object_type in object_types.iter() {
+        for suffix in [
+            "Edge",
+            "Connection",
+            "_aggregate",
+            "_aggregate_fields",
+            "_histogram",
+            "_bucket",
+            "_histogramField",
+        ].iter()
+        {
+            names.push(format!("{}{}", object_type.name, suffix));
+        }
+    }
+    names
+}
+
+/// Ensures that fields that are not stored are nullable, since entities
+/// never have values for them.
+fn validate_not_stored_fields(object_types: &Vec<&ObjectType>) -> Result<(), APISchemaError> {
+    for object_type in object_types {
+        for field in object_type.fields.iter() {
+            if let (false, &Type::NonNullType(_)) = (is_field_stored(field), &field.field_type) {
+                return Err(APISchemaError::NonNullNotStoredField(
+                    object_type.name.clone(),
+                    field.name.clone(),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Ensures that derived fields are lists of an entity type with a field,
+/// named in `@derivedFrom(field: "...")`, that references the type the
+/// derived field belongs to.
+fn validate_derived_fields(
+    schema: &Document,
+    object_types: &Vec<&ObjectType>,
+) -> Result<(), APISchemaError> {
+    for object_type in object_types {
+        for field in object_type.fields.iter().filter(|field| is_field_derived(field)) {
+            validate_derived_field(schema, object_type, field)?;
+        }
+    }
+    Ok(())
+}
+
+/// Validates a single derived field of an object type.
+fn validate_derived_field(
+    schema: &Document,
+    object_type: &ObjectType,
+    field: &Field,
+) -> Result<(), APISchemaError> {
+    let invalid = |reason: String| {
+        APISchemaError::InvalidDerivedField(object_type.name.clone(), field.name.clone(), reason)
+    };
+
+    let derived_from = field
+        .directives
+        .iter()
+        .find(|directive| directive.name == "derivedFrom")
+        .and_then(|directive| {
+            directive
+                .arguments
+                .iter()
+                .find(|(name, _)| name == "field")
+        })
+        .and_then(|(_, value)| match value {
+            Value::String(name) => Some(name),
+            _ => None,
+        })
+        .ok_or_else(|| invalid(String::from("the directive has no field name")))?;
+
+    let is_list = match field.field_type {
+        Type::ListType(_) => true,
+        Type::NonNullType(ref inner) => match **inner {
+            Type::ListType(_) => true,
+            _ => false,
+        },
+        Type::NamedType(_) => false,
+    };
+    if !is_list {
+        return Err(invalid(String::from("derived fields must be lists")));
+    }
+
+    let child_type_name = named_type_name(&field.field_type);
+    let child_fields = match ast::get_named_type(schema, child_type_name) {
+        Some(TypeDefinition::Object(t)) => &t.fields,
+        Some(TypeDefinition::Interface(t)) => &t.fields,
+        _ => return Err(invalid(format!("\"{}\" is not an entity type", child_type_name))),
+    };
+    let child_field = child_fields
+        .iter()
+        .find(|child_field| &child_field.name == derived_from)
+        .ok_or_else(|| {
+            invalid(format!(
+                "\"{}\" has no field \"{}\"",
+                child_type_name, derived_from
+            ))
+        })?;
+
+    let referenced_type_name = named_type_name(&child_field.field_type);
+    if referenced_type_name != &object_type.name
+        && !object_type
+            .implements_interfaces
+            .iter()
+            .any(|name| name == referenced_type_name)
+    {
+        return Err(invalid(format!(
+            "\"{}.{}\" does not reference \"{}\"",
+            child_type_name, derived_from, object_type.name
+        )));
+    }
+    Ok(())
+}
+
+/// Ensures that the retention periods of entity types can be parsed.
+fn validate_retention_periods(object_types: &Vec<&ObjectType>) -> Result<(), APISchemaError> {
+    for object_type in object_types {
+        if let Err(period) = retention_period(object_type) {
+            return Err(APISchemaError::InvalidRetentionPeriod(
+                object_type.name.clone(),
+                period,
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Ensures that the `@fulltext` directives of entity types are valid and
+/// that their names are unique, and returns the full-text searches.
+fn validate_fulltext_definitions(
+    object_types: &Vec<&ObjectType>,
+) -> Result<Vec<FulltextDefinition>, APISchemaError> {
+    let mut definitions: Vec<FulltextDefinition> = vec![];
+    for object_type in object_types {
+        let type_definitions = fulltext_definitions(object_type).map_err(|reason| {
+            APISchemaError::InvalidFulltextDefinition(object_type.name.clone(), reason)
+        })?;
+        for definition in type_definitions {
+            if definitions.iter().any(|other| other.name == definition.name) {
+                return Err(APISchemaError::InvalidFulltextDefinition(
+                    object_type.name.clone(),
+                    format!("the name \"{}\" is used more than once", definition.name),
+                ));
+            }
+            definitions.push(definition);
+        }
+    }
+    Ok(definitions)
+}
+
 /// Adds built-in GraphQL scalar types (`Int`, `String` etc.) to the schema.
 fn add_builtin_scalar_types(schema: &mut Document) -> Result<(), APISchemaError> {
-    for name in ["Boolean", "ID", "Int", "Float", "String", "Bytes", "BigInt"].into_iter() {
+    for name in [
+        "Boolean",
+        "ID",
+        "Int",
+        "Float",
+        "String",
+        "Bytes",
+        "BigInt",
+        "BigDecimal",
+    ].into_iter()
+    {
         match ast::get_named_type(schema, &name.to_string()) {
             None => {
                 let typedef = TypeDefinition::Scalar(ScalarType {
@@ -93,14 +423,365 @@ fn add_order_direction_enum(schema: &mut Document) {
     schema.definitions.push(def);
 }
 
+/// Adds a global `PageInfo` type to the schema, which describes a page of a
+/// connection.
+fn add_page_info_type(schema: &mut Document) -> Result<(), APISchemaError> {
+    if ast::get_named_type(schema, &"PageInfo".to_string()).is_some() {
+        return Err(APISchemaError::TypeExists("PageInfo".to_owned()));
+    }
+
+    let typedef = TypeDefinition::Object(ObjectType {
+        position: Pos::default(),
+        description: None,
+        name: "PageInfo".to_string(),
+        implements_interfaces: vec![],
+        directives: vec![],
+        fields: vec![
+            output_field(
+                "hasNextPage",
+                Type::NonNullType(Box::new(Type::NamedType("Boolean".to_string()))),
+            ),
+            output_field("endCursor", Type::NamedType("String".to_string())),
+        ],
+    });
+    let def = Definition::TypeDefinition(typedef);
+    schema.definitions.push(def);
+    Ok(())
+}
+
+/// Adds the `_Meta_` and `_Block_` types to the schema, which describe the
+/// indexing state of the subgraph returned by the `_meta` query field.
+fn add_meta_types(schema: &mut Document) -> Result<(), APISchemaError> {
+    for name in [META_TYPE_NAME, BLOCK_TYPE_NAME].iter() {
+        if ast::get_named_type(schema, &name.to_string()).is_some() {
+            return Err(APISchemaError::TypeExists(name.to_string()));
+        }
+    }
+
+    let non_null = |name: &str| Type::NonNullType(Box::new(Type::NamedType(name.to_string())));
+    let block_type = TypeDefinition::Object(ObjectType {
+        position: Pos::default(),
+        description: None,
+        name: BLOCK_TYPE_NAME.to_string(),
+        implements_interfaces: vec![],
+        directives: vec![],
+        fields: vec![
+            output_field("number", non_null("Int")),
+            output_field("hash", non_null("Bytes")),
+            output_field("timestamp", non_null("Int")),
+        ],
+    });
+    let meta_type = TypeDefinition::Object(ObjectType {
+        position: Pos::default(),
+        description: None,
+        name: META_TYPE_NAME.to_string(),
+        implements_interfaces: vec![],
+        directives: vec![],
+        fields: vec![
+            output_field("block", Type::NamedType(BLOCK_TYPE_NAME.to_string())),
+            output_field("deployment", non_null("String")),
+            output_field("hasIndexingErrors", non_null("Boolean")),
+        ],
+    });
+    schema.definitions.push(Definition::TypeDefinition(block_type));
+    schema.definitions.push(Definition::TypeDefinition(meta_type));
+    Ok(())
+}
+
+/// Adds the `Block_height` input type to the schema, with which queries
+/// select the block whose entities they see, either by `number` or by
+/// `hash`.
+fn add_block_height_type(schema: &mut Document) -> Result<(), APISchemaError> {
+    if ast::get_named_type(schema, &BLOCK_HEIGHT_TYPE_NAME.to_string()).is_some() {
+        return Err(APISchemaError::TypeExists(BLOCK_HEIGHT_TYPE_NAME.to_owned()));
+    }
+
+    let typedef = TypeDefinition::InputObject(InputObjectType {
+        position: Pos::default(),
+        description: None,
+        name: BLOCK_HEIGHT_TYPE_NAME.to_string(),
+        directives: vec![],
+        fields: vec![
+            input_value(&"hash".to_string(), "", Type::NamedType("Bytes".to_string())),
+            input_value(&"number".to_string(), "", Type::NamedType("Int".to_string())),
+        ],
+    });
+    schema.definitions.push(Definition::TypeDefinition(typedef));
+    Ok(())
+}
+
+/// Adds `*Edge` and `*Connection` types for the given object types to the
+/// schema, e.g. `UserEdge { cursor, node }` and `UserConnection { edges,
+/// pageInfo }`.
+fn add_connection_types(
+    schema: &mut Document,
+    object_types: &Vec<&ObjectType>,
+) -> Result<(), APISchemaError> {
+    for object_type in object_types {
+        let edge_type_name = format!("{}Edge", object_type.name);
+        let connection_type_name = format!("{}Connection", object_type.name);
+
+        for name in [&edge_type_name, &connection_type_name].iter() {
+            if ast::get_named_type(schema, name).is_some() {
+                return Err(APISchemaError::TypeExists(name.to_string()));
+            }
+        }
+
+        let edge_type = TypeDefinition::Object(ObjectType {
+            position: Pos::default(),
+            description: None,
+            name: edge_type_name.clone(),
+            implements_interfaces: vec![],
+            directives: vec![],
+            fields: vec![
+                output_field(
+                    "cursor",
+                    Type::NonNullType(Box::new(Type::NamedType("String".to_string()))),
+                ),
+                output_field(
+                    "node",
+                    Type::NonNullType(Box::new(Type::NamedType(object_type.name.to_owned()))),
+                ),
+            ],
+        });
+
+        // The directive tells the execution which entities the connection is made of
+        let connection_type = TypeDefinition::Object(ObjectType {
+            position: Pos::default(),
+            description: None,
+            name: connection_type_name,
+            implements_interfaces: vec![],
+            directives: vec![Directive {
+                position: Pos::default(),
+                name: "connection".to_string(),
+                arguments: vec![(
+                    "type".to_string(),
+                    Value::String(object_type.name.to_owned()),
+                )],
+            }],
+            fields: vec![
+                output_field(
+                    "edges",
+                    Type::NonNullType(Box::new(Type::ListType(Box::new(Type::NonNullType(
+                        Box::new(Type::NamedType(edge_type_name)),
+                    ))))),
+                ),
+                output_field(
+                    "pageInfo",
+                    Type::NonNullType(Box::new(Type::NamedType("PageInfo".to_string()))),
+                ),
+            ],
+        });
+
+        schema
+            .definitions
+            .push(Definition::TypeDefinition(edge_type));
+        schema
+            .definitions
+            .push(Definition::TypeDefinition(connection_type));
+    }
+    Ok(())
+}
+
+/// Adds `*_aggregate` types for the given object types to the schema, e.g.
+/// `User_aggregate { count, sum, min, max }`. `sum`, `min` and `max` are of
+/// a `User_aggregate_fields` type with a `BigDecimal` field for every numeric
+/// field of the object type; types without numeric fields only get `count`.
+fn add_aggregate_types(
+    schema: &mut Document,
+    object_types: &Vec<&ObjectType>,
+) -> Result<(), APISchemaError> {
+    for object_type in object_types {
+        let aggregate_type_name = format!("{}_aggregate", object_type.name);
+        let fields_type_name = format!("{}_aggregate_fields", object_type.name);
+
+        for name in [&aggregate_type_name, &fields_type_name].iter() {
+            if ast::get_named_type(schema, name).is_some() {
+                return Err(APISchemaError::TypeExists(name.to_string()));
+            }
+        }
+
+        let mut fields = vec![output_field(
+            "count",
+            Type::NonNullType(Box::new(Type::NamedType("Int".to_string()))),
+        )];
+
+        let numeric_fields = aggregatable_fields(&object_type.fields);
+        if !numeric_fields.is_empty() {
+            let fields_type = TypeDefinition::Object(ObjectType {
+                position: Pos::default(),
+                description: None,
+                name: fields_type_name.clone(),
+                implements_interfaces: vec![],
+                directives: vec![],
+                fields: numeric_fields
+                    .iter()
+                    .map(|field| {
+                        output_field(&field.name, Type::NamedType("BigDecimal".to_string()))
+                    })
+                    .collect(),
+            });
+            schema
+                .definitions
+                .push(Definition::TypeDefinition(fields_type));
+
+            for name in ["sum", "min", "max"].iter() {
+                fields.push(output_field(name, Type::NamedType(fields_type_name.clone())));
+            }
+        }
+
+        // The directive tells the execution which entities are aggregated
+        let aggregate_type = TypeDefinition::Object(ObjectType {
+            position: Pos::default(),
+            description: None,
+            name: aggregate_type_name,
+            implements_interfaces: vec![],
+            directives: vec![Directive {
+                position: Pos::default(),
+                name: "aggregate".to_string(),
+                arguments: vec![(
+                    "type".to_string(),
+                    Value::String(object_type.name.to_owned()),
+                )],
+            }],
+            fields,
+        });
+        schema
+            .definitions
+            .push(Definition::TypeDefinition(aggregate_type));
+    }
+    Ok(())
+}
+
+/// Adds `*_histogram` types for the given object types with numeric fields
+/// to the schema, e.g. `User_histogram { buckets }` with a list of
+/// `User_bucket { start, count }`, and a `User_histogramField` enum of the
+/// numeric fields that entities can be bucketed by.
+fn add_histogram_types(
+    schema: &mut Document,
+    object_types: &Vec<&ObjectType>,
+) -> Result<(), APISchemaError> {
+    for object_type in object_types {
+        let numeric_fields = aggregatable_fields(&object_type.fields);
+        if numeric_fields.is_empty() {
+            continue;
+        }
+
+        let histogram_type_name = format!("{}_histogram", object_type.name);
+        let bucket_type_name = format!("{}_bucket", object_type.name);
+        let field_enum_name = format!("{}_histogramField", object_type.name);
+
+        for name in [&histogram_type_name, &bucket_type_name, &field_enum_name].iter() {
+            if ast::get_named_type(schema, name).is_some() {
+                return Err(APISchemaError::TypeExists(name.to_string()));
+            }
+        }
+
+        let field_enum = TypeDefinition::Enum(EnumType {
+            position: Pos::default(),
+            description: None,
+            name: field_enum_name,
+            directives: vec![],
+            values: numeric_fields
+                .iter()
+                .map(|field| EnumValue {
+                    position: Pos::default(),
+                    description: None,
+                    name: field.name.to_owned(),
+                    directives: vec![],
+                })
+                .collect(),
+        });
+
+        let bucket_type = TypeDefinition::Object(ObjectType {
+            position: Pos::default(),
+            description: None,
+            name: bucket_type_name.clone(),
+            implements_interfaces: vec![],
+            directives: vec![],
+            fields: vec![
+                output_field(
+                    "start",
+                    Type::NonNullType(Box::new(Type::NamedType("BigDecimal".to_string()))),
+                ),
+                output_field(
+                    "count",
+                    Type::NonNullType(Box::new(Type::NamedType("Int".to_string()))),
+                ),
+            ],
+        });
+
+        // The directive tells the execution which entities are bucketed
+        let histogram_type = TypeDefinition::Object(ObjectType {
+            position: Pos::default(),
+            description: None,
+            name: histogram_type_name,
+            implements_interfaces: vec![],
+            directives: vec![Directive {
+                position: Pos::default(),
+                name: "histogram".to_string(),
+                arguments: vec![(
+                    "type".to_string(),
+                    Value::String(object_type.name.to_owned()),
+                )],
+            }],
+            fields: vec![output_field(
+                "buckets",
+                Type::NonNullType(Box::new(Type::ListType(Box::new(Type::NonNullType(
+                    Box::new(Type::NamedType(bucket_type_name)),
+                ))))),
+            )],
+        });
+
+        for typedef in vec![field_enum, bucket_type, histogram_type] {
+            schema.definitions.push(Definition::TypeDefinition(typedef));
+        }
+    }
+    Ok(())
+}
+
+/// Returns the fields that `sum`, `min` and `max` aggregate: the filterable
+/// fields with a single `Int`, `Float`, `BigInt` or `BigDecimal` value.
+pub(crate) fn aggregatable_fields(fields: &Vec<Field>) -> Vec<Field> {
+    filterable_fields(fields)
+        .into_iter()
+        .filter(|field| {
+            let field_type = match field.field_type {
+                Type::NonNullType(ref inner) => inner.as_ref(),
+                ref field_type => field_type,
+            };
+            match field_type {
+                Type::NamedType(name) => match name.as_str() {
+                    "Int" | "Float" | "BigInt" | "BigDecimal" => true,
+                    _ => false,
+                },
+                _ => false,
+            }
+        })
+        .collect()
+}
+
+/// Generates a field without arguments.
+fn output_field(name: &str, field_type: Type) -> Field {
+    Field {
+        position: Pos::default(),
+        description: None,
+        name: name.to_string(),
+        arguments: vec![],
+        field_type,
+        directives: vec![],
+    }
+}
+
 /// Adds `*_orderBy` and `*_filter` enum types for the given object types to the schema.
 fn add_types_for_object_types(
     schema: &mut Document,
     object_types: &Vec<&ObjectType>,
 ) -> Result<(), APISchemaError> {
     for object_type in object_types {
-        add_order_by_type(schema, &object_type.name, &object_type.fields)?;
-        add_filter_type(schema, &object_type.name, &object_type.fields)?;
+        let fields = filterable_fields(&object_type.fields);
+        add_order_by_type(schema, &object_type.name, &fields)?;
+        add_filter_type(schema, &object_type.name, &fields)?;
     }
     Ok(())
 }
@@ -111,12 +792,58 @@ fn add_types_for_interface_types(
     interface_types: &Vec<&InterfaceType>,
 ) -> Result<(), APISchemaError> {
     for interface_type in interface_types {
-        add_order_by_type(schema, &interface_type.name, &interface_type.fields)?;
-        add_filter_type(schema, &interface_type.name, &interface_type.fields)?;
+        let fields = filterable_fields(&interface_type.fields);
+        add_order_by_type(schema, &interface_type.name, &fields)?;
+        add_filter_type(schema, &interface_type.name, &fields)?;
     }
     Ok(())
 }
 
+/// Adds `*_orderBy` and `*_filter` types for the given unions to the schema.
+/// Unions only have the fields that all of their members share.
+fn add_types_for_union_types(
+    schema: &mut Document,
+    input_schema: &Document,
+    union_types: &Vec<&UnionType>,
+) -> Result<(), APISchemaError> {
+    for union_type in union_types {
+        let fields = filterable_fields(&union_fields(input_schema, union_type));
+        add_order_by_type(schema, &union_type.name, &fields)?;
+        add_filter_type(schema, &union_type.name, &fields)?;
+    }
+    Ok(())
+}
+
+/// Returns the fields that all members of a union have, with the same type.
+fn union_fields(schema: &Document, union_type: &UnionType) -> Vec<Field> {
+    let member_types = ast::get_union_member_types(schema, union_type);
+    match member_types.split_first() {
+        Some((first, others)) => first
+            .fields
+            .iter()
+            .filter(|field| {
+                others.iter().all(|other| {
+                    ast::get_field_type(other, &field.name)
+                        .map_or(false, |other_field| other_field.field_type == field.field_type)
+                })
+            })
+            .cloned()
+            .collect(),
+        None => vec![],
+    }
+}
+
+/// Returns the fields that are neither `@restricted` nor `@notStored`.
+/// Restricted fields cannot be used for ordering or filtering, as that would
+/// reveal their values, and fields that are not stored have no values.
+fn filterable_fields(fields: &Vec<Field>) -> Vec<Field> {
+    fields
+        .iter()
+        .filter(|field| access::get_field_role(field).is_none() && is_field_stored(field))
+        .cloned()
+        .collect()
+}
+
 /// Adds a `<type_name>_orderBy` enum type for the given fields to the schema.
 fn add_order_by_type(
     schema: &mut Document,
@@ -181,10 +908,37 @@ fn add_filter_type(
 fn field_input_values(schema: &Document, fields: &Vec<Field>) -> Vec<InputValue> {
     fields
         .iter()
-        .flat_map(|field| field_filter_input_values(schema, &field, &field.field_type))
+        .flat_map(|field| {
+            let mut input_values = field_filter_input_values(schema, &field, &field.field_type);
+            input_values.extend(field_child_filter_input_value(schema, &field));
+            input_values
+        })
         .collect()
 }
 
+/// Generates a `<field>_` input value that filters by the entities a
+/// reference field points to, e.g. `owner_: { name: "x" }`. Derived fields
+/// hold no references and references to interfaces can point to entities of
+/// any type, so neither gets one.
+fn field_child_filter_input_value(schema: &Document, field: &Field) -> Option<InputValue> {
+    let is_derived = field
+        .directives
+        .iter()
+        .any(|directive| directive.name == "derivedFrom");
+    if is_derived {
+        return None;
+    }
+
+    match ast::get_named_type(schema, named_type_name(&field.field_type)) {
+        Some(TypeDefinition::Object(t)) => Some(input_value(
+            &format!("{}_", field.name),
+            "",
+            Type::NamedType(format!("{}_filter", t.name)),
+        )),
+        _ => None,
+    }
+}
+
 /// Generates `*_filter` input values for the given field.
 fn field_filter_input_values(
     schema: &Document,
@@ -198,6 +952,9 @@ fn field_filter_input_values(
             match named_type {
                 TypeDefinition::Scalar(ref t) => field_scalar_filter_input_values(schema, field, t),
                 TypeDefinition::Enum(ref t) => field_enum_filter_input_values(schema, field, t),
+                TypeDefinition::Object(_)
+                | TypeDefinition::Interface(_)
+                | TypeDefinition::Union(_) => field_reference_filter_input_values(schema, field),
                 _ => vec![],
             }
         }
@@ -214,6 +971,7 @@ fn field_scalar_filter_input_values(
 ) -> Vec<InputValue> {
     match field_type.name.as_ref() {
         "BigInt" => vec!["", "not", "gt", "lt", "gte", "lte", "in", "not_in"],
+        "BigDecimal" => vec!["", "not", "gt", "lt", "gte", "lte", "in", "not_in"],
         "Boolean" => vec!["", "not", "in", "not_in"],
         "Bytes" => vec!["", "not", "in", "not_in", "contains", "not_contains"],
         "Float" => vec!["", "not", "gt", "lt", "gte", "lte", "in", "not_in"],
@@ -235,6 +993,14 @@ fn field_scalar_filter_input_values(
             "not_starts_with",
             "ends_with",
             "not_ends_with",
+            "contains_nocase",
+            "not_contains_nocase",
+            "starts_with_nocase",
+            "not_starts_with_nocase",
+            "ends_with_nocase",
+            "not_ends_with_nocase",
+            "regex",
+            "not_regex",
         ],
         _ => vec!["", "not"],
     }.into_iter()
@@ -273,13 +1039,47 @@ fn field_enum_filter_input_values(
         .collect()
 }
 
-/// Generates `*_filter` input values for the given list field.
+/// Generates `*_filter` input values for the given reference field, which
+/// compare the IDs of the referenced entities.
+fn field_reference_filter_input_values(_schema: &Document, field: &Field) -> Vec<InputValue> {
+    let id_type = Type::NamedType("ID".to_string());
+    let id_list_type = Type::NonNullType(Box::new(Type::ListType(Box::new(
+        Type::NonNullType(Box::new(id_type.clone())),
+    ))));
+
+    vec![
+        input_value(&field.name, "", id_type.clone()),
+        input_value(&field.name, "not", id_type),
+        input_value(&field.name, "in", id_list_type.clone()),
+        input_value(&field.name, "not_in", id_list_type),
+    ]
+}
+
+/// Generates `*_filter` input values for the given list field. Lists of
+/// references are compared by the IDs of the referenced entities.
 fn field_list_filter_input_values(
-    _schema: &Document,
-    _field: &Field,
-    _field_type: &Type,
+    schema: &Document,
+    field: &Field,
+    field_type: &Type,
 ) -> Vec<InputValue> {
-    vec![]
+    let item_type_name = named_type_name(field_type);
+    let item_type_name = match ast::get_named_type(schema, item_type_name) {
+        Some(TypeDefinition::Scalar(_)) | Some(TypeDefinition::Enum(_)) => {
+            item_type_name.to_owned()
+        }
+        Some(TypeDefinition::Object(_))
+        | Some(TypeDefinition::Interface(_))
+        | Some(TypeDefinition::Union(_)) => "ID".to_string(),
+        _ => return vec![],
+    };
+    let list_type = Type::ListType(Box::new(Type::NonNullType(Box::new(Type::NamedType(
+        item_type_name,
+    )))));
+
+    vec!["", "not", "contains", "not_contains"]
+        .into_iter()
+        .map(|filter_type| input_value(&field.name, filter_type, list_type.clone()))
+        .collect()
 }
 
 /// Generates a `*_filter` input value for the given field name, suffix and value type.
@@ -298,28 +1098,162 @@ fn input_value(name: &Name, suffix: &'static str, value_type: Type) -> InputValu
     }
 }
 
-/// Adds a root `Query` object type to the schema.
+/// Adds pagination and ordering arguments to all fields of object and
+/// interface types that return a list of entities, e.g. `User.friends`.
+fn add_collection_arguments(
+    schema: &mut Document,
+    object_types: &Vec<&ObjectType>,
+    interface_types: &Vec<&InterfaceType>,
+    union_types: &Vec<&UnionType>,
+) {
+    let entity_type_names: Vec<Name> = object_types
+        .iter()
+        .map(|t| t.name.to_owned())
+        .chain(interface_types.iter().map(|t| t.name.to_owned()))
+        .chain(union_types.iter().map(|t| t.name.to_owned()))
+        .collect();
+
+    for definition in schema.definitions.iter_mut() {
+        let fields = match definition {
+            Definition::TypeDefinition(TypeDefinition::Object(t)) => &mut t.fields,
+            Definition::TypeDefinition(TypeDefinition::Interface(t)) => &mut t.fields,
+            _ => continue,
+        };
+
+        for field in fields.iter_mut() {
+            let item_type_name = match list_item_type_name(&field.field_type) {
+                Some(name) if entity_type_names.contains(name) => name.to_owned(),
+                _ => continue,
+            };
+
+            for argument in collection_arguments(&item_type_name) {
+                if !field.arguments.iter().any(|arg| arg.name == argument.name) {
+                    field.arguments.push(argument);
+                }
+            }
+        }
+    }
+}
+
+/// Returns the name of the item type if the type is a list.
+fn list_item_type_name(field_type: &Type) -> Option<&Name> {
+    match field_type {
+        Type::NonNullType(t) => list_item_type_name(t),
+        Type::ListType(t) => Some(named_type_name(t)),
+        Type::NamedType(_) => None,
+    }
+}
+
+/// Returns the name of the type that a (possibly list or non-null) type wraps.
+pub(crate) fn named_type_name(field_type: &Type) -> &Name {
+    match field_type {
+        Type::NamedType(name) => name,
+        Type::NonNullType(t) | Type::ListType(t) => named_type_name(t),
+    }
+}
+
+/// Arguments that all fields returning a list of entities accept.
+fn collection_arguments(type_name: &Name) -> Vec<InputValue> {
+    vec![
+        input_value(&"skip".to_string(), "", Type::NamedType("Int".to_string())),
+        input_value(&"first".to_string(), "", Type::NamedType("Int".to_string())),
+        input_value(
+            &"orderBy".to_string(),
+            "",
+            Type::NamedType(format!("{}_orderBy", type_name)),
+        ),
+        input_value(
+            &"orderDirection".to_string(),
+            "",
+            Type::NamedType("OrderDirection".to_string()),
+        ),
+    ]
+}
+
+/// Adds a root `Query` object type to the schema. Its entity fields take a
+/// `block` argument that selects the block whose entities the query sees,
+/// and it has a `<name>Search` field for every full-text search.
 fn add_query_type(
     schema: &mut Document,
     object_types: &Vec<&ObjectType>,
     interface_types: &Vec<&InterfaceType>,
+    union_types: &Vec<&UnionType>,
+    fulltext_definitions: &[FulltextDefinition],
+) -> Result<(), APISchemaError> {
+    let mut fields = root_fields(schema, object_types, interface_types, union_types);
+    for field in fields.iter_mut() {
+        field.arguments.push(input_value(
+            &"block".to_string(),
+            "",
+            Type::NamedType(BLOCK_HEIGHT_TYPE_NAME.to_string()),
+        ));
+    }
+    for definition in fulltext_definitions {
+        let field = search_field(definition);
+        if fields.iter().any(|other| other.name == field.name) {
+            return Err(APISchemaError::InvalidFulltextDefinition(
+                definition.entity.clone(),
+                format!("the query field \"{}\" already exists", field.name),
+            ));
+        }
+        fields.push(field);
+    }
+    fields.push(output_field(
+        META_FIELD_NAME,
+        Type::NamedType(META_TYPE_NAME.to_string()),
+    ));
+    add_root_type(schema, "Query", fields)
+}
+
+/// Adds a root `Subscription` object type to the schema, with the same
+/// fields as the `Query` type.
+fn add_subscription_type(
+    schema: &mut Document,
+    object_types: &Vec<&ObjectType>,
+    interface_types: &Vec<&InterfaceType>,
+    union_types: &Vec<&UnionType>,
+) -> Result<(), APISchemaError> {
+    let fields = root_fields(schema, object_types, interface_types, union_types);
+    add_root_type(schema, "Subscription", fields)
+}
+
+/// Generates the fields for querying all object, interface and union types
+/// that root types have.
+fn root_fields(
+    schema: &Document,
+    object_types: &Vec<&ObjectType>,
+    interface_types: &Vec<&InterfaceType>,
+    union_types: &Vec<&UnionType>,
+) -> Vec<Field> {
+    object_types
+        .iter()
+        .map(|t| &t.name)
+        .chain(interface_types.iter().map(|t| &t.name))
+        .chain(union_types.iter().map(|t| &t.name))
+        .flat_map(|name| query_fields_for_type(schema, name))
+        .chain(object_types.iter().map(|t| connection_field_for_type(&t.name)))
+        .chain(object_types.iter().map(|t| aggregate_field_for_type(&t.name)))
+        .chain(object_types.iter().filter_map(|t| histogram_field_for_type(t)))
+        .collect()
+}
+
+/// Adds a root object type with the given fields to the schema.
+fn add_root_type(
+    schema: &mut Document,
+    type_name: &str,
+    fields: Vec<Field>,
 ) -> Result<(), APISchemaError> {
-    if ast::get_named_type(schema, &"Query".to_string()).is_some() {
-        return Err(APISchemaError::TypeExists("Query".to_owned()));
+    if ast::get_named_type(schema, &type_name.to_string()).is_some() {
+        return Err(APISchemaError::TypeExists(type_name.to_owned()));
     }
 
     let typedef = TypeDefinition::Object(ObjectType {
         position: Pos::default(),
         description: None,
-        name: "Query".to_string(),
+        name: type_name.to_string(),
         implements_interfaces: vec![],
         directives: vec![],
-        fields: object_types
-            .iter()
-            .map(|t| &t.name)
-            .chain(interface_types.iter().map(|t| &t.name))
-            .flat_map(|name| query_fields_for_type(schema, name))
-            .collect(),
+        fields,
     });
     let def = Definition::TypeDefinition(typedef);
     schema.definitions.push(def);
@@ -327,12 +1261,20 @@ fn add_query_type(
 }
 
 /// Generates `Query` fields for the given type name (e.g. `users` and `user`).
+/// Types whose name has no distinct plural (e.g. `Series`) get a
+/// `<name>_collection` field instead, so that both fields exist.
 fn query_fields_for_type(_schema: &Document, type_name: &Name) -> Vec<Field> {
+    let singular_name = type_name.as_str().to_camel_case();
+    let mut plural_name = type_name.to_plural().to_camel_case();
+    if plural_name == singular_name {
+        plural_name = format!("{}_collection", singular_name);
+    }
+
     vec![
         Field {
             position: Pos::default(),
             description: None,
-            name: type_name.as_str().to_camel_case(),
+            name: singular_name,
             arguments: vec![InputValue {
                 position: Pos::default(),
                 description: None,
@@ -347,7 +1289,7 @@ fn query_fields_for_type(_schema: &Document, type_name: &Name) -> Vec<Field> {
         Field {
             position: Pos::default(),
             description: None,
-            name: type_name.to_plural().to_camel_case(),
+            name: plural_name,
             arguments: vec![
                 input_value(&"skip".to_string(), "", Type::NamedType("Int".to_string())),
                 input_value(&"first".to_string(), "", Type::NamedType("Int".to_string())),
@@ -378,11 +1320,124 @@ fn query_fields_for_type(_schema: &Document, type_name: &Name) -> Vec<Field> {
     ]
 }
 
+/// Generates the `Query` field of a full-text search (e.g. `bandSearch`),
+/// which returns the entities that match the `text` best first.
+fn search_field(definition: &FulltextDefinition) -> Field {
+    Field {
+        position: Pos::default(),
+        description: None,
+        name: format!("{}Search", definition.name),
+        arguments: vec![
+            input_value(
+                &"text".to_string(),
+                "",
+                Type::NonNullType(Box::new(Type::NamedType("String".to_string()))),
+            ),
+            input_value(&"first".to_string(), "", Type::NamedType("Int".to_string())),
+            input_value(&"skip".to_string(), "", Type::NamedType("Int".to_string())),
+        ],
+        field_type: Type::NonNullType(Box::new(Type::ListType(Box::new(Type::NonNullType(
+            Box::new(Type::NamedType(definition.entity.to_owned())),
+        ))))),
+        directives: vec![],
+    }
+}
+
+/// Generates a `Query` field for paging through the entities of the given
+/// type with cursors (e.g. `usersConnection`).
+fn connection_field_for_type(type_name: &Name) -> Field {
+    Field {
+        position: Pos::default(),
+        description: None,
+        name: format!("{}Connection", type_name.to_plural().to_camel_case()),
+        arguments: vec![
+            input_value(&"first".to_string(), "", Type::NamedType("Int".to_string())),
+            input_value(&"after".to_string(), "", Type::NamedType("String".to_string())),
+            input_value(
+                &"orderDirection".to_string(),
+                "",
+                Type::NamedType("OrderDirection".to_string()),
+            ),
+            input_value(
+                &"where".to_string(),
+                "",
+                Type::NamedType(format!("{}_filter", type_name)),
+            ),
+        ],
+        field_type: Type::NonNullType(Box::new(Type::NamedType(format!(
+            "{}Connection",
+            type_name
+        )))),
+        directives: vec![],
+    }
+}
+
+/// Generates a `Query` field for aggregating the entities of the given type
+/// (e.g. `usersAggregate`).
+fn aggregate_field_for_type(type_name: &Name) -> Field {
+    Field {
+        position: Pos::default(),
+        description: None,
+        name: format!("{}Aggregate", type_name.to_plural().to_camel_case()),
+        arguments: vec![input_value(
+            &"where".to_string(),
+            "",
+            Type::NamedType(format!("{}_filter", type_name)),
+        )],
+        field_type: Type::NonNullType(Box::new(Type::NamedType(format!(
+            "{}_aggregate",
+            type_name
+        )))),
+        directives: vec![],
+    }
+}
+
+/// Generates a `Query` field for counting the entities of the given type by
+/// ranges of a numeric field (e.g. `usersHistogram`), for types that have
+/// numeric fields.
+fn histogram_field_for_type(object_type: &ObjectType) -> Option<Field> {
+    if aggregatable_fields(&object_type.fields).is_empty() {
+        return None;
+    }
+
+    let type_name = &object_type.name;
+    Some(Field {
+        position: Pos::default(),
+        description: None,
+        name: format!("{}Histogram", type_name.to_plural().to_camel_case()),
+        arguments: vec![
+            input_value(
+                &"field".to_string(),
+                "",
+                Type::NonNullType(Box::new(Type::NamedType(format!(
+                    "{}_histogramField",
+                    type_name
+                )))),
+            ),
+            input_value(
+                &"size".to_string(),
+                "",
+                Type::NonNullType(Box::new(Type::NamedType("BigDecimal".to_string()))),
+            ),
+            input_value(
+                &"where".to_string(),
+                "",
+                Type::NamedType(format!("{}_filter", type_name)),
+            ),
+        ],
+        field_type: Type::NonNullType(Box::new(Type::NamedType(format!(
+            "{}_histogram",
+            type_name
+        )))),
+        directives: vec![],
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use graphql_parser::schema::*;
 
-    use super::api_schema;
+    use super::{api_schema, validate_schema};
     use schema::ast;
 
     #[test]
@@ -428,6 +1483,118 @@ mod tests {
             .expect("Root Query type is missing in API schema");
     }
 
+    #[test]
+    fn api_schema_contains_meta_field_on_query_type() {
+        let input_schema =
+            parse_schema("type User { id: ID! }").expect("Failed to parse input schema");
+        let schema = api_schema(&input_schema).expect("Failed to derive API schema");
+
+        let meta_field = match ast::get_named_type(&schema, &"Query".to_string()) {
+            Some(TypeDefinition::Object(t)) => ast::get_field_type(t, &"_meta".to_string()),
+            _ => None,
+        }.expect("\"_meta\" field is missing on Query type");
+        assert_eq!(meta_field.field_type, Type::NamedType("_Meta_".to_string()));
+
+        let block_field = match ast::get_named_type(&schema, &"_Meta_".to_string()) {
+            Some(TypeDefinition::Object(t)) => ast::get_field_type(t, &"block".to_string()),
+            _ => None,
+        }.expect("\"block\" field is missing on _Meta_ type");
+        assert_eq!(block_field.field_type, Type::NamedType("_Block_".to_string()));
+        assert!(ast::get_named_type(&schema, &"_Block_".to_string()).is_some());
+    }
+
+    #[test]
+    fn api_schema_contains_block_argument_on_query_fields_only() {
+        let input_schema =
+            parse_schema("type User { id: ID! }").expect("Failed to parse input schema");
+        let schema = api_schema(&input_schema).expect("Failed to derive API schema");
+
+        let block_height = match ast::get_named_type(&schema, &"Block_height".to_string()) {
+            Some(TypeDefinition::InputObject(t)) => t,
+            _ => panic!("Block_height type is missing in derived API schema"),
+        };
+        assert_eq!(
+            block_height
+                .fields
+                .iter()
+                .map(|field| field.name.to_owned())
+                .collect::<Vec<String>>(),
+            vec!["hash", "number"]
+        );
+
+        let has_block_argument = |type_name: &str, field_name: &str| {
+            match ast::get_named_type(&schema, &type_name.to_string()) {
+                Some(TypeDefinition::Object(t)) => ast::get_field_type(t, &field_name.to_string()),
+                _ => None,
+            }.expect("Field is missing on root type")
+                .arguments
+                .iter()
+                .any(|argument| argument.name == "block")
+        };
+        assert!(has_block_argument("Query", "user"));
+        assert!(has_block_argument("Query", "users"));
+        assert!(!has_block_argument("Query", "_meta"));
+        assert!(!has_block_argument("Subscription", "users"));
+    }
+
+    #[test]
+    fn api_schema_contains_search_fields_for_fulltext_definitions() {
+        let input_schema = parse_schema(
+            "type Band @fulltext(name: \"band\", fields: [\"name\"]) { id: ID!, name: String! }",
+        ).expect("Failed to parse input schema");
+        let schema = api_schema(&input_schema).expect("Failed to derive API schema");
+
+        let search_field = match ast::get_named_type(&schema, &"Query".to_string()) {
+            Some(TypeDefinition::Object(t)) => ast::get_field_type(t, &"bandSearch".to_string()),
+            _ => None,
+        }.expect("\"bandSearch\" field is missing on Query type");
+        assert_eq!(
+            search_field
+                .arguments
+                .iter()
+                .map(|argument| argument.name.to_owned())
+                .collect::<Vec<String>>(),
+            vec!["text", "first", "skip"]
+        );
+
+        for invalid_schema in [
+            "type Band @fulltext(name: \"band\", fields: [\"founded\"]) { id: ID!, founded: Int }",
+            "type Band @fulltext(name: \"band\", language: \"klingon\", fields: [\"name\"]) \
+             { id: ID!, name: String! }",
+            "type Band @fulltext(name: \"band\", fields: [\"name\"]) { id: ID!, name: String! } \
+             type Song @fulltext(name: \"band\", fields: [\"title\"]) { id: ID!, title: String! }",
+        ].iter()
+        {
+            let input_schema = parse_schema(invalid_schema).expect("Failed to parse input schema");
+            assert!(api_schema(&input_schema).is_err());
+        }
+    }
+
+    #[test]
+    fn api_schema_contains_subscription_type() {
+        let input_schema =
+            parse_schema("type User { id: ID! }").expect("Failed to parse input schema");
+        let schema = api_schema(&input_schema).expect("Failed to derive API schema");
+        let subscription_type = match ast::get_named_type(&schema, &"Subscription".to_string()) {
+            Some(TypeDefinition::Object(t)) => t,
+            _ => panic!("Root Subscription type is missing in API schema"),
+        };
+
+        let field_names: Vec<&Name> = subscription_type
+            .fields
+            .iter()
+            .map(|field| &field.name)
+            .collect();
+        assert_eq!(
+            field_names,
+            [
+                &"user".to_string(),
+                &"users".to_string(),
+                &"usersConnection".to_string()
+            ]
+        );
+    }
+
     #[test]
     fn api_schema_contains_field_order_by_enum() {
         let input_schema = parse_schema("type User { id: ID!, name: String! }")
@@ -446,6 +1613,91 @@ mod tests {
         assert_eq!(values, [&"id".to_string(), &"name".to_string()]);
     }
 
+    #[test]
+    fn api_schema_leaves_out_fields_that_are_not_stored() {
+        let input_schema = parse_schema("type Tx { id: ID!, input: Bytes @notStored }")
+            .expect("Failed to parse input schema");
+        let schema = api_schema(&input_schema).expect("Failed to derived API schema");
+
+        let order_by = match ast::get_named_type(&schema, &"Tx_orderBy".to_string()) {
+            Some(TypeDefinition::Enum(t)) => t,
+            _ => panic!("Tx_orderBy type is missing in derived API schema"),
+        };
+        let values: Vec<&Name> = order_by.values.iter().map(|value| &value.name).collect();
+        assert_eq!(values, [&"id".to_string()]);
+
+        let input_schema = parse_schema("type Tx { id: ID!, input: Bytes! @notStored }")
+            .expect("Failed to parse input schema");
+        assert!(api_schema(&input_schema).is_err());
+    }
+
+    #[test]
+    fn api_schema_rejects_invalid_derived_fields() {
+        let input_schema = parse_schema(
+            "type Band { id: ID!, members: [Musician!]! @derivedFrom(field: \"bands\") } \
+             type Musician { id: ID!, bands: [Band!]! }",
+        ).expect("Failed to parse input schema");
+        assert!(api_schema(&input_schema).is_ok());
+
+        for invalid_schema in [
+            "type Band { id: ID!, members: [Musician!]! @derivedFrom } \
+             type Musician { id: ID!, bands: [Band!]! }",
+            "type Band { id: ID!, leader: Musician @derivedFrom(field: \"bands\") } \
+             type Musician { id: ID!, bands: [Band!]! }",
+            "type Band { id: ID!, members: [Musician!]! @derivedFrom(field: \"groups\") } \
+             type Musician { id: ID!, bands: [Band!]! }",
+            "type Band { id: ID!, members: [Musician!]! @derivedFrom(field: \"name\") } \
+             type Musician { id: ID!, name: String! }",
+        ].iter()
+        {
+            let input_schema = parse_schema(invalid_schema).expect("Failed to parse input schema");
+            assert!(api_schema(&input_schema).is_err());
+        }
+    }
+
+    #[test]
+    fn validate_schema_reports_all_problems() {
+        let input_schema = parse_schema(
+            "type Band { id: ID!, members: [Musician!]! @derivedFrom(field: \"bands\") } \
+             type Musician { id: ID!, bands: [Band!]! }",
+        ).expect("Failed to parse input schema");
+        assert!(validate_schema(&input_schema).is_ok());
+
+        let input_schema = parse_schema(
+            "type Band { name: String!, members: [Musician!]! @derivedFrom(field: \"groups\") } \
+             type Musician { id: String!, bands: [Band!]! } \
+             type PageInfo { id: ID! } \
+             enum Band_orderBy { name }",
+        ).expect("Failed to parse input schema");
+        let errors: Vec<String> = validate_schema(&input_schema)
+            .expect_err("Invalid schema passed validation")
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                "Type \"Band\" must have an \"id: ID!\" field",
+                "Type \"Musician\" must have an \"id: ID!\" field",
+                "Type name \"PageInfo\" is reserved for the API schema",
+                "Type name \"Band_orderBy\" is reserved for the API schema",
+                "Field \"Band.members\" is derived from an invalid field: \
+                 \"Musician\" has no field \"groups\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn api_schema_rejects_invalid_retention_periods() {
+        let input_schema = parse_schema("type Snapshot @entity(retention: \"30d\") { id: ID! }")
+            .expect("Failed to parse input schema");
+        assert!(api_schema(&input_schema).is_ok());
+
+        let input_schema = parse_schema("type Snapshot @entity(retention: \"a month\") { id: ID! }")
+            .expect("Failed to parse input schema");
+        assert!(api_schema(&input_schema).is_err());
+    }
+
     #[test]
     fn api_schema_contains_object_type_filter_enum() {
         let input_schema = parse_schema("type User { id: ID!, name: String! }")
@@ -488,13 +1740,67 @@ mod tests {
                 "name_starts_with",
                 "name_not_starts_with",
                 "name_ends_with",
-                "name_not_ends_with"
+                "name_not_ends_with",
+                "name_contains_nocase",
+                "name_not_contains_nocase",
+                "name_starts_with_nocase",
+                "name_not_starts_with_nocase",
+                "name_ends_with_nocase",
+                "name_not_ends_with_nocase",
+                "name_regex",
+                "name_not_regex"
             ].iter()
                 .map(|name| name.to_string())
                 .collect::<Vec<String>>()
         );
     }
 
+    #[test]
+    fn api_schema_contains_reference_and_list_filters() {
+        let input_schema = parse_schema(
+            "type User { id: ID!, nicknames: [String!]!, friends: [User!]!, bestFriend: User }",
+        ).expect("Failed to parse input schema");
+        let schema = api_schema(&input_schema).expect("Failed to derived API schema");
+
+        let filter_type = match ast::get_named_type(&schema, &"User_filter".to_string()) {
+            Some(TypeDefinition::InputObject(t)) => Some(t),
+            _ => None,
+        }.expect("User_filter type is missing in derived API schema");
+
+        let field_type = |name: &str| {
+            filter_type
+                .fields
+                .iter()
+                .find(|field| field.name == name)
+                .map(|field| field.value_type.clone())
+                .expect(format!("Filter field \"{}\" is missing", name).as_str())
+        };
+        let id_list = Type::ListType(Box::new(Type::NonNullType(Box::new(Type::NamedType(
+            "ID".to_string(),
+        )))));
+
+        assert_eq!(field_type("bestFriend"), Type::NamedType("ID".to_string()));
+        assert_eq!(
+            field_type("bestFriend_in"),
+            Type::NonNullType(Box::new(id_list.clone()))
+        );
+        assert_eq!(field_type("friends_contains"), id_list);
+        assert_eq!(
+            field_type("bestFriend_"),
+            Type::NamedType("User_filter".to_string())
+        );
+        assert_eq!(
+            field_type("friends_"),
+            Type::NamedType("User_filter".to_string())
+        );
+        assert_eq!(
+            field_type("nicknames_not_contains"),
+            Type::ListType(Box::new(Type::NonNullType(Box::new(Type::NamedType(
+                "String".to_string()
+            )))))
+        );
+    }
+
     #[test]
     fn api_schema_contains_object_fields_on_query_type() {
         let input_schema = parse_schema(
@@ -521,7 +1827,7 @@ mod tests {
                 .iter()
                 .map(|input_value| input_value.name.to_owned())
                 .collect::<Vec<String>>(),
-            vec!["id".to_string()],
+            vec!["id".to_string(), "block".to_string()],
         );
 
         let user_plural_field = match query_type {
@@ -551,6 +1857,7 @@ mod tests {
                 "orderBy",
                 "orderDirection",
                 "where",
+                "block",
             ].into_iter()
                 .map(|name| name.to_string())
                 .collect::<Vec<String>>()
@@ -581,6 +1888,201 @@ mod tests {
         );
     }
 
+    #[test]
+    fn api_schema_contains_singular_and_plural_fields_for_uncountable_names() {
+        let input_schema = parse_schema("type Series { id: ID!, title: String! }")
+            .expect("Failed to parse input schema");
+        let schema = api_schema(&input_schema).expect("Failed to derive API schema");
+
+        let query_type = match ast::get_named_type(&schema, &"Query".to_string()) {
+            Some(TypeDefinition::Object(t)) => t,
+            _ => panic!("Query type is missing in derived API schema"),
+        };
+
+        let singular_field = ast::get_field_type(query_type, &"series".to_string())
+            .expect("\"series\" field is missing on Query type");
+        assert_eq!(singular_field.field_type, Type::NamedType("Series".to_string()));
+        assert_eq!(singular_field.arguments[0].name, "id");
+
+        // The plural field doesn't shadow the singular one
+        let plural_fields: Vec<_> = query_type
+            .fields
+            .iter()
+            .filter(|field| {
+                field.field_type
+                    == Type::NonNullType(Box::new(Type::ListType(Box::new(Type::NonNullType(
+                        Box::new(Type::NamedType("Series".to_string())),
+                    )))))
+            })
+            .collect();
+        assert_eq!(plural_fields.len(), 1);
+        assert_ne!(plural_fields[0].name, "series");
+    }
+
+    #[test]
+    fn api_schema_contains_pagination_and_ordering_arguments_on_collection_fields() {
+        let input_schema = parse_schema(
+            "type User { id: ID!, name: String!, nicknames: [String!], friends: [User!]! }",
+        ).expect("Failed to parse input schema");
+        let schema = api_schema(&input_schema).expect("Failed to derive API schema");
+
+        let user_type = match ast::get_named_type(&schema, &"User".to_string()) {
+            Some(TypeDefinition::Object(t)) => t,
+            _ => panic!("User type is missing in derived API schema"),
+        };
+
+        let argument_names = |name: &str| {
+            ast::get_field_type(user_type, &name.to_string())
+                .expect("Field is missing on User type")
+                .arguments
+                .iter()
+                .map(|input_value| input_value.name.to_owned())
+                .collect::<Vec<String>>()
+        };
+
+        assert_eq!(
+            argument_names("friends"),
+            vec!["skip", "first", "orderBy", "orderDirection"]
+        );
+        assert_eq!(
+            ast::get_field_type(user_type, &"friends".to_string())
+                .unwrap()
+                .arguments[2]
+                .value_type,
+            Type::NamedType("User_orderBy".to_string())
+        );
+        assert!(argument_names("nicknames").is_empty());
+        assert!(argument_names("name").is_empty());
+    }
+
+    #[test]
+    fn api_schema_contains_connection_types_and_fields() {
+        let input_schema = parse_schema("type User { id: ID!, name: String! }")
+            .expect("Failed to parse input schema");
+        let schema = api_schema(&input_schema).expect("Failed to derive API schema");
+
+        let connection_type = match ast::get_named_type(&schema, &"UserConnection".to_string()) {
+            Some(TypeDefinition::Object(t)) => t,
+            _ => panic!("UserConnection type is missing in derived API schema"),
+        };
+        let node_type = ast::get_connection_node_type(&schema, connection_type)
+            .expect("UserConnection is not a connection");
+        assert_eq!(node_type.name, "User");
+
+        assert!(ast::get_named_type(&schema, &"UserEdge".to_string()).is_some());
+        assert!(ast::get_named_type(&schema, &"PageInfo".to_string()).is_some());
+
+        let query_type = ast::get_named_type(&schema, &"Query".to_string())
+            .expect("Query type is missing in derived API schema");
+        let connection_field = match query_type {
+            TypeDefinition::Object(t) => ast::get_field_type(t, &"usersConnection".to_string()),
+            _ => None,
+        }.expect("\"usersConnection\" field is missing on Query type");
+
+        assert_eq!(
+            connection_field
+                .arguments
+                .iter()
+                .map(|input_value| input_value.name.to_owned())
+                .collect::<Vec<String>>(),
+            vec!["first", "after", "orderDirection", "where", "block"]
+        );
+    }
+
+    #[test]
+    fn api_schema_contains_aggregate_types_and_fields() {
+        let input_schema = parse_schema(
+            "type User { id: ID!, name: String!, age: Int!, balance: BigInt, scores: [Int!] } \
+             type Tag { id: ID!, name: String! }",
+        ).expect("Failed to parse input schema");
+        let schema = api_schema(&input_schema).expect("Failed to derive API schema");
+
+        let object_type = |name: &str| match ast::get_named_type(&schema, &name.to_string()) {
+            Some(TypeDefinition::Object(t)) => t,
+            _ => panic!("{} type is missing in derived API schema", name),
+        };
+        let field_names = |name: &str| {
+            object_type(name)
+                .fields
+                .iter()
+                .map(|field| field.name.to_owned())
+                .collect::<Vec<String>>()
+        };
+
+        let entity_type = ast::get_aggregate_entity_type(&schema, object_type("User_aggregate"))
+            .expect("User_aggregate is not an aggregate type");
+        assert_eq!(entity_type.name, "User");
+        assert_eq!(field_names("User_aggregate"), vec!["count", "sum", "min", "max"]);
+        assert_eq!(field_names("User_aggregate_fields"), vec!["age", "balance"]);
+
+        // Types without numeric fields can only be counted
+        assert_eq!(field_names("Tag_aggregate"), vec!["count"]);
+        assert!(ast::get_named_type(&schema, &"Tag_aggregate_fields".to_string()).is_none());
+
+        let aggregate_field =
+            ast::get_field_type(object_type("Query"), &"usersAggregate".to_string())
+                .expect("\"usersAggregate\" field is missing on Query type");
+        assert_eq!(
+            aggregate_field.field_type,
+            Type::NonNullType(Box::new(Type::NamedType("User_aggregate".to_string())))
+        );
+        assert_eq!(
+            aggregate_field
+                .arguments
+                .iter()
+                .map(|input_value| input_value.name.to_owned())
+                .collect::<Vec<String>>(),
+            vec!["where", "block"]
+        );
+    }
+
+    #[test]
+    fn api_schema_contains_histogram_types_and_fields() {
+        let input_schema = parse_schema(
+            "type User { id: ID!, name: String!, age: Int!, balance: BigInt } \
+             type Tag { id: ID!, name: String! }",
+        ).expect("Failed to parse input schema");
+        let schema = api_schema(&input_schema).expect("Failed to derive API schema");
+
+        let object_type = |name: &str| match ast::get_named_type(&schema, &name.to_string()) {
+            Some(TypeDefinition::Object(t)) => t,
+            _ => panic!("{} type is missing in derived API schema", name),
+        };
+
+        let entity_type = ast::get_histogram_entity_type(&schema, object_type("User_histogram"))
+            .expect("User_histogram is not a histogram type");
+        assert_eq!(entity_type.name, "User");
+        let bucket_fields: Vec<_> = object_type("User_bucket")
+            .fields
+            .iter()
+            .map(|field| field.name.to_owned())
+            .collect();
+        assert_eq!(bucket_fields, vec!["start", "count"]);
+
+        let field_enum = match ast::get_named_type(&schema, &"User_histogramField".to_string()) {
+            Some(TypeDefinition::Enum(t)) => t,
+            _ => panic!("User_histogramField type is missing in derived API schema"),
+        };
+        let values: Vec<_> = field_enum.values.iter().map(|value| &value.name).collect();
+        assert_eq!(values, vec!["age", "balance"]);
+
+        let histogram_field =
+            ast::get_field_type(object_type("Query"), &"usersHistogram".to_string())
+                .expect("\"usersHistogram\" field is missing on Query type");
+        assert_eq!(
+            histogram_field
+                .arguments
+                .iter()
+                .map(|input_value| input_value.name.to_owned())
+                .collect::<Vec<String>>(),
+            vec!["field", "size", "where", "block"]
+        );
+
+        // Types without numeric fields have no histogram
+        assert!(ast::get_named_type(&schema, &"Tag_histogram".to_string()).is_none());
+        assert!(ast::get_field_type(object_type("Query"), &"tagsHistogram".to_string()).is_none());
+    }
+
     #[test]
     fn api_schema_contains_interface_fields_on_query_type() {
         let input_schema = parse_schema(
@@ -610,7 +2112,7 @@ mod tests {
                 .iter()
                 .map(|input_value| input_value.name.to_owned())
                 .collect::<Vec<String>>(),
-            vec!["id".to_string()],
+            vec!["id".to_string(), "block".to_string()],
         );
 
         let plural_field = match query_type {
@@ -640,9 +2142,55 @@ mod tests {
                 "orderBy",
                 "orderDirection",
                 "where",
+                "block",
             ].into_iter()
                 .map(|name| name.to_string())
                 .collect::<Vec<String>>()
         );
     }
+
+    #[test]
+    fn api_schema_contains_union_fields_on_query_type() {
+        let input_schema = parse_schema(
+            "
+            type Trade { id: ID!, amount: Int!, price: Int! }
+            type Transfer { id: ID!, amount: Int!, to: String! }
+            union Activity = Trade | Transfer
+            ",
+        ).expect("Failed to parse input schema");
+        let schema = api_schema(&input_schema).expect("Failed to derived API schema");
+
+        let query_type = ast::get_named_type(&schema, &"Query".to_string())
+            .expect("Query type is missing in derived API schema");
+
+        let plural_field = match query_type {
+            TypeDefinition::Object(ref t) => ast::get_field_type(t, &"activities".to_string()),
+            _ => None,
+        }.expect("\"activities\" field is missing on Query type");
+
+        assert_eq!(
+            plural_field.field_type,
+            Type::NonNullType(Box::new(Type::ListType(Box::new(Type::NonNullType(
+                Box::new(Type::NamedType("Activity".to_string()))
+            )))))
+        );
+
+        // Unions can be ordered by the fields that all of their members have
+        let order_by = match ast::get_named_type(&schema, &"Activity_orderBy".to_string()) {
+            Some(TypeDefinition::Enum(t)) => Some(t),
+            _ => None,
+        }.expect("Activity_orderBy type is missing in derived API schema");
+
+        assert_eq!(
+            order_by
+                .values
+                .iter()
+                .map(|value| value.name.to_owned())
+                .collect::<Vec<String>>(),
+            vec!["id", "amount"]
+        );
+
+        ast::get_named_type(&schema, &"Activity_filter".to_string())
+            .expect("Activity_filter type is missing in derived API schema");
+    }
 }
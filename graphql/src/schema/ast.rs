@@ -14,12 +14,37 @@ pub(crate) enum FilterOp {
     NotStartsWith,
     EndsWith,
     NotEndsWith,
+    ContainsNoCase,
+    NotContainsNoCase,
+    StartsWithNoCase,
+    NotStartsWithNoCase,
+    EndsWithNoCase,
+    NotEndsWithNoCase,
+    MatchesRegex,
+    NotMatchesRegex,
     Equal,
+    Child,
 }
 
 /// Split a "name_eq" style name into an attribute ("name") and a filter op (`Equal`).
 pub(crate) fn parse_field_as_filter(key: &Name) -> (Name, FilterOp) {
     let (suffix, op) = match key {
+        k if k.ends_with("_not_contains_nocase") => {
+            ("_not_contains_nocase", FilterOp::NotContainsNoCase)
+        }
+        k if k.ends_with("_contains_nocase") => ("_contains_nocase", FilterOp::ContainsNoCase),
+        k if k.ends_with("_not_starts_with_nocase") => {
+            ("_not_starts_with_nocase", FilterOp::NotStartsWithNoCase)
+        }
+        k if k.ends_with("_starts_with_nocase") => {
+            ("_starts_with_nocase", FilterOp::StartsWithNoCase)
+        }
+        k if k.ends_with("_not_ends_with_nocase") => {
+            ("_not_ends_with_nocase", FilterOp::NotEndsWithNoCase)
+        }
+        k if k.ends_with("_ends_with_nocase") => ("_ends_with_nocase", FilterOp::EndsWithNoCase),
+        k if k.ends_with("_not_regex") => ("_not_regex", FilterOp::NotMatchesRegex),
+        k if k.ends_with("_regex") => ("_regex", FilterOp::MatchesRegex),
         k if k.ends_with("_not") => ("_not", FilterOp::Not),
         k if k.ends_with("_gt") => ("_gt", FilterOp::GreaterThan),
         k if k.ends_with("_lt") => ("_lt", FilterOp::LessThan),
@@ -33,6 +58,7 @@ pub(crate) fn parse_field_as_filter(key: &Name) -> (Name, FilterOp) {
         k if k.ends_with("_not_ends_with") => ("_not_ends_with", FilterOp::NotEndsWith),
         k if k.ends_with("_starts_with") => ("_starts_with", FilterOp::StartsWith),
         k if k.ends_with("_ends_with") => ("_ends_with", FilterOp::EndsWith),
+        k if k.ends_with("_") => ("_", FilterOp::Child),
         _ => ("", FilterOp::Equal),
     };
 
@@ -40,6 +66,60 @@ pub(crate) fn parse_field_as_filter(key: &Name) -> (Name, FilterOp) {
     (key.trim_right_matches(suffix).to_owned(), op)
 }
 
+/// Returns the type of the entities that a connection type generated for the
+/// API schema (e.g. `UserConnection`) is made of.
+pub fn get_connection_node_type<'a>(
+    schema: &'a Document,
+    object_type: &ObjectType,
+) -> Option<&'a ObjectType> {
+    get_directive_type(schema, object_type, "connection")
+}
+
+/// Returns the type of the entities that an aggregate type generated for the
+/// API schema (e.g. `User_aggregate`) aggregates.
+pub fn get_aggregate_entity_type<'a>(
+    schema: &'a Document,
+    object_type: &ObjectType,
+) -> Option<&'a ObjectType> {
+    get_directive_type(schema, object_type, "aggregate")
+}
+
+/// Returns the type of the entities that a histogram type generated for the
+/// API schema (e.g. `User_histogram`) buckets.
+pub fn get_histogram_entity_type<'a>(
+    schema: &'a Document,
+    object_type: &ObjectType,
+) -> Option<&'a ObjectType> {
+    get_directive_type(schema, object_type, "histogram")
+}
+
+/// Returns the object type named by the `type` argument of a directive of
+/// an object type.
+fn get_directive_type<'a>(
+    schema: &'a Document,
+    object_type: &ObjectType,
+    directive_name: &str,
+) -> Option<&'a ObjectType> {
+    object_type
+        .directives
+        .iter()
+        .find(|directive| directive.name == directive_name)
+        .and_then(|directive| {
+            directive
+                .arguments
+                .iter()
+                .find(|(name, _)| name == "type")
+                .and_then(|(_, value)| match value {
+                    Value::String(type_name) => Some(type_name),
+                    _ => None,
+                })
+        })
+        .and_then(|type_name| match get_named_type(schema, type_name) {
+            Some(TypeDefinition::Object(t)) => Some(t),
+            _ => None,
+        })
+}
+
 /// Returns the root query type (if there is one).
 pub fn get_root_query_type(schema: &Document) -> Option<&ObjectType> {
     schema
@@ -59,6 +139,22 @@ pub fn get_root_query_type(schema: &Document) -> Option<&ObjectType> {
         .next()
 }
 
+/// Returns the root subscription type (if there is one).
+pub fn get_root_subscription_type(schema: &Document) -> Option<&ObjectType> {
+    match get_named_type(schema, &"Subscription".to_string()) {
+        Some(TypeDefinition::Object(t)) => Some(t),
+        _ => None,
+    }
+}
+
+/// Returns the root mutation type (if there is one).
+pub fn get_root_mutation_type(schema: &Document) -> Option<&ObjectType> {
+    match get_named_type(schema, &"Mutation".to_string()) {
+        Some(TypeDefinition::Object(t)) => Some(t),
+        _ => None,
+    }
+}
+
 /// Returns all type definitions in the schema.
 pub fn get_type_definitions<'a>(schema: &'a Document) -> Vec<&'a TypeDefinition> {
     schema
@@ -95,6 +191,45 @@ pub fn get_interface_type_definitions<'a>(schema: &'a Document) -> Vec<&'a Inter
         .collect()
 }
 
+/// Returns all union types in the schema.
+pub fn get_union_type_definitions<'a>(schema: &'a Document) -> Vec<&'a UnionType> {
+    schema
+        .definitions
+        .iter()
+        .filter_map(|d| match d {
+            Definition::TypeDefinition(TypeDefinition::Union(t)) => Some(t),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns the object types that are members of the given union, in the
+/// order in which the union lists them.
+pub fn get_union_member_types<'a>(
+    schema: &'a Document,
+    union_type: &UnionType,
+) -> Vec<&'a ObjectType> {
+    union_type
+        .types
+        .iter()
+        .filter_map(|name| match get_named_type(schema, name) {
+            Some(TypeDefinition::Object(t)) => Some(t),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns all object types in the schema that implement the given interface.
+pub fn get_object_types_implementing<'a>(
+    schema: &'a Document,
+    interface_name: &Name,
+) -> Vec<&'a ObjectType> {
+    get_object_type_definitions(schema)
+        .into_iter()
+        .filter(|object_type| object_type.implements_interfaces.contains(interface_name))
+        .collect()
+}
+
 /// Returns the type of a field of an object type.
 pub fn get_field_type<'a>(object_type: &'a ObjectType, name: &Name) -> Option<&'a Field> {
     object_type.fields.iter().find(|field| &field.name == name)
@@ -150,3 +285,23 @@ pub fn get_argument_definitions<'a>(
 ) -> Option<&'a Vec<InputValue>> {
     get_field_type(object_type, name).map(|field| &field.arguments)
 }
+
+/// Returns the reason why a field, enum value or type is deprecated if it
+/// has a `@deprecated` directive, falling back to the default reason of
+/// the directive.
+pub fn get_deprecation_reason(directives: &[Directive]) -> Option<String> {
+    directives
+        .iter()
+        .find(|directive| directive.name == "deprecated")
+        .map(|directive| {
+            directive
+                .arguments
+                .iter()
+                .find(|(name, _)| name == "reason")
+                .and_then(|(_, value)| match value {
+                    Value::String(reason) => Some(reason.to_owned()),
+                    _ => None,
+                })
+                .unwrap_or_else(|| String::from("No longer supported"))
+        })
+}
@@ -1,7 +1,14 @@
+/// Field-level access control through the `@restricted` directive.
+pub mod access;
+
 /// Generate full-fledged API schemas from existing GraphQL schemas.
 pub mod api;
 
 /// Utilities for working with GraphQL schema ASTs.
 pub mod ast;
 
-pub use self::api::{api_schema, APISchemaError};
+/// Merge the API schemas of several subgraphs into one.
+pub mod stitch;
+
+pub use self::api::{api_schema, validate_schema, APISchemaError};
+pub use self::stitch::{stitch_schemas, stitched_subgraph_ids, SchemaStitchError};
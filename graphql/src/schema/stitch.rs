@@ -0,0 +1,569 @@
+use graphql_parser::schema::*;
+use graphql_parser::Pos;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+
+use graph::prelude::Schema;
+
+use schema::api::{named_type_name, META_FIELD_NAME, META_TYPE_NAME};
+
+/// Root types of API schemas, whose fields are merged.
+const ROOT_TYPE_NAMES: [&str; 2] = ["Query", "Subscription"];
+
+/// Directives of generated types that name the entity type they belong to.
+const TYPE_DIRECTIVE_NAMES: [&str; 3] = ["connection", "aggregate", "histogram"];
+
+/// Directive of the `_meta` field of stitched schemas that lists the IDs of
+/// the subgraphs it reports on.
+const SUBGRAPH_IDS_DIRECTIVE_NAME: &str = "subgraphIds";
+
+#[derive(Debug)]
+pub enum SchemaStitchError {
+    /// Several subgraphs are stitched with the same prefix.
+    DuplicatePrefix(String),
+    /// A prefixed type or root field clashes with an existing one.
+    NameClash(String),
+}
+
+impl fmt::Display for SchemaStitchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SchemaStitchError::DuplicatePrefix(prefix) => {
+                write!(f, "Several subgraphs are stitched with the prefix \"{}\"", prefix)
+            }
+            SchemaStitchError::NameClash(name) => write!(
+                f,
+                "Prefixed name \"{}\" clashes with an existing type or field",
+                name
+            ),
+        }
+    }
+}
+
+impl Error for SchemaStitchError {
+    fn description(&self) -> &str {
+        "Schema stitch error"
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        None
+    }
+}
+
+/// Merges the API schemas of several subgraphs, given along with a prefix
+/// for each, into one API schema with the given ID.
+///
+/// The root fields of all subgraphs end up in the same `Query` and
+/// `Subscription` types. Types that the subgraphs define differently, or
+/// that refer to such types, are renamed to `<prefix>_<name>` in every
+/// subgraph, and so are root fields that several subgraphs have. Renamed
+/// entity types remember the type their entities are stored as, and all
+/// entity types keep the ID of their subgraph, so that queries against the
+/// merged schema read the entities of each field from the subgraph that
+/// owns it. Instead of the `_meta` fields of the subgraphs, `_meta` lists
+/// the indexing state of every subgraph, in the order they are given in.
+pub fn stitch_schemas(
+    id: &str,
+    subgraphs: &[(String, Schema)],
+) -> Result<Schema, SchemaStitchError> {
+    let mut prefixes = HashSet::new();
+    for (prefix, _) in subgraphs {
+        if !prefixes.insert(prefix) {
+            return Err(SchemaStitchError::DuplicatePrefix(prefix.to_owned()));
+        }
+    }
+
+    let renamed_types = colliding_types(subgraphs);
+    let renamed_fields = colliding_root_fields(subgraphs);
+
+    let mut definitions = vec![];
+    let mut type_names = HashSet::new();
+    let mut root_fields: Vec<(&str, Vec<Field>)> =
+        ROOT_TYPE_NAMES.iter().map(|name| (*name, vec![])).collect();
+    for (prefix, schema) in subgraphs {
+        let renames: HashMap<String, String> = type_definitions(&schema.document)
+            .map(type_name)
+            .filter(|name| renamed_types.contains(name))
+            .map(|name| (name.to_owned(), format!("{}_{}", prefix, name)))
+            .collect();
+
+        for definition in type_definitions(&schema.document) {
+            let mut definition = definition.clone();
+            rename_types(&mut definition, &renames);
+
+            let root_type = match definition {
+                TypeDefinition::Object(ref object_type) => ROOT_TYPE_NAMES
+                    .iter()
+                    .position(|name| *name == object_type.name),
+                _ => None,
+            };
+            match (root_type, definition) {
+                (Some(index), TypeDefinition::Object(object_type)) => {
+                    let root_type_name = root_fields[index].0;
+                    let fields = &mut root_fields[index].1;
+                    for mut field in object_type.fields {
+                        if root_type_name == "Query" && field.name == META_FIELD_NAME {
+                            continue;
+                        }
+                        if renamed_fields.contains(&(root_type_name, field.name.as_str())) {
+                            rename_root_field(&mut field, prefix, &schema.id);
+                        }
+                        if fields.iter().any(|other: &Field| other.name == field.name) {
+                            return Err(SchemaStitchError::NameClash(field.name));
+                        }
+                        fields.push(field);
+                    }
+                }
+                (_, definition) => {
+                    // Types that are the same in all subgraphs are only added once
+                    let name = type_name(&definition).to_owned();
+                    if type_names.insert(name.clone()) {
+                        definitions.push(Definition::TypeDefinition(definition));
+                    } else if renames.values().any(|renamed| renamed == &name) {
+                        return Err(SchemaStitchError::NameClash(name));
+                    }
+                }
+            }
+        }
+    }
+
+    root_fields[0].1.push(meta_field(subgraphs));
+
+    for (name, fields) in root_fields.into_iter().filter(|(_, fields)| !fields.is_empty()) {
+        if type_names.contains(name) {
+            return Err(SchemaStitchError::NameClash(name.to_owned()));
+        }
+        definitions.push(Definition::TypeDefinition(TypeDefinition::Object(
+            ObjectType {
+                position: Pos::default(),
+                description: None,
+                name: name.to_owned(),
+                implements_interfaces: vec![],
+                directives: vec![],
+                fields,
+            },
+        )));
+    }
+
+    Ok(Schema {
+        id: id.to_owned(),
+        document: Document { definitions },
+    })
+}
+
+/// Returns the IDs of the subgraphs that a stitched schema merges, in the
+/// order that its `_meta` field lists them in, or nothing if the schema is
+/// not stitched.
+pub fn stitched_subgraph_ids(document: &Document) -> Vec<String> {
+    let meta_field = type_definitions(document)
+        .filter_map(|definition| match definition {
+            TypeDefinition::Object(object_type) if object_type.name == "Query" => {
+                object_type.fields.iter().find(|field| field.name == META_FIELD_NAME)
+            }
+            _ => None,
+        })
+        .next();
+    meta_field
+        .into_iter()
+        .flat_map(|field| field.directives.iter())
+        .filter(|directive| directive.name == SUBGRAPH_IDS_DIRECTIVE_NAME)
+        .flat_map(|directive| directive.arguments.iter())
+        .filter(|(name, _)| name == "ids")
+        .filter_map(|(_, value)| match value {
+            Value::List(ids) => Some(ids),
+            _ => None,
+        })
+        .flat_map(|ids| ids.iter())
+        .filter_map(|id| match id {
+            Value::String(id) => Some(id.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns the `_meta` field of a stitched schema, which lists the indexing
+/// state of each of the subgraphs.
+fn meta_field(subgraphs: &[(String, Schema)]) -> Field {
+    let non_null = |t: Type| Type::NonNullType(Box::new(t));
+    let ids = subgraphs
+        .iter()
+        .map(|(_, schema)| Value::String(schema.id.clone()))
+        .collect();
+    Field {
+        position: Pos::default(),
+        description: None,
+        name: META_FIELD_NAME.to_owned(),
+        arguments: vec![],
+        field_type: non_null(Type::ListType(Box::new(non_null(Type::NamedType(
+            META_TYPE_NAME.to_owned(),
+        ))))),
+        directives: vec![Directive {
+            name: SUBGRAPH_IDS_DIRECTIVE_NAME.to_owned(),
+            position: Pos::default(),
+            arguments: vec![(String::from("ids"), Value::List(ids))],
+        }],
+    }
+}
+
+/// Returns the names of the non-root types that have to be renamed, because
+/// the subgraphs define them differently or because they refer to types
+/// that are renamed.
+fn colliding_types(subgraphs: &[(String, Schema)]) -> HashSet<&str> {
+    let mut definitions: HashMap<&str, HashSet<String>> = HashMap::new();
+    let mut references: HashMap<&str, HashSet<&str>> = HashMap::new();
+    for (_, schema) in subgraphs {
+        for definition in type_definitions(&schema.document) {
+            let name = type_name(definition);
+            if ROOT_TYPE_NAMES.contains(&name) {
+                continue;
+            }
+            // Positions differ between subgraphs, so definitions are compared
+            // by how they are printed
+            let printed = Document {
+                definitions: vec![Definition::TypeDefinition(definition.clone())],
+            }.to_string();
+            definitions
+                .entry(name)
+                .or_insert_with(HashSet::new)
+                .insert(printed);
+            references
+                .entry(name)
+                .or_insert_with(HashSet::new)
+                .extend(referenced_types(definition));
+        }
+    }
+
+    let mut colliding: HashSet<&str> = definitions
+        .iter()
+        .filter(|(_, printed)| printed.len() > 1)
+        .map(|(name, _)| *name)
+        .collect();
+    loop {
+        let referring: Vec<&str> = references
+            .iter()
+            .filter(|(name, _)| !colliding.contains(*name))
+            .filter(|(_, referenced)| referenced.iter().any(|name| colliding.contains(name)))
+            .map(|(name, _)| *name)
+            .collect();
+        if referring.is_empty() {
+            return colliding;
+        }
+        colliding.extend(referring);
+    }
+}
+
+/// Returns the root types and names of the root fields that several
+/// subgraphs have.
+fn colliding_root_fields(subgraphs: &[(String, Schema)]) -> HashSet<(&'static str, &str)> {
+    let mut counts: HashMap<(&'static str, &str), usize> = HashMap::new();
+    for (_, schema) in subgraphs {
+        for definition in type_definitions(&schema.document) {
+            let object_type = match definition {
+                TypeDefinition::Object(object_type) => object_type,
+                _ => continue,
+            };
+            let root_type_name = match ROOT_TYPE_NAMES
+                .iter()
+                .find(|name| **name == object_type.name)
+            {
+                Some(name) => *name,
+                None => continue,
+            };
+            for field in object_type.fields.iter() {
+                *counts.entry((root_type_name, &field.name)).or_insert(0) += 1;
+            }
+        }
+    }
+    counts
+        .into_iter()
+        .filter(|(_, count)| *count > 1)
+        .map(|(field, _)| field)
+        .collect()
+}
+
+/// Prefixes the name of a root field, and remembers the name it has in the
+/// schema of its subgraph.
+fn rename_root_field(field: &mut Field, prefix: &str, subgraph_id: &str) {
+    field.directives.push(Directive {
+        name: String::from("subgraphId"),
+        position: Pos::default(),
+        arguments: vec![
+            (String::from("id"), Value::String(subgraph_id.to_owned())),
+            (String::from("field"), Value::String(field.name.clone())),
+        ],
+    });
+    field.name = format!("{}_{}", prefix, field.name);
+}
+
+fn type_definitions(document: &Document) -> impl Iterator<Item = &TypeDefinition> {
+    document
+        .definitions
+        .iter()
+        .filter_map(|definition| match definition {
+            Definition::TypeDefinition(definition) => Some(definition),
+            _ => None,
+        })
+}
+
+fn type_name(definition: &TypeDefinition) -> &str {
+    match definition {
+        TypeDefinition::Scalar(t) => &t.name,
+        TypeDefinition::Object(t) => &t.name,
+        TypeDefinition::Interface(t) => &t.name,
+        TypeDefinition::Union(t) => &t.name,
+        TypeDefinition::Enum(t) => &t.name,
+        TypeDefinition::InputObject(t) => &t.name,
+    }
+}
+
+/// Returns the names of all types that a type definition refers to.
+fn referenced_types(definition: &TypeDefinition) -> Vec<&str> {
+    match definition {
+        TypeDefinition::Object(t) => field_types(&t.fields)
+            .into_iter()
+            .chain(t.implements_interfaces.iter().map(String::as_str))
+            .chain(directive_types(&t.directives))
+            .collect(),
+        TypeDefinition::Interface(t) => field_types(&t.fields),
+        TypeDefinition::Union(t) => t.types.iter().map(String::as_str).collect(),
+        TypeDefinition::InputObject(t) => t
+            .fields
+            .iter()
+            .map(|field| named_type_name(&field.value_type).as_str())
+            .collect(),
+        TypeDefinition::Scalar(_) | TypeDefinition::Enum(_) => vec![],
+    }
+}
+
+fn field_types(fields: &[Field]) -> Vec<&str> {
+    fields
+        .iter()
+        .flat_map(|field| {
+            field
+                .arguments
+                .iter()
+                .map(|argument| named_type_name(&argument.value_type).as_str())
+                .chain(Some(named_type_name(&field.field_type).as_str()))
+        })
+        .collect()
+}
+
+/// Returns the entity types that the directives of a generated type name.
+fn directive_types(directives: &[Directive]) -> Vec<&str> {
+    directives
+        .iter()
+        .filter(|directive| TYPE_DIRECTIVE_NAMES.contains(&directive.name.as_str()))
+        .flat_map(|directive| directive.arguments.iter())
+        .filter(|(name, _)| name == "type")
+        .filter_map(|(_, value)| match value {
+            Value::String(type_name) => Some(type_name.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Renames a type definition and the types it refers to. Renamed entity
+/// types remember the name their entities are stored under.
+fn rename_types(definition: &mut TypeDefinition, renames: &HashMap<String, String>) {
+    match definition {
+        TypeDefinition::Object(t) => {
+            if renames.contains_key(&t.name) {
+                let stored_name = Value::String(t.name.clone());
+                if let Some(directive) = t
+                    .directives
+                    .iter_mut()
+                    .find(|directive| directive.name == "subgraphId")
+                {
+                    directive
+                        .arguments
+                        .push((String::from("entity"), stored_name));
+                }
+            }
+            rename(&mut t.name, renames);
+            rename_fields(&mut t.fields, renames);
+            for name in t.implements_interfaces.iter_mut() {
+                rename(name, renames);
+            }
+            for directive in t
+                .directives
+                .iter_mut()
+                .filter(|directive| TYPE_DIRECTIVE_NAMES.contains(&directive.name.as_str()))
+            {
+                for (name, value) in directive.arguments.iter_mut() {
+                    match value {
+                        Value::String(type_name) if *name == "type" => {
+                            rename(type_name, renames)
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        TypeDefinition::Interface(t) => {
+            rename(&mut t.name, renames);
+            rename_fields(&mut t.fields, renames);
+        }
+        TypeDefinition::Union(t) => {
+            rename(&mut t.name, renames);
+            for name in t.types.iter_mut() {
+                rename(name, renames);
+            }
+        }
+        TypeDefinition::InputObject(t) => {
+            rename(&mut t.name, renames);
+            for field in t.fields.iter_mut() {
+                rename_type(&mut field.value_type, renames);
+            }
+        }
+        TypeDefinition::Enum(t) => rename(&mut t.name, renames),
+        TypeDefinition::Scalar(t) => rename(&mut t.name, renames),
+    }
+}
+
+fn rename_fields(fields: &mut Vec<Field>, renames: &HashMap<String, String>) {
+    for field in fields.iter_mut() {
+        rename_type(&mut field.field_type, renames);
+        for argument in field.arguments.iter_mut() {
+            rename_type(&mut argument.value_type, renames);
+        }
+    }
+}
+
+fn rename_type(field_type: &mut Type, renames: &HashMap<String, String>) {
+    match field_type {
+        Type::NamedType(name) => rename(name, renames),
+        Type::ListType(inner) | Type::NonNullType(inner) => rename_type(inner, renames),
+    }
+}
+
+fn rename(name: &mut String, renames: &HashMap<String, String>) {
+    if let Some(renamed) = renames.get(name) {
+        *name = renamed.to_owned();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use graphql_parser::parse_schema;
+    use graphql_parser::schema::*;
+
+    use graph::prelude::Schema;
+
+    use schema::api::api_schema;
+    use schema::ast;
+    use store::query::build_entity_name;
+
+    use super::{stitch_schemas, stitched_subgraph_ids};
+
+    fn subgraph(id: &str, input: &str) -> Schema {
+        let mut schema = Schema {
+            id: id.to_owned(),
+            document: parse_schema(input).unwrap(),
+        };
+        schema.add_subgraph_id_directives(id.to_owned());
+        Schema {
+            id: id.to_owned(),
+            document: api_schema(&schema.document).unwrap(),
+        }
+    }
+
+    fn object_type<'a>(schema: &'a Schema, name: &str) -> &'a ObjectType {
+        match ast::get_named_type(&schema.document, &name.to_owned()) {
+            Some(TypeDefinition::Object(object_type)) => object_type,
+            _ => panic!("no object type {}", name),
+        }
+    }
+
+    fn query_fields(schema: &Schema) -> Vec<String> {
+        object_type(schema, "Query")
+            .fields
+            .iter()
+            .map(|field| field.name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn merges_the_root_fields_of_all_subgraphs() {
+        let stitched = stitch_schemas(
+            "combined",
+            &[
+                (
+                    "music".to_owned(),
+                    subgraph("music", "type Band @entity { id: ID!, name: String! }"),
+                ),
+                (
+                    "tokens".to_owned(),
+                    subgraph("tokens", "type Token @entity { id: ID!, symbol: String! }"),
+                ),
+            ],
+        ).unwrap();
+
+        let fields = query_fields(&stitched);
+        assert!(fields.contains(&"bands".to_owned()));
+        assert!(fields.contains(&"tokens".to_owned()));
+        assert_eq!(build_entity_name(object_type(&stitched, "Band")), "Band");
+
+        // `_meta` reports on both subgraphs
+        let meta_fields: Vec<&Field> = object_type(&stitched, "Query")
+            .fields
+            .iter()
+            .filter(|field| field.name == "_meta")
+            .collect();
+        assert_eq!(meta_fields.len(), 1);
+        let meta_type = Type::NamedType(String::from("_Meta_"));
+        assert_eq!(
+            meta_fields[0].field_type,
+            Type::NonNullType(Box::new(Type::ListType(Box::new(Type::NonNullType(
+                Box::new(meta_type)
+            )))))
+        );
+        assert_eq!(
+            stitched_subgraph_ids(&stitched.document),
+            vec![String::from("music"), String::from("tokens")]
+        );
+        assert!(stitched_subgraph_ids(&subgraph("music", "type Band @entity { id: ID! }").document)
+            .is_empty());
+    }
+
+    #[test]
+    fn prefixes_types_and_root_fields_that_collide() {
+        let stitched = stitch_schemas(
+            "combined",
+            &[
+                (
+                    "a".to_owned(),
+                    subgraph("first", "type User @entity { id: ID!, name: String! }"),
+                ),
+                (
+                    "b".to_owned(),
+                    subgraph("second", "type User @entity { id: ID!, name: String! }"),
+                ),
+            ],
+        ).unwrap();
+
+        assert!(ast::get_named_type(&stitched.document, &"User".to_owned()).is_none());
+        assert_eq!(build_entity_name(object_type(&stitched, "a_User")), "User");
+        assert_eq!(build_entity_name(object_type(&stitched, "b_User")), "User");
+
+        let fields = query_fields(&stitched);
+        assert!(fields.contains(&"a_users".to_owned()));
+        assert!(fields.contains(&"b_users".to_owned()));
+        assert!(!fields.contains(&"users".to_owned()));
+
+        // Generated types that only refer to scalars are shared
+        assert!(ast::get_named_type(&stitched.document, &"User_filter".to_owned()).is_some());
+        assert!(ast::get_named_type(&stitched.document, &"a_UserConnection".to_owned()).is_some());
+    }
+
+    #[test]
+    fn rejects_duplicate_prefixes() {
+        let schema = subgraph("first", "type User @entity { id: ID! }");
+        assert!(
+            stitch_schemas(
+                "combined",
+                &[("a".to_owned(), schema.clone()), ("a".to_owned(), schema)]
+            ).is_err()
+        );
+    }
+}
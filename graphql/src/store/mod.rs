@@ -1,5 +1,6 @@
+mod prefetch;
 mod query;
 mod resolver;
 
-pub use self::query::build_query;
+pub use self::query::{build_connection_query, build_query};
 pub use self::resolver::StoreResolver;
@@ -0,0 +1,303 @@
+use graphql_parser::{query as q, schema as s};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use graph::components::store::*;
+use graph::prelude::{BasicStore, Entity, QueryScope, Value};
+
+use prelude::*;
+use query::ast as qast;
+use schema::ast as sast;
+use store::query::build_query;
+
+/// Resolves `fields` for all `objects` with one store query per field and
+/// level of the selection set, rather than one per object. All store queries
+/// are made in `scope`.
+pub fn prefetch(
+    store: &BasicStore,
+    scope: QueryScope,
+    schema: &s::Document,
+    objects: &mut Vec<q::Value>,
+    fields: &[PrefetchField],
+) {
+    for field in fields {
+        match derived_from_field(field) {
+            Some(derived_from_field) => {
+                prefetch_derived(store, scope, schema, objects, field, derived_from_field)
+            }
+            None => prefetch_references(store, scope, schema, objects, field),
+        }
+    }
+}
+
+/// Resolves a reference field by fetching the entities referenced by all
+/// objects at once.
+fn prefetch_references(
+    store: &BasicStore,
+    scope: QueryScope,
+    schema: &s::Document,
+    objects: &mut Vec<q::Value>,
+    field: &PrefetchField,
+) {
+    // References that are filtered, ordered or paged are left to the store
+    // to resolve for each object
+    // Filters that cannot be built are reported when the field is resolved
+    let query = match build_query(schema, field.object_type, &field.arguments) {
+        Ok(query) => query,
+        Err(_) => return,
+    };
+    if query.filter.is_some() || query.order_by.is_some() || query.range.is_some() {
+        return;
+    }
+
+    let mut ids = objects
+        .iter()
+        .filter_map(|object| object_field(object, field.name))
+        .filter_map(referenced_ids)
+        .flat_map(|ids| ids.into_iter().cloned())
+        .collect::<Vec<_>>();
+    if ids.is_empty() {
+        return;
+    }
+    ids.sort();
+    ids.dedup();
+
+    let mut ids_for_type = BTreeMap::new();
+    ids_for_type.insert(query.entity.clone(), ids);
+    let entities = match store.get_many(&query.subgraph, ids_for_type, scope) {
+        Ok(mut entities_for_type) => entities_for_type.remove(&query.entity).unwrap_or(vec![]),
+        Err(_) => return,
+    };
+
+    let mut children: Vec<q::Value> = entities
+        .into_iter()
+        .map(|entity| entity.into())
+        .collect();
+    prefetch(store, scope, schema, &mut children, &field.children);
+
+    let children_by_id = children
+        .into_iter()
+        .filter_map(|child| object_id(&child).cloned().map(|id| (id, child)))
+        .collect::<HashMap<_, _>>();
+
+    for object in objects.iter_mut() {
+        let resolved = match object_field(object, field.name) {
+            Some(q::Value::String(id)) => children_by_id.get(id).cloned().or(Some(q::Value::Null)),
+            Some(value @ q::Value::List(_)) => referenced_ids(value).map(|ids| {
+                q::Value::List(
+                    ids.into_iter()
+                        .filter_map(|id| children_by_id.get(id).cloned())
+                        .collect(),
+                )
+            }),
+            _ => None,
+        };
+
+        if let (Some(resolved), q::Value::Object(object)) = (resolved, object) {
+            object.insert(field.name.to_owned(), resolved);
+        }
+    }
+}
+
+/// Resolves a derived field by querying the entities that point to any of
+/// the objects, and then hands each object the entities that point to it.
+fn prefetch_derived(
+    store: &BasicStore,
+    scope: QueryScope,
+    schema: &s::Document,
+    objects: &mut Vec<q::Value>,
+    field: &PrefetchField,
+    derived_from_field: &s::Field,
+) {
+    let parent_ids = objects
+        .iter()
+        .filter(|object| !is_resolved(object_field(object, field.name)))
+        .filter_map(object_id)
+        .cloned()
+        .collect::<Vec<_>>();
+    if parent_ids.is_empty() {
+        return;
+    }
+
+    // The store cuts out the page of each object if there is a range;
+    // otherwise, all entities that point to any of the objects are needed
+    let mut query = match build_query(schema, field.object_type, &field.arguments) {
+        Ok(query) => query,
+        Err(_) => return,
+    };
+    query.scope = scope;
+    let is_list_reference = is_list_type(&derived_from_field.field_type);
+    let entities = if query.range.is_some() {
+        store.find_windowed(
+            query,
+            StoreWindow {
+                attribute: derived_from_field.name.clone(),
+                is_list: is_list_reference,
+                parent_ids,
+            },
+        )
+    } else {
+        find_children(store, query, derived_from_field, is_list_reference, parent_ids)
+    };
+    let entities = match entities {
+        Ok(entities) => entities,
+        Err(_) => return,
+    };
+
+    // Remember which entities belong to which object
+    let mut children_for_parent: HashMap<String, Vec<usize>> = HashMap::new();
+    for (index, (parent_id, _)) in entities.iter().enumerate() {
+        children_for_parent
+            .entry(parent_id.to_owned())
+            .or_insert_with(Vec::new)
+            .push(index);
+    }
+
+    let mut children: Vec<q::Value> = entities
+        .into_iter()
+        .map(|(_, entity)| entity.into())
+        .collect();
+    prefetch(store, scope, schema, &mut children, &field.children);
+
+    let is_list = is_list_type(&field.definition.field_type);
+    for object in objects.iter_mut() {
+        if is_resolved(object_field(object, field.name)) {
+            continue;
+        }
+
+        let page = object_id(object)
+            .and_then(|id| children_for_parent.get(id))
+            .map(|indices| {
+                indices
+                    .iter()
+                    .map(|index| children[*index].clone())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or(vec![]);
+
+        let resolved = if is_list {
+            q::Value::List(page)
+        } else {
+            page.into_iter().next().unwrap_or(q::Value::Null)
+        };
+
+        if let q::Value::Object(object) = object {
+            object.insert(field.name.to_owned(), resolved);
+        }
+    }
+}
+
+/// Queries all entities that point to any of the parents through
+/// `derived_from_field`, along with the ID of each parent they point to.
+fn find_children(
+    store: &BasicStore,
+    mut query: StoreQuery,
+    derived_from_field: &s::Field,
+    is_list_reference: bool,
+    parent_ids: Vec<String>,
+) -> Result<Vec<(String, Entity)>, ()> {
+    // Lists of references are matched by the sublist of one parent's ID
+    let attribute = derived_from_field.name.clone();
+    let filter = if is_list_reference {
+        StoreFilter::Or(
+            parent_ids
+                .iter()
+                .map(|id| {
+                    StoreFilter::Contains(attribute.clone(), Value::List(vec![Value::from(id)]))
+                })
+                .collect(),
+        )
+    } else {
+        StoreFilter::In(attribute, parent_ids.iter().map(Value::from).collect())
+    };
+    match query.filter.get_or_insert(StoreFilter::And(vec![])) {
+        StoreFilter::And(filters) => filters.push(filter),
+        _ => unreachable!("Top-level filters are always `And` filters"),
+    }
+
+    let parent_ids = parent_ids.into_iter().collect::<HashSet<_>>();
+    Ok(store
+        .find(query)?
+        .into_iter()
+        .flat_map(|entity| {
+            let referenced_ids = match entity.get(&derived_from_field.name) {
+                Some(Value::String(id)) => vec![id.to_owned()],
+                Some(Value::List(ids)) => ids
+                    .iter()
+                    .filter_map(|id| match id {
+                        Value::String(id) => Some(id.to_owned()),
+                        _ => None,
+                    })
+                    .collect(),
+                _ => vec![],
+            };
+            referenced_ids
+                .into_iter()
+                .filter(|id| parent_ids.contains(id))
+                .map(|id| (id, entity.clone()))
+                .collect::<Vec<_>>()
+        })
+        .collect())
+}
+
+/// Returns the field of the entity type that a `@derivedFrom` field is
+/// derived from.
+fn derived_from_field<'a>(field: &PrefetchField<'a>) -> Option<&'a s::Field> {
+    StoreResolver::get_derived_from_directive(field.definition)
+        .and_then(|directive| {
+            qast::get_argument_value(&directive.arguments, &q::Name::from("field"))
+        })
+        .and_then(|value| match value {
+            q::Value::String(s) => Some(s),
+            _ => None,
+        })
+        .and_then(|name| sast::get_field_type(field.object_type, name))
+}
+
+fn object_field<'a>(object: &'a q::Value, name: &q::Name) -> Option<&'a q::Value> {
+    match object {
+        q::Value::Object(object) => object.get(name),
+        _ => None,
+    }
+}
+
+fn object_id(object: &q::Value) -> Option<&String> {
+    match object_field(object, &q::Name::from("id")) {
+        Some(q::Value::String(id)) => Some(id),
+        _ => None,
+    }
+}
+
+/// Returns the IDs held by a reference field, unless it has been resolved.
+fn referenced_ids(value: &q::Value) -> Option<Vec<&String>> {
+    match value {
+        q::Value::String(id) => Some(vec![id]),
+        q::Value::List(values) => values
+            .iter()
+            .map(|value| match value {
+                q::Value::String(id) => Some(id),
+                _ => None,
+            })
+            .collect(),
+        _ => None,
+    }
+}
+
+/// Returns true if a derived field already holds its objects.
+fn is_resolved(value: Option<&q::Value>) -> bool {
+    match value {
+        Some(q::Value::Object(_)) | Some(q::Value::Null) => true,
+        Some(q::Value::List(values)) => values.iter().all(|value| match value {
+            q::Value::Object(_) => true,
+            _ => false,
+        }),
+        _ => false,
+    }
+}
+
+fn is_list_type(field_type: &s::Type) -> bool {
+    match field_type {
+        s::Type::NonNullType(t) => is_list_type(t),
+        s::Type::ListType(_) => true,
+        s::Type::NamedType(_) => false,
+    }
+}
@@ -1,22 +1,84 @@
+use graph::data::schema::fulltext_definitions;
 use graph::prelude::*;
 use graphql_parser::{query as q, schema};
+use schema::api::named_type_name;
 use schema::ast;
 use std::collections::{BTreeMap, HashMap};
 
-/// Builds a StoreQuery from GraphQL arguments.
+/// Builds a StoreQuery from GraphQL arguments. Fails if the arguments hold a
+/// filter that cannot be applied to its value.
 pub fn build_query(
+    schema: &schema::Document,
     entity: &schema::ObjectType,
     arguments: &HashMap<&q::Name, q::Value>,
-) -> StoreQuery {
-    StoreQuery {
+) -> Result<StoreQuery, UnsupportedFilter> {
+    Ok(StoreQuery {
         subgraph: build_subgraph_id(entity)
             .expect(format!("Failed to get subgraph ID from type: {}", entity.name).as_str()),
-        entity: entity.name.to_owned(),
+        entity: build_entity_name(entity),
         range: build_range(arguments),
-        filter: build_filter(entity, arguments),
-        order_by: build_order_by(arguments),
+        filter: build_filter(schema, entity, arguments)?,
+        order_by: build_order_by(entity, arguments),
         order_direction: build_order_direction(arguments),
+        scope: QueryScope::default(),
+    })
+}
+
+/// Builds a StoreQuery for a page of a connection from GraphQL arguments,
+/// along with the number of entities on the page.
+///
+/// Entities are ordered by ID, so that a page can continue after the cursor
+/// of the previous one, which is the ID of its last entity. One entity more
+/// than fits on the page is queried to find out if there is a next page.
+pub fn build_connection_query(
+    schema: &schema::Document,
+    entity: &schema::ObjectType,
+    arguments: &HashMap<&q::Name, q::Value>,
+) -> Result<(StoreQuery, usize), UnsupportedFilter> {
+    let mut query = build_query(schema, entity, arguments)?;
+    let first = query.range.as_ref().map_or(100, |range| range.first);
+
+    if let Some(q::Value::String(cursor)) = arguments.get(&"after".to_string()) {
+        let filter = match query.order_direction {
+            Some(StoreOrder::Descending) => {
+                StoreFilter::LessThan(String::from("id"), Value::from(cursor))
+            }
+            _ => StoreFilter::GreaterThan(String::from("id"), Value::from(cursor)),
+        };
+        match query.filter.get_or_insert(StoreFilter::And(vec![])) {
+            StoreFilter::And(filters) => filters.push(filter),
+            _ => unreachable!("Top-level filters are always `And` filters"),
+        }
     }
+
+    query.order_by = Some((String::from("id"), ValueType::String));
+    query.range = Some(StoreRange {
+        first: first + 1,
+        skip: 0,
+    });
+    Ok((query, first))
+}
+
+/// Builds the full-text search for a `<name>Search` field of an entity type
+/// from GraphQL arguments. Returns `None` for other fields.
+pub fn build_fulltext_search(
+    entity: &schema::ObjectType,
+    field_name: &str,
+    arguments: &HashMap<&q::Name, q::Value>,
+) -> Option<FulltextSearch> {
+    let definition = fulltext_definitions(entity)
+        .ok()?
+        .into_iter()
+        .find(|definition| format!("{}Search", definition.name) == field_name)?;
+    let text = match arguments.get(&"text".to_string()) {
+        Some(q::Value::String(text)) => text.to_owned(),
+        _ => String::new(),
+    };
+    Some(FulltextSearch {
+        name: definition.name,
+        language: definition.language,
+        text,
+    })
 }
 
 /// Parses GraphQL arguments into a StoreRange, if present.
@@ -47,24 +109,25 @@ fn build_range(arguments: &HashMap<&q::Name, q::Value>) -> Option<StoreRange> {
 
 /// Parses GraphQL arguments into a StoreFilter, if present.
 fn build_filter(
+    schema: &schema::Document,
     entity: &schema::ObjectType,
     arguments: &HashMap<&q::Name, q::Value>,
-) -> Option<StoreFilter> {
-    arguments
-        .get(&"where".to_string())
-        .and_then(|value| match value {
-            q::Value::Object(object) => Some(object),
-            _ => None,
-        })
-        .map(|object| build_filter_from_object(entity, object))
+) -> Result<Option<StoreFilter>, UnsupportedFilter> {
+    match arguments.get(&"where".to_string()) {
+        Some(q::Value::Object(object)) => {
+            build_filter_from_object(schema, entity, object).map(Some)
+        }
+        _ => Ok(None),
+    }
 }
 
 /// Parses a GraphQL input object into a StoreFilter, if present.
 fn build_filter_from_object(
+    schema: &schema::Document,
     entity: &schema::ObjectType,
     object: &BTreeMap<q::Name, q::Value>,
-) -> StoreFilter {
-    StoreFilter::And(
+) -> Result<StoreFilter, UnsupportedFilter> {
+    Ok(StoreFilter::And(
         object
             .iter()
             .map(|(key, value)| {
@@ -72,46 +135,108 @@ fn build_filter_from_object(
 
                 let (attribute, op) = ast::parse_field_as_filter(key);
 
-                let field = ast::get_field_type(entity, &attribute)
-                    .expect("attribute does not belong to entity");
+                let unsupported = || UnsupportedFilter {
+                    filter: key.to_owned(),
+                    value: Value::Null,
+                };
+                let field = ast::get_field_type(entity, &attribute).ok_or_else(unsupported)?;
                 let ty = &field.field_type;
+
+                // Filters on the entities a field references are built for
+                // the type of those entities
+                if let Child = op {
+                    return build_child_filter(schema, key, attribute, ty, value);
+                }
+
                 let store_value = Value::from_query_value(value, &ty);
 
-                match op {
+                Ok(match op {
                     Not => StoreFilter::Not(attribute, store_value),
                     GreaterThan => StoreFilter::GreaterThan(attribute, store_value),
                     LessThan => StoreFilter::LessThan(attribute, store_value),
                     GreaterOrEqual => StoreFilter::GreaterOrEqual(attribute, store_value),
                     LessOrEqual => StoreFilter::LessOrEqual(attribute, store_value),
-                    In => StoreFilter::In(attribute, list_values(store_value)),
-                    NotIn => StoreFilter::NotIn(attribute, list_values(store_value)),
+                    In => StoreFilter::In(attribute, list_values(key, store_value)?),
+                    NotIn => StoreFilter::NotIn(attribute, list_values(key, store_value)?),
                     Contains => StoreFilter::Contains(attribute, store_value),
                     NotContains => StoreFilter::NotContains(attribute, store_value),
                     StartsWith => StoreFilter::StartsWith(attribute, store_value),
                     NotStartsWith => StoreFilter::NotStartsWith(attribute, store_value),
                     EndsWith => StoreFilter::EndsWith(attribute, store_value),
                     NotEndsWith => StoreFilter::NotEndsWith(attribute, store_value),
+                    ContainsNoCase => StoreFilter::ContainsNoCase(attribute, store_value),
+                    NotContainsNoCase => StoreFilter::NotContainsNoCase(attribute, store_value),
+                    StartsWithNoCase => StoreFilter::StartsWithNoCase(attribute, store_value),
+                    NotStartsWithNoCase => {
+                        StoreFilter::NotStartsWithNoCase(attribute, store_value)
+                    }
+                    EndsWithNoCase => StoreFilter::EndsWithNoCase(attribute, store_value),
+                    NotEndsWithNoCase => StoreFilter::NotEndsWithNoCase(attribute, store_value),
+                    MatchesRegex => StoreFilter::MatchesRegex(attribute, store_value),
+                    NotMatchesRegex => StoreFilter::NotMatchesRegex(attribute, store_value),
                     Equal => StoreFilter::Equal(attribute, store_value),
-                }
+                    Child => unreachable!("child filters are built separately"),
+                })
             })
-            .collect::<Vec<StoreFilter>>(),
-    )
+            .collect::<Result<Vec<StoreFilter>, _>>()?,
+    ))
+}
+
+/// Parses the filter on the entities that a reference field points to into
+/// a StoreFilter.
+fn build_child_filter(
+    schema: &schema::Document,
+    key: &str,
+    attribute: String,
+    field_type: &schema::Type,
+    value: &q::Value,
+) -> Result<StoreFilter, UnsupportedFilter> {
+    let unsupported = || UnsupportedFilter {
+        filter: key.to_owned(),
+        value: Value::Null,
+    };
+    let child_type = match ast::get_named_type(schema, named_type_name(field_type)) {
+        Some(schema::TypeDefinition::Object(t)) => t,
+        _ => return Err(unsupported()),
+    };
+    let filter = match value {
+        q::Value::Object(object) => build_filter_from_object(schema, child_type, object)?,
+        _ => return Err(unsupported()),
+    };
+    Ok(StoreFilter::Child(
+        attribute,
+        build_entity_name(child_type),
+        Box::new(filter),
+    ))
 }
 
 /// Parses a list of GraphQL values into a vector of entity attribute values.
-fn list_values(value: Value) -> Vec<Value> {
+fn list_values(key: &str, value: Value) -> Result<Vec<Value>, UnsupportedFilter> {
     match value {
-        Value::List(values) => values,
-        _ => panic!("value is not a list"),
+        Value::List(values) => Ok(values),
+        value => Err(UnsupportedFilter {
+            filter: key.to_owned(),
+            value,
+        }),
     }
 }
 
-/// Parses GraphQL arguments into an attribute name to order by, if present.
-fn build_order_by(arguments: &HashMap<&q::Name, q::Value>) -> Option<String> {
+/// Parses GraphQL arguments into an attribute name to order by, if present,
+/// along with the type of its values.
+fn build_order_by(
+    entity: &schema::ObjectType,
+    arguments: &HashMap<&q::Name, q::Value>,
+) -> Option<(String, ValueType)> {
     arguments
         .get(&"orderBy".to_string())
         .and_then(|value| match value {
-            q::Value::Enum(name) => Some(name.to_owned()),
+            q::Value::Enum(name) => {
+                let value_type = ast::get_field_type(entity, name)
+                    .map_or(ValueType::String, |field| {
+                        ValueType::from_field_type(&field.field_type)
+                    });
+                Some((name.to_owned(), value_type))
+            }
             _ => None,
         })
 }
@@ -129,19 +254,35 @@ fn build_order_direction(arguments: &HashMap<&q::Name, q::Value>) -> Option<Stor
 
 /// Parses the subgraph ID from the ObjectType directives.
 pub fn build_subgraph_id(entity: &schema::ObjectType) -> Option<String> {
-    entity
-        .clone()
-        .directives
-        .into_iter()
-        .find(|directive| directive.name == "subgraphId".to_string())
+    subgraph_id_argument(&entity.directives, "id")
+}
+
+/// Returns the type under which the entities of an ObjectType are stored.
+/// This is the name of the type, unless the type was renamed when its
+/// schema was stitched together with others.
+pub fn build_entity_name(entity: &schema::ObjectType) -> String {
+    subgraph_id_argument(&entity.directives, "entity").unwrap_or_else(|| entity.name.to_owned())
+}
+
+/// Returns the name a root field has in the schema of its subgraph. This
+/// is the name of the field, unless the field was renamed when its schema
+/// was stitched together with others.
+pub fn build_field_name(field: &schema::Field) -> String {
+    subgraph_id_argument(&field.directives, "field").unwrap_or_else(|| field.name.to_owned())
+}
+
+fn subgraph_id_argument(directives: &[schema::Directive], argument: &str) -> Option<String> {
+    directives
+        .iter()
+        .find(|directive| directive.name == "subgraphId")
         .and_then(|directive| {
             directive
                 .arguments
-                .into_iter()
-                .find(|(name, _)| name == &"id".to_string())
+                .iter()
+                .find(|(name, _)| name == argument)
         })
         .and_then(|(_, value)| match value {
-            schema::Value::String(id) => Some(id),
+            schema::Value::String(value) => Some(value.to_owned()),
             _ => None,
         })
 }
@@ -158,7 +299,7 @@ mod tests {
 
     use graph::prelude::*;
 
-    use super::build_query;
+    use super::{build_connection_query, build_query};
 
     fn default_object() -> ObjectType {
         let subgraph_id_argument = (
@@ -180,6 +321,12 @@ mod tests {
         }
     }
 
+    fn schema() -> schema::Document {
+        schema::Document {
+            definitions: vec![],
+        }
+    }
+
     fn object(name: &str) -> ObjectType {
         ObjectType {
             name: name.to_owned(),
@@ -201,23 +348,36 @@ mod tests {
     #[test]
     fn build_query_uses_the_entity_name() {
         assert_eq!(
-            build_query(&object("Entity1"), &HashMap::new()).entity,
+            build_query(&schema(), &object("Entity1"), &HashMap::new()).unwrap().entity,
             "Entity1".to_string()
         );
         assert_eq!(
-            build_query(&object("Entity2"), &HashMap::new()).entity,
+            build_query(&schema(), &object("Entity2"), &HashMap::new()).unwrap().entity,
             "Entity2".to_string()
         );
     }
 
+    #[test]
+    fn build_query_uses_the_stored_entity_name_of_renamed_types() {
+        let mut renamed = object("music_Band");
+        renamed.directives[0].arguments.push((
+            schema::Name::from("entity"),
+            schema::Value::String("Band".to_string()),
+        ));
+        assert_eq!(
+            build_query(&schema(), &renamed, &HashMap::new()).unwrap().entity,
+            "Band".to_string()
+        );
+    }
+
     #[test]
     fn build_query_yields_no_order_if_order_arguments_are_missing() {
         assert_eq!(
-            build_query(&default_object(), &HashMap::new()).order_by,
+            build_query(&schema(), &default_object(), &HashMap::new()).unwrap().order_by,
             None,
         );
         assert_eq!(
-            build_query(&default_object(), &HashMap::new()).order_direction,
+            build_query(&schema(), &default_object(), &HashMap::new()).unwrap().order_direction,
             None,
         );
     }
@@ -226,38 +386,69 @@ mod tests {
     fn build_query_parses_order_by_from_enum_values_correctly() {
         assert_eq!(
             build_query(
+                &schema(),
                 &default_object(),
                 &HashMap::from_iter(
                     vec![(&"orderBy".to_string(), q::Value::Enum("name".to_string()))].into_iter(),
                 )
-            ).order_by,
-            Some("name".to_string())
+            ).unwrap().order_by,
+            Some(("name".to_string(), ValueType::String))
         );
         assert_eq!(
             build_query(
+                &schema(),
                 &default_object(),
                 &HashMap::from_iter(
                     vec![(&"orderBy".to_string(), q::Value::Enum("email".to_string()))].into_iter()
                 )
-            ).order_by,
-            Some("email".to_string())
+            ).unwrap().order_by,
+            Some(("email".to_string(), ValueType::String))
+        );
+    }
+
+    #[test]
+    fn build_query_orders_by_the_type_of_the_field() {
+        let mut entity = default_object();
+        entity.fields.push(field(
+            "supply",
+            Type::NonNullType(Box::new(Type::NamedType("BigInt".to_owned()))),
+        ));
+        entity.fields.push(field(
+            "tags",
+            Type::ListType(Box::new(Type::NamedType("String".to_owned()))),
+        ));
+        let order_by = |name: &str| {
+            build_query(
+                &schema(),
+                &entity,
+                &HashMap::from_iter(
+                    vec![(&"orderBy".to_string(), q::Value::Enum(name.to_string()))].into_iter(),
+                ),
+            ).unwrap().order_by
+        };
+        assert_eq!(
+            order_by("supply"),
+            Some(("supply".to_string(), ValueType::BigInt))
         );
+        assert_eq!(order_by("tags"), Some(("tags".to_string(), ValueType::List)));
     }
 
     #[test]
     fn build_query_ignores_order_by_from_non_enum_values() {
         assert_eq!(
             build_query(
+                &schema(),
                 &default_object(),
                 &HashMap::from_iter(
                     vec![(&"orderBy".to_string(), q::Value::String("name".to_string()))]
                         .into_iter()
                 ),
-            ).order_by,
+            ).unwrap().order_by,
             None,
         );
         assert_eq!(
             build_query(
+                &schema(),
                 &default_object(),
                 &HashMap::from_iter(
                     vec![(
@@ -265,7 +456,7 @@ mod tests {
                         q::Value::String("email".to_string()),
                     )].into_iter(),
                 )
-            ).order_by,
+            ).unwrap().order_by,
             None,
         );
     }
@@ -274,6 +465,7 @@ mod tests {
     fn build_query_parses_order_direction_from_enum_values_correctly() {
         assert_eq!(
             build_query(
+                &schema(),
                 &default_object(),
                 &HashMap::from_iter(
                     vec![(
@@ -281,11 +473,12 @@ mod tests {
                         q::Value::Enum("asc".to_string()),
                     )].into_iter(),
                 )
-            ).order_direction,
+            ).unwrap().order_direction,
             Some(StoreOrder::Ascending)
         );
         assert_eq!(
             build_query(
+                &schema(),
                 &default_object(),
                 &HashMap::from_iter(
                     vec![(
@@ -293,11 +486,12 @@ mod tests {
                         q::Value::Enum("desc".to_string()),
                     )].into_iter()
                 )
-            ).order_direction,
+            ).unwrap().order_direction,
             Some(StoreOrder::Descending)
         );
         assert_eq!(
             build_query(
+                &schema(),
                 &default_object(),
                 &HashMap::from_iter(
                     vec![(
@@ -305,7 +499,7 @@ mod tests {
                         q::Value::Enum("ascending...".to_string()),
                     )].into_iter()
                 )
-            ).order_direction,
+            ).unwrap().order_direction,
             None,
         );
     }
@@ -314,6 +508,7 @@ mod tests {
     fn build_query_ignores_order_direction_from_non_enum_values() {
         assert_eq!(
             build_query(
+                &schema(),
                 &default_object(),
                 &HashMap::from_iter(
                     vec![(
@@ -321,11 +516,12 @@ mod tests {
                         q::Value::String("asc".to_string()),
                     )].into_iter()
                 ),
-            ).order_direction,
+            ).unwrap().order_direction,
             None,
         );
         assert_eq!(
             build_query(
+                &schema(),
                 &default_object(),
                 &HashMap::from_iter(
                     vec![(
@@ -333,25 +529,31 @@ mod tests {
                         q::Value::String("desc".to_string()),
                     )].into_iter(),
                 )
-            ).order_direction,
+            ).unwrap().order_direction,
             None,
         );
     }
 
     #[test]
     fn build_query_yields_no_range_if_none_is_present() {
-        assert_eq!(build_query(&default_object(), &HashMap::new()).range, None,);
+        assert_eq!(
+            build_query(&schema(), &default_object(), &HashMap::new())
+                .unwrap()
+                .range,
+            None,
+        );
     }
 
     #[test]
     fn build_query_yields_default_first_if_only_skip_is_present() {
         assert_eq!(
             build_query(
+                &schema(),
                 &default_object(),
                 &HashMap::from_iter(
                     vec![(&"skip".to_string(), q::Value::Int(q::Number::from(50)))].into_iter()
                 )
-            ).range,
+            ).unwrap().range,
             Some(StoreRange {
                 first: 100,
                 skip: 50,
@@ -363,11 +565,12 @@ mod tests {
     fn build_query_yields_default_skip_if_only_first_is_present() {
         assert_eq!(
             build_query(
+                &schema(),
                 &default_object(),
                 &HashMap::from_iter(
                     vec![(&"first".to_string(), q::Value::Int(q::Number::from(70)))].into_iter()
                 )
-            ).range,
+            ).unwrap().range,
             Some(StoreRange { first: 70, skip: 0 }),
         );
     }
@@ -376,6 +579,7 @@ mod tests {
     fn build_query_yields_filters() {
         assert_eq!(
             build_query(
+                &schema(),
                 &ObjectType {
                     fields: vec![field("name", Type::NamedType("string".to_owned()))],
                     ..default_object()
@@ -389,11 +593,105 @@ mod tests {
                         )])),
                     )].into_iter(),
                 )
-            ).filter,
+            ).unwrap().filter,
             Some(StoreFilter::And(vec![StoreFilter::EndsWith(
                 "name".to_string(),
                 Value::String("ello".to_string()),
             )]))
         )
     }
+
+    #[test]
+    fn build_query_yields_child_filters() {
+        let user = ObjectType {
+            fields: vec![field("name", Type::NamedType("String".to_owned()))],
+            ..object("User")
+        };
+        let document = schema::Document {
+            definitions: vec![schema::Definition::TypeDefinition(
+                schema::TypeDefinition::Object(user),
+            )],
+        };
+
+        assert_eq!(
+            build_query(
+                &document,
+                &ObjectType {
+                    fields: vec![field("owner", Type::NamedType("User".to_owned()))],
+                    ..object("Token")
+                },
+                &HashMap::from_iter(
+                    vec![(
+                        &"where".to_string(),
+                        q::Value::Object(BTreeMap::from_iter(vec![(
+                            "owner_".to_string(),
+                            q::Value::Object(BTreeMap::from_iter(vec![(
+                                "name".to_string(),
+                                q::Value::String("x".to_string()),
+                            )])),
+                        )])),
+                    )].into_iter(),
+                )
+            ).unwrap().filter,
+            Some(StoreFilter::And(vec![StoreFilter::Child(
+                "owner".to_string(),
+                "User".to_string(),
+                Box::new(StoreFilter::And(vec![StoreFilter::Equal(
+                    "name".to_string(),
+                    Value::String("x".to_string()),
+                )])),
+            )]))
+        )
+    }
+
+    #[test]
+    fn build_connection_query_continues_after_the_cursor() {
+        let (query, first) = build_connection_query(
+            &schema(),
+            &default_object(),
+            &HashMap::from_iter(
+                vec![
+                    (&"first".to_string(), q::Value::Int(q::Number::from(10))),
+                    (&"after".to_string(), q::Value::String("m2".to_string())),
+                ].into_iter(),
+            ),
+        ).unwrap();
+
+        assert_eq!(first, 10);
+        assert_eq!(query.range, Some(StoreRange { first: 11, skip: 0 }));
+        assert_eq!(query.order_by, Some(("id".to_string(), ValueType::String)));
+        assert_eq!(
+            query.filter,
+            Some(StoreFilter::And(vec![StoreFilter::GreaterThan(
+                "id".to_string(),
+                Value::String("m2".to_string()),
+            )]))
+        );
+    }
+
+    #[test]
+    fn build_query_rejects_list_filters_without_a_list() {
+        assert_eq!(
+            build_query(
+                &schema(),
+                &ObjectType {
+                    fields: vec![field("name", Type::NamedType("String".to_owned()))],
+                    ..default_object()
+                },
+                &HashMap::from_iter(
+                    vec![(
+                        &"where".to_string(),
+                        q::Value::Object(BTreeMap::from_iter(vec![(
+                            "name_in".to_string(),
+                            q::Value::String("Alice".to_string()),
+                        )])),
+                    )].into_iter(),
+                )
+            ).unwrap_err(),
+            UnsupportedFilter {
+                filter: "name_in".to_string(),
+                value: Value::String("Alice".to_string()),
+            }
+        );
+    }
 }
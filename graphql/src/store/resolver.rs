@@ -1,21 +1,46 @@
 use graphql_parser::{query as q, schema as s};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::ops::Deref;
-use std::sync::{Arc, Mutex};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Instant;
 
+use graph::components::ethereum::EthereumBlockHeader;
 use graph::components::store::*;
-use graph::prelude::{slog::*, BasicStore, Value};
+use graph::data::store::scalar::BigDecimal;
+use graph::prelude::{
+    slog::*, BasicStore, Entity, QueryExecutionError, QueryPlan, QueryPlanStep, QueryTrace,
+    StoreQueryTrace, Value,
+};
 
 use prelude::*;
 use query::ast as qast;
+use schema::api::{aggregatable_fields, META_TYPE_NAME};
 use schema::ast as sast;
-use store::query::build_subgraph_id;
+use store::prefetch::prefetch;
+use store::query::{
+    build_connection_query, build_entity_name, build_field_name, build_fulltext_search,
+    build_subgraph_id,
+};
 
 /// A resolver that fetches entities from a `Store`.
 #[derive(Clone)]
 pub struct StoreResolver {
     logger: Logger,
     store: Arc<Mutex<BasicStore>>,
+    /// The store queries made so far, if they are being recorded.
+    plan: Option<Arc<Mutex<QueryPlan>>>,
+    /// The store queries made so far, along with when they were made, if
+    /// the query is being traced.
+    traced_steps: Option<Arc<Mutex<Vec<(Instant, QueryPlanStep)>>>>,
+    /// The time by which the query must be resolved, if any.
+    deadline: Option<Instant>,
+    /// The indexing state of each subgraph deployment that the `_meta`
+    /// field reports on.
+    metas: Vec<q::Value>,
+    /// The number of the block whose entities the query sees, if it is not
+    /// the latest one.
+    block: Option<u64>,
 }
 
 impl StoreResolver {
@@ -23,12 +48,176 @@ impl StoreResolver {
         StoreResolver {
             logger: logger.new(o!("component" => "StoreResolver")),
             store,
+            plan: None,
+            traced_steps: None,
+            deadline: None,
+            metas: vec![],
+            block: None,
+        }
+    }
+
+    /// Records the store queries the resolver makes, so that they can be
+    /// returned as the plan of the query.
+    pub fn with_query_plan(mut self) -> Self {
+        self.plan = Some(Arc::new(Mutex::new(QueryPlan::default())));
+        self
+    }
+
+    /// Records when the resolver makes which store queries, so that they can
+    /// be returned with the trace of the query.
+    pub fn with_trace(mut self) -> Self {
+        self.traced_steps = Some(Arc::new(Mutex::new(vec![])));
+        self
+    }
+
+    /// Stops querying the store once `deadline` has passed, and lets every
+    /// store query only run for the time that is left until then.
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Looks up the entities as they were at the block with the given number
+    /// instead of their latest versions.
+    pub fn with_block(mut self, block: Option<u64>) -> Self {
+        self.block = block;
+        self
+    }
+
+    /// Resolves the `_meta` field to the indexing state of the subgraph
+    /// deployment, with the latest block it has indexed if that is known.
+    /// The `_meta` field of a stitched schema lists the state of every
+    /// deployment this is called for, in the same order.
+    pub fn with_meta(mut self, deployment: String, block: Option<EthereumBlockHeader>) -> Self {
+        let block = block.map_or(q::Value::Null, |block| {
+            object_value(vec![
+                ("number", q::Value::Int(q::Number::from(block.number as i32))),
+                ("hash", q::Value::String(format!("{:#x}", block.hash))),
+                ("timestamp", q::Value::Int(q::Number::from(block.timestamp as i32))),
+            ])
+        });
+        self.metas.push(object_value(vec![
+            ("block", block),
+            ("deployment", q::Value::String(deployment)),
+            // Failed handlers stop the subgraph instead of being recorded
+            ("hasIndexingErrors", q::Value::Boolean(false)),
+        ]));
+        self
+    }
+
+    /// Fails if the store cannot run the query, e.g. because it cannot
+    /// apply its filter, with the reason rather than a store error.
+    fn check_query(&self, query: &StoreQuery) -> Result<(), QueryExecutionError> {
+        self.lock_store()?.check_query(query)
+    }
+
+    /// Returns the scope that store lookups of the query are made in, with
+    /// the time that is left until the deadline, unless the deadline of the
+    /// query has passed already.
+    fn scope(&self) -> Result<QueryScope, QueryExecutionError> {
+        let timeout = match self.deadline {
+            Some(deadline) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    return Err(QueryExecutionError::TimeoutError);
+                }
+                Some(deadline - now)
+            }
+            None => None,
+        };
+        Ok(QueryScope {
+            block: self.block,
+            timeout,
+        })
+    }
+
+    /// Makes a store query in the scope of the query.
+    fn scoped(&self, mut query: StoreQuery) -> Result<StoreQuery, QueryExecutionError> {
+        query.scope = self.scope()?;
+        Ok(query)
+    }
+
+    /// Locks the store for querying it, unless the deadline of the query has
+    /// passed already. The store is shared by all queries, so every lookup
+    /// has to carry the scope of the query itself.
+    fn lock_store(&self) -> Result<MutexGuard<BasicStore + 'static>, QueryExecutionError> {
+        self.scope()?;
+        Ok(self.store.lock().unwrap())
+    }
+
+    /// Looks up the entity of an object type by its ID in the scope of the
+    /// query. The store doesn't tell entities that don't exist apart from
+    /// lookups that fail, so the object is null either way.
+    fn get_object(
+        &self,
+        object_type: &s::ObjectType,
+        id: &str,
+    ) -> Result<q::Value, QueryExecutionError> {
+        let subgraph = build_subgraph_id(object_type).expect(
+            format!("Failed to get subgraph ID from type: {}", object_type.name).as_str(),
+        );
+        let entity_type = build_entity_name(object_type);
+        let mut ids_for_type = BTreeMap::new();
+        ids_for_type.insert(entity_type.clone(), vec![id.to_owned()]);
+
+        let entity = self
+            .lock_store()?
+            .get_many(&subgraph, ids_for_type, self.scope()?)
+            .ok()
+            .and_then(|mut entities_for_type| entities_for_type.remove(&entity_type))
+            .and_then(|mut entities| entities.pop());
+        Ok(entity.map_or(q::Value::Null, |entity| {
+            Self::typed_object(entity, &[object_type])
+        }))
+    }
+
+    /// Adds a store query to the plan if the plan is being recorded, and to
+    /// the trace if the query is being traced. The SQL is only included in
+    /// the plan, for queries that are run with `find`.
+    fn record_step(&self, field: &str, entity: &str, strategy: &str, query: Option<&StoreQuery>) {
+        if self.plan.is_none() && self.traced_steps.is_none() {
+            return;
+        }
+
+        let step = QueryPlanStep {
+            field: field.to_owned(),
+            entity: entity.to_owned(),
+            strategy: strategy.to_owned(),
+            sql: None,
+        };
+        if let Some(ref traced_steps) = self.traced_steps {
+            traced_steps
+                .lock()
+                .unwrap()
+                .push((Instant::now(), step.clone()));
+        }
+        if let Some(ref plan) = self.plan {
+            let sql = query.and_then(|query| self.store.lock().unwrap().explain(query.clone()));
+            plan.lock()
+                .unwrap()
+                .steps
+                .push(QueryPlanStep { sql, ..step });
+        }
+    }
+
+    /// Adds the fields that are prefetched, and the fields prefetched for
+    /// them in turn, to the plan.
+    fn record_prefetch_steps(&self, path: Option<&str>, fields: &[PrefetchField]) {
+        for field in fields {
+            let path = match path {
+                Some(path) => format!("{}.{}", path, field.name),
+                None => field.name.to_owned(),
+            };
+            self.record_step(&path, &field.object_type.name, "prefetch", None);
+            self.record_prefetch_steps(Some(&path), &field.children);
         }
     }
 
     /// If the field has a `@derivedFrom(field: "foo")` directive, obtain the
     /// name of the field (e.g. `"foo"`)
-    fn get_derived_from_directive(field_definition: &s::Field) -> Option<&s::Directive> {
+    pub(crate) fn get_derived_from_directive(
+        field_definition: &s::Field,
+    ) -> Option<&s::Directive> {
         field_definition
             .directives
             .iter()
@@ -160,6 +349,90 @@ impl StoreResolver {
         }
     }
 
+    /// Returns the objects that the parent object already holds for the given
+    /// field, e.g. the edges and nodes of a connection, rather than references.
+    fn resolved_objects<'a>(
+        parent: &'a Option<q::Value>,
+        field: &q::Name,
+    ) -> Option<&'a q::Value> {
+        parent
+            .as_ref()
+            .and_then(|value| match value {
+                q::Value::Object(object) => object.get(field),
+                _ => None,
+            })
+            .filter(|value| match value {
+                q::Value::Object(_) => true,
+                q::Value::List(values) => values.iter().all(|value| match value {
+                    q::Value::Object(_) => true,
+                    _ => false,
+                }),
+                _ => false,
+            })
+    }
+
+    /// Converts an entity of one of the given object types into an object,
+    /// with the name of its object type in `__typename`. Entity types only
+    /// differ from the names of their object types in stitched schemas.
+    fn typed_object(mut entity: Entity, object_types: &[&s::ObjectType]) -> q::Value {
+        let object_type = match entity.get("__typename") {
+            Some(Value::String(entity_type)) => object_types
+                .iter()
+                .find(|object_type| &build_entity_name(object_type) == entity_type),
+            _ => None,
+        };
+        if let Some(object_type) = object_type {
+            entity.insert(String::from("__typename"), Value::from(object_type.name.as_str()));
+        }
+        entity.into()
+    }
+
+    /// Returns the IDs that the parent object references in the given field,
+    /// if the field holds a list of references.
+    fn referenced_ids(parent: &Option<q::Value>, field: &q::Name) -> Option<Vec<String>> {
+        match parent {
+            Some(q::Value::Object(object)) => match object.get(field) {
+                Some(q::Value::List(values)) => values
+                    .iter()
+                    .map(|value| match value {
+                        q::Value::String(id) => Some(id.to_owned()),
+                        _ => None,
+                    })
+                    .collect(),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Fetches referenced entities by their IDs, in the order in which they
+    /// are referenced.
+    fn resolve_references(
+        &self,
+        query: StoreQuery,
+        ids: Vec<String>,
+    ) -> Result<q::Value, QueryExecutionError> {
+        let mut ids_for_type = BTreeMap::new();
+        ids_for_type.insert(query.entity.clone(), ids.clone());
+
+        self.lock_store()?
+            .get_many(&query.subgraph, ids_for_type, self.scope()?)
+            .map(|mut entities_for_type| {
+                let entities = entities_for_type.remove(&query.entity).unwrap_or(vec![]);
+                q::Value::List(
+                    ids.iter()
+                        .filter_map(|id| {
+                            entities
+                                .iter()
+                                .find(|entity| entity.get("id") == Some(&Value::from(id)))
+                        })
+                        .map(|entity| entity.clone().into())
+                        .collect(),
+                )
+            })
+            .map_err(|()| QueryExecutionError::StoreError(query.entity.clone()))
+    }
+
     /// Returns true if the object has no references in the given field.
     fn references_field_is_empty(parent: &Option<q::Value>, field: &q::Name) -> bool {
         parent
@@ -178,15 +451,78 @@ impl StoreResolver {
 }
 
 impl Resolver for StoreResolver {
+    fn query_plan(&self) -> Option<QueryPlan> {
+        self.plan.as_ref().map(|plan| plan.lock().unwrap().clone())
+    }
+
+    fn store_query_trace(&self, start: Instant) -> Vec<StoreQueryTrace> {
+        let traced_steps = match self.traced_steps {
+            Some(ref traced_steps) => traced_steps.lock().unwrap(),
+            None => return vec![],
+        };
+        traced_steps
+            .iter()
+            .map(|(made_at, step)| StoreQueryTrace {
+                field: step.field.clone(),
+                entity: step.entity.clone(),
+                strategy: step.strategy.clone(),
+                start_offset: if *made_at > start {
+                    QueryTrace::nanos(*made_at - start)
+                } else {
+                    0
+                },
+            })
+            .collect()
+    }
+
+    fn prefetch(
+        &self,
+        schema: &s::Document,
+        objects: &mut Vec<q::Value>,
+        fields: &[PrefetchField],
+    ) {
+        if self.plan.is_some() || self.traced_steps.is_some() {
+            self.record_prefetch_steps(None, fields);
+        }
+
+        // Fields that can't be prefetched are resolved, and fail, one
+        // object at a time
+        if let (Ok(store), Ok(scope)) = (self.lock_store(), self.scope()) {
+            prefetch(&*store, scope, schema, objects, fields)
+        }
+    }
+
     fn resolve_objects(
         &self,
+        schema: &s::Document,
         parent: &Option<q::Value>,
-        _field: &q::Name,
+        field: &q::Name,
         field_definition: &s::Field,
         object_type: &s::ObjectType,
         arguments: &HashMap<&q::Name, q::Value>,
-    ) -> q::Value {
-        let mut query = build_query(&object_type, arguments);
+    ) -> Result<q::Value, QueryExecutionError> {
+        if object_type.name == META_TYPE_NAME {
+            return Ok(q::Value::List(self.metas.clone()));
+        }
+
+        if let Some(objects) = Self::resolved_objects(parent, &field_definition.name) {
+            return Ok(objects.clone());
+        }
+
+        let mut query = build_query(schema, &object_type, arguments)?;
+        self.check_query(&query)?;
+        let store_error = QueryExecutionError::StoreError(query.entity.clone());
+
+        // Full-text search fields are ranked by the store
+        let field_name = build_field_name(field_definition);
+        if let Some(search) = build_fulltext_search(object_type, &field_name, arguments) {
+            self.record_step(field, &query.entity, "search", None);
+            return self
+                .lock_store()?
+                .search(self.scoped(query)?, search)
+                .map(|entities| q::Value::List(entities.into_iter().map(|e| e.into()).collect()))
+                .map_err(|()| store_error);
+        }
 
         // Add matching filter for derived fields
         let is_derived =
@@ -199,7 +535,19 @@ impl Resolver for StoreResolver {
             && parent.is_some()
             && Self::references_field_is_empty(parent, &field_definition.name)
         {
-            return q::Value::List(vec![]);
+            return Ok(q::Value::List(vec![]));
+        }
+
+        // Fetch plain lists of references in one go, without a filter for each ID
+        if !is_derived
+            && query.filter.is_none()
+            && query.order_by.is_none()
+            && query.range.is_none()
+        {
+            if let Some(ids) = Self::referenced_ids(parent, &field_definition.name) {
+                self.record_step(field, &query.entity, "get", None);
+                return self.resolve_references(query, ids);
+            }
         }
 
         // Add matching filter for reference fields
@@ -207,9 +555,9 @@ impl Resolver for StoreResolver {
             Self::add_filter_for_reference_field(&mut query, parent, field_definition, object_type);
         }
 
-        let store = self.store.lock().unwrap();
-        store
-            .find(query)
+        self.record_step(field, &query.entity, "find", Some(&query));
+        self.lock_store()?
+            .find(self.scoped(query)?)
             .map(|entities| {
                 q::Value::List(
                     entities
@@ -218,57 +566,96 @@ impl Resolver for StoreResolver {
                         .collect::<Vec<q::Value>>(),
                 )
             })
-            .unwrap_or(q::Value::Null)
+            .map_err(|()| store_error)
+    }
+
+    fn resolve_abstract_objects(
+        &self,
+        schema: &s::Document,
+        parent: &Option<q::Value>,
+        field: &q::Name,
+        field_definition: &s::Field,
+        object_types: &[&s::ObjectType],
+        arguments: &HashMap<&q::Name, q::Value>,
+    ) -> Result<q::Value, QueryExecutionError> {
+        if let Some(objects) = Self::resolved_objects(parent, &field_definition.name) {
+            return Ok(objects.clone());
+        }
+
+        // All object types have the fields of the interface or union that can
+        // be filtered and ordered by, so the query can be built from any of them
+        let object_type = match object_types.first() {
+            Some(object_type) => *object_type,
+            None => return Ok(q::Value::List(vec![])),
+        };
+        let mut query = build_query(schema, object_type, arguments)?;
+        self.check_query(&query)?;
+
+        let is_derived =
+            Self::add_filter_for_derived_field(&mut query, parent, field_definition, object_type);
+        if !is_derived {
+            if parent.is_some() && Self::references_field_is_empty(parent, &field_definition.name)
+            {
+                return Ok(q::Value::List(vec![]));
+            }
+            Self::add_filter_for_reference_field(&mut query, parent, field_definition, object_type);
+        }
+
+        // Let the store order and page the entities of all types together
+        let entity_types = object_types
+            .iter()
+            .map(|object_type| build_entity_name(object_type))
+            .collect::<Vec<_>>();
+        let entity_type_names = entity_types.join(", ");
+        self.record_step(field, &entity_type_names, "find_any", None);
+        self.lock_store()?
+            .find_any(self.scoped(query)?, entity_types)
+            .map(|entities| {
+                q::Value::List(
+                    entities
+                        .into_iter()
+                        .map(|entity| Self::typed_object(entity, object_types))
+                        .collect(),
+                )
+            })
+            .map_err(|()| QueryExecutionError::StoreError(entity_type_names))
     }
 
     fn resolve_object(
         &self,
+        schema: &s::Document,
         parent: &Option<q::Value>,
         field: &q::Name,
         field_definition: &s::Field,
         object_type: &s::ObjectType,
         arguments: &HashMap<&q::Name, q::Value>,
-    ) -> q::Value {
+    ) -> Result<q::Value, QueryExecutionError> {
+        if object_type.name == META_TYPE_NAME {
+            return Ok(self.metas.first().cloned().unwrap_or(q::Value::Null));
+        }
+
         let id = arguments.get(&"id".to_string()).and_then(|id| match id {
             q::Value::String(s) => Some(s),
             _ => None,
         });
 
         if let Some(id) = id {
-            let store = self.store.lock().unwrap();
-            return store
-                .get(StoreKey {
-                    subgraph: build_subgraph_id(object_type).expect(
-                        format!("Failed to get subgraph ID from type: {}", object_type.name)
-                            .as_str(),
-                    ),
-                    entity: object_type.name.to_owned(),
-                    id: id.to_owned(),
-                })
-                .map(|entity| entity.into())
-                .unwrap_or(q::Value::Null);
+            self.record_step(field, &object_type.name, "get", None);
+            return self.get_object(object_type, id);
         }
 
         match parent {
             Some(q::Value::Object(parent_object)) => match parent_object.get(field) {
-                Some(q::Value::String(id)) => self
-                    .store
-                    .lock()
-                    .unwrap()
-                    .get(StoreKey {
-                        subgraph: build_subgraph_id(object_type).expect(
-                            format!("Failed to get subgraph ID from type: {}", object_type.name)
-                                .as_str(),
-                        ),
-                        entity: object_type.name.to_owned(),
-                        id: id.to_owned(),
-                    })
-                    .map(|entity| entity.into())
-                    .unwrap_or(q::Value::Null),
-                _ => q::Value::Null,
+                Some(q::Value::String(id)) => {
+                    self.record_step(field, &object_type.name, "get", None);
+                    self.get_object(object_type, id)
+                }
+                Some(object @ q::Value::Object(_)) => Ok(object.clone()),
+                _ => Ok(q::Value::Null),
             },
             _ => {
-                let mut query = build_query(&object_type, arguments);
+                let mut query = build_query(schema, &object_type, arguments)?;
+                self.check_query(&query)?;
 
                 // Add matching filter for derived fields
                 Self::add_filter_for_derived_field(
@@ -280,19 +667,172 @@ impl Resolver for StoreResolver {
 
                 query.range = Some(StoreRange { first: 1, skip: 0 });
 
-                self.store
-                    .lock()
-                    .unwrap()
-                    .find(query)
+                self.record_step(field, &query.entity, "find", Some(&query));
+                let store_error = QueryExecutionError::StoreError(query.entity.clone());
+                self.lock_store()?
+                    .find(self.scoped(query)?)
                     .map(|entities| {
                         entities
                             .into_iter()
                             .next()
-                            .map(|entity| entity.into())
+                            .map(|entity| Self::typed_object(entity, &[object_type]))
                             .unwrap_or(q::Value::Null)
                     })
-                    .unwrap_or(q::Value::Null)
+                    .map_err(|()| store_error)
             }
         }
     }
+
+    fn resolve_connection(
+        &self,
+        schema: &s::Document,
+        _parent: &Option<q::Value>,
+        field: &q::Name,
+        _field_definition: &s::Field,
+        node_type: &s::ObjectType,
+        arguments: &HashMap<&q::Name, q::Value>,
+    ) -> Result<q::Value, QueryExecutionError> {
+        let (query, first) = build_connection_query(schema, node_type, arguments)?;
+        self.check_query(&query)?;
+        self.record_step(field, &query.entity, "find", Some(&query));
+
+        let store_error = QueryExecutionError::StoreError(query.entity.clone());
+        let mut entities = self
+            .lock_store()?
+            .find(self.scoped(query)?)
+            .map_err(|()| store_error)?;
+        let has_next_page = entities.len() > first;
+        entities.truncate(first);
+
+        let edges: Vec<q::Value> = entities
+            .into_iter()
+            .map(|entity| {
+                let cursor = entity
+                    .get("id")
+                    .cloned()
+                    .map_or(q::Value::Null, |id| id.into());
+                object_value(vec![("cursor", cursor), ("node", entity.into())])
+            })
+            .collect();
+
+        let end_cursor = edges
+            .last()
+            .and_then(|edge| match edge {
+                q::Value::Object(edge) => edge.get("cursor").cloned(),
+                _ => None,
+            })
+            .unwrap_or(q::Value::Null);
+
+        Ok(object_value(vec![
+            ("edges", q::Value::List(edges)),
+            (
+                "pageInfo",
+                object_value(vec![
+                    ("hasNextPage", q::Value::Boolean(has_next_page)),
+                    ("endCursor", end_cursor),
+                ]),
+            ),
+        ]))
+    }
+
+    fn resolve_aggregate(
+        &self,
+        schema: &s::Document,
+        _parent: &Option<q::Value>,
+        field: &q::Name,
+        _field_definition: &s::Field,
+        entity_type: &s::ObjectType,
+        arguments: &HashMap<&q::Name, q::Value>,
+    ) -> Result<q::Value, QueryExecutionError> {
+        let query = build_query(schema, entity_type, arguments)?;
+        self.check_query(&query)?;
+
+        // All aggregates are computed in one go, whether they are selected or not
+        let attributes: Vec<_> = aggregatable_fields(&entity_type.fields)
+            .into_iter()
+            .map(|field| field.name)
+            .collect();
+        let mut aggregates = vec![StoreAggregate::Count];
+        for attribute in attributes.iter() {
+            aggregates.push(StoreAggregate::Sum(attribute.clone()));
+            aggregates.push(StoreAggregate::Min(attribute.clone()));
+            aggregates.push(StoreAggregate::Max(attribute.clone()));
+        }
+
+        self.record_step(field, &query.entity, "aggregate", None);
+        let store_error = QueryExecutionError::StoreError(query.entity.clone());
+        let mut values = self
+            .lock_store()?
+            .aggregate(self.scoped(query)?, aggregates)
+            .map_err(|()| store_error)?
+            .into_iter()
+            .map(q::Value::from);
+
+        let count = values.next().unwrap_or(q::Value::Null);
+        if attributes.is_empty() {
+            return Ok(object_value(vec![("count", count)]));
+        }
+
+        let (mut sums, mut mins, mut maxs) = (vec![], vec![], vec![]);
+        for attribute in attributes.iter() {
+            sums.push((attribute.as_str(), values.next().unwrap_or(q::Value::Null)));
+            mins.push((attribute.as_str(), values.next().unwrap_or(q::Value::Null)));
+            maxs.push((attribute.as_str(), values.next().unwrap_or(q::Value::Null)));
+        }
+        Ok(object_value(vec![
+            ("count", count),
+            ("sum", object_value(sums)),
+            ("min", object_value(mins)),
+            ("max", object_value(maxs)),
+        ]))
+    }
+
+    fn resolve_histogram(
+        &self,
+        schema: &s::Document,
+        _parent: &Option<q::Value>,
+        field: &q::Name,
+        _field_definition: &s::Field,
+        entity_type: &s::ObjectType,
+        arguments: &HashMap<&q::Name, q::Value>,
+    ) -> Result<q::Value, QueryExecutionError> {
+        let attribute = match arguments.get(&"field".to_string()) {
+            Some(q::Value::Enum(attribute)) => attribute.to_owned(),
+            _ => return Ok(q::Value::Null),
+        };
+        let size = match arguments.get(&"size".to_string()) {
+            Some(q::Value::String(size)) => match size.parse::<BigDecimal>() {
+                Ok(size) => size,
+                Err(_) => return Ok(q::Value::Null),
+            },
+            _ => return Ok(q::Value::Null),
+        };
+        if size <= BigDecimal::from_str("0").unwrap() {
+            return Ok(q::Value::Null);
+        }
+
+        let query = build_query(schema, entity_type, arguments)?;
+        self.check_query(&query)?;
+        self.record_step(field, &query.entity, "histogram", None);
+        let store_error = QueryExecutionError::StoreError(query.entity.clone());
+        let buckets = self
+            .lock_store()?
+            .histogram(self.scoped(query)?, attribute, size)
+            .map_err(|()| store_error)?;
+
+        Ok(object_value(vec![(
+            "buckets",
+            q::Value::List(
+                buckets
+                    .into_iter()
+                    .map(|(start, count)| {
+                        object_value(vec![
+                            ("start", q::Value::String(start.to_string())),
+                            ("count", q::Value::Int(q::Number::from(count))),
+                        ])
+                    })
+                    .collect(),
+            ),
+        )]))
+    }
 }
@@ -0,0 +1,222 @@
+use futures::sync::mpsc::{channel, Receiver, Sender};
+use graphql_parser::{query as q, schema as s};
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use graph::prelude::*;
+
+use prelude::*;
+use query::ast as qast;
+use schema::api::named_type_name;
+use schema::ast as sast;
+
+/// Number of results that may be queued up for a subscriber before further
+/// updates are dropped.
+const RESULT_BUFFER_SIZE: usize = 100;
+
+/// A query that a client keeps receiving results for.
+struct Subscription {
+    query: Query,
+    /// The entity types the query reads; `None` if any change may affect it.
+    entity_types: Option<HashSet<String>>,
+    sender: Sender<QueryResult>,
+    /// The data that was last sent to the subscriber.
+    data: Option<q::Value>,
+}
+
+/// Keeps the results of GraphQL subscriptions up to date by re-running
+/// them whenever the store reports a change to an entity they read.
+pub struct SubscriptionManager<S> {
+    logger: Logger,
+    store: Arc<Mutex<S>>,
+    subscriptions: Mutex<Vec<Subscription>>,
+}
+
+impl<S> SubscriptionManager<S>
+where
+    S: BasicStore + 'static,
+{
+    /// Creates a subscription manager that resolves queries against `store`.
+    pub fn new(logger: &Logger, store: Arc<Mutex<S>>) -> Self {
+        SubscriptionManager {
+            logger: logger.new(o!("component" => "SubscriptionManager")),
+            store,
+            subscriptions: Mutex::new(vec![]),
+        }
+    }
+
+    /// Starts a subscription. The returned stream yields the current result
+    /// right away and a new one every time the result changes.
+    pub fn subscribe(&self, query: Query) -> Receiver<QueryResult> {
+        let (mut sender, receiver) = channel(RESULT_BUFFER_SIZE);
+
+        let result = self.execute(&query);
+        let data = result.data.clone();
+
+        // A fresh channel always has room for the initial result
+        if sender.try_send(result).is_ok() {
+            let entity_types = entity_types(&query);
+            self.subscriptions.lock().unwrap().push(Subscription {
+                query,
+                entity_types,
+                sender,
+                data,
+            });
+        }
+
+        receiver
+    }
+
+    /// Number of subscriptions that still have subscribers.
+    pub fn subscription_count(&self) -> usize {
+        self.subscriptions.lock().unwrap().len()
+    }
+
+    /// Re-runs all subscriptions affected by a change to the store and sends
+    /// new results to their subscribers. Subscriptions whose subscribers have
+    /// gone away are dropped.
+    pub fn handle_store_event(&self, event: &StoreEvent) {
+        let entity = match event {
+            StoreEvent::EntityAdded(entity)
+            | StoreEvent::EntityRemoved(entity)
+            | StoreEvent::EntityChanged(entity) => entity,
+            // Subscriptions are kept up to date from the entities that change
+            StoreEvent::SubgraphChanged(..) => return,
+        };
+        let entity_type = match entity.get("__typename") {
+            Some(Value::String(name)) => Some(name),
+            _ => None,
+        };
+
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        let mut remaining = Vec::with_capacity(subscriptions.len());
+
+        for mut subscription in subscriptions.drain(..) {
+            let affected = match (&subscription.entity_types, entity_type) {
+                (Some(entity_types), Some(entity_type)) => entity_types.contains(entity_type),
+                _ => true,
+            };
+
+            if affected {
+                let result = self.execute(&subscription.query);
+                if result.data != subscription.data {
+                    subscription.data = result.data.clone();
+
+                    if let Err(e) = subscription.sender.try_send(result) {
+                        if e.is_disconnected() {
+                            debug!(self.logger, "Dropping subscription without subscribers");
+                            continue;
+                        }
+                        warn!(self.logger, "Subscriber is not keeping up, dropping result");
+                    }
+                }
+            }
+
+            remaining.push(subscription);
+        }
+
+        *subscriptions = remaining;
+    }
+
+    fn execute(&self, query: &Query) -> QueryResult {
+        let options = ExecutionOptions {
+            logger: self.logger.clone(),
+            resolver: StoreResolver::new(&self.logger, self.store.clone()),
+            middleware: vec![],
+            max_first: None,
+        };
+        execute(query, options)
+    }
+}
+
+/// Returns the names of all entity types that the query reads, or `None`
+/// if they cannot be determined (e.g. because the query selects fields of
+/// an interface).
+fn entity_types(query: &Query) -> Option<HashSet<String>> {
+    let schema = &query.schema.document;
+    let operation_name = query.operation_name.as_ref().map(String::as_str);
+
+    let (root_type, selection_set) = match qast::get_operation(&query.document, operation_name) {
+        Ok(q::OperationDefinition::Subscription(subscription)) => (
+            sast::get_root_subscription_type(schema),
+            &subscription.selection_set,
+        ),
+        Ok(q::OperationDefinition::Query(operation)) => {
+            (sast::get_root_query_type(schema), &operation.selection_set)
+        }
+        Ok(q::OperationDefinition::SelectionSet(selection_set)) => {
+            (sast::get_root_query_type(schema), selection_set)
+        }
+        _ => return None,
+    };
+
+    let mut entity_types = HashSet::new();
+    if collect_entity_types(query, root_type?, selection_set, &mut entity_types) {
+        Some(entity_types)
+    } else {
+        None
+    }
+}
+
+/// Adds the object types of all fields in the selection set to
+/// `entity_types`. Returns false if a field may return any entity type.
+fn collect_entity_types(
+    query: &Query,
+    object_type: &s::ObjectType,
+    selection_set: &q::SelectionSet,
+    entity_types: &mut HashSet<String>,
+) -> bool {
+    let schema = &query.schema.document;
+
+    let object_type_named = |name: &s::Name| match sast::get_named_type(schema, name) {
+        Some(s::TypeDefinition::Object(t)) => Some(t),
+        _ => None,
+    };
+
+    selection_set.items.iter().all(|selection| match selection {
+        q::Selection::Field(field) => {
+            // `__typename`, `__schema` and `__type` don't read entities
+            if field.name.starts_with("__") {
+                return true;
+            }
+
+            let field_type = match sast::get_field_type(object_type, &field.name) {
+                Some(field_definition) => named_type_name(&field_definition.field_type),
+                None => return false,
+            };
+
+            match sast::get_named_type(schema, field_type) {
+                Some(s::TypeDefinition::Object(t)) => {
+                    entity_types.insert(t.name.clone());
+                    collect_entity_types(query, t, &field.selection_set, entity_types)
+                }
+                Some(s::TypeDefinition::Scalar(_)) | Some(s::TypeDefinition::Enum(_)) => true,
+                _ => false,
+            }
+        }
+        q::Selection::FragmentSpread(spread) => {
+            match qast::get_fragment(&query.document, &spread.fragment_name) {
+                Some(fragment) => {
+                    let q::TypeCondition::On(ref name) = fragment.type_condition;
+                    match object_type_named(name) {
+                        Some(t) => {
+                            collect_entity_types(query, t, &fragment.selection_set, entity_types)
+                        }
+                        None => false,
+                    }
+                }
+                None => false,
+            }
+        }
+        q::Selection::InlineFragment(fragment) => {
+            let fragment_type = match fragment.type_condition {
+                Some(q::TypeCondition::On(ref name)) => object_type_named(name),
+                None => Some(object_type),
+            };
+            match fragment_type {
+                Some(t) => collect_entity_types(query, t, &fragment.selection_set, entity_types),
+                None => false,
+            }
+        }
+    })
+}
@@ -0,0 +1,3 @@
+mod manager;
+
+pub use self::manager::SubscriptionManager;
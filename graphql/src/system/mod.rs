@@ -0,0 +1,5 @@
+mod resolver;
+mod schema;
+
+pub use self::resolver::SystemResolver;
+pub use self::schema::{system_schema, SYSTEM_SCHEMA_ID};
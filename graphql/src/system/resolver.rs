@@ -0,0 +1,104 @@
+use graphql_parser::{query as q, schema as s};
+use std::collections::HashMap;
+
+use graph::prelude::*;
+
+use prelude::*;
+
+fn big_int(number: Option<u64>) -> q::Value {
+    number
+        .map(|number| q::Value::String(number.to_string()))
+        .unwrap_or(q::Value::Null)
+}
+
+fn deployment_object(deployment: &DeploymentInfo) -> q::Value {
+    let usage = deployment.usage.as_ref();
+    object_value(vec![
+        ("id", q::Value::String(deployment.id.clone())),
+        ("served", q::Value::Boolean(deployment.served)),
+        ("queryCount", big_int(usage.map(|usage| usage.queries))),
+        ("trackedSince", big_int(usage.map(|usage| usage.tracked_since))),
+        ("lastQueriedAt", big_int(usage.and_then(|usage| usage.last_queried_at))),
+    ])
+}
+
+fn chain_head_object(chain_head: &ChainHead) -> q::Value {
+    object_value(vec![
+        ("network", q::Value::String(chain_head.network.clone())),
+        ("blockNumber", big_int(chain_head.block_number)),
+    ])
+}
+
+/// A resolver for the `graph-system` schema that answers queries from a
+/// snapshot of the state of the node.
+#[derive(Clone)]
+pub struct SystemResolver {
+    root: q::Value,
+}
+
+impl SystemResolver {
+    pub fn new(info: &SystemInfo) -> Self {
+        SystemResolver {
+            root: object_value(vec![
+                (
+                    "node",
+                    object_value(vec![
+                        ("version", q::Value::String(info.version.clone())),
+                        (
+                            "features",
+                            q::Value::List(
+                                info.features
+                                    .iter()
+                                    .map(|feature| q::Value::String(feature.clone()))
+                                    .collect(),
+                            ),
+                        ),
+                    ]),
+                ),
+                (
+                    "deployments",
+                    q::Value::List(info.deployments.iter().map(deployment_object).collect()),
+                ),
+                (
+                    "chainHeads",
+                    q::Value::List(info.chain_heads.iter().map(chain_head_object).collect()),
+                ),
+            ]),
+        }
+    }
+
+    /// Looks up a field of the parent object, or of the root of the snapshot
+    /// for top-level fields.
+    fn field_value(&self, parent: &Option<q::Value>, field: &q::Name) -> q::Value {
+        match parent.as_ref().unwrap_or(&self.root) {
+            q::Value::Object(object) => object.get(field).cloned().unwrap_or(q::Value::Null),
+            _ => q::Value::Null,
+        }
+    }
+}
+
+impl Resolver for SystemResolver {
+    fn resolve_objects(
+        &self,
+        _schema: &s::Document,
+        parent: &Option<q::Value>,
+        field: &q::Name,
+        _field_definition: &s::Field,
+        _object_type: &s::ObjectType,
+        _arguments: &HashMap<&q::Name, q::Value>,
+    ) -> Result<q::Value, QueryExecutionError> {
+        Ok(self.field_value(parent, field))
+    }
+
+    fn resolve_object(
+        &self,
+        _schema: &s::Document,
+        parent: &Option<q::Value>,
+        field: &q::Name,
+        _field_definition: &s::Field,
+        _object_type: &s::ObjectType,
+        _arguments: &HashMap<&q::Name, q::Value>,
+    ) -> Result<q::Value, QueryExecutionError> {
+        Ok(self.field_value(parent, field))
+    }
+}
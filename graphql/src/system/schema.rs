@@ -0,0 +1,47 @@
+use graphql_parser;
+
+use graph::prelude::Schema;
+
+/// ID of the built-in schema that describes the node itself.
+pub const SYSTEM_SCHEMA_ID: &'static str = "graph-system";
+
+const SYSTEM_SCHEMA: &'static str = "
+scalar Boolean
+scalar ID
+scalar String
+scalar BigInt
+
+type Query {
+  node: Node!
+  deployments: [Deployment!]!
+  chainHeads: [ChainHead!]!
+}
+
+type Node {
+  version: String!
+  features: [String!]!
+}
+
+type Deployment {
+  id: ID!
+  served: Boolean!
+
+  # Only set if the node tracks how much deployments are queried
+  queryCount: BigInt
+  trackedSince: BigInt
+  lastQueriedAt: BigInt
+}
+
+type ChainHead {
+  network: String!
+  blockNumber: BigInt
+}";
+
+/// Returns the schema of the deployments, features and chain heads of the
+/// node, which is queried with a `SystemResolver`.
+pub fn system_schema() -> Schema {
+    Schema {
+        id: String::from(SYSTEM_SCHEMA_ID),
+        document: graphql_parser::parse_schema(SYSTEM_SCHEMA).unwrap(),
+    }
+}
@@ -1,5 +1,8 @@
 use graphql_parser::query::Value;
 use graphql_parser::schema::{EnumType, Name, ScalarType, Type, TypeDefinition};
+use std::str::FromStr;
+
+use graph::data::store::scalar;
 
 /// A GraphQL value that can be coerced according to a type.
 pub trait MaybeCoercible<T> {
@@ -29,16 +32,30 @@ impl MaybeCoercible<ScalarType> for Value {
         match (using_type.name.as_str(), self) {
             ("Boolean", v @ Value::Boolean(_)) => Some(v.clone()),
             ("Float", v @ Value::Float(_)) => Some(v.clone()),
+            ("Float", Value::Int(i)) => i.as_i64().map(|i| Value::Float(i as f64)),
             ("Int", v @ Value::Int(_)) => Some(v.clone()),
             ("String", v @ Value::String(_)) => Some(v.clone()),
+            // IDs accept strings and integers, and are always serialized as strings
             ("ID", v @ Value::String(_)) => Some(v.clone()),
-            ("Bytes", v @ Value::String(_)) => Some(v.clone()),
-            ("BigInt", v @ Value::String(_)) => Some(v.clone()),
+            ("ID", Value::Int(i)) => i.as_i64().map(|i| Value::String(i.to_string())),
+            // Custom scalars are passed around as strings in their canonical form
+            ("Bytes", Value::String(s)) => normalize::<scalar::Bytes>(s),
+            ("BigInt", Value::String(s)) => normalize::<scalar::BigInt>(s),
+            ("BigInt", Value::Int(i)) => i.as_i64().map(|i| Value::String(i.to_string())),
+            ("BigDecimal", Value::String(s)) => normalize::<scalar::BigDecimal>(s),
+            ("BigDecimal", Value::Int(i)) => i.as_i64().map(|i| Value::String(i.to_string())),
+            ("BigDecimal", Value::Float(f)) => normalize::<scalar::BigDecimal>(&f.to_string()),
             _ => None,
         }
     }
 }
 
+/// Parses a custom scalar from a string and returns it in its canonical
+/// form, or `None` if the string is not a valid value of the scalar.
+fn normalize<T: FromStr + ToString>(s: &str) -> Option<Value> {
+    T::from_str(s).ok().map(|value| Value::String(value.to_string()))
+}
+
 impl MaybeCoercible<TypeDefinition> for Value {
     fn coerce(&self, using_type: &TypeDefinition) -> Option<Value> {
         match (using_type, self) {
@@ -95,8 +112,8 @@ where
             Some(Value::List(coerced_values))
         },
 
-        // Everything else is unsupported for now
-        _ => unimplemented!(),
+        // Single values may be coercible into lists with just that value
+        (Type::ListType(t), _) => coerce_value(value, t, resolver).map(|v| Value::List(vec![v])),
     }
 }
 
@@ -104,13 +121,13 @@ where
 mod tests {
     use graphql_parser::query::Value;
     use graphql_parser::schema::{
-        EnumType, EnumValue, InputObjectType, ScalarType, TypeDefinition,
+        EnumType, EnumValue, InputObjectType, Name, ScalarType, Type, TypeDefinition,
     };
     use graphql_parser::Pos;
     use std::collections::BTreeMap;
     use std::iter::FromIterator;
 
-    use super::MaybeCoercible;
+    use super::{coerce_value, MaybeCoercible};
 
     #[test]
     fn coercion_using_enum_type_definitions_is_correct() {
@@ -194,6 +211,12 @@ mod tests {
             Some(Value::Float(-5.879))
         );
 
+        // We can coerce from Value::Int -> TypeDefinition::Scalar(Float)
+        assert_eq!(
+            Value::Int(5.into()).coerce(&float_type),
+            Some(Value::Float(5.0))
+        );
+
         // We don't support going from Value::String -> TypeDefinition::Scalar(Float)
         assert_eq!(Value::String("23.7".to_string()).coerce(&float_type), None,);
         assert_eq!(
@@ -243,6 +266,12 @@ mod tests {
             Some(Value::String("bar".to_string()))
         );
 
+        // We can coerce from Value::Int -> TypeDefinition::Scalar(ID)
+        assert_eq!(
+            Value::Int(1234.into()).coerce(&string_type),
+            Some(Value::String("1234".to_string()))
+        );
+
         // We don't support going from Value::Boolean -> TypeDefinition::Scalar(ID)
         assert_eq!(Value::Boolean(true).coerce(&string_type), None,);
         assert_eq!(Value::Boolean(false).coerce(&string_type), None,);
@@ -297,6 +326,38 @@ mod tests {
             Value::String("1234".to_string()).coerce(&big_int_type),
             Some(Value::String("1234".to_string()))
         );
+
+        // We can coerce from Value::Int -> TypeDefinition::Scalar(BigInt)
+        assert_eq!(
+            Value::Int(1234.into()).coerce(&big_int_type),
+            Some(Value::String("1234".to_string()))
+        );
+
+        // We don't accept strings that are not numbers
+        assert_eq!(Value::String("12.5".to_string()).coerce(&big_int_type), None);
+    }
+
+    #[test]
+    fn coerce_big_decimal_scalar() {
+        let big_decimal_type = TypeDefinition::Scalar(ScalarType::new("BigDecimal".to_string()));
+
+        // We can coerce from Value::String, Value::Int and Value::Float ->
+        // TypeDefinition::Scalar(BigDecimal)
+        assert_eq!(
+            Value::String("-1234.5678".to_string()).coerce(&big_decimal_type),
+            Some(Value::String("-1234.5678".to_string()))
+        );
+        assert_eq!(
+            Value::Int(1234.into()).coerce(&big_decimal_type),
+            Some(Value::String("1234".to_string()))
+        );
+        assert_eq!(
+            Value::Float(12.5).coerce(&big_decimal_type),
+            Some(Value::String("12.5".to_string()))
+        );
+
+        // We don't accept strings that are not numbers
+        assert_eq!(Value::String("twelve".to_string()).coerce(&big_decimal_type), None);
     }
 
     #[test]
@@ -305,8 +366,33 @@ mod tests {
 
         // We can coerce from Value::String -> TypeDefinition::Scalar(Bytes)
         assert_eq!(
-            Value::String("0x21f".to_string()).coerce(&bytes_type),
-            Some(Value::String("0x21f".to_string()))
+            Value::String("0x021f".to_string()).coerce(&bytes_type),
+            Some(Value::String("0x021f".to_string()))
+        );
+
+        // Hex strings are returned in lower case and with a `0x` prefix
+        assert_eq!(
+            Value::String("021F".to_string()).coerce(&bytes_type),
+            Some(Value::String("0x021f".to_string()))
+        );
+
+        // We don't accept strings that are not hex encoded bytes
+        assert_eq!(Value::String("0x21f".to_string()).coerce(&bytes_type), None);
+    }
+
+    #[test]
+    fn coerce_single_values_into_lists() {
+        let int_type = TypeDefinition::Scalar(ScalarType::new("Int".to_string()));
+        let resolver = |_: &Name| Some(&int_type);
+        let list_type = Type::ListType(Box::new(Type::NamedType("Int".to_string())));
+
+        assert_eq!(
+            coerce_value(&Value::Int(5.into()), &list_type, &resolver),
+            Some(Value::List(vec![Value::Int(5.into())]))
+        );
+        assert_eq!(
+            coerce_value(&Value::String("5".to_string()), &list_type, &resolver),
+            None
         );
     }
 }
@@ -5,7 +5,6 @@ extern crate graph;
 extern crate graph_graphql;
 extern crate graphql_parser;
 
-use futures::sync::oneshot;
 use graphql_parser::{query as q, schema as s};
 use std::collections::HashMap;
 
@@ -19,24 +18,26 @@ pub struct MockResolver;
 impl Resolver for MockResolver {
     fn resolve_objects(
         &self,
+        _schema: &s::Document,
         _parent: &Option<q::Value>,
         _field: &q::Name,
         _field_definition: &s::Field,
         _object_type: &s::ObjectType,
         _arguments: &HashMap<&q::Name, q::Value>,
-    ) -> q::Value {
-        q::Value::Null
+    ) -> Result<q::Value, QueryExecutionError> {
+        Ok(q::Value::Null)
     }
 
     fn resolve_object(
         &self,
+        _schema: &s::Document,
         _parent: &Option<q::Value>,
         _field: &q::Name,
         _field_definition: &s::Field,
         _object_type: &s::ObjectType,
         _arguments: &HashMap<&q::Name, q::Value>,
-    ) -> q::Value {
-        q::Value::Null
+    ) -> Result<q::Value, QueryExecutionError> {
+        Ok(q::Value::Null)
     }
 }
 
@@ -480,12 +481,14 @@ fn expected_mock_schema_introspection() -> q::Value {
 /// Execute an introspection query.
 fn introspection_query(schema: Schema, query: &str) -> QueryResult {
     // Create the query
-    let (sender, _) = oneshot::channel();
     let query = Query {
         schema: schema,
         document: graphql_parser::parse_query(query).unwrap(),
         variables: None,
-        result_sender: sender,
+        operation_name: None,
+        role: None,
+        explain: false,
+        trace: false,
     };
 
     // Execute it
@@ -494,6 +497,8 @@ fn introspection_query(schema: Schema, query: &str) -> QueryResult {
         ExecutionOptions {
             logger: slog::Logger::root(slog::Discard, o!()),
             resolver: MockResolver,
+            middleware: vec![],
+            max_first: None,
         },
     )
 }
@@ -849,6 +854,203 @@ fn satisfies_graphiql_introspection_query_with_fragments() {
     assert_eq!(data, expected_mock_schema_introspection());
 }
 
+#[test]
+fn reports_root_operation_types() {
+    let schema = |document: &str| Schema {
+        id: "root-types-schema".to_string(),
+        document: graphql_parser::parse_schema(document).unwrap(),
+    };
+    let query = "
+        query {
+          __schema {
+            queryType { name }
+            mutationType { name }
+            subscriptionType { name }
+          }
+        }
+        ";
+    let name = |name: &str| object_value(vec![("name", q::Value::String(name.to_string()))]);
+
+    let result = introspection_query(
+        schema(
+            "
+            scalar ID
+            type User { id: ID! }
+            type Query { user(id: ID!): User }
+            type Subscription { user(id: ID!): User }
+            type Mutation { removeUser(id: ID!): User }
+            ",
+        ),
+        query,
+    );
+    assert_eq!(
+        result.data,
+        Some(object_value(vec![(
+            "__schema",
+            object_value(vec![
+                ("queryType", name("Query")),
+                ("mutationType", name("Mutation")),
+                ("subscriptionType", name("Subscription")),
+            ]),
+        )]))
+    );
+
+    let result = introspection_query(
+        schema("scalar ID type User { id: ID! } type Query { user(id: ID!): User }"),
+        query,
+    );
+    assert_eq!(
+        result.data,
+        Some(object_value(vec![(
+            "__schema",
+            object_value(vec![
+                ("queryType", name("Query")),
+                ("mutationType", q::Value::Null),
+                ("subscriptionType", q::Value::Null),
+            ]),
+        )]))
+    );
+}
+
+#[test]
+fn resolves_single_types_by_name() {
+    let result = introspection_query(
+        mock_schema(),
+        "
+        query {
+          user: __type(name: \"User\") {
+            kind
+            name
+            fields {
+              name
+            }
+            interfaces {
+              name
+            }
+          }
+          unknown: __type(name: \"Unknown\") {
+            name
+          }
+        }
+        ",
+    );
+
+    let name = |name: &str| object_value(vec![("name", q::Value::String(name.to_string()))]);
+
+    assert_eq!(
+        result.data,
+        Some(object_value(vec![
+            (
+                "user",
+                object_value(vec![
+                    ("kind", q::Value::Enum("OBJECT".to_string())),
+                    ("name", q::Value::String("User".to_string())),
+                    (
+                        "fields",
+                        q::Value::List(vec![name("id"), name("name"), name("role")]),
+                    ),
+                    ("interfaces", q::Value::List(vec![name("Node")])),
+                ]),
+            ),
+            ("unknown", q::Value::Null),
+        ]))
+    );
+}
+
+#[test]
+fn reports_deprecated_fields_and_enum_values() {
+    let schema = Schema {
+        id: "deprecation-schema".to_string(),
+        document: graphql_parser::parse_schema(
+            "
+             scalar String
+
+             enum Role {
+               USER
+               ADMIN @deprecated
+             }
+
+             type User {
+               name: String!
+               nickname: String @deprecated(reason: \"Use `name`\")
+               role: Role!
+             }
+
+             type Query {
+               users: [User!]!
+             }
+             ",
+        ).unwrap(),
+    };
+
+    let result = introspection_query(
+        schema,
+        "
+        query {
+          user: __type(name: \"User\") {
+            fields {
+              name
+            }
+            allFields: fields(includeDeprecated: true) {
+              name
+              isDeprecated
+              deprecationReason
+            }
+          }
+          role: __type(name: \"Role\") {
+            enumValues(includeDeprecated: true) {
+              name
+              isDeprecated
+              deprecationReason
+            }
+          }
+        }
+        ",
+    );
+
+    let deprecation = |name: &str, reason: Option<&str>| {
+        object_value(vec![
+            ("name", q::Value::String(name.to_string())),
+            ("isDeprecated", q::Value::Boolean(reason.is_some())),
+            (
+                "deprecationReason",
+                reason.map_or(q::Value::Null, |reason| q::Value::String(reason.to_string())),
+            ),
+        ])
+    };
+    let name = |name: &str| object_value(vec![("name", q::Value::String(name.to_string()))]);
+
+    assert_eq!(
+        result.data,
+        Some(object_value(vec![
+            (
+                "user",
+                object_value(vec![
+                    ("fields", q::Value::List(vec![name("name"), name("role")])),
+                    (
+                        "allFields",
+                        q::Value::List(vec![
+                            deprecation("name", None),
+                            deprecation("nickname", Some("Use `name`")),
+                            deprecation("role", None),
+                        ]),
+                    ),
+                ]),
+            ),
+            (
+                "role",
+                object_value(vec![(
+                    "enumValues",
+                    q::Value::List(vec![
+                        deprecation("USER", None),
+                        deprecation("ADMIN", Some("No longer supported")),
+                    ]),
+                )]),
+            ),
+        ]))
+    );
+}
+
 #[test]
 fn successfully_runs_introspection_query_against_complex_schema() {
     let complex_schema = "
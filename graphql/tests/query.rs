@@ -6,12 +6,17 @@ extern crate graph;
 extern crate graph_core;
 extern crate graph_graphql;
 
-use futures::sync::oneshot;
 use graphql_parser::query as q;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
+use graph::components::ethereum::EthereumBlockHeader;
 use graph::components::store::EventSource;
+use graph::data::store::scalar::BigDecimal;
 use graph::prelude::*;
+use graph::serde_json;
 use graph_graphql::prelude::*;
 
 fn test_schema() -> Schema {
@@ -20,7 +25,12 @@ fn test_schema() -> Schema {
         document: api_schema(
             &graphql_parser::parse_schema(
                 "
-            type Musician {
+            interface Artist {
+                id: ID!
+                name: String!
+            }
+
+            type Musician implements Artist {
                 id: ID!
                 name: String!
                 mainBand: Band
@@ -28,17 +38,22 @@ fn test_schema() -> Schema {
                 writtenSongs: [Song]! @derivedFrom(field: \"writtenBy\")
             }
 
-            type Band {
+            type Band implements Artist {
                 id: ID!
                 name: String!
+                genre: String!
                 members: [Musician!]! @derivedFrom(field: \"bands\")
             }
 
-            type Song {
+            type Song @fulltext(name: \"songs\", fields: [\"title\"]) {
                 id: ID!
                 title: String!
                 writtenBy: Musician!
+                duration: Int
+                ratings: [[Int!]]
             }
+
+            union SearchResult = Band | Song
             ",
             ).expect("Test schema invalid"),
         ).expect("Failed to derive API schema from test schema"),
@@ -47,9 +62,10 @@ fn test_schema() -> Schema {
     schema
 }
 
-#[derive(Clone)]
 struct TestStore {
     entities: Vec<Entity>,
+    /// Number of queries made against the store.
+    queries: AtomicUsize,
 }
 
 impl TestStore {
@@ -94,6 +110,7 @@ impl TestStore {
                     ("__typename", Value::from("Band")),
                     ("id", Value::from("b1")),
                     ("name", Value::from("The Musicians")),
+                    ("genre", Value::from("Rock")),
                 ]),
                 Entity::from(vec![
                     ("__typename", Value::from("Band")),
@@ -104,18 +121,35 @@ impl TestStore {
                     ("__typename", Value::from("Song")),
                     ("id", Value::from("s1")),
                     ("title", Value::from("Cheesy Tune")),
+                    ("duration", Value::Int(180)),
                     ("writtenBy", Value::from("m1")),
+                    (
+                        "ratings",
+                        Value::List(vec![
+                            Value::List(vec![Value::Int(5), Value::Int(4)]),
+                            Value::List(vec![Value::Int(3)]),
+                        ]),
+                    ),
                 ]),
                 Entity::from(vec![
                     ("__typename", Value::from("Song")),
                     ("id", Value::from("s2")),
                     ("title", Value::from("Rock Tune")),
+                    ("duration", Value::Int(240)),
                     ("writtenBy", Value::from("m2")),
+                    (
+                        "ratings",
+                        Value::List(vec![
+                            Value::List(vec![Value::Int(1), Value::from("bad")]),
+                            Value::List(vec![Value::Int(2)]),
+                        ]),
+                    ),
                 ]),
                 Entity::from(vec![
                     ("__typename", Value::from("Song")),
                     ("id", Value::from("s3")),
                     ("title", Value::from("Pop Tune")),
+                    ("duration", Value::Int(200)),
                     ("writtenBy", Value::from("m1")),
                 ]),
                 Entity::from(vec![
@@ -125,12 +159,14 @@ impl TestStore {
                     ("writtenBy", Value::from("m3")),
                 ]),
             ],
+            queries: AtomicUsize::new(0),
         }
     }
 }
 
 impl BasicStore for TestStore {
     fn get(&self, key: StoreKey) -> Result<Entity, ()> {
+        self.queries.fetch_add(1, Ordering::SeqCst);
         self.entities
             .iter()
             .find(|entity| {
@@ -140,15 +176,47 @@ impl BasicStore for TestStore {
             .map_or(Err(()), |entity| Ok(entity.clone()))
     }
 
-    fn set(&mut self, _key: StoreKey, _entity: Entity, _source: EventSource) -> Result<(), ()> {
+    fn get_many(
+        &self,
+        _subgraph: &str,
+        ids_for_type: BTreeMap<String, Vec<String>>,
+        _scope: QueryScope,
+    ) -> Result<BTreeMap<String, Vec<Entity>>, ()> {
+        self.queries.fetch_add(1, Ordering::SeqCst);
+        Ok(ids_for_type
+            .into_iter()
+            .map(|(entity_type, ids)| {
+                let entities = self.entities
+                    .iter()
+                    .filter(|entity| {
+                        entity.get("__typename") == Some(&Value::String(entity_type.clone()))
+                    })
+                    .filter(|entity| match entity.get("id") {
+                        Some(Value::String(id)) => ids.contains(id),
+                        _ => false,
+                    })
+                    .cloned()
+                    .collect();
+                (entity_type, entities)
+            })
+            .collect())
+    }
+
+    fn set(
+        &mut self,
+        _key: StoreKey,
+        _entity: Entity,
+        _source: EventSource,
+    ) -> Result<(), StoreWriteError> {
         unimplemented!()
     }
 
-    fn delete(&mut self, _key: StoreKey, _source: EventSource) -> Result<(), ()> {
+    fn delete(&mut self, _key: StoreKey, _source: EventSource) -> Result<(), StoreWriteError> {
         unimplemented!()
     }
 
     fn find(&self, query: StoreQuery) -> Result<Vec<Entity>, ()> {
+        self.queries.fetch_add(1, Ordering::SeqCst);
         let entity_name = Value::String(query.entity.clone());
 
         let entities = self.entities
@@ -158,8 +226,13 @@ impl BasicStore for TestStore {
             // the filters generated for reference fields and @derivedFrom fields:
             //
             // - And(Contains(...))
+            // - And(Contains(..., List(...)))
             // - And(Equal(...))
             // - And(Or([Equal(...), ...]))
+            // - And(Or([Contains(..., List(...)), ...]))
+            // - And(In(...))
+            // - And(GreaterThan("id", ...))
+            // - And(Child(...))
             .filter(|entity| {
                 query
                     .filter
@@ -170,33 +243,322 @@ impl BasicStore for TestStore {
                     })
                     .map(|filter| match filter {
                         StoreFilter::Equal(k, v) => entity.get(k) == Some(&v),
+                        StoreFilter::Contains(k, Value::List(vs)) => match entity.get(k) {
+                            Some(Value::List(values)) => vs.iter().all(|v| values.contains(v)),
+                            _ => false,
+                        },
                         StoreFilter::Contains(k, v) => match entity.get(k) {
                             Some(Value::List(values)) => values.contains(v),
                             _ => false,
                         },
                         StoreFilter::Or(filters) => filters.iter().any(|filter| match filter {
                             StoreFilter::Equal(k,v) => entity.get(k) == Some(&v),
+                            StoreFilter::Contains(k, Value::List(vs)) => match entity.get(k) {
+                                Some(Value::List(values)) => vs.iter().all(|v| values.contains(v)),
+                                _ => false,
+                            },
                             _ => unimplemented!(),
                         }),
+                        StoreFilter::In(k, values) => match entity.get(k) {
+                            Some(value) => values.contains(value),
+                            _ => false,
+                        },
+                        StoreFilter::GreaterThan(k, Value::String(v)) => match entity.get(k) {
+                            Some(Value::String(value)) => value > v,
+                            _ => false,
+                        },
+                        StoreFilter::Child(k, child_type, child_filter) => {
+                            let child_ids = self
+                                .find(StoreQuery {
+                                    subgraph: query.subgraph.clone(),
+                                    entity: child_type.clone(),
+                                    filter: Some((**child_filter).clone()),
+                                    order_by: None,
+                                    order_direction: None,
+                                    range: None,
+                                    scope: query.scope,
+                                })
+                                .unwrap()
+                                .into_iter()
+                                .filter_map(|child| child.get("id").cloned())
+                                .collect::<Vec<_>>();
+                            match entity.get(k) {
+                                Some(Value::List(ids)) => {
+                                    ids.iter().any(|id| child_ids.contains(id))
+                                }
+                                Some(id) => child_ids.contains(id),
+                                None => false,
+                            }
+                        }
                         _ => unimplemented!(),
                     })
                     .unwrap_or(true)
             })
             .map(|entity| entity.clone())
-            .collect();
+            .collect::<Vec<_>>();
+
+        // Sort by the order attribute, if there is one; only strings are
+        // compared here
+        let mut entities = entities;
+        if let Some((ref attribute, _)) = query.order_by {
+            entities.sort_by(|a, b| match (a.get(attribute), b.get(attribute)) {
+                (Some(Value::String(a)), Some(Value::String(b))) => a.cmp(b),
+                _ => unimplemented!(),
+            });
+            if let Some(StoreOrder::Descending) = query.order_direction {
+                entities.reverse();
+            }
+        }
+
+        // Apply the range, if there is one
+        let entities = match query.range {
+            Some(range) => entities
+                .into_iter()
+                .skip(range.skip)
+                .take(range.first)
+                .collect(),
+            None => entities,
+        };
 
         Ok(entities)
     }
+
+    fn search(&self, query: StoreQuery, search: FulltextSearch) -> Result<Vec<Entity>, ()> {
+        self.queries.fetch_add(1, Ordering::SeqCst);
+        assert_eq!(search.name, "songs");
+
+        // Songs match if their title contains the text as a word
+        let text = search.text.to_lowercase();
+        Ok(self
+            .entities
+            .iter()
+            .filter(|entity| entity.get("__typename") == Some(&Value::from(query.entity.as_str())))
+            .filter(|entity| match entity.get("title") {
+                Some(Value::String(title)) => title.to_lowercase().split(' ').any(|w| w == text),
+                _ => false,
+            })
+            .cloned()
+            .collect())
+    }
+
+    fn aggregate(
+        &self,
+        query: StoreQuery,
+        aggregates: Vec<StoreAggregate>,
+    ) -> Result<Vec<Value>, ()> {
+        let entities = self.find(StoreQuery {
+            range: None,
+            ..query
+        })?;
+
+        // Only `Int` values are aggregated here
+        let ints = |attribute: &str| -> Vec<i32> {
+            entities
+                .iter()
+                .filter_map(|entity| match entity.get(attribute) {
+                    Some(Value::Int(n)) => Some(*n),
+                    _ => None,
+                })
+                .collect()
+        };
+        let decimal = |n: Option<i32>| {
+            n.map_or(Value::Null, |n| {
+                Value::BigDecimal(n.to_string().parse().unwrap())
+            })
+        };
+        Ok(aggregates
+            .iter()
+            .map(|aggregate| match aggregate {
+                StoreAggregate::Count => Value::Int(entities.len() as i32),
+                StoreAggregate::Sum(attribute) => {
+                    let values = ints(attribute);
+                    decimal(if values.is_empty() {
+                        None
+                    } else {
+                        Some(values.iter().sum())
+                    })
+                }
+                StoreAggregate::Min(attribute) => decimal(ints(attribute).into_iter().min()),
+                StoreAggregate::Max(attribute) => decimal(ints(attribute).into_iter().max()),
+            })
+            .collect())
+    }
+
+    fn histogram(
+        &self,
+        query: StoreQuery,
+        attribute: Attribute,
+        size: BigDecimal,
+    ) -> Result<Vec<(BigDecimal, i32)>, ()> {
+        let entities = self.find(StoreQuery {
+            range: None,
+            ..query
+        })?;
+
+        // Only `Int` values are bucketed here, by whole sizes
+        let size: i32 = size.to_string().parse().map_err(|_| ())?;
+        let mut buckets = BTreeMap::new();
+        for entity in entities {
+            if let Some(Value::Int(n)) = entity.get(&attribute) {
+                *buckets.entry(n / size * size).or_insert(0) += 1;
+            }
+        }
+        Ok(buckets
+            .into_iter()
+            .map(|(start, count)| (start.to_string().parse().unwrap(), count))
+            .collect())
+    }
+}
+
+#[test]
+fn can_order_collection_fields() {
+    let result = execute_query(
+        graphql_parser::parse_query(
+            "
+            query {
+                musicians(first: 1) {
+                    name
+                    ascending: bands(orderBy: name, orderDirection: asc) {
+                        name
+                    }
+                    descending: bands(orderBy: name, orderDirection: desc) {
+                        name
+                    }
+                }
+            }
+            ",
+        ).expect("Invalid test query"),
+    );
+
+    assert!(
+        result.errors.is_none(),
+        format!("Unexpected errors return for query: {:#?}", result.errors)
+    );
+
+    let band = |name: &str| object_value(vec![("name", q::Value::String(String::from(name)))]);
+
+    assert_eq!(
+        result.data,
+        Some(object_value(vec![(
+            "musicians",
+            q::Value::List(vec![object_value(vec![
+                ("name", q::Value::String(String::from("John"))),
+                (
+                    "ascending",
+                    q::Value::List(vec![band("The Amateurs"), band("The Musicians")]),
+                ),
+                (
+                    "descending",
+                    q::Value::List(vec![band("The Musicians"), band("The Amateurs")]),
+                ),
+            ])]),
+        )])),
+    )
+}
+
+#[test]
+fn can_page_through_connections() {
+    let page = |arguments: &str| {
+        execute_query(
+            graphql_parser::parse_query(&format!(
+                "
+                query {{
+                    musiciansConnection({}) {{
+                        edges {{
+                            cursor
+                            node {{
+                                name
+                            }}
+                        }}
+                        pageInfo {{
+                            hasNextPage
+                            endCursor
+                        }}
+                    }}
+                }}
+                ",
+                arguments
+            )).expect("Invalid test query"),
+        )
+    };
+    let edge = |id: &str, name: &str| {
+        object_value(vec![
+            ("cursor", q::Value::String(String::from(id))),
+            (
+                "node",
+                object_value(vec![("name", q::Value::String(String::from(name)))]),
+            ),
+        ])
+    };
+
+    let result = page("first: 2");
+    assert!(
+        result.errors.is_none(),
+        format!("Unexpected errors return for query: {:#?}", result.errors)
+    );
+    assert_eq!(
+        result.data,
+        Some(object_value(vec![(
+            "musiciansConnection",
+            object_value(vec![
+                (
+                    "edges",
+                    q::Value::List(vec![edge("m1", "John"), edge("m2", "Lisa")]),
+                ),
+                (
+                    "pageInfo",
+                    object_value(vec![
+                        ("hasNextPage", q::Value::Boolean(true)),
+                        ("endCursor", q::Value::String(String::from("m2"))),
+                    ]),
+                ),
+            ]),
+        )])),
+    );
+
+    let result = page("first: 2, after: \"m2\"");
+    assert!(
+        result.errors.is_none(),
+        format!("Unexpected errors return for query: {:#?}", result.errors)
+    );
+    assert_eq!(
+        result.data,
+        Some(object_value(vec![(
+            "musiciansConnection",
+            object_value(vec![
+                (
+                    "edges",
+                    q::Value::List(vec![edge("m3", "Tom"), edge("m4", "Valerie")]),
+                ),
+                (
+                    "pageInfo",
+                    object_value(vec![
+                        ("hasNextPage", q::Value::Boolean(false)),
+                        ("endCursor", q::Value::String(String::from("m4"))),
+                    ]),
+                ),
+            ]),
+        )])),
+    );
 }
 
 fn execute_query(query: q::Document) -> QueryResult {
-    let (sender, _receiver) = oneshot::channel();
+    execute_operation(query, None, None)
+}
 
+fn execute_operation(
+    query: q::Document,
+    variables: Option<QueryVariables>,
+    operation_name: Option<&str>,
+) -> QueryResult {
     let query = Query {
         schema: test_schema(),
         document: query,
-        variables: None,
-        result_sender: sender,
+        variables,
+        operation_name: operation_name.map(String::from),
+        role: None,
+        explain: false,
+        trace: false,
     };
 
     let logger = Logger::root(slog::Discard, o!());
@@ -206,11 +568,92 @@ fn execute_query(query: q::Document) -> QueryResult {
     let options = ExecutionOptions {
         logger: logger,
         resolver: store_resolver,
+        middleware: vec![],
+        max_first: None,
     };
 
     execute(&query, options)
 }
 
+#[test]
+fn can_use_query_variables() {
+    let query = graphql_parser::parse_query(
+        "
+        query musician($id: ID!, $first: Int = 1) {
+            musician(id: $id) {
+                name
+            }
+            musicians(first: $first) {
+                name
+            }
+        }
+        ",
+    ).expect("Invalid test query");
+
+    let variables = serde_json::from_str(r#"{ "id": "m2" }"#).expect("Invalid variables");
+    let result = execute_operation(query.clone(), Some(variables), None);
+
+    assert!(
+        result.errors.is_none(),
+        format!("Unexpected errors return for query: {:#?}", result.errors)
+    );
+
+    assert_eq!(
+        result.data,
+        Some(object_value(vec![
+            (
+                "musician",
+                object_value(vec![("name", q::Value::String(String::from("Lisa")))]),
+            ),
+            (
+                "musicians",
+                q::Value::List(vec![object_value(vec![(
+                    "name",
+                    q::Value::String(String::from("John")),
+                )])]),
+            ),
+        ])),
+    );
+
+    // Required variables must be provided, and with values of the right type
+    let result = execute_operation(query.clone(), None, None);
+    assert!(result.errors.is_some());
+
+    let variables = serde_json::from_str(r#"{ "id": "m2", "first": "one" }"#).unwrap();
+    let result = execute_operation(query, Some(variables), None);
+    assert!(result.errors.is_some());
+}
+
+#[test]
+fn can_select_operations_by_name() {
+    let query = graphql_parser::parse_query(
+        "
+        query musician { musician(id: \"m1\") { name } }
+        query band { band(id: \"b1\") { name } }
+        ",
+    ).expect("Invalid test query");
+
+    let result = execute_operation(query.clone(), None, Some("band"));
+    assert!(
+        result.errors.is_none(),
+        format!("Unexpected errors return for query: {:#?}", result.errors)
+    );
+    assert_eq!(
+        result.data,
+        Some(object_value(vec![(
+            "band",
+            object_value(vec![("name", q::Value::String(String::from("The Musicians")))]),
+        )])),
+    );
+
+    // The operation to run is ambiguous without a name
+    let result = execute_operation(query.clone(), None, None);
+    assert!(result.errors.is_some());
+
+    let result = execute_operation(query, None, Some("song"));
+    assert!(result.errors.is_some());
+}
+
 #[test]
 fn can_query_one_to_one_relationship() {
     let result = execute_query(
@@ -450,3 +893,1382 @@ fn can_query_many_to_many_relationship() {
         )]))
     );
 }
+
+#[test]
+fn can_paginate_collection_fields() {
+    let result = execute_query(
+        graphql_parser::parse_query(
+            "
+            query {
+                musicians(first: 1) {
+                    name
+                    bands(first: 1, skip: 1) {
+                        name
+                    }
+                }
+            }
+            ",
+        ).expect("Invalid test query"),
+    );
+
+    assert!(
+        result.errors.is_none(),
+        format!("Unexpected errors return for query: {:#?}", result.errors)
+    );
+
+    assert_eq!(
+        result.data,
+        Some(object_value(vec![(
+            "musicians",
+            q::Value::List(vec![object_value(vec![
+                ("name", q::Value::String(String::from("John"))),
+                (
+                    "bands",
+                    q::Value::List(vec![object_value(vec![(
+                        "name",
+                        q::Value::String(String::from("The Amateurs")),
+                    )])]),
+                ),
+            ])]),
+        )])),
+    )
+}
+
+#[test]
+fn keys_results_by_alias_and_queries_aliased_fields_separately() {
+    let result = execute_query(
+        graphql_parser::parse_query(
+            "
+            query {
+                first: musicians(first: 1) {
+                    name
+                    band: mainBand { name }
+                    firstBand: bands(first: 1) { name }
+                    otherBands: bands(first: 1, skip: 1) { name }
+                }
+                rest: musicians(first: 2, skip: 1) {
+                    musician: name
+                }
+            }
+            ",
+        ).expect("Invalid test query"),
+    );
+
+    assert!(
+        result.errors.is_none(),
+        format!("Unexpected errors return for query: {:#?}", result.errors)
+    );
+
+    let name = |key: &str, name: &str| object_value(vec![(key, q::Value::String(name.into()))]);
+    assert_eq!(
+        result.data,
+        Some(object_value(vec![
+            (
+                "first",
+                q::Value::List(vec![object_value(vec![
+                    ("name", q::Value::String(String::from("John"))),
+                    ("band", name("name", "The Musicians")),
+                    ("firstBand", q::Value::List(vec![name("name", "The Musicians")])),
+                    ("otherBands", q::Value::List(vec![name("name", "The Amateurs")])),
+                ])]),
+            ),
+            (
+                "rest",
+                q::Value::List(vec![name("musician", "Lisa"), name("musician", "Tom")]),
+            ),
+        ])),
+    )
+}
+
+#[test]
+fn rejects_fields_with_the_same_response_key_and_different_arguments() {
+    let result = execute_query(
+        graphql_parser::parse_query(
+            "
+            query {
+                musicians(first: 2) {
+                    bands(first: 1) { name }
+                    bands: bands(first: 2) { name }
+                }
+            }
+            ",
+        ).expect("Invalid test query"),
+    );
+
+    // The error is reported for the field of each musician
+    let errors = result.errors.expect("Conflicting fields were not rejected");
+    let paths = errors
+        .iter()
+        .map(|error| match error {
+            QueryError::FieldError(_, path, QueryExecutionError::FieldConflictError(_, key)) => {
+                assert_eq!(key, "bands");
+                path.clone()
+            }
+            e => panic!("Unexpected error: {}", e),
+        })
+        .collect::<Vec<_>>();
+    let path = |index| {
+        vec![
+            PathSegment::Key(String::from("musicians")),
+            PathSegment::Index(index),
+            PathSegment::Key(String::from("bands")),
+        ]
+    };
+    assert_eq!(paths, vec![path(0), path(1)]);
+}
+
+#[test]
+fn rejects_fragments_that_spread_themselves() {
+    let result = execute_query(
+        graphql_parser::parse_query(
+            "
+            query {
+                musicians { ...musicianFields }
+            }
+
+            fragment musicianFields on Musician {
+                name
+                mainBand { members { ...musicianFields } }
+            }
+            ",
+        ).expect("Invalid test query"),
+    );
+
+    let errors = result.errors.expect("The fragment cycle was not rejected");
+    assert_eq!(errors.len(), 1);
+    match &errors[0] {
+        QueryError::ExecutionError(QueryExecutionError::FragmentCycleError(_, name)) => {
+            assert_eq!(name, "musicianFields")
+        }
+        e => panic!("Unexpected error: {}", e),
+    }
+}
+
+#[test]
+fn nulls_the_nearest_nullable_ancestor_of_failed_non_null_fields() {
+    // The genre of the second band is missing, which makes the optional
+    // main band of the third musician null
+    let result = execute_query(
+        graphql_parser::parse_query(
+            "
+            query {
+                musicians(first: 3) {
+                    name
+                    mainBand { name genre }
+                }
+            }
+            ",
+        ).expect("Invalid test query"),
+    );
+
+    let errors = result.errors.expect("The missing genre was not reported");
+    let paths = errors
+        .iter()
+        .map(|error| match error {
+            QueryError::FieldError(_, path, QueryExecutionError::NonNullError(_, field)) => {
+                assert_eq!(field, "genre");
+                path.clone()
+            }
+            e => panic!("Unexpected error: {}", e),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        paths,
+        vec![vec![
+            PathSegment::Key(String::from("musicians")),
+            PathSegment::Index(2),
+            PathSegment::Key(String::from("mainBand")),
+            PathSegment::Key(String::from("genre")),
+        ]]
+    );
+
+    let band = object_value(vec![
+        ("name", q::Value::String(String::from("The Musicians"))),
+        ("genre", q::Value::String(String::from("Rock"))),
+    ]);
+    let musician = |name: &str, main_band: q::Value| {
+        object_value(vec![
+            ("name", q::Value::String(name.to_owned())),
+            ("mainBand", main_band),
+        ])
+    };
+    assert_eq!(
+        result.data,
+        Some(object_value(vec![(
+            "musicians",
+            q::Value::List(vec![
+                musician("John", band.clone()),
+                musician("Lisa", band),
+                musician("Tom", q::Value::Null),
+            ]),
+        )])),
+    );
+
+    // Without a nullable ancestor, there is no data at all
+    let result = execute_query(
+        graphql_parser::parse_query("query { musicians(first: 1) { bands { genre } } }")
+            .expect("Invalid test query"),
+    );
+    assert_eq!(result.errors.map(|errors| errors.len()), Some(1));
+    assert_eq!(result.data, Some(q::Value::Null));
+}
+
+#[test]
+fn reports_all_validation_errors_before_executing_queries() {
+    let result = execute_query(
+        graphql_parser::parse_query(
+            "
+            query {
+                musicians(first: \"two\", limit: 3) {
+                    name
+                    age
+                    ... on Song { title }
+                    ...missingFields
+                    bands(first: $count) { name }
+                }
+            }
+            ",
+        ).expect("Invalid test query"),
+    );
+
+    assert_eq!(result.data, None);
+    let messages = result
+        .errors
+        .expect("Invalid query was not rejected")
+        .iter()
+        .map(|error| match error {
+            QueryError::ExecutionError(e) => format!("{}", e),
+            e => panic!("Unexpected error: {}", e),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        messages,
+        vec![
+            "Invalid value provided for argument \"first\": String(\"two\")",
+            "Field \"musicians\" has no argument \"limit\"",
+            "Type \"Musician\" has no field \"age\"",
+            "Fragment on \"Song\" can never apply to \"Musician\"",
+            "Unknown fragment \"missingFields\"",
+            "Variable \"$count\" is not defined",
+        ]
+    );
+}
+
+#[test]
+fn can_use_fragments_and_typenames() {
+    let result = execute_query(
+        graphql_parser::parse_query(
+            "
+            query {
+                musicians(first: 1) {
+                    __typename
+                    ...MusicianFields
+                    mainBand {
+                        ... on Band {
+                            name
+                        }
+                        ... on Musician {
+                            id
+                        }
+                    }
+                }
+            }
+
+            fragment MusicianFields on Musician {
+                id
+                name
+            }
+            ",
+        ).expect("Invalid test query"),
+    );
+
+    assert!(
+        result.errors.is_none(),
+        format!("Unexpected errors return for query: {:#?}", result.errors)
+    );
+
+    assert_eq!(
+        result.data,
+        Some(object_value(vec![(
+            "musicians",
+            q::Value::List(vec![object_value(vec![
+                ("__typename", q::Value::String(String::from("Musician"))),
+                ("id", q::Value::String(String::from("m1"))),
+                ("name", q::Value::String(String::from("John"))),
+                (
+                    "mainBand",
+                    object_value(vec![(
+                        "name",
+                        q::Value::String(String::from("The Musicians")),
+                    )]),
+                ),
+            ])]),
+        )])),
+    )
+}
+
+#[test]
+fn can_skip_and_include_selections() {
+    let query = graphql_parser::parse_query(
+        "
+        query musicians($withBands: Boolean!) {
+            musicians(first: 1) {
+                id @skip(if: true)
+                name @include(if: true)
+                bands @include(if: $withBands) {
+                    name
+                }
+                ... on Musician @skip(if: $withBands) {
+                    mainBand {
+                        name
+                    }
+                }
+            }
+        }
+        ",
+    ).expect("Invalid test query");
+
+    let variables = serde_json::from_str(r#"{ "withBands": false }"#).unwrap();
+    let result = execute_operation(query.clone(), Some(variables), None);
+
+    assert!(
+        result.errors.is_none(),
+        format!("Unexpected errors return for query: {:#?}", result.errors)
+    );
+
+    assert_eq!(
+        result.data,
+        Some(object_value(vec![(
+            "musicians",
+            q::Value::List(vec![object_value(vec![
+                ("name", q::Value::String(String::from("John"))),
+                (
+                    "mainBand",
+                    object_value(vec![(
+                        "name",
+                        q::Value::String(String::from("The Musicians")),
+                    )]),
+                ),
+            ])]),
+        )])),
+    );
+
+    let variables = serde_json::from_str(r#"{ "withBands": true }"#).unwrap();
+    let result = execute_operation(query, Some(variables), None);
+
+    assert!(
+        result.errors.is_none(),
+        format!("Unexpected errors return for query: {:#?}", result.errors)
+    );
+
+    assert_eq!(
+        result.data,
+        Some(object_value(vec![(
+            "musicians",
+            q::Value::List(vec![object_value(vec![
+                ("name", q::Value::String(String::from("John"))),
+                (
+                    "bands",
+                    q::Value::List(vec![
+                        object_value(vec![(
+                            "name",
+                            q::Value::String(String::from("The Musicians")),
+                        )]),
+                        object_value(vec![(
+                            "name",
+                            q::Value::String(String::from("The Amateurs")),
+                        )]),
+                    ]),
+                ),
+            ])]),
+        )])),
+    );
+}
+
+#[test]
+fn subscriptions_receive_updated_results() {
+    let logger = Logger::root(slog::Discard, o!());
+    let store = Arc::new(Mutex::new(TestStore::new()));
+    let manager = SubscriptionManager::new(&logger, store.clone());
+
+    let query = Query {
+        schema: test_schema(),
+        document: graphql_parser::parse_query("subscription { musicians { name } }")
+            .expect("Invalid test query"),
+        variables: None,
+        operation_name: None,
+        role: None,
+        explain: false,
+        trace: false,
+    };
+    let results = manager.subscribe(query);
+
+    // Changes to entities the subscription doesn't read are ignored
+    let band = Entity::from(vec![
+        ("__typename", Value::from("Band")),
+        ("id", Value::from("b3")),
+        ("name", Value::from("The Newcomers")),
+    ]);
+    store.lock().unwrap().entities.push(band.clone());
+    manager.handle_store_event(&StoreEvent::EntityAdded(band));
+
+    let musician = Entity::from(vec![
+        ("__typename", Value::from("Musician")),
+        ("id", Value::from("m5")),
+        ("name", Value::from("Zoe")),
+        ("bands", Value::List(vec![])),
+    ]);
+    store.lock().unwrap().entities.push(musician.clone());
+    manager.handle_store_event(&StoreEvent::EntityAdded(musician));
+
+    // Dropping the manager ends the stream of results
+    drop(manager);
+    let results = results.wait().collect::<Result<Vec<_>, _>>().unwrap();
+
+    let musicians = |names: Vec<&str>| {
+        Some(object_value(vec![(
+            "musicians",
+            q::Value::List(
+                names
+                    .into_iter()
+                    .map(|name| object_value(vec![("name", q::Value::String(name.to_string()))]))
+                    .collect(),
+            ),
+        )]))
+    };
+    assert_eq!(results.len(), 2);
+    assert_eq!(
+        results[0].data,
+        musicians(vec!["John", "Lisa", "Tom", "Valerie"])
+    );
+    assert_eq!(
+        results[1].data,
+        musicians(vec!["John", "Lisa", "Tom", "Valerie", "Zoe"])
+    );
+}
+
+#[test]
+fn resolves_nested_fields_with_one_query_per_level() {
+    let query = Query {
+        schema: test_schema(),
+        document: graphql_parser::parse_query(
+            "
+            query {
+                musicians {
+                    name
+                    mainBand { name }
+                    bands { name }
+                    writtenSongs { title }
+                }
+            }
+            ",
+        ).expect("Invalid test query"),
+        variables: None,
+        operation_name: None,
+        role: None,
+        explain: false,
+        trace: false,
+    };
+
+    let logger = Logger::root(slog::Discard, o!());
+    let store = Arc::new(Mutex::new(TestStore::new()));
+    let options = ExecutionOptions {
+        logger: logger.clone(),
+        resolver: StoreResolver::new(&logger, store.clone()),
+        middleware: vec![],
+        max_first: None,
+    };
+    let result = execute(&query, options);
+
+    assert!(
+        result.errors.is_none(),
+        format!("Unexpected errors return for query: {:#?}", result.errors)
+    );
+
+    let name = |name: &str| object_value(vec![("name", q::Value::String(String::from(name)))]);
+    let title = |title: &str| object_value(vec![("title", q::Value::String(String::from(title)))]);
+    let musician = |musician: &str, main_band, bands, songs| {
+        object_value(vec![
+            ("name", q::Value::String(String::from(musician))),
+            ("mainBand", main_band),
+            ("bands", q::Value::List(bands)),
+            ("writtenSongs", q::Value::List(songs)),
+        ])
+    };
+
+    assert_eq!(
+        result.data,
+        Some(object_value(vec![(
+            "musicians",
+            q::Value::List(vec![
+                musician(
+                    "John",
+                    name("The Musicians"),
+                    vec![name("The Musicians"), name("The Amateurs")],
+                    vec![title("Cheesy Tune"), title("Pop Tune")],
+                ),
+                musician(
+                    "Lisa",
+                    name("The Musicians"),
+                    vec![name("The Musicians")],
+                    vec![title("Rock Tune")],
+                ),
+                musician(
+                    "Tom",
+                    name("The Amateurs"),
+                    vec![name("The Musicians"), name("The Amateurs")],
+                    vec![title("Folk Tune")],
+                ),
+                musician("Valerie", q::Value::Null, vec![], vec![]),
+            ]),
+        )])),
+    );
+
+    // One query for the musicians and one for each of their fields
+    assert_eq!(store.lock().unwrap().queries.load(Ordering::SeqCst), 4);
+}
+
+#[test]
+fn can_page_derived_fields_of_each_parent() {
+    let result = execute_query(
+        graphql_parser::parse_query(
+            "
+            query {
+                bands {
+                    name
+                    members(first: 1, skip: 1) {
+                        name
+                        writtenSongs(first: 1, orderBy: title, orderDirection: desc) {
+                            title
+                        }
+                    }
+                }
+            }
+            ",
+        ).expect("Invalid test query"),
+    );
+
+    assert!(
+        result.errors.is_none(),
+        format!("Unexpected errors return for query: {:#?}", result.errors)
+    );
+
+    let band = |band: &str, members| {
+        object_value(vec![
+            ("name", q::Value::String(String::from(band))),
+            ("members", q::Value::List(members)),
+        ])
+    };
+    let member = |member: &str, song: &str| {
+        object_value(vec![
+            ("name", q::Value::String(String::from(member))),
+            (
+                "writtenSongs",
+                q::Value::List(vec![object_value(vec![(
+                    "title",
+                    q::Value::String(String::from(song)),
+                )])]),
+            ),
+        ])
+    };
+
+    assert_eq!(
+        result.data,
+        Some(object_value(vec![(
+            "bands",
+            q::Value::List(vec![
+                band("The Musicians", vec![member("Lisa", "Rock Tune")]),
+                band("The Amateurs", vec![member("Tom", "Folk Tune")]),
+            ]),
+        )])),
+    );
+}
+
+#[test]
+fn can_filter_by_referenced_entities() {
+    let result = execute_query(
+        graphql_parser::parse_query(
+            "
+            query {
+                byMainBand: musicians(where: { mainBand_: { name: \"The Amateurs\" } }) {
+                    name
+                }
+                byBands: musicians(where: { bands_: { name: \"The Amateurs\" } }) {
+                    name
+                }
+            }
+            ",
+        ).expect("Invalid test query"),
+    );
+
+    assert!(
+        result.errors.is_none(),
+        format!("Unexpected errors return for query: {:#?}", result.errors)
+    );
+
+    let musician = |name: &str| object_value(vec![("name", q::Value::String(String::from(name)))]);
+
+    assert_eq!(
+        result.data,
+        Some(object_value(vec![
+            ("byMainBand", q::Value::List(vec![musician("Tom")])),
+            ("byBands", q::Value::List(vec![musician("John"), musician("Tom")])),
+        ])),
+    );
+}
+
+#[test]
+fn resolves_interfaces_to_object_types() {
+    let result = execute_query(
+        graphql_parser::parse_query(
+            "
+            query {
+                artists {
+                    __typename
+                    name
+                }
+                artist(id: \"b2\") {
+                    __typename
+                    name
+                    ... on Band {
+                        members { name }
+                    }
+                }
+            }
+            ",
+        ).expect("Invalid test query"),
+    );
+
+    assert!(
+        result.errors.is_none(),
+        format!("Unexpected errors return for query: {:#?}", result.errors)
+    );
+
+    let artist = |typename: &str, name: &str| {
+        object_value(vec![
+            ("__typename", q::Value::String(String::from(typename))),
+            ("name", q::Value::String(String::from(name))),
+        ])
+    };
+    let musician = |name: &str| object_value(vec![("name", q::Value::String(String::from(name)))]);
+
+    assert_eq!(
+        result.data,
+        Some(object_value(vec![
+            (
+                "artists",
+                q::Value::List(vec![
+                    artist("Musician", "John"),
+                    artist("Musician", "Lisa"),
+                    artist("Musician", "Tom"),
+                    artist("Musician", "Valerie"),
+                    artist("Band", "The Musicians"),
+                    artist("Band", "The Amateurs"),
+                ]),
+            ),
+            (
+                "artist",
+                object_value(vec![
+                    ("__typename", q::Value::String(String::from("Band"))),
+                    ("name", q::Value::String(String::from("The Amateurs"))),
+                    ("members", q::Value::List(vec![musician("John"), musician("Tom")])),
+                ]),
+            ),
+        ])),
+    );
+}
+
+#[test]
+fn can_order_and_page_interfaces_across_object_types() {
+    let result = execute_query(
+        graphql_parser::parse_query(
+            "
+            query {
+                artists(orderBy: name, first: 3, skip: 1) {
+                    __typename
+                    name
+                }
+            }
+            ",
+        ).expect("Invalid test query"),
+    );
+
+    assert!(
+        result.errors.is_none(),
+        format!("Unexpected errors return for query: {:#?}", result.errors)
+    );
+
+    let artist = |typename: &str, name: &str| {
+        object_value(vec![
+            ("__typename", q::Value::String(String::from(typename))),
+            ("name", q::Value::String(String::from(name))),
+        ])
+    };
+
+    assert_eq!(
+        result.data,
+        Some(object_value(vec![(
+            "artists",
+            q::Value::List(vec![
+                artist("Musician", "Lisa"),
+                artist("Band", "The Amateurs"),
+                artist("Band", "The Musicians"),
+            ]),
+        )])),
+    );
+}
+
+#[test]
+fn includes_the_plan_of_queries_that_ask_for_it() {
+    let execute_explained = |explain: bool| {
+        let query = Query {
+            schema: test_schema(),
+            document: graphql_parser::parse_query("{ musicians(first: 2) { name bands { name } } }")
+                .expect("Invalid test query"),
+            variables: None,
+            operation_name: None,
+            role: None,
+            explain,
+            trace: false,
+        };
+
+        let logger = Logger::root(slog::Discard, o!());
+        let store = Arc::new(Mutex::new(TestStore::new()));
+        let options = ExecutionOptions {
+            logger: logger.clone(),
+            resolver: StoreResolver::new(&logger, store).with_query_plan(),
+            middleware: vec![],
+            max_first: None,
+        };
+        execute(&query, options)
+    };
+
+    assert_eq!(execute_explained(false).plan, None);
+
+    let plan = execute_explained(true).plan.expect("Query has no plan");
+    assert_eq!(
+        plan.steps[0],
+        QueryPlanStep {
+            field: String::from("musicians"),
+            entity: String::from("Musician"),
+            strategy: String::from("find"),
+            sql: None,
+        }
+    );
+    assert!(
+        plan.steps[1..]
+            .iter()
+            .any(|step| step.field.ends_with("bands") && step.entity == "Band")
+    );
+}
+
+#[test]
+fn includes_a_trace_of_queries_that_ask_for_it() {
+    let execute_traced = |trace: bool| {
+        let query = Query {
+            schema: test_schema(),
+            document: graphql_parser::parse_query("{ musicians(first: 2) { name bands { name } } }")
+                .expect("Invalid test query"),
+            variables: None,
+            operation_name: None,
+            role: None,
+            explain: false,
+            trace,
+        };
+
+        let logger = Logger::root(slog::Discard, o!());
+        let store = Arc::new(Mutex::new(TestStore::new()));
+        let options = ExecutionOptions {
+            logger: logger.clone(),
+            resolver: StoreResolver::new(&logger, store).with_trace(),
+            middleware: vec![],
+            max_first: None,
+        };
+        execute(&query, options)
+    };
+
+    assert_eq!(execute_traced(false).trace, None);
+
+    let trace = execute_traced(true).trace.expect("Query has no trace");
+    assert_eq!(trace.version, 1);
+
+    // Every field is traced, and the root field takes longest since it
+    // includes its subfields
+    let musicians = trace
+        .execution
+        .resolvers
+        .iter()
+        .find(|resolver| resolver.path == vec![PathSegment::Key(String::from("musicians"))])
+        .expect("Root field is not traced");
+    assert_eq!(musicians.parent_type, "Query");
+    assert_eq!(musicians.return_type, "[Musician!]!");
+    assert!(trace.execution.resolvers.iter().all(|resolver| {
+        resolver.duration <= musicians.duration && resolver.duration <= trace.duration
+    }));
+    assert!(trace.execution.resolvers.iter().any(|resolver| {
+        resolver.path
+            == vec![
+                PathSegment::Key(String::from("musicians")),
+                PathSegment::Index(1),
+                PathSegment::Key(String::from("bands")),
+                PathSegment::Index(0),
+                PathSegment::Key(String::from("name")),
+            ]
+            && resolver.parent_type == "Band"
+    }));
+
+    assert_eq!(trace.store_queries[0].field, "musicians");
+    assert_eq!(trace.store_queries[0].entity, "Musician");
+    assert_eq!(trace.store_queries[0].strategy, "find");
+}
+
+/// Field middleware that hides the names of musicians, denies access to
+/// their main bands and records the fields it has seen resolved.
+struct HidingMiddleware {
+    resolved: Mutex<Vec<String>>,
+}
+
+impl FieldMiddleware for HidingMiddleware {
+    fn before_field(&self, field: &FieldInfo) -> Result<Option<q::Value>, QueryExecutionError> {
+        match (field.parent_type, field.field_name) {
+            ("Musician", "name") => Ok(Some(q::Value::String(String::from("Anonymous")))),
+            ("Musician", "mainBand") => Err(QueryExecutionError::NotSupported(String::from(
+                "main bands",
+            ))),
+            _ => Ok(None),
+        }
+    }
+
+    fn after_field(
+        &self,
+        field: &FieldInfo,
+        _result: &Result<q::Value, QueryExecutionError>,
+        _duration: Duration,
+    ) {
+        self.resolved
+            .lock()
+            .unwrap()
+            .push(format!("{}.{}", field.parent_type, field.field_name));
+    }
+}
+
+#[test]
+fn runs_field_middleware_around_resolvers() {
+    let query = Query {
+        schema: test_schema(),
+        document: graphql_parser::parse_query("{ musicians(first: 2) { name mainBand { name } } }")
+            .expect("Invalid test query"),
+        variables: None,
+        operation_name: None,
+        role: None,
+        explain: false,
+        trace: false,
+    };
+
+    let logger = Logger::root(slog::Discard, o!());
+    let store = Arc::new(Mutex::new(TestStore::new()));
+    let middleware = Arc::new(HidingMiddleware {
+        resolved: Mutex::new(vec![]),
+    });
+    let options = ExecutionOptions {
+        logger: logger.clone(),
+        resolver: StoreResolver::new(&logger, store),
+        middleware: vec![middleware.clone()],
+        max_first: None,
+    };
+    let result = execute(&query, options);
+
+    let anonymous = object_value(vec![
+        ("name", q::Value::String(String::from("Anonymous"))),
+        ("mainBand", q::Value::Null),
+    ]);
+    assert_eq!(
+        result.data,
+        Some(object_value(vec![(
+            "musicians",
+            q::Value::List(vec![anonymous.clone(), anonymous]),
+        )])),
+    );
+    assert_eq!(result.errors.map(|errors| errors.len()), Some(2));
+    assert_eq!(
+        *middleware.resolved.lock().unwrap(),
+        vec![
+            "Query.musicians",
+            "Musician.name",
+            "Musician.mainBand",
+            "Musician.name",
+            "Musician.mainBand",
+        ]
+    );
+}
+
+#[test]
+fn rejects_fields_that_ask_for_too_many_entities() {
+    let execute_limited = |query: &str| {
+        let query = Query {
+            schema: test_schema(),
+            document: graphql_parser::parse_query(query).expect("Invalid test query"),
+            variables: None,
+            operation_name: None,
+            role: None,
+            explain: false,
+            trace: false,
+        };
+
+        let logger = Logger::root(slog::Discard, o!());
+        let store = Arc::new(Mutex::new(TestStore::new()));
+        let options = ExecutionOptions {
+            logger: logger.clone(),
+            resolver: StoreResolver::new(&logger, store),
+            middleware: vec![],
+            max_first: Some(2),
+        };
+        execute(&query, options)
+    };
+
+    let result = execute_limited("{ musicians(first: 2) { name bands(first: 1) { name } } }");
+    assert!(
+        result.errors.is_none(),
+        format!("Unexpected errors return for query: {:#?}", result.errors)
+    );
+
+    let result = execute_limited("{ musicians(first: 3) { name } }");
+    let errors = result.errors.expect("The large page was not rejected");
+    assert_eq!(errors.len(), 1);
+    match &errors[0] {
+        QueryError::FieldError(_, _, e @ QueryExecutionError::FirstTooLargeError(..)) => {
+            assert_eq!(
+                e.to_string(),
+                "Argument \"first\" is 3 but may be at most 2; page through the entities instead"
+            );
+        }
+        e => panic!("Unexpected error: {}", e),
+    }
+}
+
+#[test]
+fn stops_querying_the_store_after_the_deadline() {
+    let query = Query {
+        schema: test_schema(),
+        document: graphql_parser::parse_query("{ musicians { name bands { name } } }")
+            .expect("Invalid test query"),
+        variables: None,
+        operation_name: None,
+        role: None,
+        explain: false,
+        trace: false,
+    };
+
+    let logger = Logger::root(slog::Discard, o!());
+    let store = Arc::new(Mutex::new(TestStore::new()));
+    let options = ExecutionOptions {
+        logger: logger.clone(),
+        resolver: StoreResolver::new(&logger, store.clone()).with_deadline(Instant::now()),
+        middleware: vec![],
+        max_first: None,
+    };
+    let result = execute(&query, options);
+
+    // The field that could not be queried anymore is reported
+    match result.errors.as_ref().map(|errors| &errors[..]) {
+        Some([QueryError::FieldError(_, path, QueryExecutionError::TimeoutError)]) => {
+            assert_eq!(path, &vec![PathSegment::Key(String::from("musicians"))])
+        }
+        errors => panic!("Unexpected errors: {:?}", errors),
+    }
+    assert_eq!(store.lock().unwrap().queries.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn reports_store_failures_as_field_errors() {
+    // The test store only buckets values by whole sizes
+    let result = execute_query(
+        graphql_parser::parse_query(
+            "
+            query {
+                songsHistogram(field: duration, size: \"2.5\") {
+                    buckets { count }
+                }
+            }
+            ",
+        ).expect("Invalid test query"),
+    );
+
+    let errors = result.errors.expect("The store failure was not reported");
+    assert_eq!(errors.len(), 1);
+    match &errors[0] {
+        QueryError::FieldError(_, path, e @ QueryExecutionError::StoreError(_)) => {
+            assert_eq!(path, &vec![PathSegment::Key(String::from("songsHistogram"))]);
+            assert_eq!(e.to_string(), "Failed to query Song entities from the store");
+        }
+        e => panic!("Unexpected error: {}", e),
+    }
+
+    // The histogram is non-null, so there is no data
+    assert_eq!(result.data, Some(q::Value::Null));
+}
+
+#[test]
+fn resolves_the_meta_field_to_the_indexing_state() {
+    let execute_meta = |block| {
+        let query = Query {
+            schema: test_schema(),
+            document: graphql_parser::parse_query(
+                "{ _meta { block { number hash timestamp } deployment hasIndexingErrors } }",
+            ).expect("Invalid test query"),
+            variables: None,
+            operation_name: None,
+            role: None,
+            explain: false,
+            trace: false,
+        };
+
+        let logger = Logger::root(slog::Discard, o!());
+        let store = Arc::new(Mutex::new(TestStore::new()));
+        let options = ExecutionOptions {
+            logger: logger.clone(),
+            resolver: StoreResolver::new(&logger, store.clone())
+                .with_meta(String::from("test_subgraph"), block),
+            middleware: vec![],
+            max_first: None,
+        };
+        let result = execute(&query, options);
+        assert_eq!(store.lock().unwrap().queries.load(Ordering::SeqCst), 0);
+        result.data.expect("Query returned no data")
+    };
+
+    let block = EthereumBlockHeader {
+        hash: Default::default(),
+        number: 42,
+        timestamp: 1_536_000_000,
+    };
+    assert_eq!(
+        execute_meta(Some(block)),
+        object_value(vec![(
+            "_meta",
+            object_value(vec![
+                (
+                    "block",
+                    object_value(vec![
+                        ("number", q::Value::Int(q::Number::from(42))),
+                        ("hash", q::Value::String(format!("0x{}", "0".repeat(64)))),
+                        ("timestamp", q::Value::Int(q::Number::from(1_536_000_000))),
+                    ]),
+                ),
+                ("deployment", q::Value::String(String::from("test_subgraph"))),
+                ("hasIndexingErrors", q::Value::Boolean(false)),
+            ]),
+        )])
+    );
+
+    // Before the latest block indexed has been looked up, it is unknown
+    assert_eq!(
+        execute_meta(None),
+        object_value(vec![(
+            "_meta",
+            object_value(vec![
+                ("block", q::Value::Null),
+                ("deployment", q::Value::String(String::from("test_subgraph"))),
+                ("hasIndexingErrors", q::Value::Boolean(false)),
+            ]),
+        )])
+    );
+}
+
+#[test]
+fn resolves_the_meta_field_of_stitched_schemas_for_each_deployment() {
+    let subgraph = |id: &str, input: &str| {
+        let mut schema = Schema {
+            id: id.to_owned(),
+            document: graphql_parser::parse_schema(input).expect("Test schema invalid"),
+        };
+        schema.add_subgraph_id_directives(id.to_owned());
+        let document = api_schema(&schema.document).expect("Failed to derive API schema");
+        (
+            id.to_owned(),
+            Schema {
+                id: id.to_owned(),
+                document,
+            },
+        )
+    };
+    let schema = stitch_schemas(
+        "combined",
+        &[
+            subgraph("music", "type Band @entity { id: ID!, name: String! }"),
+            subgraph("tokens", "type Token @entity { id: ID!, symbol: String! }"),
+        ],
+    ).expect("Failed to stitch schemas");
+    let deployments = stitched_subgraph_ids(&schema.document);
+    assert_eq!(deployments, vec![String::from("music"), String::from("tokens")]);
+
+    let query = Query {
+        schema,
+        document: graphql_parser::parse_query("{ _meta { block { number } deployment } }")
+            .expect("Invalid test query"),
+        variables: None,
+        operation_name: None,
+        role: None,
+        explain: false,
+        trace: false,
+    };
+
+    // The deployments index independently, so they can be at different blocks
+    let block = EthereumBlockHeader {
+        hash: Default::default(),
+        number: 42,
+        timestamp: 1_536_000_000,
+    };
+    let logger = Logger::root(slog::Discard, o!());
+    let store = Arc::new(Mutex::new(TestStore::new()));
+    let options = ExecutionOptions {
+        logger: logger.clone(),
+        resolver: StoreResolver::new(&logger, store.clone())
+            .with_meta(deployments[0].clone(), Some(block))
+            .with_meta(deployments[1].clone(), None),
+        middleware: vec![],
+        max_first: None,
+    };
+    let result = execute(&query, options);
+    assert_eq!(store.lock().unwrap().queries.load(Ordering::SeqCst), 0);
+    assert_eq!(
+        result.data.expect("Query returned no data"),
+        object_value(vec![(
+            "_meta",
+            q::Value::List(vec![
+                object_value(vec![
+                    (
+                        "block",
+                        object_value(vec![("number", q::Value::Int(q::Number::from(42)))]),
+                    ),
+                    ("deployment", q::Value::String(String::from("music"))),
+                ]),
+                object_value(vec![
+                    ("block", q::Value::Null),
+                    ("deployment", q::Value::String(String::from("tokens"))),
+                ]),
+            ]),
+        )])
+    );
+}
+
+#[test]
+fn resolves_fulltext_search_fields() {
+    let result = execute_query(
+        graphql_parser::parse_query(
+            "
+            query {
+                songsSearch(text: \"cheesy\") {
+                    title
+                    writtenBy { name }
+                }
+            }
+            ",
+        ).expect("Invalid test query"),
+    );
+
+    assert!(
+        result.errors.is_none(),
+        format!("Unexpected errors return for query: {:#?}", result.errors)
+    );
+
+    assert_eq!(
+        result.data,
+        Some(object_value(vec![(
+            "songsSearch",
+            q::Value::List(vec![object_value(vec![
+                ("title", q::Value::String(String::from("Cheesy Tune"))),
+                (
+                    "writtenBy",
+                    object_value(vec![("name", q::Value::String(String::from("John")))]),
+                ),
+            ])]),
+        )])),
+    );
+}
+
+#[test]
+fn resolves_aggregate_fields() {
+    let result = execute_query(
+        graphql_parser::parse_query(
+            "
+            query {
+                all: songsAggregate { count }
+                songsAggregate(where: { writtenBy: \"m1\" }) {
+                    count
+                    sum { duration }
+                    min { duration }
+                    max { duration }
+                }
+            }
+            ",
+        ).expect("Invalid test query"),
+    );
+
+    assert!(
+        result.errors.is_none(),
+        format!("Unexpected errors return for query: {:#?}", result.errors)
+    );
+
+    let duration = |n: &str| object_value(vec![("duration", q::Value::String(n.to_owned()))]);
+    assert_eq!(
+        result.data,
+        Some(object_value(vec![
+            (
+                "all",
+                object_value(vec![("count", q::Value::Int(q::Number::from(4)))]),
+            ),
+            (
+                "songsAggregate",
+                object_value(vec![
+                    ("count", q::Value::Int(q::Number::from(2))),
+                    ("sum", duration("380")),
+                    ("min", duration("180")),
+                    ("max", duration("200")),
+                ]),
+            ),
+        ])),
+    );
+}
+
+#[test]
+fn resolves_histogram_fields() {
+    let result = execute_query(
+        graphql_parser::parse_query(
+            "
+            query {
+                all: songsHistogram(field: duration, size: 50) {
+                    buckets { start count }
+                }
+                songsHistogram(field: duration, size: \"100\", where: { writtenBy: \"m1\" }) {
+                    buckets { start count }
+                }
+            }
+            ",
+        ).expect("Invalid test query"),
+    );
+
+    assert!(
+        result.errors.is_none(),
+        format!("Unexpected errors return for query: {:#?}", result.errors)
+    );
+
+    let buckets = |buckets: Vec<(&str, i32)>| {
+        object_value(vec![(
+            "buckets",
+            q::Value::List(
+                buckets
+                    .into_iter()
+                    .map(|(start, count)| {
+                        object_value(vec![
+                            ("start", q::Value::String(start.to_owned())),
+                            ("count", q::Value::Int(q::Number::from(count))),
+                        ])
+                    })
+                    .collect(),
+            ),
+        )])
+    };
+    assert_eq!(
+        result.data,
+        Some(object_value(vec![
+            ("all", buckets(vec![("150", 1), ("200", 2)])),
+            ("songsHistogram", buckets(vec![("100", 1), ("200", 1)])),
+        ])),
+    );
+}
+
+#[test]
+fn coerces_nested_lists_element_by_element() {
+    let result = execute_query(
+        graphql_parser::parse_query(
+            "
+            query {
+                songs(orderBy: id, first: 3) {
+                    id
+                    ratings
+                }
+            }
+            ",
+        ).expect("Invalid test query"),
+    );
+
+    // The invalid rating turns the list it is in into null, with an error
+    let errors = result.errors.expect("Expected an error for the invalid rating");
+    assert_eq!(errors.len(), 1);
+    assert!(format!("{}", errors[0]).contains("\"bad\""));
+
+    let int = |n: i32| q::Value::Int(q::Number::from(n));
+    assert_eq!(
+        result.data,
+        Some(object_value(vec![(
+            "songs",
+            q::Value::List(vec![
+                object_value(vec![
+                    ("id", q::Value::String(String::from("s1"))),
+                    (
+                        "ratings",
+                        q::Value::List(vec![
+                            q::Value::List(vec![int(5), int(4)]),
+                            q::Value::List(vec![int(3)]),
+                        ]),
+                    ),
+                ]),
+                object_value(vec![
+                    ("id", q::Value::String(String::from("s2"))),
+                    (
+                        "ratings",
+                        q::Value::List(vec![q::Value::Null, q::Value::List(vec![int(2)])]),
+                    ),
+                ]),
+                object_value(vec![
+                    ("id", q::Value::String(String::from("s3"))),
+                    ("ratings", q::Value::Null),
+                ]),
+            ]),
+        )])),
+    );
+}
+
+#[test]
+fn resolves_unions_to_member_types() {
+    let result = execute_query(
+        graphql_parser::parse_query(
+            "
+            query {
+                searchResults(orderBy: id, first: 3) {
+                    __typename
+                    ... on Band { name }
+                    ... on Song { title }
+                }
+            }
+            ",
+        ).expect("Invalid test query"),
+    );
+
+    assert!(
+        result.errors.is_none(),
+        format!("Unexpected errors return for query: {:#?}", result.errors)
+    );
+
+    assert_eq!(
+        result.data,
+        Some(object_value(vec![(
+            "searchResults",
+            q::Value::List(vec![
+                object_value(vec![
+                    ("__typename", q::Value::String(String::from("Band"))),
+                    ("name", q::Value::String(String::from("The Musicians"))),
+                ]),
+                object_value(vec![
+                    ("__typename", q::Value::String(String::from("Band"))),
+                    ("name", q::Value::String(String::from("The Amateurs"))),
+                ]),
+                object_value(vec![
+                    ("__typename", q::Value::String(String::from("Song"))),
+                    ("title", q::Value::String(String::from("Cheesy Tune"))),
+                ]),
+            ]),
+        )])),
+    );
+}
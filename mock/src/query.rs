@@ -1,4 +1,3 @@
-use futures::sync::mpsc::{channel, Receiver, Sender};
 use graphql_parser::query as gqlq;
 use std::collections::BTreeMap;
 
@@ -7,7 +6,6 @@ use graph::prelude::*;
 /// A mock `QueryRunner`.
 pub struct MockQueryRunner<S> {
     logger: slog::Logger,
-    query_sink: Sender<Query>,
     _store: Arc<S>,
 }
 
@@ -17,48 +15,33 @@ where
 {
     /// Creates a new mock `QueryRunner`.
     pub fn new(logger: &slog::Logger, store: S) -> Self {
-        let (sink, stream) = channel(100);
-        let runner = MockQueryRunner {
+        MockQueryRunner {
             logger: logger.new(o!("component" => "MockQueryRunner")),
-            query_sink: sink,
             _store: Arc::new(store),
-        };
-        runner.run_queries(stream);
-        runner
-    }
-
-    /// Spawns a Tokio task to run any queries received through the given stream.
-    fn run_queries(&self, stream: Receiver<Query>) {
-        info!(self.logger, "Preparing to run queries");
-
-        let logger = self.logger.clone();
-
-        tokio::spawn(stream.for_each(move |query| {
-            info!(logger, "Running query"; "query" => format!("{:?}", query));
-
-            // Here we would access the store.
-            let mut data = BTreeMap::new();
-            data.insert(
-                String::from("allUsers"),
-                gqlq::Value::String("placeholder".to_string()),
-            );
-            data.insert(
-                String::from("allItems"),
-                gqlq::Value::String("placeholder".to_string()),
-            );
-            let data = gqlq::Value::Object(data);
-
-            query
-                .result_sender
-                .send(QueryResult::new(Some(data)))
-                .expect("Failed to send query result back");
-            Ok(())
-        }));
+        }
     }
 }
 
 impl<S> QueryRunner for MockQueryRunner<S> {
-    fn query_sink(&mut self) -> Sender<Query> {
-        self.query_sink.clone()
+    fn run_query(&self, query: Query, target: QueryTarget) -> QueryResponseFuture {
+        info!(self.logger, "Running query";
+              "query" => format!("{:?}", query),
+              "target" => target.to_string());
+
+        // Here we would access the store.
+        let mut data = BTreeMap::new();
+        data.insert(
+            String::from("allUsers"),
+            gqlq::Value::String("placeholder".to_string()),
+        );
+        data.insert(
+            String::from("allItems"),
+            gqlq::Value::String("placeholder".to_string()),
+        );
+        let data = gqlq::Value::Object(data);
+
+        Box::new(future::ok(QueryResponse::Complete(QueryResult::new(Some(
+            data,
+        )))))
     }
 }
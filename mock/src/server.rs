@@ -1,44 +1,26 @@
 use futures::sync::mpsc::{channel, Receiver, Sender};
-use futures::sync::oneshot;
 use graphql_parser;
-use std::error::Error;
-use std::fmt;
+use std::net::SocketAddr;
 use std::sync::Mutex;
 
 use graph::prelude::*;
 use graph_graphql::prelude::api_schema;
 
-#[derive(Debug)]
-pub struct MockServeError;
-
-impl Error for MockServeError {
-    fn description(&self) -> &str {
-        "Mock serve error"
-    }
-
-    fn cause(&self) -> Option<&Error> {
-        None
-    }
-}
-
-impl fmt::Display for MockServeError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Mock serve error")
-    }
-}
-
 /// A mock `GraphQLServer`.
-pub struct MockGraphQLServer {
+pub struct MockGraphQLServer<Q> {
     logger: Logger,
-    query_sink: Option<Sender<Query>>,
+    query_runner: Arc<Q>,
     schema_event_sink: Sender<SchemaEvent>,
     store_event_sink: Sender<StoreEvent>,
     schema: Arc<Mutex<Option<Schema>>>,
 }
 
-impl MockGraphQLServer {
+impl<Q> MockGraphQLServer<Q>
+where
+    Q: QueryRunner + Send + Sync + 'static,
+{
     /// Creates a new mock `GraphQLServer`.
-    pub fn new(logger: &Logger) -> Self {
+    pub fn new(logger: &Logger, query_runner: Arc<Q>) -> Self {
         // Create channels for handling incoming schema and store events
         let (store_sink, store_stream) = channel(100);
         let (schema_event_sink, schema_event_stream) = channel(100);
@@ -46,7 +28,7 @@ impl MockGraphQLServer {
         // Create a new mock GraphQL server
         let mut server = MockGraphQLServer {
             logger: logger.new(o!("component" => "MockGraphQLServer")),
-            query_sink: None,
+            query_runner,
             schema_event_sink,
             store_event_sink: store_sink,
             schema: Arc::new(Mutex::new(None)),
@@ -68,18 +50,27 @@ impl MockGraphQLServer {
         tokio::spawn(stream.for_each(move |event| {
             info!(logger, "Received schema event"; "event" => format!("{:?}", event));
 
-            if let SchemaEvent::SchemaAdded(new_schema) = event {
-                let mut schema = schema.lock().unwrap();
-                let derived_schema = match api_schema(&new_schema.document) {
-                    Ok(document) => Schema {
-                        id: new_schema.id.clone(),
-                        document,
-                    },
-                    Err(e) => return Ok(error!(logger, "error deriving schema {}", e)),
-                };
-                *schema = Some(derived_schema);
-            } else {
-                panic!("schema removal is yet not supported")
+            match event {
+                SchemaEvent::SchemaAdded(new_schema) | SchemaEvent::SchemaUpdated(new_schema) => {
+                    let mut schema = schema.lock().unwrap();
+                    let derived_schema = match api_schema(&new_schema.document) {
+                        Ok(document) => Schema {
+                            id: new_schema.id.clone(),
+                            document,
+                        },
+                        Err(e) => return Ok(error!(logger, "error deriving schema {}", e)),
+                    };
+                    *schema = Some(derived_schema);
+                }
+                SchemaEvent::SchemaRemoved(removed_schema) => {
+                    let mut schema = schema.lock().unwrap();
+                    if schema
+                        .as_ref()
+                        .map_or(false, |schema| schema.id == removed_schema.id)
+                    {
+                        *schema = None;
+                    }
+                }
             }
 
             Ok(())
@@ -97,8 +88,11 @@ impl MockGraphQLServer {
     }
 }
 
-impl GraphQLServer for MockGraphQLServer {
-    type ServeError = MockServeError;
+impl<Q> GraphQLServer for MockGraphQLServer<Q>
+where
+    Q: QueryRunner + Send + Sync + 'static,
+{
+    type ServeError = GraphError;
 
     fn schema_event_sink(&mut self) -> Sender<SchemaEvent> {
         self.schema_event_sink.clone()
@@ -108,62 +102,43 @@ impl GraphQLServer for MockGraphQLServer {
         self.store_event_sink.clone()
     }
 
-    fn query_stream(&mut self) -> Result<Receiver<Query>, StreamError> {
-        // If possible, create a new channel for streaming incoming queries
-        match self.query_sink {
-            Some(_) => Err(StreamError::AlreadyCreated),
-            None => {
-                let (sink, stream) = channel(100);
-                self.query_sink = Some(sink);
-                Ok(stream)
-            }
-        }
-    }
-
     fn serve(
         &mut self,
-        _port: u16,
+        _addrs: &[SocketAddr],
     ) -> Result<Box<Future<Item = (), Error = ()> + Send>, Self::ServeError> {
-        // Only launch the GraphQL server if there is a component that will handle incoming queries
-        let query_sink = self.query_sink.clone().ok_or_else(|| MockServeError)?;
+        let query_runner = self.query_runner.clone();
         let schema = self.schema.clone();
 
-        // Generate mock query requests
-        let requests = (0..5)
+        // Generate mock queries
+        let queries = (0..5)
             .map(|_| {
                 let schema = schema.lock().unwrap();
-                let (sink, stream) = oneshot::channel();
-                (
-                    stream,
-                    Query {
-                        schema: schema.clone().unwrap(),
-                        document: graphql_parser::parse_query("{ allUsers { name }}").unwrap(),
-                        variables: None,
-                        result_sender: sink,
-                    },
-                )
+                Ok(Query {
+                    schema: schema.clone().ok_or_else(|| {
+                        GraphError::from_message(ErrorCategory::Query, "No schema available")
+                    })?,
+                    document: graphql_parser::parse_query("{ allUsers { name }}").unwrap(),
+                    variables: None,
+                    operation_name: None,
+                    role: None,
+                    explain: false,
+                    trace: false,
+                })
             })
-            .collect::<Vec<(oneshot::Receiver<QueryResult>, Query)>>();
+            .collect::<Result<Vec<Query>, GraphError>>()?;
 
-        println!("Requests: {:?}", requests);
+        println!("Queries: {:?}", queries);
 
         let logger = self.logger.clone();
 
-        // Create task to generate mock queries
-        Ok(Box::new(stream::iter_ok(requests).for_each(
-            move |(receiver, query)| {
-                query_sink
-                    .clone()
-                    .send(query)
-                    .wait()
-                    .expect("Failed to forward mock query");
-
-                let logger = logger.clone();
-                receiver.then(move |result| {
-                    info!(logger, "Send query result to client: {:?}", result);
-                    Ok(())
-                })
-            },
-        )))
+        // Create task to run the mock queries
+        Ok(Box::new(stream::iter_ok(queries).for_each(move |query| {
+            let target = QueryTarget::new(SubgraphTarget::Deployment(query.schema.id.clone()));
+            let logger = logger.clone();
+            query_runner.run_query(query, target).then(move |result| {
+                info!(logger, "Send query result to client: {:?}", result);
+                Ok(())
+            })
+        })))
     }
 }
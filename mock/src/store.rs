@@ -62,11 +62,16 @@ impl BasicStore for MockStore {
         }
     }
 
-    fn set(&mut self, _key: StoreKey, _entity: Entity, _source: EventSource) -> Result<(), ()> {
+    fn set(
+        &mut self,
+        _key: StoreKey,
+        _entity: Entity,
+        _source: EventSource,
+    ) -> Result<(), StoreWriteError> {
         unimplemented!();
     }
 
-    fn delete(&mut self, _key: StoreKey, _source: EventSource) -> Result<(), ()> {
+    fn delete(&mut self, _key: StoreKey, _source: EventSource) -> Result<(), StoreWriteError> {
         unimplemented!();
     }
 
@@ -99,11 +104,11 @@ impl BasicStore for FakeStore {
         panic!("called FakeStore")
     }
 
-    fn set(&mut self, _: StoreKey, _: Entity, _source: EventSource) -> Result<(), ()> {
+    fn set(&mut self, _: StoreKey, _: Entity, _source: EventSource) -> Result<(), StoreWriteError> {
         panic!("called FakeStore")
     }
 
-    fn delete(&mut self, _: StoreKey, _source: EventSource) -> Result<(), ()> {
+    fn delete(&mut self, _: StoreKey, _source: EventSource) -> Result<(), StoreWriteError> {
         panic!("called FakeStore")
     }
 
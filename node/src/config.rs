@@ -0,0 +1,270 @@
+use clap::ArgMatches;
+use std::cmp;
+use std::fmt;
+use std::str::FromStr;
+
+/// Prefix of the environment variables that configure the node.
+const ENV_PREFIX: &str = "THEGRAPH_";
+
+/// Environment variables that the node or its tests read.
+const ENV_VARS: &[&str] = &[
+    "THEGRAPH_AUTH_SECRET",
+    "THEGRAPH_SENTRY_URL",
+    "THEGRAPH_STORE_POSTGRES_DIESEL_URL",
+];
+
+/// A problem with a single setting of the node, e.g. a malformed flag value
+/// or a misspelled environment variable.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConfigProblem {
+    /// The setting, e.g. `--admin-port` or `THEGRAPH_SENTRY_URL`
+    pub key: String,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.key, self.message)
+    }
+}
+
+/// Reads the settings of the node while collecting every problem with them,
+/// so that they can be reported together instead of one per start attempt.
+pub struct ConfigCheck<'a> {
+    matches: &'a ArgMatches<'a>,
+    problems: Vec<ConfigProblem>,
+}
+
+impl<'a> ConfigCheck<'a> {
+    pub fn new(matches: &'a ArgMatches<'a>) -> Self {
+        ConfigCheck {
+            matches,
+            problems: vec![],
+        }
+    }
+
+    /// Records a problem with the setting `key`.
+    pub fn problem<S: Into<String>>(&mut self, key: &str, message: S) {
+        self.problems.push(ConfigProblem {
+            key: key.to_owned(),
+            message: message.into(),
+        });
+    }
+
+    /// Records that `value` of the flag `name` is not what it should be.
+    pub fn invalid(&mut self, name: &str, value: &str, expected: &str) {
+        self.problem(
+            &format!("--{}", name),
+            format!("expected {}, found `{}`", expected, value),
+        );
+    }
+
+    /// All values given for the flag `name`.
+    pub fn values_of(&self, name: &str) -> Vec<&'a str> {
+        self.matches
+            .values_of(name)
+            .map(|values| values.collect())
+            .unwrap_or_default()
+    }
+
+    /// Parses the value of the flag `name`, if it was given.
+    pub fn parse<T: FromStr>(&mut self, name: &str, expected: &str) -> Option<T> {
+        let matches = self.matches;
+        matches
+            .value_of(name)
+            .and_then(|value| self.parse_value(name, value, expected))
+    }
+
+    /// Parses every value of the flag `name`, skipping malformed ones.
+    pub fn parse_all<T: FromStr>(&mut self, name: &str, expected: &str) -> Vec<T> {
+        let matches = self.matches;
+        matches
+            .values_of(name)
+            .map(|values| {
+                values
+                    .filter_map(|value| self.parse_value(name, value, expected))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Parses `value`, which was given for the flag `name`.
+    pub fn parse_value<T: FromStr>(
+        &mut self,
+        name: &str,
+        value: &str,
+        expected: &str,
+    ) -> Option<T> {
+        match value.parse() {
+            Ok(value) => Some(value),
+            Err(_) => {
+                self.invalid(name, value, expected);
+                None
+            }
+        }
+    }
+
+    /// Splits `value` of the flag `name` at the first `separator`, e.g. a
+    /// `SUBGRAPH_ID:THRESHOLD` pair.
+    pub fn split_value<'v>(
+        &mut self,
+        name: &str,
+        value: &'v str,
+        separator: char,
+        format: &str,
+    ) -> Option<(&'v str, &'v str)> {
+        let mut split = value.splitn(2, separator);
+        match (split.next(), split.next()) {
+            (Some(left), Some(right)) => Some((left, right)),
+            _ => {
+                self.invalid(name, value, format);
+                None
+            }
+        }
+    }
+
+    /// Checks that every environment variable starting with `THEGRAPH_` is
+    /// one that the node knows, suggesting the closest one otherwise.
+    pub fn environment<I: IntoIterator<Item = String>>(&mut self, names: I) {
+        for name in names {
+            if !name.starts_with(ENV_PREFIX) || ENV_VARS.contains(&name.as_str()) {
+                continue;
+            }
+            let message = match did_you_mean(&name, ENV_VARS) {
+                Some(known) => format!("unknown environment variable, did you mean `{}`?", known),
+                None => "unknown environment variable".to_owned(),
+            };
+            self.problem(&name, message);
+        }
+    }
+
+    /// Returns all problems in the order in which they were found, if any.
+    pub fn finish(self) -> Result<(), Vec<ConfigProblem>> {
+        if self.problems.is_empty() {
+            Ok(())
+        } else {
+            Err(self.problems)
+        }
+    }
+}
+
+/// Returns the candidate closest to `name` if it is close enough for `name`
+/// to be a typo of it.
+pub fn did_you_mean<'c>(name: &str, candidates: &[&'c str]) -> Option<&'c str> {
+    let max_distance = cmp::max(1, name.len() / 3);
+    candidates
+        .iter()
+        .map(|candidate| (edit_distance(name, candidate), *candidate))
+        .filter(|&(distance, _)| distance <= max_distance)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// The Levenshtein distance between `a` and `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..b.len() + 1).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + if a == *b { 0 } else { 1 };
+            let insertion = current[j] + 1;
+            let deletion = previous[j + 1] + 1;
+            current.push(cmp::min(substitution, cmp::min(insertion, deletion)));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{App, Arg};
+
+    fn matches(args: &[&str]) -> ArgMatches<'static> {
+        App::new("test")
+            .arg(Arg::with_name("port").long("port").takes_value(true))
+            .arg(
+                Arg::with_name("threshold")
+                    .long("threshold")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1),
+            )
+            .get_matches_from(args)
+    }
+
+    #[test]
+    fn edit_distance_counts_single_character_edits() {
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("same", "same"), 0);
+    }
+
+    #[test]
+    fn did_you_mean_suggests_close_names_only() {
+        assert_eq!(
+            did_you_mean("THEGRAPH_SENTRY_ULR", ENV_VARS),
+            Some("THEGRAPH_SENTRY_URL")
+        );
+        assert_eq!(
+            did_you_mean("THEGRAPH_AUTH_SECRETS", ENV_VARS),
+            Some("THEGRAPH_AUTH_SECRET")
+        );
+        assert_eq!(did_you_mean("THEGRAPH_LOG_LEVEL", ENV_VARS), None);
+    }
+
+    #[test]
+    fn check_collects_every_problem() {
+        let matches = matches(&[
+            "test",
+            "--port",
+            "80a",
+            "--threshold",
+            "a:5m",
+            "--threshold",
+            "b",
+        ]);
+        let mut config = ConfigCheck::new(&matches);
+        config.environment(vec![
+            "HOME".to_owned(),
+            "THEGRAPH_AUTH_SECRET".to_owned(),
+            "THEGRAPH_AUTH_SECERT".to_owned(),
+        ]);
+        assert_eq!(config.parse::<u16>("port", "a port number"), None);
+        let thresholds: Vec<_> = matches
+            .values_of("threshold")
+            .unwrap()
+            .filter_map(|value| {
+                config.split_value("threshold", value, ':', "SUBGRAPH_ID:THRESHOLD")
+            })
+            .collect();
+        assert_eq!(thresholds, vec![("a", "5m")]);
+
+        let problems: Vec<_> = config
+            .finish()
+            .unwrap_err()
+            .iter()
+            .map(|problem| problem.to_string())
+            .collect();
+        assert_eq!(
+            problems,
+            vec![
+                "THEGRAPH_AUTH_SECERT: unknown environment variable, \
+                 did you mean `THEGRAPH_AUTH_SECRET`?",
+                "--port: expected a port number, found `80a`",
+                "--threshold: expected SUBGRAPH_ID:THRESHOLD, found `b`",
+            ]
+        );
+    }
+
+    #[test]
+    fn check_without_problems_succeeds() {
+        let matches = matches(&["test", "--port", "8000"]);
+        let mut config = ConfigCheck::new(&matches);
+        assert_eq!(config.parse::<u16>("port", "a port number"), Some(8000));
+        assert_eq!(config.parse::<u16>("threshold", "a threshold"), None);
+        assert_eq!(config.finish(), Ok(()));
+    }
+}
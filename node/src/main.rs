@@ -7,47 +7,172 @@ extern crate sentry;
 extern crate graph;
 extern crate graph_core;
 extern crate graph_datasource_ethereum;
+extern crate graph_graphql;
 extern crate graph_mock;
 extern crate graph_runtime_wasm;
 extern crate graph_server_http;
 extern crate graph_server_json_rpc;
 extern crate graph_store_postgres;
+extern crate graphql_parser;
 extern crate ipfs_api;
 extern crate url;
 
-use clap::{App, Arg};
+mod config;
+
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use ipfs_api::IpfsClient;
 use reqwest::Client;
+use std::collections::HashMap;
 use std::env;
-use std::net::SocketAddr;
-use std::str::FromStr;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
 use url::Url;
 
+use config::ConfigCheck;
 use graph::components::forward;
 use graph::prelude::{JsonRpcServer as JsonRpcServerTrait, *};
+use graph::serde_json::{self, Value as JsonValue};
 use graph::util::log::logger;
 use graph_core::SubgraphProvider as IpfsSubgraphProvider;
 use graph_datasource_ethereum::Transport;
-use graph_runtime_wasm::RuntimeHostBuilder as WASMRuntimeHostBuilder;
-use graph_server_http::GraphQLServer as HyperGraphQLServer;
-use graph_server_json_rpc::{subgraph_add_request, JsonRpcServer};
-use graph_store_postgres::{Store as DieselStore, StoreConfig};
+use graph_graphql::prelude::{api_schema, validate_schema};
+use graph_runtime_wasm::{MemoryLimits, RuntimeHostBuilder as WASMRuntimeHostBuilder};
+use graph_server_http::{GraphQLServer as HyperGraphQLServer, GraphQLServerConfig, WarmUp};
+use graph_server_json_rpc::{
+    deployment_usage_request, entity_get_request, indexing_lag_request,
+    indexing_throughput_request, subgraph_add_request, subgraph_delete_request, JsonRpcServer,
+    MethodCall,
+};
+use graph_store_postgres::{Store as DieselStore, StoreConfig, StoreEventListener};
 
 fn main() {
-    // Run `async_main` inside the context of an executor.
-    tokio::run(future::lazy(|| async_main()))
-}
-
-fn async_main() -> impl Future<Item = (), Error = ()> + Send + 'static {
     env_logger::init();
     let logger = logger();
 
-    // Setup CLI using Clap, provide general info and capture postgres url
+    // Setup CLI using Clap, with a subcommand for every task of the node
     let matches = App::new("graph-node")
         .version("0.1.0")
         .author("Graph Protocol, Inc.")
         .about("Scalable queries for a decentralized future")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(run_command())
+        .subcommand(
+            SubCommand::with_name("deploy")
+                .about("Adds a subgraph to a running node through its admin API")
+                .arg(
+                    Arg::with_name("ipfs-hash")
+                        .required(true)
+                        .value_name("IPFS_HASH")
+                        .help("IPFS hash of the subgraph manifest"),
+                )
+                .arg(
+                    Arg::with_name("name")
+                        .takes_value(true)
+                        .default_value("cli")
+                        .long("name")
+                        .value_name("NAME")
+                        .help("name of the subgraph"),
+                )
+                .arg(
+                    Arg::with_name("allow-mapping-changes")
+                        .long("allow-mapping-changes")
+                        .help("Add the subgraph even if its mappings changed since it was indexed"),
+                )
+                .arg(admin_url_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("remove")
+                .about("Deletes all entities of a subgraph through the admin API of a node")
+                .arg(
+                    Arg::with_name("subgraph-id")
+                        .required(true)
+                        .value_name("SUBGRAPH_ID")
+                        .help("ID of the subgraph"),
+                )
+                .arg(admin_url_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("rewind")
+                .about("Reverts the entity changes that a subgraph made after a block")
+                .arg(
+                    Arg::with_name("subgraph-id")
+                        .required(true)
+                        .value_name("SUBGRAPH_ID")
+                        .help("ID of the subgraph"),
+                )
+                .arg(
+                    Arg::with_name("block-number")
+                        .required(true)
+                        .value_name("BLOCK_NUMBER")
+                        .help("number of the last block whose changes are kept"),
+                )
+                .arg(postgres_url_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Prints the latest version of all entities of a subgraph")
+                .arg(
+                    Arg::with_name("subgraph-id")
+                        .required(true)
+                        .value_name("SUBGRAPH_ID")
+                        .help("ID of the subgraph"),
+                )
+                .arg(postgres_url_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("check")
+                .about("Checks whether a subgraph schema can be indexed and queried")
+                .arg(
+                    Arg::with_name("schema")
+                        .required(true)
+                        .value_name("SCHEMA_FILE")
+                        .help("GraphQL schema of the subgraph"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("console")
+                .about("Runs queries and inspects the store of a running node interactively")
+                .arg(admin_url_arg())
+                .arg(
+                    Arg::with_name("http-url")
+                        .takes_value(true)
+                        .default_value("http://localhost:8000")
+                        .long("http-url")
+                        .value_name("URL")
+                        .help("URL of the GraphQL HTTP API of the node"),
+                ),
+        )
+        .get_matches();
+
+    match matches.subcommand() {
+        ("run", Some(matches)) => {
+            // Run the node inside the context of an executor
+            let matches = matches.clone();
+            tokio::run(future::lazy(move || run(logger, matches)))
+        }
+        ("deploy", Some(matches)) => print_output(deploy(matches)),
+        ("remove", Some(matches)) => print_output(remove(matches)),
+        ("rewind", Some(matches)) => print_output(rewind(&logger, matches)),
+        ("export", Some(matches)) => print_output(export(&logger, matches)),
+        ("check", Some(matches)) => print_output(check(matches)),
+        ("console", Some(matches)) => {
+            if let Err(e) = console(matches) {
+                print_output(Err(e))
+            }
+        }
+        _ => unreachable!("clap requires a subcommand"),
+    }
+}
+
+/// The `run` subcommand, which indexes subgraphs and serves queries.
+fn run_command<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("run")
+        .about("Indexes subgraphs and serves GraphQL queries for them")
         .arg(
             Arg::with_name("subgraph")
                 .takes_value(true)
@@ -56,17 +181,61 @@ fn async_main() -> impl Future<Item = (), Error = ()> + Send + 'static {
                 .help("name and IPFS hash of the subgraph manifest"),
         )
         .arg(
-            Arg::with_name("postgres-url")
+            Arg::with_name("allow-mapping-changes")
+                .long("allow-mapping-changes")
+                .help("Add the subgraph even if its mappings changed since it was indexed"),
+        )
+        .arg(
+            Arg::with_name("query-only")
+                .long("query-only")
+                .conflicts_with_all(&["ethereum-rpc", "ethereum-ws", "ethereum-ipc"])
+                .help(
+                    "Only serve queries for the subgraphs that other nodes index into the \
+                     same database, without connecting to Ethereum",
+                ),
+        )
+        .arg(
+            Arg::with_name("index-only")
+                .long("index-only")
+                .conflicts_with_all(&["query-only", "http-unix-socket"])
+                .help("Only index subgraphs, without serving GraphQL queries over HTTP"),
+        )
+        .arg(postgres_url_arg())
+        .arg(
+            Arg::with_name("store-intern-threshold")
                 .takes_value(true)
-                .required(true)
-                .long("postgres-url")
-                .value_name("URL")
-                .help("Location of the Postgres database used for storing entities"),
+                .long("store-intern-threshold")
+                .value_name("BYTES")
+                .help("Store string values of at least this size only once across entities"),
+        )
+        .arg(
+            Arg::with_name("postgres-query-setting")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .long("postgres-query-setting")
+                .value_name("NAME=VALUE")
+                .help(
+                    "Postgres setting, e.g. statement_timeout=5s, for the entity lookups of \
+                     GraphQL queries; may be repeated",
+                ),
+        )
+        .arg(
+            Arg::with_name("postgres-indexing-setting")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .long("postgres-indexing-setting")
+                .value_name("NAME=VALUE")
+                .help(
+                    "Postgres setting for reading and writing entities while indexing; \
+                     may be repeated",
+                ),
         )
         .arg(
             Arg::with_name("ethereum-rpc")
                 .takes_value(true)
-                .required_unless_one(&["ethereum-ws", "ethereum-ipc"])
+                .required_unless_one(&["ethereum-ws", "ethereum-ipc", "query-only"])
                 .conflicts_with_all(&["ethereum-ws", "ethereum-ipc"])
                 .long("ethereum-rpc")
                 .value_name("URL")
@@ -75,7 +244,7 @@ fn async_main() -> impl Future<Item = (), Error = ()> + Send + 'static {
         .arg(
             Arg::with_name("ethereum-ws")
                 .takes_value(true)
-                .required_unless_one(&["ethereum-rpc", "ethereum-ipc"])
+                .required_unless_one(&["ethereum-rpc", "ethereum-ipc", "query-only"])
                 .conflicts_with_all(&["ethereum-rpc", "ethereum-ipc"])
                 .long("ethereum-ws")
                 .value_name("URL")
@@ -84,7 +253,7 @@ fn async_main() -> impl Future<Item = (), Error = ()> + Send + 'static {
         .arg(
             Arg::with_name("ethereum-ipc")
                 .takes_value(true)
-                .required_unless_one(&["ethereum-rpc", "ethereum-ws"])
+                .required_unless_one(&["ethereum-rpc", "ethereum-ws", "query-only"])
                 .conflicts_with_all(&["ethereum-rpc", "ethereum-ws"])
                 .long("ethereum-ipc")
                 .value_name("FILE")
@@ -98,6 +267,205 @@ fn async_main() -> impl Future<Item = (), Error = ()> + Send + 'static {
                 .value_name("HOST:PORT")
                 .help("HTTP address of an IPFS node"),
         )
+        .arg(
+            Arg::with_name("http-host")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .default_value("0.0.0.0")
+                .long("http-host")
+                .value_name("IP")
+                .help("IPv4 or IPv6 address to serve GraphQL on; may be repeated"),
+        )
+        .arg(
+            Arg::with_name("http-port")
+                .default_value("8000")
+                .long("http-port")
+                .value_name("PORT")
+                .help("port for the GraphQL HTTP server"),
+        )
+        .arg(
+            Arg::with_name("http-unix-socket")
+                .takes_value(true)
+                .long("http-unix-socket")
+                .value_name("PATH")
+                .help("Unix domain socket to serve GraphQL on, in addition to TCP"),
+        )
+        .arg(
+            Arg::with_name("http-unix-socket-mode")
+                .takes_value(true)
+                .requires("http-unix-socket")
+                .long("http-unix-socket-mode")
+                .value_name("OCTAL")
+                .help("Permissions of the GraphQL unix domain socket, e.g. 660"),
+        )
+        .arg(
+            Arg::with_name("http-path-prefix")
+                .takes_value(true)
+                .long("http-path-prefix")
+                .value_name("PATH")
+                .help("Path prefix under which the GraphQL HTTP API is served, e.g. /api/v0"),
+        )
+        .arg(
+            Arg::with_name("graphiql-header")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .long("graphiql-header")
+                .value_name("NAME:VALUE")
+                .help("Header that GraphiQL sends with every query; may be repeated"),
+        )
+        .arg(
+            Arg::with_name("query-allow-list")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .long("query-allow-list")
+                .value_name("SUBGRAPH_ID:FILE")
+                .help(
+                    "Only allow the queries whose hashes are listed in FILE for a subgraph; \
+                     may be repeated",
+                ),
+        )
+        .arg(
+            Arg::with_name("stitched-schema")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .long("stitched-schema")
+                .value_name("NAME=PREFIX:SUBGRAPH_ID,...")
+                .help(
+                    "Serve the schemas of several subgraphs merged into one under NAME, \
+                     prefixing colliding types and fields with PREFIX; may be repeated",
+                ),
+        )
+        .arg(
+            Arg::with_name("require-api-key")
+                .long("require-api-key")
+                .help("Reject GraphQL queries that are not sent with an API key"),
+        )
+        .arg(
+            Arg::with_name("query-timeout")
+                .takes_value(true)
+                .long("query-timeout")
+                .value_name("SECONDS")
+                .help("Fail GraphQL queries that take longer than this to run"),
+        )
+        .arg(
+            Arg::with_name("regex-filter-timeout")
+                .takes_value(true)
+                .long("regex-filter-timeout")
+                .value_name("SECONDS")
+                .help("Allow regex filters in GraphQL queries, cancelled after this long"),
+        )
+        .arg(
+            Arg::with_name("max-query-complexity")
+                .takes_value(true)
+                .long("max-query-complexity")
+                .value_name("COMPLEXITY")
+                .help("Reject GraphQL queries whose estimated complexity exceeds this"),
+        )
+        .arg(
+            Arg::with_name("max-first")
+                .takes_value(true)
+                .long("max-first")
+                .value_name("ENTITIES")
+                .help("Reject GraphQL queries that ask for more entities than this with `first`"),
+        )
+        .arg(
+            Arg::with_name("query-cache-size")
+                .takes_value(true)
+                .long("query-cache-size")
+                .value_name("ENTRIES")
+                .help("Number of GraphQL query results to cache for the latest block of subgraphs"),
+        )
+        .arg(
+            Arg::with_name("idle-deployment-days")
+                .takes_value(true)
+                .long("idle-deployment-days")
+                .value_name("DAYS")
+                .help("Clean up deployments that have not been queried for this many days"),
+        )
+        .arg(
+            Arg::with_name("idle-deployment-action")
+                .takes_value(true)
+                .possible_values(&["pause", "remove"])
+                .default_value("pause")
+                .long("idle-deployment-action")
+                .value_name("ACTION")
+                .help("Whether to pause (compress) or remove idle deployments"),
+        )
+        .arg(
+            Arg::with_name("lag-threshold")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .long("lag-threshold")
+                .value_name("SUBGRAPH_ID:THRESHOLD")
+                .help(
+                    "Flag a subgraph as lagging once it is further behind the chain head, \
+                     e.g. 100blocks or 5m; may be repeated",
+                ),
+        )
+        .arg(
+            Arg::with_name("placement-rule")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .long("placement-rule")
+                .value_name("accept|refuse:name=PATTERN|network=NETWORK")
+                .help(
+                    "Accept or refuse deployments by subgraph name, e.g. internal/*, or by \
+                     network; the first matching rule decides and, if there are accept rules, \
+                     unmatched deployments are refused; may be repeated",
+                ),
+        )
+        .arg(
+            Arg::with_name("mapping-log-size")
+                .takes_value(true)
+                .default_value("1000")
+                .long("mapping-log-size")
+                .value_name("MESSAGES")
+                .help("Number of recent mapping log messages to keep per subgraph"),
+        )
+        .arg(
+            Arg::with_name("wasm-memory-limit")
+                .takes_value(true)
+                .long("wasm-memory-limit")
+                .value_name("MB")
+                .help("Largest linear memory that the WASM instance of a data source may grow to"),
+        )
+        .arg(
+            Arg::with_name("wasm-recycle-handlers")
+                .takes_value(true)
+                .long("wasm-recycle-handlers")
+                .value_name("HANDLERS")
+                .help("Replace the WASM instance of a data source after this many handlers"),
+        )
+        .arg(
+            Arg::with_name("wasm-recycle-memory")
+                .takes_value(true)
+                .long("wasm-recycle-memory")
+                .value_name("MB")
+                .help("Replace the WASM instance of a data source once its memory exceeds this"),
+        )
+        .arg(
+            Arg::with_name("parallel-handlers")
+                .takes_value(true)
+                .long("parallel-handlers")
+                .value_name("WORKERS")
+                .help("Run independent handlers of the same block on this many threads"),
+        )
+        .arg(
+            Arg::with_name("admin-host")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .default_value("0.0.0.0")
+                .long("admin-host")
+                .value_name("IP")
+                .help("IPv4 or IPv6 address to serve the admin JSON-RPC API on; may be repeated"),
+        )
         .arg(
             Arg::with_name("admin-port")
                 .default_value("8020")
@@ -105,27 +473,230 @@ fn async_main() -> impl Future<Item = (), Error = ()> + Send + 'static {
                 .value_name("PORT")
                 .help("port for the admin JSON-RPC server"),
         )
-        .get_matches();
+}
+
+/// Starts indexing subgraphs and serving queries; the returned future runs
+/// the servers.
+fn run(
+    logger: Logger,
+    matches: ArgMatches<'static>,
+) -> impl Future<Item = (), Error = ()> + Send + 'static {
+    // Read all settings before starting anything, so that every problem
+    // with them is reported at once
+    let mut config = ConfigCheck::new(&matches);
+    config.environment(env::vars_os().map(|(name, _)| name.to_string_lossy().into_owned()));
 
     // Safe to unwrap because a value is required by CLI
     let postgres_url = matches.value_of("postgres-url").unwrap().to_string();
+    let intern_threshold = config.parse("store-intern-threshold", "a number of bytes");
+    let query_settings = postgres_settings(&mut config, "postgres-query-setting");
+    let indexing_settings = postgres_settings(&mut config, "postgres-indexing-setting");
+
+    // Obtain the policy for cleaning up idle deployments, if any
+    let max_idle_days = config.parse("idle-deployment-days", "a number of days");
+    let idle_deployment_action = config.parse("idle-deployment-action", "`pause` or `remove`");
+    let idle_deployment_policy = match (max_idle_days, idle_deployment_action) {
+        (Some(max_idle_days), Some(action)) => Some(IdleDeploymentPolicy {
+            max_idle_days,
+            action,
+        }),
+        _ => None,
+    };
+
+    // Obtain the lag thresholds of subgraphs, if any
+    let mut lag_thresholds: HashMap<String, Vec<LagThreshold>> = HashMap::new();
+    if let Some(values) = matches.values_of("lag-threshold") {
+        for lag_threshold in values {
+            let split =
+                config.split_value("lag-threshold", lag_threshold, ':', "SUBGRAPH_ID:THRESHOLD");
+            if let Some((subgraph_id, threshold)) = split {
+                let threshold =
+                    config.parse_value("lag-threshold", threshold, "a threshold like 100blocks");
+                if let Some(threshold) = threshold {
+                    lag_thresholds
+                        .entry(subgraph_id.to_owned())
+                        .or_insert_with(Vec::new)
+                        .push(threshold);
+                }
+            }
+        }
+    }
+
+    // Decide which deployments this node indexes
+    let placement_rules: Vec<PlacementRule> = config.parse_all(
+        "placement-rule",
+        "a rule like accept:name=internal/* or refuse:network=ropsten",
+    );
 
     // Obtain subgraph related command-line arguments
     let subgraph = matches.value_of("subgraph");
 
+    // Nodes that only serve queries neither talk to Ethereum nor write to
+    // the store, and learn about new entities from the nodes that index them
+    let query_only = matches.is_present("query-only");
+
+    // Nodes that only index subgraphs only serve the admin API, so that they
+    // can't be exposed to the public by accident
+    let index_only = matches.is_present("index-only");
+
     // Obtain the Ethereum RPC/WS/IPC transport locations
     let ethereum_rpc = matches.value_of("ethereum-rpc");
     let ethereum_ipc = matches.value_of("ethereum-ipc");
     let ethereum_ws = matches.value_of("ethereum-ws");
+    if !query_only && ethereum_rpc.is_none() && ethereum_ipc.is_none() && ethereum_ws.is_none() {
+        config.problem(
+            "--ethereum-rpc",
+            "one of --ethereum-ipc, --ethereum-ws or --ethereum-rpc must be provided",
+        );
+    }
 
-    let ipfs_socket_addr = SocketAddr::from_str(matches.value_of("ipfs").unwrap())
-        .expect("could not parse IPFS address, expected format is host:port");
+    let ipfs_socket_addr: Option<SocketAddr> = config.parse("ipfs", "an address like host:port");
 
-    let json_rpc_port = matches
-        .value_of("admin-port")
-        .unwrap()
-        .parse()
-        .expect("invalid admin port");
+    let json_rpc_port = config.parse("admin-port", "a port number").unwrap_or_default();
+    let json_rpc_addrs = socket_addrs(&mut config, "admin-host", json_rpc_port);
+
+    let http_port = config.parse("http-port", "a port number").unwrap_or_default();
+    let http_addrs = socket_addrs(&mut config, "http-host", http_port);
+
+    // Obtain the unix socket to serve GraphQL on, if any
+    let http_unix_socket = matches.value_of("http-unix-socket").map(PathBuf::from);
+    if cfg!(not(unix)) && http_unix_socket.is_some() {
+        config.problem(
+            "--http-unix-socket",
+            "unix domain sockets are not supported on this platform",
+        );
+    }
+    let http_unix_socket_mode = matches
+        .value_of("http-unix-socket-mode")
+        .and_then(|mode| match u32::from_str_radix(mode, 8) {
+            Ok(mode) => Some(mode),
+            Err(_) => {
+                config.invalid("http-unix-socket-mode", mode, "octal digits");
+                None
+            }
+        });
+
+    // Obtain the GraphQL HTTP API configuration
+    let path_prefix = GraphQLServerConfig::normalize_path_prefix(
+        matches.value_of("http-path-prefix").unwrap_or(""),
+    ).unwrap_or_else(|e| {
+        config.problem("--http-path-prefix", e);
+        String::new()
+    });
+    let graphiql_headers = matches
+        .values_of("graphiql-header")
+        .map(|headers| {
+            headers
+                .filter_map(|header| {
+                    config
+                        .split_value("graphiql-header", header, ':', "NAME:VALUE")
+                        .map(|(name, value)| (name.trim().to_owned(), value.trim().to_owned()))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let query_allow_lists = matches
+        .values_of("query-allow-list")
+        .map(|allow_lists| {
+            allow_lists
+                .filter_map(|allow_list| {
+                    let (subgraph_id, path) = config.split_value(
+                        "query-allow-list",
+                        allow_list,
+                        ':',
+                        "SUBGRAPH_ID:FILE",
+                    )?;
+                    match fs::read_to_string(path) {
+                        Ok(contents) => Some((
+                            subgraph_id.to_owned(),
+                            GraphQLServerConfig::parse_query_allow_list(&contents),
+                        )),
+                        Err(e) => {
+                            config.problem(
+                                "--query-allow-list",
+                                format!("failed to read `{}`: {}", path, e),
+                            );
+                            None
+                        }
+                    }
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let stitched_schemas = matches
+        .values_of("stitched-schema")
+        .map(|stitched_schemas| {
+            stitched_schemas
+                .filter_map(|stitched_schema| {
+                    GraphQLServerConfig::parse_stitched_schema(stitched_schema)
+                        .map_err(|e| config.problem("--stitched-schema", e))
+                        .ok()
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let graphql_server_config = GraphQLServerConfig {
+        path_prefix,
+        graphiql_headers,
+        // Auth tokens for `@restricted` fields are signed with this secret
+        auth_secret: env::var("THEGRAPH_AUTH_SECRET").ok(),
+        require_api_key: matches.is_present("require-api-key"),
+        max_query_complexity: config.parse("max-query-complexity", "a number"),
+        query_cache_size: config.parse("query-cache-size", "a number").unwrap_or(0),
+        query_allow_lists,
+        stitched_schemas,
+        ..Default::default()
+    };
+
+    let regex_filter_timeout = config
+        .parse("regex-filter-timeout", "a number of seconds")
+        .map(Duration::from_secs);
+
+    // Safe to default because the log size has a default value
+    let mapping_log_size = config
+        .parse("mapping-log-size", "a number of messages")
+        .unwrap_or_default();
+
+    // Keep mappings that leak memory from growing the node without bounds
+    let recycle_after_handlers = config.parse("wasm-recycle-handlers", "a number of handlers");
+    let memory_limits = {
+        let mut megabytes = |name: &str| {
+            config
+                .parse::<usize>(name, "a number of megabytes")
+                .map(|megabytes| megabytes * 1024 * 1024)
+        };
+        MemoryLimits {
+            max_memory_bytes: megabytes("wasm-memory-limit"),
+            recycle_after_handlers,
+            recycle_after_bytes: megabytes("wasm-recycle-memory"),
+        }
+    };
+
+    let parallel_handlers = config.parse("parallel-handlers", "a number of threads");
+    let max_first = config.parse("max-first", "a number");
+    let query_timeout = config
+        .parse("query-timeout", "a number of seconds")
+        .map(Duration::from_secs);
+
+    // An empty Sentry URL disables error reporting
+    if let Ok(sentry_url) = env::var("THEGRAPH_SENTRY_URL") {
+        if !sentry_url.is_empty() {
+            if let Err(e) = Url::parse(&sentry_url) {
+                config.problem("THEGRAPH_SENTRY_URL", format!("expected a URL: {}", e));
+            }
+        }
+    }
+
+    if let Err(problems) = config.finish() {
+        eprintln!("Invalid configuration:");
+        for problem in problems {
+            eprintln!("  {}", problem);
+        }
+        std::process::exit(ErrorCategory::Config.exit_code());
+    }
+
+    // Safe to unwrap because the configuration was checked
+    let ipfs_socket_addr = ipfs_socket_addr.unwrap();
 
     debug!(logger, "Setting up Sentry");
 
@@ -152,55 +723,217 @@ fn async_main() -> impl Future<Item = (), Error = ()> + Send + 'static {
             ipfs_socket_addr.port(),
         ).expect("Failed to start IPFS client"),
     );
-    let mut subgraph_provider = IpfsSubgraphProvider::new(logger.clone(), resolver.clone());
-
-    let store = DieselStore::new(StoreConfig { url: postgres_url }, &logger);
+    let mut store = DieselStore::new(
+        StoreConfig {
+            url: postgres_url.clone(),
+            intern_threshold,
+            query_settings,
+            indexing_settings,
+        },
+        &logger,
+    );
+    if let Some(timeout) = regex_filter_timeout {
+        store.enable_regex_filters(timeout);
+    }
     let protected_store = Arc::new(Mutex::new(store));
-    let mut graphql_server = HyperGraphQLServer::new(&logger);
+
+    // Place subgraphs on this node by the rules, and pin them to the mappings
+    // they are indexed with
+    let mut subgraph_provider = IpfsSubgraphProvider::new(logger.clone(), resolver.clone())
+        .with_placement_rules(placement_rules);
+    if !query_only {
+        subgraph_provider = subgraph_provider.with_mapping_pins(protected_store.clone());
+    }
 
     // Create Ethereum adapter
-    let (transport_event_loop, transport) = ethereum_ipc
-        .map(Transport::new_ipc)
-        .or(ethereum_ws.map(Transport::new_ws))
-        .or(ethereum_rpc.map(Transport::new_rpc))
-        .expect("One of --ethereum-ipc, --ethereum-ws or --ethereum-rpc must be provided");
-
-    // If we drop the event loop the transport will stop working. For now it's
-    // fine to just leak it.
-    std::mem::forget(transport_event_loop);
-
-    let ethereum_watcher = graph_datasource_ethereum::EthereumAdapter::new(
-        graph_datasource_ethereum::EthereumAdapterConfig { transport },
-    );
-    let runtime_host_builder =
-        WASMRuntimeHostBuilder::new(&logger, Arc::new(Mutex::new(ethereum_watcher)), resolver);
-    let runtime_manager =
-        graph_core::RuntimeManager::new(&logger, protected_store.clone(), runtime_host_builder);
+    let ethereum_watcher = if query_only {
+        None
+    } else {
+        let (transport_event_loop, transport) = ethereum_ipc
+            .map(Transport::new_ipc)
+            .or(ethereum_ws.map(Transport::new_ws))
+            .or(ethereum_rpc.map(Transport::new_rpc))
+            .expect("One of --ethereum-ipc, --ethereum-ws or --ethereum-rpc must be provided");
 
-    // Forward subgraph events from the subgraph provider to the runtime manager
-    tokio::spawn(forward(&mut subgraph_provider, &runtime_manager).unwrap());
+        // If we drop the event loop the transport will stop working. For now
+        // it's fine to just leak it.
+        std::mem::forget(transport_event_loop);
+
+        Some(Arc::new(Mutex::new(
+            graph_datasource_ethereum::EthereumAdapter::new(
+                graph_datasource_ethereum::EthereumAdapterConfig { transport },
+            ),
+        )))
+    };
+
+    // Keep track of how far subgraphs are behind the chain head
+    let lag_monitor = Arc::new(graph_core::IndexingLagMonitor::new(&logger, lag_thresholds));
+
+    // Keep the recent log messages of mappings for the admin API
+    let mapping_logs = Arc::new(graph_core::MappingLogBuffer::new(mapping_log_size));
+
+    let query_runner = graph_core::QueryRunner::new(&logger, protected_store.clone())
+        .with_indexing_status(lag_monitor.clone());
+    let query_runner = match ethereum_watcher {
+        Some(ref ethereum_watcher) => query_runner.with_ethereum_adapter(ethereum_watcher.clone()),
+        None => query_runner,
+    };
+    let query_runner = match max_first {
+        Some(max_first) => query_runner.with_max_first(max_first),
+        None => query_runner,
+    };
+    let query_runner = Arc::new(match query_timeout {
+        Some(timeout) => query_runner.with_timeout(timeout),
+        None => query_runner,
+    });
+    let mut graphql_server =
+        HyperGraphQLServer::new(&logger, graphql_server_config, query_runner)
+            .with_api_key_store(protected_store.clone())
+            .with_deployment_usage_store(protected_store.clone());
+
+    // Clients are asked to retry queries for the subgraph given on the
+    // command line until it is loaded, rather than being told it is unknown
+    let warm_up = WarmUp::new();
+    if subgraph.is_some() && !index_only {
+        graphql_server = graphql_server.with_warm_up(warm_up.clone());
+    }
 
-    // Forward schema events from the subgraph provider to the GraphQL server.
     let schema_event_logger = logger.clone();
-    tokio::spawn(
-        subgraph_provider
-            .take_event_stream()
-            .unwrap()
-            .forward(graphql_server.schema_event_sink().sink_map_err(move |e| {
-                error!(schema_event_logger, "Error forwarding schema event {}", e);
-            }))
-            .and_then(|_| Ok(())),
-    );
+    if query_only {
+        // Subgraphs added to a node that only serves queries are not
+        // indexed, only their schemas are loaded
+        let subgraph_events: Box<Stream<Item = SubgraphProviderEvent, Error = ()> + Send> =
+            subgraph_provider.take_event_stream().unwrap();
+        tokio::spawn(subgraph_events.for_each(|_| Ok(())));
+
+        // Forward schema events from the subgraph provider to the GraphQL
+        // server
+        tokio::spawn(
+            subgraph_provider
+                .take_event_stream()
+                .unwrap()
+                .forward(graphql_server.schema_event_sink().sink_map_err(move |e| {
+                    error!(schema_event_logger, "Error forwarding schema event {}", e);
+                }))
+                .and_then(|_| Ok(())),
+        );
+    } else {
+        // Safe to unwrap because every node that indexes has an Ethereum
+        // adapter
+        let ethereum_watcher = ethereum_watcher.unwrap();
+
+        // Pause or remove deployments that are no longer being queried
+        if let Some(policy) = idle_deployment_policy {
+            graph_core::IdleDeploymentCleanup::start(&logger, protected_store.clone(), policy);
+        }
+
+        // Prune entities of types with a retention period once they are too old
+        let entity_retention = graph_core::EntityRetention::new(&logger);
+        entity_retention.start(protected_store.clone());
+
+        // Maintain the search documents of entities with full-text searches
+        let fulltext_indexing =
+            graph_core::FulltextIndexing::new(&logger, protected_store.clone());
+
+        // Keep samples of how much subgraphs index for the admin API
+        let throughput = Arc::new(graph_core::IndexingThroughput::new(&logger));
+        throughput.start(protected_store.clone());
+
+        let mut runtime_host_builder =
+            WASMRuntimeHostBuilder::new(&logger, ethereum_watcher.clone(), resolver)
+                .with_mapping_logs(mapping_logs.clone())
+                .with_throughput(throughput)
+                .with_memory_limits(memory_limits);
+        if let Some(workers) = parallel_handlers {
+            runtime_host_builder = runtime_host_builder.with_parallel_handlers(workers);
+        }
+
+        lag_monitor.start(ethereum_watcher);
+        let runtime_manager =
+            graph_core::RuntimeManager::new(&logger, protected_store.clone(), runtime_host_builder);
+
+        // Forward subgraph events from the subgraph provider to the runtime
+        // manager
+        tokio::spawn(forward(&mut subgraph_provider, &runtime_manager).unwrap());
+
+        // Forward schema events from the subgraph provider to the GraphQL
+        // server and to entity retention and full-text indexing.
+        tokio::spawn(
+            subgraph_provider
+                .take_event_stream()
+                .unwrap()
+                .forward(
+                    graphql_server
+                        .schema_event_sink()
+                        .fanout(
+                            entity_retention
+                                .schema_event_sink()
+                                .fanout(fulltext_indexing.schema_event_sink()),
+                        )
+                        .sink_map_err(move |e| {
+                            error!(schema_event_logger, "Error forwarding schema event {}", e);
+                        }),
+                )
+                .and_then(|_| Ok(())),
+        );
+    }
 
     // Start admin JSON-RPC server.
-    let json_rpc_server =
-        JsonRpcServer::serve(json_rpc_port, Arc::new(subgraph_provider), logger.clone())
-            .expect("Failed to start admin server");
+    let json_rpc_server = JsonRpcServer::serve(
+        &json_rpc_addrs,
+        Arc::new(subgraph_provider),
+        protected_store.clone(),
+        protected_store.clone(),
+        protected_store.clone(),
+        protected_store.clone(),
+        protected_store.clone(),
+        lag_monitor.clone(),
+        mapping_logs.clone(),
+        protected_store.clone(),
+        logger.clone(),
+    ).unwrap_or_else(|e| {
+        exit_with_error(&logger, "Failed to start admin server", GraphError::config(e))
+    });
 
     // Let the server run forever.
     std::mem::forget(json_rpc_server);
 
-    // Add the CLI subgraph with a REST request to the admin server.
+    // Forward store events to the GraphQL server and to the lag monitor;
+    // nodes that only serve queries are notified by the nodes that write
+    {
+        let store_stream = if query_only {
+            StoreEventListener::start(&logger, &postgres_url)
+        } else {
+            protected_store.lock().unwrap().event_stream().unwrap()
+        };
+        tokio::spawn(
+            store_stream
+                .forward(
+                    graphql_server
+                        .store_event_sink()
+                        .fanout(lag_monitor.store_event_sink())
+                        .sink_map_err(|e| {
+                            panic!("Failed to forward store event: {:?}", e);
+                        }),
+                )
+                .and_then(|_| Ok(())),
+        );
+    }
+
+    // Serve GraphQL server over HTTP on all configured addresses, or keep
+    // running without it
+    let http_server: Box<Future<Item = (), Error = ()> + Send> = if index_only {
+        info!(logger, "Not serving GraphQL queries, since the node only indexes subgraphs");
+        Box::new(future::empty())
+    } else {
+        graphql_server
+            .serve(&http_addrs)
+            .unwrap_or_else(|e| exit_with_error(&logger, "Failed to start GraphQL server", e))
+    };
+
+    // Add the CLI subgraph with a REST request to the admin server. This
+    // happens in the background, so that queries for other subgraphs are
+    // answered in the meantime.
     if let Some(subgraph) = subgraph {
         let (name, hash) = if subgraph.contains(':') {
             let mut split = subgraph.split(':');
@@ -212,50 +945,375 @@ fn async_main() -> impl Future<Item = (), Error = ()> + Send + 'static {
         let mut url = Url::parse("http://localhost").unwrap();
         url.set_port(Some(json_rpc_port))
             .expect("invalid admin port");
-        let raw_response = Client::new()
-            .post(url.clone())
-            .json(&subgraph_add_request(
-                name.to_owned(),
-                hash.to_owned(),
-                "1".to_owned(),
-            ))
-            .send()
-            .expect("failed to make `subgraph_add` request");
-
-        graph_server_json_rpc::parse_response(
-            raw_response
-                .error_for_status()
-                .and_then(|mut res| res.json())
-                .expect("`subgraph_add` request error"),
-        ).expect("`subgraph_add` server error");
+        let request = subgraph_add_request(
+            name.to_owned(),
+            hash.to_owned(),
+            matches.is_present("allow-mapping-changes"),
+            "1".to_owned(),
+        );
+        let logger = logger.clone();
+        thread::spawn(move || {
+            admin_request(&url, request)
+                .unwrap_or_else(|e| exit_with_error(&logger, "Failed to add subgraph", e));
+            warm_up.finish();
+        });
     }
 
-    // Forward store events to the GraphQL server
-    {
-        let store_stream = protected_store.lock().unwrap().event_stream().unwrap();
-        tokio::spawn(
-            store_stream
-                .forward(graphql_server.store_event_sink().sink_map_err(|e| {
-                    panic!("Failed to send store event to the GraphQL server: {:?}", e);
-                }))
-                .and_then(|_| Ok(())),
-        );
+    // Additionally serve GraphQL over a unix socket if requested
+    #[cfg(unix)]
+    let unix_server: Box<Future<Item = (), Error = ()> + Send> = match http_unix_socket {
+        Some(path) => graphql_server
+            .serve_unix(&path, http_unix_socket_mode)
+            .unwrap_or_else(|e| {
+                exit_with_error(&logger, "Failed to start GraphQL unix socket server", e)
+            }),
+        None => Box::new(future::ok(())),
+    };
+    #[cfg(not(unix))]
+    let unix_server: Box<Future<Item = (), Error = ()> + Send> = {
+        // A unix socket is reported as a configuration problem on startup
+        let _ = (http_unix_socket, http_unix_socket_mode);
+        Box::new(future::ok(()))
+    };
+
+    http_server.join(unix_server).map(|_| ())
+}
+
+/// The Postgres URL argument of the subcommands that access the store.
+fn postgres_url_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("postgres-url")
+        .takes_value(true)
+        .required(true)
+        .long("postgres-url")
+        .value_name("URL")
+        .help("Location of the Postgres database used for storing entities")
+}
+
+/// The admin API argument of the subcommands that talk to a running node.
+fn admin_url_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("admin-url")
+        .takes_value(true)
+        .default_value("http://localhost:8020")
+        .long("admin-url")
+        .value_name("URL")
+        .help("URL of the admin JSON-RPC API of the node")
+}
+
+/// Adds a subgraph to a running node.
+fn deploy(matches: &ArgMatches) -> Result<JsonValue, GraphError> {
+    // Safe to unwrap because the values are required or have defaults
+    let name = matches.value_of("name").unwrap();
+    let ipfs_hash = matches.value_of("ipfs-hash").unwrap();
+
+    admin_request(
+        &admin_url(matches)?,
+        subgraph_add_request(
+            name.to_owned(),
+            ipfs_hash.to_owned(),
+            matches.is_present("allow-mapping-changes"),
+            "1".to_owned(),
+        ),
+    )?;
+    Ok(json_object(vec![
+        ("name", JsonValue::from(name)),
+        ("ipfs_hash", JsonValue::from(ipfs_hash)),
+    ]))
+}
+
+/// Deletes the entities of a subgraph through the admin API of a node.
+fn remove(matches: &ArgMatches) -> Result<JsonValue, GraphError> {
+    // Safe to unwrap because a value is required by CLI
+    let subgraph_id = matches.value_of("subgraph-id").unwrap();
+
+    let deleted = admin_request(
+        &admin_url(matches)?,
+        subgraph_delete_request(subgraph_id.to_owned(), "1".to_owned()),
+    )?;
+    Ok(json_object(vec![
+        ("subgraph_id", JsonValue::from(subgraph_id)),
+        ("entities_deleted", deleted),
+    ]))
+}
+
+/// Reverts the changes of a subgraph after a block in the store.
+fn rewind(logger: &Logger, matches: &ArgMatches) -> Result<JsonValue, GraphError> {
+    // Safe to unwrap because the values are required by CLI
+    let subgraph_id = matches.value_of("subgraph-id").unwrap();
+    let block_number = matches
+        .value_of("block-number")
+        .unwrap()
+        .parse::<u64>()
+        .map_err(|_| {
+            GraphError::from_message(ErrorCategory::Config, "Block number must be a number")
+        })?;
+
+    let blocks_reverted = open_store(logger, matches).rewind_subgraph(subgraph_id, block_number)?;
+    Ok(json_object(vec![
+        ("subgraph_id", JsonValue::from(subgraph_id)),
+        ("block_number", JsonValue::from(block_number)),
+        ("blocks_reverted", JsonValue::from(blocks_reverted)),
+    ]))
+}
+
+/// Reads all entities of a subgraph from the store.
+fn export(logger: &Logger, matches: &ArgMatches) -> Result<JsonValue, GraphError> {
+    // Safe to unwrap because a value is required by CLI
+    let subgraph_id = matches.value_of("subgraph-id").unwrap();
+
+    let entities = open_store(logger, matches).export_entities(subgraph_id)?;
+    Ok(json_object(vec![
+        ("subgraph_id", JsonValue::from(subgraph_id)),
+        ("entities", serde_json::to_value(entities).map_err(GraphError::internal)?),
+    ]))
+}
+
+/// Validates a subgraph schema and derives its API schema from it.
+fn check(matches: &ArgMatches) -> Result<JsonValue, GraphError> {
+    // Safe to unwrap because a value is required by CLI
+    let path = matches.value_of("schema").unwrap();
+
+    let contents = fs::read_to_string(path).map_err(GraphError::config)?;
+    let errors: Vec<String> = match graphql_parser::parse_schema(&contents) {
+        Ok(document) => match validate_schema(&document) {
+            Ok(()) => api_schema(&document)
+                .err()
+                .into_iter()
+                .map(|e| e.to_string())
+                .collect(),
+            Err(errors) => errors.iter().map(|e| e.to_string()).collect(),
+        },
+        Err(e) => vec![e.to_string()],
+    };
+
+    let valid = errors.is_empty();
+    let output = json_object(vec![
+        ("schema", JsonValue::from(path)),
+        ("valid", JsonValue::from(valid)),
+        ("errors", JsonValue::from(errors)),
+    ]);
+    if !valid {
+        // Let scripts tell invalid schemas apart by the exit code
+        print_json(&output);
+        std::process::exit(ErrorCategory::Config.exit_code())
     }
+    Ok(output)
+}
+
+/// Connects to the store of the subgraph subcommands.
+fn open_store(logger: &Logger, matches: &ArgMatches) -> DieselStore {
+    DieselStore::new(
+        StoreConfig {
+            // Safe to unwrap because a value is required by CLI
+            url: matches.value_of("postgres-url").unwrap().to_owned(),
+            intern_threshold: None,
+            query_settings: vec![],
+            indexing_settings: vec![],
+        },
+        logger,
+    )
+}
 
-    // Forward incoming queries from the GraphQL server to the query runner
-    let mut query_runner = graph_core::QueryRunner::new(&logger, protected_store.clone());
-    let query_stream = graphql_server.query_stream().unwrap();
-    tokio::spawn(
-        query_stream
-            .forward(query_runner.query_sink().sink_map_err(|e| {
-                panic!("Failed to send query to query runner: {:?}", e);
-            }))
-            .and_then(|_| Ok(())),
+/// Reads commands from stdin and prints their results as JSON, until `exit`
+/// or the end of the input. Failed commands print an error and the console
+/// carries on.
+fn console(matches: &ArgMatches) -> Result<(), GraphError> {
+    let admin_url = admin_url(matches)?;
+    // Safe to unwrap because the URL has a default value
+    let http_url = Url::parse(matches.value_of("http-url").unwrap()).map_err(GraphError::config)?;
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        // The prompt goes to stderr so that stdout only holds results
+        eprint!("graph> ");
+        io::stderr().flush().map_err(GraphError::config)?;
+
+        let line = match lines.next() {
+            Some(line) => line.map_err(GraphError::config)?,
+            None => return Ok(()),
+        };
+        let mut words = line.trim().splitn(3, char::is_whitespace);
+        let result = match (words.next().unwrap_or(""), words.next(), words.next()) {
+            ("", _, _) => continue,
+            ("exit", None, None) | ("quit", None, None) => return Ok(()),
+            ("help", None, None) => {
+                println!("{}", CONSOLE_HELP);
+                continue;
+            }
+            ("deployments", None, None) => {
+                admin_request(&admin_url, deployment_usage_request("1".to_owned()))
+            }
+            ("blocks", subgraph_id, None) => {
+                admin_request(&admin_url, indexing_lag_request("1".to_owned())).map(|lags| {
+                    JsonValue::Array(
+                        lags.as_array()
+                            .into_iter()
+                            .flat_map(|lags| lags.iter())
+                            .filter(|lag| subgraph_id.map_or(true, |id| lag["subgraph_id"] == id))
+                            .cloned()
+                            .collect(),
+                    )
+                })
+            }
+            ("entity", Some(subgraph_id), Some(rest)) => {
+                let mut words = rest.split_whitespace();
+                match (words.next(), words.next(), words.next()) {
+                    (Some(entity_type), Some(id), None) => admin_request(
+                        &admin_url,
+                        entity_get_request(
+                            subgraph_id.to_owned(),
+                            entity_type.to_owned(),
+                            id.to_owned(),
+                            "1".to_owned(),
+                        ),
+                    ),
+                    _ => Err(console_usage_error("entity SUBGRAPH_ID TYPE ID")),
+                }
+            }
+            ("entity", _, _) => Err(console_usage_error("entity SUBGRAPH_ID TYPE ID")),
+            ("throughput", Some(subgraph_id), None) => admin_request(
+                &admin_url,
+                indexing_throughput_request(subgraph_id.to_owned(), None, "1".to_owned()),
+            ),
+            ("throughput", _, _) => Err(console_usage_error("throughput SUBGRAPH_ID")),
+            ("query", Some(subgraph_id), Some(query)) => {
+                graphql_request(&http_url, subgraph_id, query)
+            }
+            ("query", _, _) => Err(console_usage_error("query SUBGRAPH_ID GRAPHQL")),
+            (command, _, _) => Err(GraphError::from_message(
+                ErrorCategory::Config,
+                format!("Unknown command `{}`, `help` lists the commands", command),
+            )),
+        };
+
+        match result {
+            Ok(output) => print_json(&output),
+            Err(e) => print_json(&error_json(&e)),
+        }
+    }
+}
+
+/// The commands that the console understands.
+const CONSOLE_HELP: &str = "\
+deployments                  List the deployments of the node and when they were last queried
+blocks [SUBGRAPH_ID]         Show the latest indexed block and the chain head of subgraphs
+entity SUBGRAPH_ID TYPE ID   Show the latest version of an entity
+throughput SUBGRAPH_ID       Show how much a subgraph indexed per hour over the last day
+query SUBGRAPH_ID GRAPHQL    Run a GraphQL query, given on one line, against a subgraph
+help                         Show this list
+exit                         Leave the console";
+
+fn console_usage_error(usage: &str) -> GraphError {
+    GraphError::from_message(ErrorCategory::Config, format!("Usage: {}", usage))
+}
+
+/// Runs a GraphQL query against a subgraph through the HTTP API of a node
+/// and returns the response, including any errors of the query.
+fn graphql_request(
+    http_url: &Url,
+    subgraph_id: &str,
+    query: &str,
+) -> Result<JsonValue, GraphError> {
+    let url = format!(
+        "{}/subgraphs/id/{}/graphql",
+        http_url.as_str().trim_right_matches('/'),
+        subgraph_id
     );
+    Client::new()
+        .post(&url)
+        .json(&json_object(vec![("query", JsonValue::from(query))]))
+        .send()
+        .and_then(|response| response.error_for_status())
+        .and_then(|mut response| response.json())
+        .map_err(GraphError::config)
+}
+
+fn admin_url(matches: &ArgMatches) -> Result<Url, GraphError> {
+    // Safe to unwrap because the URL has a default value
+    Url::parse(matches.value_of("admin-url").unwrap()).map_err(GraphError::config)
+}
+
+/// Sends a JSON-RPC request to the admin API of a node and returns its
+/// result.
+fn admin_request(url: &Url, request: MethodCall) -> Result<JsonValue, GraphError> {
+    let response = Client::new()
+        .post(url.clone())
+        .json(&request)
+        .send()
+        .and_then(|response| response.error_for_status())
+        .and_then(|mut response| response.json())
+        .map_err(GraphError::config)?;
+
+    graph_server_json_rpc::parse_result(response).map_err(|e| {
+        // Admin errors carry the API code of their category as data
+        let category = e
+            .data
+            .as_ref()
+            .and_then(|code| code.as_str())
+            .and_then(ErrorCategory::from_api_code)
+            .unwrap_or(ErrorCategory::Internal);
+        GraphError::from_message(category, e.message)
+    })
+}
+
+/// Prints the result of a subcommand as JSON. Errors are printed as well,
+/// and exit with the exit code of their category.
+fn print_output(result: Result<JsonValue, GraphError>) {
+    match result {
+        Ok(output) => print_json(&output),
+        Err(e) => {
+            print_json(&error_json(&e));
+            std::process::exit(e.exit_code())
+        }
+    }
+}
+
+fn error_json(e: &GraphError) -> JsonValue {
+    let error = json_object(vec![
+        ("message", JsonValue::from(e.to_string())),
+        ("code", JsonValue::from(e.api_code())),
+    ]);
+    json_object(vec![("error", error)])
+}
+
+fn json_object(fields: Vec<(&str, JsonValue)>) -> JsonValue {
+    JsonValue::Object(
+        fields
+            .into_iter()
+            .map(|(name, value)| (name.to_owned(), value))
+            .collect(),
+    )
+}
+
+fn print_json(value: &JsonValue) {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(value).expect("Failed to serialize output")
+    );
+}
+
+/// Combines the IP addresses given for the flag `hosts` with a port.
+fn socket_addrs(config: &mut ConfigCheck, hosts: &str, port: u16) -> Vec<SocketAddr> {
+    config
+        .parse_all::<IpAddr>(hosts, "an IPv4 or IPv6 address")
+        .into_iter()
+        .map(|ip| SocketAddr::new(ip, port))
+        .collect()
+}
+
+/// Parses the Postgres settings given for the flag `name` as `NAME=VALUE`.
+fn postgres_settings(config: &mut ConfigCheck, name: &str) -> Vec<(String, String)> {
+    let mut settings = vec![];
+    for setting in config.values_of(name) {
+        if let Some((key, value)) = config.split_value(name, setting, '=', "NAME=VALUE") {
+            settings.push((key.to_owned(), value.to_owned()));
+        }
+    }
+    settings
+}
 
-    // Serve GraphQL server over HTTP. We will listen on port 8000.
-    let http_server = graphql_server
-        .serve(8000)
-        .expect("Failed to start GraphQL server");
-    http_server
+/// Logs a fatal error and exits with the exit code of its category.
+fn exit_with_error(logger: &Logger, message: &str, error: GraphError) -> ! {
+    crit!(logger, "{}", message; "error" => error.to_string());
+    std::process::exit(error.exit_code())
 }
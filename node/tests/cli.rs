@@ -1,11 +1,115 @@
 extern crate assert_cli;
 
+use std::env;
+use std::fs;
+
 #[test]
 fn node_fails_to_start_without_postgres_url() {
     assert_cli::Assert::main_binary()
+        .with_args(&["run"])
         .fails()
         .and()
         .stderr()
         .contains("error: The following required arguments were not provided:")
         .unwrap();
 }
+
+#[test]
+fn check_reports_schema_errors_as_json() {
+    let path = env::temp_dir().join("graph-node-check-schema.graphql");
+    // Entity types must have an `id: ID!` field
+    fs::write(&path, "type User { name: String }").unwrap();
+
+    assert_cli::Assert::main_binary()
+        .with_args(&["check", path.to_str().unwrap()])
+        .fails_with(2)
+        .and()
+        .stdout()
+        .contains("\"valid\": false")
+        .unwrap();
+}
+
+#[test]
+fn console_reports_unknown_commands_and_carries_on() {
+    assert_cli::Assert::main_binary()
+        .with_args(&["console"])
+        .stdin("peek\nhelp\nexit\n")
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("Unknown command `peek`")
+        .and()
+        .stdout()
+        .contains("entity SUBGRAPH_ID TYPE ID")
+        .unwrap();
+}
+
+#[test]
+fn run_reports_every_configuration_problem() {
+    assert_cli::Assert::main_binary()
+        .with_args(&[
+            "run",
+            "--postgres-url",
+            "postgresql://localhost/graph",
+            "--ethereum-rpc",
+            "http://localhost:8545",
+            "--ipfs",
+            "localhost",
+            "--admin-port",
+            "80a",
+        ])
+        .with_env(assert_cli::Environment::inherit().insert("THEGRAPH_SENTRY_ULR", ""))
+        .fails_with(2)
+        .and()
+        .stderr()
+        .contains("--ipfs: expected an address like host:port, found `localhost`")
+        .and()
+        .stderr()
+        .contains("--admin-port: expected a port number, found `80a`")
+        .and()
+        .stderr()
+        .contains("did you mean `THEGRAPH_SENTRY_URL`?")
+        .unwrap();
+}
+
+#[test]
+fn query_only_nodes_do_not_connect_to_ethereum() {
+    assert_cli::Assert::main_binary()
+        .with_args(&[
+            "run",
+            "--query-only",
+            "--postgres-url",
+            "postgresql://localhost/graph",
+            "--ethereum-rpc",
+            "http://localhost:8545",
+            "--ipfs",
+            "localhost:5001",
+        ])
+        .fails()
+        .and()
+        .stderr()
+        .contains("cannot be used with")
+        .unwrap();
+}
+
+#[test]
+fn index_only_nodes_do_not_serve_queries() {
+    assert_cli::Assert::main_binary()
+        .with_args(&[
+            "run",
+            "--index-only",
+            "--http-unix-socket",
+            "/tmp/graph-node.sock",
+            "--postgres-url",
+            "postgresql://localhost/graph",
+            "--ethereum-rpc",
+            "http://localhost:8545",
+            "--ipfs",
+            "localhost:5001",
+        ])
+        .fails()
+        .and()
+        .stderr()
+        .contains("cannot be used with")
+        .unwrap();
+}
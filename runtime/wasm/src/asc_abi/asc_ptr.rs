@@ -20,6 +20,13 @@ impl<T> Default for AscPtr<T> {
     }
 }
 
+impl<C> AscPtr<C> {
+    /// A null pointer, for values that are absent.
+    pub(crate) fn null() -> Self {
+        AscPtr(0, PhantomData)
+    }
+}
+
 impl<C: AscType> AscPtr<C> {
     /// Read from `self` into the Rust struct `C`.
     pub(super) fn read_ptr<H: AscHeap>(self, heap: &H) -> C {
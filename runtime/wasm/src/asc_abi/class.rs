@@ -341,6 +341,9 @@ pub(crate) struct AscEthereumEvent {
     pub event_signature: AscPtr<AscH256>,
     pub block_hash: AscPtr<AscH256>,
     pub params: AscPtr<AscLogParamArray>,
+    pub log_index: AscPtr<AscU256>,
+    pub transaction_log_index: AscPtr<AscU256>,
+    pub removed: bool,
 }
 
 impl AscType for AscEthereumEvent {}
@@ -376,6 +379,27 @@ pub(crate) struct AscUnresolvedContractCall {
 
 impl AscType for AscUnresolvedContractCall {}
 
+/// The result of a contract call that may revert. `value` is null if the
+/// call reverted, and `reason` is null unless it did.
+#[repr(C)]
+pub(crate) struct AscEthereumCallResult {
+    pub value: AscPtr<Array<AscPtr<AscEnum<EthereumValueKind>>>>,
+    pub reason: AscPtr<AscString>,
+    pub reverted: bool,
+}
+
+impl AscType for AscEthereumCallResult {}
+
+#[repr(C)]
+pub(crate) struct AscEthereumTransactionFees {
+    pub gas_price: AscPtr<AscU256>,
+    pub gas_used: AscPtr<AscU256>,
+    /// Null for blocks before the London fork.
+    pub base_fee_per_gas: AscPtr<AscU256>,
+}
+
+impl AscType for AscEthereumTransactionFees {}
+
 #[repr(u32)]
 #[derive(Copy, Clone)]
 pub(crate) enum JsonValueKind {
@@ -1,5 +1,6 @@
 use ethereum_types::Address;
-use futures::sync::mpsc::{channel, Receiver};
+use futures::sync::mpsc::{channel, Receiver, Sender};
+use std::collections::HashMap;
 use std::str::FromStr;
 use std::sync::Mutex;
 use std::thread;
@@ -13,18 +14,31 @@ use graph::prelude::{
 };
 use graph::util;
 
-use module::{WasmiModule, WasmiModuleConfig};
+use log_limiter::MappingLogLimiter;
+use module::{MemoryLimits, WasmiModule, WasmiModuleConfig};
+use parallel::{BlockBatches, HandlerEvent, HandlerPool};
 
 #[derive(Clone)]
 pub struct RuntimeHostConfig {
     subgraph_manifest: SubgraphManifest,
     data_source: DataSource,
+    log_limiter: Arc<Mutex<MappingLogLimiter>>,
+    mapping_logs: Option<Arc<MappingLogCapture + Send + Sync>>,
+    throughput: Option<Arc<ThroughputCapture + Send + Sync>>,
+    memory_limits: MemoryLimits,
+    parallel_handlers: Option<usize>,
 }
 
 pub struct RuntimeHostBuilder<T, L> {
     logger: Logger,
     ethereum_adapter: Arc<Mutex<T>>,
     link_resolver: Arc<L>,
+    /// Mapping log limits by subgraph, shared by all its data sources.
+    log_limiters: HashMap<String, Arc<Mutex<MappingLogLimiter>>>,
+    mapping_logs: Option<Arc<MappingLogCapture + Send + Sync>>,
+    throughput: Option<Arc<ThroughputCapture + Send + Sync>>,
+    memory_limits: MemoryLimits,
+    parallel_handlers: Option<usize>,
 }
 
 impl<T, L> RuntimeHostBuilder<T, L>
@@ -37,8 +51,41 @@ where
             logger: logger.new(o!("component" => "RuntimeHostBuilder")),
             ethereum_adapter,
             link_resolver,
+            log_limiters: HashMap::new(),
+            mapping_logs: None,
+            throughput: None,
+            memory_limits: MemoryLimits::default(),
+            parallel_handlers: None,
         }
     }
+
+    /// Keeps the messages that mappings log in `mapping_logs`, in addition
+    /// to writing them to the node logs.
+    pub fn with_mapping_logs(mut self, mapping_logs: Arc<MappingLogCapture + Send + Sync>) -> Self {
+        self.mapping_logs = Some(mapping_logs);
+        self
+    }
+
+    /// Counts the triggers that handlers are called for and the entities
+    /// they write in `throughput`.
+    pub fn with_throughput(mut self, throughput: Arc<ThroughputCapture + Send + Sync>) -> Self {
+        self.throughput = Some(throughput);
+        self
+    }
+
+    /// Limits the linear memory of the WASM instances that run the mappings,
+    /// and when they are replaced with fresh ones.
+    pub fn with_memory_limits(mut self, memory_limits: MemoryLimits) -> Self {
+        self.memory_limits = memory_limits;
+        self
+    }
+
+    /// Runs the handlers of the same block on `workers` threads per data
+    /// source, concurrently where they touch different entity types.
+    pub fn with_parallel_handlers(mut self, workers: usize) -> Self {
+        self.parallel_handlers = Some(workers);
+        self
+    }
 }
 
 impl<T, L> RuntimeHostBuilderTrait for RuntimeHostBuilder<T, L>
@@ -53,6 +100,11 @@ where
         subgraph_manifest: SubgraphManifest,
         data_source: DataSource,
     ) -> Self::Host {
+        let log_limiter = self
+            .log_limiters
+            .entry(subgraph_manifest.id.clone())
+            .or_insert_with(|| Arc::new(Mutex::new(MappingLogLimiter::new())))
+            .clone();
         RuntimeHost::new(
             &self.logger,
             self.ethereum_adapter.clone(),
@@ -60,6 +112,11 @@ where
             RuntimeHostConfig {
                 subgraph_manifest,
                 data_source,
+                log_limiter,
+                mapping_logs: self.mapping_logs.clone(),
+                throughput: self.throughput.clone(),
+                memory_limits: self.memory_limits,
+                parallel_handlers: self.parallel_handlers,
             },
         )
     }
@@ -86,30 +143,68 @@ impl RuntimeHost {
         // Create channel for sending runtime host events
         let (event_sender, event_receiver) = channel(100);
 
+        // Reorgs are reported through the same channel as the entity changes
+        // of handlers, so that they reach the store in order
+        let reorg_sink = event_sender.clone();
+        let subgraph_id = config.subgraph_manifest.id.clone();
+
         let wasmi_config = WasmiModuleConfig {
             subgraph: config.subgraph_manifest.clone(),
             data_source: config.data_source.clone(),
             event_sink: event_sender,
             ethereum_adapter: ethereum_adapter.clone(),
             link_resolver: link_resolver.clone(),
+            log_limiter: config.log_limiter.clone(),
+            mapping_logs: config.mapping_logs.clone(),
+            memory_limits: config.memory_limits,
         };
 
         info!(logger, "Loading WASM runtime"; "data_source" => &config.data_source.name);
 
+        let parallel_handlers = config.parallel_handlers;
+        let throughput = config.throughput.clone();
+
         // wasmi modules are not `Send` therefore they cannot be scheduled by
         // the regular tokio executor, so we create a dedicated thread inside
         // which we may wait on futures.
         thread::spawn(move || {
             let data_source = wasmi_config.data_source.clone();
+            let events = Self::subscribe_to_events(
+                &logger,
+                subgraph_id,
+                data_source,
+                ethereum_adapter,
+                reorg_sink,
+                throughput,
+            );
 
-            // Load the mappings as a WASM module
-            let module = WasmiModule::new(&logger, wasmi_config);
+            match parallel_handlers {
+                // Process one block at a time, with the handlers spread over
+                // the workers of the pool
+                Some(workers) => {
+                    let pool = HandlerPool::new(&logger, wasmi_config, workers);
+                    for batch in BlockBatches::new(events).wait() {
+                        if let Ok(batch) = batch {
+                            pool.handle_block(batch);
+                        }
+                    }
+                }
+
+                // Process one event at a time, blocking the thread when
+                // waiting for the next event.
+                None => {
+                    // Load the mappings as a WASM module
+                    let mut module = WasmiModule::new(&logger, wasmi_config);
 
-            // Process one event at a time, blocking the thread when waiting for
-            // the next event.
-            Self::subscribe_to_events(&logger, data_source, module, ethereum_adapter)
-                .wait()
-                .for_each(drop);
+                    for event in events.wait() {
+                        if let Ok((event_handler, event)) = event {
+                            debug!(logger, "  Call event handler";
+                                   "name" => &event_handler.handler);
+                            module.handle_ethereum_event(event_handler.handler.as_str(), event);
+                        }
+                    }
+                }
+            }
         });
 
         RuntimeHost {
@@ -119,16 +214,18 @@ impl RuntimeHost {
     }
 
     /// Subscribe to all smart contract events of `data_source` contained in
-    /// `subgraph`.
-    fn subscribe_to_events<T, L>(
+    /// `subgraph`, along with the handlers to pass them to. Blocks that logs
+    /// are removed from by a reorg are sent to `reorg_sink` to be reverted.
+    fn subscribe_to_events<T>(
         logger: &Logger,
+        subgraph_id: String,
         data_source: DataSource,
-        mut module: WasmiModule<T, L>,
         ethereum_adapter: Arc<Mutex<T>>,
-    ) -> impl Stream<Item = (), Error = ()> + 'static
+        reorg_sink: Sender<RuntimeHostEvent>,
+        throughput: Option<Arc<ThroughputCapture + Send + Sync>>,
+    ) -> impl Stream<Item = HandlerEvent, Error = ()> + 'static
     where
         T: EthereumAdapter + 'static,
-        L: LinkResolver + 'static,
     {
         info!(logger, "Subscribe to events");
 
@@ -195,14 +292,46 @@ impl RuntimeHost {
         let event_logger = logger.clone();
         let error_logger = logger.clone();
 
+        // The block that was last reverted; a reorg removes all logs of a
+        // block at once, and the block only needs to be reverted once
+        let mut reverted_block = None;
+
         event_stream
-            .map(move |event| {
+            .and_then(move |event| {
                 info!(event_logger, "Ethereum event received");
 
+                // Logs that were removed by a reorg are not new events, and
+                // what the handlers wrote for their block is undone
                 if event.removed {
                     info!(event_logger, "Event removed";
-                              "block" => event.block_hash.to_string());
+                              "block" => event.block_hash.to_string(),
+                              "log_index" => event.log_index.to_string());
+                    if reverted_block == Some(event.block_hash) {
+                        return future::Either::A(future::ok(None));
+                    }
+                    reverted_block = Some(event.block_hash);
+
+                    let send_logger = event_logger.clone();
+                    let block_hash = event.block_hash;
+                    future::Either::B(
+                        reorg_sink
+                            .clone()
+                            .send(RuntimeHostEvent::BlockReverted(
+                                subgraph_id.clone(),
+                                block_hash,
+                            ))
+                            .map(|_| None)
+                            .or_else(move |e| {
+                                error!(send_logger, "Failed to revert block";
+                                       "block" => block_hash.to_string(),
+                                       "error" => e.to_string());
+                                Ok(None)
+                            }),
+                    )
                 } else {
+                    // The block may be added to the chain again after a reorg
+                    reverted_block = None;
+
                     let event_handler = data_source
                         .mapping
                         .event_handlers
@@ -214,12 +343,13 @@ impl RuntimeHost {
                         .expect("Received an Ethereum event not mentioned in the data set")
                         .to_owned();
 
-                    debug!(event_logger, "  Call event handler";
-                               "name" => &event_handler.handler);
-
-                    module.handle_ethereum_event(event_handler.handler.as_str(), event);
+                    if let Some(ref throughput) = throughput {
+                        throughput.record_trigger(&subgraph_id, event.block_number);
+                    }
+                    future::Either::A(future::ok(Some((event_handler, event))))
                 }
             })
+            .filter_map(|handler_event| handler_event)
             .map_err(move |e| error!(error_logger, "Event subscription failed: {}", e))
     }
 }
@@ -228,9 +358,21 @@ impl EventProducer<RuntimeHostEvent> for RuntimeHost {
     fn take_event_stream(
         &mut self,
     ) -> Option<Box<Stream<Item = RuntimeHostEvent, Error = ()> + Send>> {
-        self.output
-            .take()
-            .map(|s| Box::new(s) as Box<Stream<Item = RuntimeHostEvent, Error = ()> + Send>)
+        let subgraph_id = self.config.subgraph_manifest.id.clone();
+        let throughput = self.config.throughput.clone();
+        self.output.take().map(|s| {
+            Box::new(s.map(move |event| {
+                if let Some(ref throughput) = throughput {
+                    match event {
+                        RuntimeHostEvent::EntitySet(..) | RuntimeHostEvent::EntityRemoved(..) => {
+                            throughput.record_entity_write(&subgraph_id)
+                        }
+                        RuntimeHostEvent::BlockReverted(..) => (),
+                    }
+                }
+                event
+            })) as Box<Stream<Item = RuntimeHostEvent, Error = ()> + Send>
+        })
     }
 }
 
@@ -4,16 +4,20 @@ extern crate futures;
 extern crate graph;
 extern crate hex;
 extern crate nan_preserving_float;
+extern crate parity_wasm;
 extern crate uuid;
 extern crate wasmi;
 extern crate web3;
 
 mod asc_abi;
 mod host;
+mod log_limiter;
 mod module;
+mod parallel;
 mod to_from;
 
 pub use self::host::{RuntimeHost, RuntimeHostBuilder, RuntimeHostConfig};
+pub use self::module::MemoryLimits;
 
 #[derive(Clone, Debug)]
 pub(crate) struct UnresolvedContractCall {
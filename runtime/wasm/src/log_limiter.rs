@@ -0,0 +1,73 @@
+use std::time::{Duration, Instant};
+
+/// How many messages the mappings of a deployment may log per window.
+const MAX_MESSAGES_PER_WINDOW: u32 = 100;
+
+/// Length of the windows that messages are counted in.
+const WINDOW_SECS: u64 = 10;
+
+/// Limits how many messages the mappings of a deployment log, so that a
+/// mapping that logs in a tight loop cannot flood the node logs. Shared by
+/// the runtime hosts of all data sources of the deployment.
+pub(crate) struct MappingLogLimiter {
+    window_start: Option<Instant>,
+    logged: u32,
+    dropped: u32,
+}
+
+impl MappingLogLimiter {
+    pub fn new() -> Self {
+        MappingLogLimiter {
+            window_start: None,
+            logged: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Counts a message that is about to be logged at `now`. Returns whether
+    /// it may be logged, along with the number of messages dropped in the
+    /// previous window if that window just ended.
+    pub fn admit(&mut self, now: Instant) -> (bool, u32) {
+        let mut dropped_before = 0;
+        let window_ended = self.window_start.map_or(true, |start| {
+            now.duration_since(start) >= Duration::from_secs(WINDOW_SECS)
+        });
+        if window_ended {
+            dropped_before = self.dropped;
+            self.window_start = Some(now);
+            self.logged = 0;
+            self.dropped = 0;
+        }
+
+        if self.logged < MAX_MESSAGES_PER_WINDOW {
+            self.logged += 1;
+            (true, dropped_before)
+        } else {
+            self.dropped += 1;
+            (false, dropped_before)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MappingLogLimiter, MAX_MESSAGES_PER_WINDOW, WINDOW_SECS};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn drops_messages_beyond_the_limit_of_a_window() {
+        let mut limiter = MappingLogLimiter::new();
+        let start = Instant::now();
+
+        for _ in 0..MAX_MESSAGES_PER_WINDOW {
+            assert_eq!(limiter.admit(start), (true, 0));
+        }
+        assert_eq!(limiter.admit(start), (false, 0));
+        assert_eq!(limiter.admit(start + Duration::from_secs(1)), (false, 0));
+
+        // The next window starts over and reports what was dropped
+        let next = start + Duration::from_secs(WINDOW_SECS);
+        assert_eq!(limiter.admit(next), (true, 2));
+        assert_eq!(limiter.admit(next), (true, 0));
+    }
+}
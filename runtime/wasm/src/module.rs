@@ -1,11 +1,13 @@
+use ethabi::Token;
 use ethereum_types::{H160, H256, U256};
 use futures::sync::mpsc::Sender;
 use graph::serde_json;
 use nan_preserving_float::F64;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::str::FromStr;
 use std::sync::Mutex;
+use std::time::Instant;
 
 use wasmi::{
     Error, Externals, FuncInstance, FuncRef, HostError, ImportsBuilder, MemoryRef, Module,
@@ -18,6 +20,7 @@ use graph::components::ethereum::*;
 use graph::components::store::{EventSource, StoreKey};
 use graph::components::subgraph::RuntimeHostEvent;
 use graph::data::store::scalar;
+use graph::data::usage::current_timestamp;
 use graph::data::subgraph::DataSource;
 use graph::prelude::*;
 
@@ -26,6 +29,8 @@ use asc_abi::asc_ptr::*;
 use asc_abi::class::*;
 use asc_abi::*;
 use hex;
+use log_limiter::MappingLogLimiter;
+use parity_wasm::elements::MemoryType;
 
 /// AssemblyScript-compatible WASM memory heap.
 #[derive(Clone)]
@@ -85,6 +90,32 @@ const JSON_TO_U64_FUNC_INDEX: usize = 16;
 const JSON_TO_F64_FUNC_INDEX: usize = 17;
 const JSON_TO_BIG_INT_FUNC_INDEX: usize = 18;
 const IPFS_CAT_FUNC_INDEX: usize = 19;
+const ETHEREUM_TRY_CALL_FUNC_INDEX: usize = 20;
+const ETHEREUM_TRANSACTION_FEES_FUNC_INDEX: usize = 21;
+const LOG_DEBUG_FUNC_INDEX: usize = 22;
+const LOG_INFO_FUNC_INDEX: usize = 23;
+const LOG_WARNING_FUNC_INDEX: usize = 24;
+const LOG_ERROR_FUNC_INDEX: usize = 25;
+
+/// Size of a page of WASM linear memory.
+const WASM_PAGE_SIZE: usize = 64 * 1024;
+
+/// Limits on the linear memory of the WASM instances that run mappings, so
+/// that a mapping that leaks memory cannot grow the node without bounds.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MemoryLimits {
+    /// The largest size in bytes that an instance may grow its linear memory
+    /// to. Allocations beyond that fail the handler that makes them.
+    pub max_memory_bytes: Option<usize>,
+
+    /// Number of handlers after which an instance is replaced with a fresh
+    /// one.
+    pub recycle_after_handlers: Option<u64>,
+
+    /// Size in bytes of linear memory beyond which an instance is replaced
+    /// with a fresh one after the handler that grew it.
+    pub recycle_after_bytes: Option<usize>,
+}
 
 pub struct WasmiModuleConfig<T, L> {
     pub subgraph: SubgraphManifest,
@@ -92,6 +123,9 @@ pub struct WasmiModuleConfig<T, L> {
     pub event_sink: Sender<RuntimeHostEvent>,
     pub ethereum_adapter: Arc<Mutex<T>>,
     pub link_resolver: Arc<L>,
+    pub(crate) log_limiter: Arc<Mutex<MappingLogLimiter>>,
+    pub(crate) mapping_logs: Option<Arc<MappingLogCapture + Send + Sync>>,
+    pub memory_limits: MemoryLimits,
 }
 
 impl<T, L> Clone for WasmiModuleConfig<T, L> {
@@ -102,6 +136,9 @@ impl<T, L> Clone for WasmiModuleConfig<T, L> {
             event_sink: self.event_sink.clone(),
             ethereum_adapter: self.ethereum_adapter.clone(),
             link_resolver: self.link_resolver.clone(),
+            log_limiter: self.log_limiter.clone(),
+            mapping_logs: self.mapping_logs.clone(),
+            memory_limits: self.memory_limits,
         }
     }
 }
@@ -112,6 +149,10 @@ pub struct WasmiModule<T, L> {
     pub module: ModuleRef,
     externals: HostExternals<T, L>,
     heap: WasmiAscHeap,
+    /// Kept to replace the instance with a fresh one when it is recycled.
+    config: WasmiModuleConfig<T, L>,
+    /// Number of handlers the current instance has run.
+    handlers_run: u64,
 }
 
 impl<T, L> WasmiModule<T, L>
@@ -122,14 +163,35 @@ where
     /// Creates a new wasmi module
     pub fn new(logger: &Logger, config: WasmiModuleConfig<T, L>) -> Self {
         let logger = logger.new(o!("component" => "WasmiModule"));
+        let (module, externals, heap) = Self::instantiate(&logger, &config);
 
-        let module = Module::from_parity_wasm_module(config.data_source.mapping.runtime.clone())
-            .expect(
-                format!(
-                    "Wasmi could not interpret module of data source: {}",
-                    config.data_source.name
-                ).as_str(),
-            );
+        WasmiModule {
+            logger,
+            module,
+            externals,
+            heap,
+            config,
+            handlers_run: 0,
+        }
+    }
+
+    /// Instantiates and starts the mappings of the data source, with the
+    /// linear memory capped as the memory limits require.
+    fn instantiate(
+        logger: &Logger,
+        config: &WasmiModuleConfig<T, L>,
+    ) -> (ModuleRef, HostExternals<T, L>, WasmiAscHeap) {
+        let mut runtime = config.data_source.mapping.runtime.clone();
+        if let Some(max_memory_bytes) = config.memory_limits.max_memory_bytes {
+            limit_memory(&mut runtime, max_memory_bytes);
+        }
+
+        let module = Module::from_parity_wasm_module(runtime).expect(
+            format!(
+                "Wasmi could not interpret module of data source: {}",
+                config.data_source.name
+            ).as_str(),
+        );
 
         // Build import resolver
         let mut imports = ImportsBuilder::new();
@@ -139,6 +201,7 @@ where
         imports.push_resolver("typeConversion", &TypeConversionModuleResolver);
         imports.push_resolver("json", &JsonModuleResolver);
         imports.push_resolver("ipfs", &IpfsModuleResolver);
+        imports.push_resolver("log", &LogModuleResolver);
 
         // Instantiate the runtime module using hosted functions and import resolver
         let module =
@@ -158,30 +221,34 @@ where
 
         // Create new instance of externally hosted functions invoker
         let mut externals = HostExternals {
-            subgraph: config.subgraph,
-            data_source: config.data_source,
+            not_stored_fields: config.subgraph.schema.not_stored_fields(),
+            subgraph: config.subgraph.clone(),
+            data_source: config.data_source.clone(),
             logger: logger.clone(),
             event_sink: config.event_sink.clone(),
             heap: heap.clone(),
             ethereum_adapter: config.ethereum_adapter.clone(),
             link_resolver: config.link_resolver.clone(),
+            log_limiter: config.log_limiter.clone(),
+            mapping_logs: config.mapping_logs.clone(),
+            handler_name: String::new(),
             block_hash: H256::zero(),
+            block_number: 0,
+            transaction_hash: H256::zero(),
         };
 
         let module = module
             .run_start(&mut externals)
             .expect("Failed to start WASM module instance");
 
-        WasmiModule {
-            logger,
-            module,
-            externals,
-            heap,
-        }
+        (module, externals, heap)
     }
 
     pub fn handle_ethereum_event(&mut self, handler_name: &str, event: EthereumEvent) {
+        self.externals.handler_name = handler_name.to_owned();
         self.externals.block_hash = event.block_hash.clone();
+        self.externals.block_number = event.block_number;
+        self.externals.transaction_hash = event.transaction_hash.clone();
         self.module
             .invoke_export(
                 handler_name,
@@ -194,6 +261,61 @@ where
                       "error" => format!("{}", e));
                 None
             });
+
+        self.handlers_run += 1;
+        self.recycle_if_needed();
+    }
+
+    /// Sends the entity changes of the handlers that run from now on to
+    /// `event_sink`.
+    pub(crate) fn set_event_sink(&mut self, event_sink: Sender<RuntimeHostEvent>) {
+        self.externals.event_sink = event_sink.clone();
+        self.config.event_sink = event_sink;
+    }
+
+    /// Replaces the instance with a fresh one once it has run as many
+    /// handlers, or grown its linear memory as much, as the memory limits
+    /// allow. This releases whatever memory the mappings have leaked, along
+    /// with any state they kept in globals.
+    fn recycle_if_needed(&mut self) {
+        let limits = self.config.memory_limits;
+        let memory_bytes = self.heap.memory.current_size().0 * WASM_PAGE_SIZE;
+        let handlers_exceeded = limits
+            .recycle_after_handlers
+            .map_or(false, |handlers| self.handlers_run >= handlers);
+        let memory_exceeded = limits
+            .recycle_after_bytes
+            .map_or(false, |bytes| memory_bytes > bytes);
+        if !handlers_exceeded && !memory_exceeded {
+            return;
+        }
+
+        debug!(self.logger, "Recycling WASM instance";
+               "handlers_run" => self.handlers_run,
+               "memory_bytes" => memory_bytes);
+        let (module, externals, heap) = Self::instantiate(&self.logger, &self.config);
+        self.module = module;
+        self.externals = externals;
+        self.heap = heap;
+        self.handlers_run = 0;
+    }
+}
+
+/// Caps the linear memory that a WASM module defines at `max_bytes`, so that
+/// growing it further fails. Memories keep at least their initial size.
+fn limit_memory(module: &mut parity_wasm::elements::Module, max_bytes: usize) {
+    let max_pages = (max_bytes / WASM_PAGE_SIZE) as u32;
+    if let Some(section) = module.memory_section_mut() {
+        for memory in section.entries_mut().iter_mut() {
+            let (initial, maximum) = {
+                let limits = memory.limits();
+                (limits.initial(), limits.maximum())
+            };
+            let maximum = maximum
+                .map_or(max_pages, |maximum| maximum.min(max_pages))
+                .max(initial);
+            *memory = MemoryType::new(initial, Some(maximum));
+        }
     }
 }
 
@@ -224,8 +346,19 @@ pub struct HostExternals<T, L> {
     heap: WasmiAscHeap,
     ethereum_adapter: Arc<Mutex<T>>,
     link_resolver: Arc<L>,
+    log_limiter: Arc<Mutex<MappingLogLimiter>>,
+    // Where messages logged by the mapping are kept for subgraph authors.
+    mapping_logs: Option<Arc<MappingLogCapture + Send + Sync>>,
+    // Fields of each entity type that are dropped before entities are stored.
+    not_stored_fields: HashMap<String, HashSet<String>>,
+    // Name of the handler mapping the current event.
+    handler_name: String,
     // Block hash of the event being mapped.
     block_hash: H256,
+    // Block number of the event being mapped.
+    block_number: u64,
+    // Hash of the transaction that emitted the event being mapped.
+    transaction_hash: H256,
 }
 
 impl<T, L> HostExternals<T, L>
@@ -243,7 +376,10 @@ where
         let block_hash: H256 = self.block_hash.clone();
         let entity: String = self.heap.asc_get(entity_ptr);
         let id: String = self.heap.asc_get(id_ptr);
-        let data: HashMap<String, Value> = self.heap.asc_get(data_ptr);
+        let mut data: HashMap<String, Value> = self.heap.asc_get(data_ptr);
+        if let Some(not_stored_fields) = self.not_stored_fields.get(&entity) {
+            data.retain(|attribute, _| !not_stored_fields.contains(attribute));
+        }
         let store_key = StoreKey {
             subgraph: self.subgraph.id.clone(),
             entity,
@@ -259,7 +395,7 @@ where
             .send(RuntimeHostEvent::EntitySet(
                 store_key,
                 entity_data,
-                EventSource::EthereumBlock(block_hash),
+                EventSource::EthereumBlock(block_hash, self.block_number),
             ))
             .map_err(move |e| {
                 error!(logger, "Failed to forward runtime host event";
@@ -292,7 +428,7 @@ where
             .clone()
             .send(RuntimeHostEvent::EntityRemoved(
                 store_key,
-                EventSource::EthereumBlock(block_hash),
+                EventSource::EthereumBlock(block_hash, self.block_number),
             ))
             .map_err(move |e| {
                 error!(logger, "Failed to forward runtime host event";
@@ -311,7 +447,73 @@ where
         call_ptr: AscPtr<AscUnresolvedContractCall>,
     ) -> Result<Option<RuntimeValue>, Trap> {
         let unresolved_call: UnresolvedContractCall = self.heap.asc_get(call_ptr);
+        self.contract_call(&unresolved_call)?
+            .map(|result| Some(RuntimeValue::from(self.heap.asc_new(&*result))))
+            .map_err(|e| {
+                host_error(format!(
+                    "Failed to call function \"{}\" of contract \"{}\": {}",
+                    unresolved_call.function_name, unresolved_call.contract_name, e
+                ))
+            })
+    }
+
+    /// Like `ethereum.call`, but returns calls that revert to the mapping
+    /// instead of failing the handler.
+    ///
+    /// function ethereum.tryCall(call: SmartContractCall): EthereumCallResult
+    fn ethereum_try_call(
+        &self,
+        call_ptr: AscPtr<AscUnresolvedContractCall>,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        let unresolved_call: UnresolvedContractCall = self.heap.asc_get(call_ptr);
+        let result = match self.contract_call(&unresolved_call)? {
+            Ok(result) => Ok(result),
+            Err(EthereumContractCallError::Revert(reason)) => {
+                info!(self.logger, "Smart contract call reverted";
+                      "contract" => &unresolved_call.contract_name,
+                      "function" => &unresolved_call.function_name,
+                      "reason" => &reason);
+                Err(reason)
+            }
+            Err(e) => {
+                return Err(host_error(format!(
+                    "Failed to call function \"{}\" of contract \"{}\": {}",
+                    unresolved_call.function_name, unresolved_call.contract_name, e
+                )))
+            }
+        };
+        let result_ptr: AscPtr<AscEthereumCallResult> = self.heap.asc_new(&result);
+        Ok(Some(RuntimeValue::from(result_ptr)))
+    }
 
+    /// Returns the fees paid by the transaction that emitted the event being
+    /// mapped, as of the block of the event.
+    ///
+    /// function ethereum.transactionFees(): EthereumTransactionFees
+    fn ethereum_transaction_fees(&self) -> Result<Option<RuntimeValue>, Trap> {
+        let fees = self
+            .ethereum_adapter
+            .lock()
+            .unwrap()
+            .transaction_fees(self.transaction_hash, self.block_hash)
+            .wait()
+            .map_err(|e| {
+                host_error(format!(
+                    "Failed to look up the fees of transaction {:x}: {}",
+                    self.transaction_hash, e
+                ))
+            })?;
+        let fees_ptr: AscPtr<AscEthereumTransactionFees> = self.heap.asc_new(&fees);
+        Ok(Some(RuntimeValue::from(fees_ptr)))
+    }
+
+    /// Makes a contract call on behalf of the mapping. Fails if the contract
+    /// or function is unknown, but leaves errors of the call itself to the
+    /// caller.
+    fn contract_call(
+        &self,
+        unresolved_call: &UnresolvedContractCall,
+    ) -> Result<Result<Vec<Token>, EthereumContractCallError>, Trap> {
         info!(self.logger, "Call smart contract";
               "contract" => &unresolved_call.contract_name,
               "function" => &unresolved_call.function_name);
@@ -346,18 +548,12 @@ where
             args: unresolved_call.function_args.clone(),
         };
 
-        self.ethereum_adapter
+        Ok(self
+            .ethereum_adapter
             .lock()
             .unwrap()
             .contract_call(call)
-            .wait()
-            .map(|result| Some(RuntimeValue::from(self.heap.asc_new(&*result))))
-            .map_err(|e| {
-                host_error(format!(
-                    "Failed to call function \"{}\" of contract \"{}\": {}",
-                    unresolved_call.function_name, unresolved_call.contract_name, e
-                ))
-            })
+            .wait())
     }
 
     /// function typeConversion.bytesToString(bytes: Bytes): string
@@ -541,6 +737,55 @@ where
         let big_int_ptr: AscPtr<BigInt> = self.heap.asc_new(&*big_int.to_signed_bytes_le());
         Ok(Some(RuntimeValue::from(big_int_ptr)))
     }
+
+    /// function log.debug(message: string): void
+    /// function log.info(message: string): void
+    /// function log.warning(message: string): void
+    /// function log.error(message: string): void
+    fn log(
+        &self,
+        level: MappingLogLevel,
+        message_ptr: AscPtr<AscString>,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        let message: String = self.heap.asc_get(message_ptr);
+
+        let (admitted, dropped) = self.log_limiter.lock().unwrap().admit(Instant::now());
+        if dropped > 0 {
+            warn!(self.logger, "Dropped mapping log messages, the subgraph logs too much";
+                  "subgraph" => &self.subgraph.id, "dropped" => dropped);
+        }
+        if !admitted {
+            return Ok(None);
+        }
+
+        let entry = MappingLogEntry {
+            subgraph_id: self.subgraph.id.clone(),
+            timestamp: current_timestamp(),
+            level,
+            message,
+            handler: self.handler_name.clone(),
+            block_number: self.block_number,
+            block_hash: format!("{:x}", self.block_hash),
+            transaction_hash: format!("{:x}", self.transaction_hash),
+        };
+        let logger = self.logger.new(o!(
+            "subgraph" => entry.subgraph_id.clone(),
+            "handler" => entry.handler.clone(),
+            "block_number" => entry.block_number,
+            "block_hash" => entry.block_hash.clone(),
+            "transaction" => entry.transaction_hash.clone(),
+        ));
+        match level {
+            MappingLogLevel::Debug => debug!(logger, "{}", entry.message),
+            MappingLogLevel::Info => info!(logger, "{}", entry.message),
+            MappingLogLevel::Warning => warn!(logger, "{}", entry.message),
+            MappingLogLevel::Error => error!(logger, "{}", entry.message),
+        }
+        if let Some(ref mapping_logs) = self.mapping_logs {
+            mapping_logs.capture(entry);
+        }
+        Ok(None)
+    }
 }
 
 impl<T, L> Externals for HostExternals<T, L>
@@ -563,6 +808,8 @@ where
                 self.store_remove(args.nth_checked(0)?, args.nth_checked(1)?)
             }
             ETHEREUM_CALL_FUNC_INDEX => self.ethereum_call(args.nth_checked(0)?),
+            ETHEREUM_TRY_CALL_FUNC_INDEX => self.ethereum_try_call(args.nth_checked(0)?),
+            ETHEREUM_TRANSACTION_FEES_FUNC_INDEX => self.ethereum_transaction_fees(),
             TYPE_CONVERSION_BYTES_TO_STRING_FUNC_INDEX => {
                 self.convert_bytes_to_string(args.nth_checked(0)?)
             }
@@ -587,6 +834,10 @@ where
             JSON_TO_F64_FUNC_INDEX => self.json_to_f64(args.nth_checked(0)?),
             JSON_TO_BIG_INT_FUNC_INDEX => self.json_to_big_int(args.nth_checked(0)?),
             IPFS_CAT_FUNC_INDEX => self.ipfs_cat(args.nth_checked(0)?),
+            LOG_DEBUG_FUNC_INDEX => self.log(MappingLogLevel::Debug, args.nth_checked(0)?),
+            LOG_INFO_FUNC_INDEX => self.log(MappingLogLevel::Info, args.nth_checked(0)?),
+            LOG_WARNING_FUNC_INDEX => self.log(MappingLogLevel::Warning, args.nth_checked(0)?),
+            LOG_ERROR_FUNC_INDEX => self.log(MappingLogLevel::Error, args.nth_checked(0)?),
             _ => panic!("Unimplemented function at {}", index),
         }
     }
@@ -654,6 +905,14 @@ impl ModuleImportResolver for EthereumModuleResolver {
                 Signature::new(&[ValueType::I32][..], Some(ValueType::I32)),
                 ETHEREUM_CALL_FUNC_INDEX,
             ),
+            "tryCall" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32][..], Some(ValueType::I32)),
+                ETHEREUM_TRY_CALL_FUNC_INDEX,
+            ),
+            "transactionFees" => FuncInstance::alloc_host(
+                Signature::new(&[] as &[ValueType], Some(ValueType::I32)),
+                ETHEREUM_TRANSACTION_FEES_FUNC_INDEX,
+            ),
             _ => {
                 return Err(Error::Instantiation(format!(
                     "Export '{}' not found",
@@ -774,6 +1033,26 @@ impl ModuleImportResolver for IpfsModuleResolver {
     }
 }
 
+struct LogModuleResolver;
+
+impl ModuleImportResolver for LogModuleResolver {
+    fn resolve_func(&self, field_name: &str, _signature: &Signature) -> Result<FuncRef, Error> {
+        let index = match field_name {
+            "debug" => LOG_DEBUG_FUNC_INDEX,
+            "info" => LOG_INFO_FUNC_INDEX,
+            "warning" => LOG_WARNING_FUNC_INDEX,
+            "error" => LOG_ERROR_FUNC_INDEX,
+            _ => {
+                return Err(Error::Instantiation(format!(
+                    "Export '{}' not found",
+                    field_name
+                )))
+            }
+        };
+        Ok(FuncInstance::alloc_host(Signature::new(&[ValueType::I32][..], None), index))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     extern crate failure;
@@ -807,6 +1086,21 @@ mod tests {
             unimplemented!()
         }
 
+        fn transaction_fees(
+            &mut self,
+            _transaction_hash: H256,
+            _block_hash: H256,
+        ) -> Box<Future<Item = EthereumTransactionFees, Error = EthereumTransactionError>> {
+            unimplemented!()
+        }
+
+        fn block_header(
+            &mut self,
+            _block_id: BlockId,
+        ) -> Box<Future<Item = EthereumBlockHeader, Error = EthereumBlockError>> {
+            unimplemented!()
+        }
+
         fn subscribe_to_event(
             &mut self,
             _subscription: EthereumEventSubscription,
@@ -847,6 +1141,7 @@ mod tests {
 
         DataSource {
             kind: String::from("ethereum/contract"),
+            network: None,
             name: String::from("example data source"),
             source: Source {
                 address: String::from("0123123123"),
@@ -881,6 +1176,9 @@ mod tests {
                 event_sink: sender,
                 ethereum_adapter: mock_ethereum_adapter,
                 link_resolver: Arc::new(FakeLinkResolver),
+                log_limiter: Arc::new(Mutex::new(MappingLogLimiter::new())),
+                mapping_logs: None,
+                memory_limits: MemoryLimits::default(),
             },
         );
 
@@ -889,10 +1187,14 @@ mod tests {
             address: Address::from("22843e74c59580b3eaf6c233fa67d8b7c561a835"),
             event_signature: util::ethereum::string_to_h256("ExampleEvent(string)"),
             block_hash: util::ethereum::string_to_h256("example block hash"),
+            block_number: 1,
+            transaction_hash: util::ethereum::string_to_h256("example transaction hash"),
             params: vec![LogParam {
                 name: String::from("exampleParam"),
                 value: Token::String(String::from("some data")),
             }],
+            log_index: U256::from(0),
+            transaction_log_index: U256::from(0),
             removed: false,
         };
 
@@ -904,6 +1206,62 @@ mod tests {
         );
     }
 
+    #[test]
+    fn limit_and_recycle_instances() {
+        let logger = slog::Logger::root(slog::Discard, o!());
+        let (sender, _receiver) = channel(1);
+        let mock_ethereum_adapter = Arc::new(Mutex::new(MockEthereumAdapter::default()));
+        let data_source = mock_data_source("wasm_test/example_event_handler.wasm");
+        let initial_pages = data_source.mapping.runtime.memory_section().unwrap().entries()[0]
+            .limits()
+            .initial() as usize;
+        let mut module = WasmiModule::new(
+            &logger,
+            WasmiModuleConfig {
+                subgraph: mock_subgraph(),
+                data_source,
+                event_sink: sender,
+                ethereum_adapter: mock_ethereum_adapter,
+                link_resolver: Arc::new(FakeLinkResolver),
+                log_limiter: Arc::new(Mutex::new(MappingLogLimiter::new())),
+                mapping_logs: None,
+                memory_limits: MemoryLimits {
+                    max_memory_bytes: Some((initial_pages + 2) * WASM_PAGE_SIZE),
+                    recycle_after_handlers: Some(2),
+                    recycle_after_bytes: None,
+                },
+            },
+        );
+
+        // The memory may grow by two pages
+        assert_eq!(
+            module.heap.memory.maximum().map(|pages| pages.0),
+            Some(initial_pages + 2)
+        );
+
+        let ethereum_event = || EthereumEvent {
+            address: Address::from("22843e74c59580b3eaf6c233fa67d8b7c561a835"),
+            event_signature: util::ethereum::string_to_h256("ExampleEvent(string)"),
+            block_hash: util::ethereum::string_to_h256("example block hash"),
+            block_number: 1,
+            transaction_hash: util::ethereum::string_to_h256("example transaction hash"),
+            params: vec![],
+            log_index: U256::from(0),
+            transaction_log_index: U256::from(0),
+            removed: false,
+        };
+
+        // The instance is replaced after every second handler
+        module.handle_ethereum_event("handleNonExistentExampleEvent", ethereum_event());
+        assert_eq!(module.handlers_run, 1);
+        module.handle_ethereum_event("handleNonExistentExampleEvent", ethereum_event());
+        assert_eq!(module.handlers_run, 0);
+        assert_eq!(
+            module.heap.memory.maximum().map(|pages| pages.0),
+            Some(initial_pages + 2)
+        );
+    }
+
     #[test]
     fn call_event_handler_and_receive_store_event() {
         tokio::run(future::lazy(|| {
@@ -927,6 +1285,9 @@ mod tests {
                         event_sink: sender,
                         ethereum_adapter: mock_ethereum_adapter,
                         link_resolver: Arc::new(FakeLinkResolver),
+                        log_limiter: Arc::new(Mutex::new(MappingLogLimiter::new())),
+                        mapping_logs: None,
+                        memory_limits: MemoryLimits::default(),
                     },
                 );
 
@@ -935,10 +1296,14 @@ mod tests {
                     address: Address::from("22843e74c59580b3eaf6c233fa67d8b7c561a835"),
                     event_signature: util::ethereum::string_to_h256("ExampleEvent(string)"),
                     block_hash: util::ethereum::string_to_h256("example block hash"),
+                    block_number: 1,
+                    transaction_hash: util::ethereum::string_to_h256("example transaction hash"),
                     params: vec![LogParam {
                         name: String::from("exampleParam"),
                         value: Token::String(String::from("some data")),
                     }],
+                    log_index: U256::from(0),
+                    transaction_log_index: U256::from(0),
                     removed: false,
                 };
 
@@ -967,9 +1332,10 @@ mod tests {
                             vec![(String::from("exampleAttribute"), Value::from("some data"))]
                                 .into_iter()
                         )),
-                        EventSource::EthereumBlock(util::ethereum::string_to_h256(
-                            "example block hash",
-                        )),
+                        EventSource::EthereumBlock(
+                            util::ethereum::string_to_h256("example block hash"),
+                            1,
+                        ),
                     )
                 );
             })
@@ -992,6 +1358,9 @@ mod tests {
                         event_sink: sender,
                         ethereum_adapter: mock_ethereum_adapter,
                         link_resolver: Arc::new(FakeLinkResolver),
+                        log_limiter: Arc::new(Mutex::new(MappingLogLimiter::new())),
+                        mapping_logs: None,
+                        memory_limits: MemoryLimits::default(),
                     },
                 );
 
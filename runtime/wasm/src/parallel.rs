@@ -0,0 +1,330 @@
+use futures::sync::mpsc::{channel, Sender};
+use std::collections::HashSet;
+use std::mem;
+use std::sync::mpsc;
+use std::thread;
+
+use graph::components::ethereum::EthereumEvent;
+use graph::components::subgraph::RuntimeHostEvent;
+use graph::data::subgraph::MappingEventHandler;
+use graph::prelude::*;
+
+use module::{WasmiModule, WasmiModuleConfig};
+
+/// An event along with the handler that it is passed to.
+pub(crate) type HandlerEvent = (MappingEventHandler, EthereumEvent);
+
+/// Splits the events of a block into groups that can be handled
+/// independently of each other, given the handlers they are passed to.
+/// Handlers that declare the entity types they touch are independent of each
+/// other if these don't overlap; handlers that don't declare them depend on
+/// all other handlers.
+///
+/// Returns the indexes of the events in each group, in the order of the
+/// events. Groups are ordered by their first event.
+pub(crate) fn independent_groups<'a, I>(handlers: I) -> Vec<Vec<usize>>
+where
+    I: IntoIterator<Item = &'a MappingEventHandler>,
+{
+    // The events of each group, along with the entity types they touch;
+    // `None` if they may touch any entity type
+    let mut groups: Vec<(Vec<usize>, Option<HashSet<&'a str>>)> = vec![];
+
+    for (index, handler) in handlers.into_iter().enumerate() {
+        let entities: Option<HashSet<&str>> = handler
+            .entities
+            .as_ref()
+            .map(|entities| entities.iter().map(|entity| entity.as_str()).collect());
+
+        let (dependencies, mut others): (Vec<_>, Vec<_>) =
+            groups.into_iter().partition(|(_, group_entities)| {
+                match (&entities, group_entities) {
+                    (Some(entities), Some(group_entities)) => {
+                        !entities.is_disjoint(group_entities)
+                    }
+                    _ => true,
+                }
+            });
+
+        // Merge the event with all groups it depends on
+        let mut group = (vec![index], entities);
+        for (events, group_entities) in dependencies {
+            group.0.extend(events);
+            group.1 = match (group.1, group_entities) {
+                (Some(mut entities), Some(group_entities)) => {
+                    entities.extend(group_entities);
+                    Some(entities)
+                }
+                _ => None,
+            };
+        }
+        group.0.sort();
+
+        others.push(group);
+        others.sort_by_key(|(events, _)| events[0]);
+        groups = others;
+    }
+
+    groups.into_iter().map(|(events, _)| events).collect()
+}
+
+/// Collects the events of a stream into batches of consecutive events from
+/// the same block. A batch ends when an event from another block arrives, or
+/// when no further event is ready yet.
+pub(crate) struct BlockBatches<S> {
+    events: S,
+    batch: Vec<HandlerEvent>,
+    /// The first event of the next batch.
+    next: Option<HandlerEvent>,
+    done: bool,
+}
+
+impl<S> BlockBatches<S> {
+    pub fn new(events: S) -> Self {
+        BlockBatches {
+            events,
+            batch: vec![],
+            next: None,
+            done: false,
+        }
+    }
+}
+
+impl<S> Stream for BlockBatches<S>
+where
+    S: Stream<Item = HandlerEvent>,
+{
+    type Item = Vec<HandlerEvent>;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        if self.done {
+            return Ok(Async::Ready(None));
+        }
+        if let Some(next) = self.next.take() {
+            self.batch.push(next);
+        }
+
+        loop {
+            match self.events.poll()? {
+                Async::Ready(Some(event)) => {
+                    let other_block = self
+                        .batch
+                        .first()
+                        .map_or(false, |(_, first)| first.block_hash != event.1.block_hash);
+                    if other_block {
+                        self.next = Some(event);
+                        return Ok(Async::Ready(Some(mem::replace(&mut self.batch, vec![]))));
+                    }
+                    self.batch.push(event);
+                }
+                Async::Ready(None) => {
+                    self.done = true;
+                    return Ok(Async::Ready(if self.batch.is_empty() {
+                        None
+                    } else {
+                        Some(mem::replace(&mut self.batch, vec![]))
+                    }));
+                }
+                Async::NotReady => {
+                    return Ok(if self.batch.is_empty() {
+                        Async::NotReady
+                    } else {
+                        Async::Ready(Some(mem::replace(&mut self.batch, vec![])))
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Events for a worker to handle, in order, and where to send the entity
+/// changes of their handlers.
+struct Job {
+    events: Vec<HandlerEvent>,
+    event_sink: Sender<RuntimeHostEvent>,
+}
+
+/// Runs the handlers of a data source on a number of worker threads, each
+/// with a WASM instance of its own. Independent groups of the events of a
+/// block are handled concurrently, and the entity changes of each group are
+/// forwarded one group after the other, so that they reach the store in the
+/// same order for every run.
+pub(crate) struct HandlerPool {
+    logger: Logger,
+    workers: Vec<mpsc::Sender<Job>>,
+    event_sink: Sender<RuntimeHostEvent>,
+}
+
+impl HandlerPool {
+    pub fn new<T, L>(logger: &Logger, config: WasmiModuleConfig<T, L>, workers: usize) -> Self
+    where
+        T: EthereumAdapter + 'static,
+        L: LinkResolver + 'static,
+    {
+        let logger = logger.new(o!("component" => "HandlerPool"));
+        let event_sink = config.event_sink.clone();
+
+        let workers = (0..workers.max(1))
+            .map(|_| {
+                let (job_sender, job_receiver) = mpsc::channel::<Job>();
+                let logger = logger.clone();
+                let config = config.clone();
+
+                // wasmi modules are not `Send`, so each worker creates its
+                // own module
+                thread::spawn(move || {
+                    let event_sink = config.event_sink.clone();
+                    let mut module = WasmiModule::new(&logger, config);
+                    for job in job_receiver {
+                        module.set_event_sink(job.event_sink);
+                        for (event_handler, event) in job.events {
+                            debug!(logger, "  Call event handler";
+                                   "name" => &event_handler.handler);
+                            module.handle_ethereum_event(event_handler.handler.as_str(), event);
+                        }
+                        // Drops the sink of the job, which tells that the job
+                        // is done
+                        module.set_event_sink(event_sink.clone());
+                    }
+                });
+
+                job_sender
+            })
+            .collect();
+
+        HandlerPool {
+            logger,
+            workers,
+            event_sink,
+        }
+    }
+
+    /// Handles the events of a block and waits until the entity changes of
+    /// all handlers have been forwarded.
+    pub fn handle_block(&self, events: Vec<HandlerEvent>) {
+        let groups = independent_groups(events.iter().map(|(event_handler, _)| event_handler));
+        debug!(self.logger, "Handle block";
+               "events" => events.len(),
+               "groups" => groups.len());
+
+        let mut events: Vec<_> = events.into_iter().map(Some).collect();
+        let outputs: Vec<_> = groups
+            .into_iter()
+            .enumerate()
+            .map(|(index, group)| {
+                let (event_sink, output) = channel(100);
+                let job = Job {
+                    events: group
+                        .into_iter()
+                        .map(|event| events[event].take().unwrap())
+                        .collect(),
+                    event_sink,
+                };
+                self.workers[index % self.workers.len()]
+                    .send(job)
+                    .expect("Handler worker stopped");
+                output
+            })
+            .collect();
+
+        for output in outputs {
+            for event in output.wait() {
+                if let Ok(event) = event {
+                    if let Err(e) = self.event_sink.clone().send(event).wait() {
+                        error!(self.logger, "Failed to forward runtime host event";
+                               "error" => format!("{}", e));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethabi::Token;
+    use ethereum_types::{Address, U256};
+    use graph::components::ethereum::*;
+    use graph::data::subgraph::MappingEventHandler;
+    use graph::prelude::*;
+    use graph::util;
+
+    use super::*;
+
+    fn handler(entities: Option<Vec<&str>>) -> MappingEventHandler {
+        MappingEventHandler {
+            event: String::from("ExampleEvent(string)"),
+            handler: String::from("handleExampleEvent"),
+            entities: entities.map(|entities| entities.into_iter().map(String::from).collect()),
+        }
+    }
+
+    fn event(block: &str, log_index: u64) -> HandlerEvent {
+        let event = EthereumEvent {
+            address: Address::from("22843e74c59580b3eaf6c233fa67d8b7c561a835"),
+            event_signature: util::ethereum::string_to_h256("ExampleEvent(string)"),
+            block_hash: util::ethereum::string_to_h256(block),
+            block_number: 1,
+            transaction_hash: util::ethereum::string_to_h256("example transaction hash"),
+            params: vec![LogParam {
+                name: String::from("exampleParam"),
+                value: Token::String(String::from("some data")),
+            }],
+            log_index: U256::from(log_index),
+            transaction_log_index: U256::from(0),
+            removed: false,
+        };
+        (handler(None), event)
+    }
+
+    #[test]
+    fn groups_handlers_by_the_entities_they_touch() {
+        let handlers = vec![
+            handler(Some(vec!["User"])),
+            handler(Some(vec!["Token"])),
+            handler(Some(vec!["Band"])),
+            handler(Some(vec!["User", "Band"])),
+            handler(Some(vec![])),
+        ];
+        assert_eq!(
+            independent_groups(&handlers),
+            vec![vec![0, 2, 3], vec![1], vec![4]]
+        );
+
+        // Handlers that don't declare entities depend on everything
+        let handlers = vec![
+            handler(Some(vec!["User"])),
+            handler(None),
+            handler(Some(vec!["Token"])),
+        ];
+        assert_eq!(independent_groups(&handlers), vec![vec![0, 1, 2]]);
+    }
+
+    #[test]
+    fn batches_events_by_block() {
+        let events = vec![event("a", 0), event("a", 1), event("b", 0), event("c", 0)];
+        let batches: Vec<Vec<_>> = BlockBatches::new(stream::iter_ok::<_, ()>(events))
+            .collect()
+            .wait()
+            .unwrap()
+            .into_iter()
+            .map(|batch| {
+                batch
+                    .into_iter()
+                    .map(|(_, event)| (event.block_hash, event.log_index))
+                    .collect()
+            })
+            .collect();
+
+        let block = util::ethereum::string_to_h256;
+        assert_eq!(
+            batches,
+            vec![
+                vec![(block("a"), U256::from(0)), (block("a"), U256::from(1))],
+                vec![(block("b"), U256::from(0))],
+                vec![(block("c"), U256::from(0))],
+            ]
+        );
+    }
+}
@@ -2,7 +2,7 @@ use ethabi;
 use ethereum_types;
 use graph::serde_json;
 
-use graph::components::ethereum::EthereumEvent;
+use graph::components::ethereum::{EthereumEvent, EthereumTransactionFees};
 use graph::data::store;
 
 use asc_abi::class::*;
@@ -219,6 +219,40 @@ impl ToAscObj<AscEthereumEvent> for EthereumEvent {
             event_signature: heap.asc_new(&self.event_signature),
             block_hash: heap.asc_new(&self.block_hash),
             params: heap.asc_new(self.params.as_slice()),
+            log_index: heap.asc_new(&self.log_index),
+            transaction_log_index: heap.asc_new(&self.transaction_log_index),
+            removed: self.removed,
+        }
+    }
+}
+
+/// Converts the result of a contract call, or the reason it reverted with.
+impl ToAscObj<AscEthereumCallResult> for Result<Vec<ethabi::Token>, String> {
+    fn to_asc_obj<H: AscHeap>(&self, heap: &H) -> AscEthereumCallResult {
+        match self {
+            Ok(tokens) => AscEthereumCallResult {
+                value: heap.asc_new(tokens.as_slice()),
+                reason: AscPtr::null(),
+                reverted: false,
+            },
+            Err(reason) => AscEthereumCallResult {
+                value: AscPtr::null(),
+                reason: heap.asc_new(reason.as_str()),
+                reverted: true,
+            },
+        }
+    }
+}
+
+impl ToAscObj<AscEthereumTransactionFees> for EthereumTransactionFees {
+    fn to_asc_obj<H: AscHeap>(&self, heap: &H) -> AscEthereumTransactionFees {
+        AscEthereumTransactionFees {
+            gas_price: heap.asc_new(&self.gas_price),
+            gas_used: heap.asc_new(&self.gas_used),
+            base_fee_per_gas: match self.base_fee_per_gas {
+                Some(ref base_fee_per_gas) => heap.asc_new(base_fee_per_gas),
+                None => AscPtr::null(),
+            },
         }
     }
 }
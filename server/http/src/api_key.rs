@@ -0,0 +1,104 @@
+use graphql_parser::query as q;
+use hyper::{Body, Request};
+use std::sync::Mutex;
+
+use graph::components::server::GraphQLServerError;
+use graph::prelude::*;
+
+/// Header through which clients supply their API key.
+pub const API_KEY_HEADER: &str = "X-Api-Key";
+
+/// Checks the API keys of incoming queries and attributes their usage.
+#[derive(Clone)]
+pub struct ApiKeys {
+    logger: Logger,
+    store: Arc<Mutex<ApiKeyStore + Send>>,
+    required: bool,
+}
+
+impl ApiKeys {
+    /// Creates API key handling backed by `store`. If `required` is set,
+    /// queries without an API key are rejected.
+    pub fn new(logger: &Logger, store: Arc<Mutex<ApiKeyStore + Send>>, required: bool) -> Self {
+        ApiKeys {
+            logger: logger.new(o!("component" => "ApiKeys")),
+            store,
+            required,
+        }
+    }
+
+    /// Returns the API key supplied with the request, if there is one.
+    /// Fails if the key is unknown or missing when keys are required.
+    pub fn authenticate(
+        &self,
+        request: &Request<Body>,
+    ) -> Result<Option<String>, GraphQLServerError> {
+        let key = match request.headers().get(API_KEY_HEADER) {
+            Some(header) => header
+                .to_str()
+                .map(|key| key.trim().to_owned())
+                .map_err(|_| GraphQLServerError::Unauthorized(String::from("Invalid API key")))?,
+            None if self.required => {
+                return Err(GraphQLServerError::Unauthorized(String::from(
+                    "An API key is required",
+                )))
+            }
+            None => return Ok(None),
+        };
+
+        match self.store.lock().unwrap().api_key(&key) {
+            Ok(Some(_)) => Ok(Some(key)),
+            Ok(None) => Err(GraphQLServerError::Unauthorized(String::from(
+                "Invalid API key",
+            ))),
+            Err(e) => Err(GraphQLServerError::InternalError(e.to_string())),
+        }
+    }
+
+    /// Attributes a query and its result to an API key.
+    pub fn record(&self, key: &str, result: &Result<QueryResult, GraphQLServerError>) {
+        let usage = match result {
+            Ok(result) => ApiKeyUsage::query(
+                result.errors.is_some(),
+                result.data.as_ref().map_or(0, value_count),
+            ),
+            Err(_) => ApiKeyUsage::query(true, 0),
+        };
+
+        if let Err(e) = self.store.lock().unwrap().record_api_key_usage(key, usage) {
+            error!(self.logger, "Failed to record API key usage"; "error" => e.to_string());
+        }
+    }
+}
+
+/// Counts the values in a query result, which is what queries cost.
+fn value_count(value: &q::Value) -> u64 {
+    match value {
+        q::Value::List(values) => values.iter().map(value_count).sum(),
+        q::Value::Object(fields) => fields.values().map(value_count).sum(),
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use graphql_parser::query::Value;
+    use std::collections::BTreeMap;
+
+    use super::value_count;
+
+    #[test]
+    fn counts_leaf_values() {
+        let mut user = BTreeMap::new();
+        user.insert(String::from("id"), Value::String(String::from("1")));
+        user.insert(String::from("name"), Value::String(String::from("Jordi")));
+
+        let mut data = BTreeMap::new();
+        data.insert(
+            String::from("users"),
+            Value::List(vec![Value::Object(user.clone()), Value::Object(user)]),
+        );
+
+        assert_eq!(value_count(&Value::Object(data)), 4);
+    }
+}
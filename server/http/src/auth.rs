@@ -0,0 +1,59 @@
+use graph::util::ethereum::keccak256;
+
+/// The role that may see how queries are resolved, e.g. with `?debug=plan`.
+pub const ADMIN_ROLE: &str = "admin";
+
+/// Creates an auth token that grants `role` to whoever presents it. Tokens
+/// have the form `<role>:<signature>`, where the signature is derived from
+/// the role and the server's secret.
+pub fn auth_token(secret: &str, role: &str) -> String {
+    format!("{}:{}", role, signature(secret, role))
+}
+
+/// Verifies an auth token against the server's secret and returns the role
+/// it grants, or `None` if the token is invalid.
+pub fn verify_auth_token(secret: &str, token: &str) -> Option<String> {
+    let separator = token.rfind(':')?;
+    let (role, provided) = (&token[..separator], &token[separator + 1..]);
+    let expected = signature(secret, role);
+
+    // Compare all bytes so the time taken does not reveal the signature
+    let matches = provided.len() == expected.len()
+        && provided
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0;
+
+    if matches && !role.is_empty() {
+        Some(role.to_owned())
+    } else {
+        None
+    }
+}
+
+fn signature(secret: &str, role: &str) -> String {
+    format!("{:x}", keccak256(format!("{}:{}", secret, role).as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{auth_token, verify_auth_token};
+
+    #[test]
+    fn verifies_tokens_signed_with_the_secret() {
+        let token = auth_token("secret", "admin");
+        assert_eq!(verify_auth_token("secret", &token), Some("admin".into()));
+    }
+
+    #[test]
+    fn rejects_forged_tokens() {
+        let token = auth_token("other secret", "admin");
+        assert_eq!(verify_auth_token("secret", &token), None);
+
+        let token = auth_token("secret", "user").replace("user", "admin");
+        assert_eq!(verify_auth_token("secret", &token), None);
+
+        assert_eq!(verify_auth_token("secret", "admin"), None);
+    }
+}
@@ -0,0 +1,230 @@
+use graphql_parser::query as q;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use graph::components::store::{EventSource, StoreEvent};
+use graph::prelude::*;
+
+/// The state of a subgraph's entities that query results are valid for.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SubgraphVersion {
+    /// Hash of the latest block that entities were written for, if any
+    /// have been written since the node started.
+    block: Option<String>,
+    /// Number of writes to the subgraph since the node started.
+    writes: u64,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+struct CacheKey {
+    subgraph_id: String,
+    block: Option<String>,
+    query_hash: String,
+}
+
+struct CacheState {
+    capacity: usize,
+    versions: HashMap<String, SubgraphVersion>,
+    /// Cached results along with when they were last used.
+    entries: HashMap<CacheKey, (q::Value, u64)>,
+    /// The keys of all entries by when they were last used.
+    recently_used: BTreeMap<u64, CacheKey>,
+    clock: u64,
+}
+
+impl CacheState {
+    fn touch(&mut self, key: &CacheKey) {
+        self.clock += 1;
+        let clock = self.clock;
+        if let Some(entry) = self.entries.get_mut(key) {
+            self.recently_used.remove(&entry.1);
+            self.recently_used.insert(clock, key.clone());
+            entry.1 = clock;
+        }
+    }
+
+    fn remove_where(&mut self, predicate: impl Fn(&CacheKey) -> bool) {
+        let keys = self
+            .entries
+            .keys()
+            .filter(|key| predicate(key))
+            .cloned()
+            .collect::<Vec<_>>();
+        for key in keys {
+            if let Some((_, last_used)) = self.entries.remove(&key) {
+                self.recently_used.remove(&last_used);
+            }
+        }
+    }
+}
+
+/// An in-memory LRU cache for the results of queries against the latest
+/// block of a subgraph. Results are keyed by the subgraph, the hash of the
+/// latest block and the hash of the normalized query and its variables,
+/// and are dropped as soon as the store reports that the subgraph changed.
+#[derive(Clone)]
+pub struct QueryCache {
+    state: Arc<Mutex<CacheState>>,
+}
+
+impl QueryCache {
+    /// Creates a cache that holds the results of at most `capacity` queries.
+    pub fn new(capacity: usize) -> Self {
+        QueryCache {
+            state: Arc::new(Mutex::new(CacheState {
+                capacity,
+                versions: HashMap::new(),
+                entries: HashMap::new(),
+                recently_used: BTreeMap::new(),
+                clock: 0,
+            })),
+        }
+    }
+
+    /// Returns the current version of a subgraph's entities. Results must be
+    /// looked up and added with the version from before the query ran.
+    pub fn version(&self, subgraph_id: &str) -> SubgraphVersion {
+        let state = self.state.lock().unwrap();
+        state.versions.get(subgraph_id).cloned().unwrap_or_default()
+    }
+
+    /// Returns the cached result of a query, if there is one.
+    pub fn get(
+        &self,
+        subgraph_id: &str,
+        version: &SubgraphVersion,
+        query_hash: &str,
+    ) -> Option<q::Value> {
+        let key = CacheKey {
+            subgraph_id: subgraph_id.to_owned(),
+            block: version.block.clone(),
+            query_hash: query_hash.to_owned(),
+        };
+
+        let mut state = self.state.lock().unwrap();
+        let data = state.entries.get(&key).map(|(data, _)| data.clone());
+        if data.is_some() {
+            state.touch(&key);
+        }
+        data
+    }
+
+    /// Caches the result of a query that was run against `version` of the
+    /// subgraph, unless the subgraph has changed since. Evicts the least
+    /// recently used result if the cache is full.
+    pub fn insert(
+        &self,
+        subgraph_id: &str,
+        version: &SubgraphVersion,
+        query_hash: &str,
+        data: q::Value,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        if state.capacity == 0
+            || state.versions.get(subgraph_id).cloned().unwrap_or_default() != *version
+        {
+            return;
+        }
+
+        let key = CacheKey {
+            subgraph_id: subgraph_id.to_owned(),
+            block: version.block.clone(),
+            query_hash: query_hash.to_owned(),
+        };
+        if !state.entries.contains_key(&key) && state.entries.len() >= state.capacity {
+            let oldest = state
+                .recently_used
+                .iter()
+                .next()
+                .map(|(last_used, key)| (*last_used, key.clone()));
+            if let Some((last_used, oldest_key)) = oldest {
+                state.recently_used.remove(&last_used);
+                state.entries.remove(&oldest_key);
+            }
+        }
+
+        state.entries.insert(key.clone(), (data, 0));
+        state.touch(&key);
+    }
+
+    /// Drops the results that a change to the store may have affected.
+    pub fn handle_store_event(&self, event: &StoreEvent) {
+        let mut state = self.state.lock().unwrap();
+        match event {
+            StoreEvent::SubgraphChanged(subgraph_id, event_source) => {
+                {
+                    let version = state
+                        .versions
+                        .entry(subgraph_id.to_owned())
+                        .or_insert_with(SubgraphVersion::default);
+                    // Offchain data is written independently of blocks
+                    if let EventSource::EthereumBlock(block_hash, _) = event_source {
+                        version.block = Some(format!("{:x}", block_hash));
+                    }
+                    version.writes += 1;
+                }
+                state.remove_where(|key| &key.subgraph_id == subgraph_id);
+            }
+            // Entities don't tell which subgraph they belong to
+            StoreEvent::EntityAdded(_)
+            | StoreEvent::EntityRemoved(_)
+            | StoreEvent::EntityChanged(_) => {
+                for version in state.versions.values_mut() {
+                    version.writes += 1;
+                }
+                state.remove_where(|_| true);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use graphql_parser::query as q;
+
+    use graph::components::store::{EventSource, StoreEvent};
+    use graph::prelude::*;
+
+    use super::QueryCache;
+
+    fn block(n: u64) -> EventSource {
+        EventSource::EthereumBlock(n.into(), n)
+    }
+
+    #[test]
+    fn serves_results_until_the_subgraph_changes() {
+        let cache = QueryCache::new(10);
+        let version = cache.version("subgraph");
+        cache.insert("subgraph", &version, "query", q::Value::Int(1.into()));
+        assert_eq!(
+            cache.get("subgraph", &version, "query"),
+            Some(q::Value::Int(1.into()))
+        );
+        assert_eq!(cache.get("subgraph", &version, "other query"), None);
+
+        cache.handle_store_event(&StoreEvent::SubgraphChanged(String::from("other"), block(1)));
+        assert!(cache.get("subgraph", &version, "query").is_some());
+
+        cache.handle_store_event(&StoreEvent::SubgraphChanged(String::from("subgraph"), block(1)));
+        assert_eq!(cache.get("subgraph", &version, "query"), None);
+        assert_eq!(cache.get("subgraph", &cache.version("subgraph"), "query"), None);
+
+        // Results of queries that ran while the subgraph changed are not cached
+        cache.insert("subgraph", &version, "query", q::Value::Int(1.into()));
+        assert_eq!(cache.get("subgraph", &version, "query"), None);
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used_results() {
+        let cache = QueryCache::new(2);
+        let version = cache.version("subgraph");
+        cache.insert("subgraph", &version, "a", q::Value::Int(1.into()));
+        cache.insert("subgraph", &version, "b", q::Value::Int(2.into()));
+        cache.get("subgraph", &version, "a");
+        cache.insert("subgraph", &version, "c", q::Value::Int(3.into()));
+
+        assert!(cache.get("subgraph", &version, "a").is_some());
+        assert!(cache.get("subgraph", &version, "b").is_none());
+        assert!(cache.get("subgraph", &version, "c").is_some());
+    }
+}
@@ -0,0 +1,234 @@
+use graph::serde_json;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Configuration of the HTTP API served by a [GraphQLServer](struct.GraphQLServer.html).
+#[derive(Clone, Debug)]
+pub struct GraphQLServerConfig {
+    /// Path under which the whole API is served, e.g. `/api/v0`. Empty if
+    /// the API is served from the root.
+    pub path_prefix: String,
+    /// Headers that GraphiQL sends along with every query.
+    pub graphiql_headers: BTreeMap<String, String>,
+    /// Number of blocks after which a block is considered final, i.e. no
+    /// longer subject to chain reorganizations.
+    pub reorg_threshold: u64,
+    /// How long, in seconds, responses for final blocks may be cached.
+    pub immutable_max_age: u64,
+    /// Secret with which auth tokens are signed. Without it, all clients
+    /// are anonymous and cannot see `@restricted` fields.
+    pub auth_secret: Option<String>,
+    /// Deployments that are locked down to a fixed set of queries, with the
+    /// document hashes of the queries they allow.
+    pub query_allow_lists: HashMap<String, HashSet<String>>,
+    /// Whether queries without an API key are rejected. Only takes effect
+    /// if the server has access to API keys.
+    pub require_api_key: bool,
+    /// Queries whose estimated complexity exceeds this are rejected.
+    pub max_query_complexity: Option<u64>,
+    /// Number of query results kept in memory for repeated queries against
+    /// the latest block of a subgraph. Results are not cached if zero.
+    pub query_cache_size: usize,
+    /// Schemas that merge the schemas of several subgraphs, by the name
+    /// they are served under, with the prefix and ID of each subgraph.
+    pub stitched_schemas: BTreeMap<String, Vec<(String, String)>>,
+}
+
+impl Default for GraphQLServerConfig {
+    fn default() -> Self {
+        GraphQLServerConfig {
+            path_prefix: String::new(),
+            graphiql_headers: BTreeMap::new(),
+            reorg_threshold: 50,
+            immutable_max_age: 31_536_000,
+            auth_secret: None,
+            query_allow_lists: HashMap::new(),
+            require_api_key: false,
+            max_query_complexity: None,
+            query_cache_size: 0,
+            stitched_schemas: BTreeMap::new(),
+        }
+    }
+}
+
+impl GraphQLServerConfig {
+    /// Normalizes a path prefix so that it is either empty or starts, but
+    /// does not end, with a `/`. Fails if the prefix contains characters
+    /// other than letters, digits, `/`, `-`, `_`, `.` and `~`.
+    pub fn normalize_path_prefix(prefix: &str) -> Result<String, String> {
+        if let Some(c) = prefix
+            .chars()
+            .find(|c| !(c.is_ascii_alphanumeric() || "/-_.~".contains(*c)))
+        {
+            return Err(format!("invalid character `{}` in path prefix", c));
+        }
+
+        let trimmed = prefix.trim_matches('/');
+        if trimmed.is_empty() {
+            Ok(String::new())
+        } else {
+            Ok(format!("/{}", trimmed))
+        }
+    }
+
+    /// Strips the path prefix from a request path. Returns `None` if the
+    /// path lies outside of the prefix.
+    pub fn strip_path_prefix<'a>(&self, path: &'a str) -> Option<&'a str> {
+        if !path.starts_with(self.path_prefix.as_str()) {
+            return None;
+        }
+
+        match &path[self.path_prefix.len()..] {
+            "" => Some("/"),
+            rest if rest.starts_with('/') => Some(rest),
+            _ => None,
+        }
+    }
+
+    /// Parses an allow-list file with one query document hash per line.
+    /// Empty lines and lines starting with `#` are ignored.
+    pub fn parse_query_allow_list(contents: &str) -> HashSet<String> {
+        contents
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| line.to_lowercase())
+            .collect()
+    }
+
+    /// Returns true if a query with the given document hash may run against
+    /// the deployment. Deployments without an allow-list accept any query.
+    pub fn is_query_allowed(&self, subgraph_id: &str, document_hash: &str) -> bool {
+        self.query_allow_lists
+            .get(subgraph_id)
+            .map_or(true, |allowed| allowed.contains(document_hash))
+    }
+
+    /// Parses a stitched schema of the form
+    /// `NAME=PREFIX:SUBGRAPH_ID,PREFIX:SUBGRAPH_ID,...` into its name and
+    /// the prefix and ID of each subgraph.
+    pub fn parse_stitched_schema(value: &str) -> Result<(String, Vec<(String, String)>), String> {
+        let mut parts = value.splitn(2, '=');
+        let name = parts.next().unwrap_or("").trim();
+        let subgraphs = parts
+            .next()
+            .ok_or_else(|| format!("stitched schema `{}` has no subgraphs", value))?;
+        if name.is_empty() {
+            return Err(format!("stitched schema `{}` has no name", value));
+        }
+
+        let subgraphs = subgraphs
+            .split(',')
+            .map(|subgraph| {
+                let mut parts = subgraph.splitn(2, ':').map(str::trim);
+                match (parts.next(), parts.next()) {
+                    (Some(prefix), Some(id)) if !prefix.is_empty() && !id.is_empty() => {
+                        Ok((prefix.to_owned(), id.to_owned()))
+                    }
+                    _ => Err(format!(
+                        "expected `PREFIX:SUBGRAPH_ID` in stitched schema `{}`, found `{}`",
+                        name, subgraph
+                    )),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if subgraphs.len() < 2 {
+            return Err(format!(
+                "stitched schema `{}` needs at least two subgraphs",
+                name
+            ));
+        }
+        Ok((name.to_owned(), subgraphs))
+    }
+
+    /// Returns true if the ID is the name of a stitched schema rather than
+    /// that of a deployment.
+    pub fn is_stitched(&self, id: &str) -> bool {
+        self.stitched_schemas.contains_key(id)
+    }
+
+    /// Renders the GraphiQL page for this configuration.
+    pub fn graphiql_index(&self) -> String {
+        // Escape `<` so header values cannot close the surrounding script tag
+        let headers = serde_json::to_string(&self.graphiql_headers)
+            .expect("Failed to serialize GraphiQL headers")
+            .replace('<', "\\u003c");
+
+        include_str!("../assets/index.html")
+            .replace("{{PATH_PREFIX}}", &self.path_prefix)
+            .replace("{{DEFAULT_HEADERS}}", &headers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GraphQLServerConfig;
+
+    #[test]
+    fn normalizes_path_prefixes() {
+        assert_eq!(GraphQLServerConfig::normalize_path_prefix(""), Ok("".into()));
+        assert_eq!(GraphQLServerConfig::normalize_path_prefix("/"), Ok("".into()));
+        assert_eq!(
+            GraphQLServerConfig::normalize_path_prefix("api/v0/"),
+            Ok("/api/v0".into())
+        );
+        assert!(GraphQLServerConfig::normalize_path_prefix("/api'").is_err());
+    }
+
+    #[test]
+    fn strips_path_prefixes() {
+        let config = GraphQLServerConfig {
+            path_prefix: "/api/v0".into(),
+            ..Default::default()
+        };
+        assert_eq!(config.strip_path_prefix("/api/v0/graphql"), Some("/graphql"));
+        assert_eq!(config.strip_path_prefix("/api/v0"), Some("/"));
+        assert_eq!(config.strip_path_prefix("/api/v01/graphql"), None);
+        assert_eq!(config.strip_path_prefix("/graphql"), None);
+    }
+
+    #[test]
+    fn only_allows_listed_queries_for_locked_down_deployments() {
+        let mut config = GraphQLServerConfig::default();
+        config.query_allow_lists.insert(
+            "locked".into(),
+            GraphQLServerConfig::parse_query_allow_list("# queries of the dapp\nABC\n\n  def \n"),
+        );
+
+        assert!(config.is_query_allowed("locked", "abc"));
+        assert!(config.is_query_allowed("locked", "def"));
+        assert!(!config.is_query_allowed("locked", "ghi"));
+        assert!(config.is_query_allowed("open", "ghi"));
+    }
+
+    #[test]
+    fn parses_stitched_schemas() {
+        assert_eq!(
+            GraphQLServerConfig::parse_stitched_schema("all=music:QmA, tokens:QmB"),
+            Ok((
+                "all".into(),
+                vec![("music".into(), "QmA".into()), ("tokens".into(), "QmB".into())]
+            ))
+        );
+        assert!(GraphQLServerConfig::parse_stitched_schema("all").is_err());
+        assert!(GraphQLServerConfig::parse_stitched_schema("=music:QmA,tokens:QmB").is_err());
+        assert!(GraphQLServerConfig::parse_stitched_schema("all=music:QmA").is_err());
+        assert!(GraphQLServerConfig::parse_stitched_schema("all=music:QmA,QmB").is_err());
+    }
+
+    #[test]
+    fn renders_graphiql_with_prefix_and_headers() {
+        let mut config = GraphQLServerConfig {
+            path_prefix: "/api".into(),
+            ..Default::default()
+        };
+        config
+            .graphiql_headers
+            .insert("Authorization".into(), "Bearer </script>".into());
+
+        let index = config.graphiql_index();
+        assert!(index.contains("'/api/graphql'"));
+        assert!(index.contains("href=\"/api/graphiql.css\""));
+        assert!(index.contains("{\"Authorization\":\"Bearer \\u003c/script>\"}"));
+        assert!(!index.contains("{{"));
+    }
+}
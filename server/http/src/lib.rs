@@ -4,16 +4,31 @@ extern crate graph_graphql;
 extern crate graphql_parser;
 extern crate http;
 extern crate hyper;
+extern crate net2;
 extern crate serde;
+#[cfg(unix)]
+extern crate tokio_uds;
 
+mod api_key;
+mod auth;
+mod cache;
+mod config;
 mod request;
 mod response;
 mod server;
 mod service;
+mod usage;
+mod warm_up;
 
+pub use self::api_key::{ApiKeys, API_KEY_HEADER};
+pub use self::auth::{auth_token, verify_auth_token};
+pub use self::cache::QueryCache;
+pub use self::config::GraphQLServerConfig;
 pub use self::request::GraphQLRequest;
-pub use self::response::GraphQLResponse;
+pub use self::response::{CachePolicy, GraphQLResponse};
 pub use self::server::GraphQLServer;
 pub use self::service::{GraphQLService, GraphQLServiceResponse};
+pub use self::usage::DeploymentUsageTracker;
+pub use self::warm_up::{WarmUp, WARM_UP_RETRY_AFTER};
 
 pub mod test_utils;
@@ -1,4 +1,3 @@
-use futures::sync::oneshot;
 use graph::serde_json;
 use graphql_parser;
 use hyper::Chunk;
@@ -13,89 +12,152 @@ pub struct GraphQLRequest {
 }
 
 impl GraphQLRequest {
-    /// Creates a new GraphQLRequest future based on an HTTP request and a result sender.
+    /// Creates a new GraphQLRequest future based on an HTTP request body and a schema.
     pub fn new(body: Chunk, schema: Option<Schema>) -> Self {
         GraphQLRequest { body, schema }
     }
 }
 
 impl Future for GraphQLRequest {
-    type Item = (Query, oneshot::Receiver<QueryResult>);
+    type Item = Query;
     type Error = GraphQLServerError;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        // Fail if no schema is available
-        let schema = self
-            .schema
-            .clone()
-            .ok_or(GraphQLServerError::InternalError(
-                "No schema available to query".to_string(),
-            ))?;
+        // Fail if no subgraph is being served, e.g. because it was removed
+        let schema = self.schema.clone().ok_or(GraphQLServerError::NotFound(
+            "No schema available to query".to_string(),
+        ))?;
 
         // Parse request body as JSON
         let json: serde_json::Value = serde_json::from_slice(&self.body)
             .map_err(|e| GraphQLServerError::ClientError(format!("{}", e)))?;
 
-        // Ensure the JSON data is an object
-        let obj = json
-            .as_object()
-            .ok_or(GraphQLServerError::ClientError(String::from(
-                "Request data is not an object",
-            )))?;
-
-        // Ensure the JSON data has a "query" field
-        let query_value = obj
-            .get("query")
-            .ok_or(GraphQLServerError::ClientError(String::from(
-                "The \"query\" field missing in request data",
-            )))?;
-
-        // Ensure the "query" field is a string
-        let query_string = query_value.as_str().ok_or(GraphQLServerError::ClientError(
-            String::from("The\"query\" field is not a string"),
+        parse_query(&json, schema).map(Async::Ready)
+    }
+}
+
+/// Future for the queries of a batched HTTP request, in the order in which
+/// they were sent. Invalid queries fail on their own, without failing the
+/// whole batch.
+pub struct GraphQLBatchRequest {
+    body: Chunk,
+    schema: Option<Schema>,
+}
+
+impl GraphQLBatchRequest {
+    /// Creates a new GraphQLBatchRequest future based on an HTTP request body
+    /// and a schema.
+    pub fn new(body: Chunk, schema: Option<Schema>) -> Self {
+        GraphQLBatchRequest { body, schema }
+    }
+
+    /// Returns true if an HTTP request body holds a batch of queries, i.e. a
+    /// JSON array as sent by Apollo's batch link, rather than a single query.
+    pub fn is_batch(body: &[u8]) -> bool {
+        body.iter()
+            .find(|byte| !(**byte as char).is_whitespace())
+            .map_or(false, |byte| *byte == b'[')
+    }
+}
+
+impl Future for GraphQLBatchRequest {
+    type Item = Vec<Result<Query, GraphQLServerError>>;
+    type Error = GraphQLServerError;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        // Fail if no subgraph is being served, e.g. because it was removed
+        let schema = self.schema.clone().ok_or(GraphQLServerError::NotFound(
+            "No schema available to query".to_string(),
         ))?;
 
-        // Parse the "query" field of the JSON body
-        let document = graphql_parser::parse_query(query_string)
-            .map_err(|e| GraphQLServerError::from(QueryError::from(e)))?;
-
-        // Parse the "variables" field of the JSON body, if present
-        let variables = match obj.get("variables") {
-            None | Some(serde_json::Value::Null) => Ok(None),
-            Some(variables @ serde_json::Value::Object(_)) => {
-                serde_json::from_value(variables.clone())
-                    .map_err(|e| GraphQLServerError::ClientError(format!("{}", e)))
-                    .map(|v| Some(v))
-            }
-            _ => Err(GraphQLServerError::ClientError(format!(
-                "Invalid query variables provided"
-            ))),
-        }?;
-
-        // Create a one-shot channel to allow another part of the system
-        // to notify the service when the query has completed
-        let (sender, receiver) = oneshot::channel();
-
-        Ok(Async::Ready((
-            Query {
-                document,
-                variables,
-                schema: schema,
-                result_sender: sender,
-            },
-            receiver,
-        )))
+        // Parse request body as a JSON array
+        let batch: Vec<serde_json::Value> = serde_json::from_slice(&self.body)
+            .map_err(|e| GraphQLServerError::ClientError(format!("{}", e)))?;
+        if batch.is_empty() {
+            return Err(GraphQLServerError::ClientError(String::from(
+                "The batch contains no queries",
+            )));
+        }
+
+        Ok(Async::Ready(
+            batch
+                .iter()
+                .map(|json| parse_query(json, schema.clone()))
+                .collect(),
+        ))
     }
 }
 
+/// Parses a query from the JSON object it was sent as.
+fn parse_query(json: &serde_json::Value, schema: Schema) -> Result<Query, GraphQLServerError> {
+    // Ensure the JSON data is an object
+    let obj = json
+        .as_object()
+        .ok_or(GraphQLServerError::ClientError(String::from(
+            "Request data is not an object",
+        )))?;
+
+    // Ensure the JSON data has a "query" field
+    let query_value = obj
+        .get("query")
+        .ok_or(GraphQLServerError::ClientError(String::from(
+            "The \"query\" field missing in request data",
+        )))?;
+
+    // Ensure the "query" field is a string
+    let query_string = query_value.as_str().ok_or(GraphQLServerError::ClientError(
+        String::from("The\"query\" field is not a string"),
+    ))?;
+
+    // Parse the "query" field of the JSON body
+    let document = graphql_parser::parse_query(query_string)
+        .map_err(|e| GraphQLServerError::from(QueryError::from(e)))?;
+
+    // Parse the "variables" field of the JSON body, if present
+    let variables = match obj.get("variables") {
+        None | Some(serde_json::Value::Null) => Ok(None),
+        Some(variables @ serde_json::Value::Object(_)) => {
+            serde_json::from_value(variables.clone())
+                .map_err(|e| GraphQLServerError::ClientError(format!("{}", e)))
+                .map(|v| Some(v))
+        }
+        _ => Err(GraphQLServerError::ClientError(format!(
+            "Invalid query variables provided"
+        ))),
+    }?;
+
+    // Parse the "operationName" field of the JSON body, if present
+    let operation_name = match obj.get("operationName") {
+        None | Some(serde_json::Value::Null) => None,
+        Some(serde_json::Value::String(name)) => Some(name.clone()),
+        _ => {
+            return Err(GraphQLServerError::ClientError(String::from(
+                "The \"operationName\" field is not a string",
+            )))
+        }
+    };
+
+    Ok(Query {
+        document,
+        variables,
+        operation_name,
+        schema: schema,
+        role: None,
+        explain: false,
+        trace: false,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use graphql_parser;
+    use graphql_parser::query as q;
     use hyper;
+    use std::collections::BTreeMap;
 
     use graph::prelude::*;
 
-    use super::GraphQLRequest;
+    use super::{GraphQLBatchRequest, GraphQLRequest};
 
     const EXAMPLE_SCHEMA: &'static str = "type Query { users: [User!] }";
 
@@ -153,7 +215,7 @@ mod tests {
             hyper::Chunk::from("{\"query\": \"{ user { name } }\"}"),
             Some(schema),
         );
-        let (query, _) = request.wait().expect("Should accept valid queries");
+        let query = request.wait().expect("Should accept valid queries");
         assert_eq!(
             query.document,
             graphql_parser::parse_query("{ user { name } }").unwrap()
@@ -176,7 +238,7 @@ mod tests {
             ),
             Some(schema),
         );
-        let (query, _) = request.wait().expect("Should accept null variables");
+        let query = request.wait().expect("Should accept null variables");
 
         let expected_query = graphql_parser::parse_query("{ user { name } }").unwrap();
         assert_eq!(query.document, expected_query);
@@ -218,7 +280,7 @@ mod tests {
             ),
             Some(schema),
         );
-        let (query, _) = request.wait().expect("Should accept valid queries");
+        let query = request.wait().expect("Should accept valid queries");
 
         let expected_query = graphql_parser::parse_query("{ user { name } }").unwrap();
         let mut expected_variables = QueryVariables::new();
@@ -227,4 +289,93 @@ mod tests {
         assert_eq!(query.document, expected_query);
         assert_eq!(query.variables, Some(expected_variables));
     }
+
+    #[test]
+    fn parses_operation_names() {
+        let schema = Schema {
+            id: "test".to_string(),
+            document: graphql_parser::parse_schema(EXAMPLE_SCHEMA).unwrap(),
+        };
+        let request = GraphQLRequest::new(
+            hyper::Chunk::from(
+                "\
+                 {\
+                 \"query\": \"query a { user { name } } query b { user { id } }\", \
+                 \"operationName\": \"b\" \
+                 }",
+            ),
+            Some(schema.clone()),
+        );
+        let query = request.wait().expect("Should accept operation names");
+        assert_eq!(query.operation_name, Some(String::from("b")));
+
+        let request = GraphQLRequest::new(
+            hyper::Chunk::from("{\"query\": \"{ user { name } }\", \"operationName\": 5}"),
+            Some(schema),
+        );
+        request
+            .wait()
+            .expect_err("Should reject non-string operation names");
+    }
+
+    #[test]
+    fn parses_variables_of_all_types() {
+        let schema = Schema {
+            id: "test".to_string(),
+            document: graphql_parser::parse_schema(EXAMPLE_SCHEMA).unwrap(),
+        };
+        let request = GraphQLRequest::new(
+            hyper::Chunk::from(
+                "\
+                 {\
+                 \"query\": \"{ user { name } }\", \
+                 \"variables\": { \"int\": 5, \"float\": 1.5, \"list\": [true, null], \
+                 \"object\": { \"name\": \"John\" } } \
+                 }",
+            ),
+            Some(schema),
+        );
+        let query = request.wait().expect("Should accept valid queries");
+        let variables = query.variables.expect("Should parse variables");
+
+        let mut object = BTreeMap::new();
+        object.insert("name".to_string(), q::Value::String("John".to_string()));
+
+        assert_eq!(*variables["int"], q::Value::Int(q::Number::from(5)));
+        assert_eq!(*variables["float"], q::Value::Float(1.5));
+        assert_eq!(
+            *variables["list"],
+            q::Value::List(vec![q::Value::Boolean(true), q::Value::Null])
+        );
+        assert_eq!(*variables["object"], q::Value::Object(object));
+    }
+
+    #[test]
+    fn parses_batches_in_order() {
+        let schema = Schema {
+            id: "test".to_string(),
+            document: graphql_parser::parse_schema(EXAMPLE_SCHEMA).unwrap(),
+        };
+        let body = "  [{\"query\": \"{ users { id } }\"}, {}, {\"query\": \"{ users { name } }\"}]";
+        assert!(GraphQLBatchRequest::is_batch(body.as_bytes()));
+        assert!(!GraphQLBatchRequest::is_batch(b"{\"query\": \"{ users { id } }\"}"));
+
+        let queries = GraphQLBatchRequest::new(hyper::Chunk::from(body), Some(schema.clone()))
+            .wait()
+            .expect("Should accept batches");
+        assert_eq!(queries.len(), 3);
+        assert_eq!(
+            queries[0].as_ref().unwrap().document,
+            graphql_parser::parse_query("{ users { id } }").unwrap()
+        );
+        assert!(queries[1].is_err());
+        assert_eq!(
+            queries[2].as_ref().unwrap().document,
+            graphql_parser::parse_query("{ users { name } }").unwrap()
+        );
+
+        GraphQLBatchRequest::new(hyper::Chunk::from("[]"), Some(schema))
+            .wait()
+            .expect_err("Should reject empty batches");
+    }
 }
@@ -3,21 +3,115 @@ use graph::tokio::prelude::*;
 use http::status::StatusCode;
 use hyper::{Body, Response};
 use serde::ser::*;
+use std::collections::BTreeMap;
 
 use graph::components::server::GraphQLServerError;
-use graph::data::query::QueryResult;
+use graph::data::query::{BlockConstraint, QueryError, QueryResult, QueryTarget};
 use graph_graphql::prelude::SerializableValue;
 
+use config::GraphQLServerConfig;
+
+/// Whether and for how long clients and CDNs may cache a response.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CachePolicy {
+    /// The response may change at any time and must not be cached.
+    NoStore,
+    /// The response will never change and may be cached for `max_age` seconds.
+    Immutable { max_age: u64 },
+}
+
+impl CachePolicy {
+    /// Determines the cache policy for a query against `target`. Only results
+    /// for blocks that are older than the reorg threshold can never change.
+    ///
+    /// A target is only at a block if every top-level field of the query is
+    /// pinned to it, as `block_constraint` rejects queries whose fields are
+    /// at different blocks; a query that reads any field at the latest block
+    /// has a target without a block and is never immutable.
+    pub fn for_target(
+        target: &QueryTarget,
+        latest_block_number: Option<u64>,
+        config: &GraphQLServerConfig,
+    ) -> Self {
+        match (&target.block, latest_block_number) {
+            (Some(BlockConstraint::Number(number)), Some(latest))
+                if number.saturating_add(config.reorg_threshold) < latest =>
+            {
+                CachePolicy::Immutable {
+                    max_age: config.immutable_max_age,
+                }
+            }
+            _ => CachePolicy::NoStore,
+        }
+    }
+
+    fn header_value(&self) -> String {
+        match self {
+            CachePolicy::NoStore => String::from("no-store"),
+            CachePolicy::Immutable { max_age } => format!("public, max-age={}", max_age),
+        }
+    }
+}
+
 /// Future for HTTP responses to GraphQL query requests.
 pub struct GraphQLResponse {
     result: Result<QueryResult, GraphQLServerError>,
+    cache_policy: CachePolicy,
+    query_hash: Option<String>,
+    query_complexity: Option<u64>,
+    retry_after: Option<u64>,
 }
 
 impl GraphQLResponse {
     /// Creates a new GraphQLResponse future based on the result generated by
-    /// running a query.
+    /// running a query. The response must not be cached.
     pub fn new(result: Result<QueryResult, GraphQLServerError>) -> Self {
-        GraphQLResponse { result }
+        Self::with_cache_policy(result, CachePolicy::NoStore)
+    }
+
+    /// Creates a new GraphQLResponse future that may be cached according to
+    /// `cache_policy` unless running the query failed.
+    pub fn with_cache_policy(
+        result: Result<QueryResult, GraphQLServerError>,
+        cache_policy: CachePolicy,
+    ) -> Self {
+        GraphQLResponse {
+            result,
+            cache_policy,
+            query_hash: None,
+            query_complexity: None,
+            retry_after: None,
+        }
+    }
+
+    /// Includes the hash of the normalized query in the response, both in
+    /// the `extensions` of the body and in the `X-GraphQL-Query-Hash`
+    /// header, so that caches in front of the server can key on it.
+    pub fn with_query_hash(mut self, query_hash: String) -> Self {
+        self.query_hash = Some(query_hash);
+        self
+    }
+
+    /// Includes the estimated complexity of the query in the `extensions`
+    /// of the body, so that clients can tell how close a query comes to the
+    /// maximum the server accepts.
+    pub fn with_query_complexity(mut self, complexity: u64) -> Self {
+        self.query_complexity = Some(complexity);
+        self
+    }
+
+    /// Asks the client to retry the query after `seconds` through the
+    /// `Retry-After` header.
+    pub fn with_retry_after(mut self, seconds: u64) -> Self {
+        self.retry_after = Some(seconds);
+        self
+    }
+
+    fn cache_policy_from_result(&self) -> CachePolicy {
+        match self.result {
+            Ok(QueryResult { errors: None, .. }) => self.cache_policy,
+            _ => CachePolicy::NoStore,
+        }
     }
 
     fn status_code_from_result(&self) -> StatusCode {
@@ -30,9 +124,14 @@ impl GraphQLResponse {
                 }
             }
             Err(ref e) => match e {
+                &GraphQLServerError::QueryError(QueryError::Timeout)
+                | &GraphQLServerError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
                 &GraphQLServerError::ClientError(_) | &GraphQLServerError::QueryError(_) => {
                     StatusCode::BAD_REQUEST
                 }
+                &GraphQLServerError::NotFound(_) => StatusCode::NOT_FOUND,
+                &GraphQLServerError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+                &GraphQLServerError::Forbidden(_) => StatusCode::FORBIDDEN,
                 _ => StatusCode::INTERNAL_SERVER_ERROR,
             },
         }
@@ -56,6 +155,25 @@ impl Serialize for GraphQLResponse {
                     map.serialize_entry("errors", errors)?;
                 }
 
+                let mut extensions = BTreeMap::new();
+                if let Some(ref query_hash) = self.query_hash {
+                    extensions.insert("queryHash", serde_json::Value::from(query_hash.as_str()));
+                }
+                if let Some(complexity) = self.query_complexity {
+                    extensions.insert("complexity", serde_json::Value::from(complexity));
+                }
+                if let Some(ref plan) = result.plan {
+                    let plan = serde_json::to_value(plan).map_err(S::Error::custom)?;
+                    extensions.insert("plan", plan);
+                }
+                if let Some(ref trace) = result.trace {
+                    let trace = serde_json::to_value(trace).map_err(S::Error::custom)?;
+                    extensions.insert("tracing", trace);
+                }
+                if !extensions.is_empty() {
+                    map.serialize_entry("extensions", &extensions)?;
+                }
+
                 map.end()
             }
             Err(ref e) => {
@@ -76,10 +194,69 @@ impl Future for GraphQLResponse {
         let status_code = self.status_code_from_result();
         let json =
             serde_json::to_string(self).expect("Failed to serialize GraphQL response to JSON");
-        let response = Response::builder()
+        let mut builder = Response::builder();
+        builder
             .status(status_code)
             .header("Access-Control-Allow-Origin", "*")
-            .header("Access-Control-Allow-Headers", "Content-Type")
+            .header("Access-Control-Allow-Headers", "Content-Type, Authorization")
+            .header("Cache-Control", self.cache_policy_from_result().header_value())
+            .header("Vary", "Authorization");
+        if let Some(ref query_hash) = self.query_hash {
+            builder.header("X-GraphQL-Query-Hash", query_hash.as_str());
+        }
+        if let Some(retry_after) = self.retry_after {
+            builder.header("Retry-After", retry_after.to_string().as_str());
+        }
+        let response = builder.body(Body::from(json)).unwrap();
+        Ok(Async::Ready(response))
+    }
+}
+
+/// Future for the HTTP response to a batch of GraphQL queries, which holds
+/// the responses to the queries of the batch in order.
+pub struct GraphQLBatchResponse {
+    responses: Vec<GraphQLResponse>,
+}
+
+impl GraphQLBatchResponse {
+    pub fn new(responses: Vec<GraphQLResponse>) -> Self {
+        GraphQLBatchResponse { responses }
+    }
+
+    /// Only batches whose responses may all be cached may be cached, and
+    /// only for as long as every one of them may be.
+    fn cache_policy(&self) -> CachePolicy {
+        let max_ages = self
+            .responses
+            .iter()
+            .map(|response| match response.cache_policy_from_result() {
+                CachePolicy::Immutable { max_age } => Some(max_age),
+                CachePolicy::NoStore => None,
+            })
+            .collect::<Option<Vec<_>>>();
+
+        match max_ages.and_then(|max_ages| max_ages.into_iter().min()) {
+            Some(max_age) => CachePolicy::Immutable { max_age },
+            None => CachePolicy::NoStore,
+        }
+    }
+}
+
+impl Future for GraphQLBatchResponse {
+    type Item = Response<Body>;
+    type Error = GraphQLServerError;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        // Failed queries are reported in the responses to them, so the batch
+        // as a whole succeeds
+        let json = serde_json::to_string(&self.responses)
+            .expect("Failed to serialize GraphQL batch response to JSON");
+        let response = Response::builder()
+            .status(StatusCode::OK)
+            .header("Access-Control-Allow-Origin", "*")
+            .header("Access-Control-Allow-Headers", "Content-Type, Authorization")
+            .header("Cache-Control", self.cache_policy().header_value())
+            .header("Vary", "Authorization")
             .body(Body::from(json))
             .unwrap();
         Ok(Async::Ready(response))
@@ -88,10 +265,13 @@ impl Future for GraphQLResponse {
 
 #[cfg(test)]
 mod tests {
-    use super::GraphQLResponse;
+    use super::{CachePolicy, GraphQLBatchResponse, GraphQLResponse};
+    use config::GraphQLServerConfig;
     use futures::sync::oneshot;
     use graph::components::server::GraphQLServerError;
     use graph::prelude::*;
+    use graph::serde_json;
+    use graph_graphql::prelude::block_constraint;
     use graphql_parser;
     use http::status::StatusCode;
     use std::collections::BTreeMap;
@@ -122,6 +302,181 @@ mod tests {
         test_utils::assert_error_response(response, StatusCode::BAD_REQUEST);
     }
 
+    #[test]
+    fn generates_404_for_missing_subgraphs() {
+        let error = GraphQLServerError::NotFound(String::from("foo"));
+        let future = GraphQLResponse::new(Err(error));
+        let response = future.wait().expect("Should generate a response");
+        test_utils::assert_error_response(response, StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn generates_401_for_invalid_auth_tokens() {
+        let error = GraphQLServerError::Unauthorized(String::from("foo"));
+        let future = GraphQLResponse::new(Err(error));
+        let response = future.wait().expect("Should generate a response");
+        test_utils::assert_error_response(response, StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn generates_503_for_timeouts() {
+        let error = GraphQLServerError::from(QueryError::Timeout);
+        let future = GraphQLResponse::new(Err(error));
+        let response = future.wait().expect("Should generate a response");
+        test_utils::assert_error_response(response, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[test]
+    fn asks_clients_to_retry_while_unavailable() {
+        let error = GraphQLServerError::Unavailable(String::from("Starting up"));
+        let future = GraphQLResponse::new(Err(error)).with_retry_after(5);
+        let response = future.wait().expect("Should generate a response");
+        assert_eq!(response.headers()["Retry-After"], "5");
+        test_utils::assert_error_response(response, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[test]
+    fn only_caches_successful_results() {
+        let policy = CachePolicy::Immutable { max_age: 60 };
+        let data = graphql_parser::query::Value::Object(BTreeMap::new());
+
+        let future = GraphQLResponse::with_cache_policy(Ok(QueryResult::new(Some(data))), policy);
+        let response = future.wait().expect("Should generate a response");
+        assert_eq!(
+            response.headers().get("Cache-Control").unwrap(),
+            "public, max-age=60"
+        );
+
+        let error = GraphQLServerError::ClientError(String::from("foo"));
+        let future = GraphQLResponse::with_cache_policy(Err(error), policy);
+        let response = future.wait().expect("Should generate a response");
+        assert_eq!(response.headers().get("Cache-Control").unwrap(), "no-store");
+    }
+
+    #[test]
+    fn responds_to_batches_with_an_array() {
+        let data = graphql_parser::query::Value::Object(BTreeMap::new());
+        let response = |max_age| {
+            GraphQLResponse::with_cache_policy(
+                Ok(QueryResult::new(Some(data.clone()))),
+                CachePolicy::Immutable { max_age },
+            )
+        };
+
+        let future = GraphQLBatchResponse::new(vec![response(60), response(30)]);
+        let response = future.wait().expect("Should generate a response");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get("Cache-Control").unwrap(),
+            "public, max-age=30"
+        );
+
+        let error = GraphQLServerError::ClientError(String::from("foo"));
+        let future = GraphQLBatchResponse::new(vec![
+            GraphQLResponse::new(Ok(QueryResult::new(Some(data.clone())))),
+            GraphQLResponse::new(Err(error)),
+        ]);
+        let response = future.wait().expect("Should generate a response");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get("Cache-Control").unwrap(), "no-store");
+        let body = response.into_body().concat2().wait().unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(json[0]["data"].is_object());
+        assert_eq!(json[1]["errors"][0]["message"], "foo");
+    }
+
+    #[test]
+    fn caches_only_blocks_older_than_the_reorg_threshold() {
+        let config = GraphQLServerConfig {
+            reorg_threshold: 10,
+            immutable_max_age: 60,
+            ..Default::default()
+        };
+        let subgraph = SubgraphTarget::Deployment(String::from("subgraph"));
+        let live = QueryTarget::new(subgraph.clone());
+        let old = QueryTarget::at_block(subgraph.clone(), BlockConstraint::Number(80));
+        let recent = QueryTarget::at_block(subgraph, BlockConstraint::Number(95));
+
+        assert_eq!(
+            CachePolicy::for_target(&old, Some(100), &config),
+            CachePolicy::Immutable { max_age: 60 }
+        );
+        assert_eq!(
+            CachePolicy::for_target(&recent, Some(100), &config),
+            CachePolicy::NoStore
+        );
+        assert_eq!(
+            CachePolicy::for_target(&old, None, &config),
+            CachePolicy::NoStore
+        );
+        assert_eq!(
+            CachePolicy::for_target(&live, Some(100), &config),
+            CachePolicy::NoStore
+        );
+    }
+
+    #[test]
+    fn caches_only_queries_with_all_fields_at_a_finalized_block() {
+        let config = GraphQLServerConfig {
+            reorg_threshold: 10,
+            immutable_max_age: 60,
+            ..Default::default()
+        };
+        let policy = |document: &str| {
+            let query = Query {
+                schema: Schema {
+                    id: String::from("subgraph"),
+                    document: graphql_parser::parse_schema("type Query { users: [User!]! }")
+                        .unwrap(),
+                },
+                document: graphql_parser::parse_query(document).unwrap(),
+                variables: None,
+                operation_name: None,
+                role: None,
+                explain: false,
+                trace: false,
+            };
+            block_constraint(&query).ok().map(|block| {
+                let target = QueryTarget {
+                    subgraph: SubgraphTarget::Deployment(String::from("subgraph")),
+                    block,
+                };
+                CachePolicy::for_target(&target, Some(100), &config)
+            })
+        };
+
+        for document in &[
+            "{ a: users(block: { number: 80 }) { id } b: users(block: { number: 80 }) { id } }",
+            "{ users(block: { number: 80 }) { id } __typename }",
+        ] {
+            assert_eq!(policy(document), Some(CachePolicy::Immutable { max_age: 60 }));
+        }
+        assert_eq!(policy("{ users { id } }"), Some(CachePolicy::NoStore));
+
+        // Queries that read any field at the latest block are rejected
+        // rather than cached
+        for document in &[
+            "{ a: users(block: { number: 80 }) { id } b: users { id } }",
+            "{ users(block: { number: 80 }) { id } _meta { block { number } } }",
+            "{ a: users(block: { number: 80 }) { id } b: users(block: { number: 95 }) { id } }",
+        ] {
+            assert_eq!(policy(document), None, "{} should be rejected", document);
+        }
+    }
+
+    #[test]
+    fn includes_the_query_hash() {
+        let data = graphql_parser::query::Value::Object(BTreeMap::new());
+        let future = GraphQLResponse::new(Ok(QueryResult::new(Some(data))))
+            .with_query_hash(String::from("abc"));
+        let response = future.wait().expect("Should generate a response");
+        assert_eq!(response.headers().get("X-GraphQL-Query-Hash").unwrap(), "abc");
+
+        let body = response.into_body().concat2().wait().unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["extensions"]["queryHash"], "abc");
+    }
+
     #[test]
     fn generates_200_for_query_results() {
         let data = graphql_parser::query::Value::Object(BTreeMap::new());
@@ -234,6 +589,73 @@ mod tests {
         assert_eq!(column, 1);
     }
 
+    #[test]
+    fn includes_the_location_and_path_of_field_errors() {
+        let mut result = QueryResult::new(None);
+        result.add_error(QueryError::FieldError(
+            graphql_parser::Pos { line: 3, column: 5 },
+            vec![
+                PathSegment::Key(String::from("users")),
+                PathSegment::Index(1),
+                PathSegment::Key(String::from("friends")),
+            ],
+            QueryExecutionError::NamedTypeError(String::from("Friend")),
+        ));
+        let future = GraphQLResponse::new(Ok(result));
+        let response = future.wait().expect("Should generate a response");
+        let errors = test_utils::assert_error_response(response, StatusCode::BAD_REQUEST);
+
+        let expected: serde_json::Value = serde_json::from_str(
+            "{
+                \"message\": \"Failed to resolve named type: Friend\",
+                \"locations\": [{ \"line\": 3, \"column\": 5 }],
+                \"path\": [\"users\", 1, \"friends\"]
+            }",
+        ).unwrap();
+        assert_eq!(errors, vec![expected]);
+    }
+
+    #[test]
+    fn includes_the_earliest_block_of_blocks_out_of_range() {
+        let error = QueryError::from(QueryExecutionError::BlockOutOfRangeError(5, 12));
+        let future = GraphQLResponse::new(Err(GraphQLServerError::from(error)));
+        let response = future.wait().expect("Should generate a response");
+        let errors = test_utils::assert_error_response(response, StatusCode::BAD_REQUEST);
+
+        let expected: serde_json::Value = serde_json::from_str(
+            "{
+                \"message\": \"Block #5 is not available anymore; \
+                              the earliest block that can be queried is #12\",
+                \"extensions\": { \"code\": \"BLOCK_OUT_OF_RANGE\", \"earliestBlock\": 12 }
+            }",
+        ).unwrap();
+        assert_eq!(errors, vec![expected]);
+    }
+
+    #[test]
+    fn includes_the_canonical_block_of_reorged_out_blocks() {
+        let error = QueryError::from(QueryExecutionError::BlockReorgedOutError(
+            String::from("0xaa"),
+            7,
+            String::from("0xbb"),
+        ));
+        let future = GraphQLResponse::new(Err(GraphQLServerError::from(error)));
+        let response = future.wait().expect("Should generate a response");
+        let errors = test_utils::assert_error_response(response, StatusCode::BAD_REQUEST);
+
+        let expected: serde_json::Value = serde_json::from_str(
+            "{
+                \"message\": \"Block 0xaa was reorged out of the chain; block #7 is now 0xbb\",
+                \"extensions\": {
+                    \"code\": \"REORGED_OUT\",
+                    \"blockNumber\": 7,
+                    \"canonicalBlockHash\": \"0xbb\"
+                }
+            }",
+        ).unwrap();
+        assert_eq!(errors, vec![expected]);
+    }
+
     #[test]
     fn generates_valid_json_for_internal_error() {
         let err = GraphQLServerError::InternalError(String::from("Something went wrong"));
@@ -1,71 +1,88 @@
 use futures::sync::mpsc::{channel, Receiver, Sender};
 use hyper;
 use hyper::Server;
+use net2::TcpBuilder;
 
-use std::error::Error;
-use std::fmt;
-use std::net::{Ipv4Addr, SocketAddrV4};
+use std::collections::BTreeMap;
+use std::io;
+use std::net::SocketAddr;
 use std::sync::Mutex;
+#[cfg(unix)]
+use std::ffi::OsString;
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+#[cfg(unix)]
+use std::{fs, path::Path, process};
+#[cfg(unix)]
+use tokio_uds::UnixListener;
 
 use graph::components::store::StoreEvent;
-use graph::data::query::Query;
 use graph::data::schema::Schema;
 use graph::prelude::{GraphQLServer as GraphQLServerTrait, *};
-use graph_graphql::prelude::api_schema;
+use graph::tokio::net::TcpListener;
+use graph::tokio::reactor::Handle;
+use graph::util::semaphore::Semaphore;
+use graph_graphql::prelude::{api_schema, stitch_schemas, validate_schema};
+
+use api_key::ApiKeys;
+use cache::QueryCache;
+use config::GraphQLServerConfig;
 use service::GraphQLService;
+use usage::DeploymentUsageTracker;
+use warm_up::WarmUp;
 
-/// Errors that may occur when starting the server.
-#[derive(Debug)]
-pub enum GraphQLServeError {
-    OrphanError,
-    BindError(hyper::Error),
-}
-
-impl Error for GraphQLServeError {
-    fn description(&self) -> &str {
-        "Failed to start the server"
-    }
-
-    fn cause(&self) -> Option<&Error> {
-        None
-    }
-}
-
-impl fmt::Display for GraphQLServeError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "OrphanError: No component set up to handle the queries")
-    }
-}
-
-impl From<hyper::Error> for GraphQLServeError {
-    fn from(err: hyper::Error) -> Self {
-        GraphQLServeError::BindError(err)
-    }
-}
+/// Maximum number of queries that are executed at the same time.
+const MAX_CONCURRENT_QUERIES: usize = 100;
 
 /// A GraphQL server based on Hyper.
-pub struct GraphQLServer {
+pub struct GraphQLServer<Q> {
     logger: slog::Logger,
-    query_sink: Option<Sender<Query>>,
+    config: Arc<GraphQLServerConfig>,
+    query_runner: Arc<Q>,
+    query_semaphore: Semaphore,
     schema_event_sink: Sender<SchemaEvent>,
     store_event_sink: Sender<StoreEvent>,
-    schema: Arc<Mutex<Option<Schema>>>,
+    /// The API schemas of the subgraphs served, by subgraph ID.
+    schemas: Arc<Mutex<BTreeMap<String, Schema>>>,
+    api_keys: Option<ApiKeys>,
+    usage_tracker: Arc<Mutex<Option<DeploymentUsageTracker>>>,
+    query_cache: Option<QueryCache>,
+    warm_up: Option<WarmUp>,
 }
 
-impl GraphQLServer {
-    /// Creates a new GraphQL server.
-    pub fn new(logger: &slog::Logger) -> Self {
+impl<Q> GraphQLServer<Q>
+where
+    Q: QueryRunner + Send + Sync + 'static,
+{
+    /// Creates a new GraphQL server that runs queries with `query_runner`.
+    pub fn new(
+        logger: &slog::Logger,
+        config: GraphQLServerConfig,
+        query_runner: Arc<Q>,
+    ) -> Self {
         // Create channels for handling incoming schema and store events.
         let (store_sink, store_stream) = channel(100);
         let (schema_event_sink, schema_event_stream) = channel(100);
 
+        let query_cache = if config.query_cache_size > 0 {
+            Some(QueryCache::new(config.query_cache_size))
+        } else {
+            None
+        };
+
         // Create a new GraphQL server
         let mut server = GraphQLServer {
             logger: logger.new(o!("component" => "GraphQLServer")),
-            query_sink: None,
+            config: Arc::new(config),
+            query_runner,
+            query_semaphore: Semaphore::new(MAX_CONCURRENT_QUERIES),
             schema_event_sink,
             store_event_sink: store_sink,
-            schema: Arc::new(Mutex::new(None)),
+            schemas: Arc::new(Mutex::new(BTreeMap::new())),
+            api_keys: None,
+            usage_tracker: Arc::new(Mutex::new(None)),
+            query_cache,
+            warm_up: None,
         };
 
         // Spawn tasks to handle incoming schema and store events.
@@ -76,45 +93,186 @@ impl GraphQLServer {
         server
     }
 
+    /// Checks the API keys of queries against `store` and attributes their
+    /// usage to the keys.
+    pub fn with_api_key_store(mut self, store: Arc<Mutex<ApiKeyStore + Send>>) -> Self {
+        self.api_keys = Some(ApiKeys::new(
+            &self.logger,
+            store,
+            self.config.require_api_key,
+        ));
+        self
+    }
+
+    /// Records in `store` when the deployments served are queried.
+    pub fn with_deployment_usage_store(
+        self,
+        store: Arc<Mutex<DeploymentUsageStore + Send>>,
+    ) -> Self {
+        let usage_tracker = DeploymentUsageTracker::new(&self.logger, store);
+        *self.usage_tracker.lock().unwrap() = Some(usage_tracker);
+        self
+    }
+
+    /// Answers queries for subgraphs that are not served yet with `503
+    /// Service Unavailable` until `warm_up` is finished, so that the server
+    /// can start listening before the subgraphs it serves are loaded.
+    pub fn with_warm_up(mut self, warm_up: WarmUp) -> Self {
+        self.warm_up = Some(warm_up);
+        self
+    }
+
     /// Handle incoming schema events.
     fn handle_schema_events(&mut self, stream: Receiver<SchemaEvent>) {
         let logger = self.logger.clone();
-        let schema = self.schema.clone();
+        let config = self.config.clone();
+        let schemas = self.schemas.clone();
+        let usage_tracker = self.usage_tracker.clone();
 
         tokio::spawn(stream.for_each(move |event| {
             info!(logger, "Received schema event");
 
-            if let SchemaEvent::SchemaAdded(new_schema) = event {
-                let mut schema = schema.lock().unwrap();
-                let derived_schema = match api_schema(&new_schema.document) {
-                    Ok(document) => Schema {
-                        id: new_schema.id.clone(),
-                        document,
-                    },
-                    Err(e) => return Ok(error!(logger, "error deriving schema {}", e)),
-                };
-                *schema = Some(derived_schema);
-            } else {
-                panic!("schema removal is yet not supported")
+            match event {
+                SchemaEvent::SchemaAdded(new_schema) | SchemaEvent::SchemaUpdated(new_schema) => {
+                    // Reject schemas that could not be queried correctly,
+                    // listing everything that is wrong with them
+                    if let Err(errors) = validate_schema(&new_schema.document) {
+                        let problems: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+                        return Ok(error!(logger, "Rejected invalid schema";
+                                         "id" => &new_schema.id,
+                                         "problems" => problems.join("; ")));
+                    }
+
+                    if let Some(ref usage_tracker) = *usage_tracker.lock().unwrap() {
+                        usage_tracker.track(&new_schema.id);
+                    }
+
+                    let derived_schema = match api_schema(&new_schema.document) {
+                        Ok(document) => Schema {
+                            id: new_schema.id.clone(),
+                            document,
+                        },
+                        Err(e) => return Ok(error!(logger, "error deriving schema {}", e)),
+                    };
+
+                    // Other subgraphs keep being served alongside this one
+                    schemas
+                        .lock()
+                        .unwrap()
+                        .insert(derived_schema.id.clone(), derived_schema);
+                }
+                SchemaEvent::SchemaRemoved(removed_schema) => {
+                    // Queries for the removed subgraph are answered with 404s
+                    // from then on
+                    let removed = schemas.lock().unwrap().remove(&removed_schema.id);
+                    if removed.is_none() {
+                        warn!(logger, "Ignoring removal of a schema that is not served";
+                              "id" => &removed_schema.id);
+                    }
+                }
             }
 
+            update_stitched_schemas(&logger, &config, &mut schemas.lock().unwrap());
             Ok(())
         }));
     }
 
+    /// Returns a function that creates a new GraphQL service for every
+    /// incoming connection. All services share the same concurrency limit.
+    fn new_service(
+        &self,
+    ) -> impl Fn() -> future::FutureResult<GraphQLService<Q>, hyper::Error> + Send + Sync + 'static
+    {
+        let config = self.config.clone();
+        let query_runner = self.query_runner.clone();
+        let query_semaphore = self.query_semaphore.clone();
+        let schemas = self.schemas.clone();
+        let api_keys = self.api_keys.clone();
+        let usage_tracker = self.usage_tracker.lock().unwrap().clone();
+        let query_cache = self.query_cache.clone();
+        let warm_up = self.warm_up.clone();
+        let logger = self.logger.clone();
+
+        move || {
+            let service = GraphQLService::new(
+                config.clone(),
+                schemas.clone(),
+                query_runner.clone(),
+                query_semaphore.clone(),
+                api_keys.clone(),
+                usage_tracker.clone(),
+            ).with_system_schema(&logger);
+            let service = match warm_up {
+                Some(ref warm_up) => service.with_warm_up(warm_up.clone()),
+                None => service,
+            };
+            future::ok(match query_cache {
+                Some(ref query_cache) => service.with_query_cache(query_cache.clone()),
+                None => service,
+            })
+        }
+    }
+
+    /// Creates a new Tokio task that, when spawned, serves GraphQL over a
+    /// unix domain socket at `path`. A stale socket at `path` is replaced,
+    /// but no other kind of file; `mode` sets the permissions of the socket,
+    /// e.g. `0o660`, before anyone can connect to it.
+    #[cfg(unix)]
+    pub fn serve_unix(
+        &mut self,
+        path: &Path,
+        mode: Option<u32>,
+    ) -> Result<Box<Future<Item = (), Error = ()> + Send>, GraphError> {
+        match fs::symlink_metadata(path) {
+            Ok(ref metadata) if metadata.file_type().is_socket() => {
+                fs::remove_file(path).map_err(GraphError::config)?
+            }
+            Ok(_) => {
+                return Err(GraphError::from_message(
+                    ErrorCategory::Config,
+                    format!("{} exists and is not a socket", path.display()),
+                ))
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => return Err(GraphError::config(e)),
+        }
+
+        let listener = match mode {
+            Some(mode) => bind_unix_with_mode(path, mode),
+            None => UnixListener::bind(path),
+        }.map_err(GraphError::config)?;
+
+        info!(self.logger, "Listening for GraphQL queries";
+              "socket" => path.display().to_string());
+
+        let logger = self.logger.clone();
+        let task = Server::builder(listener.incoming())
+            .serve(self.new_service())
+            .map_err(move |e| error!(logger, "Server error"; "error" => format!("{}", e)));
+
+        Ok(Box::new(task))
+    }
+
     // Handle incoming events from the store.
     fn handle_store_events(&mut self, stream: Receiver<StoreEvent>) {
         let logger = self.logger.clone();
+        let query_cache = self.query_cache.clone();
 
         tokio::spawn(stream.for_each(move |event| {
-            info!(logger, "Received store event"; "event" => format!("{:?}",  event));
+            debug!(logger, "Received store event"; "event" => format!("{:?}",  event));
+            if let Some(ref query_cache) = query_cache {
+                query_cache.handle_store_event(&event);
+            }
             Ok(())
         }));
     }
 }
 
-impl GraphQLServerTrait for GraphQLServer {
-    type ServeError = GraphQLServeError;
+impl<Q> GraphQLServerTrait for GraphQLServer<Q>
+where
+    Q: QueryRunner + Send + Sync + 'static,
+{
+    type ServeError = GraphError;
 
     fn schema_event_sink(&mut self) -> Sender<SchemaEvent> {
         self.schema_event_sink.clone()
@@ -124,54 +282,165 @@ impl GraphQLServerTrait for GraphQLServer {
         self.store_event_sink.clone()
     }
 
-    fn query_stream(&mut self) -> Result<Receiver<Query>, StreamError> {
-        // If possible, create a new channel for streaming incoming queries
-        match self.query_sink {
-            Some(_) => Err(StreamError::AlreadyCreated),
-            None => {
-                let (sink, stream) = channel(100);
-                self.query_sink = Some(sink);
-                Ok(stream)
+    fn serve(
+        &mut self,
+        addrs: &[SocketAddr],
+    ) -> Result<Box<Future<Item = (), Error = ()> + Send>, Self::ServeError> {
+        if addrs.is_empty() {
+            return Err(GraphError::from_message(
+                ErrorCategory::Config,
+                "No addresses to serve GraphQL on",
+            ));
+        }
+
+        // Listen on all addresses before serving any, so that every address
+        // that can't be listened on is reported
+        let mut listeners = vec![];
+        let mut failures = vec![];
+        for addr in addrs {
+            match bind_tcp(addr) {
+                Ok(listener) => listeners.push((addr, listener)),
+                Err(e) => failures.push(format!("{} ({})", addr, e)),
             }
         }
+        if !failures.is_empty() {
+            return Err(GraphError::from_message(
+                ErrorCategory::Config,
+                format!("Failed to listen on {}", failures.join(", ")),
+            ));
+        }
+
+        // Create one task per address to run the server and handle HTTP
+        // requests; a server that fails leaves the others running
+        let mut tasks = vec![];
+        for (addr, listener) in listeners {
+            let accept_logger = self.logger.clone();
+            let logger = self.logger.clone();
+            let address = addr.to_string();
+
+            let connections = listener
+                .incoming()
+                .then(move |result| match result {
+                    Ok(stream) => Ok(Some(stream)),
+                    Err(e) => {
+                        warn!(accept_logger, "Failed to accept connection";
+                              "error" => e.to_string());
+                        Ok::<_, io::Error>(None)
+                    }
+                })
+                .filter_map(|stream| stream);
+            let task = Server::builder(connections)
+                .serve(self.new_service())
+                .or_else(move |e| {
+                    error!(logger, "Server error";
+                           "address" => &address, "error" => format!("{}", e));
+                    Ok(())
+                });
+
+            info!(self.logger, "Listening for GraphQL queries"; "address" => addr.to_string());
+            tasks.push(task);
+        }
+
+        Ok(Box::new(future::join_all(tasks).map(|_| ())))
     }
+}
 
-    fn serve(
-        &mut self,
-        port: u16,
-    ) -> Result<Box<Future<Item = (), Error = ()> + Send>, Self::ServeError> {
-        let logger = self.logger.clone();
+/// Listens for TCP connections on `addr`. IPv6 listeners only accept IPv6
+/// connections, so that an IPv4 and an IPv6 address, e.g. `0.0.0.0` and
+/// `::`, can be listened on with the same port.
+fn bind_tcp(addr: &SocketAddr) -> io::Result<TcpListener> {
+    let builder = match addr {
+        SocketAddr::V4(_) => TcpBuilder::new_v4()?,
+        SocketAddr::V6(_) => {
+            let builder = TcpBuilder::new_v6()?;
+            builder.only_v6(true)?;
+            builder
+        }
+    };
+    if cfg!(unix) {
+        // As the standard library does; on Windows, this would let other
+        // processes listen on the same address
+        builder.reuse_address(true)?;
+    }
+    let listener = builder.bind(addr)?.listen(1024)?;
+    TcpListener::from_std(listener, &Handle::default())
+}
 
-        let addr = SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), port);
-
-        // Only launch the GraphQL server if there is a component that will handle incoming queries
-        let query_sink = self
-            .query_sink
-            .as_ref()
-            .ok_or(GraphQLServeError::OrphanError)?;
-
-        // On every incoming request, launch a new GraphQL service that writes
-        // incoming queries to the query sink.
-        let query_sink = query_sink.clone();
-        let schema = self.schema.clone();
-        let new_service = move || {
-            let service = GraphQLService::new(schema.clone(), query_sink.clone());
-            future::ok::<GraphQLService, hyper::Error>(service)
-        };
+/// Binds a unix domain socket at `path` that has the permissions `mode` by
+/// the time it appears there. The socket is bound in a directory that only
+/// the node can access and moved to `path` once its permissions are set.
+#[cfg(unix)]
+fn bind_unix_with_mode(path: &Path, mode: u32) -> io::Result<UnixListener> {
+    let file_name = path.file_name().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "Socket path has no file name")
+    })?;
+    let mut private_dir_name = OsString::from(".");
+    private_dir_name.push(file_name);
+    private_dir_name.push(format!(".{}", process::id()));
+    let private_dir = path.with_file_name(private_dir_name);
+    fs::DirBuilder::new().mode(0o700).create(&private_dir)?;
+
+    let private_path = private_dir.join("socket");
+    let result = UnixListener::bind(&private_path).and_then(|listener| {
+        fs::set_permissions(&private_path, fs::Permissions::from_mode(mode))?;
+        fs::rename(&private_path, path)?;
+        Ok(listener)
+    });
+
+    // Clean up after failures; after a success, only the directory is left
+    let _ = fs::remove_file(&private_path);
+    let _ = fs::remove_dir(&private_dir);
+    result
+}
 
-        // Create a task to run the server and handle HTTP requests
-        let task = Server::try_bind(&addr.into())?
-            .serve(new_service)
-            .map_err(move |e| error!(logger, "Server error"; "error" => format!("{}", e)));
+/// Stitches the schemas configured in `config` from the schemas of the
+/// subgraphs served. Stitched schemas are only served while all of their
+/// subgraphs are.
+fn update_stitched_schemas(
+    logger: &slog::Logger,
+    config: &GraphQLServerConfig,
+    schemas: &mut BTreeMap<String, Schema>,
+) {
+    for (name, subgraphs) in config.stitched_schemas.iter() {
+        let subgraph_schemas: Option<Vec<_>> = subgraphs
+            .iter()
+            .map(|(prefix, id)| {
+                schemas
+                    .get(id)
+                    .map(|schema| (prefix.to_owned(), schema.clone()))
+            })
+            .collect();
+
+        match subgraph_schemas.map(|subgraph_schemas| stitch_schemas(name, &subgraph_schemas)) {
+            Some(Ok(stitched_schema)) => {
+                schemas.insert(name.to_owned(), stitched_schema);
+            }
+            Some(Err(e)) => {
+                error!(logger, "Failed to stitch schemas";
+                       "name" => name,
+                       "error" => e.to_string());
+                schemas.remove(name);
+            }
+            None => {
+                schemas.remove(name);
+            }
+        }
+    }
+}
 
-        Ok(Box::new(task))
+#[cfg(test)]
+struct TestQueryRunner;
+
+#[cfg(test)]
+impl QueryRunner for TestQueryRunner {
+    fn run_query(&self, _query: Query, _target: QueryTarget) -> QueryResponseFuture {
+        Box::new(future::ok(QueryResponse::Complete(QueryResult::new(None))))
     }
 }
 
 #[test]
 fn emits_an_api_schema_after_one_schema_is_added() {
     use graph_graphql::schema::ast;
-    use std::ops::Deref;
     use std::time::{Duration, Instant};
 
     let mut runtime = tokio::runtime::Runtime::new().unwrap();
@@ -180,12 +449,16 @@ fn emits_an_api_schema_after_one_schema_is_added() {
             let res: Result<_, ()> = Ok({
                 // Set up the server
                 let logger = Logger::root(slog::Discard, o!());
-                let mut server = GraphQLServer::new(&logger);
+                let mut server = GraphQLServer::new(
+                    &logger,
+                    GraphQLServerConfig::default(),
+                    Arc::new(TestQueryRunner),
+                );
                 let schema_sink = server.schema_event_sink();
 
                 // Create an input schema event
                 let input_doc =
-                    ::graphql_parser::parse_schema("type User { name: String! }").unwrap();
+                    ::graphql_parser::parse_schema("type User { id: ID!, name: String! }").unwrap();
                 let input_schema = Schema {
                     id: "input-schema".to_string(),
                     document: input_doc,
@@ -200,7 +473,7 @@ fn emits_an_api_schema_after_one_schema_is_added() {
                 let start_time = Instant::now();
                 let max_wait = Duration::from_secs(30);
                 let output_schema = loop {
-                    if let Some(schema) = server.schema.lock().unwrap().deref() {
+                    if let Some(schema) = server.schemas.lock().unwrap().get(&input_schema.id) {
                         break schema.clone();
                     } else if Instant::now().duration_since(start_time) > max_wait {
                         panic!("Timed out, schema not received")
@@ -224,3 +497,62 @@ fn emits_an_api_schema_after_one_schema_is_added() {
         }))
         .unwrap();
 }
+
+#[test]
+fn serves_the_schemas_of_several_subgraphs() {
+    use std::time::{Duration, Instant};
+
+    let mut runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime
+        .block_on(future::lazy(|| {
+            let res: Result<_, ()> = Ok({
+                let logger = Logger::root(slog::Discard, o!());
+                let mut server = GraphQLServer::new(
+                    &logger,
+                    GraphQLServerConfig::default(),
+                    Arc::new(TestQueryRunner),
+                );
+                let schema_sink = server.schema_event_sink();
+
+                let schema = |id: &str| Schema {
+                    id: id.to_string(),
+                    document: ::graphql_parser::parse_schema("type User { id: ID!, name: String! }")
+                        .unwrap(),
+                };
+
+                // Adding a second subgraph keeps the first one served, and
+                // removing one keeps the other
+                schema_sink
+                    .clone()
+                    .send(SchemaEvent::SchemaAdded(schema("first")))
+                    .wait()
+                    .unwrap();
+                schema_sink
+                    .clone()
+                    .send(SchemaEvent::SchemaAdded(schema("second")))
+                    .wait()
+                    .unwrap();
+                schema_sink
+                    .send(SchemaEvent::SchemaRemoved(schema("first")))
+                    .wait()
+                    .unwrap();
+
+                // Wait for thirty seconds for the events to be handled,
+                // otherwise fail the test
+                let start_time = Instant::now();
+                let max_wait = Duration::from_secs(30);
+                loop {
+                    let served: Vec<String> =
+                        server.schemas.lock().unwrap().keys().cloned().collect();
+                    if served == vec!["second"] {
+                        break;
+                    } else if Instant::now().duration_since(start_time) > max_wait {
+                        panic!("Timed out, schemas served: {:?}", served)
+                    }
+                    ::std::thread::yield_now();
+                }
+            });
+            res
+        }))
+        .unwrap();
+}
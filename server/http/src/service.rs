@@ -1,32 +1,110 @@
-use futures::sync::mpsc::Sender;
+use http::header::AUTHORIZATION;
 use hyper::service::Service;
 use hyper::{Body, Method, Request, Response, StatusCode};
+use std::collections::BTreeMap;
 use std::sync::Mutex;
 
 use graph::components::server::GraphQLServerError;
 use graph::prelude::*;
+use graph::util::semaphore::Semaphore;
+use graph_graphql::prelude::{
+    block_constraint, execute, query_complexity, query_hash, system_schema, ExecutionOptions,
+    SystemResolver,
+};
 
-use request::GraphQLRequest;
-use response::GraphQLResponse;
+use api_key::ApiKeys;
+use auth::{verify_auth_token, ADMIN_ROLE};
+use cache::QueryCache;
+use config::GraphQLServerConfig;
+use request::{GraphQLBatchRequest, GraphQLRequest};
+use response::{CachePolicy, GraphQLBatchResponse, GraphQLResponse};
+use usage::DeploymentUsageTracker;
+use warm_up::{WarmUp, WARM_UP_RETRY_AFTER};
 
 /// An asynchronous response to a GraphQL request.
 pub type GraphQLServiceResponse =
     Box<Future<Item = Response<Body>, Error = GraphQLServerError> + Send>;
 
 /// A Hyper Service that serves GraphQL over a POST / endpoint.
-#[derive(Debug)]
-pub struct GraphQLService {
-    schema: Arc<Mutex<Option<Schema>>>,
-    query_sink: Sender<Query>,
+pub struct GraphQLService<Q> {
+    config: Arc<GraphQLServerConfig>,
+    /// The API schemas of the subgraphs served, by subgraph ID.
+    schemas: Arc<Mutex<BTreeMap<String, Schema>>>,
+    query_runner: Arc<Q>,
+    semaphore: Semaphore,
+    api_keys: Option<ApiKeys>,
+    usage_tracker: Option<DeploymentUsageTracker>,
+    query_cache: Option<QueryCache>,
+    system_logger: Option<Logger>,
+    warm_up: Option<WarmUp>,
 }
 
-impl GraphQLService {
-    /// Creates a new GraphQL service.
-    pub fn new(schema: Arc<Mutex<Option<Schema>>>, query_sink: Sender<Query>) -> Self {
-        GraphQLService { schema, query_sink }
+impl<Q> GraphQLService<Q>
+where
+    Q: QueryRunner + Send + Sync + 'static,
+{
+    /// Creates a new GraphQL service. At most as many queries as the
+    /// `semaphore` has permits are executed at the same time; any further
+    /// requests wait until a running query has finished. With `api_keys`,
+    /// queries are attributed to the API key they are sent with; with
+    /// `usage_tracker`, to the deployment they are run against.
+    ///
+    /// Queries are run against the subgraph of `schemas` that their path
+    /// names; queries to `/graphql` only run while a single subgraph is
+    /// served.
+    pub fn new(
+        config: Arc<GraphQLServerConfig>,
+        schemas: Arc<Mutex<BTreeMap<String, Schema>>>,
+        query_runner: Arc<Q>,
+        semaphore: Semaphore,
+        api_keys: Option<ApiKeys>,
+        usage_tracker: Option<DeploymentUsageTracker>,
+    ) -> Self {
+        GraphQLService {
+            config,
+            schemas,
+            query_runner,
+            semaphore,
+            api_keys,
+            usage_tracker,
+            query_cache: None,
+            system_logger: None,
+            warm_up: None,
+        }
+    }
+
+    /// Serves repeated queries against the latest block of a subgraph from
+    /// `query_cache` instead of running them again.
+    pub fn with_query_cache(mut self, query_cache: QueryCache) -> Self {
+        self.query_cache = Some(query_cache);
+        self
+    }
+
+    /// Answers queries about the node itself against the `graph-system`
+    /// schema at `/system/graphql`, and logs them to `logger`.
+    pub fn with_system_schema(mut self, logger: &Logger) -> Self {
+        self.system_logger = Some(logger.new(o!("schema" => "graph-system")));
+        self
+    }
+
+    /// Asks clients to retry queries for subgraphs that are not served yet
+    /// while `warm_up` is in progress.
+    pub fn with_warm_up(mut self, warm_up: WarmUp) -> Self {
+        self.warm_up = Some(warm_up);
+        self
     }
 
-    /// Serves a GraphiQL index.html.
+    /// Serves the GraphiQL index.html.
+    fn serve_index(&self) -> GraphQLServiceResponse {
+        Box::new(future::ok(
+            Response::builder()
+                .status(200)
+                .body(Body::from(self.config.graphiql_index()))
+                .unwrap(),
+        ))
+    }
+
+    /// Serves a static GraphiQL asset.
     fn serve_file(&self, contents: &'static str) -> GraphQLServiceResponse {
         Box::new(future::ok(
             Response::builder()
@@ -36,31 +114,243 @@ impl GraphQLService {
         ))
     }
 
-    /// Handles GraphQL queries received via POST /.
-    fn handle_graphql_query(&self, request: Request<Body>) -> GraphQLServiceResponse {
-        let query_sink = self.query_sink.clone();
-        let schema = self.schema.clone();
+    /// Determines the role of the client from an `Authorization: Bearer <token>`
+    /// header. The header is ignored if no auth secret is configured.
+    fn client_role(&self, request: &Request<Body>) -> Result<Option<String>, GraphQLServerError> {
+        let secret = match self.config.auth_secret {
+            Some(ref secret) => secret,
+            None => return Ok(None),
+        };
+
+        match request.headers().get(AUTHORIZATION) {
+            None => Ok(None),
+            Some(header) => header
+                .to_str()
+                .ok()
+                .and_then(|value| {
+                    if value.starts_with("Bearer ") {
+                        Some(&value["Bearer ".len()..])
+                    } else {
+                        None
+                    }
+                })
+                .and_then(|token| verify_auth_token(secret, token.trim()))
+                .map(Some)
+                .ok_or_else(|| GraphQLServerError::Unauthorized(String::from("Invalid auth token"))),
+        }
+    }
+
+    /// Determines whether the client asks for the plan of the query with a
+    /// `?debug=plan` parameter, which only admins may do, or for a trace of
+    /// its execution with `?debug=trace`. Returns whether to explain and
+    /// whether to trace the query.
+    fn debug_options(
+        &self,
+        request: &Request<Body>,
+        role: Option<&str>,
+    ) -> Result<(bool, bool), GraphQLServerError> {
+        let debug = request
+            .uri()
+            .query()
+            .into_iter()
+            .flat_map(|query| query.split('&'))
+            .find(|parameter| parameter.starts_with("debug="))
+            .map(|parameter| &parameter["debug=".len()..]);
+
+        match debug {
+            None => Ok((false, false)),
+            Some("plan") if role == Some(ADMIN_ROLE) => Ok((true, false)),
+            Some("plan") => Err(GraphQLServerError::Forbidden(String::from(
+                "Query plans are only available to admins",
+            ))),
+            Some("trace") => Ok((false, true)),
+            Some(option) => Err(GraphQLServerError::ClientError(format!(
+                "Unsupported debug option: {}",
+                option
+            ))),
+        }
+    }
+
+    /// Returns the schema that queries for `subgraph_id` run against; without
+    /// an ID, the schema of the only subgraph served, if there is just one.
+    fn schema(&self, subgraph_id: Option<&str>) -> Option<Schema> {
+        let schemas = self.schemas.lock().unwrap();
+        match subgraph_id {
+            Some(subgraph_id) => schemas.get(subgraph_id).cloned(),
+            None if schemas.len() == 1 => schemas.values().next().cloned(),
+            None => None,
+        }
+    }
+
+    /// Handles GraphQL queries received via POST /graphql, or via
+    /// POST /subgraphs/id/<ID>/graphql for the subgraph with `subgraph_id`.
+    fn handle_graphql_query(
+        &self,
+        request: Request<Body>,
+        subgraph_id: Option<String>,
+    ) -> GraphQLServiceResponse {
+        let role = match self.client_role(&request) {
+            Ok(role) => role,
+            Err(e) => return Box::new(GraphQLResponse::new(Err(e))),
+        };
+
+        let (explain, trace) =
+            match self.debug_options(&request, role.as_ref().map(String::as_str)) {
+                Ok(options) => options,
+                Err(e) => return Box::new(GraphQLResponse::new(Err(e))),
+            };
+
+        let api_key = match self.api_keys {
+            Some(ref api_keys) => match api_keys.authenticate(&request) {
+                Ok(key) => key.map(|key| (api_keys.clone(), key)),
+                Err(e) => return Box::new(GraphQLResponse::new(Err(e))),
+            },
+            None => None,
+        };
+
+        let execution = QueryExecution {
+            config: self.config.clone(),
+            query_runner: self.query_runner.clone(),
+            semaphore: self.semaphore.clone(),
+            usage_tracker: self.usage_tracker.clone(),
+            query_cache: self.query_cache.clone(),
+            api_key,
+            role,
+            explain,
+            trace,
+        };
+        let schema = self.schema(subgraph_id.as_ref().map(String::as_str));
+
+        // The subgraph may just not have been loaded yet after a restart
+        if schema.is_none() && self.warm_up.as_ref().map_or(false, WarmUp::in_progress) {
+            return Box::new(
+                GraphQLResponse::new(Err(GraphQLServerError::Unavailable(String::from(
+                    "The node is starting up, try again shortly",
+                )))).with_retry_after(WARM_UP_RETRY_AFTER),
+            );
+        }
 
         Box::new(
             request
                 .into_body()
                 .concat2()
                 .map_err(|_| GraphQLServerError::from("Failed to read request body"))
-                .and_then(move |body| {
-                    let schema = schema.lock().unwrap();
-                    GraphQLRequest::new(body, schema.clone())
-                })
-                .and_then(move |(query, receiver)| {
-                    // Forward the query to the system
-                    query_sink
-                        .send(query)
-                        .wait()
-                        .expect("Failed to forward incoming query");
-
-                    // Continue with waiting to receive a result
-                    receiver.map_err(|e| GraphQLServerError::from(e))
-                })
-                .then(|result| GraphQLResponse::new(result)),
+                .then(move |body| -> GraphQLServiceResponse {
+                    let body = match body {
+                        Ok(body) => body,
+                        Err(e) => return Box::new(execution.run(Err(e)).flatten()),
+                    };
+
+                    // The queries of a batch run concurrently, and are
+                    // answered all at once
+                    if GraphQLBatchRequest::is_batch(&body) {
+                        Box::new(GraphQLBatchRequest::new(body, schema).then(
+                            move |queries| -> GraphQLServiceResponse {
+                                let queries = match queries {
+                                    Ok(queries) => queries,
+                                    Err(e) => return Box::new(GraphQLResponse::new(Err(e))),
+                                };
+                                let responses = queries
+                                    .into_iter()
+                                    .map(|query| execution.run(query))
+                                    .collect::<Vec<_>>();
+                                Box::new(
+                                    future::join_all(responses).and_then(GraphQLBatchResponse::new),
+                                )
+                            },
+                        ))
+                    } else {
+                        Box::new(
+                            GraphQLRequest::new(body, schema)
+                                .then(move |query| execution.run(query))
+                                .flatten(),
+                        )
+                    }
+                }),
+        )
+    }
+
+    /// Returns the names of the optional features enabled on the node.
+    fn features(&self) -> Vec<String> {
+        vec![
+            ("api-keys", self.api_keys.is_some()),
+            ("auth", self.config.auth_secret.is_some()),
+            ("deployment-usage", self.usage_tracker.is_some()),
+            ("max-query-complexity", self.config.max_query_complexity.is_some()),
+            ("query-allow-lists", !self.config.query_allow_lists.is_empty()),
+            ("query-cache", self.query_cache.is_some()),
+        ].into_iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(feature, _)| feature.to_owned())
+            .collect()
+    }
+
+    /// Takes a snapshot of the state of the node.
+    fn system_info(&self, logger: &Logger) -> SystemInfo {
+        let served: Vec<String> = self.schemas.lock().unwrap().keys().cloned().collect();
+
+        let usage = match self.usage_tracker {
+            Some(ref usage_tracker) => usage_tracker.usage().unwrap_or_else(|e| {
+                error!(logger, "Failed to load deployment usage"; "error" => e.to_string());
+                vec![]
+            }),
+            None => vec![],
+        };
+        let mut deployments: Vec<DeploymentInfo> = usage
+            .into_iter()
+            .map(|usage| DeploymentInfo {
+                id: usage.subgraph_id.clone(),
+                served: served.contains(&usage.subgraph_id),
+                usage: Some(usage),
+            })
+            .collect();
+        for served in served {
+            if !deployments.iter().any(|deployment| deployment.id == served) {
+                deployments.push(DeploymentInfo {
+                    id: served,
+                    served: true,
+                    usage: None,
+                });
+            }
+        }
+
+        SystemInfo {
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            features: self.features(),
+            deployments,
+            chain_heads: vec![ChainHead {
+                network: String::from("ethereum"),
+                block_number: self.query_runner.latest_block_number(),
+            }],
+        }
+    }
+
+    /// Handles queries against the `graph-system` schema received via
+    /// POST /system/graphql.
+    fn handle_system_query(&self, request: Request<Body>) -> GraphQLServiceResponse {
+        let logger = match self.system_logger {
+            Some(ref logger) => logger.clone(),
+            None => return self.handle_not_found(request),
+        };
+        let resolver = SystemResolver::new(&self.system_info(&logger));
+
+        Box::new(
+            request
+                .into_body()
+                .concat2()
+                .map_err(|_| GraphQLServerError::from("Failed to read request body"))
+                .and_then(|body| GraphQLRequest::new(body, Some(system_schema())))
+                .then(move |query| {
+                    GraphQLResponse::new(query.map(|query| {
+                        let options = ExecutionOptions {
+                            logger,
+                            resolver,
+                            middleware: vec![],
+                            max_first: None,
+                        };
+                        execute(&query, options)
+                    }))
+                }),
         )
     }
 
@@ -70,7 +360,7 @@ impl GraphQLService {
             Response::builder()
                 .status(200)
                 .header("Access-Control-Allow-Origin", "*")
-                .header("Access-Control-Allow-Headers", "Content-Type")
+                .header("Access-Control-Allow-Headers", "Content-Type, Authorization")
                 .body(Body::from(""))
                 .unwrap(),
         ))
@@ -87,16 +377,244 @@ impl GraphQLService {
     }
 }
 
-impl Service for GraphQLService {
+/// Everything needed to run the queries of a request on behalf of the
+/// client that sent it.
+struct QueryExecution<Q> {
+    config: Arc<GraphQLServerConfig>,
+    query_runner: Arc<Q>,
+    semaphore: Semaphore,
+    usage_tracker: Option<DeploymentUsageTracker>,
+    query_cache: Option<QueryCache>,
+    api_key: Option<(ApiKeys, String)>,
+    role: Option<String>,
+    explain: bool,
+    trace: bool,
+}
+
+impl<Q> QueryExecution<Q>
+where
+    Q: QueryRunner + Send + Sync + 'static,
+{
+    /// Runs a query and responds with its result, or with the error that
+    /// kept it from running.
+    fn run(
+        &self,
+        query: Result<Query, GraphQLServerError>,
+    ) -> Box<Future<Item = GraphQLResponse, Error = GraphQLServerError> + Send> {
+        let config = self.config.clone();
+        let query_runner = self.query_runner.clone();
+        let semaphore = self.semaphore.clone();
+        let usage_tracker = self.usage_tracker.clone();
+        let query_cache = self.query_cache.clone();
+        let api_key = self.api_key.clone();
+        let role = self.role.clone();
+        let explain = self.explain;
+        let trace = self.trace;
+
+        Box::new(
+            future::result(query)
+                .and_then(move |mut query| {
+                    query.role = role;
+                    query.explain = explain;
+                    query.trace = trace;
+
+                    // Locked-down deployments only run pre-registered queries,
+                    // regardless of the variables they are run with
+                    let document_hash = query_hash(&query.document, None, None);
+                    if !config.is_query_allowed(&query.schema.id, &document_hash) {
+                        return future::Either::A(future::err(GraphQLServerError::Forbidden(
+                            format!("Query {} is not allowed for this subgraph", document_hash),
+                        )));
+                    }
+
+                    // Stitched schemas are not deployments of their own; their
+                    // results are not cached since their subgraphs change
+                    // independently of each other
+                    let stitched = config.is_stitched(&query.schema.id);
+                    if let Some(usage_tracker) = usage_tracker.filter(|_| !stitched) {
+                        usage_tracker.record(&query.schema.id);
+                    }
+
+                    let query_hash = query_hash(
+                        &query.document,
+                        query.variables.as_ref(),
+                        query.operation_name.as_ref().map(String::as_str),
+                    );
+
+                    // Queries that are too expensive to run are rejected
+                    // before they take up an execution slot
+                    let complexity = query_complexity(&query).ok();
+                    if let (Some(complexity), Some(max_complexity)) =
+                        (complexity, config.max_query_complexity)
+                    {
+                        if complexity > max_complexity {
+                            let error = QueryExecutionError::TooComplex(complexity, max_complexity);
+                            return future::Either::A(future::ok((
+                                QueryResponse::Complete(QueryResult::from(error)),
+                                CachePolicy::NoStore,
+                                query_hash,
+                                Some(complexity),
+                            )));
+                        }
+                    }
+
+                    // Queries whose fields are at different blocks are rejected,
+                    // since the whole query is run at one block
+                    let block = match block_constraint(&query) {
+                        Ok(block) => block,
+                        Err(e) => {
+                            return future::Either::A(future::ok((
+                                QueryResponse::Complete(QueryResult::from(e)),
+                                CachePolicy::NoStore,
+                                query_hash,
+                                complexity,
+                            )))
+                        }
+                    };
+
+                    let subgraph_id = query.schema.id.clone();
+                    let target = QueryTarget {
+                        subgraph: SubgraphTarget::Deployment(subgraph_id.clone()),
+                        block,
+                    };
+
+                    // Only results at the latest block are cached, and only
+                    // for anonymous clients since roles may see more fields
+                    let cached = match query_cache {
+                        Some(ref cache)
+                            if !explain
+                                && !trace
+                                && !stitched
+                                && target.block.is_none()
+                                && query.role.is_none() =>
+                        {
+                            Some((cache.clone(), cache.version(&subgraph_id)))
+                        }
+                        _ => None,
+                    };
+                    if let Some((ref cache, ref version)) = cached {
+                        if let Some(data) = cache.get(&subgraph_id, version, &query_hash) {
+                            let cache_policy = CachePolicy::for_target(
+                                &target,
+                                query_runner.latest_block_number(),
+                                &config,
+                            );
+                            return future::Either::A(future::ok((
+                                QueryResponse::Complete(QueryResult::new(Some(data))),
+                                cache_policy,
+                                query_hash,
+                                complexity,
+                            )));
+                        }
+                    }
+
+                    // Wait for an execution slot, then run the query while
+                    // holding on to it
+                    future::Either::B(
+                        semaphore
+                            .acquire()
+                            .map_err(|_| GraphQLServerError::from("Failed to schedule query"))
+                            .and_then(move |permit| {
+                                query_runner
+                                    .run_query(query, target.clone())
+                                    .map_err(GraphQLServerError::from)
+                                    .then(move |result| {
+                                        drop(permit);
+
+                                        // Results are cached for the version of the
+                                        // subgraph from before the query ran
+                                        if let (
+                                            Some((cache, version)),
+                                            Ok(&QueryResponse::Complete(ref result)),
+                                        ) = (cached, result.as_ref())
+                                        {
+                                            if let (&Some(ref data), &None, &None) =
+                                                (&result.data, &result.errors, &result.plan)
+                                            {
+                                                cache.insert(
+                                                    &subgraph_id,
+                                                    &version,
+                                                    &query_hash,
+                                                    data.clone(),
+                                                );
+                                            }
+                                        }
+
+                                        // Results at old enough blocks will never change,
+                                        // but their plans and traces are not meant to be
+                                        // cached
+                                        let cache_policy = if explain || trace {
+                                            CachePolicy::NoStore
+                                        } else {
+                                            CachePolicy::for_target(
+                                                &target,
+                                                query_runner.latest_block_number(),
+                                                &config,
+                                            )
+                                        };
+                                        result.map(|response| {
+                                            (response, cache_policy, query_hash, complexity)
+                                        })
+                                    })
+                            }),
+                    )
+                })
+                .and_then(|(response, cache_policy, query_hash, complexity)| match response {
+                    QueryResponse::Complete(result) => {
+                        Ok((result, cache_policy, query_hash, complexity))
+                    }
+                    QueryResponse::Stream(_) => Err(GraphQLServerError::ClientError(
+                        String::from("Streamed query responses are not supported over HTTP"),
+                    )),
+                })
+                .then(move |result| -> Result<GraphQLResponse, GraphQLServerError> {
+                    let (result, cache_policy, query_hash, complexity) = match result {
+                        Ok((result, cache_policy, query_hash, complexity)) => {
+                            (Ok(result), cache_policy, Some(query_hash), complexity)
+                        }
+                        Err(e) => (Err(e), CachePolicy::NoStore, None, None),
+                    };
+
+                    if let Some((api_keys, key)) = api_key {
+                        api_keys.record(&key, &result);
+                    }
+
+                    let mut response = GraphQLResponse::with_cache_policy(result, cache_policy);
+                    if let Some(query_hash) = query_hash {
+                        response = response.with_query_hash(query_hash);
+                    }
+                    if let Some(complexity) = complexity {
+                        response = response.with_query_complexity(complexity);
+                    }
+                    Ok(response)
+                }),
+        )
+    }
+}
+
+impl<Q> Service for GraphQLService<Q>
+where
+    Q: QueryRunner + Send + Sync + 'static,
+{
     type ReqBody = Body;
     type ResBody = Body;
     type Error = GraphQLServerError;
     type Future = GraphQLServiceResponse;
 
     fn call(&mut self, req: Request<Self::ReqBody>) -> Self::Future {
-        match (req.method(), req.uri().path()) {
+        // Everything outside of the path prefix results in a 404
+        let path = match self.config.strip_path_prefix(req.uri().path()) {
+            Some(path) => path.to_owned(),
+            None => return self.handle_not_found(req),
+        };
+
+        // POST /subgraphs/id/<ID>/graphql receives queries for one of the
+        // subgraphs served
+        let subgraph_id = subgraph_id_in_path(&path).map(str::to_owned);
+
+        match (req.method(), path.as_str()) {
             // GraphiQL
-            (&Method::GET, "/") => self.serve_file(include_str!("../assets/index.html")),
+            (&Method::GET, "/") => self.serve_index(),
             (&Method::GET, "/graphiql.css") => {
                 self.serve_file(include_str!("../assets/graphiql.css"))
             }
@@ -105,10 +623,18 @@ impl Service for GraphQLService {
             }
 
             // POST / receives GraphQL queries
-            (&Method::POST, "/graphql") => self.handle_graphql_query(req),
+            (&Method::POST, "/graphql") => self.handle_graphql_query(req, None),
+            (&Method::POST, _) if subgraph_id.is_some() => {
+                self.handle_graphql_query(req, subgraph_id)
+            }
 
             // OPTIONS / allows to check for GraphQL HTTP features
             (&Method::OPTIONS, "/graphql") => self.handle_graphql_options(req),
+            (&Method::OPTIONS, _) if subgraph_id.is_some() => self.handle_graphql_options(req),
+
+            // POST /system/graphql receives queries about the node itself
+            (&Method::POST, "/system/graphql") => self.handle_system_query(req),
+            (&Method::OPTIONS, "/system/graphql") => self.handle_graphql_options(req),
 
             // Everything else results in a 404
             _ => self.handle_not_found(req),
@@ -116,25 +642,73 @@ impl Service for GraphQLService {
     }
 }
 
+/// Returns the subgraph ID in a `/subgraphs/id/<ID>/graphql` path.
+fn subgraph_id_in_path(path: &str) -> Option<&str> {
+    let segments: Vec<&str> = path.split('/').collect();
+    match segments.as_slice() {
+        ["", "subgraphs", "id", id, "graphql"] if !id.is_empty() => Some(*id),
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use futures::sync::mpsc::channel;
     use graphql_parser;
     use graphql_parser::query::Value;
     use http::status::StatusCode;
     use hyper::service::Service;
     use hyper::{Body, Method, Request};
     use std::collections::BTreeMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
     use std::sync::Mutex;
 
+    use graph::components::store::{EventSource, StoreEvent};
     use graph::prelude::*;
+    use graph::serde_json;
+    use graph::util::semaphore::Semaphore;
 
     use super::GraphQLService;
+    use api_key::ApiKeys;
+    use auth::auth_token;
+    use cache::QueryCache;
+    use config::GraphQLServerConfig;
     use test_utils;
+    use usage::DeploymentUsageTracker;
+    use warm_up::WarmUp;
+
+    /// Serves the given schemas, by their subgraph IDs.
+    fn served(schemas: Vec<Schema>) -> Arc<Mutex<BTreeMap<String, Schema>>> {
+        Arc::new(Mutex::new(
+            schemas
+                .into_iter()
+                .map(|schema| (schema.id.clone(), schema))
+                .collect(),
+        ))
+    }
+
+    /// A query runner that returns the same result for every query, along
+    /// with an empty plan or trace if the query asks for one.
+    struct TestQueryRunner;
+
+    impl QueryRunner for TestQueryRunner {
+        fn run_query(&self, query: Query, _target: QueryTarget) -> QueryResponseFuture {
+            let mut map = BTreeMap::new();
+            map.insert("name".to_string(), Value::String("Jordi".to_string()));
+            let data = Value::Object(map);
+            let mut result = QueryResult::new(Some(data));
+            if query.explain {
+                result.plan = Some(QueryPlan::default());
+            }
+            if query.trace {
+                result.trace = Some(QueryTrace::default());
+            }
+            Box::new(future::ok(QueryResponse::Complete(result)))
+        }
+    }
 
     #[test]
     fn posting_invalid_query_yields_error_response() {
-        let schema = Arc::new(Mutex::new(Some(Schema {
+        let schema = served(vec![Schema {
             id: "test-schema".to_string(),
             document: graphql_parser::parse_schema(
                 "\
@@ -142,9 +716,15 @@ mod tests {
                  type Query { name: String } \
                  ",
             ).unwrap(),
-        })));
-        let (query_sink, _) = channel(1);
-        let mut service = GraphQLService::new(schema, query_sink);
+        }]);
+        let mut service = GraphQLService::new(
+            Arc::new(GraphQLServerConfig::default()),
+            schema,
+            Arc::new(TestQueryRunner),
+            Semaphore::new(1),
+            None,
+            None,
+        );
 
         let request = Request::builder()
             .method(Method::POST)
@@ -173,7 +753,7 @@ mod tests {
     fn posting_valid_queries_yields_result_response() {
         tokio::run(future::lazy(|| {
             Ok({
-                let schema = Arc::new(Mutex::new(Some(Schema {
+                let schema = served(vec![Schema {
                     id: "test-schema".to_string(),
                     document: graphql_parser::parse_schema(
                         "\
@@ -181,21 +761,14 @@ mod tests {
                          type Query { name: String } \
                          ",
                     ).unwrap(),
-                })));
-                let (query_sink, query_stream) = channel(1);
-                let mut service = GraphQLService::new(schema, query_sink);
-
-                tokio::spawn(
-                    query_stream
-                        .for_each(move |query| {
-                            let mut map = BTreeMap::new();
-                            map.insert("name".to_string(), Value::String("Jordi".to_string()));
-                            let data = Value::Object(map);
-                            let result = QueryResult::new(Some(data));
-                            query.result_sender.send(result).unwrap();
-                            Ok(())
-                        })
-                        .fuse(),
+                }]);
+                let mut service = GraphQLService::new(
+                    Arc::new(GraphQLServerConfig::default()),
+                    schema,
+                    Arc::new(TestQueryRunner),
+                    Semaphore::new(1),
+                    None,
+                    None,
                 );
 
                 let request = Request::builder()
@@ -221,4 +794,554 @@ mod tests {
             })
         }))
     }
+
+    /// A query runner that answers every query with the ID of the subgraph
+    /// it is run against.
+    struct SubgraphIdQueryRunner;
+
+    impl QueryRunner for SubgraphIdQueryRunner {
+        fn run_query(&self, query: Query, _target: QueryTarget) -> QueryResponseFuture {
+            let mut map = BTreeMap::new();
+            map.insert("name".to_string(), Value::String(query.schema.id));
+            let result = QueryResult::new(Some(Value::Object(map)));
+            Box::new(future::ok(QueryResponse::Complete(result)))
+        }
+    }
+
+    #[test]
+    fn routes_queries_to_the_subgraph_in_the_path() {
+        let schema = |id: &str| Schema {
+            id: id.to_string(),
+            document: graphql_parser::parse_schema("scalar String type Query { name: String }")
+                .unwrap(),
+        };
+        let mut service = GraphQLService::new(
+            Arc::new(GraphQLServerConfig::default()),
+            served(vec![schema("first"), schema("second")]),
+            Arc::new(SubgraphIdQueryRunner),
+            Semaphore::new(1),
+            None,
+            None,
+        );
+        let mut query = |path: &str| {
+            let request = Request::builder()
+                .method(Method::POST)
+                .uri(format!("http://localhost:8000{}", path).as_str())
+                .body(Body::from("{\"query\": \"{ name }\"}"))
+                .unwrap();
+            service.call(request).wait().unwrap()
+        };
+
+        for id in vec!["first", "second"] {
+            let response = query(&format!("/subgraphs/id/{}/graphql", id));
+            let data = test_utils::assert_successful_response(response);
+            assert_eq!(data["name"], id);
+        }
+
+        // Unknown subgraphs are not found, and neither is the subgraph to
+        // query at `/graphql` while there are several
+        let response = query("/subgraphs/id/third/graphql");
+        test_utils::assert_error_response(response, StatusCode::NOT_FOUND);
+        let response = query("/graphql");
+        test_utils::assert_error_response(response, StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn asks_clients_to_retry_subgraphs_that_are_not_loaded_while_warming_up() {
+        let warm_up = WarmUp::new();
+        let mut service = GraphQLService::new(
+            Arc::new(GraphQLServerConfig::default()),
+            served(vec![Schema {
+                id: "first".to_string(),
+                document: graphql_parser::parse_schema("scalar String type Query { name: String }")
+                    .unwrap(),
+            }]),
+            Arc::new(SubgraphIdQueryRunner),
+            Semaphore::new(1),
+            None,
+            None,
+        ).with_warm_up(warm_up.clone());
+        let mut query = |path: &str| {
+            let request = Request::builder()
+                .method(Method::POST)
+                .uri(format!("http://localhost:8000{}", path).as_str())
+                .body(Body::from("{\"query\": \"{ name }\"}"))
+                .unwrap();
+            service.call(request).wait().unwrap()
+        };
+
+        // Subgraphs that are loaded already are served while warming up
+        let response = query("/subgraphs/id/first/graphql");
+        test_utils::assert_successful_response(response);
+
+        let response = query("/subgraphs/id/second/graphql");
+        assert_eq!(response.headers()["Retry-After"], "5");
+        test_utils::assert_error_response(response, StatusCode::SERVICE_UNAVAILABLE);
+
+        warm_up.finish();
+        let response = query("/subgraphs/id/second/graphql");
+        test_utils::assert_error_response(response, StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn only_serves_requests_under_the_path_prefix() {
+        let config = GraphQLServerConfig {
+            path_prefix: "/api/v0".to_string(),
+            ..Default::default()
+        };
+        let mut service = GraphQLService::new(
+            Arc::new(config),
+            served(vec![]),
+            Arc::new(TestQueryRunner),
+            Semaphore::new(1),
+            None,
+            None,
+        );
+
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri("http://localhost:8000/")
+            .body(Body::from(""))
+            .unwrap();
+        let response = service.call(request).wait().unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri("http://localhost:8000/api/v0/")
+            .body(Body::from(""))
+            .unwrap();
+        let response = service.call(request).wait().unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn rejects_invalid_auth_tokens() {
+        let config = GraphQLServerConfig {
+            auth_secret: Some("secret".to_string()),
+            ..Default::default()
+        };
+        let mut service = GraphQLService::new(
+            Arc::new(config),
+            served(vec![]),
+            Arc::new(TestQueryRunner),
+            Semaphore::new(1),
+            None,
+            None,
+        );
+
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("http://localhost:8000/graphql")
+            .header("Authorization", "Bearer admin:forged")
+            .body(Body::from("{\"query\": \"{ name }\"}"))
+            .unwrap();
+        let response = service.call(request).wait().unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn only_returns_query_plans_to_admins() {
+        let config = GraphQLServerConfig {
+            auth_secret: Some("secret".to_string()),
+            ..Default::default()
+        };
+        let schema = served(vec![Schema {
+            id: "test-schema".to_string(),
+            document: graphql_parser::parse_schema("scalar String type Query { name: String }")
+                .unwrap(),
+        }]);
+        let mut service = GraphQLService::new(
+            Arc::new(config),
+            schema,
+            Arc::new(TestQueryRunner),
+            Semaphore::new(1),
+            None,
+            None,
+        );
+
+        let request = |role: &str| {
+            Request::builder()
+                .method(Method::POST)
+                .uri("http://localhost:8000/graphql?debug=plan")
+                .header(
+                    "Authorization",
+                    format!("Bearer {}", auth_token("secret", role)).as_str(),
+                )
+                .body(Body::from("{\"query\": \"{ name }\"}"))
+                .unwrap()
+        };
+
+        let response = service.call(request("user")).wait().unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = service.call(request("admin")).wait().unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get("Cache-Control").unwrap(), "no-store");
+
+        let body = response.into_body().concat2().wait().unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let steps = json["extensions"]["plan"]["steps"].as_array();
+        assert_eq!(steps.map(Vec::len), Some(0));
+    }
+
+    #[test]
+    fn returns_traces_to_all_clients_that_ask_for_them() {
+        let schema = served(vec![Schema {
+            id: "test-schema".to_string(),
+            document: graphql_parser::parse_schema("scalar String type Query { name: String }")
+                .unwrap(),
+        }]);
+        let mut service = GraphQLService::new(
+            Arc::new(GraphQLServerConfig::default()),
+            schema,
+            Arc::new(TestQueryRunner),
+            Semaphore::new(1),
+            None,
+            None,
+        );
+
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("http://localhost:8000/graphql?debug=trace")
+            .body(Body::from("{\"query\": \"{ name }\"}"))
+            .unwrap();
+        let response = service.call(request).wait().unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get("Cache-Control").unwrap(), "no-store");
+
+        let body = response.into_body().concat2().wait().unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let tracing = &json["extensions"]["tracing"];
+        assert_eq!(tracing["execution"]["resolvers"].as_array().map(Vec::len), Some(0));
+        assert_eq!(tracing["storeQueries"].as_array().map(Vec::len), Some(0));
+    }
+
+    #[test]
+    fn rejects_queries_above_the_maximum_complexity() {
+        let config = GraphQLServerConfig {
+            max_query_complexity: Some(50),
+            ..Default::default()
+        };
+        let schema = served(vec![Schema {
+            id: "test-schema".to_string(),
+            document: graphql_parser::parse_schema(
+                "type Query { names(first: Int): [Name!]! } type Name { name: String }",
+            ).unwrap(),
+        }]);
+        let mut service = GraphQLService::new(
+            Arc::new(config),
+            schema,
+            Arc::new(TestQueryRunner),
+            Semaphore::new(1),
+            None,
+            None,
+        );
+
+        let request = |query: &str| {
+            Request::builder()
+                .method(Method::POST)
+                .uri("http://localhost:8000/graphql")
+                .body(Body::from(format!("{{\"query\": \"{}\"}}", query)))
+                .unwrap()
+        };
+
+        let response = service
+            .call(request("{ names(first: 10) { name } }"))
+            .wait()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().concat2().wait().unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["extensions"]["complexity"], 11);
+
+        let response = service.call(request("{ names { name } }")).wait().unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = response.into_body().concat2().wait().unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["extensions"]["complexity"], 101);
+        assert_eq!(
+            json["errors"][0]["message"],
+            "Query is too complex: its complexity of 101 exceeds the maximum of 50"
+        );
+    }
+
+    /// An API key store that knows a single key.
+    struct TestApiKeyStore {
+        usage: ApiKeyUsage,
+    }
+
+    impl ApiKeyStore for TestApiKeyStore {
+        fn create_api_key(&mut self, _name: String) -> Result<ApiKey, GraphError> {
+            unimplemented!()
+        }
+
+        fn api_key(&self, key: &str) -> Result<Option<ApiKey>, GraphError> {
+            Ok(if key == "valid" {
+                Some(ApiKey {
+                    key: key.to_owned(),
+                    name: "test".to_owned(),
+                    usage: self.usage,
+                })
+            } else {
+                None
+            })
+        }
+
+        fn api_keys(&self) -> Result<Vec<ApiKey>, GraphError> {
+            unimplemented!()
+        }
+
+        fn rename_api_key(&mut self, _key: &str, _name: String) -> Result<bool, GraphError> {
+            unimplemented!()
+        }
+
+        fn delete_api_key(&mut self, _key: &str) -> Result<bool, GraphError> {
+            unimplemented!()
+        }
+
+        fn record_api_key_usage(
+            &mut self,
+            _key: &str,
+            usage: ApiKeyUsage,
+        ) -> Result<(), GraphError> {
+            self.usage.queries += usage.queries;
+            self.usage.errors += usage.errors;
+            self.usage.cost += usage.cost;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn attributes_queries_to_api_keys() {
+        let logger = Logger::root(slog::Discard, o!());
+        let store = Arc::new(Mutex::new(TestApiKeyStore {
+            usage: ApiKeyUsage::default(),
+        }));
+        let schema = served(vec![Schema {
+            id: "test-schema".to_string(),
+            document: graphql_parser::parse_schema("scalar String type Query { name: String }")
+                .unwrap(),
+        }]);
+        let mut service = GraphQLService::new(
+            Arc::new(GraphQLServerConfig::default()),
+            schema,
+            Arc::new(TestQueryRunner),
+            Semaphore::new(1),
+            Some(ApiKeys::new(&logger, store.clone(), true)),
+            None,
+        );
+
+        let request = |key: Option<&str>| {
+            let mut builder = Request::builder();
+            builder.method(Method::POST).uri("http://localhost:8000/graphql");
+            if let Some(key) = key {
+                builder.header("X-Api-Key", key);
+            }
+            builder.body(Body::from("{\"query\": \"{ name }\"}")).unwrap()
+        };
+
+        let response = service.call(request(None)).wait().unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = service.call(request(Some("invalid"))).wait().unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = service.call(request(Some("valid"))).wait().unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            store.lock().unwrap().usage,
+            ApiKeyUsage {
+                queries: 1,
+                errors: 0,
+                cost: 1,
+            }
+        );
+    }
+
+    /// A deployment usage store that counts queries per deployment.
+    struct TestDeploymentUsageStore {
+        queries: Vec<String>,
+    }
+
+    impl DeploymentUsageStore for TestDeploymentUsageStore {
+        fn track_deployment(&mut self, _subgraph_id: &str) -> Result<(), GraphError> {
+            unimplemented!()
+        }
+
+        fn record_deployment_query(&mut self, subgraph_id: &str) -> Result<(), GraphError> {
+            self.queries.push(subgraph_id.to_owned());
+            Ok(())
+        }
+
+        fn deployment_usage(&self) -> Result<Vec<DeploymentUsage>, GraphError> {
+            unimplemented!()
+        }
+    }
+
+    #[test]
+    fn records_queries_against_deployments() {
+        let logger = Logger::root(slog::Discard, o!());
+        let store = Arc::new(Mutex::new(TestDeploymentUsageStore { queries: vec![] }));
+        let schema = served(vec![Schema {
+            id: "test-schema".to_string(),
+            document: graphql_parser::parse_schema("scalar String type Query { name: String }")
+                .unwrap(),
+        }]);
+        let mut service = GraphQLService::new(
+            Arc::new(GraphQLServerConfig::default()),
+            schema,
+            Arc::new(TestQueryRunner),
+            Semaphore::new(1),
+            None,
+            Some(DeploymentUsageTracker::new(&logger, store.clone())),
+        );
+
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("http://localhost:8000/graphql")
+            .body(Body::from("{\"query\": \"{ name }\"}"))
+            .unwrap();
+        let response = service.call(request).wait().unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(store.lock().unwrap().queries, vec!["test-schema"]);
+    }
+
+    #[test]
+    fn responds_to_batched_queries_in_order() {
+        let schema = served(vec![Schema {
+            id: "test-schema".to_string(),
+            document: graphql_parser::parse_schema("scalar String type Query { name: String }")
+                .unwrap(),
+        }]);
+        let mut service = GraphQLService::new(
+            Arc::new(GraphQLServerConfig::default()),
+            schema,
+            Arc::new(TestQueryRunner),
+            Semaphore::new(1),
+            None,
+            None,
+        );
+
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("http://localhost:8000/graphql")
+            .body(Body::from(
+                "[{\"query\": \"{ name }\"}, {\"query\": \"{ \"}, {\"query\": \"{ name }\"}]",
+            ))
+            .unwrap();
+        let response = service.call(request).wait().unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = response.into_body().concat2().wait().unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let responses = json.as_array().expect("Batch response is not an array");
+        assert_eq!(responses.len(), 3);
+        assert_eq!(responses[0]["data"]["name"], "Jordi");
+        assert!(responses[1]["errors"].is_array());
+        assert_eq!(responses[2]["data"]["name"], "Jordi");
+    }
+
+    #[test]
+    fn answers_queries_about_the_node_against_the_system_schema() {
+        let logger = Logger::root(slog::Discard, o!());
+        let schema = served(vec![Schema {
+            id: "test-schema".to_string(),
+            document: graphql_parser::parse_schema("scalar String type Query { name: String }")
+                .unwrap(),
+        }]);
+        let system_query = || {
+            Request::builder()
+                .method(Method::POST)
+                .uri("http://localhost:8000/system/graphql")
+                .body(Body::from(
+                    "{\"query\": \"{ node { features } deployments { id served queryCount } \
+                     chainHeads { network } }\"}",
+                ))
+                .unwrap()
+        };
+
+        // The system schema is only served if enabled
+        let mut service = GraphQLService::new(
+            Arc::new(GraphQLServerConfig::default()),
+            schema.clone(),
+            Arc::new(TestQueryRunner),
+            Semaphore::new(1),
+            None,
+            None,
+        );
+        let response = service.call(system_query()).wait().unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let mut service = service.with_system_schema(&logger);
+        let response = service.call(system_query()).wait().unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = response.into_body().concat2().wait().unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let data = &json["data"];
+        assert_eq!(data["node"]["features"].as_array().map(Vec::len), Some(0));
+        assert_eq!(data["deployments"][0]["id"], "test-schema");
+        assert_eq!(data["deployments"][0]["served"], true);
+        assert!(data["deployments"][0]["queryCount"].is_null());
+        assert_eq!(data["chainHeads"][0]["network"], "ethereum");
+    }
+
+    /// A query runner that counts the queries it runs.
+    struct CountingQueryRunner {
+        queries: AtomicUsize,
+    }
+
+    impl QueryRunner for CountingQueryRunner {
+        fn run_query(&self, query: Query, target: QueryTarget) -> QueryResponseFuture {
+            self.queries.fetch_add(1, Ordering::SeqCst);
+            TestQueryRunner.run_query(query, target)
+        }
+    }
+
+    #[test]
+    fn serves_repeated_queries_from_the_cache_until_the_subgraph_changes() {
+        let query_runner = Arc::new(CountingQueryRunner {
+            queries: AtomicUsize::new(0),
+        });
+        let query_cache = QueryCache::new(10);
+        let schema = served(vec![Schema {
+            id: "test-schema".to_string(),
+            document: graphql_parser::parse_schema("scalar String type Query { name: String }")
+                .unwrap(),
+        }]);
+        let mut service = GraphQLService::new(
+            Arc::new(GraphQLServerConfig::default()),
+            schema,
+            query_runner.clone(),
+            Semaphore::new(1),
+            None,
+            None,
+        ).with_query_cache(query_cache.clone());
+
+        let mut query = || {
+            let request = Request::builder()
+                .method(Method::POST)
+                .uri("http://localhost:8000/graphql")
+                .body(Body::from("{\"query\": \"{ name }\"}"))
+                .unwrap();
+            let response = service.call(request).wait().unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = response.into_body().concat2().wait().unwrap();
+            let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(json["data"]["name"], "Jordi");
+        };
+
+        query();
+        query();
+        assert_eq!(query_runner.queries.load(Ordering::SeqCst), 1);
+
+        query_cache.handle_store_event(&StoreEvent::SubgraphChanged(
+            "test-schema".to_string(),
+            EventSource::EthereumBlock(1.into(), 1),
+        ));
+        query();
+        assert_eq!(query_runner.queries.load(Ordering::SeqCst), 2);
+    }
 }
@@ -0,0 +1,47 @@
+use std::sync::Mutex;
+
+use graph::prelude::*;
+
+/// Records when the deployments served by the node are queried.
+#[derive(Clone)]
+pub struct DeploymentUsageTracker {
+    logger: Logger,
+    store: Arc<Mutex<DeploymentUsageStore + Send>>,
+}
+
+impl DeploymentUsageTracker {
+    /// Creates usage tracking backed by `store`.
+    pub fn new(logger: &Logger, store: Arc<Mutex<DeploymentUsageStore + Send>>) -> Self {
+        DeploymentUsageTracker {
+            logger: logger.new(o!("component" => "DeploymentUsageTracker")),
+            store,
+        }
+    }
+
+    /// Starts tracking a deployment once it is being served, so that
+    /// deployments that are never queried show up as idle.
+    pub fn track(&self, subgraph_id: &str) {
+        if let Err(e) = self.store.lock().unwrap().track_deployment(subgraph_id) {
+            error!(self.logger, "Failed to track deployment usage";
+                   "subgraph" => subgraph_id, "error" => e.to_string());
+        }
+    }
+
+    /// Lists the usage of all tracked deployments.
+    pub fn usage(&self) -> Result<Vec<DeploymentUsage>, GraphError> {
+        self.store.lock().unwrap().deployment_usage()
+    }
+
+    /// Records a query against a deployment.
+    pub fn record(&self, subgraph_id: &str) {
+        if let Err(e) = self
+            .store
+            .lock()
+            .unwrap()
+            .record_deployment_query(subgraph_id)
+        {
+            error!(self.logger, "Failed to record deployment usage";
+                   "subgraph" => subgraph_id, "error" => e.to_string());
+        }
+    }
+}
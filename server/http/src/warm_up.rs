@@ -0,0 +1,49 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// How many seconds clients are asked to wait before retrying queries that
+/// arrive while the server is warming up.
+pub const WARM_UP_RETRY_AFTER: u64 = 5;
+
+/// Tracks whether the subgraphs that a server serves after starting up are
+/// still being loaded. Until then, queries for subgraphs that are not served
+/// yet are answered with `503 Service Unavailable` and a `Retry-After`
+/// header instead of `404 Not Found`, so that load balancers retry them
+/// rather than dropping them during rolling restarts.
+#[derive(Clone, Debug)]
+pub struct WarmUp {
+    finished: Arc<AtomicBool>,
+}
+
+impl WarmUp {
+    /// Starts warming up; the server warms up until `finish` is called on
+    /// any clone of the returned value.
+    pub fn new() -> Self {
+        WarmUp {
+            finished: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Marks the subgraphs served after starting up as loaded.
+    pub fn finish(&self) {
+        self.finished.store(true, Ordering::SeqCst);
+    }
+
+    pub fn in_progress(&self) -> bool {
+        !self.finished.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WarmUp;
+
+    #[test]
+    fn warm_up_is_finished_through_any_clone() {
+        let warm_up = WarmUp::new();
+        let handle = warm_up.clone();
+        assert!(warm_up.in_progress());
+        handle.finish();
+        assert!(!warm_up.in_progress());
+    }
+}
@@ -6,32 +6,61 @@ extern crate http;
 extern crate hyper;
 
 use futures::prelude::*;
-use futures::sync::mpsc::Receiver;
 use graphql_parser::query::Value;
 use http::StatusCode;
 use hyper::{Body, Client, Request};
 use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
 
 use graph::prelude::*;
 
 use graph_server_http::test_utils;
-use graph_server_http::GraphQLServer as HyperGraphQLServer;
-
-/// Helper function that simulates running a single incoming query and then
-/// closing the query stream.
-fn simulate_running_one_query(query_stream: Receiver<Query>) {
-    tokio::spawn(
-        query_stream
-            .for_each(move |query| {
-                let mut map = BTreeMap::new();
-                map.insert("name".to_string(), Value::String("Jordi".to_string()));
-                let data = Value::Object(map);
-                let result = QueryResult::new(Some(data));
-                query.result_sender.send(result).unwrap();
-                Ok(())
-            })
-            .fuse(),
-    );
+use graph_server_http::{GraphQLServer as HyperGraphQLServer, GraphQLServerConfig};
+
+/// Returns the address of `port` on the loopback interface.
+fn local_addr(port: u16) -> SocketAddr {
+    SocketAddr::from(([127, 0, 0, 1], port))
+}
+
+/// Sends a query to the server on `port` until it responds with `status`,
+/// since the server handles schema events in the background, and returns
+/// the last response.
+fn query_until_status(
+    port: u16,
+    status: StatusCode,
+) -> impl Future<Item = hyper::Response<Body>, Error = hyper::Error> {
+    let client = Client::new();
+    let uri = format!("http://localhost:{}/graphql", port);
+    future::loop_fn(0, move |attempt| {
+        let request = Request::post(uri.as_str())
+            .body(Body::from("{\"query\": \"{ name }\"}"))
+            .unwrap();
+        client.request(request).and_then(move |response| {
+            if response.status() == status || attempt >= 50 {
+                return future::Either::A(future::ok(future::Loop::Break(response)));
+            }
+            future::Either::B(
+                tokio::timer::Delay::new(Instant::now() + Duration::from_millis(20))
+                    .map_err(|e| panic!("Failed to wait before retrying: {}", e))
+                    .map(move |()| future::Loop::Continue(attempt + 1)),
+            )
+        })
+    })
+}
+
+/// A query runner that returns the same result for every query.
+struct TestQueryRunner;
+
+impl QueryRunner for TestQueryRunner {
+    fn run_query(&self, _query: Query, _target: QueryTarget) -> QueryResponseFuture {
+        let mut map = BTreeMap::new();
+        map.insert("name".to_string(), Value::String("Jordi".to_string()));
+        let data = Value::Object(map);
+        Box::new(future::ok(QueryResponse::Complete(QueryResult::new(Some(
+            data,
+        )))))
+    }
 }
 
 #[test]
@@ -41,9 +70,14 @@ fn rejects_empty_json() {
         .block_on(futures::lazy(|| {
             let logger = slog::Logger::root(slog::Discard, o!());
 
-            let mut server = HyperGraphQLServer::new(&logger);
-            let query_stream = server.query_stream().unwrap();
-            let http_server = server.serve(8001).expect("Failed to start GraphQL server");
+            let mut server = HyperGraphQLServer::new(
+                &logger,
+                GraphQLServerConfig::default(),
+                Arc::new(TestQueryRunner),
+            );
+            let http_server = server
+                .serve(&[local_addr(8001)])
+                .expect("Failed to start GraphQL server");
 
             // Create a simple schema and send it to the server
             let schema = Schema {
@@ -57,7 +91,6 @@ fn rejects_empty_json() {
                 .expect("Failed to send schema to server");
 
             // Launch the server to handle a single request
-            simulate_running_one_query(query_stream);
             tokio::spawn(http_server.fuse());
 
             // Send an empty JSON POST request
@@ -91,12 +124,16 @@ fn rejects_invalid_queries() {
         .block_on(futures::lazy(|| {
             let logger = slog::Logger::root(slog::Discard, o!());
 
-            let mut server = HyperGraphQLServer::new(&logger);
-            let query_stream = server.query_stream().unwrap();
-            let http_server = server.serve(8002).expect("Failed to start GraphQL server");
+            let mut server = HyperGraphQLServer::new(
+                &logger,
+                GraphQLServerConfig::default(),
+                Arc::new(TestQueryRunner),
+            );
+            let http_server = server
+                .serve(&[local_addr(8002)])
+                .expect("Failed to start GraphQL server");
 
             // Launch the server to handle a single request
-            simulate_running_one_query(query_stream);
             tokio::spawn(http_server.fuse());
 
             // Create a simple schema and send it to the server
@@ -175,12 +212,16 @@ fn accepts_valid_queries() {
         .block_on(futures::lazy(|| {
             let logger = slog::Logger::root(slog::Discard, o!());
 
-            let mut server = HyperGraphQLServer::new(&logger);
-            let query_stream = server.query_stream().unwrap();
-            let http_server = server.serve(8003).expect("Failed to start GraphQL server");
+            let mut server = HyperGraphQLServer::new(
+                &logger,
+                GraphQLServerConfig::default(),
+                Arc::new(TestQueryRunner),
+            );
+            let http_server = server
+                .serve(&[local_addr(8003)])
+                .expect("Failed to start GraphQL server");
 
             // Launch the server to handle a single request
-            simulate_running_one_query(query_stream);
             tokio::spawn(http_server.fuse());
 
             // Create a simple schema and send it to the server
@@ -217,3 +258,247 @@ fn accepts_valid_queries() {
         }))
         .unwrap()
 }
+
+#[test]
+fn returns_404_for_removed_subgraphs() {
+    let mut runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime
+        .block_on(futures::lazy(|| {
+            let logger = slog::Logger::root(slog::Discard, o!());
+
+            let mut server = HyperGraphQLServer::new(
+                &logger,
+                GraphQLServerConfig::default(),
+                Arc::new(TestQueryRunner),
+            );
+            let http_server = server
+                .serve(&[local_addr(8004)])
+                .expect("Failed to start GraphQL server");
+
+            tokio::spawn(http_server.fuse());
+
+            // Add a simple schema
+            let schema = Schema {
+                id: "test-schema".to_string(),
+                document: Default::default(),
+            };
+            let schema_event_sink = server.schema_event_sink();
+            schema_event_sink
+                .clone()
+                .send(SchemaEvent::SchemaAdded(schema.clone()))
+                .wait()
+                .expect("Failed to send schema to server");
+
+            // Remove the schema once it is served
+            query_until_status(8004, StatusCode::OK)
+                .and_then(move |response| {
+                    assert_eq!(response.status(), StatusCode::OK);
+                    schema_event_sink
+                        .send(SchemaEvent::SchemaRemoved(schema))
+                        .wait()
+                        .expect("Failed to send schema removal to server");
+                    query_until_status(8004, StatusCode::NOT_FOUND)
+                })
+                .and_then(|response| {
+                    // Queries for the removed subgraph must be 404s
+                    test_utils::assert_error_response(response, StatusCode::NOT_FOUND);
+                    Ok(())
+                })
+        }))
+        .unwrap()
+}
+
+#[test]
+fn serves_on_multiple_addresses() {
+    let mut runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime
+        .block_on(futures::lazy(|| {
+            let logger = slog::Logger::root(slog::Discard, o!());
+
+            let mut server = HyperGraphQLServer::new(
+                &logger,
+                GraphQLServerConfig::default(),
+                Arc::new(TestQueryRunner),
+            );
+            let http_server = server
+                .serve(&[local_addr(8005), local_addr(8006)])
+                .expect("Failed to start GraphQL server");
+            tokio::spawn(http_server.fuse());
+
+            // Create a simple schema and send it to the server
+            let schema = Schema {
+                id: "test-schema".to_string(),
+                document: Default::default(),
+            };
+            server
+                .schema_event_sink()
+                .send(SchemaEvent::SchemaAdded(schema))
+                .wait()
+                .expect("Failed to send schema to server");
+
+            // Send the same valid query to both addresses
+            let client = Client::new();
+            let requests = vec![8005, 8006].into_iter().map(move |port| {
+                let uri = format!("http://localhost:{}/graphql", port);
+                let request = Request::post(uri.as_str())
+                    .body(Body::from("{\"query\": \"{ name }\"}"))
+                    .unwrap();
+                client.request(request)
+            });
+
+            // Both responses must be a 200
+            futures::future::join_all(requests).and_then(|responses| {
+                for response in responses {
+                    test_utils::assert_successful_response(response);
+                }
+                Ok(())
+            })
+        }))
+        .unwrap()
+}
+
+#[test]
+fn serves_on_ipv4_and_ipv6_with_the_same_port() {
+    let mut runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime
+        .block_on(futures::lazy(|| {
+            let logger = slog::Logger::root(slog::Discard, o!());
+
+            let mut server = HyperGraphQLServer::new(
+                &logger,
+                GraphQLServerConfig::default(),
+                Arc::new(TestQueryRunner),
+            );
+            let http_server = server
+                .serve(&[
+                    SocketAddr::from(([0, 0, 0, 0], 8007)),
+                    SocketAddr::from(([0, 0, 0, 0, 0, 0, 0, 0], 8007)),
+                ])
+                .expect("Failed to start GraphQL server");
+            tokio::spawn(http_server.fuse());
+
+            let schema = Schema {
+                id: "test-schema".to_string(),
+                document: Default::default(),
+            };
+            server
+                .schema_event_sink()
+                .send(SchemaEvent::SchemaAdded(schema))
+                .wait()
+                .expect("Failed to send schema to server");
+
+            let client = Client::new();
+            let request = Request::post("http://127.0.0.1:8007/graphql")
+                .body(Body::from("{\"query\": \"{ name }\"}"))
+                .unwrap();
+            client.request(request).and_then(|response| {
+                test_utils::assert_successful_response(response);
+                Ok(())
+            })
+        }))
+        .unwrap()
+}
+
+#[test]
+fn reports_addresses_that_cannot_be_listened_on() {
+    let mut runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime
+        .block_on(futures::lazy(|| {
+            let logger = slog::Logger::root(slog::Discard, o!());
+
+            let mut server = HyperGraphQLServer::new(
+                &logger,
+                GraphQLServerConfig::default(),
+                Arc::new(TestQueryRunner),
+            );
+
+            // Another process is already listening on one of the addresses
+            let _listener = std::net::TcpListener::bind(local_addr(8008)).unwrap();
+            let error = server
+                .serve(&[local_addr(8009), local_addr(8008)])
+                .err()
+                .expect("Listened on an address that is in use");
+            assert!(error.to_string().contains("127.0.0.1:8008"));
+            assert!(!error.to_string().contains("127.0.0.1:8009"));
+            Ok::<(), ()>(())
+        }))
+        .unwrap()
+}
+
+#[cfg(unix)]
+#[test]
+fn serves_on_a_unix_socket() {
+    use std::fs;
+    use std::io::{Read, Write};
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::{env, process, thread, time::Duration};
+
+    let path = env::temp_dir().join(format!("graph-node-test-{}.sock", process::id()));
+    let file_path = env::temp_dir().join(format!("graph-node-test-{}.txt", process::id()));
+
+    // Leave a stale socket behind, as a node that crashed would
+    drop(UnixListener::bind(&path).expect("Failed to create stale socket"));
+    fs::write(&file_path, "not a socket").unwrap();
+
+    let mut runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime
+        .block_on(futures::lazy(|| {
+            let logger = slog::Logger::root(slog::Discard, o!());
+
+            let mut server = HyperGraphQLServer::new(
+                &logger,
+                GraphQLServerConfig::default(),
+                Arc::new(TestQueryRunner),
+            );
+
+            // Files other than sockets are not replaced
+            assert!(server.serve_unix(&file_path, None).is_err());
+            assert_eq!(fs::read_to_string(&file_path).unwrap(), "not a socket");
+
+            let unix_server = server
+                .serve_unix(&path, Some(0o600))
+                .expect("Failed to start GraphQL unix socket server");
+            tokio::spawn(unix_server.fuse());
+
+            let schema = Schema {
+                id: "test-schema".to_string(),
+                document: Default::default(),
+            };
+            server
+                .schema_event_sink()
+                .send(SchemaEvent::SchemaAdded(schema))
+                .wait()
+                .expect("Failed to send schema to server");
+            Ok::<(), ()>(())
+        }))
+        .unwrap();
+
+    let mode = fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+
+    // Send a valid query until the schema is served
+    let body = "{\"query\": \"{ name }\"}";
+    let request = format!(
+        "POST /graphql HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    );
+    let mut response = String::new();
+    for _ in 0..50 {
+        let mut stream = UnixStream::connect(&path).expect("Failed to connect to socket");
+        stream.write_all(request.as_bytes()).unwrap();
+        response.clear();
+        stream.read_to_string(&mut response).unwrap();
+        if response.starts_with("HTTP/1.1 200") {
+            break;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    assert!(response.contains("Jordi"), "{}", response);
+
+    fs::remove_file(&path).unwrap();
+    fs::remove_file(&file_path).unwrap();
+}
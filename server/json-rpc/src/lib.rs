@@ -4,22 +4,29 @@ extern crate serde;
 extern crate serde_derive;
 extern crate graph;
 
+use graph::data::usage::current_timestamp;
 use graph::prelude::{JsonRpcServer as JsonRpcServerTrait, *};
 use graph::serde_json;
 use jsonrpc_http_server::{
-    jsonrpc_core::{self, Id, IoHandler, MethodCall, Params, Value, Version},
+    jsonrpc_core::{self, Id, IoHandler, Params, Value, Version},
     RestApi, Server, ServerBuilder,
 };
 use std::fmt;
 use std::io;
-use std::net::{Ipv4Addr, SocketAddrV4};
+use std::net::SocketAddr;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+
+pub use jsonrpc_http_server::jsonrpc_core::MethodCall;
 
 #[derive(Debug, Serialize, Deserialize)]
 struct SubgraphAddParams {
     name: String,
     ipfs_hash: String,
+    /// Adds the subgraph even if its mappings changed since it was indexed.
+    #[serde(default)]
+    allow_mapping_changes: bool,
 }
 
 impl fmt::Display for SubgraphAddParams {
@@ -28,67 +35,527 @@ impl fmt::Display for SubgraphAddParams {
     }
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+struct ApiKeyCreateParams {
+    name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ApiKeyParams {
+    key: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ApiKeyRenameParams {
+    key: String,
+    name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SubgraphIdParams {
+    subgraph_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EntityGetParams {
+    subgraph_id: String,
+    entity: String,
+    id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct NoParams {}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SubgraphLogsParams {
+    subgraph_id: String,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexingThroughputParams {
+    subgraph_id: String,
+    /// How many hours of history to return, 24 by default.
+    hours: Option<u64>,
+}
+
+/// How many hours of throughput history are returned by default.
+const DEFAULT_THROUGHPUT_HOURS: u64 = 24;
+
 pub struct JsonRpcServer {}
 
 impl JsonRpcServerTrait for JsonRpcServer {
-    type Server = Server;
+    type Server = Vec<Server>;
 
     fn serve(
-        port: u16,
+        addrs: &[SocketAddr],
         provider: Arc<impl SubgraphProvider>,
+        api_key_store: Arc<Mutex<impl ApiKeyStore + Send + 'static>>,
+        compression_store: Arc<Mutex<impl CompressionStore + Send + 'static>>,
+        usage_store: Arc<Mutex<impl DeploymentUsageStore + Send + 'static>>,
+        metrics_store: Arc<Mutex<impl StoreMetrics + Send + 'static>>,
+        entity_store: Arc<Mutex<impl BasicStore + Send + 'static>>,
+        lag_status: Arc<impl IndexingLagStatus + Send + Sync + 'static>,
+        mapping_logs: Arc<impl MappingLogCapture + Send + Sync + 'static>,
+        throughput_store: Arc<Mutex<impl ThroughputStore + Send + 'static>>,
         logger: Logger,
     ) -> Result<Self::Server, io::Error> {
-        let addr = SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), port);
-
-        let mut handler = IoHandler::new();
-
-        // `subgraph_add` handler.
-        let add_provider = provider.clone();
-        let add_logger = logger.clone();
-        let subgraph_added = AtomicBool::new(false);
-        let add_handler = move |params: SubgraphAddParams| {
-            let provider = add_provider.clone();
-            info!(add_logger, "Received subgraph_add request"; "params" => params.to_string());
-            provider
-                .add(format!("/ipfs/{}", params.ipfs_hash))
-                .map_err(|e| json_rpc_error(0, e.to_string()))
-                .map(|_| Ok(Value::Null))
-                .flatten()
-        };
-        handler.add_method("subgraph_add", move |params: Params| {
-            let handler = add_handler.clone();
-            let added = subgraph_added.load(Ordering::SeqCst);
-            subgraph_added.store(true, Ordering::SeqCst);
-            if added {
-                Err(json_rpc_error(
-                    1,
-                    "adding multiple subgraphs is not yet supported".to_owned(),
-                ))
-            } else {
-                Ok(())
-            }.into_future()
-                .and_then(|_| params.parse())
-                .and_then(handler)
-        });
-
-        ServerBuilder::new(handler)
-            // Enable REST API:
-            // POST /<method>/<param1>/<param2>
-            .rest_api(RestApi::Secure)
-            .start_http(&addr.into())
+        if addrs.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no addresses to serve the admin API on",
+            ));
+        }
+
+        // Shared by all addresses, so that only one subgraph can be added overall
+        let subgraph_added = Arc::new(AtomicBool::new(false));
+
+        addrs
+            .iter()
+            .map(|addr| {
+                let mut handler =
+                    io_handler(provider.clone(), logger.clone(), subgraph_added.clone());
+                add_api_key_methods(&mut handler, api_key_store.clone(), logger.clone());
+                add_compression_methods(&mut handler, compression_store.clone(), logger.clone());
+                add_usage_methods(&mut handler, usage_store.clone());
+                add_metrics_methods(&mut handler, metrics_store.clone());
+                add_entity_methods(&mut handler, entity_store.clone());
+                add_lag_methods(&mut handler, lag_status.clone());
+                add_mapping_log_methods(&mut handler, mapping_logs.clone());
+                add_throughput_methods(&mut handler, throughput_store.clone());
+
+                let server = ServerBuilder::new(handler)
+                    // Enable REST API:
+                    // POST /<method>/<param1>/<param2>
+                    .rest_api(RestApi::Secure)
+                    .start_http(addr)
+                    .map_err(|e| {
+                        io::Error::new(e.kind(), format!("failed to listen on {}: {}", addr, e))
+                    })?;
+
+                info!(logger, "Listening for admin requests"; "address" => addr.to_string());
+                Ok::<_, io::Error>(server)
+            })
+            .collect()
     }
 }
 
-fn json_rpc_error(code: i64, message: String) -> jsonrpc_core::Error {
+/// Creates the handler for all JSON-RPC methods of the admin API.
+fn io_handler<P: SubgraphProvider>(
+    provider: Arc<P>,
+    logger: Logger,
+    subgraph_added: Arc<AtomicBool>,
+) -> IoHandler {
+    let mut handler = IoHandler::new();
+
+    // `subgraph_add` handler.
+    let add_provider = provider.clone();
+    let add_logger = logger.clone();
+    let add_handler = move |params: SubgraphAddParams| {
+        let provider = add_provider.clone();
+        info!(add_logger, "Received subgraph_add request"; "params" => params.to_string());
+        provider
+            .add(
+                params.name,
+                format!("/ipfs/{}", params.ipfs_hash),
+                params.allow_mapping_changes,
+            )
+            .map_err(|e| json_rpc_error(0, GraphError::from(e)))
+            .map(|_| Ok(Value::Null))
+            .flatten()
+    };
+    handler.add_method("subgraph_add", move |params: Params| {
+        let handler = add_handler.clone();
+        let added = subgraph_added.swap(true, Ordering::SeqCst);
+        if added {
+            Err(json_rpc_error(
+                1,
+                GraphError::from_message(
+                    ErrorCategory::Config,
+                    "adding multiple subgraphs is not yet supported",
+                ),
+            ))
+        } else {
+            Ok(())
+        }.into_future()
+            .and_then(|_| params.parse())
+            .and_then(handler)
+    });
+
+    handler
+}
+
+/// Adds the methods for managing API keys to the admin API.
+fn add_api_key_methods<S: ApiKeyStore + Send + 'static>(
+    handler: &mut IoHandler,
+    store: Arc<Mutex<S>>,
+    logger: Logger,
+) {
+    // `api_key_create` handler.
+    let create_store = store.clone();
+    let create_logger = logger.clone();
+    handler.add_method("api_key_create", move |params: Params| {
+        let params: ApiKeyCreateParams = params.parse()?;
+        info!(create_logger, "Received api_key_create request"; "name" => &params.name);
+        create_store
+            .lock()
+            .unwrap()
+            .create_api_key(params.name)
+            .map(|api_key| api_key_value(&api_key))
+            .map_err(|e| json_rpc_error(2, e))
+    });
+
+    // `api_key_list` handler.
+    let list_store = store.clone();
+    handler.add_method("api_key_list", move |_params: Params| {
+        list_store
+            .lock()
+            .unwrap()
+            .api_keys()
+            .map(|api_keys| Value::Array(api_keys.iter().map(api_key_value).collect()))
+            .map_err(|e| json_rpc_error(2, e))
+    });
+
+    // `api_key_get` handler.
+    let get_store = store.clone();
+    handler.add_method("api_key_get", move |params: Params| {
+        let params: ApiKeyParams = params.parse()?;
+        get_store
+            .lock()
+            .unwrap()
+            .api_key(&params.key)
+            .map_err(|e| json_rpc_error(2, e))
+            .and_then(|api_key| api_key.ok_or_else(api_key_not_found))
+            .map(|api_key| api_key_value(&api_key))
+    });
+
+    // `api_key_rename` handler.
+    let rename_store = store.clone();
+    handler.add_method("api_key_rename", move |params: Params| {
+        let params: ApiKeyRenameParams = params.parse()?;
+        rename_store
+            .lock()
+            .unwrap()
+            .rename_api_key(&params.key, params.name)
+            .map_err(|e| json_rpc_error(2, e))
+            .and_then(|renamed| if renamed { Ok(Value::Null) } else { Err(api_key_not_found()) })
+    });
+
+    // `api_key_delete` handler.
+    let delete_store = store;
+    let delete_logger = logger;
+    handler.add_method("api_key_delete", move |params: Params| {
+        let params: ApiKeyParams = params.parse()?;
+        info!(delete_logger, "Received api_key_delete request");
+        delete_store
+            .lock()
+            .unwrap()
+            .delete_api_key(&params.key)
+            .map_err(|e| json_rpc_error(2, e))
+            .and_then(|deleted| if deleted { Ok(Value::Null) } else { Err(api_key_not_found()) })
+    });
+}
+
+/// Adds the methods for compressing and deleting dormant subgraphs to the admin
+/// API.
+fn add_compression_methods<S: CompressionStore + Send + 'static>(
+    handler: &mut IoHandler,
+    store: Arc<Mutex<S>>,
+    logger: Logger,
+) {
+    // `subgraph_compress` handler.
+    let compress_store = store.clone();
+    let compress_logger = logger.clone();
+    handler.add_method("subgraph_compress", move |params: Params| {
+        let params: SubgraphIdParams = params.parse()?;
+        info!(compress_logger, "Received subgraph_compress request";
+              "subgraph" => &params.subgraph_id);
+        compress_store
+            .lock()
+            .unwrap()
+            .compress_subgraph(&params.subgraph_id)
+            .map(|compressed| compressed_subgraph_value(&compressed))
+            .map_err(|e| json_rpc_error(2, e))
+    });
+
+    // `subgraph_decompress` handler.
+    let decompress_store = store.clone();
+    let decompress_logger = logger.clone();
+    handler.add_method("subgraph_decompress", move |params: Params| {
+        let params: SubgraphIdParams = params.parse()?;
+        info!(decompress_logger, "Received subgraph_decompress request";
+              "subgraph" => &params.subgraph_id);
+        decompress_store
+            .lock()
+            .unwrap()
+            .decompress_subgraph(&params.subgraph_id)
+            .map_err(|e| json_rpc_error(2, e))
+            .and_then(|decompressed| {
+                if decompressed {
+                    Ok(Value::Null)
+                } else {
+                    Err(subgraph_not_compressed())
+                }
+            })
+    });
+
+    // `subgraph_delete` handler.
+    let delete_store = store.clone();
+    let delete_logger = logger;
+    handler.add_method("subgraph_delete", move |params: Params| {
+        let params: SubgraphIdParams = params.parse()?;
+        info!(delete_logger, "Received subgraph_delete request";
+              "subgraph" => &params.subgraph_id);
+        delete_store
+            .lock()
+            .unwrap()
+            .delete_subgraph(&params.subgraph_id)
+            .map(Value::from)
+            .map_err(|e| json_rpc_error(2, e))
+    });
+
+    // `subgraph_compressed_list` handler.
+    let list_store = store;
+    handler.add_method("subgraph_compressed_list", move |_params: Params| {
+        list_store
+            .lock()
+            .unwrap()
+            .compressed_subgraphs()
+            .map(|subgraphs| {
+                Value::Array(subgraphs.iter().map(compressed_subgraph_value).collect())
+            })
+            .map_err(|e| json_rpc_error(2, e))
+    });
+}
+
+/// Adds the method for reporting the usage of deployments to the admin API.
+fn add_usage_methods<S: DeploymentUsageStore + Send + 'static>(
+    handler: &mut IoHandler,
+    store: Arc<Mutex<S>>,
+) {
+    // `deployment_usage` handler.
+    handler.add_method("deployment_usage", move |_params: Params| {
+        let now = current_timestamp();
+        store
+            .lock()
+            .unwrap()
+            .deployment_usage()
+            .map(|usage| {
+                Value::Array(
+                    usage
+                        .iter()
+                        .map(|usage| deployment_usage_value(usage, now))
+                        .collect(),
+                )
+            })
+            .map_err(|e| json_rpc_error(2, e))
+    });
+}
+
+/// Adds the method for reporting store latencies to the admin API.
+fn add_metrics_methods<S: StoreMetrics + Send + 'static>(
+    handler: &mut IoHandler,
+    store: Arc<Mutex<S>>,
+) {
+    // `store_latencies` handler.
+    handler.add_method("store_latencies", move |_params: Params| {
+        let latencies = store.lock().unwrap().store_latencies();
+        Ok(serde_json::to_value(latencies).expect("Failed to serialize store latencies"))
+    });
+}
+
+/// Adds the method for looking up the latest version of an entity to the
+/// admin API.
+fn add_entity_methods<S: BasicStore + Send + 'static>(
+    handler: &mut IoHandler,
+    store: Arc<Mutex<S>>,
+) {
+    // `entity_get` handler.
+    handler.add_method("entity_get", move |params: Params| {
+        let params: EntityGetParams = params.parse()?;
+        let key = StoreKey {
+            subgraph: params.subgraph_id,
+            entity: params.entity,
+            id: params.id,
+        };
+        store
+            .lock()
+            .unwrap()
+            .get(key)
+            .map(|entity| serde_json::to_value(entity).expect("Failed to serialize entity"))
+            .map_err(|_| entity_not_found())
+    });
+}
+
+/// Adds the method for reporting how far subgraphs are behind the chain head
+/// to the admin API.
+fn add_lag_methods<S: IndexingLagStatus + Send + Sync + 'static>(
+    handler: &mut IoHandler,
+    status: Arc<S>,
+) {
+    // `indexing_lag` handler.
+    handler.add_method("indexing_lag", move |_params: Params| {
+        let lags = status.indexing_lags();
+        Ok(serde_json::to_value(lags).expect("Failed to serialize indexing lags"))
+    });
+}
+
+/// Adds the method for reading the recent log messages of the mappings of a
+/// subgraph to the admin API.
+fn add_mapping_log_methods<S: MappingLogCapture + Send + Sync + 'static>(
+    handler: &mut IoHandler,
+    mapping_logs: Arc<S>,
+) {
+    // `subgraph_logs` handler.
+    handler.add_method("subgraph_logs", move |params: Params| {
+        let params: SubgraphLogsParams = params.parse()?;
+        let entries = mapping_logs.mapping_logs(&params.subgraph_id, params.limit);
+        Ok(serde_json::to_value(entries).expect("Failed to serialize mapping logs"))
+    });
+}
+
+/// Adds the method for reporting the throughput history of a subgraph to the
+/// admin API.
+fn add_throughput_methods<S: ThroughputStore + Send + 'static>(
+    handler: &mut IoHandler,
+    store: Arc<Mutex<S>>,
+) {
+    // `indexing_throughput` handler.
+    handler.add_method("indexing_throughput", move |params: Params| {
+        let params: IndexingThroughputParams = params.parse()?;
+        let hours = params.hours.unwrap_or(DEFAULT_THROUGHPUT_HOURS);
+        let since = current_timestamp().saturating_sub(hours * 60 * 60);
+        store
+            .lock()
+            .unwrap()
+            .throughput_history(&params.subgraph_id, since)
+            .map(|samples| Value::Array(samples.iter().map(throughput_sample_value).collect()))
+            .map_err(|e| json_rpc_error(2, e))
+    });
+}
+
+/// Serializes the usage of a deployment along with how long it has been idle.
+fn deployment_usage_value(usage: &DeploymentUsage, now: u64) -> Value {
+    let mut value = serde_json::to_value(usage).expect("Failed to serialize deployment usage");
+    value["idle_seconds"] = Value::from(usage.idle_seconds(now));
+    value
+}
+
+/// Serializes a throughput sample along with its rates per second.
+fn throughput_sample_value(sample: &ThroughputSample) -> Value {
+    let mut value = serde_json::to_value(sample).expect("Failed to serialize throughput sample");
+    value["blocks_per_second"] = Value::from(sample.blocks_per_second());
+    value["triggers_per_second"] = Value::from(sample.triggers_per_second());
+    value["entities_per_second"] = Value::from(sample.entities_per_second());
+    value
+}
+
+/// Serializes a compressed subgraph along with its compression ratio.
+fn compressed_subgraph_value(compressed: &CompressedSubgraph) -> Value {
+    let mut value =
+        serde_json::to_value(compressed).expect("Failed to serialize compressed subgraph");
+    value["ratio"] = serde_json::to_value(compressed.ratio())
+        .expect("Failed to serialize compression ratio");
+    value
+}
+
+/// Serializes an API key along with the error rate of its queries.
+fn api_key_value(api_key: &ApiKey) -> Value {
+    let mut value = serde_json::to_value(api_key).expect("Failed to serialize API key");
+    value["usage"]["error_rate"] = serde_json::to_value(api_key.usage.error_rate())
+        .expect("Failed to serialize API key error rate");
+    value
+}
+
+fn api_key_not_found() -> jsonrpc_core::Error {
+    json_rpc_error(
+        3,
+        GraphError::from_message(ErrorCategory::Query, "API key not found"),
+    )
+}
+
+fn subgraph_not_compressed() -> jsonrpc_core::Error {
+    json_rpc_error(
+        4,
+        GraphError::from_message(ErrorCategory::Query, "Subgraph is not compressed"),
+    )
+}
+
+fn entity_not_found() -> jsonrpc_core::Error {
+    json_rpc_error(
+        5,
+        GraphError::from_message(ErrorCategory::Query, "Entity not found"),
+    )
+}
+
+fn json_rpc_error(code: i64, error: GraphError) -> jsonrpc_core::Error {
     jsonrpc_core::Error {
         code: jsonrpc_core::ErrorCode::ServerError(code),
-        message,
-        data: None,
+        message: error.to_string(),
+        data: Some(Value::String(error.api_code().to_owned())),
     }
 }
 
-pub fn subgraph_add_request(name: String, ipfs_hash: String, id: String) -> MethodCall {
-    let params = serde_json::to_value(SubgraphAddParams { name, ipfs_hash })
+pub fn subgraph_add_request(
+    name: String,
+    ipfs_hash: String,
+    allow_mapping_changes: bool,
+    id: String,
+) -> MethodCall {
+    method_call(
+        "subgraph_add",
+        SubgraphAddParams {
+            name,
+            ipfs_hash,
+            allow_mapping_changes,
+        },
+        id,
+    )
+}
+
+pub fn subgraph_delete_request(subgraph_id: String, id: String) -> MethodCall {
+    method_call("subgraph_delete", SubgraphIdParams { subgraph_id }, id)
+}
+
+pub fn deployment_usage_request(id: String) -> MethodCall {
+    method_call("deployment_usage", NoParams {}, id)
+}
+
+pub fn indexing_lag_request(id: String) -> MethodCall {
+    method_call("indexing_lag", NoParams {}, id)
+}
+
+pub fn indexing_throughput_request(
+    subgraph_id: String,
+    hours: Option<u64>,
+    id: String,
+) -> MethodCall {
+    method_call(
+        "indexing_throughput",
+        IndexingThroughputParams { subgraph_id, hours },
+        id,
+    )
+}
+
+pub fn entity_get_request(
+    subgraph_id: String,
+    entity: String,
+    entity_id: String,
+    id: String,
+) -> MethodCall {
+    method_call(
+        "entity_get",
+        EntityGetParams {
+            subgraph_id,
+            entity,
+            id: entity_id,
+        },
+        id,
+    )
+}
+
+fn method_call<P: serde::Serialize>(method: &str, params: P, id: String) -> MethodCall {
+    let params = serde_json::to_value(params)
         .unwrap()
         .as_object()
         .cloned()
@@ -96,13 +563,18 @@ pub fn subgraph_add_request(name: String, ipfs_hash: String, id: String) -> Meth
 
     MethodCall {
         jsonrpc: Some(Version::V2),
-        method: "subgraph_add".to_owned(),
+        method: method.to_owned(),
         params: Params::Map(params),
         id: Id::Str(id),
     }
 }
 
 pub fn parse_response(response: Value) -> Result<(), jsonrpc_core::Error> {
+    parse_result(response).map(|_| ())
+}
+
+/// Returns the result of a JSON-RPC response, or its error.
+pub fn parse_result(response: Value) -> Result<Value, jsonrpc_core::Error> {
     // serde deserialization of the `id` field to an `Id` struct is somehow
     // incompatible with the `arbitrary-precision` feature which we use, so we
     // need custom parsing logic.
@@ -110,6 +582,6 @@ pub fn parse_response(response: Value) -> Result<(), jsonrpc_core::Error> {
     if let Some(error) = object.get("error") {
         Err(serde_json::from_value(error.clone()).unwrap())
     } else {
-        Ok(())
+        Ok(object.get("result").cloned().unwrap_or(Value::Null))
     }
 }
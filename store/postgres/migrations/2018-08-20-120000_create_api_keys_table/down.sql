@@ -0,0 +1,4 @@
+/**************************************************************
+* DROP TABLE
+**************************************************************/
+DROP TABLE api_keys;
@@ -0,0 +1,11 @@
+/**************************************************************
+* CREATE TABLE
+**************************************************************/
+CREATE TABLE IF NOT EXISTS api_keys (
+     key VARCHAR NOT NULL PRIMARY KEY,
+     name VARCHAR NOT NULL,
+     query_count BIGINT NOT NULL DEFAULT 0,
+     error_count BIGINT NOT NULL DEFAULT 0,
+     cost BIGINT NOT NULL DEFAULT 0,
+     created_at TIMESTAMP NOT NULL DEFAULT now()
+ );
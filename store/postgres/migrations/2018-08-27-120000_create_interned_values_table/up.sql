@@ -0,0 +1,7 @@
+/**************************************************************
+* CREATE TABLE
+**************************************************************/
+CREATE TABLE IF NOT EXISTS interned_values (
+     hash VARCHAR NOT NULL PRIMARY KEY,
+     value TEXT NOT NULL
+ );
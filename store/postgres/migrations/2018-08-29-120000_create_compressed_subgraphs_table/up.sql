@@ -0,0 +1,13 @@
+/**************************************************************
+* CREATE TABLE
+**************************************************************/
+-- Stores the entities of dormant subgraphs, compressed into a single blob
+-- per subgraph
+CREATE TABLE IF NOT EXISTS compressed_subgraphs (
+     subgraph VARCHAR NOT NULL PRIMARY KEY,
+     entity_count BIGINT NOT NULL,
+     size BIGINT NOT NULL,
+     compressed_size BIGINT NOT NULL,
+     data BYTEA NOT NULL,
+     compressed_at TIMESTAMP NOT NULL DEFAULT now()
+ );
@@ -0,0 +1,11 @@
+/**************************************************************
+* CREATE TABLE
+**************************************************************/
+-- Tracks when subgraph deployments were last queried; timestamps are in
+-- seconds since the Unix epoch
+CREATE TABLE IF NOT EXISTS deployment_usage (
+     subgraph VARCHAR NOT NULL PRIMARY KEY,
+     query_count BIGINT NOT NULL DEFAULT 0,
+     tracked_since BIGINT NOT NULL,
+     last_queried_at BIGINT
+ );
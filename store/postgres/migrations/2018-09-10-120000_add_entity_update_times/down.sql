@@ -0,0 +1,5 @@
+/**************************************************************
+* DROP COLUMN
+**************************************************************/
+DROP INDEX entities_updated_at;
+ALTER TABLE entities DROP COLUMN updated_at;
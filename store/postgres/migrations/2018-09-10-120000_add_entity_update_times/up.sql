@@ -0,0 +1,9 @@
+/**************************************************************
+* ADD COLUMN
+**************************************************************/
+-- When an entity was last set, in seconds since the Unix epoch; entities
+-- whose type has a retention period are pruned once they are older than it
+ALTER TABLE entities
+    ADD COLUMN updated_at BIGINT NOT NULL DEFAULT extract(epoch FROM now())::BIGINT;
+
+CREATE INDEX entities_updated_at ON entities (subgraph, entity, updated_at);
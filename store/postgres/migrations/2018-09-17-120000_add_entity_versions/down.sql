@@ -0,0 +1,4 @@
+/**************************************************************
+* DROP COLUMN
+**************************************************************/
+ALTER TABLE entities DROP COLUMN version;
@@ -0,0 +1,7 @@
+/**************************************************************
+* ADD COLUMN
+**************************************************************/
+-- Starts at 1 and is bumped whenever the entity is set, so that writers
+-- can detect when an entity was modified since they read it
+ALTER TABLE entities
+    ADD COLUMN version BIGINT NOT NULL DEFAULT 1;
@@ -0,0 +1,16 @@
+/**************************************************************
+* CREATE TABLE
+**************************************************************/
+-- Entities written by offchain handlers, e.g. from files on IPFS, kept
+-- apart from the entities written for blocks so that they can be dropped
+-- and fetched again without touching onchain state. Every source the data
+-- was fetched from is a separate region with its own row.
+CREATE TABLE IF NOT EXISTS offchain_entities (
+     id VARCHAR NOT NULL,
+     subgraph VARCHAR NOT NULL,
+     entity VARCHAR NOT NULL,
+     source VARCHAR NOT NULL,
+     data JSONB NOT NULL,
+     updated_at BIGINT NOT NULL,
+     PRIMARY KEY (subgraph, entity, id, source)
+ );
@@ -0,0 +1,16 @@
+/**************************************************************
+* DROP VIEW
+**************************************************************/
+DROP VIEW history.entities;
+DROP SCHEMA history;
+
+/**************************************************************
+* DROP INDEXES
+**************************************************************/
+DROP INDEX entity_history_entity_event_id;
+DROP INDEX entity_history_subgraph_event_id;
+
+/**************************************************************
+* DROP TABLE
+**************************************************************/
+DROP TABLE history_blocks;
@@ -0,0 +1,58 @@
+/**************************************************************
+* CREATE TABLE
+**************************************************************/
+-- The number of every block that a subgraph wrote or deleted entities
+-- for, so that the history of entities can be looked up by block number;
+-- block hashes are formatted as in the `source` of `event_meta_data`
+CREATE TABLE IF NOT EXISTS history_blocks (
+     subgraph VARCHAR NOT NULL,
+     block_hash VARCHAR NOT NULL,
+     block_number BIGINT NOT NULL,
+     PRIMARY KEY (subgraph, block_hash)
+ );
+
+CREATE INDEX history_blocks_block_number ON history_blocks (subgraph, block_number);
+
+/**************************************************************
+* CREATE INDEXES
+**************************************************************/
+CREATE INDEX entity_history_subgraph_event_id
+    ON entity_history (subgraph, event_id);
+CREATE INDEX entity_history_entity_event_id
+    ON entity_history (subgraph, entity, entity_id, event_id);
+
+/**************************************************************
+* CREATE VIEW
+**************************************************************/
+-- The entities as they were right after the event whose ID is in the
+-- `vars.history_event_id` setting, with the same columns as `entities`.
+-- Queries see them instead of the latest entities when `history` comes
+-- first in the `search_path`.
+CREATE SCHEMA IF NOT EXISTS history;
+
+CREATE VIEW history.entities AS
+    -- Entities that have not changed since the event
+    SELECT e.id, e.subgraph, e.entity, e.data, e.event_source, e.updated_at, e.version
+    FROM public.entities e
+    WHERE NOT EXISTS (
+        SELECT 1
+        FROM public.entity_history h
+        WHERE h.subgraph = e.subgraph
+          AND h.entity = e.entity
+          AND h.entity_id = e.id
+          AND h.event_id > current_setting('vars.history_event_id')::BIGINT
+    )
+    UNION ALL
+    -- Entities that have changed or were deleted since the event, as they
+    -- were before the first of those changes; entities that were inserted
+    -- after the event did not exist yet
+    SELECT b.entity_id, b.subgraph, b.entity, b.data_before,
+           NULL::VARCHAR, NULL::BIGINT, NULL::BIGINT
+    FROM (
+        SELECT DISTINCT ON (h.subgraph, h.entity, h.entity_id)
+            h.entity_id, h.subgraph, h.entity, h.data_before
+        FROM public.entity_history h
+        WHERE h.event_id > current_setting('vars.history_event_id')::BIGINT
+        ORDER BY h.subgraph, h.entity, h.entity_id, h.event_id, h.id
+    ) b
+    WHERE b.data_before IS NOT NULL;
@@ -0,0 +1,15 @@
+/**************************************************************
+* CREATE TABLE
+**************************************************************/
+-- The text of entities that `@fulltext` directives make searchable, one
+-- document per search and entity, parsed with the language of the search
+CREATE TABLE IF NOT EXISTS fulltext_documents (
+     subgraph VARCHAR NOT NULL,
+     name VARCHAR NOT NULL,
+     entity VARCHAR NOT NULL,
+     id VARCHAR NOT NULL,
+     document TSVECTOR NOT NULL,
+     PRIMARY KEY (subgraph, name, entity, id)
+ );
+
+CREATE INDEX fulltext_documents_document ON fulltext_documents USING GIN (document);
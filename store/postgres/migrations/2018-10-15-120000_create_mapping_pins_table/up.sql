@@ -0,0 +1,12 @@
+/**************************************************************
+* CREATE TABLE
+**************************************************************/
+-- The mappings that each data source of a deployment is indexed with, so
+-- that the deployment is not resumed with other mappings by accident
+CREATE TABLE IF NOT EXISTS mapping_pins (
+     subgraph VARCHAR NOT NULL,
+     data_source VARCHAR NOT NULL,
+     api_version VARCHAR NOT NULL,
+     module_hash VARCHAR NOT NULL,
+     PRIMARY KEY (subgraph, data_source)
+ );
@@ -0,0 +1,4 @@
+/**************************************************************
+* DROP INDEX
+**************************************************************/
+DROP INDEX entities_data;
@@ -0,0 +1,9 @@
+/**************************************************************
+* CREATE INDEX
+**************************************************************/
+-- Lets filters that are written as containment in `data` with `@>`, such as
+-- equality filters, use an index scan instead of a sequential scan.
+-- `jsonb_path_ops` only supports `@>`, but its index is much smaller than
+-- that of the default operator class, which would also support `?`, `?|`
+-- and `?&`
+CREATE INDEX IF NOT EXISTS entities_data ON entities USING GIN (data jsonb_path_ops);
@@ -0,0 +1,14 @@
+/**************************************************************
+* CREATE TABLE
+**************************************************************/
+-- Samples of how much each deployment indexed, taken once a minute; timestamps and
+-- durations are in seconds
+CREATE TABLE IF NOT EXISTS indexing_throughput (
+     subgraph VARCHAR NOT NULL,
+     started_at BIGINT NOT NULL,
+     seconds BIGINT NOT NULL,
+     blocks BIGINT NOT NULL,
+     triggers BIGINT NOT NULL,
+     entities BIGINT NOT NULL,
+     PRIMARY KEY (subgraph, started_at)
+ );
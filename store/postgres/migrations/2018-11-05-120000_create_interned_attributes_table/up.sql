@@ -0,0 +1,11 @@
+/**************************************************************
+* CREATE TABLE
+**************************************************************/
+-- The attributes of each entity type that hold interned values for at
+-- least one entity; filters and orders can't compare these values in SQL
+CREATE TABLE IF NOT EXISTS interned_attributes (
+     subgraph VARCHAR NOT NULL,
+     entity VARCHAR NOT NULL,
+     attribute VARCHAR NOT NULL,
+     PRIMARY KEY (subgraph, entity, attribute)
+ );
@@ -0,0 +1,51 @@
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use graph::serde_json;
+use std::io::{self, Read, Write};
+
+/// An entity as it is kept in compressed storage: its ID, entity type, data
+/// and event source.
+pub type EntityRow = (String, String, serde_json::Value, String);
+
+/// Compresses entity rows into a single blob. Returns the size of the data
+/// before compression along with the blob.
+pub fn compress_entities(rows: &[EntityRow]) -> io::Result<(usize, Vec<u8>)> {
+    let data = serde_json::to_vec(rows)?;
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(&data)?;
+    Ok((data.len(), encoder.finish()?))
+}
+
+/// Restores entity rows from a blob created with `compress_entities`.
+pub fn decompress_entities(blob: &[u8]) -> io::Result<Vec<EntityRow>> {
+    let mut data = vec![];
+    GzDecoder::new(blob).read_to_end(&mut data)?;
+    Ok(serde_json::from_slice(&data)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use graph::serde_json;
+
+    use super::{compress_entities, decompress_entities};
+
+    #[test]
+    fn restores_compressed_entities() {
+        let rows: Vec<_> = (0..100)
+            .map(|i| {
+                (
+                    format!("{}", i),
+                    String::from("User"),
+                    serde_json::from_str(r#"{"name": "Johnton", "age": 67}"#).unwrap(),
+                    String::from("1cYsEjD7LKVExSj0aFA8"),
+                )
+            })
+            .collect();
+
+        let (size, blob) = compress_entities(&rows).unwrap();
+        assert!(blob.len() < size);
+        assert_eq!(decompress_entities(&blob).unwrap(), rows);
+    }
+}
@@ -5,5 +5,90 @@ table! {
         entity -> Varchar,
         data -> Jsonb,
         event_source -> Varchar,
+        updated_at -> BigInt,
+        version -> BigInt,
+    }
+}
+
+table! {
+    offchain_entities (subgraph, entity, id, source) {
+        id -> Varchar,
+        subgraph -> Varchar,
+        entity -> Varchar,
+        source -> Varchar,
+        data -> Jsonb,
+        updated_at -> BigInt,
+    }
+}
+
+table! {
+    api_keys (key) {
+        key -> Varchar,
+        name -> Varchar,
+        query_count -> BigInt,
+        error_count -> BigInt,
+        cost -> BigInt,
+    }
+}
+
+table! {
+    interned_values (hash) {
+        hash -> Varchar,
+        value -> Text,
+    }
+}
+
+table! {
+    interned_attributes (subgraph, entity, attribute) {
+        subgraph -> Varchar,
+        entity -> Varchar,
+        attribute -> Varchar,
+    }
+}
+
+table! {
+    compressed_subgraphs (subgraph) {
+        subgraph -> Varchar,
+        entity_count -> BigInt,
+        size -> BigInt,
+        compressed_size -> BigInt,
+        data -> Bytea,
+    }
+}
+
+table! {
+    deployment_usage (subgraph) {
+        subgraph -> Varchar,
+        query_count -> BigInt,
+        tracked_since -> BigInt,
+        last_queried_at -> Nullable<BigInt>,
+    }
+}
+
+table! {
+    history_blocks (subgraph, block_hash) {
+        subgraph -> Varchar,
+        block_hash -> Varchar,
+        block_number -> BigInt,
+    }
+}
+
+table! {
+    mapping_pins (subgraph, data_source) {
+        subgraph -> Varchar,
+        data_source -> Varchar,
+        api_version -> Varchar,
+        module_hash -> Varchar,
+    }
+}
+
+table! {
+    indexing_throughput (subgraph, started_at) {
+        subgraph -> Varchar,
+        started_at -> BigInt,
+        seconds -> BigInt,
+        blocks -> BigInt,
+        triggers -> BigInt,
+        entities -> BigInt,
     }
 }
@@ -1,3 +1,4 @@
+use std::mem;
 use std::str::FromStr;
 
 use bigdecimal::BigDecimal;
@@ -6,38 +7,91 @@ use diesel::dsl::{self, sql};
 use diesel::expression::NonAggregate;
 use diesel::pg::Pg;
 use diesel::prelude::*;
-use diesel::query_builder::{BoxedSelectStatement, QueryFragment};
-use diesel::sql_types::{Array, Bool, Float, Integer, Jsonb, Numeric, Text};
+use diesel::query_builder::{AstPass, BoxedSelectStatement, QueryFragment};
+use diesel::sql_types::{Array, Bool, Float, Integer, Numeric, Text};
+use diesel::result::QueryResult;
 use diesel::AppearsOnTable;
 
-use graph::components::store::StoreFilter;
+use graph::components::store::{StoreFilter, UnsupportedFilter};
 use graph::data::store::*;
 use graph::serde_json;
 use models::SqlValue;
 
-pub(crate) struct UnsupportedFilter {
-    pub filter: String,
-    pub value: Value,
-}
+/// The longest regular expression that `regex` and `not_regex` filters
+/// accept, which keeps them from being arbitrarily expensive to match.
+const MAX_REGEX_LENGTH: usize = 100;
 
 enum FilterMode {
     And,
     Or,
 }
 
-/// Adds `filter` to a `SELECT data FROM entities` statement.
-pub(crate) fn store_filter<'a>(
-    query: BoxedSelectStatement<'a, Jsonb, entities::table, Pg>,
+/// Adds `filter` to a `SELECT ... FROM entities` statement for the entities
+/// of `subgraph`.
+///
+/// Equality of strings, bytes, booleans, ints and `null`, and `contains` on
+/// lists, are written as containment of a document like `{"name": "John"}`
+/// in `data` with `@>`, which is the only operator that the `jsonb_path_ops`
+/// GIN index on `data` supports. All other filters extract the attribute
+/// with `->` or `->>`, compare it with `=`, `<`, `LIKE` or `~`, or match
+/// children with `?|`, none of which this index can serve.
+pub(crate) fn store_filter<'a, ST>(
+    query: BoxedSelectStatement<'a, ST, entities::table, Pg>,
+    subgraph: &str,
     filter: StoreFilter,
-) -> Result<BoxedSelectStatement<'a, Jsonb, entities::table, Pg>, UnsupportedFilter> {
-    store_filter_by_mode(query, filter, FilterMode::And)
+) -> Result<BoxedSelectStatement<'a, ST, entities::table, Pg>, UnsupportedFilter> {
+    store_filter_by_mode(query, subgraph, filter, FilterMode::And)
+}
+
+/// Returns the attributes that `filter` compares for entities of
+/// `entity_type`, along with their entity types, including the attributes
+/// of the child entities it matches.
+pub(crate) fn filtered_attributes(
+    entity_type: &str,
+    filter: &StoreFilter,
+) -> Vec<(String, Attribute)> {
+    let attribute = match filter {
+        StoreFilter::And(filters) | StoreFilter::Or(filters) => {
+            return filters
+                .iter()
+                .flat_map(|filter| filtered_attributes(entity_type, filter))
+                .collect()
+        }
+        StoreFilter::Child(attribute, child_type, filter) => {
+            let mut attributes = filtered_attributes(child_type, filter);
+            attributes.push((entity_type.to_owned(), attribute.clone()));
+            return attributes;
+        }
+        StoreFilter::In(attribute, _) | StoreFilter::NotIn(attribute, _) => attribute,
+        StoreFilter::Equal(attribute, _)
+        | StoreFilter::Not(attribute, _)
+        | StoreFilter::GreaterThan(attribute, _)
+        | StoreFilter::LessThan(attribute, _)
+        | StoreFilter::GreaterOrEqual(attribute, _)
+        | StoreFilter::LessOrEqual(attribute, _)
+        | StoreFilter::Contains(attribute, _)
+        | StoreFilter::NotContains(attribute, _)
+        | StoreFilter::StartsWith(attribute, _)
+        | StoreFilter::NotStartsWith(attribute, _)
+        | StoreFilter::EndsWith(attribute, _)
+        | StoreFilter::NotEndsWith(attribute, _)
+        | StoreFilter::ContainsNoCase(attribute, _)
+        | StoreFilter::NotContainsNoCase(attribute, _)
+        | StoreFilter::StartsWithNoCase(attribute, _)
+        | StoreFilter::NotStartsWithNoCase(attribute, _)
+        | StoreFilter::EndsWithNoCase(attribute, _)
+        | StoreFilter::NotEndsWithNoCase(attribute, _)
+        | StoreFilter::MatchesRegex(attribute, _)
+        | StoreFilter::NotMatchesRegex(attribute, _) => attribute,
+    };
+    vec![(entity_type.to_owned(), attribute.clone())]
 }
 
-fn add_filter<'a, P: 'a>(
-    query: BoxedSelectStatement<'a, Jsonb, entities::table, Pg>,
+fn add_filter<'a, ST, P: 'a>(
+    query: BoxedSelectStatement<'a, ST, entities::table, Pg>,
     filter_mode: FilterMode,
     predicate: P,
-) -> BoxedSelectStatement<'a, Jsonb, entities::table, Pg>
+) -> BoxedSelectStatement<'a, ST, entities::table, Pg>
 where
     P: AppearsOnTable<entities::table>
         + NonAggregate
@@ -50,19 +104,113 @@ where
     }
 }
 
-/// Adds `filter` to a `SELECT data FROM entities` statement.
-fn store_filter_by_mode<'a>(
-    query: BoxedSelectStatement<'a, Jsonb, entities::table, Pg>,
+/// Matches entities whose reference attribute holds the ID of one of the
+/// entities that `children` selects; the attribute may hold a single ID or
+/// a list of IDs.
+struct ChildFilter<'a> {
+    attribute: String,
+    children: BoxedSelectStatement<'a, Text, entities::table, Pg>,
+}
+
+impl<'a> Expression for ChildFilter<'a> {
+    type SqlType = Bool;
+}
+
+impl<'a> NonAggregate for ChildFilter<'a> {}
+
+impl<'a> AppearsOnTable<entities::table> for ChildFilter<'a> {}
+
+impl<'a> QueryFragment<Pg> for ChildFilter<'a> {
+    fn walk_ast(&self, mut out: AstPass<Pg>) -> QueryResult<()> {
+        out.push_sql("(data -> ");
+        out.push_bind_param::<Text, _>(&self.attribute)?;
+        out.push_sql(") ?| array(");
+        self.children.walk_ast(out.reborrow())?;
+        out.push_sql(")::text[]");
+        Ok(())
+    }
+}
+
+/// Returns true if both values are compared as the same SQL type.
+fn same_sql_type(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::BigInt(_), Value::BigDecimal(_)) | (Value::BigDecimal(_), Value::BigInt(_)) => true,
+        _ => mem::discriminant(a) == mem::discriminant(b),
+    }
+}
+
+/// Serializes a value to the JSON it is stored as.
+fn json_string(filter: &str, value: Value) -> Result<String, UnsupportedFilter> {
+    serde_json::to_string(&value).map_err(|_| UnsupportedFilter {
+        filter: filter.to_owned(),
+        value,
+    })
+}
+
+/// Serializes the document `{attribute: value}`, which `data` contains if
+/// the attribute is `value`, or for lists, if it contains all of `value`.
+fn json_document(
+    filter: &str,
+    attribute: String,
+    value: Value,
+) -> Result<String, UnsupportedFilter> {
+    let value = serde_json::to_value(&value).map_err(|_| UnsupportedFilter {
+        filter: filter.to_owned(),
+        value,
+    })?;
+    let mut document = serde_json::Map::new();
+    document.insert(attribute, value);
+    Ok(serde_json::Value::Object(document).to_string())
+}
+
+/// Returns true if equality with `value` can be written as containment,
+/// because the value is stored as it is serialized.
+fn compared_as_json(value: &Value) -> bool {
+    match value {
+        Value::String(_) | Value::Bytes(_) | Value::Bool(_) | Value::Int(_) | Value::Null => true,
+        Value::Float(_) | Value::BigInt(_) | Value::BigDecimal(_) | Value::List(_) => false,
+    }
+}
+
+/// Escapes the characters of `value` that have a special meaning in `LIKE`
+/// patterns, so that the pattern only matches `value` itself.
+fn like_literal(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Adds `filter` to a `SELECT ... FROM entities` statement.
+fn store_filter_by_mode<'a, ST>(
+    query: BoxedSelectStatement<'a, ST, entities::table, Pg>,
+    subgraph: &str,
     filter: StoreFilter,
     filter_mode: FilterMode,
-) -> Result<BoxedSelectStatement<'a, Jsonb, entities::table, Pg>, UnsupportedFilter> {
+) -> Result<BoxedSelectStatement<'a, ST, entities::table, Pg>, UnsupportedFilter> {
     Ok(match filter {
         StoreFilter::And(filters) => filters
             .into_iter()
-            .try_fold(query, |q, f| store_filter_by_mode(q, f, FilterMode::And))?,
+            .try_fold(query, |q, f| store_filter_by_mode(q, subgraph, f, FilterMode::And))?,
         StoreFilter::Or(filters) => filters
             .into_iter()
-            .try_fold(query, |q, f| store_filter_by_mode(q, f, FilterMode::Or))?,
+            .try_fold(query, |q, f| store_filter_by_mode(q, subgraph, f, FilterMode::Or))?,
+        StoreFilter::Child(attribute, entity_type, filter) => {
+            let children = entities::table
+                .select(entities::id)
+                .filter(entities::entity.eq(entity_type))
+                .filter(entities::subgraph.eq(subgraph.to_owned()))
+                .into_boxed::<Pg>();
+            let children = store_filter(children, subgraph, *filter)?;
+            add_filter(
+                query,
+                filter_mode,
+                ChildFilter {
+                    attribute,
+                    children,
+                },
+            )
+        }
         StoreFilter::Contains(..) | StoreFilter::NotContains(..) => {
             let (attribute, not, value) = match filter {
                 StoreFilter::Contains(attribute, value) => (attribute, false, value),
@@ -87,25 +235,32 @@ fn store_filter_by_mode<'a>(
                         .sql(op)
                         .bind::<Text, _>(query_value.to_string()),
                 ),
-                Value::List(query_value) => {
-                    let query_array =
-                        serde_json::to_string(&query_value).expect("Failed to serialize Value");
-                    // Is `query_array` contained in array `data ->> attribute`?
-                    let predicate = sql("data ->> ")
+                Value::List(query_value) => if not {
+                    let query_array = json_string("not_contains", Value::List(query_value))?;
+                    // Is `query_array` not contained in array `data -> attribute`?
+                    let predicate = sql("data -> ")
                         .bind::<Text, _>(attribute)
                         .sql(" @> ")
-                        .bind::<Text, _>(query_array);
-                    if not {
-                        add_filter(query, filter_mode, dsl::not(predicate))
-                    } else {
-                        add_filter(query, filter_mode, predicate)
-                    }
-                }
+                        .bind::<Text, _>(query_array)
+                        .sql("::jsonb");
+                    add_filter(query, filter_mode, dsl::not(predicate))
+                } else {
+                    // Is `{attribute: query_value}` contained in `data`? That
+                    // is the same as `query_value` being contained in array
+                    // `data -> attribute`, but can use the index on `data`
+                    let document = json_document("contains", attribute, Value::List(query_value))?;
+                    add_filter(
+                        query,
+                        filter_mode,
+                        sql("data @> ").bind::<Text, _>(document).sql("::jsonb"),
+                    )
+                },
                 Value::Null
                 | Value::Float(_)
                 | Value::Int(_)
                 | Value::Bool(_)
-                | Value::BigInt(_) => {
+                | Value::BigInt(_)
+                | Value::BigDecimal(_) => {
                     return Err(UnsupportedFilter {
                         filter: if not { "not_contains" } else { "contains" }.to_owned(),
                         value,
@@ -114,12 +269,23 @@ fn store_filter_by_mode<'a>(
             }
         }
         StoreFilter::Equal(..) | StoreFilter::Not(..) => {
-            let (attribute, op, value) = match filter {
-                StoreFilter::Equal(attribute, value) => (attribute, " = ", value),
-                StoreFilter::Not(attribute, value) => (attribute, " != ", value),
+            let (attribute, name, op, value) = match filter {
+                StoreFilter::Equal(attribute, value) => (attribute, "equal", " = ", value),
+                StoreFilter::Not(attribute, value) => (attribute, "not", " != ", value),
                 _ => unreachable!(),
             };
 
+            // Equality is written as containment where possible, so that it
+            // can use the index on `data`
+            if name == "equal" && compared_as_json(&value) {
+                let document = json_document(name, attribute, value)?;
+                return Ok(add_filter(
+                    query,
+                    filter_mode,
+                    sql("data @> ").bind::<Text, _>(document).sql("::jsonb"),
+                ));
+            }
+
             match value {
                 Value::String(query_value) => add_filter(
                     query,
@@ -170,8 +336,7 @@ fn store_filter_by_mode<'a>(
                 Value::List(query_value) => {
                     // Note that lists with the same elements but in different order
                     // are considered not equal.
-                    let query_array =
-                        serde_json::to_string(&query_value).expect("Failed to serialize Value");
+                    let query_array = json_string(name, Value::List(query_value))?;
                     add_filter(
                         query,
                         filter_mode,
@@ -181,18 +346,15 @@ fn store_filter_by_mode<'a>(
                             .bind::<Text, _>(query_array),
                     )
                 }
-                Value::Bytes(query_value) => {
-                    let hex_string =
-                        serde_json::to_string(&query_value).expect("Failed to serialize Value");
-                    add_filter(
-                        query,
-                        filter_mode,
-                        sql("(data ->> ")
-                            .bind::<Text, _>(attribute)
-                            .sql(op)
-                            .bind::<Text, _>(hex_string),
-                    )
-                }
+                // Bytes are stored as hex strings
+                Value::Bytes(query_value) => add_filter(
+                    query,
+                    filter_mode,
+                    sql("data ->> ")
+                        .bind::<Text, _>(attribute)
+                        .sql(op)
+                        .bind::<Text, _>(query_value.to_string()),
+                ),
                 Value::BigInt(query_value) => add_filter(
                     query,
                     filter_mode,
@@ -206,17 +368,31 @@ fn store_filter_by_mode<'a>(
                 // representation to work around that.
                 .bind::<Numeric, _>(BigDecimal::from_str(&query_value.to_string()).unwrap()),
                 ),
+                Value::BigDecimal(query_value) => add_filter(
+                    query,
+                    filter_mode,
+                    sql("(data ->> ")
+                        .bind::<Text, _>(attribute)
+                        .sql(")")
+                        .sql("::numeric")
+                        .sql(op)
+                        .bind::<Numeric, _>(
+                            BigDecimal::from_str(&query_value.to_string()).unwrap(),
+                        ),
+                ),
             }
         }
         StoreFilter::GreaterThan(..)
         | StoreFilter::LessThan(..)
         | StoreFilter::GreaterOrEqual(..)
         | StoreFilter::LessOrEqual(..) => {
-            let (attribute, op, value) = match filter {
-                StoreFilter::GreaterThan(attribute, value) => (attribute, " > ", value),
-                StoreFilter::LessThan(attribute, value) => (attribute, " < ", value),
-                StoreFilter::GreaterOrEqual(attribute, value) => (attribute, " >= ", value),
-                StoreFilter::LessOrEqual(attribute, value) => (attribute, " <= ", value),
+            let (attribute, name, op, value) = match filter {
+                StoreFilter::GreaterThan(attribute, value) => (attribute, "gt", " > ", value),
+                StoreFilter::LessThan(attribute, value) => (attribute, "lt", " < ", value),
+                StoreFilter::GreaterOrEqual(attribute, value) => {
+                    (attribute, "gte", " >= ", value)
+                }
+                StoreFilter::LessOrEqual(attribute, value) => (attribute, "lte", " <= ", value),
                 _ => unreachable!(),
             };
             match value {
@@ -261,18 +437,55 @@ fn store_filter_by_mode<'a>(
                 // representation to work around that.
                 .bind::<Numeric, _>(BigDecimal::from_str(&query_value.to_string()).unwrap()),
                 ),
+                Value::BigDecimal(query_value) => add_filter(
+                    query,
+                    filter_mode,
+                    sql("(data ->> ")
+                        .bind::<Text, _>(attribute)
+                        .sql(")")
+                        .sql("::numeric")
+                        .sql(op)
+                        .bind::<Numeric, _>(
+                            BigDecimal::from_str(&query_value.to_string()).unwrap(),
+                        ),
+                ),
                 Value::Null | Value::Bool(_) | Value::List(_) | Value::Bytes(_) => {
                     return Err(UnsupportedFilter {
-                        filter: op.to_owned(),
+                        filter: name.to_owned(),
                         value,
                     })
                 }
             }
         }
-        // Is `attribute` equal to some `v` in `query_values`?
-        StoreFilter::In(attribute, query_values) => {
-            let op = " = ANY (";
-            match query_values[0].clone() {
+        // Is `attribute` equal to some, or different from all, of `query_values`?
+        StoreFilter::In(..) | StoreFilter::NotIn(..) => {
+            let (attribute, query_values, name, op) = match filter {
+                StoreFilter::In(attribute, values) => (attribute, values, "in", " = ANY ("),
+                StoreFilter::NotIn(attribute, values) => {
+                    (attribute, values, "not_in", " != ALL (")
+                }
+                _ => unreachable!(),
+            };
+
+            // No entity is in an empty list, and every entity is not in it
+            let first = match query_values.first() {
+                Some(first) => first.clone(),
+                None => {
+                    let matches = if name == "in" { "false" } else { "true" };
+                    return Ok(add_filter(query, filter_mode, sql::<Bool>(matches)));
+                }
+            };
+
+            // The values are compared as one SQL type, so they must all have
+            // the same type
+            if !query_values.iter().all(|value| same_sql_type(&first, value)) {
+                return Err(UnsupportedFilter {
+                    filter: name.to_owned(),
+                    value: Value::List(query_values),
+                });
+            }
+
+            match first {
                 Value::Bool(_) => add_filter(
                     query,
                     filter_mode,
@@ -284,10 +497,10 @@ fn store_filter_by_mode<'a>(
                         .bind::<Array<Bool>, _>(SqlValue::new_array(query_values))
                         .sql(")"),
                 ),
-                Value::BigInt(_) => add_filter(
+                Value::BigInt(_) | Value::BigDecimal(_) => add_filter(
                     query,
                     filter_mode,
-                    sql("data ->> ")
+                    sql("(data ->> ")
                         .bind::<Text, _>(attribute)
                         .sql(")")
                         .sql("::numeric")
@@ -295,15 +508,6 @@ fn store_filter_by_mode<'a>(
                         .bind::<Array<Numeric>, _>(SqlValue::new_array(query_values))
                         .sql(")"),
                 ),
-                Value::Bytes(_) => add_filter(
-                    query,
-                    filter_mode,
-                    sql("data ->> ")
-                        .bind::<Text, _>(attribute)
-                        .sql(op)
-                        .bind::<Array<Text>, _>(SqlValue::new_array(query_values))
-                        .sql(")"),
-                ),
                 Value::Float(_) => add_filter(
                     query,
                     filter_mode,
@@ -326,7 +530,8 @@ fn store_filter_by_mode<'a>(
                         .bind::<Array<Integer>, _>(SqlValue::new_array(query_values))
                         .sql(")"),
                 ),
-                Value::String(_) => add_filter(
+                // Bytes are stored as hex strings
+                Value::String(_) | Value::Bytes(_) => add_filter(
                     query,
                     filter_mode,
                     sql("data ->> ")
@@ -335,15 +540,31 @@ fn store_filter_by_mode<'a>(
                         .bind::<Array<Text>, _>(SqlValue::new_array(query_values))
                         .sql(")"),
                 ),
-                _ => unimplemented!(),
+                // Lists are compared as JSON, including the order of their
+                // elements, like with `Equal`
+                Value::List(_) => {
+                    let query_arrays = query_values
+                        .into_iter()
+                        .map(|value| json_string(name, value))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    add_filter(
+                        query,
+                        filter_mode,
+                        sql("data -> ")
+                            .bind::<Text, _>(attribute)
+                            .sql(op)
+                            .bind::<Array<Text>, _>(query_arrays)
+                            .sql("::jsonb[])"),
+                    )
+                }
+                Value::Null => {
+                    return Err(UnsupportedFilter {
+                        filter: name.to_owned(),
+                        value: Value::List(query_values),
+                    })
+                }
             }
         }
-        // Is `attribute` different from all `query_values`?
-        StoreFilter::NotIn(attribute, query_values) => {
-            query_values.into_iter().try_fold(query, |q, v| {
-                store_filter_by_mode(q, StoreFilter::Not(attribute.clone(), v), FilterMode::And)
-            })?
-        }
         StoreFilter::StartsWith(..) | StoreFilter::NotStartsWith(..) => {
             let (attribute, op, value) = match filter {
                 StoreFilter::StartsWith(attribute, value) => (attribute, " LIKE ", value),
@@ -357,7 +578,7 @@ fn store_filter_by_mode<'a>(
                     sql("data ->> ")
                         .bind::<Text, _>(attribute)
                         .sql(op)
-                        .bind::<Text, _>(format!("{}%", query_value)),
+                        .bind::<Text, _>(format!("{}%", like_literal(&query_value))),
                 ),
                 Value::List(_)
                 | Value::Null
@@ -365,6 +586,7 @@ fn store_filter_by_mode<'a>(
                 | Value::Int(_)
                 | Value::Bool(_)
                 | Value::BigInt(_)
+                | Value::BigDecimal(_)
                 | Value::Bytes(_) => {
                     return Err(UnsupportedFilter {
                         filter: if op == " LIKE " {
@@ -391,7 +613,7 @@ fn store_filter_by_mode<'a>(
                     sql("data ->> ")
                         .bind::<Text, _>(attribute)
                         .sql(op)
-                        .bind::<Text, _>(format!("%{}", query_value)),
+                        .bind::<Text, _>(format!("%{}", like_literal(&query_value))),
                 ),
                 Value::List(_)
                 | Value::Null
@@ -399,6 +621,7 @@ fn store_filter_by_mode<'a>(
                 | Value::Int(_)
                 | Value::Bool(_)
                 | Value::BigInt(_)
+                | Value::BigDecimal(_)
                 | Value::Bytes(_) => {
                     return Err(UnsupportedFilter {
                         filter: if op == " LIKE " {
@@ -411,5 +634,105 @@ fn store_filter_by_mode<'a>(
                 }
             }
         }
+
+        StoreFilter::ContainsNoCase(..)
+        | StoreFilter::NotContainsNoCase(..)
+        | StoreFilter::StartsWithNoCase(..)
+        | StoreFilter::NotStartsWithNoCase(..)
+        | StoreFilter::EndsWithNoCase(..)
+        | StoreFilter::NotEndsWithNoCase(..) => {
+            // The value becomes a pattern that may be preceded or followed by
+            // anything, depending on the filter
+            let (attribute, value, name, op, prefix, suffix) = match filter {
+                StoreFilter::ContainsNoCase(attribute, value) => {
+                    (attribute, value, "contains_nocase", " ILIKE ", "%", "%")
+                }
+                StoreFilter::NotContainsNoCase(attribute, value) => {
+                    (attribute, value, "not_contains_nocase", " NOT ILIKE ", "%", "%")
+                }
+                StoreFilter::StartsWithNoCase(attribute, value) => {
+                    (attribute, value, "starts_with_nocase", " ILIKE ", "", "%")
+                }
+                StoreFilter::NotStartsWithNoCase(attribute, value) => {
+                    (attribute, value, "not_starts_with_nocase", " NOT ILIKE ", "", "%")
+                }
+                StoreFilter::EndsWithNoCase(attribute, value) => {
+                    (attribute, value, "ends_with_nocase", " ILIKE ", "%", "")
+                }
+                StoreFilter::NotEndsWithNoCase(attribute, value) => {
+                    (attribute, value, "not_ends_with_nocase", " NOT ILIKE ", "%", "")
+                }
+                _ => unreachable!(),
+            };
+            match value {
+                Value::String(query_value) => add_filter(
+                    query,
+                    filter_mode,
+                    sql("data ->> ")
+                        .bind::<Text, _>(attribute)
+                        .sql(op)
+                        .bind::<Text, _>(format!(
+                            "{}{}{}",
+                            prefix,
+                            like_literal(&query_value),
+                            suffix
+                        )),
+                ),
+                Value::List(_)
+                | Value::Null
+                | Value::Float(_)
+                | Value::Int(_)
+                | Value::Bool(_)
+                | Value::BigInt(_)
+                | Value::BigDecimal(_)
+                | Value::Bytes(_) => {
+                    return Err(UnsupportedFilter {
+                        filter: name.to_owned(),
+                        value,
+                    })
+                }
+            }
+        }
+
+        StoreFilter::MatchesRegex(..) | StoreFilter::NotMatchesRegex(..) => {
+            let (attribute, value, name, op) = match filter {
+                StoreFilter::MatchesRegex(attribute, value) => (attribute, value, "regex", " ~* "),
+                StoreFilter::NotMatchesRegex(attribute, value) => {
+                    (attribute, value, "not_regex", " !~* ")
+                }
+                _ => unreachable!(),
+            };
+            match value {
+                Value::String(pattern) => {
+                    if pattern.len() > MAX_REGEX_LENGTH {
+                        return Err(UnsupportedFilter {
+                            filter: name.to_owned(),
+                            value: Value::String(pattern),
+                        });
+                    }
+                    add_filter(
+                        query,
+                        filter_mode,
+                        sql("data ->> ")
+                            .bind::<Text, _>(attribute)
+                            .sql(op)
+                            .bind::<Text, _>(pattern),
+                    )
+                }
+                Value::List(_)
+                | Value::Null
+                | Value::Float(_)
+                | Value::Int(_)
+                | Value::Bool(_)
+                | Value::BigInt(_)
+                | Value::BigDecimal(_)
+                | Value::Bytes(_) => {
+                    return Err(UnsupportedFilter {
+                        filter: name.to_owned(),
+                        value,
+                    })
+                }
+            }
+        }
     })
 }
@@ -20,3 +20,10 @@ sql_function! {
     SetConfig,
     (setting_name: Text, new_value: Text, is_local: Bool)
 }
+
+// Create module for hosting the try advisory lock function
+sql_function! {
+    pg_try_advisory_lock,
+    PgTryAdvisoryLock,
+    (key: BigInt) -> Bool
+}
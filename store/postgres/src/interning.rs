@@ -0,0 +1,234 @@
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result::Error;
+use diesel::{insert_into, QueryResult};
+use graph::serde_json::{self, Map};
+use std::collections::{HashMap, HashSet};
+
+use graph::util::ethereum::keccak256;
+
+/// Key under which entity data references an interned value. Entity values
+/// are never JSON objects, so such references cannot be confused with them.
+const INTERNED_KEY: &str = "interned";
+
+/// Replaces all attribute values of an entity that are strings of at least
+/// `threshold` bytes with references to a copy in the `interned_values`
+/// table. Identical values are only stored once. Returns the attributes
+/// whose values, or some of whose list items, were replaced.
+pub fn intern_values(
+    conn: &PgConnection,
+    entity: &mut serde_json::Value,
+    threshold: usize,
+) -> QueryResult<Vec<String>> {
+    let mut interned = vec![];
+    if let serde_json::Value::Object(attributes) = entity {
+        for (attribute, value) in attributes.iter_mut() {
+            if intern_value(conn, value, threshold)? {
+                interned.push(attribute.clone());
+            }
+        }
+    }
+    Ok(interned)
+}
+
+/// Interns a value, or the items of a list, and returns whether anything
+/// was interned.
+fn intern_value(
+    conn: &PgConnection,
+    data: &mut serde_json::Value,
+    threshold: usize,
+) -> QueryResult<bool> {
+    let value_hash = match data {
+        serde_json::Value::String(s) if s.len() >= threshold => {
+            let value_hash = format!("{:x}", keccak256(s.as_bytes()));
+
+            use db_schema::interned_values::dsl::*;
+            insert_into(interned_values)
+                .values((hash.eq(&value_hash), value.eq(s.as_str())))
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+
+            value_hash
+        }
+        serde_json::Value::Array(values) => {
+            let mut interned = false;
+            for value in values.iter_mut() {
+                interned |= intern_value(conn, value, threshold)?;
+            }
+            return Ok(interned);
+        }
+        _ => return Ok(false),
+    };
+
+    let mut reference = Map::new();
+    reference.insert(INTERNED_KEY.to_owned(), serde_json::Value::String(value_hash));
+    *data = serde_json::Value::Object(reference);
+    Ok(true)
+}
+
+/// Records that attributes of an entity type hold interned values. SQL only
+/// sees the references to these values, so queries must not filter or order
+/// by these attributes.
+pub fn record_interned_attributes(
+    conn: &PgConnection,
+    subgraph_id: &str,
+    entity_type: &str,
+    attributes: &[String],
+) -> QueryResult<()> {
+    use db_schema::interned_attributes::dsl::*;
+
+    for name in attributes {
+        insert_into(interned_attributes)
+            .values((
+                subgraph.eq(subgraph_id),
+                entity.eq(entity_type),
+                attribute.eq(name),
+            ))
+            .on_conflict_do_nothing()
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+/// Returns the attributes of an entity type that hold interned values for
+/// at least one entity.
+pub fn interned_attributes(
+    conn: &PgConnection,
+    subgraph_id: &str,
+    entity_type: &str,
+) -> QueryResult<HashSet<String>> {
+    use db_schema::interned_attributes::dsl::*;
+
+    interned_attributes
+        .filter(subgraph.eq(subgraph_id))
+        .filter(entity.eq(entity_type))
+        .select(attribute)
+        .load::<String>(conn)
+        .map(|attributes| attributes.into_iter().collect())
+}
+
+/// Replaces all references to interned values in `entities` with the values
+/// themselves, looking all of them up at once.
+pub fn resolve_interned_values(
+    conn: &PgConnection,
+    entities: &mut [serde_json::Value],
+) -> QueryResult<()> {
+    let mut hashes = vec![];
+    for entity in entities.iter() {
+        if let serde_json::Value::Object(attributes) = entity {
+            attributes
+                .values()
+                .for_each(|value| collect_references(value, &mut hashes));
+        }
+    }
+
+    if hashes.is_empty() {
+        return Ok(());
+    }
+
+    let values: HashMap<String, String> = {
+        use db_schema::interned_values::dsl::*;
+
+        interned_values
+            .filter(hash.eq_any(&hashes))
+            .select((hash, value))
+            .load::<(String, String)>(conn)?
+            .into_iter()
+            .collect()
+    };
+
+    for entity in entities.iter_mut() {
+        if let serde_json::Value::Object(attributes) = entity {
+            for value in attributes.values_mut() {
+                replace_references(value, &values).map_err(|hash| {
+                    Error::DeserializationError(
+                        format!("Entity references interned value {} that does not exist", hash)
+                            .into(),
+                    )
+                })?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn reference(data: &serde_json::Value) -> Option<&String> {
+    match data {
+        serde_json::Value::Object(object) if object.len() == 1 => match object.get(INTERNED_KEY) {
+            Some(serde_json::Value::String(hash)) => Some(hash),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn collect_references(data: &serde_json::Value, hashes: &mut Vec<String>) {
+    if let Some(hash) = reference(data) {
+        hashes.push(hash.clone());
+        return;
+    }
+
+    if let serde_json::Value::Array(values) = data {
+        values
+            .iter()
+            .for_each(|value| collect_references(value, hashes));
+    }
+}
+
+/// Replaces the references in a value with the interned values. Fails with
+/// the hash of a reference to a value that is not in `values`.
+fn replace_references(
+    data: &mut serde_json::Value,
+    values: &HashMap<String, String>,
+) -> Result<(), String> {
+    let resolved = match reference(data) {
+        Some(hash) => Some(values.get(hash).cloned().ok_or_else(|| hash.clone())?),
+        None => None,
+    };
+    if let Some(resolved) = resolved {
+        *data = serde_json::Value::String(resolved);
+        return Ok(());
+    }
+
+    if let serde_json::Value::Array(list) = data {
+        for value in list.iter_mut() {
+            replace_references(value, values)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use graph::serde_json;
+    use std::collections::HashMap;
+
+    use super::replace_references;
+
+    #[test]
+    fn replaces_references_with_their_values() {
+        let mut values = HashMap::new();
+        values.insert(String::from("abc"), String::from("large value"));
+
+        let mut metadata = json_value(r#"{"interned": "abc"}"#);
+        replace_references(&mut metadata, &values).unwrap();
+        assert_eq!(metadata, json_value(r#""large value""#));
+
+        let mut list = json_value(r#"["small value", {"interned": "abc"}]"#);
+        replace_references(&mut list, &values).unwrap();
+        assert_eq!(list, json_value(r#"["small value", "large value"]"#));
+    }
+
+    #[test]
+    fn fails_on_references_to_missing_values() {
+        let mut list = json_value(r#"["small value", {"interned": "def"}]"#);
+        assert_eq!(
+            replace_references(&mut list, &HashMap::new()),
+            Err(String::from("def"))
+        );
+    }
+
+    fn json_value(s: &str) -> serde_json::Value {
+        serde_json::from_str(s).unwrap()
+    }
+}
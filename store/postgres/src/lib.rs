@@ -4,13 +4,25 @@ extern crate diesel;
 extern crate diesel_dynamic_schema;
 #[macro_use]
 extern crate diesel_migrations;
+extern crate ethereum_types;
+extern crate flate2;
 extern crate futures;
 extern crate graph;
+extern crate pq_sys;
+extern crate rand;
 
+mod compression;
 pub mod db_schema;
 mod filter;
 pub mod functions;
+mod interning;
+mod lock;
 pub mod models;
+pub mod notification;
+mod order;
+mod retry;
 pub mod store;
+mod window;
 
+pub use self::notification::StoreEventListener;
 pub use self::store::{Store, StoreConfig};
@@ -0,0 +1,32 @@
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::select;
+
+use functions::pg_try_advisory_lock;
+use graph::util::ethereum::keccak256;
+
+/// Derives the key of the advisory lock that guards writes to a deployment.
+fn deployment_lock_key(subgraph_id: &str) -> i64 {
+    let hash = keccak256(subgraph_id.as_bytes());
+    hash[..8]
+        .iter()
+        .fold(0i64, |key, byte| (key << 8) | i64::from(*byte))
+}
+
+/// Tries to take the advisory lock for writing to a deployment. The lock is
+/// held until the connection is closed, so no other connection can write to
+/// the deployment meanwhile.
+pub fn try_lock_deployment(conn: &PgConnection, subgraph_id: &str) -> QueryResult<bool> {
+    select(pg_try_advisory_lock(deployment_lock_key(subgraph_id))).get_result(conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::deployment_lock_key;
+
+    #[test]
+    fn lock_keys_differ_between_deployments() {
+        assert_eq!(deployment_lock_key("subgraph"), deployment_lock_key("subgraph"));
+        assert_ne!(deployment_lock_key("subgraph"), deployment_lock_key("other"));
+    }
+}
@@ -67,6 +67,10 @@ impl ToSql<Numeric, Pg> for SqlValue {
                 &BigDecimal::from_str(&number.to_string()).unwrap(),
                 out,
             ),
+            Value::BigDecimal(ref number) => <BigDecimal as ToSql<Numeric, Pg>>::to_sql(
+                &BigDecimal::from_str(&number.to_string()).unwrap(),
+                out,
+            ),
             _ => panic!("Failed to convert attribute value to bigint in SQL"),
         }
     }
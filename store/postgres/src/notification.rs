@@ -0,0 +1,197 @@
+use ethereum_types::H256;
+use pq_sys::*;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_void;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+use futures::sync::mpsc::{channel, Receiver, Sender};
+use graph::components::store::EventSource;
+use graph::prelude::*;
+
+/// The channel that stores notify about the subgraphs they wrote to, so that
+/// nodes which only serve queries learn about the writes of other nodes.
+pub const STORE_EVENTS_CHANNEL: &str = "store_events";
+
+/// How often the listener checks for new notifications.
+const POLL_INTERVAL_MS: u64 = 100;
+
+/// How long the listener waits before reconnecting after losing its
+/// connection to Postgres.
+const RECONNECT_DELAY_SECS: u64 = 5;
+
+/// Encodes that entities of a subgraph were written as the payload of a
+/// notification, e.g. `<subgraph> block <hash> <number>`.
+pub fn notification_payload(subgraph_id: &str, event_source: &EventSource) -> String {
+    match *event_source {
+        EventSource::EthereumBlock(hash, number) => {
+            format!("{} block {:x} {}", subgraph_id, hash, number)
+        }
+        EventSource::Offchain(ref source) => format!("{} offchain {}", subgraph_id, source),
+    }
+}
+
+/// Decodes the payload of a notification into a store event.
+pub fn parse_notification(payload: &str) -> Option<StoreEvent> {
+    let mut parts = payload.splitn(3, ' ');
+    let (subgraph_id, kind, source) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(subgraph_id), Some(kind), Some(source)) => (subgraph_id, kind, source),
+        _ => return None,
+    };
+    let event_source = match kind {
+        "block" => {
+            let mut block = source.splitn(2, ' ');
+            let hash = block.next().and_then(|hash| H256::from_str(hash).ok())?;
+            let number = block.next().and_then(|number| number.parse().ok())?;
+            EventSource::EthereumBlock(hash, number)
+        }
+        "offchain" => EventSource::Offchain(source.to_owned()),
+        _ => return None,
+    };
+    Some(StoreEvent::SubgraphChanged(subgraph_id.to_owned(), event_source))
+}
+
+/// Listens to the store events that the nodes writing to a database notify
+/// about, e.g. for a node that only serves queries and writes nothing itself.
+pub struct StoreEventListener;
+
+impl StoreEventListener {
+    /// Starts listening on a connection of its own in a background thread,
+    /// reconnecting whenever the connection is lost, until the returned
+    /// stream is dropped.
+    pub fn start(logger: &Logger, postgres_url: &str) -> Receiver<StoreEvent> {
+        let logger = logger.new(o!("component" => "StoreEventListener"));
+        let url = CString::new(postgres_url).expect("Postgres URL contains a null byte");
+        let (sink, stream) = channel(100);
+        thread::spawn(move || {
+            let mut sink = sink;
+            loop {
+                sink = match Self::listen(&logger, &url, sink) {
+                    Some(sink) => sink,
+                    None => return,
+                };
+                thread::sleep(Duration::from_secs(RECONNECT_DELAY_SECS));
+            }
+        });
+        stream
+    }
+
+    /// Forwards notifications until the connection fails, returning the sink
+    /// for reconnecting, or `None` if nobody is listening anymore.
+    fn listen(
+        logger: &Logger,
+        url: &CStr,
+        mut sink: Sender<StoreEvent>,
+    ) -> Option<Sender<StoreEvent>> {
+        let conn = match Connection::listen(url) {
+            Ok(conn) => conn,
+            Err(e) => {
+                warn!(logger, "Failed to listen to store events"; "error" => e);
+                return Some(sink);
+            }
+        };
+        info!(logger, "Listening to store events"; "channel" => STORE_EVENTS_CHANNEL);
+
+        loop {
+            let payloads = match conn.notifications() {
+                Ok(payloads) => payloads,
+                Err(e) => {
+                    warn!(logger, "Lost connection for store events"; "error" => e);
+                    return Some(sink);
+                }
+            };
+            for payload in payloads {
+                match parse_notification(&payload) {
+                    Some(event) => sink = sink.send(event).wait().ok()?,
+                    None => warn!(logger, "Ignoring malformed store event"; "payload" => payload),
+                }
+            }
+            thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+        }
+    }
+}
+
+/// A libpq connection, since Diesel connections cannot receive notifications.
+struct Connection(*mut PGconn);
+
+impl Connection {
+    /// Connects and subscribes to the store events channel.
+    fn listen(url: &CStr) -> Result<Self, String> {
+        let conn = Connection(unsafe { PQconnectdb(url.as_ptr()) });
+        match unsafe { PQstatus(conn.0) } {
+            ConnStatusType::CONNECTION_OK => (),
+            _ => return Err(conn.error_message()),
+        }
+
+        let command = CString::new(format!("LISTEN {}", STORE_EVENTS_CHANNEL)).unwrap();
+        unsafe {
+            let result = PQexec(conn.0, command.as_ptr());
+            let status = PQresultStatus(result);
+            PQclear(result);
+            match status {
+                ExecStatusType::PGRES_COMMAND_OK => (),
+                _ => return Err(conn.error_message()),
+            }
+        }
+        Ok(conn)
+    }
+
+    /// Reads the payloads of all notifications that arrived so far.
+    fn notifications(&self) -> Result<Vec<String>, String> {
+        let mut payloads = vec![];
+        unsafe {
+            if PQconsumeInput(self.0) == 0 {
+                return Err(self.error_message());
+            }
+            loop {
+                let notification = PQnotifies(self.0);
+                if notification.is_null() {
+                    break;
+                }
+                let payload = CStr::from_ptr((*notification).extra);
+                payloads.push(payload.to_string_lossy().into_owned());
+                PQfreemem(notification as *mut c_void);
+            }
+        }
+        Ok(payloads)
+    }
+
+    fn error_message(&self) -> String {
+        unsafe { CStr::from_ptr(PQerrorMessage(self.0)) }
+            .to_string_lossy()
+            .trim()
+            .to_owned()
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        unsafe { PQfinish(self.0) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payloads_round_trip() {
+        let block = EventSource::EthereumBlock(H256::from(5), 5);
+        let offchain = EventSource::Offchain("QmLink with spaces".to_owned());
+        for source in vec![block, offchain] {
+            let payload = notification_payload("subgraph", &source);
+            assert_eq!(
+                parse_notification(&payload),
+                Some(StoreEvent::SubgraphChanged("subgraph".to_owned(), source))
+            );
+        }
+    }
+
+    #[test]
+    fn malformed_payloads_are_rejected() {
+        assert_eq!(parse_notification("subgraph"), None);
+        assert_eq!(parse_notification("subgraph block 12 5"), None);
+        assert_eq!(parse_notification("subgraph epoch 5"), None);
+    }
+}
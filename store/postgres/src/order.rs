@@ -0,0 +1,75 @@
+use diesel::expression::{AppearsOnTable, Expression, NonAggregate};
+use diesel::pg::Pg;
+use diesel::query_builder::{AstPass, QueryFragment};
+use diesel::result::QueryResult;
+use diesel::sql_types::Text;
+use graph::prelude::{StoreOrder, ValueType};
+
+use db_schema::entities;
+
+/// Orders entities by an attribute, comparing its values as their type
+/// requires, and by ID where they are equal, so that the order, and with it
+/// every page of the entities, is deterministic.
+pub(crate) struct EntityOrder {
+    attribute: String,
+    value_type: ValueType,
+    direction: &'static str,
+    /// The expression for the ID of an entity.
+    id: &'static str,
+}
+
+impl EntityOrder {
+    pub fn new(attribute: String, value_type: ValueType, direction: Option<StoreOrder>) -> Self {
+        EntityOrder {
+            attribute,
+            value_type,
+            direction: match direction {
+                Some(StoreOrder::Descending) => "DESC",
+                _ => "ASC",
+            },
+            id: "id",
+        }
+    }
+
+    /// Breaks ties by the ID in the data of entities, for queries whose
+    /// rows have no `id` column.
+    pub fn by_data_id(self) -> Self {
+        EntityOrder {
+            id: "data ->> 'id'",
+            ..self
+        }
+    }
+}
+
+impl Expression for EntityOrder {
+    type SqlType = Text;
+}
+
+impl NonAggregate for EntityOrder {}
+
+impl AppearsOnTable<entities::table> for EntityOrder {}
+
+impl QueryFragment<Pg> for EntityOrder {
+    fn walk_ast(&self, mut out: AstPass<Pg>) -> QueryResult<()> {
+        // Big numbers are stored as strings, so all numbers are compared
+        // as `numeric` to order them by their value
+        let (operator, cast) = match self.value_type {
+            ValueType::BigDecimal | ValueType::BigInt | ValueType::Float | ValueType::Int => {
+                ("(data ->> ", ")::numeric")
+            }
+            ValueType::Boolean => ("(data ->> ", ")::boolean"),
+            ValueType::Bytes | ValueType::String => ("(data ->> ", ")"),
+            ValueType::List => ("(data -> ", ")"),
+        };
+        out.push_sql(operator);
+        out.push_bind_param::<Text, _>(&self.attribute)?;
+        out.push_sql(cast);
+        out.push_sql(" ");
+        out.push_sql(self.direction);
+        out.push_sql(", ");
+        out.push_sql(self.id);
+        out.push_sql(" ");
+        out.push_sql(self.direction);
+        Ok(())
+    }
+}
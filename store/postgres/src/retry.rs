@@ -0,0 +1,157 @@
+use diesel::result::{DatabaseErrorKind, Error};
+use diesel::QueryResult;
+use rand::{thread_rng, Rng};
+use std::thread;
+use std::time::Duration;
+
+use graph::prelude::*;
+
+/// How often a write transaction is attempted before a conflict with
+/// concurrent transactions is treated as a failure of the node.
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Delay before the first retry; it doubles with every further retry.
+const BASE_DELAY_MS: u64 = 10;
+
+/// Returns true if a write transaction failed because it could not be
+/// serialized with concurrent transactions (SQLSTATE 40001) or deadlocked
+/// with them (SQLSTATE 40P01), in which case running it again is expected
+/// to succeed.
+fn is_transient(error: &Error) -> bool {
+    match error {
+        Error::DatabaseError(DatabaseErrorKind::SerializationFailure, _) => true,
+        // Diesel only maps the SQLSTATE of serialization failures to an
+        // error kind, so deadlocks are told apart by their primary message
+        Error::DatabaseError(DatabaseErrorKind::__Unknown, info) => {
+            info.message() == "deadlock detected"
+        }
+        _ => false,
+    }
+}
+
+/// Runs a write transaction until it succeeds or fails for a reason other
+/// than a conflict with concurrent transactions, waiting a little longer
+/// with some jitter before each retry. `write` must run the whole
+/// transaction, since Postgres rolls back all of it on such a conflict.
+pub fn retry_transient<T>(
+    logger: &Logger,
+    mut write: impl FnMut() -> QueryResult<T>,
+) -> QueryResult<T> {
+    let mut attempt = 1;
+    loop {
+        match write() {
+            Err(ref e) if is_transient(e) && attempt < MAX_ATTEMPTS => {
+                let backoff = BASE_DELAY_MS << (attempt - 1);
+                let delay = backoff + thread_rng().gen_range(0, backoff);
+                debug!(logger, "Retrying write that conflicted with a concurrent transaction";
+                       "attempt" => attempt, "delay_ms" => delay, "error" => e.to_string());
+                thread::sleep(Duration::from_millis(delay));
+                attempt += 1;
+            }
+            Err(e) => {
+                if is_transient(&e) {
+                    error!(logger, "Write keeps conflicting with concurrent transactions";
+                           "attempts" => attempt, "error" => e.to_string());
+                }
+                return Err(e);
+            }
+            Ok(value) => return Ok(value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::result::{DatabaseErrorInformation, DatabaseErrorKind, Error};
+    use graph::prelude::*;
+
+    use super::{retry_transient, MAX_ATTEMPTS};
+
+    struct TestErrorInformation(&'static str);
+
+    impl DatabaseErrorInformation for TestErrorInformation {
+        fn message(&self) -> &str {
+            self.0
+        }
+        fn details(&self) -> Option<&str> {
+            None
+        }
+        fn hint(&self) -> Option<&str> {
+            None
+        }
+        fn table_name(&self) -> Option<&str> {
+            None
+        }
+        fn column_name(&self) -> Option<&str> {
+            None
+        }
+        fn constraint_name(&self) -> Option<&str> {
+            None
+        }
+    }
+
+    fn database_error(kind: DatabaseErrorKind, message: &'static str) -> Error {
+        Error::DatabaseError(kind, Box::new(TestErrorInformation(message)))
+    }
+
+    #[test]
+    fn retries_deadlocks_and_serialization_failures() {
+        let logger = Logger::root(slog::Discard, o!());
+
+        let mut attempts = 0;
+        let result = retry_transient(&logger, || {
+            attempts += 1;
+            match attempts {
+                1 => Err(database_error(
+                    DatabaseErrorKind::__Unknown,
+                    "deadlock detected",
+                )),
+                2 => Err(database_error(
+                    DatabaseErrorKind::SerializationFailure,
+                    "could not serialize access due to concurrent update",
+                )),
+                _ => Ok(attempts),
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        let mut attempts = 0;
+        let result: Result<(), _> = retry_transient(&logger, || {
+            attempts += 1;
+            Err(database_error(
+                DatabaseErrorKind::SerializationFailure,
+                "could not serialize access due to read/write dependencies",
+            ))
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, MAX_ATTEMPTS);
+    }
+
+    #[test]
+    fn does_not_retry_other_errors() {
+        let logger = Logger::root(slog::Discard, o!());
+
+        let mut attempts = 0;
+        let result: Result<(), _> = retry_transient(&logger, || {
+            attempts += 1;
+            Err(database_error(
+                DatabaseErrorKind::__Unknown,
+                "relation \"entities\" does not exist",
+            ))
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+
+        // Other errors are not retried just because they mention a conflict
+        let mut attempts = 0;
+        let result: Result<(), _> = retry_transient(&logger, || {
+            attempts += 1;
+            Err(database_error(
+                DatabaseErrorKind::UniqueViolation,
+                "could not serialize access",
+            ))
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+}
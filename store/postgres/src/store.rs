@@ -1,20 +1,75 @@
-use diesel::dsl::sql;
+use diesel::dsl::{min, sql};
 use diesel::pg::Pg;
 use diesel::pg::PgConnection;
 use diesel::prelude::*;
-use diesel::sql_types::Text;
-use diesel::{debug_query, delete, insert_into, result, select};
-use filter::store_filter;
+use diesel::query_builder::BoxedSelectStatement;
+use diesel::sql_types::{Array, BigInt, Bool, Integer, Jsonb, SmallInt, Text};
+use diesel::{debug_query, delete, insert_into, result, select, sql_query, update};
+use ethereum_types::H256;
+use filter::{filtered_attributes, store_filter};
+use order::EntityOrder;
 use futures::sync::mpsc::{channel, Receiver, Sender};
 use graph::serde_json;
+use rand::{thread_rng, Rng};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use compression::{compress_entities, decompress_entities, EntityRow};
 
 use functions::{revert_block, set_config};
+use interning::{
+    intern_values, interned_attributes, record_interned_attributes, resolve_interned_values,
+};
+use lock::try_lock_deployment;
+use notification::{notification_payload, STORE_EVENTS_CHANNEL};
+use retry::retry_transient;
+use window::{AggregateQuery, HistogramQuery, UnionQuery, WindowQuery};
 
 use graph::components::store::{EventSource, Store as StoreTrait};
+use graph::data::api_key::{ApiKey, ApiKeyUsage};
+use graph::data::compression::CompressedSubgraph;
+use graph::data::store::scalar::BigDecimal;
+use graph::data::usage::current_timestamp;
 use graph::prelude::*;
 
 embed_migrations!("./migrations");
 
+/// The attribute that entities returned to queries hold their type in.
+const TYPENAME: &str = "__typename";
+
+/// Deserializes the data of an entity.
+fn entity_from_json(value: serde_json::Value) -> Entity {
+    serde_json::from_value::<Entity>(value).expect("Failed to deserialize entity")
+}
+
+/// Deserializes the data of an entity of type `entity_type` that a query
+/// asked for. The type is added as `__typename`, so that entities returned
+/// for an interface can be mapped back to their object types.
+fn typed_entity_from_json(entity_type: &str, value: serde_json::Value) -> Entity {
+    let mut entity = entity_from_json(value);
+    entity.insert(String::from(TYPENAME), Value::from(entity_type));
+    entity
+}
+
+/// Joins the string values of the given attributes of an entity, including
+/// the strings in lists, into the text that full-text searches match.
+fn fulltext_text(entity: &Entity, fields: &[String]) -> String {
+    let mut words = vec![];
+    for field in fields {
+        match entity.get(field) {
+            Some(Value::String(s)) => words.push(s.as_str()),
+            Some(Value::List(values)) => for value in values {
+                if let Value::String(s) = value {
+                    words.push(s.as_str());
+                }
+            },
+            _ => (),
+        }
+    }
+    words.join(" ")
+}
+
 /// Run all initial schema migrations.
 ///
 /// Creates the "entities" table if it doesn't already exist.
@@ -37,13 +92,32 @@ fn initiate_schema(logger: &slog::Logger, conn: &PgConnection) {
 /// Configuration for the Diesel/Postgres store.
 pub struct StoreConfig {
     pub url: String,
+    /// String values of at least this many bytes are stored only once and
+    /// referenced from entities; `None` stores all values inline.
+    pub intern_threshold: Option<usize>,
+    /// Postgres settings, such as `statement_timeout` or `work_mem`, that
+    /// apply to the entity lookups of GraphQL queries.
+    pub query_settings: Vec<(String, String)>,
+    /// Postgres settings that apply to reading and writing entities while
+    /// indexing.
+    pub indexing_settings: Vec<(String, String)>,
 }
 
 /// A Store based on Diesel and Postgres.
 pub struct Store {
     event_sink: Option<Sender<StoreEvent>>,
     logger: slog::Logger,
-    _config: StoreConfig,
+    config: StoreConfig,
+    compressed_subgraph_ids: Mutex<HashSet<String>>,
+    latencies: Mutex<HashMap<(String, StoreOperation), LatencyHistogram>>,
+    write_locked_subgraph_ids: Mutex<HashSet<String>>,
+    /// The latest block recorded in `history_blocks` for each subgraph.
+    recorded_blocks: Mutex<HashMap<String, String>>,
+    /// The full-text searches of each subgraph that has any.
+    fulltext_definitions: Mutex<HashMap<String, Vec<FulltextDefinition>>>,
+    /// How long entity lookups with regular expression filters may run;
+    /// `None` if these filters are disabled.
+    regex_timeout: Option<Duration>,
     pub conn: PgConnection,
 }
 
@@ -61,14 +135,181 @@ impl Store {
         // Create the entities table (if necessary)
         initiate_schema(&logger, &conn);
 
+        let compressed_subgraph_ids = {
+            use db_schema::compressed_subgraphs::dsl::*;
+
+            compressed_subgraphs
+                .select(subgraph)
+                .load::<String>(&conn)
+                .expect("Failed to load compressed subgraphs")
+                .into_iter()
+                .collect()
+        };
+
         Store {
             logger,
             event_sink: None,
-            _config: config,
+            config,
+            compressed_subgraph_ids: Mutex::new(compressed_subgraph_ids),
+            latencies: Mutex::new(HashMap::new()),
+            write_locked_subgraph_ids: Mutex::new(HashSet::new()),
+            recorded_blocks: Mutex::new(HashMap::new()),
+            fulltext_definitions: Mutex::new(HashMap::new()),
+            regex_timeout: None,
             conn: conn,
         }
     }
 
+    /// Allows GraphQL queries to filter entities with regular expressions.
+    /// Entity lookups that do are cancelled after `timeout`.
+    pub fn enable_regex_filters(&mut self, timeout: Duration) {
+        self.regex_timeout = Some(timeout);
+    }
+
+    /// Returns whether `filter` matches regular expressions, or fails if it
+    /// does and regular expression filters are disabled.
+    fn uses_regex(&self, filter: &Option<StoreFilter>) -> Result<bool, ()> {
+        let uses_regex = filter.as_ref().map_or(false, StoreFilter::uses_regex);
+        if uses_regex && self.regex_timeout.is_none() {
+            error!(self.logger, "Regular expression filters are disabled");
+            return Err(());
+        }
+        Ok(uses_regex)
+    }
+
+    /// Runs a store operation against a deployment and records how long it
+    /// took.
+    fn timed<T>(&self, subgraph_id: &str, operation: StoreOperation, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+
+        self.latencies
+            .lock()
+            .unwrap()
+            .entry((subgraph_id.to_owned(), operation))
+            .or_insert_with(LatencyHistogram::new)
+            .observe(start.elapsed());
+        result
+    }
+
+    /// Runs `f` in a transaction in which `settings` are in effect.
+    fn with_settings<T>(
+        &self,
+        settings: &[(String, String)],
+        f: impl FnOnce() -> QueryResult<T>,
+    ) -> QueryResult<T> {
+        if settings.is_empty() {
+            return f();
+        }
+
+        self.conn.transaction(|| {
+            apply_settings(&self.conn, settings)?;
+            f()
+        })
+    }
+
+    /// Runs the writes of a block event for a subgraph in one transaction
+    /// with the indexing settings in effect. The whole transaction is run
+    /// again if it conflicts with concurrent transactions.
+    fn write_transaction<T>(
+        &self,
+        subgraph_id: &str,
+        mut f: impl FnMut() -> QueryResult<T>,
+    ) -> QueryResult<T> {
+        retry_transient(&self.logger, || {
+            let result = self.conn.transaction(|| {
+                apply_settings(&self.conn, &self.config.indexing_settings)?;
+                f()
+            });
+            if result.is_err() {
+                // The block may have been recorded in the rolled back
+                // transaction
+                self.recorded_blocks.lock().unwrap().remove(subgraph_id);
+            }
+            result
+        })
+    }
+
+    /// Runs an entity lookup of a GraphQL query with the query settings in
+    /// effect. If the query only has limited time left, the lookup is
+    /// cancelled once it runs out, unless the query settings already
+    /// include a `statement_timeout`. Lookups that filter with regular
+    /// expressions (`regex`) are also cancelled after the regex timeout. If
+    /// the query is at an older block, the lookup sees the `history.entities`
+    /// view instead of the entities table.
+    fn with_query_settings<T>(
+        &self,
+        scope: QueryScope,
+        subgraph_id: &str,
+        regex: bool,
+        f: impl FnOnce() -> QueryResult<T>,
+    ) -> QueryResult<T> {
+        let regex_timeout = if regex { self.regex_timeout } else { None };
+        let timeout = match (scope.timeout, regex_timeout) {
+            (Some(timeout), Some(regex_timeout)) => Some(timeout.min(regex_timeout)),
+            (timeout, regex_timeout) => timeout.or(regex_timeout),
+        };
+
+        let mut settings = vec![];
+        if let Some(timeout) = timeout {
+            // A `statement_timeout` of 0 would disable the timeout
+            let millis = timeout.as_secs() * 1000 + u64::from(timeout.subsec_millis());
+            settings.push((
+                String::from("statement_timeout"),
+                format!("{}ms", millis.max(1)),
+            ));
+        }
+        settings.extend(self.config.query_settings.iter().cloned());
+        if let Some(block_number) = scope.block {
+            let event_id = self.history_event_id(subgraph_id, block_number)?;
+            settings.push((String::from("search_path"), String::from("history, public")));
+            settings.push((String::from("vars.history_event_id"), event_id.to_string()));
+        }
+        self.with_settings(&settings, f)
+    }
+
+    /// Returns the ID of the latest event in which the subgraph wrote
+    /// entities for the block with the given number or an earlier one, or 0
+    /// if there is none.
+    fn history_event_id(&self, subgraph_id: &str, block_number: u64) -> QueryResult<i64> {
+        sql::<BigInt>(
+            "SELECT COALESCE(MAX(h.event_id), 0) \
+             FROM entity_history h \
+             JOIN event_meta_data m ON m.id = h.event_id \
+             JOIN history_blocks b ON b.subgraph = h.subgraph AND b.block_hash = m.source \
+             WHERE h.subgraph = ",
+        ).bind::<Text, _>(subgraph_id)
+            .sql(" AND b.block_number <= ")
+            .bind::<BigInt, _>(block_number as i64)
+            .get_result::<i64>(&self.conn)
+    }
+
+    /// Reverts the entity changes with the given history IDs and operations,
+    /// in the given order, and drops them from the history so that they are
+    /// not reverted again.
+    fn revert_changes(&self, changes: &[(i32, i16)]) -> QueryResult<()> {
+        for (history_id, op_id) in changes.iter() {
+            sql_query("SELECT revert_entity_event($1, $2)")
+                .bind::<Integer, _>(history_id)
+                .bind::<Integer, _>(*op_id as i32)
+                .execute(&self.conn)?;
+        }
+        let history_ids: Vec<i32> = changes.iter().map(|(history_id, _)| *history_id).collect();
+        sql_query("DELETE FROM entity_history WHERE id = ANY($1)")
+            .bind::<Array<Integer>, _>(&history_ids)
+            .execute(&self.conn)?;
+
+        // The history triggers record the reverts themselves as changes of
+        // this transaction; lookups at older blocks would see the reverted
+        // data as what the entities were before them
+        sql_query(
+            "DELETE FROM entity_history h USING event_meta_data m \
+             WHERE h.event_id = m.id AND h.reversion \
+             AND m.db_transaction_id = txid_current()",
+        ).execute(&self.conn)?;
+        Ok(())
+    }
+
     /// Handles block reorganizations.
     /// Revert all store events related to the given block
     pub fn revert_events(&self, block_hash: String) {
@@ -76,37 +317,500 @@ impl Store {
             .execute(&self.conn)
             .unwrap();
     }
-}
 
-impl BasicStore for Store {
-    fn get(&self, key: StoreKey) -> Result<Entity, ()> {
-        debug!(self.logger, "get"; "key" => format!("{:?}", key));
+    /// Records the number of the block that entities of a subgraph are
+    /// written for, so that the history of the entities can be looked up by
+    /// block number.
+    fn record_block(&self, subgraph_id: &str, event_source: &EventSource) -> QueryResult<()> {
+        let number = match event_source {
+            EventSource::EthereumBlock(_, number) => *number,
+            _ => return Ok(()),
+        };
+        let hash = event_source.to_string();
+
+        let mut recorded_blocks = self.recorded_blocks.lock().unwrap();
+        if recorded_blocks.get(subgraph_id) == Some(&hash) {
+            return Ok(());
+        }
+
+        use db_schema::history_blocks::dsl::*;
+
+        insert_into(history_blocks)
+            .values((
+                subgraph.eq(subgraph_id),
+                block_hash.eq(&hash),
+                block_number.eq(number as i64),
+            ))
+            .on_conflict_do_nothing()
+            .execute(&self.conn)?;
+        recorded_blocks.insert(subgraph_id.to_owned(), hash);
+        Ok(())
+    }
+
+    /// Tells whoever listens to the store events, such as the query cache of
+    /// the GraphQL server, that entities of a subgraph were written. Other
+    /// nodes using the same database are notified through Postgres.
+    fn send_subgraph_changed(&self, subgraph_id: &str, event_source: &EventSource) {
+        let payload = notification_payload(subgraph_id, event_source);
+        if let Err(e) = sql_query("SELECT pg_notify($1, $2)")
+            .bind::<Text, _>(STORE_EVENTS_CHANNEL)
+            .bind::<Text, _>(payload)
+            .execute(&self.conn)
+        {
+            warn!(self.logger, "Failed to notify about store event";
+                  "subgraph" => subgraph_id, "error" => e.to_string());
+        }
+
+        if let Some(ref sink) = self.event_sink {
+            let event = StoreEvent::SubgraphChanged(subgraph_id.to_owned(), event_source.clone());
+            if sink.clone().send(event).wait().is_err() {
+                warn!(self.logger, "Failed to send store event, nobody is listening";
+                      "subgraph" => subgraph_id);
+            }
+        }
+    }
+
+    /// Makes sure that this store is the only one writing to a deployment,
+    /// so that duplicate assignments of the deployment cannot interleave
+    /// their writes. Fails right away with `NotWriter` if another store is
+    /// writing to the deployment; backing off is up to the caller, so that
+    /// the store isn't blocked meanwhile.
+    fn ensure_write_lock(&self, subgraph_id: &str) -> Result<(), StoreWriteError> {
+        let mut locked_subgraph_ids = self.write_locked_subgraph_ids.lock().unwrap();
+        if locked_subgraph_ids.contains(subgraph_id) {
+            return Ok(());
+        }
+
+        let locked = try_lock_deployment(&self.conn, subgraph_id).map_err(|e| {
+            error!(self.logger, "Failed to lock deployment for writing";
+                   "subgraph" => subgraph_id, "error" => e.to_string());
+            StoreWriteError::Failed
+        })?;
+        if !locked {
+            warn!(self.logger, "Not writing to a deployment that another node is writing to";
+                  "subgraph" => subgraph_id);
+            return Err(StoreWriteError::NotWriter(subgraph_id.to_owned()));
+        }
+
+        locked_subgraph_ids.insert(subgraph_id.to_owned());
+        Ok(())
+    }
+
+    /// Decompresses a subgraph before its entities are accessed, if it is
+    /// compressed.
+    fn ensure_decompressed(&self, subgraph_id: &str) -> Result<(), ()> {
+        if !self
+            .compressed_subgraph_ids
+            .lock()
+            .unwrap()
+            .contains(subgraph_id)
+        {
+            return Ok(());
+        }
+
+        info!(self.logger, "Decompressing subgraph on access"; "subgraph" => subgraph_id);
+        self.decompress(subgraph_id).map(|_| ()).map_err(|e| {
+            error!(self.logger, "Failed to decompress subgraph";
+                   "subgraph" => subgraph_id, "error" => e.to_string())
+        })
+    }
+
+    /// Moves the entities of a subgraph from compressed storage back into
+    /// the entities table.
+    fn decompress(&self, subgraph_id: &str) -> Result<bool, GraphError> {
+        let blob = {
+            use db_schema::compressed_subgraphs::dsl::*;
+
+            compressed_subgraphs
+                .find(subgraph_id)
+                .select(data)
+                .first::<Vec<u8>>(&self.conn)
+                .optional()
+                .map_err(GraphError::store)?
+        };
+        let blob = match blob {
+            Some(blob) => blob,
+            None => return Ok(false),
+        };
+
+        let rows = decompress_entities(&blob).map_err(GraphError::store)?;
+
+        let decompressed = self
+            .conn
+            .transaction::<_, result::Error, _>(|| {
+                let deleted = {
+                    use db_schema::compressed_subgraphs::dsl::*;
+
+                    delete(compressed_subgraphs.find(subgraph_id)).execute(&self.conn)?
+                };
+
+                // Another store may have decompressed the subgraph meanwhile
+                if deleted == 0 {
+                    return Ok(false);
+                }
+
+                {
+                    use db_schema::entities::dsl::*;
+
+                    let values: Vec<_> = rows
+                        .iter()
+                        .map(|(row_id, row_entity, row_data, row_event_source)| {
+                            (
+                                id.eq(row_id),
+                                subgraph.eq(subgraph_id),
+                                entity.eq(row_entity),
+                                data.eq(row_data),
+                                event_source.eq(row_event_source),
+                            )
+                        })
+                        .collect();
+                    // Stay well below the limit of bind parameters per statement
+                    for chunk in values.chunks(1000) {
+                        insert_into(entities).values(chunk).execute(&self.conn)?;
+                    }
+                }
+
+                // Restoring the entities must not be undone by reverting the
+                // blocks they were originally written in
+                delete_history(&self.conn, subgraph_id)?;
+
+                Ok(true)
+            })
+            .map_err(GraphError::store)?;
+
+        self.compressed_subgraph_ids
+            .lock()
+            .unwrap()
+            .remove(subgraph_id);
+        self.recorded_blocks.lock().unwrap().remove(subgraph_id);
+
+        // Searches may have been declared while the subgraph was compressed
+        if decompressed {
+            self.write_missing_fulltext_documents(subgraph_id)
+                .map_err(GraphError::store)?;
+        }
+        Ok(decompressed)
+    }
+
+    /// Writes the search documents of an entity for the full-text searches
+    /// of its type.
+    fn write_fulltext_documents(&self, key: &StoreKey, entity: &Entity) -> QueryResult<()> {
+        let fulltext_definitions = self.fulltext_definitions.lock().unwrap();
+        let definitions = match fulltext_definitions.get(&key.subgraph) {
+            Some(definitions) => definitions,
+            None => return Ok(()),
+        };
+        for definition in definitions.iter().filter(|d| d.entity == key.entity) {
+            write_fulltext_document(
+                &self.conn,
+                &key.subgraph,
+                definition,
+                &key.id,
+                &fulltext_text(entity, &definition.fields),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes search documents for the entities of a subgraph that have
+    /// none yet for one of its full-text searches, e.g. because the search
+    /// was only just declared.
+    fn write_missing_fulltext_documents(&self, subgraph_id: &str) -> QueryResult<usize> {
+        let definitions = match self.fulltext_definitions.lock().unwrap().get(subgraph_id) {
+            Some(definitions) => definitions.clone(),
+            None => return Ok(0),
+        };
 
         use db_schema::entities::dsl::*;
 
-        // Use primary key fields to get the entity; deserialize the result JSON
-        entities
-            .find((key.id, key.subgraph, key.entity))
+        let mut written = 0;
+        for definition in definitions {
+            let (ids, mut values): (Vec<String>, Vec<serde_json::Value>) = entities
+                .filter(subgraph.eq(subgraph_id))
+                .filter(entity.eq(&definition.entity))
+                .filter(
+                    sql::<Bool>(
+                        "NOT EXISTS (SELECT 1 FROM fulltext_documents d \
+                         WHERE d.subgraph = entities.subgraph AND d.name = ",
+                    ).bind::<Text, _>(&definition.name)
+                        .sql(" AND d.entity = entities.entity AND d.id = entities.id)"),
+                )
+                .select((id, data))
+                .load::<(String, serde_json::Value)>(&self.conn)?
+                .into_iter()
+                .unzip();
+            resolve_interned_values(&self.conn, &mut values)?;
+
+            for (entity_id, value) in ids.iter().zip(values.into_iter()) {
+                let existing_entity = entity_from_json(value);
+                write_fulltext_document(
+                    &self.conn,
+                    subgraph_id,
+                    &definition,
+                    entity_id,
+                    &fulltext_text(&existing_entity, &definition.fields),
+                )?;
+            }
+            written += ids.len();
+        }
+        Ok(written)
+    }
+
+    /// Writes the search documents of the entities of a subgraph with the
+    /// given types and IDs again, e.g. because a revert changed or removed
+    /// them.
+    fn rewrite_fulltext_documents(
+        &self,
+        subgraph_id: &str,
+        keys: &HashSet<(String, String)>,
+    ) -> QueryResult<()> {
+        let definitions = match self.fulltext_definitions.lock().unwrap().get(subgraph_id) {
+            Some(definitions) => definitions.clone(),
+            None => return Ok(()),
+        };
+
+        for (entity_type, entity_id) in keys.iter() {
+            sql_query(
+                "DELETE FROM fulltext_documents WHERE subgraph = $1 AND entity = $2 AND id = $3",
+            ).bind::<Text, _>(subgraph_id)
+                .bind::<Text, _>(entity_type)
+                .bind::<Text, _>(entity_id)
+                .execute(&self.conn)?;
+        }
+
+        use db_schema::entities::dsl::*;
+
+        for definition in definitions {
+            let entity_ids: Vec<String> = keys
+                .iter()
+                .filter(|(entity_type, _)| entity_type == &definition.entity)
+                .map(|(_, entity_id)| entity_id.clone())
+                .collect();
+            if entity_ids.is_empty() {
+                continue;
+            }
+
+            // Entities that the revert removed have no documents anymore
+            let (ids, mut values): (Vec<String>, Vec<serde_json::Value>) = entities
+                .filter(subgraph.eq(subgraph_id))
+                .filter(entity.eq(&definition.entity))
+                .filter(id.eq_any(entity_ids))
+                .select((id, data))
+                .load::<(String, serde_json::Value)>(&self.conn)?
+                .into_iter()
+                .unzip();
+            resolve_interned_values(&self.conn, &mut values)?;
+
+            for (entity_id, value) in ids.iter().zip(values.into_iter()) {
+                let existing_entity = entity_from_json(value);
+                write_fulltext_document(
+                    &self.conn,
+                    subgraph_id,
+                    &definition,
+                    entity_id,
+                    &fulltext_text(&existing_entity, &definition.fields),
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds the query for the data of the entities that match `query`,
+    /// filtered, ordered and paged as it asks for.
+    fn entity_query(
+        &self,
+        query: StoreQuery,
+    ) -> Result<BoxedSelectStatement<'static, Jsonb, db_schema::entities::table, Pg>, ()> {
+        self.check_interned_attributes(&query).map_err(|e| {
+            error!(self.logger, "Failed to build entity query"; "error" => e.to_string())
+        })?;
+
+        use db_schema::entities::dsl::*;
+
+        // Create base boxed query; this will be added to based on the
+        // query parameters provided
+        let subgraph_id = query.subgraph;
+        let mut diesel_query = entities
+            .filter(entity.eq(query.entity))
+            .filter(subgraph.eq(subgraph_id.clone()))
             .select(data)
-            .first::<serde_json::Value>(&self.conn)
-            .map(|value| {
-                serde_json::from_value::<Entity>(value).expect("Failed to deserialize entity")
+            .into_boxed::<Pg>();
+
+        // Add specified filter to query
+        if let Some(filter) = query.filter {
+            diesel_query = store_filter(diesel_query, &subgraph_id, filter).map_err(|e| {
+                error!(self.logger, "value does not support this filter";
+                                    "value" => format!("{:?}", e.value),
+                                    "filter" => e.filter)
+            })?;
+        }
+
+        // Add order by filters to query
+        if let Some((attribute, value_type)) = query.order_by {
+            diesel_query =
+                diesel_query.order(EntityOrder::new(attribute, value_type, query.order_direction));
+        }
+
+        // Add range filter to query
+        if let Some(range) = query.range {
+            diesel_query = diesel_query
+                .limit(range.first as i64)
+                .offset(range.skip as i64);
+        }
+
+        Ok(diesel_query)
+    }
+
+    /// Fails if the store query filters or orders by attributes that hold
+    /// interned values for some entities. SQL only sees references to these
+    /// values, so it would silently match and order the wrong entities.
+    fn check_interned_attributes(&self, query: &StoreQuery) -> Result<(), QueryExecutionError> {
+        let mut attributes = query.filter.as_ref().map_or(vec![], |filter| {
+            filtered_attributes(&query.entity, filter)
+        });
+        if let Some((ref attribute, _)) = query.order_by {
+            attributes.push((query.entity.clone(), attribute.clone()));
+        }
+
+        let mut interned_for_type = HashMap::new();
+        for (entity_type, attribute) in attributes {
+            if !interned_for_type.contains_key(&entity_type) {
+                let interned = interned_attributes(&self.conn, &query.subgraph, &entity_type)
+                    .map_err(|e| {
+                        error!(self.logger, "Failed to load interned attributes";
+                               "entity" => &entity_type, "error" => e.to_string());
+                        QueryExecutionError::StoreError(entity_type.clone())
+                    })?;
+                interned_for_type.insert(entity_type.clone(), interned);
+            }
+            if interned_for_type[&entity_type].contains(&attribute) {
+                return Err(QueryExecutionError::InternedAttributeError(
+                    entity_type,
+                    attribute,
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Looks up the entity with the given key as it was written onchain,
+    /// without the attributes set offchain.
+    fn onchain_entity(&self, key: StoreKey) -> Result<Entity, ()> {
+        self.ensure_decompressed(&key.subgraph)?;
+
+        use db_schema::entities::dsl::*;
+
+        // Use primary key fields to get the entity; deserialize the result JSON
+        let value = self
+            .with_settings(&self.config.indexing_settings, || {
+                entities
+                    .find((key.id, key.subgraph, key.entity))
+                    .select(data)
+                    .first::<serde_json::Value>(&self.conn)
+                    .and_then(|value| {
+                        let mut values = vec![value];
+                        resolve_interned_values(&self.conn, &mut values)?;
+                        Ok(values.remove(0))
+                    })
             })
-            .map_err(|_| ())
+            .map_err(|_| ())?;
+        Ok(entity_from_json(value))
     }
 
-    fn set(
-        &mut self,
-        key: StoreKey,
+    /// Adds the attributes that offchain handlers set for entities to the
+    /// entities of a subgraph that were read from the entities table. The
+    /// attributes set onchain take precedence, and of those set offchain,
+    /// the ones fetched from the source updated last.
+    ///
+    /// Offchain entities that have no onchain counterpart are not returned,
+    /// so entities never appear in or disappear from the results of filters
+    /// because of offchain data. The entities must have their types in
+    /// `__typename`.
+    fn merge_offchain(&self, subgraph_id: &str, entities: Vec<Entity>) -> Result<Vec<Entity>, ()> {
+        let keys: Vec<(String, String)> = entities
+            .iter()
+            .filter_map(|e| match (e.get(TYPENAME), e.get("id")) {
+                (Some(Value::String(entity_type)), Some(Value::String(entity_id))) => {
+                    Some((entity_type.to_owned(), entity_id.to_owned()))
+                }
+                _ => None,
+            })
+            .collect();
+        if keys.is_empty() {
+            return Ok(entities);
+        }
+        let (entity_types, ids): (Vec<_>, Vec<_>) = keys.into_iter().unzip();
+
+        let rows = {
+            use db_schema::offchain_entities::dsl::*;
+
+            offchain_entities
+                .filter(subgraph.eq(subgraph_id))
+                .filter(entity.eq_any(&entity_types))
+                .filter(id.eq_any(&ids))
+                .select((entity, id, data))
+                .order((updated_at, source))
+                .load::<(String, String, serde_json::Value)>(&self.conn)
+                .map_err(|e| {
+                    error!(self.logger, "Failed to load offchain entities";
+                           "subgraph" => subgraph_id, "error" => e.to_string())
+                })?
+        };
+        if rows.is_empty() {
+            return Ok(entities);
+        }
+
+        let mut offchain_entities: HashMap<(String, String), Entity> = HashMap::new();
+        for (entity_type, entity_id, value) in rows {
+            let update =
+                serde_json::from_value::<Entity>(value).expect("Failed to deserialize entity");
+            offchain_entities
+                .entry((entity_type, entity_id))
+                .or_insert_with(Entity::new)
+                .merge(update);
+        }
+
+        Ok(entities
+            .into_iter()
+            .map(|onchain_entity| {
+                let offchain_entity = match (
+                    onchain_entity.get(TYPENAME),
+                    onchain_entity.get("id"),
+                ) {
+                    (Some(Value::String(entity_type)), Some(Value::String(entity_id))) => {
+                        offchain_entities.get(&(entity_type.to_owned(), entity_id.to_owned()))
+                    }
+                    _ => None,
+                };
+                match offchain_entity {
+                    Some(offchain_entity) => {
+                        let mut merged = offchain_entity.clone();
+                        merged.merge(onchain_entity);
+                        merged
+                    }
+                    None => onchain_entity,
+                }
+            })
+            .collect())
+    }
+
+    /// Merges `input_entity` into the entity with the given key, or inserts
+    /// it if there is no such entity yet, and bumps the entity's version.
+    /// The caller must hold the write lock of the deployment and run this in
+    /// a write transaction.
+    fn write_entity(
+        &self,
+        key: &StoreKey,
         input_entity: Entity,
-        input_event_source: EventSource,
-    ) -> Result<(), ()> {
-        debug!(self.logger, "set"; "key" => format!("{:?}", key));
+        input_event_source: &EventSource,
+    ) -> QueryResult<()> {
+        self.record_block(&key.subgraph, input_event_source)?;
 
         use db_schema::entities::dsl::*;
 
-        // Update the existing entity, if necessary
-        let updated_entity = match self.get(key.clone()) {
+        // Update the existing entity, if necessary; the attributes set
+        // offchain must not end up in the entities table
+        let mut updated_entity = match self.onchain_entity(key.clone()) {
             Ok(mut existing_entity) => {
                 existing_entity.merge(input_entity);
                 existing_entity
@@ -114,11 +818,21 @@ impl BasicStore for Store {
             Err(_) => input_entity,
         };
 
+        // The type of the entity is stored in its own column
+        updated_entity.remove(TYPENAME);
+
         // Convert Entity hashmap to serde_json::Value for insert
-        let entity_json: serde_json::Value =
+        let mut entity_json: serde_json::Value =
             serde_json::to_value(&updated_entity).expect("Failed to serialize entity");
 
+        // Store large values separately, so identical ones are only stored once
+        if let Some(threshold) = self.config.intern_threshold {
+            let attributes = intern_values(&self.conn, &mut entity_json, threshold)?;
+            record_interned_attributes(&self.conn, &key.subgraph, &key.entity, &attributes)?;
+        }
+
         // Insert entity, perform an update in case of a primary key conflict
+        let now = current_timestamp() as i64;
         insert_into(entities)
             .values((
                 id.eq(&key.id),
@@ -126,6 +840,7 @@ impl BasicStore for Store {
                 subgraph.eq(&key.subgraph),
                 data.eq(&entity_json),
                 event_source.eq(&input_event_source.to_string()),
+                updated_at.eq(now),
             ))
             .on_conflict((id, entity, subgraph))
             .do_update()
@@ -135,103 +850,582 @@ impl BasicStore for Store {
                 subgraph.eq(&key.subgraph),
                 data.eq(&entity_json),
                 event_source.eq(&input_event_source.to_string()),
+                updated_at.eq(now),
+                version.eq(version + 1),
             ))
-            .execute(&self.conn)
-            .map(|_| ())
-            .map_err(|_| ())
+            .execute(&self.conn)?;
+
+        self.write_fulltext_documents(key, &updated_entity)
+    }
+}
+
+impl BasicStore for Store {
+    fn get(&self, key: StoreKey) -> Result<Entity, ()> {
+        debug!(self.logger, "get"; "key" => format!("{:?}", key));
+
+        let subgraph_id = key.subgraph.clone();
+        self.timed(&subgraph_id, StoreOperation::Get, || {
+            // Offchain attributes are looked up by the type of the entity,
+            // which mappings don't get back
+            let entity_type = key.entity.clone();
+            let mut entity = self.onchain_entity(key)?;
+            entity.insert(String::from(TYPENAME), Value::from(entity_type));
+            self.merge_offchain(&subgraph_id, vec![entity]).map(|mut entities| {
+                let mut entity = entities.remove(0);
+                entity.remove(TYPENAME);
+                entity
+            })
+        })
+    }
+
+    fn get_many(
+        &self,
+        subgraph_id: &str,
+        ids_for_type: BTreeMap<String, Vec<String>>,
+        scope: QueryScope,
+    ) -> Result<BTreeMap<String, Vec<Entity>>, ()> {
+        debug!(self.logger, "get_many"; "subgraph" => subgraph_id,
+               "types" => format!("{:?}", ids_for_type.keys().collect::<Vec<_>>()));
+
+        self.timed(subgraph_id, StoreOperation::GetMany, || {
+            self.ensure_decompressed(subgraph_id)?;
+
+            use db_schema::entities::dsl::*;
+
+            // Fetch the entities of each type with a single query
+            ids_for_type
+                .into_iter()
+                .map(|(entity_type, ids)| {
+                    let values = self
+                        .with_query_settings(scope, subgraph_id, false, || {
+                            entities
+                                .filter(subgraph.eq(subgraph_id))
+                                .filter(entity.eq(&entity_type))
+                                .filter(id.eq_any(&ids))
+                                .select(data)
+                                .load::<serde_json::Value>(&self.conn)
+                                .and_then(|mut values| {
+                                    resolve_interned_values(&self.conn, &mut values)?;
+                                    Ok(values)
+                                })
+                        })
+                        .map_err(|e| {
+                            error!(self.logger, "Failed to load entities";
+                                   "entity" => &entity_type, "error" => e.to_string())
+                        })?;
+
+                    let found = values
+                        .into_iter()
+                        .map(|value| typed_entity_from_json(&entity_type, value))
+                        .collect();
+                    let found = self.merge_offchain(subgraph_id, found)?;
+                    Ok((entity_type, found))
+                })
+                .collect()
+        })
     }
 
-    fn delete(&mut self, key: StoreKey, input_event_source: EventSource) -> Result<(), ()> {
+    fn set(
+        &mut self,
+        key: StoreKey,
+        input_entity: Entity,
+        input_event_source: EventSource,
+    ) -> Result<(), StoreWriteError> {
+        debug!(self.logger, "set"; "key" => format!("{:?}", key));
+
+        self.ensure_write_lock(&key.subgraph)?;
+
+        let subgraph_id = key.subgraph.clone();
+        let result = self
+            .timed(&subgraph_id, StoreOperation::Set, || {
+                self.write_transaction(&subgraph_id, || {
+                    self.write_entity(&key, input_entity.clone(), &input_event_source)
+                })
+            })
+            .map_err(|e| {
+                error!(self.logger, "Failed to set entity";
+                       "subgraph" => &subgraph_id, "error" => e.to_string());
+                StoreWriteError::Failed
+            });
+
+        if result.is_ok() {
+            self.send_subgraph_changed(&subgraph_id, &input_event_source);
+        }
+        result
+    }
+
+    fn delete(
+        &mut self,
+        key: StoreKey,
+        input_event_source: EventSource,
+    ) -> Result<(), StoreWriteError> {
         debug!(self.logger, "delete"; "key" => format!("{:?}", key));
 
-        use db_schema::entities::dsl::*;
+        self.ensure_write_lock(&key.subgraph)?;
+        self.ensure_decompressed(&key.subgraph)
+            .map_err(|()| StoreWriteError::Failed)?;
 
-        self.conn
-            .transaction::<usize, result::Error, _>(|| {
-                // Set session variable to store the source of the event
-                select(set_config(
-                    "vars.current_event_source",
-                    input_event_source.to_string(),
-                    false,
-                )).execute(&self.conn)
-                    .unwrap();
-
-                // Delete from DB where rows match the subgraph ID, entity name and ID
-                delete(
-                    entities
-                        .filter(subgraph.eq(&key.subgraph))
-                        .filter(entity.eq(&key.entity))
-                        .filter(id.eq(&key.id)),
-                ).execute(&self.conn)
+        let subgraph_id = key.subgraph.clone();
+        let result = self
+            .timed(&subgraph_id, StoreOperation::Delete, || {
+                self.write_transaction(&subgraph_id, || {
+                    self.record_block(&key.subgraph, &input_event_source)?;
+
+                    use db_schema::entities::dsl::*;
+
+                    // Set session variable to store the source of the event
+                    select(set_config(
+                        "vars.current_event_source",
+                        input_event_source.to_string(),
+                        false,
+                    )).execute(&self.conn)?;
+
+                    sql_query(
+                        "DELETE FROM fulltext_documents \
+                         WHERE subgraph = $1 AND entity = $2 AND id = $3",
+                    ).bind::<Text, _>(&key.subgraph)
+                        .bind::<Text, _>(&key.entity)
+                        .bind::<Text, _>(&key.id)
+                        .execute(&self.conn)?;
+
+                    // Delete from DB where rows match the subgraph ID, entity name and ID
+                    delete(
+                        entities
+                            .filter(subgraph.eq(&key.subgraph))
+                            .filter(entity.eq(&key.entity))
+                            .filter(id.eq(&key.id)),
+                    ).execute(&self.conn)
+                        .map(|_| ())
+                })
             })
-            .map(|_| ())
-            .map_err(|_| ())
+            .map_err(|e| {
+                error!(self.logger, "Failed to delete entity";
+                       "subgraph" => &subgraph_id, "error" => e.to_string());
+                StoreWriteError::Failed
+            });
+
+        if result.is_ok() {
+            self.send_subgraph_changed(&subgraph_id, &input_event_source);
+        }
+        result
     }
 
     fn find(&self, query: StoreQuery) -> Result<Vec<Entity>, ()> {
-        use db_schema::entities::dsl::*;
+        let subgraph_id = query.subgraph.clone();
+        let scope = query.scope;
+        self.timed(&subgraph_id, StoreOperation::Find, || {
+            self.ensure_decompressed(&query.subgraph)?;
 
-        // Create base boxed query; this will be added to based on the
-        // query parameters provided
-        let mut diesel_query = entities
-            .filter(entity.eq(query.entity))
-            .filter(subgraph.eq(query.subgraph))
-            .select(data)
-            .into_boxed::<Pg>();
+            let entity_type = query.entity.clone();
+            let regex = self.uses_regex(&query.filter)?;
+            let diesel_query = self.entity_query(query)?;
 
-        // Add specified filter to query
-        if let Some(filter) = query.filter {
-            diesel_query = store_filter(diesel_query, filter).map_err(|e| {
-                error!(self.logger, "value does not support this filter";
-                                    "value" => format!("{:?}", e.value),
-                                    "filter" => e.filter)
-            })?;
-        }
+            debug!(self.logger, "find";
+                    "sql" => format!("{:?}", debug_query::<Pg, _>(&diesel_query)));
 
-        // Add order by filters to query
-        if let Some(order_attribute) = query.order_by {
-            let direction = query
-                .order_direction
-                .map(|direction| match direction {
-                    StoreOrder::Ascending => String::from("ASC"),
-                    StoreOrder::Descending => String::from("DESC"),
+            // Process results; deserialize JSON data
+            let values = self
+                .with_query_settings(scope, &subgraph_id, regex, || {
+                    diesel_query
+                        .load::<serde_json::Value>(&self.conn)
+                        .and_then(|mut values| {
+                            resolve_interned_values(&self.conn, &mut values)?;
+                            Ok(values)
+                        })
                 })
-                .unwrap_or(String::from("ASC"));
+                .map_err(|_| ())?;
+            let entities = values
+                .into_iter()
+                .map(|value| typed_entity_from_json(&entity_type, value))
+                .collect();
+            self.merge_offchain(&subgraph_id, entities)
+        })
+    }
 
-            diesel_query = diesel_query.order(
-                sql::<Text>("data ->> ")
-                    .bind::<Text, _>(order_attribute)
-                    .sql(&format!(" {} ", direction)),
-            )
-        }
+    fn explain(&self, query: StoreQuery) -> Option<String> {
+        self.entity_query(query)
+            .ok()
+            .map(|diesel_query| debug_query::<Pg, _>(&diesel_query).to_string())
+    }
 
-        // Add range filter to query
-        if let Some(range) = query.range {
-            diesel_query = diesel_query
-                .limit(range.first as i64)
-                .offset(range.skip as i64);
+    fn check_query(&self, query: &StoreQuery) -> Result<(), QueryExecutionError> {
+        // Translating the filter into SQL rejects exactly the filters that
+        // `find` would fail on, without touching the database
+        if let Some(ref filter) = query.filter {
+            let entities_query = db_schema::entities::table
+                .select(db_schema::entities::data)
+                .into_boxed::<Pg>();
+            store_filter(entities_query, "", filter.clone())?;
         }
+        self.check_interned_attributes(query)
+    }
 
-        debug!(self.logger, "find";
-                "sql" => format!("{:?}", debug_query::<Pg, _>(&diesel_query)));
+    fn find_windowed(
+        &self,
+        query: StoreQuery,
+        window: StoreWindow,
+    ) -> Result<Vec<(String, Entity)>, ()> {
+        let subgraph_id = query.subgraph.clone();
+        let scope = query.scope;
+        self.timed(&subgraph_id, StoreOperation::Find, || {
+            self.ensure_decompressed(&query.subgraph)?;
 
-        // Process results; deserialize JSON data
-        diesel_query
-            .load::<serde_json::Value>(&self.conn)
-            .map(|values| {
-                values
-                    .into_iter()
-                    .map(|value| {
-                        serde_json::from_value::<Entity>(value)
-                            .expect("Error to deserialize entity")
-                    })
-                    .collect()
+            let entity_type = query.entity.clone();
+            let regex = self.uses_regex(&query.filter)?;
+
+            // Restrict the query to the entities of the parent that the
+            // lateral join runs it for
+            let parent_filter = if window.is_list {
+                sql::<Bool>("data -> ")
+                    .bind::<Text, _>(window.attribute)
+                    .sql(" @> jsonb_build_array(parent_id)")
+            } else {
+                sql::<Bool>("(data ->> ")
+                    .bind::<Text, _>(window.attribute)
+                    .sql(") = parent_id")
+            };
+            let window_query = WindowQuery {
+                parent_ids: window.parent_ids,
+                children: self.entity_query(query)?.filter(parent_filter),
+            };
+
+            debug!(self.logger, "find_windowed";
+                    "sql" => format!("{:?}", debug_query::<Pg, _>(&window_query)));
+
+            let (parent_ids, values): (Vec<String>, Vec<serde_json::Value>) = self
+                .with_query_settings(scope, &subgraph_id, regex, || {
+                    window_query
+                        .load::<(String, serde_json::Value)>(&self.conn)
+                        .and_then(|rows| {
+                            let (parent_ids, mut values): (Vec<_>, Vec<_>) =
+                                rows.into_iter().unzip();
+                            resolve_interned_values(&self.conn, &mut values)?;
+                            Ok((parent_ids, values))
+                        })
+                })
+                .map_err(|_| ())?;
+            let entities = values
+                .into_iter()
+                .map(|value| typed_entity_from_json(&entity_type, value))
+                .collect();
+            let entities = self.merge_offchain(&subgraph_id, entities)?;
+            Ok(parent_ids.into_iter().zip(entities.into_iter()).collect())
+        })
+    }
+
+    fn earliest_history_block(&self, subgraph_id: &str) -> Option<u64> {
+        use db_schema::history_blocks::dsl::*;
+
+        history_blocks
+            .filter(subgraph.eq(subgraph_id))
+            .select(min(block_number))
+            .first::<Option<i64>>(&self.conn)
+            .map_err(|e| {
+                error!(self.logger, "Failed to look up the earliest block with history";
+                       "subgraph" => subgraph_id, "error" => e.to_string())
             })
-            .map_err(|_| ())
+            .ok()
+            .and_then(|number| number)
+            .map(|number| number as u64)
     }
-}
 
-impl StoreTrait for Store {
+    fn revert_block_operations(
+        &mut self,
+        subgraph_id: &str,
+        block_hash: H256,
+    ) -> Result<u64, StoreWriteError> {
+        self.ensure_write_lock(subgraph_id)?;
+        self.ensure_decompressed(subgraph_id)
+            .map_err(|()| StoreWriteError::Failed)?;
+
+        // Entity changes are tagged with the event source they were written
+        // for, which is the hash of their block
+        let block_hash = format!("{:x}", block_hash);
+        let reverted = self
+            .write_transaction(subgraph_id, || {
+                // Undo the changes of the subgraph in the block, latest first
+                let rows = sql::<(Integer, SmallInt, Text, Text)>(
+                    "SELECT h.id, m.op_id, h.entity, h.entity_id \
+                     FROM entity_history h \
+                     JOIN event_meta_data m ON m.id = h.event_id \
+                     WHERE h.subgraph = ",
+                ).bind::<Text, _>(subgraph_id)
+                    .sql(" AND m.source = ")
+                    .bind::<Text, _>(block_hash.as_str())
+                    .sql(" ORDER BY h.id DESC")
+                    .load::<(i32, i16, String, String)>(&self.conn)?;
+                let changes: Vec<(i32, i16)> = rows
+                    .iter()
+                    .map(|(history_id, op_id, _, _)| (*history_id, *op_id))
+                    .collect();
+                self.revert_changes(&changes)?;
+
+                sql_query("DELETE FROM history_blocks WHERE subgraph = $1 AND block_hash = $2")
+                    .bind::<Text, _>(subgraph_id)
+                    .bind::<Text, _>(block_hash.as_str())
+                    .execute(&self.conn)?;
+
+                // Search documents are written again for the reverted entities
+                let reverted_keys: HashSet<(String, String)> = rows
+                    .into_iter()
+                    .map(|(_, _, entity_type, entity_id)| (entity_type, entity_id))
+                    .collect();
+                self.rewrite_fulltext_documents(subgraph_id, &reverted_keys)?;
+
+                Ok(changes.len() as u64)
+            })
+            .map_err(|e| {
+                error!(self.logger, "Failed to revert block";
+                       "subgraph" => subgraph_id, "block" => &block_hash,
+                       "error" => e.to_string());
+                StoreWriteError::Failed
+            })?;
+
+        self.recorded_blocks.lock().unwrap().remove(subgraph_id);
+
+        info!(self.logger, "Reverted block";
+              "subgraph" => subgraph_id, "block" => &block_hash, "changes" => reverted);
+        Ok(reverted)
+    }
+
+    fn search(&self, query: StoreQuery, search: FulltextSearch) -> Result<Vec<Entity>, ()> {
+        let subgraph_id = query.subgraph.clone();
+        let scope = query.scope;
+        self.timed(&subgraph_id, StoreOperation::Find, || {
+            self.ensure_decompressed(&query.subgraph)?;
+
+            // Without a range, return as many entities as a page of a
+            // collection field would by default
+            let (first, skip) = query
+                .range
+                .map_or((100, 0), |range| (range.first as i64, range.skip as i64));
+
+            // The best matches come first
+            let search_query = sql::<Jsonb>(
+                "SELECT e.data FROM entities e \
+                 JOIN fulltext_documents d \
+                 ON d.subgraph = e.subgraph AND d.entity = e.entity AND d.id = e.id \
+                 WHERE d.subgraph = ",
+            ).bind::<Text, _>(&query.subgraph)
+                .sql(" AND d.entity = ")
+                .bind::<Text, _>(&query.entity)
+                .sql(" AND d.name = ")
+                .bind::<Text, _>(&search.name)
+                .sql(" AND d.document @@ plainto_tsquery(CAST(")
+                .bind::<Text, _>(&search.language)
+                .sql(" AS regconfig), ")
+                .bind::<Text, _>(&search.text)
+                .sql(") ORDER BY ts_rank(d.document, plainto_tsquery(CAST(")
+                .bind::<Text, _>(&search.language)
+                .sql(" AS regconfig), ")
+                .bind::<Text, _>(&search.text)
+                .sql(")) DESC, e.id LIMIT ")
+                .bind::<BigInt, _>(first)
+                .sql(" OFFSET ")
+                .bind::<BigInt, _>(skip);
+
+            debug!(self.logger, "search";
+                    "sql" => format!("{:?}", debug_query::<Pg, _>(&search_query)));
+
+            let values = self
+                .with_query_settings(scope, &subgraph_id, false, || {
+                    search_query
+                        .load::<serde_json::Value>(&self.conn)
+                        .and_then(|mut values| {
+                            resolve_interned_values(&self.conn, &mut values)?;
+                            Ok(values)
+                        })
+                })
+                .map_err(|e| {
+                    error!(self.logger, "Failed to search entities";
+                           "search" => &search.name, "error" => e.to_string())
+                })?;
+            let entities = values
+                .into_iter()
+                .map(|value| typed_entity_from_json(&query.entity, value))
+                .collect();
+            self.merge_offchain(&subgraph_id, entities)
+        })
+    }
+
+    fn aggregate(
+        &self,
+        query: StoreQuery,
+        aggregates: Vec<StoreAggregate>,
+    ) -> Result<Vec<Value>, ()> {
+        let subgraph_id = query.subgraph.clone();
+        let scope = query.scope;
+        self.timed(&subgraph_id, StoreOperation::Find, || {
+            self.ensure_decompressed(&query.subgraph)?;
+
+            // Aggregate over all matching entities rather than a page of them
+            let entity_type = query.entity.clone();
+            let regex = self.uses_regex(&query.filter)?;
+            let aggregate_query = AggregateQuery {
+                entities: self.entity_query(StoreQuery {
+                    range: None,
+                    order_by: None,
+                    order_direction: None,
+                    ..query
+                })?,
+                aggregates: aggregates.clone(),
+            };
+
+            debug!(self.logger, "aggregate";
+                    "sql" => format!("{:?}", debug_query::<Pg, _>(&aggregate_query)));
+
+            let values = self
+                .with_query_settings(scope, &subgraph_id, regex, || {
+                    aggregate_query.get_result::<serde_json::Value>(&self.conn)
+                })
+                .map_err(|e| {
+                    error!(self.logger, "Failed to aggregate entities";
+                           "entity" => &entity_type, "error" => e.to_string())
+                })?;
+            let values = match values {
+                serde_json::Value::Array(values) => values,
+                _ => return Err(()),
+            };
+
+            aggregates
+                .iter()
+                .zip(values)
+                .map(|(aggregate, value)| match (aggregate, value) {
+                    (_, serde_json::Value::Null) => Ok(Value::Null),
+                    (StoreAggregate::Count, serde_json::Value::String(count)) => {
+                        count.parse().map(Value::Int).map_err(|_| ())
+                    }
+                    (_, serde_json::Value::String(number)) => {
+                        number.parse().map(Value::BigDecimal).map_err(|_| ())
+                    }
+                    _ => Err(()),
+                })
+                .collect()
+        })
+    }
+
+    fn histogram(
+        &self,
+        query: StoreQuery,
+        attribute: Attribute,
+        size: BigDecimal,
+    ) -> Result<Vec<(BigDecimal, i32)>, ()> {
+        let subgraph_id = query.subgraph.clone();
+        let scope = query.scope;
+        self.timed(&subgraph_id, StoreOperation::Find, || {
+            self.ensure_decompressed(&query.subgraph)?;
+
+            // Bucket all matching entities rather than a page of them
+            let entity_type = query.entity.clone();
+            let regex = self.uses_regex(&query.filter)?;
+            let histogram_query = HistogramQuery {
+                entities: self.entity_query(StoreQuery {
+                    range: None,
+                    order_by: None,
+                    order_direction: None,
+                    ..query
+                })?,
+                attribute,
+                size: size.to_string(),
+            };
+
+            debug!(self.logger, "histogram";
+                    "sql" => format!("{:?}", debug_query::<Pg, _>(&histogram_query)));
+
+            let rows = self
+                .with_query_settings(scope, &subgraph_id, regex, || {
+                    histogram_query.load::<(String, i64)>(&self.conn)
+                })
+                .map_err(|e| {
+                    error!(self.logger, "Failed to compute histogram";
+                           "entity" => &entity_type, "error" => e.to_string())
+                })?;
+            rows.into_iter()
+                .map(|(bucket, count)| {
+                    bucket
+                        .parse()
+                        .map(|bucket| (bucket, count as i32))
+                        .map_err(|_| ())
+                })
+                .collect()
+        })
+    }
+
+    fn find_any(&self, query: StoreQuery, entity_types: Vec<String>) -> Result<Vec<Entity>, ()> {
+        if entity_types.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let subgraph_id = query.subgraph.clone();
+        let scope = query.scope;
+        self.timed(&subgraph_id, StoreOperation::Find, || {
+            self.ensure_decompressed(&query.subgraph)?;
+
+            use db_schema::entities::dsl::*;
+
+            let regex = self.uses_regex(&query.filter)?;
+
+            // Every type gets its own branch with the filter of the query
+            let mut branches = vec![];
+            for entity_type in entity_types {
+                self.check_interned_attributes(&StoreQuery {
+                    entity: entity_type.clone(),
+                    ..query.clone()
+                }).map_err(|e| {
+                    error!(self.logger, "Failed to build entity query"; "error" => e.to_string())
+                })?;
+
+                let mut branch = entities
+                    .filter(entity.eq(entity_type))
+                    .filter(subgraph.eq(subgraph_id.clone()))
+                    .select((entity, data))
+                    .into_boxed::<Pg>();
+                if let Some(ref filter) = query.filter {
+                    branch = store_filter(branch, &subgraph_id, filter.clone()).map_err(|e| {
+                        error!(self.logger, "value does not support this filter";
+                                            "value" => format!("{:?}", e.value),
+                                            "filter" => e.filter)
+                    })?;
+                }
+                branches.push(branch);
+            }
+
+            // Order and page the entities of all types together
+            let direction = query.order_direction;
+            let union_query = UnionQuery {
+                branches,
+                order: query.order_by.map(|(attribute, value_type)| {
+                    EntityOrder::new(attribute, value_type, direction).by_data_id()
+                }),
+                limit: query.range.map(|range| (range.first as i64, range.skip as i64)),
+            };
+
+            debug!(self.logger, "find_any";
+                    "sql" => format!("{:?}", debug_query::<Pg, _>(&union_query)));
+
+            let (entity_types, values): (Vec<String>, Vec<serde_json::Value>) = self
+                .with_query_settings(scope, &subgraph_id, regex, || {
+                    union_query
+                        .load::<(String, serde_json::Value)>(&self.conn)
+                        .and_then(|rows| {
+                            let (entity_types, mut values): (Vec<_>, Vec<_>) =
+                                rows.into_iter().unzip();
+                            resolve_interned_values(&self.conn, &mut values)?;
+                            Ok((entity_types, values))
+                        })
+                })
+                .map_err(|_| ())?;
+            let found = entity_types
+                .iter()
+                .zip(values.into_iter())
+                .map(|(entity_type, value)| typed_entity_from_json(entity_type, value))
+                .collect();
+            self.merge_offchain(&subgraph_id, found)
+        })
+    }
+}
+
+impl StoreTrait for Store {
     fn event_stream(&mut self) -> Result<Receiver<StoreEvent>, StreamError> {
         // If possible, create a new channel for streaming store events
         match self.event_sink {
@@ -244,3 +1438,844 @@ impl StoreTrait for Store {
         }
     }
 }
+
+impl ApiKeyStore for Store {
+    fn create_api_key(&mut self, input_name: String) -> Result<ApiKey, GraphError> {
+        use db_schema::api_keys::dsl::*;
+
+        // 32 random bytes, hex-encoded
+        let random_bytes: [u8; 32] = thread_rng().gen();
+        let new_key = random_bytes
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>();
+
+        insert_into(api_keys)
+            .values((key.eq(&new_key), name.eq(&input_name)))
+            .execute(&self.conn)
+            .map_err(GraphError::store)?;
+
+        info!(self.logger, "Created API key"; "name" => &input_name);
+
+        Ok(ApiKey {
+            key: new_key,
+            name: input_name,
+            usage: ApiKeyUsage::default(),
+        })
+    }
+
+    fn api_key(&self, input_key: &str) -> Result<Option<ApiKey>, GraphError> {
+        use db_schema::api_keys::dsl::*;
+
+        api_keys
+            .find(input_key)
+            .select((key, name, query_count, error_count, cost))
+            .first::<(String, String, i64, i64, i64)>(&self.conn)
+            .optional()
+            .map(|row| row.map(api_key_from_row))
+            .map_err(GraphError::store)
+    }
+
+    fn api_keys(&self) -> Result<Vec<ApiKey>, GraphError> {
+        use db_schema::api_keys::dsl::*;
+
+        api_keys
+            .select((key, name, query_count, error_count, cost))
+            .order(name.asc())
+            .load::<(String, String, i64, i64, i64)>(&self.conn)
+            .map(|rows| rows.into_iter().map(api_key_from_row).collect())
+            .map_err(GraphError::store)
+    }
+
+    fn rename_api_key(&mut self, input_key: &str, input_name: String) -> Result<bool, GraphError> {
+        use db_schema::api_keys::dsl::*;
+
+        update(api_keys.find(input_key))
+            .set(name.eq(input_name))
+            .execute(&self.conn)
+            .map(|rows| rows > 0)
+            .map_err(GraphError::store)
+    }
+
+    fn delete_api_key(&mut self, input_key: &str) -> Result<bool, GraphError> {
+        use db_schema::api_keys::dsl::*;
+
+        info!(self.logger, "Deleting API key");
+
+        delete(api_keys.find(input_key))
+            .execute(&self.conn)
+            .map(|rows| rows > 0)
+            .map_err(GraphError::store)
+    }
+
+    fn record_api_key_usage(
+        &mut self,
+        input_key: &str,
+        usage: ApiKeyUsage,
+    ) -> Result<(), GraphError> {
+        use db_schema::api_keys::dsl::*;
+
+        // Increment the counters in the database, so that concurrent updates
+        // from several nodes are not lost
+        update(api_keys.find(input_key))
+            .set((
+                query_count.eq(query_count + usage.queries as i64),
+                error_count.eq(error_count + usage.errors as i64),
+                cost.eq(cost + usage.cost as i64),
+            ))
+            .execute(&self.conn)
+            .map(|_| ())
+            .map_err(GraphError::store)
+    }
+}
+
+impl CompressionStore for Store {
+    fn compress_subgraph(&mut self, subgraph_id: &str) -> Result<CompressedSubgraph, GraphError> {
+        // A node that is still indexing the subgraph must not write to it
+        // while its entities are moved
+        self.ensure_write_lock(subgraph_id).map_err(|_| {
+            GraphError::from_message(ErrorCategory::Store, "Failed to lock deployment")
+        })?;
+
+        let compressed = self
+            .conn
+            .transaction::<_, result::Error, _>(|| {
+                let rows = {
+                    use db_schema::entities::dsl::*;
+
+                    entities
+                        .filter(subgraph.eq(subgraph_id))
+                        .select((id, entity, data, event_source))
+                        .load::<EntityRow>(&self.conn)?
+                };
+                if rows.is_empty() {
+                    return Ok(Ok(None));
+                }
+
+                // Nothing has been written yet if the entities can't be
+                // compressed; the error is reported once the transaction ends
+                let (uncompressed_size, blob) = match compress_entities(&rows) {
+                    Ok(compressed) => compressed,
+                    Err(e) => return Ok(Err(e)),
+                };
+                let compressed = CompressedSubgraph {
+                    subgraph_id: subgraph_id.to_owned(),
+                    entities: rows.len() as u64,
+                    size: uncompressed_size as u64,
+                    compressed_size: blob.len() as u64,
+                };
+
+                {
+                    use db_schema::compressed_subgraphs::dsl::*;
+
+                    insert_into(compressed_subgraphs)
+                        .values((
+                            subgraph.eq(subgraph_id),
+                            entity_count.eq(compressed.entities as i64),
+                            size.eq(compressed.size as i64),
+                            compressed_size.eq(compressed.compressed_size as i64),
+                            data.eq(&blob),
+                        ))
+                        .execute(&self.conn)?;
+                }
+
+                {
+                    use db_schema::entities::dsl::*;
+
+                    select(set_config(
+                        "vars.current_event_source",
+                        "COMPRESSION",
+                        true,
+                    )).execute(&self.conn)?;
+                    delete(entities.filter(subgraph.eq(subgraph_id))).execute(&self.conn)?;
+                }
+
+                // The history of a dormant subgraph is no longer needed to
+                // revert blocks, and would keep its data around uncompressed
+                delete_history(&self.conn, subgraph_id)?;
+
+                Ok(Ok(Some(compressed)))
+            })
+            .map_err(GraphError::store)?
+            .map_err(GraphError::store)?
+            .ok_or_else(|| {
+                GraphError::from_message(
+                    ErrorCategory::Store,
+                    format!("Subgraph {} has no entities to compress", subgraph_id),
+                )
+            })?;
+
+        self.compressed_subgraph_ids
+            .lock()
+            .unwrap()
+            .insert(subgraph_id.to_owned());
+
+        info!(self.logger, "Compressed subgraph";
+              "subgraph" => subgraph_id,
+              "entities" => compressed.entities,
+              "size" => compressed.size,
+              "compressed_size" => compressed.compressed_size);
+
+        Ok(compressed)
+    }
+
+    fn decompress_subgraph(&mut self, subgraph_id: &str) -> Result<bool, GraphError> {
+        self.decompress(subgraph_id)
+    }
+
+    fn delete_subgraph(&mut self, subgraph_id: &str) -> Result<u64, GraphError> {
+        let deleted = self
+            .conn
+            .transaction::<_, result::Error, _>(|| {
+                let compressed_entities = {
+                    use db_schema::compressed_subgraphs::dsl::*;
+
+                    delete(compressed_subgraphs.find(subgraph_id))
+                        .returning(entity_count)
+                        .get_result::<i64>(&self.conn)
+                        .optional()?
+                        .unwrap_or(0)
+                };
+
+                let deleted_entities = {
+                    use db_schema::entities::dsl::*;
+
+                    select(set_config(
+                        "vars.current_event_source",
+                        "DELETION",
+                        true,
+                    )).execute(&self.conn)?;
+                    delete(entities.filter(subgraph.eq(subgraph_id))).execute(&self.conn)?
+                };
+
+                // Reverting a block must not bring back entities of a deleted subgraph
+                delete_history(&self.conn, subgraph_id)?;
+
+                {
+                    use db_schema::offchain_entities::dsl::*;
+
+                    delete(offchain_entities.filter(subgraph.eq(subgraph_id)))
+                        .execute(&self.conn)?;
+                }
+
+                sql_query("DELETE FROM fulltext_documents WHERE subgraph = $1")
+                    .bind::<Text, _>(subgraph_id)
+                    .execute(&self.conn)?;
+
+                {
+                    use db_schema::deployment_usage::dsl::*;
+
+                    delete(deployment_usage.find(subgraph_id)).execute(&self.conn)?;
+                }
+
+                {
+                    use db_schema::mapping_pins::dsl::*;
+
+                    delete(mapping_pins.filter(subgraph.eq(subgraph_id))).execute(&self.conn)?;
+                }
+
+                {
+                    use db_schema::indexing_throughput::dsl::*;
+
+                    delete(indexing_throughput.filter(subgraph.eq(subgraph_id)))
+                        .execute(&self.conn)?;
+                }
+
+                {
+                    use db_schema::interned_attributes::dsl::*;
+
+                    delete(interned_attributes.filter(subgraph.eq(subgraph_id)))
+                        .execute(&self.conn)?;
+                }
+
+                Ok(compressed_entities as u64 + deleted_entities as u64)
+            })
+            .map_err(GraphError::store)?;
+
+        self.compressed_subgraph_ids
+            .lock()
+            .unwrap()
+            .remove(subgraph_id);
+        self.recorded_blocks.lock().unwrap().remove(subgraph_id);
+
+        info!(self.logger, "Deleted subgraph";
+              "subgraph" => subgraph_id, "entities" => deleted);
+
+        Ok(deleted)
+    }
+
+    fn compressed_subgraphs(&self) -> Result<Vec<CompressedSubgraph>, GraphError> {
+        use db_schema::compressed_subgraphs::dsl::*;
+
+        compressed_subgraphs
+            .select((subgraph, entity_count, size, compressed_size))
+            .order(subgraph.asc())
+            .load::<(String, i64, i64, i64)>(&self.conn)
+            .map(|rows| {
+                rows.into_iter()
+                    .map(|(subgraph_id, entities, original_size, blob_size)| {
+                        CompressedSubgraph {
+                            subgraph_id,
+                            entities: entities as u64,
+                            size: original_size as u64,
+                            compressed_size: blob_size as u64,
+                        }
+                    })
+                    .collect()
+            })
+            .map_err(GraphError::store)
+    }
+}
+
+impl DeploymentUsageStore for Store {
+    fn track_deployment(&mut self, subgraph_id: &str) -> Result<(), GraphError> {
+        use db_schema::deployment_usage::dsl::*;
+
+        insert_into(deployment_usage)
+            .values((
+                subgraph.eq(subgraph_id),
+                tracked_since.eq(current_timestamp() as i64),
+            ))
+            .on_conflict_do_nothing()
+            .execute(&self.conn)
+            .map(|_| ())
+            .map_err(GraphError::store)
+    }
+
+    fn record_deployment_query(&mut self, subgraph_id: &str) -> Result<(), GraphError> {
+        use db_schema::deployment_usage::dsl::*;
+
+        let now = current_timestamp() as i64;
+
+        // Increment the counter in the database, so that concurrent updates
+        // from several nodes are not lost
+        insert_into(deployment_usage)
+            .values((
+                subgraph.eq(subgraph_id),
+                query_count.eq(1),
+                tracked_since.eq(now),
+                last_queried_at.eq(now),
+            ))
+            .on_conflict(subgraph)
+            .do_update()
+            .set((query_count.eq(query_count + 1), last_queried_at.eq(now)))
+            .execute(&self.conn)
+            .map(|_| ())
+            .map_err(GraphError::store)
+    }
+
+    fn deployment_usage(&self) -> Result<Vec<DeploymentUsage>, GraphError> {
+        use db_schema::deployment_usage::dsl::*;
+
+        deployment_usage
+            .select((subgraph, query_count, tracked_since, last_queried_at))
+            .order(subgraph.asc())
+            .load::<(String, i64, i64, Option<i64>)>(&self.conn)
+            .map(|rows| {
+                rows.into_iter()
+                    .map(|(subgraph_id, queries, since, last_queried)| DeploymentUsage {
+                        subgraph_id,
+                        queries: queries as u64,
+                        tracked_since: since as u64,
+                        last_queried_at: last_queried.map(|timestamp| timestamp as u64),
+                    })
+                    .collect()
+            })
+            .map_err(GraphError::store)
+    }
+}
+
+impl MappingPinStore for Store {
+    fn mapping_pins(&self, subgraph_id: &str) -> Result<Vec<MappingPin>, GraphError> {
+        use db_schema::mapping_pins::dsl::*;
+
+        mapping_pins
+            .select((data_source, api_version, module_hash))
+            .filter(subgraph.eq(subgraph_id))
+            .order(data_source.asc())
+            .load::<(String, String, String)>(&self.conn)
+            .map(|rows| {
+                rows.into_iter()
+                    .map(|(name, version, hash)| MappingPin {
+                        data_source: name,
+                        api_version: version,
+                        module_hash: hash,
+                    })
+                    .collect()
+            })
+            .map_err(GraphError::store)
+    }
+
+    fn pin_mappings(
+        &mut self,
+        subgraph_id: &str,
+        pins: Vec<MappingPin>,
+    ) -> Result<(), GraphError> {
+        use db_schema::mapping_pins::dsl::*;
+
+        self.conn
+            .transaction::<_, result::Error, _>(|| {
+                delete(mapping_pins.filter(subgraph.eq(subgraph_id))).execute(&self.conn)?;
+                for pin in pins {
+                    insert_into(mapping_pins)
+                        .values((
+                            subgraph.eq(subgraph_id),
+                            data_source.eq(pin.data_source),
+                            api_version.eq(pin.api_version),
+                            module_hash.eq(pin.module_hash),
+                        ))
+                        .execute(&self.conn)?;
+                }
+                Ok(())
+            })
+            .map_err(GraphError::store)
+    }
+}
+
+impl ThroughputStore for Store {
+    fn record_throughput(&mut self, samples: &[ThroughputSample]) -> Result<(), GraphError> {
+        use db_schema::indexing_throughput::dsl::*;
+
+        self.conn
+            .transaction::<_, result::Error, _>(|| {
+                for sample in samples {
+                    insert_into(indexing_throughput)
+                        .values((
+                            subgraph.eq(&sample.subgraph_id),
+                            started_at.eq(sample.started_at as i64),
+                            seconds.eq(sample.seconds as i64),
+                            blocks.eq(sample.blocks as i64),
+                            triggers.eq(sample.triggers as i64),
+                            entities.eq(sample.entities as i64),
+                        ))
+                        .execute(&self.conn)?;
+                }
+                Ok(())
+            })
+            .map_err(GraphError::store)
+    }
+
+    fn throughput_history(
+        &self,
+        subgraph_id: &str,
+        since: u64,
+    ) -> Result<Vec<ThroughputSample>, GraphError> {
+        // Roll the samples up per hour
+        sql::<(BigInt, BigInt, BigInt, BigInt, BigInt)>(
+            "SELECT started_at / 3600 * 3600 AS hour, \
+             CAST(SUM(seconds) AS BIGINT), CAST(SUM(blocks) AS BIGINT), \
+             CAST(SUM(triggers) AS BIGINT), CAST(SUM(entities) AS BIGINT) \
+             FROM indexing_throughput \
+             WHERE subgraph = ",
+        ).bind::<Text, _>(subgraph_id)
+            .sql(" AND started_at / 3600 * 3600 >= ")
+            .bind::<BigInt, _>(since as i64)
+            .sql(" GROUP BY hour ORDER BY hour")
+            .load::<(i64, i64, i64, i64, i64)>(&self.conn)
+            .map(|rows| {
+                rows.into_iter()
+                    .map(|(start, length, block_count, trigger_count, entity_count)| {
+                        ThroughputSample {
+                            subgraph_id: subgraph_id.to_owned(),
+                            started_at: start as u64,
+                            seconds: length as u64,
+                            blocks: block_count as u64,
+                            triggers: trigger_count as u64,
+                            entities: entity_count as u64,
+                        }
+                    })
+                    .collect()
+            })
+            .map_err(GraphError::store)
+    }
+}
+
+impl EntityRetentionStore for Store {
+    fn prune_entities(
+        &mut self,
+        subgraph_id: &str,
+        entity_type: &str,
+        updated_before: u64,
+    ) -> Result<u64, GraphError> {
+        self.ensure_write_lock(subgraph_id).map_err(|_| {
+            GraphError::from_message(ErrorCategory::Store, "Failed to lock deployment")
+        })?;
+
+        let pruned = self
+            .conn
+            .transaction::<_, result::Error, _>(|| {
+                let ids = {
+                    use db_schema::entities::dsl::*;
+
+                    select(set_config(
+                        "vars.current_event_source",
+                        "RETENTION",
+                        true,
+                    )).execute(&self.conn)?;
+                    delete(
+                        entities
+                            .filter(subgraph.eq(subgraph_id))
+                            .filter(entity.eq(entity_type))
+                            .filter(updated_at.lt(updated_before as i64)),
+                    ).returning(id)
+                        .get_results::<String>(&self.conn)?
+                };
+
+                // Pruned entities are gone for good; reverting a block must
+                // not bring them back, and their history would take up space
+                sql_query(
+                    "DELETE FROM entity_history \
+                     WHERE subgraph = $1 AND entity = $2 AND entity_id = ANY($3)",
+                ).bind::<Text, _>(subgraph_id)
+                    .bind::<Text, _>(entity_type)
+                    .bind::<Array<Text>, _>(&ids)
+                    .execute(&self.conn)?;
+                sql_query(
+                    "DELETE FROM fulltext_documents \
+                     WHERE subgraph = $1 AND entity = $2 AND id = ANY($3)",
+                ).bind::<Text, _>(subgraph_id)
+                    .bind::<Text, _>(entity_type)
+                    .bind::<Array<Text>, _>(&ids)
+                    .execute(&self.conn)?;
+
+                Ok(ids.len() as u64)
+            })
+            .map_err(GraphError::store)?;
+
+        if pruned > 0 {
+            info!(self.logger, "Pruned entities";
+                  "subgraph" => subgraph_id, "entity" => entity_type, "entities" => pruned);
+            // Pruning is not tied to a block; it is tagged like its history
+            let event_source = EventSource::Offchain(String::from("RETENTION"));
+            self.send_subgraph_changed(subgraph_id, &event_source);
+        }
+
+        Ok(pruned)
+    }
+}
+
+impl RewindStore for Store {
+    fn rewind_subgraph(&mut self, subgraph_id: &str, block_number: u64) -> Result<u64, GraphError> {
+        self.ensure_decompressed(subgraph_id).map_err(|_| {
+            GraphError::from_message(ErrorCategory::Store, "Failed to decompress subgraph")
+        })?;
+        self.ensure_write_lock(subgraph_id).map_err(|_| {
+            GraphError::from_message(ErrorCategory::Store, "Failed to lock deployment")
+        })?;
+
+        let reverted = self
+            .conn
+            .transaction::<_, result::Error, _>(|| {
+                // Undo the changes of the subgraph, latest first
+                let changes = sql::<(Integer, SmallInt)>(
+                    "SELECT h.id, m.op_id \
+                     FROM entity_history h \
+                     JOIN event_meta_data m ON m.id = h.event_id \
+                     JOIN history_blocks b ON b.subgraph = h.subgraph AND b.block_hash = m.source \
+                     WHERE h.subgraph = ",
+                ).bind::<Text, _>(subgraph_id)
+                    .sql(" AND b.block_number > ")
+                    .bind::<BigInt, _>(block_number as i64)
+                    .sql(" ORDER BY h.id DESC")
+                    .load::<(i32, i16)>(&self.conn)?;
+                self.revert_changes(&changes)?;
+
+                let blocks = sql_query(
+                    "DELETE FROM history_blocks WHERE subgraph = $1 AND block_number > $2",
+                ).bind::<Text, _>(subgraph_id)
+                    .bind::<BigInt, _>(block_number as i64)
+                    .execute(&self.conn)?;
+
+                // Search documents are written again for the reverted entities
+                sql_query("DELETE FROM fulltext_documents WHERE subgraph = $1")
+                    .bind::<Text, _>(subgraph_id)
+                    .execute(&self.conn)?;
+
+                Ok(blocks as u64)
+            })
+            .map_err(GraphError::store)?;
+
+        self.recorded_blocks.lock().unwrap().remove(subgraph_id);
+        self.write_missing_fulltext_documents(subgraph_id)
+            .map_err(GraphError::store)?;
+
+        info!(self.logger, "Rewound subgraph";
+              "subgraph" => subgraph_id, "block" => block_number, "blocks" => reverted);
+
+        Ok(reverted)
+    }
+}
+
+impl EntityExportStore for Store {
+    fn export_entities(&self, subgraph_id: &str) -> Result<Vec<Entity>, GraphError> {
+        self.ensure_decompressed(subgraph_id).map_err(|_| {
+            GraphError::from_message(ErrorCategory::Store, "Failed to decompress subgraph")
+        })?;
+
+        use db_schema::entities::dsl::*;
+
+        let (entity_types, mut values): (Vec<String>, Vec<serde_json::Value>) = entities
+            .filter(subgraph.eq(subgraph_id))
+            .order((entity, id))
+            .select((entity, data))
+            .load::<(String, serde_json::Value)>(&self.conn)
+            .map_err(GraphError::store)?
+            .into_iter()
+            .unzip();
+        resolve_interned_values(&self.conn, &mut values).map_err(GraphError::store)?;
+
+        Ok(entity_types
+            .iter()
+            .zip(values.into_iter())
+            .map(|(entity_type, value)| typed_entity_from_json(entity_type, value))
+            .collect())
+    }
+}
+
+impl EntityVersionStore for Store {
+    fn entity_version(&self, key: StoreKey) -> Result<Option<u64>, GraphError> {
+        self.ensure_decompressed(&key.subgraph).map_err(|_| {
+            GraphError::from_message(ErrorCategory::Store, "Failed to decompress subgraph")
+        })?;
+
+        use db_schema::entities::dsl::*;
+
+        entities
+            .find((key.id, key.subgraph, key.entity))
+            .select(version)
+            .first::<i64>(&self.conn)
+            .optional()
+            .map(|row| row.map(|entity_version| entity_version as u64))
+            .map_err(GraphError::store)
+    }
+
+    fn set_if_version(
+        &mut self,
+        key: StoreKey,
+        input_entity: Entity,
+        expected_version: Option<u64>,
+        input_event_source: EventSource,
+    ) -> Result<Option<u64>, GraphError> {
+        self.ensure_write_lock(&key.subgraph).map_err(|e| {
+            GraphError::from_message(ErrorCategory::Store, e.to_string())
+        })?;
+        self.ensure_decompressed(&key.subgraph).map_err(|_| {
+            GraphError::from_message(ErrorCategory::Store, "Failed to decompress subgraph")
+        })?;
+
+        let subgraph_id = key.subgraph.clone();
+        let new_version = self.timed(&subgraph_id, StoreOperation::Set, || {
+            // Lock the entity until it is written, so that nobody can modify
+            // it between checking its version and writing it
+            self.write_transaction(&subgraph_id, || {
+                let current_version = {
+                    use db_schema::entities::dsl::*;
+
+                    entities
+                        .find((&key.id, &key.subgraph, &key.entity))
+                        .select(version)
+                        .for_update()
+                        .first::<i64>(&self.conn)
+                        .optional()?
+                        .map(|current_version| current_version as u64)
+                };
+                if current_version != expected_version {
+                    return Ok(None);
+                }
+
+                self.write_entity(&key, input_entity.clone(), &input_event_source)?;
+                Ok(Some(expected_version.map_or(1, |version| version + 1)))
+            })
+        }).map_err(GraphError::store)?;
+
+        if new_version.is_some() {
+            self.send_subgraph_changed(&subgraph_id, &input_event_source);
+        }
+        Ok(new_version)
+    }
+}
+
+impl OffchainEntityStore for Store {
+    fn set_offchain(
+        &mut self,
+        key: StoreKey,
+        input_entity: Entity,
+        input_source: String,
+    ) -> Result<(), GraphError> {
+        self.ensure_write_lock(&key.subgraph).map_err(|_| {
+            GraphError::from_message(ErrorCategory::Store, "Failed to lock deployment")
+        })?;
+
+        let subgraph_id = key.subgraph.clone();
+        self.timed(&subgraph_id, StoreOperation::Set, || {
+            use db_schema::offchain_entities::dsl::*;
+
+            self.conn.transaction::<_, result::Error, _>(|| {
+                let existing_entity = offchain_entities
+                    .find((&key.subgraph, &key.entity, &key.id, &input_source))
+                    .select(data)
+                    .for_update()
+                    .first::<serde_json::Value>(&self.conn)
+                    .optional()?;
+                let updated_entity = match existing_entity {
+                    Some(value) => {
+                        let mut existing_entity = serde_json::from_value::<Entity>(value)
+                            .expect("Failed to deserialize entity");
+                        existing_entity.merge(input_entity);
+                        existing_entity
+                    }
+                    None => input_entity,
+                };
+                let entity_json =
+                    serde_json::to_value(&updated_entity).expect("Failed to serialize entity");
+
+                let now = current_timestamp() as i64;
+                insert_into(offchain_entities)
+                    .values((
+                        id.eq(&key.id),
+                        subgraph.eq(&key.subgraph),
+                        entity.eq(&key.entity),
+                        source.eq(&input_source),
+                        data.eq(&entity_json),
+                        updated_at.eq(now),
+                    ))
+                    .on_conflict((subgraph, entity, id, source))
+                    .do_update()
+                    .set((data.eq(&entity_json), updated_at.eq(now)))
+                    .execute(&self.conn)
+                    .map(|_| ())
+            })
+        }).map_err(GraphError::store)?;
+
+        self.send_subgraph_changed(&subgraph_id, &EventSource::Offchain(input_source));
+        Ok(())
+    }
+
+    fn drop_offchain(
+        &mut self,
+        subgraph_id: &str,
+        input_source: Option<&str>,
+    ) -> Result<u64, GraphError> {
+        use db_schema::offchain_entities::dsl::*;
+
+        let dropped = match input_source {
+            Some(input_source) => delete(
+                offchain_entities
+                    .filter(subgraph.eq(subgraph_id))
+                    .filter(source.eq(input_source)),
+            ).execute(&self.conn),
+            None => delete(offchain_entities.filter(subgraph.eq(subgraph_id))).execute(&self.conn),
+        }.map_err(GraphError::store)? as u64;
+
+        if dropped > 0 {
+            info!(self.logger, "Dropped offchain entities";
+                  "subgraph" => subgraph_id, "entities" => dropped);
+            // Dropping the entities of all sources has no single source
+            let event_source = EventSource::Offchain(input_source.unwrap_or("").to_owned());
+            self.send_subgraph_changed(subgraph_id, &event_source);
+        }
+
+        Ok(dropped)
+    }
+}
+
+impl FulltextStore for Store {
+    fn set_fulltext_definitions(
+        &mut self,
+        subgraph_id: &str,
+        definitions: Vec<FulltextDefinition>,
+    ) -> Result<(), GraphError> {
+        {
+            let mut fulltext_definitions = self.fulltext_definitions.lock().unwrap();
+            if definitions.is_empty() {
+                fulltext_definitions.remove(subgraph_id);
+                return Ok(());
+            }
+            fulltext_definitions.insert(subgraph_id.to_owned(), definitions);
+        }
+
+        // The entities of compressed subgraphs get their documents once
+        // they are decompressed
+        let written = self
+            .write_missing_fulltext_documents(subgraph_id)
+            .map_err(GraphError::store)?;
+        if written > 0 {
+            info!(self.logger, "Wrote search documents for existing entities";
+                  "subgraph" => subgraph_id, "documents" => written);
+        }
+        Ok(())
+    }
+}
+
+impl StoreMetrics for Store {
+    fn store_latencies(&self) -> Vec<StoreLatency> {
+        let mut latencies: Vec<_> = self
+            .latencies
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|((subgraph_id, operation), histogram)| StoreLatency {
+                subgraph_id: subgraph_id.clone(),
+                operation: *operation,
+                histogram: histogram.clone(),
+            })
+            .collect();
+        latencies.sort_by(|a, b| (&a.subgraph_id, a.operation).cmp(&(&b.subgraph_id, b.operation)));
+        latencies
+    }
+}
+
+/// Applies Postgres settings until the end of the current transaction.
+fn apply_settings(conn: &PgConnection, settings: &[(String, String)]) -> QueryResult<()> {
+    for (name, value) in settings {
+        select(set_config(name.as_str(), value.as_str(), true)).execute(conn)?;
+    }
+    Ok(())
+}
+
+/// Removes the change history of all entities of a subgraph.
+fn delete_history(conn: &PgConnection, subgraph_id: &str) -> QueryResult<usize> {
+    {
+        use db_schema::history_blocks::dsl::*;
+
+        delete(history_blocks.filter(subgraph.eq(subgraph_id))).execute(conn)?;
+    }
+
+    sql_query("DELETE FROM entity_history WHERE subgraph = $1")
+        .bind::<Text, _>(subgraph_id)
+        .execute(conn)
+}
+
+/// Writes the search document of an entity for a full-text search, parsing
+/// the text with the language of the search.
+fn write_fulltext_document(
+    conn: &PgConnection,
+    subgraph_id: &str,
+    definition: &FulltextDefinition,
+    entity_id: &str,
+    text: &str,
+) -> QueryResult<usize> {
+    sql_query(
+        "INSERT INTO fulltext_documents (subgraph, name, entity, id, document) \
+         VALUES ($1, $2, $3, $4, to_tsvector(CAST($5 AS regconfig), $6)) \
+         ON CONFLICT (subgraph, name, entity, id) DO UPDATE SET document = EXCLUDED.document",
+    ).bind::<Text, _>(subgraph_id)
+        .bind::<Text, _>(&definition.name)
+        .bind::<Text, _>(&definition.entity)
+        .bind::<Text, _>(entity_id)
+        .bind::<Text, _>(&definition.language)
+        .bind::<Text, _>(text)
+        .execute(conn)
+}
+
+fn api_key_from_row((key, name, queries, errors, cost): (String, String, i64, i64, i64)) -> ApiKey {
+    ApiKey {
+        key,
+        name,
+        usage: ApiKeyUsage {
+            queries: queries as u64,
+            errors: errors as u64,
+            cost: cost as u64,
+        },
+    }
+}
@@ -0,0 +1,185 @@
+use diesel::pg::{Pg, PgConnection};
+use diesel::query_builder::{AstPass, BoxedSelectStatement, Query, QueryFragment, QueryId};
+use diesel::query_dsl::RunQueryDsl;
+use diesel::result::QueryResult;
+use diesel::sql_types::{Array, BigInt, Jsonb, Text};
+use graph::prelude::{Attribute, StoreAggregate};
+
+use db_schema::entities;
+use order::EntityOrder;
+
+/// Runs a `SELECT data FROM entities` statement once for every parent ID
+/// through a lateral join, so that its `ORDER BY`, `LIMIT` and `OFFSET`
+/// apply to the entities of each parent separately.
+///
+/// The statement refers to the parent it is run for as `parent_id`. Rows
+/// are `(parent_id, data)` pairs, grouped by parent in the order of
+/// `parent_ids`.
+pub(crate) struct WindowQuery<'a> {
+    pub parent_ids: Vec<String>,
+    pub children: BoxedSelectStatement<'a, Jsonb, entities::table, Pg>,
+}
+
+impl<'a> QueryFragment<Pg> for WindowQuery<'a> {
+    fn walk_ast(&self, mut out: AstPass<Pg>) -> QueryResult<()> {
+        out.unsafe_to_cache_prepared();
+        out.push_sql("SELECT parents.parent_id, children.data FROM unnest(");
+        out.push_bind_param::<Array<Text>, _>(&self.parent_ids)?;
+        out.push_sql(") AS parents(parent_id) CROSS JOIN LATERAL (");
+        self.children.walk_ast(out.reborrow())?;
+        out.push_sql(") AS children");
+        Ok(())
+    }
+}
+
+impl<'a> QueryId for WindowQuery<'a> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<'a> Query for WindowQuery<'a> {
+    type SqlType = (Text, Jsonb);
+}
+
+impl<'a> RunQueryDsl<PgConnection> for WindowQuery<'a> {}
+
+/// Combines `SELECT entity, data FROM entities` statements for several
+/// entity types with `UNION ALL`, and orders and pages the entities of all
+/// types together.
+pub(crate) struct UnionQuery<'a> {
+    pub branches: Vec<BoxedSelectStatement<'a, (Text, Jsonb), entities::table, Pg>>,
+    /// How to order the entities of all types.
+    pub order: Option<EntityOrder>,
+    /// How many entities to return and how many to skip.
+    pub limit: Option<(i64, i64)>,
+}
+
+impl<'a> QueryFragment<Pg> for UnionQuery<'a> {
+    fn walk_ast(&self, mut out: AstPass<Pg>) -> QueryResult<()> {
+        out.unsafe_to_cache_prepared();
+        out.push_sql("SELECT entity, data FROM (");
+        for (index, branch) in self.branches.iter().enumerate() {
+            if index > 0 {
+                out.push_sql(" UNION ALL ");
+            }
+            out.push_sql("(");
+            branch.walk_ast(out.reborrow())?;
+            out.push_sql(")");
+        }
+        out.push_sql(") AS entities");
+
+        if let Some(ref order) = self.order {
+            out.push_sql(" ORDER BY ");
+            order.walk_ast(out.reborrow())?;
+        }
+        if let Some((ref first, ref skip)) = self.limit {
+            out.push_sql(" LIMIT ");
+            out.push_bind_param::<BigInt, _>(first)?;
+            out.push_sql(" OFFSET ");
+            out.push_bind_param::<BigInt, _>(skip)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> QueryId for UnionQuery<'a> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<'a> Query for UnionQuery<'a> {
+    type SqlType = (Text, Jsonb);
+}
+
+impl<'a> RunQueryDsl<PgConnection> for UnionQuery<'a> {}
+
+/// Computes aggregates over the entities that a `SELECT data FROM entities`
+/// statement returns. The row is a JSON array with an element for each
+/// aggregate, in order. Numbers are turned into text so that they keep
+/// their precision; aggregates over no values are `null`.
+pub(crate) struct AggregateQuery<'a> {
+    pub entities: BoxedSelectStatement<'a, Jsonb, entities::table, Pg>,
+    pub aggregates: Vec<StoreAggregate>,
+}
+
+impl<'a> QueryFragment<Pg> for AggregateQuery<'a> {
+    fn walk_ast(&self, mut out: AstPass<Pg>) -> QueryResult<()> {
+        out.unsafe_to_cache_prepared();
+        out.push_sql("SELECT jsonb_build_array(");
+        for (index, aggregate) in self.aggregates.iter().enumerate() {
+            if index > 0 {
+                out.push_sql(", ");
+            }
+            let (function, attribute) = match aggregate {
+                StoreAggregate::Count => {
+                    out.push_sql("COUNT(*)::text");
+                    continue;
+                }
+                StoreAggregate::Sum(attribute) => ("SUM", attribute),
+                StoreAggregate::Min(attribute) => ("MIN", attribute),
+                StoreAggregate::Max(attribute) => ("MAX", attribute),
+            };
+            out.push_sql(function);
+            out.push_sql("((data ->> ");
+            out.push_bind_param::<Text, _>(attribute)?;
+            out.push_sql(")::numeric)::text");
+        }
+        out.push_sql(") FROM (");
+        self.entities.walk_ast(out.reborrow())?;
+        out.push_sql(") AS entities");
+        Ok(())
+    }
+}
+
+impl<'a> QueryId for AggregateQuery<'a> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<'a> Query for AggregateQuery<'a> {
+    type SqlType = Jsonb;
+}
+
+impl<'a> RunQueryDsl<PgConnection> for AggregateQuery<'a> {}
+
+/// Counts the entities that a `SELECT data FROM entities` statement returns
+/// by the bucket of width `size` that the value of `attribute` falls into.
+/// Every row is the lower bound of a non-empty bucket, as text so that it
+/// keeps its precision, and the number of entities in it.
+pub(crate) struct HistogramQuery<'a> {
+    pub entities: BoxedSelectStatement<'a, Jsonb, entities::table, Pg>,
+    pub attribute: Attribute,
+    pub size: String,
+}
+
+impl<'a> QueryFragment<Pg> for HistogramQuery<'a> {
+    fn walk_ast(&self, mut out: AstPass<Pg>) -> QueryResult<()> {
+        out.unsafe_to_cache_prepared();
+        out.push_sql("SELECT bucket::text, COUNT(*) FROM (SELECT floor((data ->> ");
+        out.push_bind_param::<Text, _>(&self.attribute)?;
+        out.push_sql(")::numeric / ");
+        out.push_bind_param::<Text, _>(&self.size)?;
+        out.push_sql("::numeric) * ");
+        out.push_bind_param::<Text, _>(&self.size)?;
+        out.push_sql("::numeric AS bucket FROM (");
+        self.entities.walk_ast(out.reborrow())?;
+        out.push_sql(") AS entities) AS buckets WHERE bucket IS NOT NULL");
+        out.push_sql(" GROUP BY bucket ORDER BY bucket");
+        Ok(())
+    }
+}
+
+impl<'a> QueryId for HistogramQuery<'a> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<'a> Query for HistogramQuery<'a> {
+    type SqlType = (Text, BigInt);
+}
+
+impl<'a> RunQueryDsl<PgConnection> for HistogramQuery<'a> {}
@@ -10,12 +10,16 @@ use diesel::pg::PgConnection;
 use diesel::*;
 use ethereum_types::H256;
 use futures::sync::oneshot;
+use std::collections::BTreeMap;
 use std::panic;
 use std::sync::Mutex;
+use std::time::Duration;
 
 use graph::components::store::{
     EventSource, StoreFilter, StoreKey, StoreOrder, StoreQuery, StoreRange,
 };
+use graph::data::store::scalar::{BigDecimal, BigInt, Bytes};
+use graph::data::usage::current_timestamp;
 use graph::prelude::*;
 use graph_store_postgres::{db_schema, Store as DieselStore, StoreConfig};
 
@@ -79,7 +83,7 @@ fn create_test_entity(
     (
         test_key,
         test_entity,
-        EventSource::EthereumBlock(H256::from_slice(&block_hash.as_bytes())),
+        EventSource::EthereumBlock(H256::from_slice(&block_hash.as_bytes()), 0),
     )
 }
 
@@ -87,7 +91,15 @@ fn create_test_entity(
 fn insert_test_data() {
     let logger = Logger::root(slog::Discard, o!());
     let url = postgres_test_url();
-    let mut store = DieselStore::new(StoreConfig { url }, &logger);
+    let mut store = DieselStore::new(
+        StoreConfig {
+            url,
+            intern_threshold: None,
+            query_settings: vec![],
+            indexing_settings: vec![],
+        },
+        &logger,
+    );
 
     let test_entity_1 = create_test_entity(
         String::from("1"),
@@ -155,6 +167,33 @@ fn remove_test_data() {
     delete(entities)
         .execute(&conn)
         .expect("Failed to remove test data");
+    delete(db_schema::interned_values::table)
+        .execute(&conn)
+        .expect("Failed to remove interned test values");
+    delete(db_schema::interned_attributes::table)
+        .execute(&conn)
+        .expect("Failed to remove interned test attributes");
+    delete(db_schema::compressed_subgraphs::table)
+        .execute(&conn)
+        .expect("Failed to remove compressed test subgraphs");
+    delete(db_schema::deployment_usage::table)
+        .execute(&conn)
+        .expect("Failed to remove test deployment usage");
+    delete(db_schema::offchain_entities::table)
+        .execute(&conn)
+        .expect("Failed to remove offchain test entities");
+    delete(db_schema::history_blocks::table)
+        .execute(&conn)
+        .expect("Failed to remove test history blocks");
+    delete(db_schema::mapping_pins::table)
+        .execute(&conn)
+        .expect("Failed to remove test mapping pins");
+    delete(db_schema::indexing_throughput::table)
+        .execute(&conn)
+        .expect("Failed to remove test throughput samples");
+    sql_query("DELETE FROM fulltext_documents")
+        .execute(&conn)
+        .expect("Failed to remove test search documents");
 }
 
 #[test]
@@ -163,116 +202,1230 @@ fn delete_entity() {
         use db_schema::entities::dsl::*;
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let mut store = DieselStore::new(StoreConfig { url }, &logger);
+        let mut store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
 
         let test_key = StoreKey {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
             id: String::from("3"),
         };
-        let source = EventSource::EthereumBlock(H256::random());
-        store.delete(test_key, source).unwrap();
+        let source = EventSource::EthereumBlock(H256::random(), 1);
+        store.delete(test_key, source).unwrap();
+
+        //Get all ids in table
+        let all_ids = entities.select(id).load::<String>(&store.conn).unwrap();
+
+        // Check that that the deleted entity id is not present
+        assert!(!all_ids.contains(&String::from("3")));
+    })
+}
+
+#[test]
+fn get_entity() {
+    run_test(|| {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
+
+        let key = StoreKey {
+            subgraph: String::from("test_subgraph"),
+            entity: String::from("user"),
+            id: String::from("1"),
+        };
+        let result = store.get(key).unwrap();
+
+        let mut expected_entity = Entity::new();
+        expected_entity.insert(String::from("name"), Value::String(String::from("Johnton")));
+        expected_entity.insert(
+            String::from("email"),
+            Value::String(String::from("tonofjohn@email.com")),
+        );
+        expected_entity.insert(String::from("age"), Value::Int(67 as i32));
+        expected_entity.insert(String::from("weight"), Value::Float(184.4 as f32));
+        expected_entity.insert(String::from("coffee"), Value::Bool(false));
+
+        // Check that the expected entity was returned
+        assert_eq!(result, expected_entity);
+    })
+}
+
+#[test]
+fn get_many_entities() {
+    run_test(|| {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
+
+        let mut ids_for_type = BTreeMap::new();
+        ids_for_type.insert(
+            String::from("user"),
+            vec![String::from("1"), String::from("3"), String::from("4")],
+        );
+        ids_for_type.insert(String::from("band"), vec![String::from("1")]);
+
+        let result = store
+            .get_many("test_subgraph", ids_for_type, QueryScope::default())
+            .unwrap();
+
+        // Entities that don't exist are left out
+        let mut names = result["user"]
+            .iter()
+            .map(|entity| entity.get("name").unwrap().clone())
+            .collect::<Vec<_>>();
+        names.sort_by_key(|name| format!("{:?}", name));
+        assert_eq!(names, vec![Value::from("Johnton"), Value::from("Shaqueeena")]);
+        assert!(result["band"].is_empty());
+    })
+}
+
+#[test]
+fn find_windowed_pages_each_parent() {
+    run_test(|| {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
+        let this_query = StoreQuery {
+            subgraph: String::from("test_subgraph"),
+            entity: String::from("user"),
+            filter: None,
+            order_by: Some((String::from("email"), ValueType::String)),
+            order_direction: Some(StoreOrder::Descending),
+            range: Some(StoreRange { first: 1, skip: 0 }),
+            scope: QueryScope::default(),
+        };
+        let window = StoreWindow {
+            attribute: String::from("name"),
+            is_list: false,
+            parent_ids: vec![
+                String::from("Shaqueeena"),
+                String::from("Nobody"),
+                String::from("Johnton"),
+            ],
+        };
+        let returned_entities = store
+            .find_windowed(this_query, window)
+            .expect("store.find_windowed operation failed");
+
+        // Every parent gets its own page; parents without entities get none
+        let returned_emails = returned_entities
+            .iter()
+            .map(|(parent_id, entity)| (parent_id.as_str(), entity.get("email").unwrap().clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            returned_emails,
+            vec![
+                ("Shaqueeena", Value::from("teeko@email.com")),
+                ("Johnton", Value::from("tonofjohn@email.com")),
+            ]
+        );
+    })
+}
+
+#[test]
+fn intern_large_values() {
+    run_test(|| {
+        use db_schema::interned_values::dsl::*;
+
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let mut store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: Some(16),
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
+
+        for entity_id in vec!["8", "9"] {
+            let test_entity = create_test_entity(
+                String::from(entity_id),
+                String::from("user"),
+                String::from("Wanjon"),
+                String::from("wanawana@email.com"),
+                76 as i32,
+                111.7 as f32,
+                true,
+                String::from("MSjZmOE7UqBOzzYibsw9"),
+            );
+            store
+                .set(test_entity.0, test_entity.1, test_entity.2)
+                .expect("Failed to set entity in the store");
+        }
+
+        // Check that the email is only stored once and the name not at all
+        let count = interned_values
+            .filter(value.eq("wanawana@email.com"))
+            .count()
+            .get_result::<i64>(&store.conn)
+            .unwrap();
+        assert_eq!(count, 1);
+        let count = interned_values
+            .filter(value.eq("Wanjon"))
+            .count()
+            .get_result::<i64>(&store.conn)
+            .unwrap();
+        assert_eq!(count, 0);
+
+        // Check that interned values are resolved when reading entities
+        let key = StoreKey {
+            subgraph: String::from("test_subgraph"),
+            entity: String::from("user"),
+            id: String::from("8"),
+        };
+        let result = store.get(key).unwrap();
+        assert_eq!(
+            result.get("email"),
+            Some(&Value::String(String::from("wanawana@email.com")))
+        );
+
+        let results = store
+            .find(StoreQuery {
+                subgraph: String::from("test_subgraph"),
+                entity: String::from("user"),
+                filter: Some(StoreFilter::Equal(
+                    String::from("name"),
+                    Value::String(String::from("Wanjon")),
+                )),
+                order_by: None,
+                order_direction: None,
+                range: None,
+                scope: QueryScope::default(),
+            })
+            .unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|entity| {
+            entity.get("email") == Some(&Value::String(String::from("wanawana@email.com")))
+        }));
+
+        // Attributes that hold interned values can't be filtered or ordered by
+        let by_email = StoreQuery {
+            subgraph: String::from("test_subgraph"),
+            entity: String::from("user"),
+            filter: Some(StoreFilter::Equal(
+                String::from("email"),
+                Value::String(String::from("wanawana@email.com")),
+            )),
+            order_by: None,
+            order_direction: None,
+            range: None,
+            scope: QueryScope::default(),
+        };
+        match store.check_query(&by_email) {
+            Err(QueryExecutionError::InternedAttributeError(entity_type, attribute)) => {
+                assert_eq!(entity_type, "user");
+                assert_eq!(attribute, "email");
+            }
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        assert!(store.find(by_email).is_err());
+
+        let ordered_by_email = StoreQuery {
+            subgraph: String::from("test_subgraph"),
+            entity: String::from("user"),
+            filter: None,
+            order_by: Some((String::from("email"), ValueType::String)),
+            order_direction: None,
+            range: None,
+            scope: QueryScope::default(),
+        };
+        assert!(store.check_query(&ordered_by_email).is_err());
+        assert!(store.find(ordered_by_email).is_err());
+    })
+}
+
+#[test]
+fn decompress_subgraph_on_access() {
+    run_test(|| {
+        use db_schema::entities::dsl::*;
+
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let mut store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
+
+        let compressed = store
+            .compress_subgraph("test_subgraph")
+            .expect("Failed to compress subgraph");
+        assert_eq!(compressed.entities, 3);
+
+        // Check that the entities were moved out of the entities table
+        let count = entities
+            .filter(subgraph.eq("test_subgraph"))
+            .count()
+            .get_result::<i64>(&store.conn)
+            .unwrap();
+        assert_eq!(count, 0);
+        assert_eq!(store.compressed_subgraphs().unwrap(), vec![compressed]);
+
+        // Check that accessing an entity decompresses the subgraph
+        let key = StoreKey {
+            subgraph: String::from("test_subgraph"),
+            entity: String::from("user"),
+            id: String::from("3"),
+        };
+        let result = store.get(key).unwrap();
+        assert_eq!(
+            result.get("email"),
+            Some(&Value::String(String::from("teeko@email.com")))
+        );
+        assert!(store.compressed_subgraphs().unwrap().is_empty());
+    })
+}
+
+#[test]
+fn delete_compressed_subgraph() {
+    run_test(|| {
+        use db_schema::entities::dsl::*;
+
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let mut store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
+
+        store
+            .compress_subgraph("test_subgraph")
+            .expect("Failed to compress subgraph");
+        assert_eq!(store.delete_subgraph("test_subgraph").unwrap(), 3);
+        assert!(store.compressed_subgraphs().unwrap().is_empty());
+
+        // Check that accessing the subgraph finds nothing to decompress
+        let count = entities
+            .filter(subgraph.eq("test_subgraph"))
+            .count()
+            .get_result::<i64>(&store.conn)
+            .unwrap();
+        assert_eq!(count, 0);
+        let key = StoreKey {
+            subgraph: String::from("test_subgraph"),
+            entity: String::from("user"),
+            id: String::from("1"),
+        };
+        assert!(store.get(key).is_err());
+    })
+}
+
+#[test]
+fn track_deployment_usage() {
+    run_test(|| {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let mut store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
+
+        store.track_deployment("test_subgraph").unwrap();
+        let usage = store.deployment_usage().unwrap();
+        assert_eq!(usage.len(), 1);
+        assert_eq!(usage[0].queries, 0);
+        assert_eq!(usage[0].last_queried_at, None);
+
+        // Tracking again does not reset the usage
+        store.record_deployment_query("test_subgraph").unwrap();
+        store.record_deployment_query("test_subgraph").unwrap();
+        store.track_deployment("test_subgraph").unwrap();
+        let usage = store.deployment_usage().unwrap();
+        assert_eq!(usage[0].queries, 2);
+        assert!(usage[0].last_queried_at.unwrap() >= usage[0].tracked_since);
+
+        // Deleting the subgraph stops tracking it
+        store.delete_subgraph("test_subgraph").unwrap();
+        assert!(store.deployment_usage().unwrap().is_empty());
+    })
+}
+
+#[test]
+fn record_indexing_throughput() {
+    run_test(|| {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let mut store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
+
+        let sample = |subgraph_id: &str, started_at: u64, minutes: u64| ThroughputSample {
+            subgraph_id: subgraph_id.to_owned(),
+            started_at,
+            seconds: 60 * minutes,
+            blocks: 10 * minutes,
+            triggers: 20 * minutes,
+            entities: 30 * minutes,
+        };
+        store
+            .record_throughput(&[sample("test_subgraph", 7200, 1), sample("other", 7200, 1)])
+            .unwrap();
+        store
+            .record_throughput(&[sample("test_subgraph", 3600, 1)])
+            .unwrap();
+        store
+            .record_throughput(&[sample("test_subgraph", 3660, 1)])
+            .unwrap();
+
+        // The samples are rolled up per hour
+        assert_eq!(
+            store.throughput_history("test_subgraph", 0).unwrap(),
+            vec![
+                sample("test_subgraph", 3600, 2),
+                sample("test_subgraph", 7200, 1),
+            ]
+        );
+        assert_eq!(
+            store.throughput_history("test_subgraph", 3601).unwrap(),
+            vec![sample("test_subgraph", 7200, 1)]
+        );
+
+        // Deleting the subgraph deletes its history
+        store.delete_subgraph("test_subgraph").unwrap();
+        assert!(store.throughput_history("test_subgraph", 0).unwrap().is_empty());
+        assert_eq!(store.throughput_history("other", 0).unwrap().len(), 1);
+    })
+}
+
+#[test]
+fn pin_deployment_mappings() {
+    run_test(|| {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let mut store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
+
+        let pin = |data_source: &str, module_hash: &str| MappingPin {
+            data_source: data_source.to_owned(),
+            api_version: String::from("0.1.0"),
+            module_hash: module_hash.to_owned(),
+        };
+
+        assert!(store.mapping_pins("test_subgraph").unwrap().is_empty());
+        store
+            .pin_mappings("test_subgraph", vec![pin("Token", "0x1"), pin("Market", "0x2")])
+            .unwrap();
+        assert_eq!(
+            store.mapping_pins("test_subgraph").unwrap(),
+            vec![pin("Market", "0x2"), pin("Token", "0x1")]
+        );
+
+        // Pinning again replaces all pins of the deployment
+        store.pin_mappings("test_subgraph", vec![pin("Token", "0x3")]).unwrap();
+        assert_eq!(store.mapping_pins("test_subgraph").unwrap(), vec![pin("Token", "0x3")]);
+
+        // Deleting the subgraph drops its pins
+        store.delete_subgraph("test_subgraph").unwrap();
+        assert!(store.mapping_pins("test_subgraph").unwrap().is_empty());
+    })
+}
+
+#[test]
+fn prune_entities_after_their_retention_period() {
+    run_test(|| {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let mut store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
+
+        let events = store
+            .event_stream()
+            .expect("Failed to stream store events");
+
+        // The test entities were just written, so none of them are pruned yet
+        let written_at = current_timestamp();
+        assert_eq!(
+            store
+                .prune_entities("test_subgraph", "user", written_at - 60)
+                .unwrap(),
+            0
+        );
+
+        assert!(
+            store
+                .prune_entities("test_subgraph", "user", written_at + 60)
+                .unwrap() > 0
+        );
+        let key = StoreKey {
+            subgraph: String::from("test_subgraph"),
+            entity: String::from("user"),
+            id: String::from("1"),
+        };
+        assert!(store.get(key).is_err());
+
+        // Cached query results are dropped since they may hold the entities
+        match events.into_future().wait() {
+            Ok((Some(StoreEvent::SubgraphChanged(subgraph_id, _)), _)) => {
+                assert_eq!(subgraph_id, "test_subgraph")
+            }
+            _ => panic!("Pruning entities sent no store event"),
+        }
+    })
+}
+
+#[test]
+fn record_store_latencies() {
+    run_test(|| {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
+
+        let key = StoreKey {
+            subgraph: String::from("test_subgraph"),
+            entity: String::from("user"),
+            id: String::from("1"),
+        };
+        store.get(key.clone()).unwrap();
+        store.get(key).unwrap();
+        store
+            .find(StoreQuery {
+                subgraph: String::from("test_subgraph"),
+                entity: String::from("user"),
+                filter: None,
+                order_by: None,
+                order_direction: None,
+                range: None,
+                scope: QueryScope::default(),
+            })
+            .unwrap();
+
+        // Each operation is measured separately per deployment
+        let latencies = store.store_latencies();
+        assert_eq!(latencies.len(), 2);
+        assert_eq!(latencies[0].subgraph_id, "test_subgraph");
+        assert_eq!(latencies[0].operation, StoreOperation::Get);
+        assert_eq!(latencies[0].histogram.count, 2);
+        assert_eq!(latencies[1].operation, StoreOperation::Find);
+        assert_eq!(latencies[1].histogram.count, 1);
+    })
+}
+
+#[test]
+fn apply_postgres_settings() {
+    run_test(|| {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![(String::from("statement_timeout"), String::from("10s"))],
+                indexing_settings: vec![(String::from("work_mem"), String::from("64MB"))],
+            },
+            &logger,
+        );
+
+        let key = StoreKey {
+            subgraph: String::from("test_subgraph"),
+            entity: String::from("user"),
+            id: String::from("1"),
+        };
+        assert!(store.get(key).is_ok());
+
+        assert!(
+            store
+                .find(StoreQuery {
+                    subgraph: String::from("test_subgraph"),
+                    entity: String::from("user"),
+                    filter: None,
+                    order_by: None,
+                    order_direction: None,
+                    range: None,
+                    scope: QueryScope::default(),
+                })
+                .is_ok()
+        );
+
+        // The settings only last for the transaction they were applied in
+        let timeout = diesel::select(diesel::dsl::sql::<diesel::sql_types::Text>(
+            "current_setting('statement_timeout')",
+        )).get_result::<String>(&store.conn)
+            .unwrap();
+        assert_eq!(timeout, "0");
+    })
+}
+
+#[test]
+fn only_one_store_writes_to_a_deployment() {
+    run_test(|| {
+        let logger = Logger::root(slog::Discard, o!());
+        let new_store = || {
+            DieselStore::new(
+                StoreConfig {
+                    url: postgres_test_url(),
+                    intern_threshold: None,
+                    query_settings: vec![],
+                    indexing_settings: vec![],
+                },
+                &logger,
+            )
+        };
+        let mut store = new_store();
+        let mut duplicate_store = new_store();
+
+        let (key, entity, event_source) = create_test_entity(
+            String::from("7"),
+            String::from("user"),
+            String::from("Wanjon"),
+            String::from("wanawana@email.com"),
+            76 as i32,
+            111.7 as f32,
+            true,
+            String::from("MSjZmOE7UqBOzzYibsw9"),
+        );
+        store
+            .set(key.clone(), entity.clone(), event_source.clone())
+            .expect("Failed to set entity in the store");
+
+        // The duplicate store doesn't write to the same deployment, and says why
+        assert_eq!(
+            duplicate_store.set(key.clone(), entity.clone(), event_source.clone()),
+            Err(StoreWriteError::NotWriter(String::from("test_subgraph")))
+        );
+
+        // Once the first store is gone, the deployment can be written to again
+        drop(store);
+        assert!(duplicate_store.set(key, entity, event_source).is_ok());
+    })
+}
+
+#[test]
+fn insert_entity() {
+    run_test(|| {
+        use db_schema::entities::dsl::*;
+
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let mut store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
+
+        let test_entity_1 = create_test_entity(
+            String::from("7"),
+            String::from("user"),
+            String::from("Wanjon"),
+            String::from("wanawana@email.com"),
+            76 as i32,
+            111.7 as f32,
+            true,
+            String::from("MSjZmOE7UqBOzzYibsw9"),
+        );
+        store
+            .set(test_entity_1.0, test_entity_1.1, test_entity_1.2)
+            .expect("Failed to set entity in the store");
+
+        // Check that new record is in the store
+        let all_ids = entities.select(id).load::<String>(&store.conn).unwrap();
+        assert!(all_ids.iter().any(|x| x == &String::from("7")));
+    })
+}
+
+#[test]
+fn update_existing() {
+    run_test(|| {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let mut store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
+
+        let entity_key = StoreKey {
+            subgraph: String::from("test_subgraph"),
+            entity: String::from("user"),
+            id: String::from("1"),
+        };
+
+        let test_entity_1 = create_test_entity(
+            String::from("1"),
+            String::from("user"),
+            String::from("Wanjon"),
+            String::from("wanawana@email.com"),
+            76 as i32,
+            111.7 as f32,
+            true,
+            String::from("6SFIlpqNoDy6FfJQryNM"),
+        );
+
+        // Verify that the entity before updating is different from what we expect afterwards
+        assert_ne!(store.get(entity_key.clone()).unwrap(), test_entity_1.1);
+
+        // Set test entity; as the entity already exists an update should be performed
+        store
+            .set(test_entity_1.0, test_entity_1.1.clone(), test_entity_1.2)
+            .expect("Failed to update entity that already exists");
+
+        // Verify that the entity in the store has changed to what we have set
+        assert_eq!(store.get(entity_key.clone()).unwrap(), test_entity_1.1);
+
+        // Entities found by queries carry their type, which is not written
+        // back with them
+        let found = store
+            .find(StoreQuery {
+                subgraph: String::from("test_subgraph"),
+                entity: String::from("user"),
+                filter: Some(StoreFilter::Equal(String::from("id"), Value::from("1"))),
+                order_by: None,
+                order_direction: None,
+                range: None,
+                scope: QueryScope::default(),
+            })
+            .unwrap()
+            .remove(0);
+        assert_eq!(found.get("__typename"), Some(&Value::from("user")));
+        store
+            .set(
+                entity_key.clone(),
+                found,
+                EventSource::EthereumBlock(H256::from(9), 9),
+            )
+            .expect("Failed to write found entity");
+        assert_eq!(store.get(entity_key).unwrap(), test_entity_1.1);
+    })
+}
+
+#[test]
+fn reject_writes_of_entities_modified_in_the_meantime() {
+    run_test(|| {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let mut store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
+
+        let (key, entity, event_source) = create_test_entity(
+            String::from("1"),
+            String::from("user"),
+            String::from("Wanjon"),
+            String::from("wanawana@email.com"),
+            76 as i32,
+            111.7 as f32,
+            true,
+            String::from("6SFIlpqNoDy6FfJQryNM"),
+        );
+
+        // Every write bumps the version of the entity
+        let version = store.entity_version(key.clone()).unwrap().unwrap();
+        store.set(key.clone(), entity.clone(), event_source.clone()).unwrap();
+        assert_eq!(store.entity_version(key.clone()).unwrap(), Some(version + 1));
+
+        // Writes that expect an outdated version are not applied
+        let mut stale_entity = entity.clone();
+        stale_entity.insert(String::from("name"), Value::from("Stale"));
+        assert_eq!(
+            store
+                .set_if_version(key.clone(), stale_entity, Some(version), event_source.clone())
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            store.get(key.clone()).unwrap().get("name"),
+            Some(&Value::from("Wanjon"))
+        );
+
+        assert_eq!(
+            store
+                .set_if_version(key.clone(), entity, Some(version + 1), event_source.clone())
+                .unwrap(),
+            Some(version + 2)
+        );
+
+        // Entities that don't exist have no version
+        let new_key = StoreKey {
+            id: String::from("new"),
+            ..key
+        };
+        assert_eq!(store.entity_version(new_key.clone()).unwrap(), None);
+        assert_eq!(
+            store.set_if_version(new_key, Entity::new(), None, event_source).unwrap(),
+            Some(1)
+        );
+    })
+}
+
+#[test]
+fn merge_offchain_entities_on_read() {
+    run_test(|| {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let mut store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
+
+        let key = StoreKey {
+            subgraph: String::from("test_subgraph"),
+            entity: String::from("user"),
+            id: String::from("1"),
+        };
+        let block = EventSource::EthereumBlock(H256::from(1), 1);
+        let mut update = Entity::new();
+        update.insert(String::from("id"), Value::from("1"));
+        store.set(key.clone(), update, block.clone()).unwrap();
+
+        let mut offchain_entity = Entity::new();
+        offchain_entity.insert(String::from("name"), Value::from("Offchain"));
+        offchain_entity.insert(String::from("avatar"), Value::from("ipfs://avatar"));
+        store
+            .set_offchain(key.clone(), offchain_entity, String::from("ipfs://profile"))
+            .unwrap();
+
+        // Offchain attributes are added, but onchain ones take precedence
+        let entity = store.get(key.clone()).unwrap();
+        assert_eq!(entity.get("name"), Some(&Value::from("Johnton")));
+        assert_eq!(entity.get("avatar"), Some(&Value::from("ipfs://avatar")));
+        let found = store
+            .find(StoreQuery {
+                subgraph: String::from("test_subgraph"),
+                entity: String::from("user"),
+                filter: Some(StoreFilter::Equal(String::from("name"), Value::from("Johnton"))),
+                order_by: None,
+                order_direction: None,
+                range: None,
+                scope: QueryScope::default(),
+            })
+            .unwrap();
+        assert_eq!(found[0].get("avatar"), Some(&Value::from("ipfs://avatar")));
+
+        // Onchain writes don't pick up offchain attributes
+        let mut update = Entity::new();
+        update.insert(String::from("age"), Value::Int(68));
+        store.set(key.clone(), update, block).unwrap();
+        assert_eq!(store.drop_offchain("test_subgraph", None).unwrap(), 1);
+        let entity = store.get(key.clone()).unwrap();
+        assert_eq!(entity.get("age"), Some(&Value::Int(68)));
+        assert_eq!(entity.get("avatar"), None);
+
+        // Offchain entities without an onchain entity are not returned
+        let new_key = StoreKey {
+            id: String::from("new"),
+            ..key
+        };
+        store
+            .set_offchain(new_key.clone(), Entity::new(), String::from("ipfs://profile"))
+            .unwrap();
+        assert!(store.get(new_key).is_err());
+    })
+}
+
+#[test]
+fn find_entities_at_older_blocks() {
+    run_test(|| {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let mut store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
+
+        let user_key = |user_id: &str| StoreKey {
+            subgraph: String::from("test_subgraph"),
+            entity: String::from("user"),
+            id: String::from(user_id),
+        };
+        let named = |name: &str| {
+            let mut entity = Entity::new();
+            entity.insert(String::from("name"), Value::from(name));
+            entity
+        };
+
+        // The test data is written for block 0
+        store
+            .set(
+                user_key("1"),
+                named("Jonathan"),
+                EventSource::EthereumBlock(H256::from(5), 5),
+            )
+            .unwrap();
+        store
+            .delete(user_key("2"), EventSource::EthereumBlock(H256::from(6), 6))
+            .unwrap();
+        store
+            .set(
+                user_key("4"),
+                named("Tobias"),
+                EventSource::EthereumBlock(H256::from(6), 6),
+            )
+            .unwrap();
+
+        let at_block = |block| QueryScope {
+            block,
+            timeout: None,
+        };
+        let names = |store: &DieselStore, block| -> Vec<Value> {
+            store
+                .find(StoreQuery {
+                    subgraph: String::from("test_subgraph"),
+                    entity: String::from("user"),
+                    filter: None,
+                    order_by: Some((String::from("name"), ValueType::String)),
+                    order_direction: Some(StoreOrder::Ascending),
+                    range: None,
+                    scope: at_block(block),
+                })
+                .unwrap()
+                .into_iter()
+                .map(|user| user.get("name").unwrap().clone())
+                .collect()
+        };
+        let user_name = |store: &DieselStore, id: &str, block| -> Option<Value> {
+            let mut ids_for_type = BTreeMap::new();
+            ids_for_type.insert(String::from("user"), vec![String::from(id)]);
+            store
+                .get_many("test_subgraph", ids_for_type, at_block(block))
+                .unwrap()
+                .remove("user")
+                .and_then(|mut users| users.pop())
+                .and_then(|user| user.get("name").cloned())
+        };
 
-        //Get all ids in table
-        let all_ids = entities.select(id).load::<String>(&store.conn).unwrap();
+        assert_eq!(store.earliest_history_block("test_subgraph"), Some(0));
 
-        // Check that that the deleted entity id is not present
-        assert!(!all_ids.contains(&String::from("3")));
+        assert_eq!(user_name(&store, "1", Some(0)), Some(Value::from("Johnton")));
+        assert_eq!(user_name(&store, "4", Some(0)), None);
+
+        assert_eq!(
+            names(&store, Some(5)),
+            vec![
+                Value::from("Cindini"),
+                Value::from("Jonathan"),
+                Value::from("Shaqueeena"),
+            ]
+        );
+
+        // Lookups at older blocks don't change what other lookups see
+        assert_eq!(
+            store.get(user_key("4")).unwrap().get("name"),
+            Some(&Value::from("Tobias"))
+        );
+        assert_eq!(
+            names(&store, None),
+            vec![
+                Value::from("Jonathan"),
+                Value::from("Shaqueeena"),
+                Value::from("Tobias"),
+            ]
+        );
+
+        // After block 6 is reorged out, older blocks look as they did
+        // before it was written
+        store
+            .revert_block_operations("test_subgraph", H256::from(6))
+            .expect("Failed to revert block");
+        let before_block_6 = vec![
+            Value::from("Cindini"),
+            Value::from("Jonathan"),
+            Value::from("Shaqueeena"),
+        ];
+        assert_eq!(names(&store, Some(5)), before_block_6);
+        assert_eq!(names(&store, None), before_block_6);
+        assert_eq!(user_name(&store, "4", Some(5)), None);
+        assert_eq!(user_name(&store, "1", Some(0)), Some(Value::from("Johnton")));
     })
 }
 
 #[test]
-fn get_entity() {
+fn search_entities_by_text() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let mut store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
 
-        let key = StoreKey {
+        let search = |store: &DieselStore, text: &str| -> Vec<String> {
+            store
+                .search(
+                    StoreQuery {
+                        subgraph: String::from("test_subgraph"),
+                        entity: String::from("user"),
+                        filter: None,
+                        order_by: None,
+                        order_direction: None,
+                        range: None,
+                        scope: QueryScope::default(),
+                    },
+                    FulltextSearch {
+                        name: String::from("users"),
+                        language: String::from("simple"),
+                        text: String::from(text),
+                    },
+                )
+                .unwrap()
+                .into_iter()
+                .map(|user| match user.get("id") {
+                    Some(Value::String(id)) => id.clone(),
+                    _ => panic!("User without ID"),
+                })
+                .collect()
+        };
+
+        // The existing entities get their documents right away
+        store
+            .set_fulltext_definitions(
+                "test_subgraph",
+                vec![FulltextDefinition {
+                    name: String::from("users"),
+                    entity: String::from("user"),
+                    language: String::from("simple"),
+                    fields: vec![String::from("name"), String::from("email")],
+                }],
+            )
+            .unwrap();
+        assert_eq!(search(&store, "Shaqueeena"), vec![String::from("3")]);
+        assert_eq!(search(&store, "dinici@email.com"), vec![String::from("2")]);
+        assert!(search(&store, "Tobias").is_empty());
+
+        // Writing and deleting entities updates their documents
+        let user_key = |user_id: &str| StoreKey {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
-            id: String::from("1"),
+            id: String::from(user_id),
         };
-        let result = store.get(key).unwrap();
-
-        let mut expected_entity = Entity::new();
-        expected_entity.insert(String::from("name"), Value::String(String::from("Johnton")));
-        expected_entity.insert(
-            String::from("email"),
-            Value::String(String::from("tonofjohn@email.com")),
-        );
-        expected_entity.insert(String::from("age"), Value::Int(67 as i32));
-        expected_entity.insert(String::from("weight"), Value::Float(184.4 as f32));
-        expected_entity.insert(String::from("coffee"), Value::Bool(false));
+        let mut tobias = Entity::new();
+        tobias.insert(String::from("name"), Value::from("Tobias Shaqueeena"));
+        store
+            .set(
+                user_key("4"),
+                tobias,
+                EventSource::EthereumBlock(H256::from(5), 5),
+            )
+            .unwrap();
+        store
+            .delete(user_key("3"), EventSource::EthereumBlock(H256::from(5), 5))
+            .unwrap();
+        assert_eq!(search(&store, "shaqueeena"), vec![String::from("4")]);
+        assert_eq!(search(&store, "Tobias"), vec![String::from("4")]);
 
-        // Check that the expected entity was returned
-        assert_eq!(result, expected_entity);
+        // Reverting the block restores the documents of the entities it changed
+        store
+            .revert_block_operations("test_subgraph", H256::from(5))
+            .unwrap();
+        assert_eq!(search(&store, "shaqueeena"), vec![String::from("3")]);
+        assert!(search(&store, "Tobias").is_empty());
+        assert_eq!(search(&store, "dinici@email.com"), vec![String::from("2")]);
     })
 }
 
 #[test]
-fn insert_entity() {
+fn aggregate_entities() {
     run_test(|| {
-        use db_schema::entities::dsl::*;
-
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let mut store = DieselStore::new(StoreConfig { url }, &logger);
-
-        let test_entity_1 = create_test_entity(
-            String::from("7"),
-            String::from("user"),
-            String::from("Wanjon"),
-            String::from("wanawana@email.com"),
-            76 as i32,
-            111.7 as f32,
-            true,
-            String::from("MSjZmOE7UqBOzzYibsw9"),
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
         );
-        store
-            .set(test_entity_1.0, test_entity_1.1, test_entity_1.2)
-            .expect("Failed to set entity in the store");
 
-        // Check that new record is in the store
-        let all_ids = entities.select(id).load::<String>(&store.conn).unwrap();
-        assert!(all_ids.iter().any(|x| x == &String::from("7")));
+        let aggregate = |filter: Option<StoreFilter>| {
+            store
+                .aggregate(
+                    StoreQuery {
+                        subgraph: String::from("test_subgraph"),
+                        entity: String::from("user"),
+                        filter,
+                        order_by: Some((String::from("name"), ValueType::String)),
+                        order_direction: None,
+                        range: Some(StoreRange { first: 1, skip: 0 }),
+                        scope: QueryScope::default(),
+                    },
+                    vec![
+                        StoreAggregate::Count,
+                        StoreAggregate::Sum(String::from("age")),
+                        StoreAggregate::Min(String::from("age")),
+                        StoreAggregate::Max(String::from("age")),
+                        StoreAggregate::Sum(String::from("height")),
+                    ],
+                )
+                .expect("Failed to aggregate entities")
+        };
+        let decimal = |number: &str| Value::BigDecimal(number.parse().unwrap());
+
+        // The range of the query does not apply to aggregates
+        assert_eq!(
+            aggregate(None),
+            vec![
+                Value::Int(3),
+                decimal("138"),
+                decimal("28"),
+                decimal("67"),
+                Value::Null,
+            ]
+        );
+        assert_eq!(
+            aggregate(Some(StoreFilter::And(vec![StoreFilter::Equal(
+                String::from("coffee"),
+                Value::Bool(false),
+            )]))),
+            vec![
+                Value::Int(2),
+                decimal("95"),
+                decimal("28"),
+                decimal("67"),
+                Value::Null,
+            ]
+        );
     })
 }
 
 #[test]
-fn update_existing() {
+fn histogram_of_entities() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let mut store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
 
-        let entity_key = StoreKey {
-            subgraph: String::from("test_subgraph"),
-            entity: String::from("user"),
-            id: String::from("1"),
+        let histogram = |attribute: &str, size: &str| {
+            store
+                .histogram(
+                    StoreQuery {
+                        subgraph: String::from("test_subgraph"),
+                        entity: String::from("user"),
+                        filter: None,
+                        order_by: Some((String::from("name"), ValueType::String)),
+                        order_direction: None,
+                        range: Some(StoreRange { first: 1, skip: 0 }),
+                        scope: QueryScope::default(),
+                    },
+                    String::from(attribute),
+                    size.parse().unwrap(),
+                )
+                .expect("Failed to compute histogram")
+        };
+        let bucket = |start: &str, count: i32| -> (BigDecimal, i32) {
+            (start.parse().unwrap(), count)
         };
 
-        let test_entity_1 = create_test_entity(
-            String::from("1"),
-            String::from("user"),
-            String::from("Wanjon"),
-            String::from("wanawana@email.com"),
-            76 as i32,
-            111.7 as f32,
-            true,
-            String::from("6SFIlpqNoDy6FfJQryNM"),
+        // The ages are 28, 43 and 67; empty buckets are left out
+        assert_eq!(histogram("age", "25"), vec![bucket("25", 2), bucket("50", 1)]);
+        assert_eq!(
+            histogram("age", "10"),
+            vec![bucket("20", 1), bucket("40", 1), bucket("60", 1)]
         );
 
-        // Verify that the entity before updating is different from what we expect afterwards
-        assert_ne!(store.get(entity_key.clone()).unwrap(), test_entity_1.1);
-
-        // Set test entity; as the entity already exists an update should be performed
-        store
-            .set(test_entity_1.0, test_entity_1.1.clone(), test_entity_1.2)
-            .expect("Failed to update entity that already exists");
-
-        // Verify that the entity in the store has changed to what we have set
-        assert_eq!(store.get(entity_key).unwrap(), test_entity_1.1);
+        // Entities without a value are not counted
+        assert_eq!(histogram("height", "10"), vec![]);
     })
 }
 
@@ -281,7 +1434,15 @@ fn partially_update_existing() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let mut store = DieselStore::new(StoreConfig { url }, &logger);
+        let mut store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
 
         let entity_key = StoreKey {
             subgraph: String::from("test_subgraph"),
@@ -296,7 +1457,7 @@ fn partially_update_existing() {
         ]);
 
         let original_entity = store.get(entity_key.clone()).unwrap();
-        let event_source = EventSource::EthereumBlock(H256::random());
+        let event_source = EventSource::EthereumBlock(H256::random(), 1);
         // Verify that the entity before updating is different from what we expect afterwards
         assert_ne!(original_entity, partial_entity);
 
@@ -326,7 +1487,15 @@ fn find_string_contains() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -337,6 +1506,7 @@ fn find_string_contains() {
             order_by: None,
             order_direction: None,
             range: None,
+            scope: QueryScope::default(),
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -352,7 +1522,15 @@ fn find_string_equal() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -363,6 +1541,7 @@ fn find_string_equal() {
             order_by: None,
             order_direction: None,
             range: None,
+            scope: QueryScope::default(),
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -378,7 +1557,15 @@ fn find_string_not_equal() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -389,6 +1576,7 @@ fn find_string_not_equal() {
             order_by: None,
             order_direction: None,
             range: None,
+            scope: QueryScope::default(),
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -408,7 +1596,15 @@ fn find_string_greater_than() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -419,6 +1615,7 @@ fn find_string_greater_than() {
             order_by: None,
             order_direction: None,
             range: None,
+            scope: QueryScope::default(),
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -438,7 +1635,15 @@ fn find_string_less_than() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -449,6 +1654,7 @@ fn find_string_less_than() {
             order_by: None,
             order_direction: None,
             range: None,
+            scope: QueryScope::default(),
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -468,7 +1674,15 @@ fn find_string_less_than_order_by_asc() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -476,9 +1690,10 @@ fn find_string_less_than_order_by_asc() {
                 String::from("name"),
                 Value::String(String::from("Kundi")),
             )])),
-            order_by: Some(String::from("name")),
+            order_by: Some((String::from("name"), ValueType::String)),
             order_direction: Some(StoreOrder::Ascending),
             range: None,
+            scope: QueryScope::default(),
         };
         let result = store
             .find(this_query)
@@ -509,7 +1724,15 @@ fn find_string_less_than_order_by_desc() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -517,9 +1740,10 @@ fn find_string_less_than_order_by_desc() {
                 String::from("name"),
                 Value::String(String::from("Kundi")),
             )])),
-            order_by: Some(String::from("name")),
+            order_by: Some((String::from("name"), ValueType::String)),
             order_direction: Some(StoreOrder::Descending),
             range: None,
+            scope: QueryScope::default(),
         };
         let result = store
             .find(this_query)
@@ -550,7 +1774,15 @@ fn find_string_less_than_range() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -558,9 +1790,10 @@ fn find_string_less_than_range() {
                 String::from("name"),
                 Value::String(String::from("ZZZ")),
             )])),
-            order_by: Some(String::from("name")),
+            order_by: Some((String::from("name"), ValueType::String)),
             order_direction: Some(StoreOrder::Descending),
             range: Some(StoreRange { first: 1, skip: 1 }),
+            scope: QueryScope::default(),
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -575,12 +1808,138 @@ fn find_string_less_than_range() {
     })
 }
 
+#[test]
+fn find_child_filter() {
+    run_test(|| {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let mut store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
+
+        let mut band = Entity::new();
+        band.insert(String::from("founder"), Value::from("1"));
+        band.insert(
+            String::from("members"),
+            Value::List(vec![Value::from("1"), Value::from("3")]),
+        );
+        store
+            .set(
+                StoreKey {
+                    subgraph: String::from("test_subgraph"),
+                    entity: String::from("band"),
+                    id: String::from("1"),
+                },
+                band,
+                EventSource::EthereumBlock(H256::zero(), 0),
+            )
+            .expect("Failed to insert band into the store");
+
+        let bands_with_user = |attribute: &str, name: &str| {
+            let this_query = StoreQuery {
+                subgraph: String::from("test_subgraph"),
+                entity: String::from("band"),
+                filter: Some(StoreFilter::And(vec![StoreFilter::Child(
+                    String::from(attribute),
+                    String::from("user"),
+                    Box::new(StoreFilter::And(vec![StoreFilter::Equal(
+                        String::from("name"),
+                        Value::from(name),
+                    )])),
+                )])),
+                order_by: None,
+                order_direction: None,
+                range: None,
+                scope: QueryScope::default(),
+            };
+            store.find(this_query).expect("store.find operation failed").len()
+        };
+
+        // Single references and lists of references are both matched
+        assert_eq!(bands_with_user("founder", "Johnton"), 1);
+        assert_eq!(bands_with_user("founder", "Shaqueeena"), 0);
+        assert_eq!(bands_with_user("members", "Shaqueeena"), 1);
+        assert_eq!(bands_with_user("members", "Cindini"), 0);
+    })
+}
+
+#[test]
+fn find_any_pages_entities_of_all_types() {
+    run_test(|| {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let mut store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
+
+        let mut band = Entity::new();
+        band.insert(String::from("name"), Value::from("Dolphins"));
+        store
+            .set(
+                StoreKey {
+                    subgraph: String::from("test_subgraph"),
+                    entity: String::from("band"),
+                    id: String::from("1"),
+                },
+                band,
+                EventSource::EthereumBlock(H256::zero(), 0),
+            )
+            .expect("Failed to insert band into the store");
+
+        let this_query = StoreQuery {
+            subgraph: String::from("test_subgraph"),
+            entity: String::from("user"),
+            filter: None,
+            order_by: Some((String::from("name"), ValueType::String)),
+            order_direction: Some(StoreOrder::Ascending),
+            range: Some(StoreRange { first: 2, skip: 1 }),
+            scope: QueryScope::default(),
+        };
+        let entities = store
+            .find_any(this_query, vec![String::from("user"), String::from("band")])
+            .expect("store.find_any operation failed");
+
+        // Entities of both types are ordered and paged together
+        let names_and_types = entities
+            .iter()
+            .map(|entity| (entity.get("name").cloned(), entity.get("__typename").cloned()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names_and_types,
+            vec![
+                (Some(Value::from("Dolphins")), Some(Value::from("band"))),
+                (Some(Value::from("Johnton")), Some(Value::from("user"))),
+            ]
+        );
+    })
+}
+
 #[test]
 fn find_string_multiple_and() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -588,15 +1947,172 @@ fn find_string_multiple_and() {
                 StoreFilter::LessThan(String::from("name"), Value::String(String::from("Cz"))),
                 StoreFilter::Equal(String::from("name"), Value::String(String::from("Cindini"))),
             ])),
-            order_by: Some(String::from("name")),
+            order_by: Some((String::from("name"), ValueType::String)),
+            order_direction: Some(StoreOrder::Descending),
+            range: None,
+            scope: QueryScope::default(),
+        };
+        let returned_entities = store.find(this_query).expect("store.find operation failed");
+
+        // Check if the first user in the result vector is "Cindini"
+        let returned_name = returned_entities[0].get(&String::from("name"));
+        let test_value = Value::String(String::from("Cindini"));
+        assert!(returned_name.is_some());
+        assert_eq!(&test_value, returned_name.unwrap());
+
+        // There should be 1 user returned in results
+        assert_eq!(1, returned_entities.len());
+    })
+}
+
+#[test]
+fn find_string_ends_with() {
+    run_test(|| {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
+        let this_query = StoreQuery {
+            subgraph: String::from("test_subgraph"),
+            entity: String::from("user"),
+            filter: Some(StoreFilter::And(vec![StoreFilter::EndsWith(
+                String::from("name"),
+                Value::String(String::from("ini")),
+            )])),
+            order_by: Some((String::from("name"), ValueType::String)),
+            order_direction: Some(StoreOrder::Descending),
+            range: None,
+            scope: QueryScope::default(),
+        };
+        let returned_entities = store.find(this_query).expect("store.find operation failed");
+
+        // Check if the first user in the result vector is "Cindini"
+        let returned_name = returned_entities[0].get(&String::from("name"));
+        let test_value = Value::String(String::from("Cindini"));
+        assert!(returned_name.is_some());
+        assert_eq!(&test_value, returned_name.unwrap());
+
+        // There should be 1 user returned in results
+        assert_eq!(1, returned_entities.len());
+    })
+}
+
+#[test]
+fn find_string_not_ends_with() {
+    run_test(|| {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
+        let this_query = StoreQuery {
+            subgraph: String::from("test_subgraph"),
+            entity: String::from("user"),
+            filter: Some(StoreFilter::And(vec![StoreFilter::NotEndsWith(
+                String::from("name"),
+                Value::String(String::from("ini")),
+            )])),
+            order_by: Some((String::from("name"), ValueType::String)),
             order_direction: Some(StoreOrder::Descending),
             range: None,
+            scope: QueryScope::default(),
+        };
+        let returned_entities = store.find(this_query).expect("store.find operation failed");
+
+        // Check if the first user in the result vector is "Shaqueeena"
+        let returned_name = returned_entities[0].get(&String::from("name"));
+        let test_value = Value::String(String::from("Shaqueeena"));
+        assert!(returned_name.is_some());
+        assert_eq!(&test_value, returned_name.unwrap());
+
+        // There should be 2 users returned in results
+        assert_eq!(2, returned_entities.len());
+    })
+}
+
+#[test]
+fn find_string_contains_nocase() {
+    run_test(|| {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
+        let this_query = StoreQuery {
+            subgraph: String::from("test_subgraph"),
+            entity: String::from("user"),
+            filter: Some(StoreFilter::And(vec![StoreFilter::ContainsNoCase(
+                String::from("name"),
+                Value::String(String::from("IND")),
+            )])),
+            order_by: Some((String::from("name"), ValueType::String)),
+            order_direction: Some(StoreOrder::Ascending),
+            range: None,
+            scope: QueryScope::default(),
+        };
+        let returned_entities = store.find(this_query).expect("store.find operation failed");
+
+        // Check if the first user in the result vector is "Cindini"
+        let returned_name = returned_entities[0].get(&String::from("name"));
+        let test_value = Value::String(String::from("Cindini"));
+        assert!(returned_name.is_some());
+        assert_eq!(&test_value, returned_name.unwrap());
+
+        // There should be 1 user returned in results
+        assert_eq!(1, returned_entities.len());
+    })
+}
+
+#[test]
+fn find_string_starts_with_nocase() {
+    run_test(|| {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
+        let this_query = StoreQuery {
+            subgraph: String::from("test_subgraph"),
+            entity: String::from("user"),
+            filter: Some(StoreFilter::And(vec![StoreFilter::StartsWithNoCase(
+                String::from("name"),
+                Value::String(String::from("shaq")),
+            )])),
+            order_by: Some((String::from("name"), ValueType::String)),
+            order_direction: Some(StoreOrder::Ascending),
+            range: None,
+            scope: QueryScope::default(),
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
-        // Check if the first user in the result vector is "Cindini"
+        // Check if the first user in the result vector is "Shaqueeena"
         let returned_name = returned_entities[0].get(&String::from("name"));
-        let test_value = Value::String(String::from("Cindini"));
+        let test_value = Value::String(String::from("Shaqueeena"));
         assert!(returned_name.is_some());
         assert_eq!(&test_value, returned_name.unwrap());
 
@@ -606,62 +2122,97 @@ fn find_string_multiple_and() {
 }
 
 #[test]
-fn find_string_ends_with() {
+fn find_string_not_ends_with_nocase() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
-            filter: Some(StoreFilter::And(vec![StoreFilter::EndsWith(
+            filter: Some(StoreFilter::And(vec![StoreFilter::NotEndsWithNoCase(
                 String::from("name"),
-                Value::String(String::from("ini")),
+                Value::String(String::from("INI")),
             )])),
-            order_by: Some(String::from("name")),
+            order_by: Some((String::from("name"), ValueType::String)),
             order_direction: Some(StoreOrder::Descending),
             range: None,
+            scope: QueryScope::default(),
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
-        // Check if the first user in the result vector is "Cindini"
+        // Check if the first user in the result vector is "Shaqueeena"
         let returned_name = returned_entities[0].get(&String::from("name"));
-        let test_value = Value::String(String::from("Cindini"));
+        let test_value = Value::String(String::from("Shaqueeena"));
         assert!(returned_name.is_some());
         assert_eq!(&test_value, returned_name.unwrap());
 
-        // There should be 1 user returned in results
-        assert_eq!(1, returned_entities.len());
+        // There should be 2 users returned in results
+        assert_eq!(2, returned_entities.len());
     })
 }
 
 #[test]
-fn find_string_not_ends_with() {
+fn find_string_matches_regex() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let mut store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
-            filter: Some(StoreFilter::And(vec![StoreFilter::NotEndsWith(
+            filter: Some(StoreFilter::And(vec![StoreFilter::MatchesRegex(
                 String::from("name"),
-                Value::String(String::from("ini")),
+                Value::String(String::from("^shaq.*a$")),
             )])),
-            order_by: Some(String::from("name")),
-            order_direction: Some(StoreOrder::Descending),
+            order_by: Some((String::from("name"), ValueType::String)),
+            order_direction: Some(StoreOrder::Ascending),
             range: None,
+            scope: QueryScope::default(),
         };
+
+        // Regular expression filters are disabled by default
+        assert!(store.find(this_query.clone()).is_err());
+
+        store.enable_regex_filters(Duration::from_secs(5));
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
-        // Check if the first user in the result vector is "Shaqueeena"
+        // There should be 1 user returned in results, "Shaqueeena"
+        assert_eq!(1, returned_entities.len());
         let returned_name = returned_entities[0].get(&String::from("name"));
         let test_value = Value::String(String::from("Shaqueeena"));
-        assert!(returned_name.is_some());
-        assert_eq!(&test_value, returned_name.unwrap());
+        assert_eq!(Some(&test_value), returned_name);
 
-        // There should be 2 users returned in results
-        assert_eq!(2, returned_entities.len());
+        // Overly long patterns are rejected
+        let this_query = StoreQuery {
+            subgraph: String::from("test_subgraph"),
+            entity: String::from("user"),
+            filter: Some(StoreFilter::And(vec![StoreFilter::NotMatchesRegex(
+                String::from("name"),
+                Value::String("a".repeat(101)),
+            )])),
+            order_by: None,
+            order_direction: None,
+            range: None,
+            scope: QueryScope::default(),
+        };
+        assert!(store.find(this_query).is_err());
     })
 }
 
@@ -670,7 +2221,15 @@ fn find_string_in() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -678,9 +2237,10 @@ fn find_string_in() {
                 String::from("name"),
                 vec![Value::String(String::from("Johnton"))],
             )])),
-            order_by: Some(String::from("name")),
+            order_by: Some((String::from("name"), ValueType::String)),
             order_direction: Some(StoreOrder::Descending),
             range: None,
+            scope: QueryScope::default(),
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -700,7 +2260,15 @@ fn find_string_not_in() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -708,9 +2276,10 @@ fn find_string_not_in() {
                 String::from("name"),
                 vec![Value::String(String::from("Shaqueeena"))],
             )])),
-            order_by: Some(String::from("name")),
+            order_by: Some((String::from("name"), ValueType::String)),
             order_direction: Some(StoreOrder::Descending),
             range: None,
+            scope: QueryScope::default(),
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -731,7 +2300,15 @@ fn find_float_equal() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -742,6 +2319,7 @@ fn find_float_equal() {
             order_by: None,
             order_direction: None,
             range: None,
+            scope: QueryScope::default(),
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -761,7 +2339,15 @@ fn find_float_not_equal() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -769,9 +2355,10 @@ fn find_float_not_equal() {
                 String::from("weight"),
                 Value::Float(184.4 as f32),
             )])),
-            order_by: Some(String::from("name")),
+            order_by: Some((String::from("name"), ValueType::String)),
             order_direction: Some(StoreOrder::Descending),
             range: None,
+            scope: QueryScope::default(),
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -791,7 +2378,15 @@ fn find_float_greater_than() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -802,6 +2397,7 @@ fn find_float_greater_than() {
             order_by: None,
             order_direction: None,
             range: None,
+            scope: QueryScope::default(),
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -821,7 +2417,15 @@ fn find_float_less_than() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -829,9 +2433,10 @@ fn find_float_less_than() {
                 String::from("weight"),
                 Value::Float(160 as f32),
             )])),
-            order_by: Some(String::from("name")),
+            order_by: Some((String::from("name"), ValueType::String)),
             order_direction: Some(StoreOrder::Ascending),
             range: None,
+            scope: QueryScope::default(),
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -851,7 +2456,15 @@ fn find_float_less_than_order_by_desc() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -859,9 +2472,10 @@ fn find_float_less_than_order_by_desc() {
                 String::from("weight"),
                 Value::Float(160 as f32),
             )])),
-            order_by: Some(String::from("name")),
+            order_by: Some((String::from("name"), ValueType::String)),
             order_direction: Some(StoreOrder::Descending),
             range: None,
+            scope: QueryScope::default(),
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -881,7 +2495,15 @@ fn find_float_less_than_range() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -889,9 +2511,10 @@ fn find_float_less_than_range() {
                 String::from("weight"),
                 Value::Float(161 as f32),
             )])),
-            order_by: Some(String::from("name")),
+            order_by: Some((String::from("name"), ValueType::String)),
             order_direction: Some(StoreOrder::Descending),
             range: Some(StoreRange { first: 1, skip: 1 }),
+            scope: QueryScope::default(),
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
         // Check if the first user in the result vector is "Cindini"
@@ -910,7 +2533,15 @@ fn find_float_in() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -918,9 +2549,10 @@ fn find_float_in() {
                 String::from("weight"),
                 vec![Value::Float(184.4 as f32), Value::Float(111.7 as f32)],
             )])),
-            order_by: Some(String::from("name")),
+            order_by: Some((String::from("name"), ValueType::String)),
             order_direction: Some(StoreOrder::Descending),
             range: Some(StoreRange { first: 5, skip: 0 }),
+            scope: QueryScope::default(),
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -940,7 +2572,15 @@ fn find_float_not_in() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -948,9 +2588,10 @@ fn find_float_not_in() {
                 String::from("weight"),
                 vec![Value::Float(184.4 as f32), Value::Float(111.7 as f32)],
             )])),
-            order_by: Some(String::from("name")),
+            order_by: Some((String::from("name"), ValueType::String)),
             order_direction: Some(StoreOrder::Descending),
             range: Some(StoreRange { first: 5, skip: 0 }),
+            scope: QueryScope::default(),
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -970,7 +2611,15 @@ fn find_int_equal() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -978,9 +2627,10 @@ fn find_int_equal() {
                 String::from("age"),
                 Value::Int(67 as i32),
             )])),
-            order_by: Some(String::from("name")),
+            order_by: Some((String::from("name"), ValueType::String)),
             order_direction: Some(StoreOrder::Descending),
             range: None,
+            scope: QueryScope::default(),
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -1000,7 +2650,15 @@ fn find_int_not_equal() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -1008,9 +2666,10 @@ fn find_int_not_equal() {
                 String::from("age"),
                 Value::Int(67 as i32),
             )])),
-            order_by: Some(String::from("name")),
+            order_by: Some((String::from("name"), ValueType::String)),
             order_direction: Some(StoreOrder::Descending),
             range: None,
+            scope: QueryScope::default(),
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -1030,7 +2689,15 @@ fn find_int_greater_than() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -1041,6 +2708,7 @@ fn find_int_greater_than() {
             order_by: None,
             order_direction: None,
             range: None,
+            scope: QueryScope::default(),
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -1060,7 +2728,15 @@ fn find_int_greater_or_equal() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -1068,9 +2744,10 @@ fn find_int_greater_or_equal() {
                 String::from("age"),
                 Value::Int(43 as i32),
             )])),
-            order_by: Some(String::from("name")),
+            order_by: Some((String::from("name"), ValueType::String)),
             order_direction: Some(StoreOrder::Ascending),
             range: None,
+            scope: QueryScope::default(),
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -1090,7 +2767,15 @@ fn find_int_less_than() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -1098,9 +2783,10 @@ fn find_int_less_than() {
                 String::from("age"),
                 Value::Int(50 as i32),
             )])),
-            order_by: Some(String::from("name")),
+            order_by: Some((String::from("name"), ValueType::String)),
             order_direction: Some(StoreOrder::Ascending),
             range: None,
+            scope: QueryScope::default(),
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -1120,7 +2806,15 @@ fn find_int_less_or_equal() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -1128,9 +2822,10 @@ fn find_int_less_or_equal() {
                 String::from("age"),
                 Value::Int(43 as i32),
             )])),
-            order_by: Some(String::from("name")),
+            order_by: Some((String::from("name"), ValueType::String)),
             order_direction: Some(StoreOrder::Ascending),
             range: None,
+            scope: QueryScope::default(),
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -1150,7 +2845,15 @@ fn find_int_less_than_order_by_desc() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -1158,9 +2861,10 @@ fn find_int_less_than_order_by_desc() {
                 String::from("age"),
                 Value::Int(50 as i32),
             )])),
-            order_by: Some(String::from("name")),
+            order_by: Some((String::from("name"), ValueType::String)),
             order_direction: Some(StoreOrder::Descending),
             range: None,
+            scope: QueryScope::default(),
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -1180,7 +2884,15 @@ fn find_int_less_than_range() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -1188,9 +2900,10 @@ fn find_int_less_than_range() {
                 String::from("age"),
                 Value::Int(67 as i32),
             )])),
-            order_by: Some(String::from("name")),
+            order_by: Some((String::from("name"), ValueType::String)),
             order_direction: Some(StoreOrder::Descending),
             range: Some(StoreRange { first: 1, skip: 1 }),
+            scope: QueryScope::default(),
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -1210,7 +2923,15 @@ fn find_int_in() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -1218,9 +2939,10 @@ fn find_int_in() {
                 String::from("age"),
                 vec![Value::Int(67 as i32), Value::Int(43 as i32)],
             )])),
-            order_by: Some(String::from("name")),
+            order_by: Some((String::from("name"), ValueType::String)),
             order_direction: Some(StoreOrder::Descending),
             range: Some(StoreRange { first: 5, skip: 0 }),
+            scope: QueryScope::default(),
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -1240,7 +2962,15 @@ fn find_int_not_in() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -1248,9 +2978,10 @@ fn find_int_not_in() {
                 String::from("age"),
                 vec![Value::Int(67 as i32), Value::Int(43 as i32)],
             )])),
-            order_by: Some(String::from("name")),
+            order_by: Some((String::from("name"), ValueType::String)),
             order_direction: Some(StoreOrder::Descending),
             range: Some(StoreRange { first: 5, skip: 0 }),
+            scope: QueryScope::default(),
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -1270,7 +3001,15 @@ fn find_bool_equal() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -1278,9 +3017,10 @@ fn find_bool_equal() {
                 String::from("coffee"),
                 Value::Bool(true),
             )])),
-            order_by: Some(String::from("name")),
+            order_by: Some((String::from("name"), ValueType::String)),
             order_direction: Some(StoreOrder::Descending),
             range: None,
+            scope: QueryScope::default(),
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -1300,7 +3040,15 @@ fn find_bool_not_equal() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -1308,9 +3056,10 @@ fn find_bool_not_equal() {
                 String::from("coffee"),
                 Value::Bool(true),
             )])),
-            order_by: Some(String::from("name")),
+            order_by: Some((String::from("name"), ValueType::String)),
             order_direction: Some(StoreOrder::Ascending),
             range: None,
+            scope: QueryScope::default(),
         };
         let returned_entities = store.find(this_query).expect("store.find query failed");
 
@@ -1330,7 +3079,15 @@ fn find_bool_in() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -1338,9 +3095,10 @@ fn find_bool_in() {
                 String::from("coffee"),
                 vec![Value::Bool(true)],
             )])),
-            order_by: Some(String::from("name")),
+            order_by: Some((String::from("name"), ValueType::String)),
             order_direction: Some(StoreOrder::Descending),
             range: Some(StoreRange { first: 5, skip: 0 }),
+            scope: QueryScope::default(),
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -1360,7 +3118,15 @@ fn find_bool_not_in() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -1368,9 +3134,10 @@ fn find_bool_not_in() {
                 String::from("coffee"),
                 vec![Value::Bool(true)],
             )])),
-            order_by: Some(String::from("name")),
+            order_by: Some((String::from("name"), ValueType::String)),
             order_direction: Some(StoreOrder::Descending),
             range: Some(StoreRange { first: 5, skip: 0 }),
+            scope: QueryScope::default(),
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -1385,12 +3152,435 @@ fn find_bool_not_in() {
     })
 }
 
+/// Returns the names of the entities of `entity_type` that match `filter`,
+/// in alphabetical order.
+fn find_names(entity_type: &str, filter: StoreFilter) -> Result<Vec<String>, ()> {
+    let logger = Logger::root(slog::Discard, o!());
+    let url = postgres_test_url();
+    let store = DieselStore::new(
+        StoreConfig {
+            url,
+            intern_threshold: None,
+            query_settings: vec![],
+            indexing_settings: vec![],
+        },
+        &logger,
+    );
+    let entities = store.find(StoreQuery {
+        subgraph: String::from("test_subgraph"),
+        entity: String::from(entity_type),
+        filter: Some(StoreFilter::And(vec![filter])),
+        order_by: Some((String::from("name"), ValueType::String)),
+        order_direction: Some(StoreOrder::Ascending),
+        range: None,
+        scope: QueryScope::default(),
+    })?;
+    Ok(entities
+        .iter()
+        .map(|entity| match entity.get("name") {
+            Some(Value::String(name)) => name.to_owned(),
+            name => panic!("unexpected name {:?}", name),
+        })
+        .collect())
+}
+
+/// Inserts tokens with attributes of the types that users don't have.
+fn insert_test_tokens() {
+    let logger = Logger::root(slog::Discard, o!());
+    let url = postgres_test_url();
+    let mut store = DieselStore::new(
+        StoreConfig {
+            url,
+            intern_threshold: None,
+            query_settings: vec![],
+            indexing_settings: vec![],
+        },
+        &logger,
+    );
+
+    let tokens = vec![
+        ("1", "Alpha", "1000", "0.5", "0x01", vec!["a", "b"]),
+        ("2", "Beta", "2000", "1.25", "0x02", vec!["b"]),
+        ("3", "Gamma", "3000", "2.75", "0x03", vec![]),
+    ];
+    for (id, name, supply, price, owner, tags) in tokens {
+        let mut token = Entity::new();
+        token.insert(String::from("name"), Value::from(name));
+        token.insert(String::from("supply"), Value::BigInt(supply.parse::<BigInt>().unwrap()));
+        token.insert(
+            String::from("price"),
+            Value::BigDecimal(price.parse::<BigDecimal>().unwrap()),
+        );
+        token.insert(String::from("owner"), Value::Bytes(owner.parse::<Bytes>().unwrap()));
+        token.insert(
+            String::from("tags"),
+            Value::List(tags.into_iter().map(Value::from).collect()),
+        );
+        let key = StoreKey {
+            subgraph: String::from("test_subgraph"),
+            entity: String::from("token"),
+            id: String::from(id),
+        };
+        store
+            .set(key, token, EventSource::EthereumBlock(H256::from(1), 1))
+            .expect("Failed to insert test token into the store");
+    }
+}
+
+#[test]
+fn find_float_in() {
+    run_test(|| {
+        let filter = StoreFilter::In(
+            String::from("weight"),
+            vec![Value::Float(184.4 as f32), Value::Float(111.7 as f32)],
+        );
+        assert_eq!(
+            find_names("user", filter),
+            Ok(vec![String::from("Johnton"), String::from("Shaqueeena")])
+        );
+    })
+}
+
+#[test]
+fn find_float_not_in() {
+    run_test(|| {
+        let filter = StoreFilter::NotIn(
+            String::from("weight"),
+            vec![Value::Float(184.4 as f32), Value::Float(111.7 as f32)],
+        );
+        assert_eq!(find_names("user", filter), Ok(vec![String::from("Cindini")]));
+    })
+}
+
+#[test]
+fn find_big_int_in_and_not_in() {
+    run_test(|| {
+        insert_test_tokens();
+        let supplies = || {
+            vec![
+                Value::BigInt("1000".parse::<BigInt>().unwrap()),
+                Value::BigInt("3000".parse::<BigInt>().unwrap()),
+            ]
+        };
+        assert_eq!(
+            find_names("token", StoreFilter::In(String::from("supply"), supplies())),
+            Ok(vec![String::from("Alpha"), String::from("Gamma")])
+        );
+        assert_eq!(
+            find_names("token", StoreFilter::NotIn(String::from("supply"), supplies())),
+            Ok(vec![String::from("Beta")])
+        );
+    })
+}
+
+#[test]
+fn find_big_decimal_in_and_not_in() {
+    run_test(|| {
+        insert_test_tokens();
+        // Numbers are compared by value, regardless of trailing zeros
+        let prices = || {
+            vec![
+                Value::BigDecimal("1.250".parse::<BigDecimal>().unwrap()),
+                Value::BigDecimal("2.75".parse::<BigDecimal>().unwrap()),
+            ]
+        };
+        assert_eq!(
+            find_names("token", StoreFilter::In(String::from("price"), prices())),
+            Ok(vec![String::from("Beta"), String::from("Gamma")])
+        );
+        assert_eq!(
+            find_names("token", StoreFilter::NotIn(String::from("price"), prices())),
+            Ok(vec![String::from("Alpha")])
+        );
+    })
+}
+
+#[test]
+fn find_bytes_in_and_not_in() {
+    run_test(|| {
+        insert_test_tokens();
+        let owners = || vec![Value::Bytes("0x02".parse::<Bytes>().unwrap())];
+        assert_eq!(
+            find_names("token", StoreFilter::In(String::from("owner"), owners())),
+            Ok(vec![String::from("Beta")])
+        );
+        assert_eq!(
+            find_names("token", StoreFilter::NotIn(String::from("owner"), owners())),
+            Ok(vec![String::from("Alpha"), String::from("Gamma")])
+        );
+    })
+}
+
+#[test]
+fn find_list_in_and_not_in() {
+    run_test(|| {
+        insert_test_tokens();
+        let tags = || {
+            vec![
+                Value::List(vec![Value::from("a"), Value::from("b")]),
+                Value::List(vec![]),
+            ]
+        };
+        assert_eq!(
+            find_names("token", StoreFilter::In(String::from("tags"), tags())),
+            Ok(vec![String::from("Alpha"), String::from("Gamma")])
+        );
+        assert_eq!(
+            find_names("token", StoreFilter::NotIn(String::from("tags"), tags())),
+            Ok(vec![String::from("Beta")])
+        );
+    })
+}
+
+#[test]
+fn find_in_empty_list() {
+    run_test(|| {
+        assert_eq!(
+            find_names("user", StoreFilter::In(String::from("name"), vec![])),
+            Ok(vec![])
+        );
+        assert_eq!(
+            find_names("user", StoreFilter::NotIn(String::from("name"), vec![])),
+            Ok(vec![
+                String::from("Cindini"),
+                String::from("Johnton"),
+                String::from("Shaqueeena"),
+            ])
+        );
+    })
+}
+
+#[test]
+fn reject_in_with_values_of_different_types() {
+    run_test(|| {
+        let filter = StoreFilter::In(String::from("age"), vec![Value::Int(67), Value::from("43")]);
+        assert!(find_names("user", filter).is_err());
+
+        let filter = StoreFilter::NotIn(String::from("age"), vec![Value::Null]);
+        assert!(find_names("user", filter).is_err());
+    })
+}
+
+#[test]
+fn find_string_starts_with() {
+    run_test(|| {
+        let filter = StoreFilter::StartsWith(String::from("name"), Value::from("Sha"));
+        assert_eq!(find_names("user", filter), Ok(vec![String::from("Shaqueeena")]));
+
+        let filter = StoreFilter::NotStartsWith(String::from("name"), Value::from("Sha"));
+        assert_eq!(
+            find_names("user", filter),
+            Ok(vec![String::from("Cindini"), String::from("Johnton")])
+        );
+    })
+}
+
+#[test]
+fn find_string_starts_and_ends_with_pattern_characters() {
+    run_test(|| {
+        // `_` and `%` are matched literally rather than as wildcards
+        let filter = StoreFilter::StartsWith(String::from("name"), Value::from("_ohn"));
+        assert_eq!(find_names("user", filter), Ok(vec![]));
+
+        let filter = StoreFilter::EndsWith(String::from("name"), Value::from("%"));
+        assert_eq!(find_names("user", filter), Ok(vec![]));
+
+        let filter = StoreFilter::NotEndsWith(String::from("name"), Value::from("%ton"));
+        assert_eq!(
+            find_names("user", filter),
+            Ok(vec![
+                String::from("Cindini"),
+                String::from("Johnton"),
+                String::from("Shaqueeena"),
+            ])
+        );
+    })
+}
+
+#[test]
+fn find_string_nocase_with_pattern_characters() {
+    run_test(|| {
+        // `_` and `%` are matched literally, whatever their case
+        let filter = StoreFilter::ContainsNoCase(String::from("name"), Value::from("o_n"));
+        assert_eq!(find_names("user", filter), Ok(vec![]));
+
+        let filter = StoreFilter::StartsWithNoCase(String::from("name"), Value::from("%"));
+        assert_eq!(find_names("user", filter), Ok(vec![]));
+
+        let filter = StoreFilter::NotEndsWithNoCase(String::from("name"), Value::from("_TON"));
+        assert_eq!(
+            find_names("user", filter),
+            Ok(vec![
+                String::from("Cindini"),
+                String::from("Johnton"),
+                String::from("Shaqueeena"),
+            ])
+        );
+    })
+}
+
+#[test]
+fn find_orders_big_ints_by_value_and_ties_by_id() {
+    run_test(|| {
+        insert_test_tokens();
+
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let mut store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
+
+        // "900" sorts after "3000" as text, and Epsilon ties with Beta
+        for (id, name, supply) in vec![("4", "Delta", "900"), ("5", "Epsilon", "2000")] {
+            let mut token = Entity::new();
+            token.insert(String::from("name"), Value::from(name));
+            token.insert(String::from("supply"), Value::BigInt(supply.parse::<BigInt>().unwrap()));
+            let key = StoreKey {
+                subgraph: String::from("test_subgraph"),
+                entity: String::from("token"),
+                id: String::from(id),
+            };
+            store
+                .set(key, token, EventSource::EthereumBlock(H256::from(1), 1))
+                .expect("Failed to insert test token into the store");
+        }
+
+        let names = |direction| {
+            store
+                .find(StoreQuery {
+                    subgraph: String::from("test_subgraph"),
+                    entity: String::from("token"),
+                    filter: None,
+                    order_by: Some((String::from("supply"), ValueType::BigInt)),
+                    order_direction: Some(direction),
+                    range: None,
+                    scope: QueryScope::default(),
+                })
+                .expect("Failed to order tokens by their supply")
+                .iter()
+                .map(|token| match token.get("name") {
+                    Some(Value::String(name)) => name.to_owned(),
+                    name => panic!("unexpected name {:?}", name),
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(StoreOrder::Ascending),
+            vec!["Delta", "Alpha", "Beta", "Epsilon", "Gamma"]
+        );
+        assert_eq!(
+            names(StoreOrder::Descending),
+            vec!["Gamma", "Epsilon", "Beta", "Alpha", "Delta"]
+        );
+    })
+}
+
+#[test]
+fn find_pages_entities_in_the_database() {
+    run_test(|| {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
+        let query = StoreQuery {
+            subgraph: String::from("test_subgraph"),
+            entity: String::from("user"),
+            filter: None,
+            order_by: Some((String::from("name"), ValueType::String)),
+            order_direction: Some(StoreOrder::Ascending),
+            range: Some(StoreRange { first: 1, skip: 1 }),
+            scope: QueryScope::default(),
+        };
+
+        // Only the page is fetched, rather than every entity
+        let plan = store.explain(query.clone()).expect("Failed to explain the query");
+        assert!(plan.contains("LIMIT"), "no LIMIT in {}", plan);
+        assert!(plan.contains("OFFSET"), "no OFFSET in {}", plan);
+
+        let names = store
+            .find(query)
+            .expect("Failed to find a page of users")
+            .iter()
+            .map(|user| user.get("name").unwrap().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec![Value::from("Johnton")]);
+    })
+}
+
+#[test]
+fn find_filters_by_equality_through_the_data_index() {
+    run_test(|| {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
+        let index = diesel::select(diesel::dsl::sql::<diesel::sql_types::Text>(
+            "(SELECT indexdef FROM pg_indexes WHERE indexname = 'entities_data')",
+        )).get_result::<String>(&store.conn)
+            .expect("The index on the data of entities is missing");
+        assert!(index.contains("jsonb_path_ops"), "unexpected index {}", index);
+
+        let query = StoreQuery {
+            subgraph: String::from("test_subgraph"),
+            entity: String::from("user"),
+            filter: Some(StoreFilter::And(vec![
+                StoreFilter::Equal(String::from("name"), Value::from("Johnton")),
+                StoreFilter::Equal(String::from("age"), Value::Int(67)),
+            ])),
+            order_by: None,
+            order_direction: None,
+            range: None,
+            scope: QueryScope::default(),
+        };
+
+        // The filters are written as containment, which the index supports
+        let sql = store.explain(query.clone()).expect("Failed to explain the query");
+        assert!(sql.contains("data @> "), "no containment in {}", sql);
+
+        let names = store
+            .find(query)
+            .expect("Failed to find users")
+            .iter()
+            .map(|user| user.get("name").unwrap().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec![Value::from("Johnton")]);
+    })
+}
+
 #[test]
 fn revert_block() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -1398,14 +3588,15 @@ fn revert_block() {
                 String::from("name"),
                 Value::String(String::from("Shaqueeena")),
             )])),
-            order_by: Some(String::from("name")),
+            order_by: Some((String::from("name"), ValueType::String)),
             order_direction: Some(StoreOrder::Descending),
             range: None,
+            scope: QueryScope::default(),
         };
 
         let block_hash = "znuyjijnezBiGFuZAW9Q";
         let event_source =
-            EventSource::EthereumBlock(H256::from_slice(&block_hash.as_bytes())).to_string();
+            EventSource::EthereumBlock(H256::from_slice(&block_hash.as_bytes()), 0).to_string();
 
         // Revert all events associated with event_source, "znuyjijnezBiGFuZAW9Q"
         store.revert_events(event_source);
@@ -1437,7 +3628,15 @@ fn revert_block_with_delete() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let mut store = DieselStore::new(StoreConfig { url }, &logger);
+        let mut store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -1445,9 +3644,10 @@ fn revert_block_with_delete() {
                 String::from("name"),
                 Value::String(String::from("Cindini")),
             )])),
-            order_by: Some(String::from("name")),
+            order_by: Some((String::from("name"), ValueType::String)),
             order_direction: Some(StoreOrder::Descending),
             range: None,
+            scope: QueryScope::default(),
         };
 
         // Delete an entity using a randomly created event source
@@ -1458,7 +3658,7 @@ fn revert_block_with_delete() {
         };
 
         let block_hash = "test_block_to_revert";
-        let event_source = EventSource::EthereumBlock(H256::from_slice(&block_hash.as_bytes()));
+        let event_source = EventSource::EthereumBlock(H256::from_slice(&block_hash.as_bytes()), 0);
         let revert_event_source = event_source.to_string();
         store
             .delete(del_key.clone(), event_source)
@@ -1483,7 +3683,7 @@ fn revert_block_with_delete() {
         // Perform revert operation again to confirm idempotent nature of revert_events()
         // Delete an entity using a randomly created event source
         let block_hash = "test_block_to_revert";
-        let event_source = EventSource::EthereumBlock(H256::from_slice(&block_hash.as_bytes()));
+        let event_source = EventSource::EthereumBlock(H256::from_slice(&block_hash.as_bytes()), 0);
         let revert_event_source = event_source.to_string();
         store
             .delete(del_key.clone(), event_source)
@@ -1499,12 +3699,75 @@ fn revert_block_with_delete() {
     })
 }
 
+#[test]
+fn revert_block_operations_only_reverts_the_subgraph() {
+    run_test(|| {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let mut store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
+        let user_key = StoreKey {
+            subgraph: String::from("test_subgraph"),
+            entity: String::from("user"),
+            id: String::from("2"),
+        };
+        let other_key = StoreKey {
+            subgraph: String::from("other_subgraph"),
+            entity: String::from("user"),
+            id: String::from("2"),
+        };
+
+        // Both subgraphs write entities for the same block
+        let block = EventSource::EthereumBlock(H256::from(7), 7);
+        store
+            .delete(user_key.clone(), block.clone())
+            .expect("Failed to delete user");
+        let mut other = Entity::new();
+        other.insert(String::from("name"), Value::from("Other"));
+        store
+            .set(other_key.clone(), other, block)
+            .expect("Failed to set user");
+
+        // The block is reorged out of the chain for one of them
+        let reverted = store
+            .revert_block_operations("test_subgraph", H256::from(7))
+            .expect("Failed to revert block");
+        assert_eq!(reverted, 1);
+        let user = store.get(user_key.clone()).expect("The user was not restored");
+        assert_eq!(user.get("name"), Some(&Value::from("Cindini")));
+        let other = store.get(other_key).expect("The other user was reverted");
+        assert_eq!(other.get("name"), Some(&Value::from("Other")));
+
+        // Reverting the block again changes nothing
+        let reverted = store
+            .revert_block_operations("test_subgraph", H256::from(7))
+            .expect("Failed to revert block");
+        assert_eq!(reverted, 0);
+        assert!(store.get(user_key).is_ok());
+    })
+}
+
 #[test]
 fn revert_block_with_partial_update() {
     run_test(|| {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let mut store = DieselStore::new(StoreConfig { url }, &logger);
+        let mut store = DieselStore::new(
+            StoreConfig {
+                url,
+                intern_threshold: None,
+                query_settings: vec![],
+                indexing_settings: vec![],
+            },
+            &logger,
+        );
 
         let entity_key = StoreKey {
             subgraph: String::from("test_subgraph"),
@@ -1519,7 +3782,7 @@ fn revert_block_with_partial_update() {
         ]);
 
         let original_entity = store.get(entity_key.clone()).unwrap();
-        let event_source = EventSource::EthereumBlock(H256::random());
+        let event_source = EventSource::EthereumBlock(H256::random(), 1);
         let revert_event_source = event_source.to_string();
 
         // Verify that the entity before updating is different from what we expect afterwards